@@ -0,0 +1,79 @@
+#ifndef MERGE_PDF_H
+#define MERGE_PDF_H
+
+/* Generated by cbindgen from src/ffi.rs, do not edit by hand. */
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+/**
+ * Result of a `merge_pdf_*` call.
+ */
+typedef enum MergePdfStatus {
+  MERGE_PDF_STATUS_OK = 0,
+  /**
+   * A required pointer was null, or an option was out of range.
+   */
+  MERGE_PDF_STATUS_INVALID_ARGUMENT = 1,
+  /**
+   * An input could not be parsed or the merge failed, see `merge_pdf_last_error`.
+   */
+  MERGE_PDF_STATUS_MERGE_FAILED = 2,
+  /**
+   * The merge engine panicked; no output was produced.
+   */
+  MERGE_PDF_STATUS_PANICKED = 3,
+} MergePdfStatus;
+
+/**
+ * Settings of a merge, the C counterpart of `MergeOptions`.
+ */
+typedef struct MergePdfOptions {
+  /**
+   * 0 plain, 1 italic, 2 bold, 3 bold italic.
+   */
+  uint32_t bookmark_style;
+  bool compress;
+  bool pdfa;
+  /**
+   * Input (0-based) whose Catalog and page tree root are kept, negative for the first.
+   */
+  int64_t root;
+} MergePdfOptions;
+
+/**
+ * Merge `count` PDFs held in memory, in order, into a newly allocated buffer.
+ *
+ * On `MERGE_PDF_STATUS_OK` the merged file is stored in `*out` and its length in `*out_len`;
+ * release it with `merge_pdf_free`. `options` may be null for the defaults.
+ *
+ * # Safety
+ *
+ * `inputs` and `lengths` must point to `count` elements each, every `inputs[i]` to
+ * `lengths[i]` readable bytes. `options` must be null or valid, `out` and `out_len` writable.
+ */
+enum MergePdfStatus merge_pdf_merge(const uint8_t *const *inputs,
+                                    const size_t *lengths,
+                                    size_t count,
+                                    const struct MergePdfOptions *options,
+                                    uint8_t **out,
+                                    size_t *out_len);
+
+/**
+ * Release a buffer returned by `merge_pdf_merge`. Null is ignored.
+ *
+ * # Safety
+ *
+ * `buffer` and `len` must be exactly what `merge_pdf_merge` stored, and not be freed twice.
+ */
+void merge_pdf_free(uint8_t *buffer, size_t len);
+
+/**
+ * The message of the last failed call on this thread, or null. Valid until the next call.
+ */
+const char *merge_pdf_last_error(void);
+
+#endif  /* MERGE_PDF_H */
@@ -0,0 +1,225 @@
+//! AES (FIPS 197) with 128- and 256-bit keys, used to encrypt output and decrypt inputs with the
+//! standard security handler.
+
+const SBOX: [u8; 256] = [
+    0x63, 0x7c, 0x77, 0x7b, 0xf2, 0x6b, 0x6f, 0xc5, 0x30, 0x01, 0x67, 0x2b, 0xfe, 0xd7, 0xab, 0x76, 0xca, 0x82, 0xc9, 0x7d, 0xfa, 0x59,
+    0x47, 0xf0, 0xad, 0xd4, 0xa2, 0xaf, 0x9c, 0xa4, 0x72, 0xc0, 0xb7, 0xfd, 0x93, 0x26, 0x36, 0x3f, 0xf7, 0xcc, 0x34, 0xa5, 0xe5, 0xf1,
+    0x71, 0xd8, 0x31, 0x15, 0x04, 0xc7, 0x23, 0xc3, 0x18, 0x96, 0x05, 0x9a, 0x07, 0x12, 0x80, 0xe2, 0xeb, 0x27, 0xb2, 0x75, 0x09, 0x83,
+    0x2c, 0x1a, 0x1b, 0x6e, 0x5a, 0xa0, 0x52, 0x3b, 0xd6, 0xb3, 0x29, 0xe3, 0x2f, 0x84, 0x53, 0xd1, 0x00, 0xed, 0x20, 0xfc, 0xb1, 0x5b,
+    0x6a, 0xcb, 0xbe, 0x39, 0x4a, 0x4c, 0x58, 0xcf, 0xd0, 0xef, 0xaa, 0xfb, 0x43, 0x4d, 0x33, 0x85, 0x45, 0xf9, 0x02, 0x7f, 0x50, 0x3c,
+    0x9f, 0xa8, 0x51, 0xa3, 0x40, 0x8f, 0x92, 0x9d, 0x38, 0xf5, 0xbc, 0xb6, 0xda, 0x21, 0x10, 0xff, 0xf3, 0xd2, 0xcd, 0x0c, 0x13, 0xec,
+    0x5f, 0x97, 0x44, 0x17, 0xc4, 0xa7, 0x7e, 0x3d, 0x64, 0x5d, 0x19, 0x73, 0x60, 0x81, 0x4f, 0xdc, 0x22, 0x2a, 0x90, 0x88, 0x46, 0xee,
+    0xb8, 0x14, 0xde, 0x5e, 0x0b, 0xdb, 0xe0, 0x32, 0x3a, 0x0a, 0x49, 0x06, 0x24, 0x5c, 0xc2, 0xd3, 0xac, 0x62, 0x91, 0x95, 0xe4, 0x79,
+    0xe7, 0xc8, 0x37, 0x6d, 0x8d, 0xd5, 0x4e, 0xa9, 0x6c, 0x56, 0xf4, 0xea, 0x65, 0x7a, 0xae, 0x08, 0xba, 0x78, 0x25, 0x2e, 0x1c, 0xa6,
+    0xb4, 0xc6, 0xe8, 0xdd, 0x74, 0x1f, 0x4b, 0xbd, 0x8b, 0x8a, 0x70, 0x3e, 0xb5, 0x66, 0x48, 0x03, 0xf6, 0x0e, 0x61, 0x35, 0x57, 0xb9,
+    0x86, 0xc1, 0x1d, 0x9e, 0xe1, 0xf8, 0x98, 0x11, 0x69, 0xd9, 0x8e, 0x94, 0x9b, 0x1e, 0x87, 0xe9, 0xce, 0x55, 0x28, 0xdf, 0x8c, 0xa1,
+    0x89, 0x0d, 0xbf, 0xe6, 0x42, 0x68, 0x41, 0x99, 0x2d, 0x0f, 0xb0, 0x54, 0xbb, 0x16,
+];
+
+/// The inverse of `SBOX`.
+const INV_SBOX: [u8; 256] = [
+    0x52, 0x09, 0x6a, 0xd5, 0x30, 0x36, 0xa5, 0x38, 0xbf, 0x40, 0xa3, 0x9e, 0x81, 0xf3, 0xd7, 0xfb, 0x7c, 0xe3, 0x39, 0x82, 0x9b, 0x2f,
+    0xff, 0x87, 0x34, 0x8e, 0x43, 0x44, 0xc4, 0xde, 0xe9, 0xcb, 0x54, 0x7b, 0x94, 0x32, 0xa6, 0xc2, 0x23, 0x3d, 0xee, 0x4c, 0x95, 0x0b,
+    0x42, 0xfa, 0xc3, 0x4e, 0x08, 0x2e, 0xa1, 0x66, 0x28, 0xd9, 0x24, 0xb2, 0x76, 0x5b, 0xa2, 0x49, 0x6d, 0x8b, 0xd1, 0x25, 0x72, 0xf8,
+    0xf6, 0x64, 0x86, 0x68, 0x98, 0x16, 0xd4, 0xa4, 0x5c, 0xcc, 0x5d, 0x65, 0xb6, 0x92, 0x6c, 0x70, 0x48, 0x50, 0xfd, 0xed, 0xb9, 0xda,
+    0x5e, 0x15, 0x46, 0x57, 0xa7, 0x8d, 0x9d, 0x84, 0x90, 0xd8, 0xab, 0x00, 0x8c, 0xbc, 0xd3, 0x0a, 0xf7, 0xe4, 0x58, 0x05, 0xb8, 0xb3,
+    0x45, 0x06, 0xd0, 0x2c, 0x1e, 0x8f, 0xca, 0x3f, 0x0f, 0x02, 0xc1, 0xaf, 0xbd, 0x03, 0x01, 0x13, 0x8a, 0x6b, 0x3a, 0x91, 0x11, 0x41,
+    0x4f, 0x67, 0xdc, 0xea, 0x97, 0xf2, 0xcf, 0xce, 0xf0, 0xb4, 0xe6, 0x73, 0x96, 0xac, 0x74, 0x22, 0xe7, 0xad, 0x35, 0x85, 0xe2, 0xf9,
+    0x37, 0xe8, 0x1c, 0x75, 0xdf, 0x6e, 0x47, 0xf1, 0x1a, 0x71, 0x1d, 0x29, 0xc5, 0x89, 0x6f, 0xb7, 0x62, 0x0e, 0xaa, 0x18, 0xbe, 0x1b,
+    0xfc, 0x56, 0x3e, 0x4b, 0xc6, 0xd2, 0x79, 0x20, 0x9a, 0xdb, 0xc0, 0xfe, 0x78, 0xcd, 0x5a, 0xf4, 0x1f, 0xdd, 0xa8, 0x33, 0x88, 0x07,
+    0xc7, 0x31, 0xb1, 0x12, 0x10, 0x59, 0x27, 0x80, 0xec, 0x5f, 0x60, 0x51, 0x7f, 0xa9, 0x19, 0xb5, 0x4a, 0x0d, 0x2d, 0xe5, 0x7a, 0x9f,
+    0x93, 0xc9, 0x9c, 0xef, 0xa0, 0xe0, 0x3b, 0x4d, 0xae, 0x2a, 0xf5, 0xb0, 0xc8, 0xeb, 0xbb, 0x3c, 0x83, 0x53, 0x99, 0x61, 0x17, 0x2b,
+    0x04, 0x7e, 0xba, 0x77, 0xd6, 0x26, 0xe1, 0x69, 0x14, 0x63, 0x55, 0x21, 0x0c, 0x7d,
+];
+
+/// The round constants of the key expansion.
+const RCON: [u8; 10] = [0x01, 0x02, 0x04, 0x08, 0x10, 0x20, 0x40, 0x80, 0x1b, 0x36];
+
+/// An expanded AES key.
+pub struct Aes {
+    round_keys: Vec<[u8; 16]>,
+}
+
+impl Aes {
+    /// Expand a 16- or 32-byte key.
+    pub fn new(key: &[u8]) -> Self {
+        assert!(matches!(key.len(), 16 | 32), "AES keys are 16 or 32 bytes");
+        let nk = key.len() / 4;
+        let rounds = nk + 6;
+        let mut words = key.chunks_exact(4).map(|word| [word[0], word[1], word[2], word[3]]).collect::<Vec<_>>();
+        for i in nk..4 * (rounds + 1) {
+            let mut word = words[i - 1];
+            if i % nk == 0 {
+                word = [SBOX[word[1] as usize] ^ RCON[i / nk - 1], SBOX[word[2] as usize], SBOX[word[3] as usize], SBOX[word[0] as usize]];
+            } else if nk > 6 && i % nk == 4 {
+                word = word.map(|b| SBOX[b as usize]);
+            }
+            words.push(std::array::from_fn(|j| words[i - nk][j] ^ word[j]));
+        }
+        let round_keys = words.chunks_exact(4).map(|round| std::array::from_fn(|i| round[i / 4][i % 4])).collect();
+        Aes { round_keys }
+    }
+
+    /// Encrypt one 16-byte block in place.
+    pub fn encrypt_block(&self, block: &mut [u8; 16]) {
+        let last = self.round_keys.len() - 1;
+        add_round_key(block, &self.round_keys[0]);
+        for (round, key) in self.round_keys.iter().enumerate().skip(1) {
+            for b in block.iter_mut() {
+                *b = SBOX[*b as usize];
+            }
+            shift_rows(block);
+            if round != last {
+                mix_columns(block);
+            }
+            add_round_key(block, key);
+        }
+    }
+
+    /// Encrypt `data`, a whole number of blocks long, in cipher block chaining mode.
+    pub fn encrypt_cbc(&self, iv: [u8; 16], data: &[u8]) -> Vec<u8> {
+        debug_assert!(data.len().is_multiple_of(16));
+        let mut encrypted = Vec::with_capacity(data.len());
+        let mut previous = iv;
+        for chunk in data.chunks_exact(16) {
+            let mut block: [u8; 16] = std::array::from_fn(|i| chunk[i] ^ previous[i]);
+            self.encrypt_block(&mut block);
+            encrypted.extend_from_slice(&block);
+            previous = block;
+        }
+        encrypted
+    }
+
+    /// Decrypt one 16-byte block in place.
+    pub fn decrypt_block(&self, block: &mut [u8; 16]) {
+        let last = self.round_keys.len() - 1;
+        add_round_key(block, &self.round_keys[last]);
+        for (round, key) in self.round_keys.iter().enumerate().rev().skip(1) {
+            inverse_shift_rows(block);
+            for b in block.iter_mut() {
+                *b = INV_SBOX[*b as usize];
+            }
+            add_round_key(block, key);
+            if round != 0 {
+                inverse_mix_columns(block);
+            }
+        }
+    }
+
+    /// Decrypt `data` in cipher block chaining mode; a trailing partial block is dropped.
+    pub fn decrypt_cbc(&self, iv: [u8; 16], data: &[u8]) -> Vec<u8> {
+        let mut decrypted = Vec::with_capacity(data.len());
+        let mut previous = iv;
+        for chunk in data.chunks_exact(16) {
+            let mut block: [u8; 16] = chunk.try_into().unwrap();
+            self.decrypt_block(&mut block);
+            decrypted.extend(block.iter().zip(previous).map(|(b, p)| b ^ p));
+            previous = chunk.try_into().unwrap();
+        }
+        decrypted
+    }
+}
+
+fn add_round_key(block: &mut [u8; 16], key: &[u8; 16]) {
+    block.iter_mut().zip(key).for_each(|(b, k)| *b ^= k);
+}
+
+/// Rotate row `r` of the column-major state left by `r`.
+fn shift_rows(block: &mut [u8; 16]) {
+    let state = *block;
+    for (i, b) in block.iter_mut().enumerate() {
+        let (column, row) = (i / 4, i % 4);
+        *b = state[(column + row) % 4 * 4 + row];
+    }
+}
+
+fn inverse_shift_rows(block: &mut [u8; 16]) {
+    let state = *block;
+    for (i, b) in block.iter_mut().enumerate() {
+        let (column, row) = (i / 4, i % 4);
+        *b = state[(column + 4 - row) % 4 * 4 + row];
+    }
+}
+
+fn mix_columns(block: &mut [u8; 16]) {
+    for column in block.chunks_exact_mut(4) {
+        let [a, b, c, d] = [column[0], column[1], column[2], column[3]];
+        column[0] = double(a) ^ double(b) ^ b ^ c ^ d;
+        column[1] = a ^ double(b) ^ double(c) ^ c ^ d;
+        column[2] = a ^ b ^ double(c) ^ double(d) ^ d;
+        column[3] = double(a) ^ a ^ b ^ c ^ double(d);
+    }
+}
+
+fn inverse_mix_columns(block: &mut [u8; 16]) {
+    for column in block.chunks_exact_mut(4) {
+        let [a, b, c, d] = [column[0], column[1], column[2], column[3]];
+        column[0] = multiply(a, 14) ^ multiply(b, 11) ^ multiply(c, 13) ^ multiply(d, 9);
+        column[1] = multiply(a, 9) ^ multiply(b, 14) ^ multiply(c, 11) ^ multiply(d, 13);
+        column[2] = multiply(a, 13) ^ multiply(b, 9) ^ multiply(c, 14) ^ multiply(d, 11);
+        column[3] = multiply(a, 11) ^ multiply(b, 13) ^ multiply(c, 9) ^ multiply(d, 14);
+    }
+}
+
+/// Multiply `a` by `b` in GF(2^8).
+fn multiply(mut a: u8, mut b: u8) -> u8 {
+    let mut product = 0;
+    while b != 0 {
+        if b & 1 != 0 {
+            product ^= a;
+        }
+        a = double(a);
+        b >>= 1;
+    }
+    product
+}
+
+/// Multiply by x in GF(2^8).
+fn double(b: u8) -> u8 {
+    (b << 1) ^ if b & 0x80 != 0 { 0x1b } else { 0 }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hex(text: &str) -> Vec<u8> {
+        (0..text.len()).step_by(2).map(|i| u8::from_str_radix(&text[i..i + 2], 16).unwrap()).collect()
+    }
+
+    /// FIPS 197, appendix C: the example vectors for both key sizes, both ways.
+    #[test]
+    fn blocks_match_fips_197() {
+        let plain: [u8; 16] = hex("00112233445566778899aabbccddeeff").try_into().unwrap();
+        for (key, cipher) in [
+            ("000102030405060708090a0b0c0d0e0f", "69c4e0d86a7b0430d8cdb78070b4c55a"),
+            ("000102030405060708090a0b0c0d0e0f101112131415161718191a1b1c1d1e1f", "8ea2b7ca516745bfeafc49904b496089"),
+        ] {
+            let aes = Aes::new(&hex(key));
+            let mut block = plain;
+            aes.encrypt_block(&mut block);
+            assert_eq!(block.to_vec(), hex(cipher), "{}", key);
+            aes.decrypt_block(&mut block);
+            assert_eq!(block, plain, "{}", key);
+        }
+    }
+
+    /// FIPS 197, appendix B: the cipher example, whose key expansion is spelled out in appendix A.1.
+    #[test]
+    fn cipher_example_matches_fips_197() {
+        let mut block = hex("3243f6a8885a308d313198a2e0370734").try_into().unwrap();
+        Aes::new(&hex("2b7e151628aed2a6abf7158809cf4f3c")).encrypt_block(&mut block);
+        assert_eq!(block.to_vec(), hex("3925841d02dc09fbdc118597196a0b32"));
+    }
+
+    /// SP 800-38A, F.2.5 and F.2.6: the first two blocks of CBC-AES256.
+    #[test]
+    fn cbc_matches_sp_800_38a() {
+        let aes = Aes::new(&hex("603deb1015ca71be2b73aef0857d77811f352c073b6108d72d9810a30914dff4"));
+        let iv = hex("000102030405060708090a0b0c0d0e0f").try_into().unwrap();
+        let plain = hex("6bc1bee22e409f96e93d7e117393172aae2d8a571e03ac9c9eb76fac45af8e51");
+        let cipher = hex("f58c4c04d6e5f1ba779eabfb5f7bfbd69cfc4e967edb808d679f777bc6702c7d");
+        assert_eq!(aes.encrypt_cbc(iv, &plain), cipher);
+        assert_eq!(aes.decrypt_cbc(iv, &cipher), plain);
+    }
+}
@@ -0,0 +1,242 @@
+use std::collections::BTreeSet;
+
+use lopdf::{Dictionary, Document, Object, ObjectId};
+
+/// Make the annotations of `doc` fit the merge of only its `pages`: every annotation on one of
+/// them names it as its page (`P`), and whatever pointed to a page left out (an annotation's
+/// page, a link's destination, a go-to action) is removed, so no object of the input refers to
+/// one that is not in the output. Pages keep their objects in the merged document, so links and
+/// bookmarks to the others still work; those that give the page by its number, as some
+/// producers do, are made to refer to its object.
+pub fn remap(doc: &mut Document, pages: &BTreeSet<ObjectId>) -> crate::Result<()> {
+    let numbered = doc.get_pages().into_values().collect::<Vec<_>>();
+    for object in doc.objects.values_mut() {
+        number_pages(object, &numbered);
+    }
+    for &page_id in pages {
+        let Ok(annotations) = doc.get_dictionary(page_id)?.get_deref(b"Annots", doc).and_then(Object::as_array).cloned() else { continue };
+        for annotation in annotations {
+            if let Ok(annotation) = annotation.as_reference().and_then(|id| doc.get_dictionary_mut(id)) {
+                if annotation.has(b"P") {
+                    annotation.set("P", page_id);
+                }
+            }
+        }
+    }
+
+    let dropped = numbered.into_iter().filter(|id| !pages.contains(id)).collect::<BTreeSet<_>>();
+    if dropped.is_empty() {
+        return Ok(());
+    }
+    // Destinations and actions that are objects of their own go along with the pages they lead to
+    let mut dead = dropped.clone();
+    dead.extend(doc.objects.iter().filter(|(_, object)| leads_to(object, &dropped)).map(|(id, _)| *id));
+    for (id, object) in doc.objects.iter_mut() {
+        // The page tree still numbers the pages, and is replaced by the merged one
+        if !dropped.contains(id) && object.type_name().ok() != Some("Pages") {
+            strip(object, &dead);
+        }
+    }
+    Ok(())
+}
+
+/// Whether `object` is a reference to one of `dead`, an explicit destination of one of its
+/// pages, or a go-to action to one.
+fn leads_to(object: &Object, dead: &BTreeSet<ObjectId>) -> bool {
+    match object {
+        Object::Reference(id) => dead.contains(id),
+        Object::Array(items) => items.first().is_some_and(|page| matches!(page, Object::Reference(id) if dead.contains(id))),
+        Object::Dictionary(dict) => is_go_to(dict) && dict.get(b"D").is_ok_and(|destination| leads_to(destination, dead)),
+        _ => false,
+    }
+}
+
+/// Replace the 0-based page numbers of the local destinations in `object` by the objects of
+/// those of `pages`.
+fn number_pages(object: &mut Object, pages: &[ObjectId]) {
+    let dict = match object {
+        Object::Dictionary(dict) => dict,
+        Object::Array(items) => return items.iter_mut().for_each(|item| number_pages(item, pages)),
+        _ => return,
+    };
+    let go_to = is_go_to(dict);
+    for (key, value) in dict.iter_mut() {
+        match value {
+            Object::Array(destination) if key == b"Dest" || (go_to && key == b"D") => {
+                if let Some(page) = destination.first_mut() {
+                    if let Some(&id) = page.as_i64().ok().and_then(|n| pages.get(usize::try_from(n).ok()?)) {
+                        *page = Object::Reference(id);
+                    }
+                }
+            }
+            value => number_pages(value, pages),
+        }
+    }
+}
+
+fn is_go_to(dict: &Dictionary) -> bool {
+    dict.get(b"S").and_then(Object::as_name).ok() == Some(b"GoTo".as_slice())
+}
+
+/// Remove from `object` the entries that lead to one of `dead`, and put null in place of the
+/// array items that do.
+fn strip(object: &mut Object, dead: &BTreeSet<ObjectId>) {
+    match object {
+        Object::Array(items) => {
+            for item in items.iter_mut() {
+                if leads_to(item, dead) {
+                    *item = Object::Null;
+                } else {
+                    strip(item, dead);
+                }
+            }
+        }
+        Object::Dictionary(dict) => strip_dictionary(dict, dead),
+        Object::Stream(stream) => strip_dictionary(&mut stream.dict, dead),
+        _ => {}
+    }
+}
+
+fn strip_dictionary(dict: &mut Dictionary, dead: &BTreeSet<ObjectId>) {
+    let mut removed = Vec::new();
+    for (key, value) in dict.iter_mut() {
+        if leads_to(value, dead) {
+            removed.push(key.clone());
+        } else {
+            strip(value, dead);
+        }
+    }
+    for key in removed {
+        dict.remove(&key);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use lopdf::{dictionary, Stream};
+
+    use crate::{flatten::Annotations, input::InputSpec, merge_inputs, verify, Input, MergeOptions};
+
+    use super::*;
+
+    /// Three pages: a comment with its popup on the first, a highlight drawn by an appearance
+    /// stream on the second, and on the third links to the first page, by object and by number,
+    /// and a go-to action to the second.
+    fn annotated() -> Document {
+        let mut doc = crate::tests::document(3);
+        let pages = doc.get_pages().into_values().collect::<Vec<_>>();
+        let rect = || vec![100.into(), 100.into(), 200.into(), 120.into()];
+
+        let comment = doc.new_object_id();
+        let popup = doc.add_object(dictionary! { "Type" => "Annot", "Subtype" => "Popup", "Rect" => rect(), "Parent" => comment, "P" => pages[0] });
+        doc.objects.insert(
+            comment,
+            Object::Dictionary(dictionary! {
+                "Type" => "Annot", "Subtype" => "Text", "Rect" => rect(), "Contents" => Object::string_literal("a comment"),
+                "Popup" => popup, "P" => pages[0],
+            }),
+        );
+        let appearance = doc.add_object(Stream::new(
+            dictionary! { "Type" => "XObject", "Subtype" => "Form", "BBox" => rect() },
+            b"1 1 0 rg 100 100 100 20 re f".to_vec(),
+        ));
+        let highlight = doc.add_object(dictionary! {
+            "Type" => "Annot", "Subtype" => "Highlight", "Rect" => rect(), "P" => pages[1],
+            "QuadPoints" => vec![100.into(), 120.into(), 200.into(), 120.into(), 100.into(), 100.into(), 200.into(), 100.into()],
+            "AP" => dictionary! { "N" => appearance },
+        });
+        let link = |destination: Object| dictionary! { "Type" => "Annot", "Subtype" => "Link", "Rect" => rect(), "Dest" => destination };
+        let to_first = doc.add_object(link(vec![pages[0].into(), "Fit".into()].into()));
+        let to_first_by_number = doc.add_object(link(vec![0.into(), "Fit".into()].into()));
+        let to_second = doc.add_object(dictionary! {
+            "Type" => "Annot", "Subtype" => "Link", "Rect" => rect(),
+            "A" => dictionary! { "S" => "GoTo", "D" => vec![pages[1].into(), "XYZ".into(), Object::Null, Object::Null, Object::Null] },
+        });
+        for (page, annotations) in pages.iter().zip([vec![comment, popup], vec![highlight], vec![to_first, to_first_by_number, to_second]]) {
+            let annotations = annotations.into_iter().map(Object::Reference).collect::<Vec<_>>();
+            doc.get_dictionary_mut(*page).unwrap().set("Annots", annotations);
+        }
+        doc
+    }
+
+    /// The annotation dictionaries on every page of `doc`, in page order.
+    fn annotations(doc: &Document) -> Vec<Vec<Dictionary>> {
+        doc.get_pages()
+            .into_values()
+            .map(|page| {
+                let Ok(annotations) = doc.get_dictionary(page).unwrap().get_deref(b"Annots", doc).and_then(Object::as_array) else {
+                    return Vec::new();
+                };
+                annotations.iter().map(|annotation| doc.dereference(annotation).unwrap().1.as_dict().unwrap().clone()).collect()
+            })
+            .collect()
+    }
+
+    fn subtypes(annotations: &[Dictionary]) -> Vec<&str> {
+        annotations.iter().map(|annotation| std::str::from_utf8(annotation.get(b"Subtype").unwrap().as_name().unwrap()).unwrap()).collect()
+    }
+
+    fn merge(spec: InputSpec, options: MergeOptions) -> Document {
+        let merged = merge_inputs(vec![Input { spec, doc: annotated() }], &options).unwrap();
+        let problems = verify::check(&merged.document);
+        assert!(problems.is_empty(), "{:?}", problems);
+        merged.document
+    }
+
+    #[test]
+    fn kept_annotations_point_at_their_pages() {
+        let doc = merge(InputSpec::new(PathBuf::from("a.pdf")), MergeOptions::new());
+        let pages = doc.get_pages().into_values().collect::<Vec<_>>();
+        let annotations = annotations(&doc);
+        assert_eq!(annotations.iter().map(|page| subtypes(page)).collect::<Vec<_>>(), [vec!["Text", "Popup"], vec!["Highlight"], vec!["Link"; 3]]);
+        for (page, annotations) in pages.iter().zip(&annotations) {
+            for annotation in annotations.iter().filter(|annotation| annotation.has(b"P")) {
+                assert_eq!(annotation.get(b"P").unwrap().as_reference().unwrap(), *page);
+            }
+        }
+        // The comment and its popup still refer to each other
+        let popup = annotations[0][0].get(b"Popup").unwrap().as_reference().unwrap();
+        assert_eq!(doc.get_dictionary(popup).unwrap().get(b"Subtype").unwrap().as_name().unwrap(), b"Popup");
+        let appearance = annotations[1][0].get_deref(b"AP", &doc).unwrap().as_dict().unwrap().get(b"N").unwrap().as_reference().unwrap();
+        assert!(doc.get_object(appearance).unwrap().as_stream().is_ok());
+        // A page given by its number becomes the page object
+        let links = &annotations[2];
+        assert_eq!(links[1].get(b"Dest").unwrap().as_array().unwrap()[0], Object::Reference(pages[0]));
+    }
+
+    #[test]
+    fn links_to_pages_left_out_are_removed() {
+        let mut spec = InputSpec::new(PathBuf::from("a.pdf"));
+        spec.pages = Some("2-3".parse().unwrap());
+        let doc = merge(spec, MergeOptions::new());
+        let pages = doc.get_pages().into_values().collect::<Vec<_>>();
+        let annotations = annotations(&doc);
+        assert_eq!(annotations.iter().map(|page| subtypes(page)).collect::<Vec<_>>(), [vec!["Highlight"], vec!["Link"; 3]]);
+        let links = &annotations[1];
+        assert!(!links[0].has(b"Dest"));
+        assert!(!links[1].has(b"Dest"));
+        let action = links[2].get_deref(b"A", &doc).unwrap().as_dict().unwrap();
+        assert_eq!(action.get(b"D").unwrap().as_array().unwrap()[0], Object::Reference(pages[0]));
+        // Neither the comment nor its popup made it into the output
+        assert!(!doc.objects.values().any(|object| object.as_dict().is_ok_and(|d| d.get(b"Subtype").is_ok_and(|s| s.as_name().ok() == Some(b"Popup".as_slice())))));
+    }
+
+    #[test]
+    fn flattening_draws_the_highlight_and_keeps_the_links() {
+        let doc = merge(InputSpec::new(PathBuf::from("a.pdf")), MergeOptions::new().annotations(Annotations::Flatten));
+        let annotations = annotations(&doc);
+        // The comment has no appearance to draw and goes, and its popup with it
+        assert_eq!(annotations.iter().map(|page| subtypes(page)).collect::<Vec<_>>(), [vec![], vec![], vec!["Link"; 3]]);
+        let second = doc.get_pages()[&2];
+        let content = doc.get_page_content(second).unwrap();
+        assert!(String::from_utf8_lossy(&content).contains(" Do"), "{}", String::from_utf8_lossy(&content));
+    }
+
+    #[test]
+    fn stripping_removes_every_annotation() {
+        let doc = merge(InputSpec::new(PathBuf::from("a.pdf")), MergeOptions::new().annotations(Annotations::Strip));
+        assert!(annotations(&doc).iter().all(Vec::is_empty));
+    }
+}
@@ -0,0 +1,151 @@
+use std::collections::BTreeSet;
+
+use lopdf::{Dictionary, Document, Object};
+
+use crate::{messages::tr, metadata, renumber, verify, writer};
+
+/// Catalog entries of an appended document that have no place in the update, with what they hold.
+const LEFT_OUT: [(&[u8], &str); 5] = [
+    (b"Names", "named destinations and attachments"),
+    (b"Dests", "named destinations"),
+    (b"OCProperties", "layers"),
+    (b"StructTreeRoot", "the structure tree"),
+    (b"Threads", "article threads"),
+];
+
+/// The incremental update that appends `doc`, a merged document, to the PDF `bytes`: its pages
+/// go after those of the file, its top-level bookmarks after the file's own and its form fields
+/// into the file's form. The file itself stays as it is, byte for byte, so a reader that kept it
+/// only has to fetch the update.
+pub fn update(bytes: &[u8], mut doc: Document) -> crate::Result<Vec<u8>> {
+    let base = Document::load_mem(bytes)?;
+    if base.is_encrypted() || doc.is_encrypted() {
+        return Err(crate::Error::Invalid(tr("encrypted files cannot be appended to", &[])));
+    }
+    let size = base.trailer.get(b"Size").and_then(Object::as_i64).map_or(0, |size| size as u32).max(base.max_id + 1);
+    renumber::renumber_objects_with(&mut doc, size);
+    let next_id = doc.max_id.max(size - 1) + 1;
+
+    let root_id = base.trailer.get(b"Root").and_then(Object::as_reference)?;
+    let mut catalog = base.catalog()?.clone();
+    let pages_id = catalog.get(b"Pages").and_then(Object::as_reference)?;
+    let new_catalog = doc.catalog()?.clone();
+    for (key, what) in LEFT_OUT {
+        if new_catalog.has(key) {
+            log::warn!("{}", tr("the {} of the appended inputs are left out", &[&tr(what, &[])]));
+        }
+    }
+    // Never written: the update keeps the Catalog, page tree root and metadata of the file
+    let mut left_out = BTreeSet::new();
+    left_out.extend(doc.trailer.get(b"Root").and_then(Object::as_reference));
+    left_out.extend(new_catalog.get(b"Pages").and_then(Object::as_reference));
+    left_out.extend(new_catalog.get(b"Metadata").and_then(Object::as_reference));
+    left_out.extend(doc.trailer.get(b"Info").and_then(Object::as_reference));
+    let mut roots = Vec::new();
+    let mut changed = Vec::new();
+
+    let new_pages = doc.get_pages().into_values().collect::<Vec<_>>();
+    for id in &new_pages {
+        if let Ok(page) = doc.get_dictionary_mut(*id) {
+            page.set("Parent", pages_id);
+        }
+    }
+    roots.extend(new_pages.iter().copied());
+    let mut pages = base.get_dictionary(pages_id)?.clone();
+    let mut kids = pages.get(b"Kids").and_then(Object::as_array).cloned().unwrap_or_default();
+    kids.extend(new_pages.iter().copied().map(Object::Reference));
+    pages.set("Kids", kids);
+    let count = pages.get(b"Count").and_then(Object::as_i64).unwrap_or(0);
+    pages.set("Count", count + new_pages.len() as i64);
+    changed.push((pages_id, pages));
+
+    let new_outline = new_catalog.get(b"Outlines").and_then(Object::as_reference).ok();
+    let first = new_outline.and_then(|id| doc.get_dictionary(id).ok()).and_then(|root| root.get(b"First").and_then(Object::as_reference).ok());
+    if let (Some(new_outline), Some(first)) = (new_outline, first) {
+        let outline = catalog.get(b"Outlines").and_then(Object::as_reference).ok().filter(|id| base.get_dictionary(*id).is_ok());
+        match outline.and_then(|id| Some((id, base.get_dictionary(id).ok()?.get(b"Last").and_then(Object::as_reference).ok()?))) {
+            Some((outline_id, last)) => {
+                // The file's last top-level bookmark leads on to the appended ones
+                left_out.insert(new_outline);
+                let mut top = Some(first);
+                let mut seen = BTreeSet::new();
+                while let Some(id) = top.filter(|id| seen.insert(*id)) {
+                    roots.push(id);
+                    let item = doc.get_dictionary_mut(id)?;
+                    item.set("Parent", outline_id);
+                    top = item.get(b"Next").and_then(Object::as_reference).ok();
+                }
+                doc.get_dictionary_mut(first)?.set("Prev", last);
+                let mut last_item = base.get_dictionary(last)?.clone();
+                last_item.set("Next", first);
+                changed.push((last, last_item));
+                let appended = doc.get_dictionary(new_outline)?;
+                let mut outline = base.get_dictionary(outline_id)?.clone();
+                outline.set("Last", appended.get(b"Last")?.clone());
+                let count = |dict: &Dictionary| dict.get(b"Count").and_then(Object::as_i64).unwrap_or(0);
+                outline.set("Count", count(&outline) + count(appended));
+                changed.push((outline_id, outline));
+            }
+            None => {
+                roots.push(new_outline);
+                catalog.set("Outlines", new_outline);
+            }
+        }
+    }
+
+    let new_fields = new_catalog
+        .get_deref(b"AcroForm", &doc)
+        .and_then(|form| form.as_dict()?.get_deref(b"Fields", &doc))
+        .and_then(Object::as_array)
+        .cloned()
+        .unwrap_or_default();
+    if !new_fields.is_empty() {
+        let mut form = catalog.get_deref(b"AcroForm", &base).and_then(Object::as_dict).cloned().unwrap_or_default();
+        let mut fields = form.get_deref(b"Fields", &base).and_then(Object::as_array).cloned().unwrap_or_default();
+        roots.extend(new_fields.iter().filter_map(|field| field.as_reference().ok()));
+        fields.extend(new_fields);
+        form.set("Fields", fields);
+        catalog.set("AcroForm", form);
+    }
+    if base.catalog()? != &catalog {
+        changed.push((root_id, catalog));
+    }
+    // Modified just now
+    if let Ok(info_id) = base.trailer.get(b"Info").and_then(Object::as_reference) {
+        if let Ok(info) = base.get_dictionary(info_id) {
+            let mut info = info.clone();
+            let date = format!("D:{}", metadata::now().replace(['-', ':', 'T'], ""));
+            info.set("ModDate", Object::string_literal(date));
+            changed.push((info_id, info));
+        }
+    }
+
+    // What the pages, bookmarks and fields appended need, and nothing else
+    let mut written = BTreeSet::new();
+    let mut pending = roots;
+    while let Some(id) = pending.pop() {
+        if left_out.contains(&id) || !written.insert(id) {
+            continue;
+        }
+        if let Some(object) = doc.objects.get(&id) {
+            verify::references(object, &mut pending);
+        }
+    }
+
+    let mut update = b"\n".to_vec();
+    let mut offsets = Vec::new();
+    let objects = changed.into_iter().map(|(id, dict)| (id, Object::Dictionary(dict)));
+    let appended = written.into_iter().filter_map(|id| Some((id, doc.objects.remove(&id)?)));
+    for (id, object) in objects.chain(appended) {
+        offsets.push((id, bytes.len() + update.len()));
+        update.extend_from_slice(&writer::indirect_object(id, &object));
+    }
+    let mut trailer = Dictionary::new();
+    for key in [b"Root".as_slice(), b"Info", b"ID"] {
+        if let Ok(value) = base.trailer.get(key) {
+            trailer.set(key, value.clone());
+        }
+    }
+    writer::finish_update(bytes, &mut update, offsets, trailer, next_id)?;
+    Ok(update)
+}
@@ -0,0 +1,80 @@
+use std::path::PathBuf;
+
+use lopdf::Document;
+use tokio::{
+    io::{AsyncRead, AsyncReadExt},
+    task::{self, JoinHandle},
+};
+
+use crate::{input::{self, InputSpec}, merge_inputs, messages::tr, without_empty, Input, MergeOptions};
+
+/// `merge`, parsing all inputs concurrently on blocking tasks so the runtime is never blocked.
+///
+/// ```no_run
+/// # async fn run() -> merge_pdf::Result<()> {
+/// use std::{fs::File, io::{BufWriter, Write}};
+///
+/// use merge_pdf::{asynchronous::merge_async, write_to, MergeOptions};
+///
+/// let options = MergeOptions::new();
+/// let mut merged = merge_async(&["a.pdf".into(), "b.pdf".into()], &options).await?;
+/// write_to(&mut merged, BufWriter::new(File::create("merged.pdf")?), &options)?.flush()?;
+/// # Ok(())
+/// # }
+/// ```
+pub async fn merge_async(inputs: &[PathBuf], options: &MergeOptions) -> crate::Result<Document> {
+    let tasks = inputs
+        .iter()
+        .cloned()
+        .map(|path| task::spawn_blocking(move || input::load(&path).map(|doc| Input { spec: InputSpec::new(path), doc })))
+        .collect::<Vec<_>>();
+    merge_loaded(join_all(tasks).await?, options).await
+}
+
+/// `merge_async` for documents read from async readers (uploads, object storage, ...). The path
+/// given with every reader only names the input in messages.
+pub async fn merge_readers<R: AsyncRead + Unpin>(
+    readers: Vec<(PathBuf, R)>,
+    options: &MergeOptions,
+) -> crate::Result<Document> {
+    let mut tasks = Vec::with_capacity(readers.len());
+    // Every input starts parsing as soon as it is read, while the next one is being read
+    for (path, mut reader) in readers {
+        let mut buffer = Vec::new();
+        reader.read_to_end(&mut buffer).await?;
+        tasks.push(task::spawn_blocking(move || {
+            Document::load_mem(&buffer)
+                .map(|doc| Input { spec: InputSpec::new(path.clone()), doc })
+                .map_err(|e| crate::Error::Invalid(tr("{} could not be loaded: {}", &[&path.display(), &e])))
+        }));
+    }
+    merge_loaded(join_all(tasks).await?, options).await
+}
+
+/// Read a whole PDF from `reader` and parse it on a blocking task.
+pub async fn load_reader(mut reader: impl AsyncRead + Unpin) -> crate::Result<Document> {
+    let mut buffer = Vec::new();
+    reader.read_to_end(&mut buffer).await?;
+    Ok(task::spawn_blocking(move || Document::load_mem(&buffer)).await.map_err(join_error)??)
+}
+
+/// Run the merge itself on a blocking task as well, it is CPU-bound for large inputs.
+async fn merge_loaded(inputs: Vec<Input>, options: &MergeOptions) -> crate::Result<Document> {
+    let options = options.clone();
+    task::spawn_blocking(move || merge_inputs(without_empty(inputs), &options).map(|merged| merged.document))
+        .await
+        .map_err(join_error)?
+}
+
+/// Wait for every task in order, failing with the first error.
+async fn join_all(tasks: Vec<JoinHandle<crate::Result<Input>>>) -> crate::Result<Vec<Input>> {
+    let mut inputs = Vec::with_capacity(tasks.len());
+    for task in tasks {
+        inputs.push(task.await.map_err(join_error)??);
+    }
+    Ok(inputs)
+}
+
+fn join_error(e: task::JoinError) -> crate::Error {
+    crate::Error::Invalid(tr("loading task failed: {}", &[&e]))
+}
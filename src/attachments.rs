@@ -0,0 +1,120 @@
+use std::collections::HashSet;
+
+use lopdf::{Dictionary, Document, Object};
+
+use crate::{input::InputSpec, messages::tr, text};
+
+/// How deep the nodes of a name tree are followed.
+const MAX_DEPTH: usize = 32;
+
+/// Embedded files (the `EmbeddedFiles` name tree, PDF 32000-1, 7.11.4) and associated files (the
+/// catalog's `AF`, as ZUGFeRD and Factur-X invoices use) of the inputs, gathered for the merged
+/// document, whose catalog comes from a single input.
+#[derive(Debug, Clone, Default)]
+pub struct Attachments {
+    files: Vec<(Vec<u8>, Object)>,
+    associated: Vec<Object>,
+}
+
+impl Attachments {
+    /// The attachments of `doc`, renumbered as it will be merged, taken out of its catalog so
+    /// the catalog that is kept does not hold them twice. A `Names` dictionary of its own is
+    /// copied into the catalog, for `apply` to change.
+    pub fn take(doc: &mut Document) -> Self {
+        let mut attachments = Attachments::default();
+        let Ok(catalog) = doc.catalog() else { return attachments };
+        let names = catalog.get_deref(b"Names", doc).and_then(Object::as_dict).ok().cloned();
+        let associated = catalog.get_deref(b"AF", doc).and_then(Object::as_array).ok().cloned();
+        if let Some(mut names) = names {
+            if let Some(tree) = names.remove(b"EmbeddedFiles") {
+                leaves(doc, &tree, 0, &mut attachments.files);
+            }
+            if let Ok(catalog) = doc.catalog_mut() {
+                catalog.set("Names", names);
+            }
+        }
+        if let Some(associated) = associated {
+            attachments.associated = associated;
+            if let Ok(catalog) = doc.catalog_mut() {
+                catalog.remove(b"AF");
+            }
+        }
+        attachments
+    }
+
+    /// Add the attachments `other` took from the input `spec`, renaming those whose name is
+    /// taken to `name (2).ext` and so on.
+    pub fn append(&mut self, spec: &InputSpec, other: Attachments) {
+        let mut used = self.files.iter().map(|(name, _)| name.clone()).collect::<HashSet<_>>();
+        for (name, file) in other.files {
+            let unique = unique_name(&name, &mut used);
+            if unique != name {
+                log::info!("{}", tr("{}: attachment {} renamed to {}", &[&spec.path.display(), &text::decode(&name), &text::decode(&unique)]));
+            }
+            self.files.push((unique, file));
+        }
+        self.associated.extend(other.associated);
+    }
+
+    /// Give `catalog` an `EmbeddedFiles` name tree and `AF` array of all the attachments, if any.
+    pub fn apply(&self, catalog: &mut Dictionary) {
+        if !self.files.is_empty() {
+            // Names in a name tree are sorted by their bytes
+            let mut files = self.files.clone();
+            files.sort_by(|a, b| a.0.cmp(&b.0));
+            let names = files
+                .into_iter()
+                .flat_map(|(name, file)| [Object::String(name, lopdf::StringFormat::Literal), file])
+                .collect::<Vec<_>>();
+            let mut tree = Dictionary::new();
+            tree.set("Names", names);
+            let mut dictionary = catalog.get(b"Names").and_then(Object::as_dict).cloned().unwrap_or_default();
+            dictionary.set("EmbeddedFiles", tree);
+            catalog.set("Names", dictionary);
+        }
+        if !self.associated.is_empty() {
+            catalog.set("AF", self.associated.clone());
+        }
+    }
+}
+
+/// The attachment name `name`, or `name (2).ext` and so on when `used` holds it already; what
+/// is returned is added to `used`.
+pub(crate) fn unique_name(name: &[u8], used: &mut HashSet<Vec<u8>>) -> Vec<u8> {
+    let mut unique = name.to_vec();
+    let mut n = 1;
+    while used.contains(&unique) {
+        n += 1;
+        let decoded = text::decode(name);
+        let renamed = match decoded.rsplit_once('.') {
+            Some((stem, extension)) if !stem.is_empty() => format!("{} ({}).{}", stem, n, extension),
+            _ => format!("{} ({})", decoded, n),
+        };
+        unique = text::encode(&renamed);
+    }
+    used.insert(unique.clone());
+    unique
+}
+
+/// Collect the name and value of every leaf below the name tree node `node`.
+pub(crate) fn leaves(doc: &Document, node: &Object, depth: usize, files: &mut Vec<(Vec<u8>, Object)>) {
+    let node = match node {
+        Object::Reference(id) => doc.get_dictionary(*id).ok(),
+        node => node.as_dict().ok(),
+    };
+    let Some(node) = node else { return };
+    if let Ok(names) = node.get_deref(b"Names", doc).and_then(Object::as_array) {
+        for pair in names.chunks_exact(2) {
+            if let Ok(name) = pair[0].as_str() {
+                files.push((name.to_vec(), pair[1].clone()));
+            }
+        }
+    }
+    if depth < MAX_DEPTH {
+        if let Ok(kids) = node.get_deref(b"Kids", doc).and_then(Object::as_array) {
+            for kid in kids {
+                leaves(doc, kid, depth + 1, files);
+            }
+        }
+    }
+}
@@ -0,0 +1,160 @@
+use std::io::Read;
+
+use flate2::read::ZlibDecoder;
+use lopdf::{content::Content, Dictionary, Document, Object, ObjectId, Stream};
+
+use crate::{image::unfilter_png, input::InputSpec, messages::tr, pages};
+
+/// Samples lighter than this (0 black to 255 white) count as paper.
+const PAPER: u8 = 0xC0;
+/// Images with at most this share of darker samples count as blank, which forgives scanner dust.
+const MAX_DARK_SHARE: f64 = 0.005;
+/// How deep Form XObjects may nest before a page is taken as not blank.
+const MAX_FORM_DEPTH: usize = 8;
+
+/// Leave the blank pages of `doc` out of the pages `spec` selects, saying how many when there
+/// are any; returns how many it left out.
+pub fn drop_blank_pages(spec: &mut InputSpec, doc: &Document) -> usize {
+    let selected = doc.get_pages().into_iter().filter(|(number, _)| spec.selects(*number)).collect::<Vec<_>>();
+    let kept = selected.iter().filter(|(_, id)| !is_blank(doc, *id)).map(|(number, _)| *number).collect::<Vec<_>>();
+    let dropped = selected.len() - kept.len();
+    if dropped > 0 {
+        log::info!("{}", tr("{}: dropped {} blank page(s)", &[&spec.path.display(), &dropped]));
+        spec.pages = Some(kept.into_iter().collect());
+    }
+    dropped
+}
+
+/// Whether a page shows nothing: its content paints no visible text, no non-white paths or
+/// shadings and only images that are (nearly) all white.
+///
+/// Anything that cannot be looked into, such as JPEG images or content that fails to parse,
+/// counts as not blank, so a page is never dropped by mistake.
+pub fn is_blank(doc: &Document, page_id: ObjectId) -> bool {
+    let Ok(Object::Dictionary(page)) = pages::with_inherited_attributes(doc, page_id) else { return false };
+    let Ok(content) = doc.get_and_decode_page_content(page_id) else { return false };
+    let resources = page.get_deref(b"Resources", doc).and_then(Object::as_dict).ok();
+    paints_nothing(doc, &content, resources, 0)
+}
+
+/// Colors that count as paper, by the operator setting them.
+fn is_white(operator: &str, operands: &[Object]) -> bool {
+    let values = operands.iter().map(|o| o.as_float().ok()).collect::<Option<Vec<_>>>().unwrap_or_default();
+    match operator {
+        "g" | "G" | "rg" | "RG" => !values.is_empty() && values.iter().all(|v| *v >= 0.99),
+        "k" | "K" => !values.is_empty() && values.iter().all(|v| *v <= 0.01),
+        _ => false,
+    }
+}
+
+fn paints_nothing(doc: &Document, content: &Content, resources: Option<&Dictionary>, depth: usize) -> bool {
+    // Fill white, stroke white and invisible text, saved and restored by q/Q
+    let mut state = (false, false, false);
+    let mut saved = Vec::new();
+    for operation in &content.operations {
+        let operands = &operation.operands;
+        match operation.operator.as_str() {
+            "q" => saved.push(state),
+            "Q" => state = saved.pop().unwrap_or(state),
+            "g" | "rg" | "k" | "sc" | "scn" | "cs" => state.0 = is_white(&operation.operator, operands),
+            "G" | "RG" | "K" | "SC" | "SCN" | "CS" => state.1 = is_white(&operation.operator, operands),
+            // Render mode 3 hides text, as in the OCR layer of a scan
+            "Tr" => state.2 = operands.first().and_then(|o| o.as_i64().ok()) == Some(3),
+            "Tj" | "TJ" | "'" | "\"" if !state.2 => return false,
+            "f" | "F" | "f*" if !state.0 => return false,
+            "S" | "s" if !state.1 => return false,
+            "B" | "B*" | "b" | "b*" if !(state.0 && state.1) => return false,
+            "sh" | "BI" | "EI" => return false,
+            "Do" => {
+                let xobject = operands
+                    .first()
+                    .and_then(|name| name.as_name().ok())
+                    .and_then(|name| resources?.get_deref(b"XObject", doc).and_then(Object::as_dict).ok()?.get_deref(name, doc).ok())
+                    .and_then(|xobject| xobject.as_stream().ok());
+                match xobject {
+                    Some(xobject) if xobject_is_blank(doc, xobject, resources, depth) => {}
+                    _ => return false,
+                }
+            }
+            _ => {}
+        }
+    }
+    true
+}
+
+fn xobject_is_blank(doc: &Document, xobject: &Stream, resources: Option<&Dictionary>, depth: usize) -> bool {
+    match xobject.dict.get(b"Subtype").and_then(Object::as_name) {
+        Ok(b"Image") => image_is_blank(doc, xobject),
+        Ok(b"Form") if depth < MAX_FORM_DEPTH => {
+            let content = xobject.decompressed_content().unwrap_or_else(|_| xobject.content.clone());
+            let Ok(content) = Content::decode(&content) else { return false };
+            // A form without resources of its own uses those of the page
+            let own = xobject.dict.get_deref(b"Resources", doc).and_then(Object::as_dict).ok();
+            paints_nothing(doc, &content, own.or(resources), depth + 1)
+        }
+        _ => false,
+    }
+}
+
+/// Whether an image is (nearly) all white. Only 1 and 8 bit gray or RGB images stored raw or
+/// with Flate can be looked into.
+fn image_is_blank(doc: &Document, image: &Stream) -> bool {
+    let dict = &image.dict;
+    if dict.get(b"ImageMask").and_then(Object::as_bool).unwrap_or(false) || dict.has(b"Decode") {
+        return false;
+    }
+    let components = match dict.get_deref(b"ColorSpace", doc).and_then(Object::as_name) {
+        Ok(b"DeviceGray") => 1,
+        Ok(b"DeviceRGB") => 3,
+        _ => return false,
+    };
+    let number = |key: &[u8]| dict.get(key).and_then(Object::as_i64).ok().and_then(|n| usize::try_from(n).ok());
+    let (Some(width), Some(height), Some(bits)) = (number(b"Width"), number(b"Height"), number(b"BitsPerComponent")) else {
+        return false;
+    };
+    let row_bytes = (width * components * bits).div_ceil(8);
+    let Some(samples) = image_samples(image, row_bytes, (components * bits).div_ceil(8), height) else { return false };
+    if width == 0 || height == 0 || samples.len() < row_bytes * height {
+        return false;
+    }
+    let dark = match bits {
+        // Rows are padded to whole bytes; 0 is black
+        1 => samples
+            .chunks(row_bytes)
+            .take(height)
+            .map(|row| (0..width).filter(|x| row[x / 8] & (0x80 >> (x % 8)) == 0).count())
+            .sum::<usize>(),
+        8 => samples[..row_bytes * height]
+            .chunks(components)
+            .filter(|pixel| (pixel.iter().map(|&c| c as usize).sum::<usize>() / components) < PAPER as usize)
+            .count(),
+        _ => return false,
+    };
+    (dark as f64) <= (width * height) as f64 * MAX_DARK_SHARE
+}
+
+/// The raw samples of an image stored without a filter or with Flate, PNG predictors undone.
+pub(crate) fn image_samples(image: &Stream, row_bytes: usize, pixel_bytes: usize, rows: usize) -> Option<Vec<u8>> {
+    let filter = match image.dict.get(b"Filter") {
+        Err(_) => return Some(image.content.clone()),
+        Ok(Object::Array(filters)) if filters.len() == 1 => filters[0].as_name().ok()?,
+        Ok(filter) => filter.as_name().ok()?,
+    };
+    if filter != b"FlateDecode" {
+        return None;
+    }
+    let mut samples = Vec::new();
+    ZlibDecoder::new(image.content.as_slice()).read_to_end(&mut samples).ok()?;
+    let predictor = image
+        .dict
+        .get(b"DecodeParms")
+        .and_then(Object::as_dict)
+        .and_then(|params| params.get(b"Predictor"))
+        .and_then(Object::as_i64)
+        .unwrap_or(1);
+    match predictor {
+        1 => Some(samples),
+        10.. => unfilter_png(&samples, row_bytes, pixel_bytes.max(1), rows).ok(),
+        _ => None,
+    }
+}
@@ -0,0 +1,269 @@
+use std::{
+    collections::{BTreeMap, HashSet},
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicBool, Ordering},
+    time::Duration,
+};
+
+use lopdf::{Document, Object};
+
+use crate::{
+    messages::tr,
+    outline,
+    output::{self, OnExisting},
+};
+
+/// Where the parts of a split go and what they may replace.
+#[derive(Debug, Clone, Copy)]
+pub struct PartOutput<'a> {
+    /// The file being split, which no part may replace
+    pub source: &'a Path,
+    /// Directory to write the parts into
+    pub out_dir: &'a Path,
+    /// What to do when a part's file already exists
+    pub on_existing: OnExisting,
+    /// How long to wait for another merge_pdf writing a part's file, see `output::Lock`
+    pub lock_wait: Duration,
+    /// Stop with `Error::Interrupted` once set, leaving the parts written so far whole and
+    /// nothing of the one being written, like `MergeOptions::cancel`
+    pub cancel: Option<&'a AtomicBool>,
+}
+
+impl PartOutput<'_> {
+    fn check_cancelled(&self) -> crate::Result<()> {
+        match self.cancel {
+            Some(flag) if flag.load(Ordering::Relaxed) => Err(crate::Error::Interrupted),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Write one PDF per top-level bookmark of `doc` into `to.out_dir`.
+///
+/// Each part runs from the page its bookmark points at up to the page before the next
+/// bookmark's target; the last part runs to the end of the document. Returns the written paths.
+pub fn burst_by_bookmark(doc: &Document, to: &PartOutput) -> crate::Result<Vec<PathBuf>> {
+    let pages = doc.get_pages();
+    let page_numbers = pages.iter().map(|(&number, &id)| (id, number)).collect::<BTreeMap<_, _>>();
+
+    // Bookmarks sorted by the page they start on; several bookmarks on one page share it with the first
+    let mut starts: Vec<(u32, String)> = Vec::new();
+    for entry in outline::read(doc) {
+        match entry.page.and_then(|id| page_numbers.get(&id)) {
+            Some(&number) if !starts.iter().any(|(n, _)| *n == number) => starts.push((number, entry.title)),
+            Some(_) => {}
+            None => log::warn!("{}", tr("bookmark {} does not point at a page, skipping", &[&format!("{:?}", entry.title)])),
+        }
+    }
+    starts.sort_by_key(|(number, _)| *number);
+    if starts.is_empty() {
+        return Err(crate::Error::Invalid(tr("document has no top-level bookmarks to split at", &[])));
+    }
+    if starts[0].0 > 1 {
+        log::warn!("{}", tr("pages 1-{} come before the first bookmark and are not written", &[&(starts[0].0 - 1)]));
+    }
+
+    let last_page = pages.keys().copied().max().unwrap_or(0);
+    let mut used_names = HashSet::new();
+    let mut names = Vec::new();
+    for (i, (_, title)) in starts.iter().enumerate() {
+        let mut name = sanitize_file_name(title, i + 1);
+        let mut n = 2;
+        while !used_names.insert(name.clone()) {
+            name = format!("{}-{}", sanitize_file_name(title, i + 1), n);
+            n += 1;
+        }
+        names.push(format!("{}.pdf", name));
+    }
+    refuse_existing(&names, to)?;
+    let mut written = Vec::new();
+    for (i, ((first, _), name)) in starts.iter().zip(&names).enumerate() {
+        let last = starts.get(i + 1).map(|(next, _)| next - 1).unwrap_or(last_page);
+        let mut part = subset(doc, |n| (*first..=last).contains(&n));
+        written.push(save_part(&mut part, name, to)?);
+    }
+    Ok(written)
+}
+
+/// Fail before anything is written if a part would be written over an existing file and
+/// `to.on_existing` says to fail.
+fn refuse_existing(names: &[String], to: &PartOutput) -> crate::Result<()> {
+    if to.on_existing == OnExisting::Fail {
+        for name in names {
+            output::target_path(&to.out_dir.join(name), OnExisting::Fail)?;
+        }
+    }
+    Ok(())
+}
+
+/// Save `part` as `name` in `to.out_dir`, or the next free name if `to.on_existing` says so, and
+/// return where it went. It is written next to it first and moved in place when whole, see
+/// `output::Partial`, under a lock on it, and it never replaces the file being split.
+fn save_part(part: &mut Document, name: &str, to: &PartOutput) -> crate::Result<PathBuf> {
+    let target = output::target_path(&to.out_dir.join(name), to.on_existing)?;
+    let _lock = output::Lock::acquire(&target, to.lock_wait, || to.check_cancelled())?;
+    let source = to.source.canonicalize().ok();
+    if source.is_some() && target.canonicalize().ok() == source {
+        return Err(crate::Error::Usage(tr("{} is the file being split, a part cannot replace it", &[&target.display()])));
+    }
+    let partial = output::Partial::new(&target);
+    part.save(partial.path()).map_err(|e| crate::Error::saving(&target, e))?;
+    to.check_cancelled()?;
+    partial.persist()?;
+    Ok(target)
+}
+
+/// Write `doc` into `to.out_dir` in parts of `every` pages, named `{stem}-1.pdf`, `{stem}-2.pdf`, ...
+/// Returns the written paths.
+pub fn burst_every(doc: &Document, every: u32, stem: &str, to: &PartOutput) -> crate::Result<Vec<PathBuf>> {
+    let last_page = doc.get_pages().keys().copied().max().unwrap_or(0);
+    let starts = (1..=last_page).step_by(every.max(1) as usize).skip(1).collect::<Vec<_>>();
+    burst_at(doc, &starts, stem, to)
+}
+
+/// Write `doc` into `to.out_dir` in parts that start at page 1 and at each of the pages `starts`,
+/// named like `burst_every` does. Starts past the last page are left out with a warning.
+/// Returns the written paths.
+pub fn burst_at(doc: &Document, starts: &[u32], stem: &str, to: &PartOutput) -> crate::Result<Vec<PathBuf>> {
+    let last_page = doc.get_pages().keys().copied().max().unwrap_or(0);
+    let mut firsts = vec![1];
+    for &start in starts {
+        if start > last_page {
+            log::warn!("{}", tr("{} has {} pages, not splitting at page {}", &[&stem, &last_page, &start]));
+        } else if start > 1 {
+            firsts.push(start);
+        }
+    }
+    firsts.sort_unstable();
+    firsts.dedup();
+    let names = (1..=firsts.len()).map(|number| format!("{}-{}.pdf", stem, number)).collect::<Vec<_>>();
+    refuse_existing(&names, to)?;
+    let mut written = Vec::new();
+    for (i, (&first, name)) in firsts.iter().zip(&names).enumerate() {
+        let last = firsts.get(i + 1).map(|next| next - 1).unwrap_or(last_page);
+        let mut part = subset(doc, |n| (first..=last).contains(&n));
+        written.push(save_part(&mut part, name, to)?);
+    }
+    Ok(written)
+}
+
+/// A copy of `doc` with only the pages `keep` accepts. The outline is dropped, as its entries
+/// would point at pages that may be gone.
+pub fn subset(doc: &Document, keep: impl Fn(u32) -> bool) -> Document {
+    let mut part = doc.clone();
+    let outside = doc.get_pages().into_keys().filter(|n| !keep(*n)).collect::<Vec<_>>();
+    part.delete_pages(&outside);
+    if let Ok(catalog) = part.catalog_mut() {
+        catalog.remove(b"Outlines");
+        if catalog.get(b"PageMode").and_then(Object::as_name).ok() == Some(b"UseOutlines".as_slice()) {
+            catalog.remove(b"PageMode");
+        }
+    }
+    part.prune_objects();
+    part.renumber_objects();
+    part
+}
+
+/// Make a bookmark title usable as a file name on every common filesystem.
+fn sanitize_file_name(title: &str, index: usize) -> String {
+    let cleaned = title
+        .chars()
+        .map(|c| if c.is_control() || "/\\:*?\"<>|".contains(c) { '_' } else { c })
+        .collect::<String>();
+    let cleaned = cleaned.trim().trim_matches('.').chars().take(100).collect::<String>();
+    if cleaned.is_empty() {
+        format!("bookmark-{}", index)
+    } else {
+        cleaned
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use crate::{input::InputSpec, merge_inputs, tempdir::TempDir, BookmarkMode, Input, MergeOptions};
+
+    use super::*;
+
+    /// A document of two one-page inputs bookmarked `a` and `b`.
+    fn bookmarked() -> Document {
+        let inputs = ["a", "b"]
+            .into_iter()
+            .map(|title| {
+                let mut spec = InputSpec::new(PathBuf::from(format!("{}.pdf", title)));
+                spec.title = Some(title.to_string());
+                Input { spec, doc: crate::tests::document(1) }
+            })
+            .collect();
+        merge_inputs(inputs, &MergeOptions::new().bookmarks(BookmarkMode::PerFile)).unwrap().document
+    }
+
+    #[test]
+    fn parts_never_replace_existing_files_unless_asked() {
+        let scratch = TempDir::named("burst-existing").unwrap();
+        let dir = scratch.path();
+        fs::write(dir.join("a.pdf"), "the original").unwrap();
+        let source = dir.join("merged.pdf");
+        let to = |on_existing| PartOutput { source: &source, out_dir: dir, on_existing, lock_wait: Duration::ZERO, cancel: None };
+
+        assert!(burst_by_bookmark(&bookmarked(), &to(OnExisting::Fail)).is_err());
+        // Nothing is written once one part cannot be
+        assert!(!dir.join("b.pdf").exists());
+
+        let written = burst_by_bookmark(&bookmarked(), &to(OnExisting::Increment)).unwrap();
+        assert_eq!(written, [dir.join("a-1.pdf"), dir.join("b.pdf")]);
+        assert_eq!(fs::read_to_string(dir.join("a.pdf")).unwrap(), "the original");
+        for path in &written {
+            assert_eq!(Document::load(path).unwrap().get_pages().len(), 1);
+        }
+
+        burst_by_bookmark(&bookmarked(), &to(OnExisting::Overwrite)).unwrap();
+        assert_eq!(Document::load(dir.join("a.pdf")).unwrap().get_pages().len(), 1);
+    }
+
+    #[test]
+    fn the_split_file_is_never_replaced() {
+        let scratch = TempDir::named("burst-source").unwrap();
+        let source = scratch.path().join("a.pdf");
+        bookmarked().save(&source).unwrap();
+        let before = fs::read(&source).unwrap();
+        let to = PartOutput { source: &source, out_dir: scratch.path(), on_existing: OnExisting::Overwrite, lock_wait: Duration::ZERO, cancel: None };
+        assert!(matches!(burst_by_bookmark(&bookmarked(), &to), Err(crate::Error::Usage(_))));
+        assert_eq!(fs::read(&source).unwrap(), before);
+    }
+
+    #[test]
+    fn page_parts_are_checked_and_locked_like_bookmark_parts() {
+        let scratch = TempDir::named("burst-every").unwrap();
+        let dir = scratch.path();
+        let doc = crate::tests::document(5);
+        let source = dir.join("scan.pdf");
+        let to = |on_existing| PartOutput { source: &source, out_dir: dir, on_existing, lock_wait: Duration::ZERO, cancel: None };
+        fs::write(dir.join("scan-3.pdf"), "the original").unwrap();
+        assert!(burst_every(&doc, 2, "scan", &to(OnExisting::Fail)).is_err());
+        assert!(!dir.join("scan-1.pdf").exists());
+
+        let written = burst_at(&doc, &[3, 5], "scan", &to(OnExisting::Increment)).unwrap();
+        assert_eq!(written, [dir.join("scan-1.pdf"), dir.join("scan-2.pdf"), dir.join("scan-3-1.pdf")]);
+        assert_eq!(fs::read_to_string(dir.join("scan-3.pdf")).unwrap(), "the original");
+        let pages = written.iter().map(|path| Document::load(path).unwrap().get_pages().len()).collect::<Vec<_>>();
+        assert_eq!(pages, [2, 2, 1]);
+
+        // Another merge_pdf writing one of the parts
+        let _held = output::Lock::acquire(&dir.join("scan-2.pdf"), Duration::ZERO, || Ok(())).unwrap();
+        assert!(matches!(burst_every(&doc, 2, "scan", &to(OnExisting::Overwrite)), Err(crate::Error::Locked { .. })));
+    }
+
+    #[test]
+    fn interrupted_splits_leave_only_whole_parts() {
+        let scratch = TempDir::named("burst-cancel").unwrap();
+        let dir = scratch.path();
+        let source = dir.join("scan.pdf");
+        let cancel = AtomicBool::new(true);
+        let to = PartOutput { source: &source, out_dir: dir, on_existing: OnExisting::Fail, lock_wait: Duration::ZERO, cancel: Some(&cancel) };
+        assert!(matches!(burst_every(&crate::tests::document(3), 1, "scan", &to), Err(crate::Error::Interrupted)));
+        // Neither the part nor its partial file or lock
+        assert_eq!(fs::read_dir(dir).unwrap().count(), 0);
+    }
+}
@@ -0,0 +1,198 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use lopdf::{Dictionary, Document, Object, Stream, StringFormat};
+
+use crate::{messages::tr, sha256};
+
+/// Start of every cache file; a new layout needs a new one.
+const MAGIC: &[u8] = b"merge_pdf cache 1\n";
+
+/// A directory of parsed inputs, keyed by the contents of their files, so merging the same
+/// inputs again skips parsing (and repairing or converting) all but those that changed.
+///
+/// Documents are kept in a layout of their own that loads without a PDF parser. Encrypted inputs
+/// are never cached, their decrypted objects would end up on disk.
+#[derive(Debug, Clone)]
+pub struct Cache {
+    dir: PathBuf,
+}
+
+impl Cache {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Cache { dir: dir.into() }
+    }
+
+    /// The document at `path` as cached when its file had the same contents and was loaded with
+    /// the same `lenient`, else `load`ed and cached for the next time.
+    pub fn load(&self, path: &Path, lenient: bool, load: impl FnOnce() -> crate::Result<Document>) -> crate::Result<Document> {
+        let Ok(bytes) = fs::read(path) else { return load() };
+        if bytes.windows(8).any(|window| window == b"/Encrypt") {
+            return load();
+        }
+        let mut hasher = sha256::Sha256::new();
+        hasher.update(MAGIC);
+        hasher.update(env!("CARGO_PKG_VERSION").as_bytes());
+        hasher.update(&[lenient as u8]);
+        hasher.update(&bytes);
+        let key = sha256::hex(&hasher.finalize());
+        let file = self.dir.join(format!("{}.bin", key));
+        if let Some(doc) = fs::read(&file).ok().and_then(|data| decode(&data)) {
+            log::debug!(path:% = path.display(); "read from the cache");
+            return Ok(doc);
+        }
+        let doc = load()?;
+        // Written aside and moved in place, a merge that dies halfway leaves no broken entry
+        let partial = self.dir.join(format!("{}.{}.partial", key, std::process::id()));
+        let stored = fs::create_dir_all(&self.dir).and_then(|_| fs::write(&partial, encode(&doc))).and_then(|_| fs::rename(&partial, &file));
+        if let Err(e) = stored {
+            let _ = fs::remove_file(&partial);
+            log::warn!("{}", tr("{} could not be cached in {}: {}", &[&path.display(), &self.dir.display(), &e]));
+        }
+        Ok(doc)
+    }
+}
+
+fn encode(doc: &Document) -> Vec<u8> {
+    let mut out = MAGIC.to_vec();
+    put_bytes(&mut out, doc.version.as_bytes());
+    out.extend_from_slice(&doc.max_id.to_le_bytes());
+    put_dictionary(&mut out, &doc.trailer);
+    out.extend_from_slice(&(doc.objects.len() as u64).to_le_bytes());
+    for (&(id, generation), object) in &doc.objects {
+        out.extend_from_slice(&id.to_le_bytes());
+        out.extend_from_slice(&generation.to_le_bytes());
+        put_object(&mut out, object);
+    }
+    out
+}
+
+fn put_bytes(out: &mut Vec<u8>, bytes: &[u8]) {
+    out.extend_from_slice(&(bytes.len() as u64).to_le_bytes());
+    out.extend_from_slice(bytes);
+}
+
+fn put_dictionary(out: &mut Vec<u8>, dict: &Dictionary) {
+    out.extend_from_slice(&(dict.len() as u64).to_le_bytes());
+    for (key, value) in dict.iter() {
+        put_bytes(out, key);
+        put_object(out, value);
+    }
+}
+
+fn put_object(out: &mut Vec<u8>, object: &Object) {
+    match object {
+        Object::Null => out.push(0),
+        Object::Boolean(value) => out.extend_from_slice(&[1, *value as u8]),
+        Object::Integer(value) => {
+            out.push(2);
+            out.extend_from_slice(&value.to_le_bytes());
+        }
+        Object::Real(value) => {
+            out.push(3);
+            out.extend_from_slice(&value.to_le_bytes());
+        }
+        Object::Name(name) => {
+            out.push(4);
+            put_bytes(out, name);
+        }
+        Object::String(text, format) => {
+            out.extend_from_slice(&[5, matches!(format, StringFormat::Hexadecimal) as u8]);
+            put_bytes(out, text);
+        }
+        Object::Array(items) => {
+            out.push(6);
+            out.extend_from_slice(&(items.len() as u64).to_le_bytes());
+            items.iter().for_each(|item| put_object(out, item));
+        }
+        Object::Dictionary(dict) => {
+            out.push(7);
+            put_dictionary(out, dict);
+        }
+        Object::Stream(stream) => {
+            out.extend_from_slice(&[8, stream.allows_compression as u8]);
+            put_dictionary(out, &stream.dict);
+            put_bytes(out, &stream.content);
+        }
+        Object::Reference((id, generation)) => {
+            out.push(9);
+            out.extend_from_slice(&id.to_le_bytes());
+            out.extend_from_slice(&generation.to_le_bytes());
+        }
+    }
+}
+
+/// The document `encode` wrote to `data`, `None` if it is not one.
+fn decode(data: &[u8]) -> Option<Document> {
+    let mut reader = Reader(data.strip_prefix(MAGIC)?);
+    let mut doc = Document::new();
+    doc.version = String::from_utf8(reader.bytes()?.to_vec()).ok()?;
+    doc.max_id = u32::from_le_bytes(reader.take()?);
+    doc.trailer = reader.dictionary()?;
+    for _ in 0..reader.len()? {
+        let id = u32::from_le_bytes(reader.take()?);
+        let generation = u16::from_le_bytes(reader.take()?);
+        doc.objects.insert((id, generation), reader.object()?);
+    }
+    reader.0.is_empty().then_some(doc)
+}
+
+/// What is left to read of a cache file.
+struct Reader<'a>(&'a [u8]);
+
+impl<'a> Reader<'a> {
+    fn take<const N: usize>(&mut self) -> Option<[u8; N]> {
+        let (bytes, rest) = self.0.split_first_chunk::<N>()?;
+        self.0 = rest;
+        Some(*bytes)
+    }
+
+    /// A length, which can be no more than the bytes left
+    fn len(&mut self) -> Option<usize> {
+        usize::try_from(u64::from_le_bytes(self.take()?)).ok().filter(|len| *len <= self.0.len())
+    }
+
+    fn bytes(&mut self) -> Option<&'a [u8]> {
+        let len = self.len()?;
+        let (bytes, rest) = self.0.split_at(len);
+        self.0 = rest;
+        Some(bytes)
+    }
+
+    fn dictionary(&mut self) -> Option<Dictionary> {
+        let mut dict = Dictionary::new();
+        for _ in 0..self.len()? {
+            let key = self.bytes()?.to_vec();
+            dict.set(key, self.object()?);
+        }
+        Some(dict)
+    }
+
+    fn object(&mut self) -> Option<Object> {
+        let [tag] = self.take()?;
+        Some(match tag {
+            0 => Object::Null,
+            1 => Object::Boolean(self.take::<1>()?[0] != 0),
+            2 => Object::Integer(i64::from_le_bytes(self.take()?)),
+            3 => Object::Real(f32::from_le_bytes(self.take()?)),
+            4 => Object::Name(self.bytes()?.to_vec()),
+            5 => {
+                let format = if self.take::<1>()?[0] != 0 { StringFormat::Hexadecimal } else { StringFormat::Literal };
+                Object::String(self.bytes()?.to_vec(), format)
+            }
+            6 => Object::Array((0..self.len()?).map(|_| self.object()).collect::<Option<_>>()?),
+            7 => Object::Dictionary(self.dictionary()?),
+            8 => {
+                let allows_compression = self.take::<1>()?[0] != 0;
+                let dict = self.dictionary()?;
+                let mut stream = Stream::new(dict, self.bytes()?.to_vec());
+                stream.allows_compression = allows_compression;
+                Object::Stream(stream)
+            }
+            9 => Object::Reference((u32::from_le_bytes(self.take()?), u16::from_le_bytes(self.take()?))),
+            _ => return None,
+        })
+    }
+}
@@ -0,0 +1,76 @@
+use std::collections::BTreeMap;
+
+use lopdf::{Document, Object, ObjectId, Stream};
+
+/// How many stream objects of one source document were compressed or left alone.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct CompressionStats {
+    pub compressed: usize,
+    pub skipped: usize,
+}
+
+/// Compress the streams of `doc`, except those whose source document opted out.
+///
+/// `provenance` maps each object to the index of the input it was copied from and `enabled`
+/// holds the per-input setting. Objects without provenance (the ones we synthesize) are compressed.
+pub fn compress_by_source(
+    doc: &mut Document,
+    provenance: &BTreeMap<ObjectId, usize>,
+    enabled: &[bool],
+) -> Vec<CompressionStats> {
+    let mut stats = vec![CompressionStats::default(); enabled.len()];
+    for (id, object) in doc.objects.iter_mut() {
+        let Object::Stream(ref mut stream) = *object else { continue };
+        let source = provenance.get(id).copied();
+        if source.is_some_and(|i| !enabled[i]) {
+            if let Some(s) = source.and_then(|i| stats.get_mut(i)) {
+                s.skipped += 1;
+            }
+            continue;
+        }
+        if compress_stream(stream) {
+            if let Some(s) = source.and_then(|i| stats.get_mut(i)) {
+                s.compressed += 1;
+            }
+        }
+    }
+    stats
+}
+
+/// Compress every stream of a single input document.
+pub fn compress_document(doc: &mut Document) -> CompressionStats {
+    let mut stats = CompressionStats::default();
+    for object in doc.objects.values_mut() {
+        if let Object::Stream(ref mut stream) = *object {
+            if compress_stream(stream) {
+                stats.compressed += 1;
+            }
+        }
+    }
+    stats
+}
+
+/// Undo the filters of every stream of `doc` that only has filters lopdf can decode, so the
+/// output can be read in a text editor; images keep theirs. Returns how many were decoded.
+pub fn decompress_document(doc: &mut Document) -> usize {
+    let mut decompressed = 0;
+    for object in doc.objects.values_mut() {
+        if let Object::Stream(ref mut stream) = *object {
+            if stream.dict.has(b"Filter") {
+                stream.decompress();
+                decompressed += usize::from(!stream.dict.has(b"Filter"));
+            }
+        }
+    }
+    decompressed
+}
+
+/// Flate-compress a stream that has no filter yet, returning whether it is compressed now.
+fn compress_stream(stream: &mut Stream) -> bool {
+    if !stream.allows_compression || stream.dict.has(b"Filter") {
+        return false;
+    }
+    // Ignore any error and continue to compress other streams, same as Document::compress
+    let _ = stream.compress();
+    stream.dict.has(b"Filter")
+}
@@ -0,0 +1,78 @@
+use std::{
+    env, fs,
+    io::ErrorKind,
+    path::{Path, PathBuf},
+};
+
+use clap::ValueEnum;
+
+use merge_pdf::{messages::tr, outline::{BookmarkColor, BookmarkMode, BookmarkStyle}, output::OnExisting, sort::SortOrder};
+
+use crate::toml;
+
+/// Defaults from the user's config file. Every setting is overridden by its command line flag.
+#[derive(Debug, Default)]
+pub struct Config {
+    pub predir: Option<PathBuf>,
+    pub output: Option<String>,
+    pub on_existing: Option<OnExisting>,
+    pub sort: Option<SortOrder>,
+    pub bookmarks: Option<BookmarkMode>,
+    pub bookmark_color: Option<BookmarkColor>,
+    pub bookmark_style: Option<BookmarkStyle>,
+    pub bookmark_template: Option<String>,
+    pub compress: Option<bool>,
+    pub confirm_threshold: Option<usize>,
+}
+
+/// `$XDG_CONFIG_HOME/merge_pdf/config.toml`, falling back to `~/.config/merge_pdf/config.toml`.
+pub fn default_path() -> Option<PathBuf> {
+    let base = env::var_os("XDG_CONFIG_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))?;
+    Some(base.join("merge_pdf").join("config.toml"))
+}
+
+/// Read the config at `path`, or at the default location when none is given. A missing default
+/// config is not an error, a missing explicit one is.
+pub fn load(path: Option<&Path>) -> merge_pdf::Result<Config> {
+    let (path, explicit) = match path {
+        Some(path) => (path.to_path_buf(), true),
+        None => match default_path() {
+            Some(path) => (path, false),
+            None => return Ok(Config::default()),
+        },
+    };
+    let text = match fs::read_to_string(&path) {
+        Ok(text) => text,
+        Err(e) if !explicit && e.kind() == ErrorKind::NotFound => return Ok(Config::default()),
+        Err(e) => return Err(merge_pdf::Error::Invalid(tr("{}: {}", &[&path.display(), &e]))),
+    };
+    parse(&text).map_err(|e| merge_pdf::Error::Invalid(tr("{}: {}", &[&path.display(), &e])))
+}
+
+fn parse(text: &str) -> Result<Config, String> {
+    let mut config = Config::default();
+    for (key, value) in toml::parse(text)? {
+        let string = || value.as_str().ok_or_else(|| format!("`{}` must be a string", key));
+        let choice = |s: &str| s.replace('_', "-");
+        match key.as_str() {
+            "predir" => config.predir = Some(PathBuf::from(string()?)),
+            "output" => config.output = Some(string()?.to_string()),
+            "on_existing" => config.on_existing = Some(OnExisting::from_str(&choice(string()?), true)?),
+            "sort" => config.sort = Some(SortOrder::from_str(&choice(string()?), true)?),
+            "bookmarks" => config.bookmarks = Some(BookmarkMode::from_str(&choice(string()?), true)?),
+            "bookmark_color" => config.bookmark_color = Some(string()?.parse()?),
+            "bookmark_style" => config.bookmark_style = Some(BookmarkStyle::from_str(&choice(string()?), true)?),
+            "bookmark_template" => config.bookmark_template = Some(string()?.to_string()),
+            "compress" => config.compress = Some(value.as_bool().ok_or("`compress` must be true or false")?),
+            "confirm_threshold" => {
+                let n = value.as_i64().filter(|n| *n >= 0).ok_or("`confirm_threshold` must be a number")?;
+                config.confirm_threshold = Some(n as usize);
+            }
+            _ => log::warn!("{}", tr("unknown config key `{}`", &[&key])),
+        }
+    }
+    Ok(config)
+}
@@ -0,0 +1,29 @@
+use std::{
+    io::{self, BufRead, IsTerminal, Write},
+    path::PathBuf,
+};
+
+use merge_pdf::messages::{lang, tr, Lang};
+
+/// Print the inputs about to be merged and ask the user whether to go on.
+///
+/// Without a terminal to ask on, only `assume_yes` lets the merge proceed.
+pub fn confirm(inputs: &[(PathBuf, usize)], assume_yes: bool) -> io::Result<bool> {
+    let total: usize = inputs.iter().map(|(_, pages)| pages).sum();
+    println!("{}", tr("About to merge {} files ({} pages):", &[&inputs.len(), &total]));
+    for (path, pages) in inputs {
+        println!("  {}", tr("{} ({} pages)", &[&path.display(), pages]));
+    }
+    if assume_yes {
+        return Ok(true);
+    }
+    if !io::stdin().is_terminal() {
+        log::error!("{}", tr("not running on a terminal, pass --yes to merge without confirmation", &[]));
+        return Ok(false);
+    }
+    print!("{} ", tr("Proceed? [y/N]", &[]));
+    io::stdout().flush()?;
+    let mut answer = String::new();
+    io::stdin().lock().read_line(&mut answer)?;
+    Ok(matches!(answer.trim(), "y" | "Y" | "yes" | "Yes") || (lang() == Lang::De && matches!(answer.trim(), "j" | "J" | "ja" | "Ja")))
+}
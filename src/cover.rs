@@ -0,0 +1,70 @@
+use lopdf::{dictionary, Dictionary, Object, ObjectId, Stream};
+
+use crate::pages::{self, Objects};
+
+/// A page, or pages, put before everything else in the merged document.
+#[derive(Debug, Clone)]
+pub enum Cover {
+    /// All pages of this document.
+    Document(Box<lopdf::Document>),
+    /// A generated page showing the title, the date and how many documents were merged. `{date}`
+    /// and `{count}` in the title are replaced by the same.
+    Generated { title: String, date: String },
+}
+
+impl Cover {
+    /// The cover's pages and objects like `pages::all_pages` gives them. A generated cover
+    /// counts `documents` and is sized by `media_box`.
+    pub fn pages(&self, documents: usize, media_box: [f32; 4], max_id: &mut u32) -> crate::Result<(Vec<(ObjectId, Object)>, Objects)> {
+        match self {
+            Cover::Document(doc) => pages::all_pages(doc, max_id),
+            Cover::Generated { title, date } => {
+                let count = if documents == 1 { "1 document".to_string() } else { format!("{} documents", documents) };
+                let title = title.replace("{date}", date).replace("{count}", &documents.to_string());
+                let (page_id, font_id, content_id) = ((*max_id, 0), (*max_id + 1, 0), (*max_id + 2, 0));
+                *max_id += 3;
+
+                let [left, bottom, right, top] = media_box;
+                let margin = 72.0_f32.min((right - left).min(top - bottom) / 8.5);
+                let title_size = 28.0_f32.min((top - bottom) / 12.0);
+                let text_size = title_size / 2.0;
+                let x = left + margin;
+                let y = bottom + (top - bottom) * 2.0 / 3.0;
+                let content = format!(
+                    "BT /F1 {} Tf {} {} Td {} Tj ET\nBT /F1 {} Tf {} {} Td {} Tj ET\nBT /F1 {} Tf {} {} Td {} Tj ET\n",
+                    title_size,
+                    x,
+                    y,
+                    crate::toc::literal(&title),
+                    text_size,
+                    x,
+                    y - 2.0 * title_size,
+                    crate::toc::literal(date),
+                    text_size,
+                    x,
+                    y - 2.0 * title_size - 1.5 * text_size,
+                    crate::toc::literal(&count),
+                );
+                let page = dictionary! {
+                    "Type" => "Page",
+                    "MediaBox" => media_box.iter().map(|&v| v.into()).collect::<Vec<Object>>(),
+                    "Contents" => content_id,
+                    "Resources" => dictionary! { "Font" => dictionary! { "F1" => font_id } },
+                };
+                let mut objects = Objects::new();
+                objects.insert(page_id, Object::Dictionary(page.clone()));
+                objects.insert(
+                    font_id,
+                    Object::Dictionary(dictionary! {
+                        "Type" => "Font",
+                        "Subtype" => "Type1",
+                        "BaseFont" => "Helvetica",
+                        "Encoding" => "WinAnsiEncoding",
+                    }),
+                );
+                objects.insert(content_id, Object::Stream(Stream::new(Dictionary::new(), content.into_bytes())));
+                Ok((vec![(page_id, Object::Dictionary(page))], objects))
+            }
+        }
+    }
+}
@@ -0,0 +1,327 @@
+use std::{
+    collections::HashSet,
+    path::Path,
+    sync::{Mutex, PoisonError, RwLock},
+};
+
+use lopdf::{xref::XrefEntry, Dictionary, Document, Object, ObjectId};
+use md5::{Digest, Md5};
+
+use crate::{aes::Aes, encrypt, messages::tr, sha256};
+
+/// What passwords are padded with for revisions 2 to 4 (PDF 32000-1, algorithm 2).
+const PADDING: [u8; 32] = [
+    0x28, 0xbf, 0x4e, 0x5e, 0x4e, 0x75, 0x8a, 0x41, 0x64, 0x00, 0x4e, 0x56, 0xff, 0xfa, 0x01, 0x08, 0x2e, 0x2e, 0x00, 0xb6, 0xd0, 0x68,
+    0x3e, 0x80, 0x2f, 0x0c, 0xa9, 0xfe, 0x64, 0x53, 0x69, 0x7a,
+];
+
+/// The decryptor of the document being loaded again for its object streams, see
+/// `decrypt_object_stream`; `LOADING` makes such loads take turns.
+static DECRYPTOR: RwLock<Option<Decryptor>> = RwLock::new(None);
+static LOADING: Mutex<()> = Mutex::new(());
+
+/// How strings or streams are encrypted, by the crypt filter (`/CFM`) that applies to them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Method {
+    Identity,
+    Rc4,
+    Aes128,
+    Aes256,
+}
+
+/// The file key of an encrypted document and how it is used.
+struct Decryptor {
+    key: Vec<u8>,
+    strings: Method,
+    streams: Method,
+    /// Whether the XMP metadata streams are encrypted too
+    metadata: bool,
+}
+
+/// Decrypt `doc`, loaded from `bytes` with lopdf, which leaves it encrypted, with the first of
+/// `passwords` that is its user or its owner password, or else with the empty user password of
+/// documents that only restrict what may be done with them. The standard security handler is supported,
+/// RC4 and AES alike (revisions 2 to 6).
+pub fn decrypt(path: &Path, bytes: &[u8], mut doc: Document, passwords: &[&str]) -> crate::Result<Document> {
+    let invalid = |message: String| crate::Error::Invalid(tr("{} {}", &[&path.display(), &message]));
+    let encrypt_id = doc.trailer.get(b"Encrypt").and_then(Object::as_reference).ok();
+    let dict = doc.trailer.get_deref(b"Encrypt", &doc).and_then(Object::as_dict)?.clone();
+    let handler = dict.get(b"Filter").and_then(Object::as_name).unwrap_or(b"Standard");
+    if handler != b"Standard" {
+        return Err(invalid(tr("is encrypted for the {} security handler, only passwords are supported", &[&String::from_utf8_lossy(handler)])));
+    }
+    let id = doc.trailer.get(b"ID").and_then(Object::as_array).ok().and_then(|id| id.first()).and_then(|id| id.as_str().ok()).unwrap_or_default();
+    let mut opened = None;
+    for password in passwords.iter().chain([&""]) {
+        opened = Decryptor::new(&dict, id, password).map_err(|e| invalid(tr("is encrypted in a way that is not supported: {}", &[&e])))?;
+        if opened.is_some() {
+            break;
+        }
+    }
+    let Some(mut decryptor) = opened else {
+        return Err(crate::Error::EncryptedInput { path: path.to_path_buf(), tried: passwords.len() });
+    };
+
+    let in_streams = doc
+        .reference_table
+        .entries
+        .iter()
+        .filter(|(_, entry)| matches!(entry, XrefEntry::Compressed { .. }))
+        .map(|(number, _)| *number)
+        .collect::<HashSet<_>>();
+    if !in_streams.is_empty() {
+        // lopdf could not read the objects in object streams while they were encrypted
+        let _turn = LOADING.lock().unwrap_or_else(PoisonError::into_inner);
+        *DECRYPTOR.write().unwrap_or_else(PoisonError::into_inner) = Some(decryptor);
+        let reloaded = lopdf::Reader { buffer: bytes, document: Document::new() }.read(Some(decrypt_object_stream));
+        decryptor = DECRYPTOR.write().unwrap_or_else(PoisonError::into_inner).take().expect("the decryptor was set above");
+        doc = reloaded?;
+    }
+    for (id, object) in doc.objects.iter_mut() {
+        // Objects in object streams were decrypted with them
+        if Some(*id) != encrypt_id && !in_streams.contains(&id.0) {
+            decryptor.decrypt(*id, object);
+        }
+    }
+    doc.trailer.remove(b"Encrypt");
+    if let Some(id) = encrypt_id {
+        doc.objects.remove(&id);
+    }
+    Ok(doc)
+}
+
+/// Decrypt object streams as lopdf reads them, before it takes them apart.
+fn decrypt_object_stream(id: ObjectId, object: &mut Object) -> Option<(ObjectId, Object)> {
+    if let Object::Stream(stream) = object {
+        if stream.dict.type_is(b"ObjStm") {
+            if let Some(decryptor) = DECRYPTOR.read().unwrap_or_else(PoisonError::into_inner).as_ref() {
+                let content = decryptor.decrypt_bytes(id, decryptor.streams, &stream.content);
+                stream.set_content(content);
+            }
+        }
+    }
+    Some((id, object.clone()))
+}
+
+impl Decryptor {
+    /// The decryptor for the encryption dictionary `dict` if `password` opens the document, the
+    /// first part of the file identifier `id` going into the key of revisions 2 to 4; an error
+    /// if the encryption is not supported.
+    fn new(dict: &Dictionary, id: &[u8], password: &str) -> Result<Option<Decryptor>, String> {
+        let number = |key: &[u8]| dict.get(key).and_then(Object::as_i64).ok();
+        let string = |key: &[u8]| dict.get(key).and_then(Object::as_str).map_err(|_| format!("/{} is missing", String::from_utf8_lossy(key)));
+        let version = number(b"V").unwrap_or(0);
+        let revision = number(b"R").ok_or("/R is missing")?;
+        let metadata = dict.get(b"EncryptMetadata").and_then(Object::as_bool).unwrap_or(true);
+        let (owner, user) = (string(b"O")?, string(b"U")?);
+
+        let method = |key: &[u8]| -> Result<Method, String> {
+            if version < 4 {
+                return Ok(Method::Rc4);
+            }
+            let name = dict.get(key).and_then(Object::as_name).unwrap_or(b"Identity");
+            if name == b"Identity" {
+                return Ok(Method::Identity);
+            }
+            let filter = dict
+                .get(b"CF")
+                .and_then(Object::as_dict)
+                .and_then(|filters| filters.get(name))
+                .and_then(Object::as_dict)
+                .map_err(|_| format!("crypt filter {} is missing", String::from_utf8_lossy(name)))?;
+            match filter.get(b"CFM").and_then(Object::as_name).unwrap_or(b"None") {
+                b"None" => Ok(Method::Identity),
+                b"V2" => Ok(Method::Rc4),
+                b"AESV2" => Ok(Method::Aes128),
+                b"AESV3" => Ok(Method::Aes256),
+                other => Err(format!("crypt filter method {}", String::from_utf8_lossy(other))),
+            }
+        };
+        let (strings, streams) = (method(b"StrF")?, method(b"StmF")?);
+
+        let key = match revision {
+            2..=4 => {
+                let length = match version {
+                    1 => 5,
+                    4 => 16,
+                    _ => number(b"Length").unwrap_or(40) as usize / 8,
+                };
+                if !(5..=16).contains(&length) {
+                    return Err(format!("key length of {} bits", length * 8));
+                }
+                let legacy = Legacy { revision, owner, user, permissions: number(b"P").ok_or("/P is missing")?, id, length, metadata };
+                legacy.key(password)
+            }
+            5 | 6 => {
+                let (owner_wrapped, user_wrapped) = (string(b"OE")?, string(b"UE")?);
+                if owner.len() < 48 || user.len() < 48 || owner_wrapped.len() < 32 || user_wrapped.len() < 32 {
+                    return Err("/O, /U, /OE or /UE is too short".to_string());
+                }
+                let hash = |salt: &[u8], udata: &[u8]| match revision {
+                    5 => sha256::digest(&[encrypt::password(password), salt, udata].concat()),
+                    _ => encrypt::hash(encrypt::password(password), salt, udata),
+                };
+                let user = &user[..48];
+                let unwrap = |key: [u8; 32], wrapped: &[u8]| Aes::new(&key).decrypt_cbc([0; 16], &wrapped[..32]);
+                if hash(&user[32..40], &[]) == user[..32] {
+                    Some(unwrap(hash(&user[40..48], &[]), user_wrapped))
+                } else if hash(&owner[32..40], user) == owner[..32] {
+                    Some(unwrap(hash(&owner[40..48], user), owner_wrapped))
+                } else {
+                    None
+                }
+            }
+            _ => return Err(format!("revision {}", revision)),
+        };
+        Ok(key.map(|key| Decryptor { key, strings, streams, metadata }))
+    }
+
+    /// Decrypt the strings in `object`, the object `id`, and if it is a stream, its content.
+    fn decrypt(&self, id: ObjectId, object: &mut Object) {
+        match object {
+            Object::String(text, _) => *text = self.decrypt_bytes(id, self.strings, text),
+            Object::Array(items) => items.iter_mut().for_each(|item| self.decrypt(id, item)),
+            Object::Dictionary(dict) => dict.iter_mut().for_each(|(_, value)| self.decrypt(id, value)),
+            Object::Stream(stream) => {
+                // Cross-reference streams are never encrypted, object streams were decrypted on loading
+                if stream.dict.type_is(b"XRef") || stream.dict.type_is(b"ObjStm") {
+                    return;
+                }
+                stream.dict.iter_mut().for_each(|(_, value)| self.decrypt(id, value));
+                if self.metadata || !stream.dict.type_is(b"Metadata") {
+                    let content = self.decrypt_bytes(id, self.streams, &stream.content);
+                    stream.set_content(content);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn decrypt_bytes(&self, id: ObjectId, method: Method, data: &[u8]) -> Vec<u8> {
+        match method {
+            Method::Identity => data.to_vec(),
+            Method::Rc4 => rc4(&self.object_key(id, false), data),
+            Method::Aes128 => aes(&self.object_key(id, true), data),
+            Method::Aes256 => aes(&self.key, data),
+        }
+    }
+
+    /// The key of object `id` for revisions 2 to 4 (PDF 32000-1, algorithm 1).
+    fn object_key(&self, id: ObjectId, aes: bool) -> Vec<u8> {
+        let mut hasher = Md5::new();
+        hasher.update(&self.key);
+        hasher.update(&id.0.to_le_bytes()[..3]);
+        hasher.update(id.1.to_le_bytes());
+        if aes {
+            hasher.update(b"sAlT");
+        }
+        hasher.finalize()[..(self.key.len() + 5).min(16)].to_vec()
+    }
+}
+
+/// The encryption dictionary of revisions 2 to 4, which derive the file key with MD5 and RC4.
+struct Legacy<'a> {
+    revision: i64,
+    owner: &'a [u8],
+    user: &'a [u8],
+    permissions: i64,
+    id: &'a [u8],
+    length: usize,
+    metadata: bool,
+}
+
+impl Legacy<'_> {
+    /// The file key if `password` is the user or the owner password.
+    fn key(&self, password: &str) -> Option<Vec<u8>> {
+        // Passwords are in PDFDocEncoding, which agrees with Latin-1 on letters
+        let password = password.chars().map(|c| u8::try_from(c).unwrap_or(b'?')).collect::<Vec<_>>();
+        let key = self.user_key(&password);
+        if self.is_user_key(&key) {
+            return Some(key);
+        }
+        // The owner password encrypts the user password (algorithm 7)
+        let mut owner_key = Md5::digest(padded(&password)).to_vec();
+        if self.revision >= 3 {
+            for _ in 0..50 {
+                owner_key = Md5::digest(&owner_key).to_vec();
+            }
+        }
+        owner_key.truncate(self.length);
+        let user_password = match self.revision {
+            2 => rc4(&owner_key, self.owner),
+            _ => (0..=19u8).rev().fold(self.owner.to_vec(), |data, i| rc4(&xor(&owner_key, i), &data)),
+        };
+        let key = self.user_key(&user_password);
+        self.is_user_key(&key).then_some(key)
+    }
+
+    /// The file key from the user password (algorithm 2).
+    fn user_key(&self, password: &[u8]) -> Vec<u8> {
+        let mut hasher = Md5::new();
+        hasher.update(padded(password));
+        hasher.update(self.owner);
+        hasher.update((self.permissions as u32).to_le_bytes());
+        hasher.update(self.id);
+        if self.revision >= 4 && !self.metadata {
+            hasher.update([0xff; 4]);
+        }
+        let mut key = hasher.finalize()[..self.length].to_vec();
+        if self.revision >= 3 {
+            for _ in 0..50 {
+                key = Md5::digest(&key)[..self.length].to_vec();
+            }
+        }
+        key
+    }
+
+    /// Whether `key` reproduces the `/U` string (algorithms 4 and 5).
+    fn is_user_key(&self, key: &[u8]) -> bool {
+        if self.revision == 2 {
+            return rc4(key, &PADDING) == self.user;
+        }
+        let mut hasher = Md5::new();
+        hasher.update(PADDING);
+        hasher.update(self.id);
+        let check = (1..=19u8).fold(rc4(key, &hasher.finalize()), |data, i| rc4(&xor(key, i), &data));
+        self.user.get(..16) == Some(&check[..])
+    }
+}
+
+/// `password` cut or padded to 32 bytes.
+fn padded(password: &[u8]) -> Vec<u8> {
+    let password = &password[..password.len().min(32)];
+    [password, &PADDING[..32 - password.len()]].concat()
+}
+
+fn xor(key: &[u8], value: u8) -> Vec<u8> {
+    key.iter().map(|b| b ^ value).collect()
+}
+
+fn rc4(key: &[u8], data: &[u8]) -> Vec<u8> {
+    let mut state: [u8; 256] = std::array::from_fn(|i| i as u8);
+    let mut j = 0u8;
+    for i in 0..256 {
+        j = j.wrapping_add(state[i]).wrapping_add(key[i % key.len()]);
+        state.swap(i, j as usize);
+    }
+    let (mut i, mut j) = (0u8, 0u8);
+    data.iter()
+        .map(|byte| {
+            i = i.wrapping_add(1);
+            j = j.wrapping_add(state[i as usize]);
+            state.swap(i as usize, j as usize);
+            byte ^ state[state[i as usize].wrapping_add(state[j as usize]) as usize]
+        })
+        .collect()
+}
+
+/// `data`, its first 16 bytes the initialization vector, decrypted and without its padding.
+fn aes(key: &[u8], data: &[u8]) -> Vec<u8> {
+    let Some((iv, data)) = data.split_first_chunk::<16>() else { return Vec::new() };
+    let mut plain = Aes::new(key).decrypt_cbc(*iv, data);
+    let padding = plain.last().copied().unwrap_or(0) as usize;
+    if (1..=16).contains(&padding) && padding <= plain.len() {
+        plain.truncate(plain.len() - padding);
+    }
+    plain
+}
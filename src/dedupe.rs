@@ -0,0 +1,167 @@
+use std::collections::{hash_map::Entry, BTreeMap, HashMap, HashSet};
+
+use lopdf::{Document, Object, ObjectId};
+
+use crate::{input::InputSpec, layers, messages::tr, pages, sha256::Sha256};
+
+/// How deep references are followed into a page's resources before the rest is left out of
+/// its fingerprint.
+const MAX_DEPTH: usize = 32;
+
+/// Leave out the pages `spec` selects from `doc` that are exact duplicates of a page of an
+/// earlier input, saying how many when there are any; returns how many it left out.
+///
+/// `seen` holds the fingerprints of the pages of the earlier inputs and gets those of the pages
+/// kept. Duplicates within one input are kept, only pages repeated across inputs are dropped.
+pub fn drop_duplicate_pages(spec: &mut InputSpec, doc: &Document, seen: &mut HashSet<[u8; 32]>) -> usize {
+    let selected = doc.get_pages().into_iter().filter(|(number, _)| spec.selects(*number)).collect::<Vec<_>>();
+    let fingerprints = selected.iter().map(|(number, id)| (*number, fingerprint(doc, *id))).collect::<Vec<_>>();
+    let kept = fingerprints
+        .iter()
+        .filter(|(_, fingerprint)| fingerprint.is_none_or(|fingerprint| !seen.contains(&fingerprint)))
+        .map(|(number, _)| *number)
+        .collect::<Vec<_>>();
+    seen.extend(fingerprints.into_iter().filter_map(|(_, fingerprint)| fingerprint));
+    let dropped = selected.len() - kept.len();
+    if dropped > 0 {
+        log::info!("{}", tr("{}: dropped {} duplicate page(s)", &[&spec.path.display(), &dropped]));
+        spec.pages = Some(kept.into_iter().collect());
+    }
+    dropped
+}
+
+/// How many streams `share_streams` replaced by another copy, and how long they were.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Shared {
+    pub streams: usize,
+    pub bytes: usize,
+}
+
+impl Shared {
+    /// Say how many streams were shared, if any.
+    pub fn report(&self) {
+        if self.streams > 0 {
+            log::info!("{}", tr("{} duplicate stream(s) replaced by a single copy, {} bytes saved", &[&self.streams, &self.bytes]));
+        }
+    }
+}
+
+/// Keep a single copy of the streams of `doc` that are alike, fonts, color profiles and images
+/// that several inputs embed: a stream like one before it in `doc`, or like one in `seen`, is
+/// removed and the references to it rewritten to that one. Streams are alike when their
+/// dictionaries and decoded content are, with every reference followed, see `hash_object`.
+///
+/// `seen` holds the kept streams of the earlier inputs, when they are written one at a time, and
+/// gets those of `doc`.
+pub fn share_streams(doc: &mut Document, seen: &mut HashMap<[u8; 32], ObjectId>) -> Shared {
+    let mut merged = BTreeMap::new();
+    let mut shared = Shared::default();
+    for (&id, object) in &doc.objects {
+        let Object::Stream(stream) = object else { continue };
+        // What the document was read from, not part of it
+        if matches!(object.type_name(), Ok("XRef" | "ObjStm")) {
+            continue;
+        }
+        let mut hasher = Sha256::new();
+        hash_object(doc, object, &mut hasher, 0);
+        match seen.entry(hasher.finalize()) {
+            Entry::Occupied(kept) => {
+                merged.insert(id, *kept.get());
+                shared.streams += 1;
+                shared.bytes += stream.content.len();
+            }
+            Entry::Vacant(entry) => {
+                entry.insert(id);
+            }
+        }
+    }
+    if merged.is_empty() {
+        return shared;
+    }
+    for id in merged.keys() {
+        doc.objects.remove(id);
+    }
+    for object in doc.objects.values_mut() {
+        layers::replace(object, &merged);
+    }
+    for (_, value) in doc.trailer.iter_mut() {
+        layers::replace(value, &merged);
+    }
+    shared
+}
+
+/// A hash of what a page shows: its decoded content, its resources with every reference
+/// followed, and its boxes and rotation. Pages with the same fingerprint look exactly alike,
+/// whatever document and object numbers they come from; annotations are not compared.
+///
+/// Pages whose content cannot be read get none, so they are never taken for duplicates.
+pub fn fingerprint(doc: &Document, page_id: ObjectId) -> Option<[u8; 32]> {
+    let Ok(Object::Dictionary(page)) = pages::with_inherited_attributes(doc, page_id) else { return None };
+    let content = doc.get_page_content(page_id).ok()?;
+    let mut hasher = Sha256::new();
+    hasher.update(&(content.len() as u64).to_be_bytes());
+    hasher.update(&content);
+    for key in [b"Resources".as_slice(), b"MediaBox", b"CropBox", b"Rotate"] {
+        hasher.update(key);
+        match page.get(key) {
+            Ok(value) => hash_object(doc, value, &mut hasher, 0),
+            Err(_) => hasher.update(b"-"),
+        }
+    }
+    Some(hasher.finalize())
+}
+
+/// Feed `object` into `hasher` unambiguously, references replaced by what they point to and
+/// dictionary keys sorted, so that equal objects hash alike however they are numbered or ordered.
+fn hash_object(doc: &Document, object: &Object, hasher: &mut Sha256, depth: usize) {
+    if depth > MAX_DEPTH {
+        hasher.update(b"...");
+        return;
+    }
+    match object {
+        Object::Reference(id) => match doc.get_object(*id) {
+            Ok(target) => hash_object(doc, target, hasher, depth + 1),
+            Err(_) => hasher.update(b"null"),
+        },
+        Object::Array(items) => {
+            hasher.update(format!("[{}", items.len()).as_bytes());
+            for item in items {
+                hash_object(doc, item, hasher, depth + 1);
+            }
+        }
+        Object::Dictionary(dict) => hash_dictionary(doc, dict, hasher, depth),
+        Object::Stream(stream) => {
+            hash_dictionary(doc, &stream.dict, hasher, depth);
+            let content = stream.decompressed_content().unwrap_or_else(|_| stream.content.clone());
+            hasher.update(format!("stream{}", content.len()).as_bytes());
+            hasher.update(&content);
+        }
+        Object::String(bytes, _) => {
+            hasher.update(format!("s{}:", bytes.len()).as_bytes());
+            hasher.update(bytes);
+        }
+        Object::Name(bytes) => {
+            hasher.update(format!("n{}:", bytes.len()).as_bytes());
+            hasher.update(bytes);
+        }
+        Object::Real(value) => hasher.update(format!("r{}", value).as_bytes()),
+        Object::Integer(value) => hasher.update(format!("i{}", value).as_bytes()),
+        Object::Boolean(value) => hasher.update(format!("b{}", value).as_bytes()),
+        Object::Null => hasher.update(b"null"),
+    }
+}
+
+fn hash_dictionary(doc: &Document, dict: &lopdf::Dictionary, hasher: &mut Sha256, depth: usize) {
+    let mut entries = dict
+        .iter()
+        // Stream lengths and filters change with compression, parents lead back up the page tree
+        .filter(|(key, _)| !matches!(key.as_slice(), b"Length" | b"Filter" | b"DecodeParms" | b"Parent"))
+        .collect::<Vec<_>>();
+    entries.sort_by(|a, b| a.0.cmp(b.0));
+    hasher.update(format!("<<{}", entries.len()).as_bytes());
+    for (key, value) in entries {
+        hasher.update(format!("/{}:", key.len()).as_bytes());
+        hasher.update(key);
+        hash_object(doc, value, hasher, depth + 1);
+    }
+}
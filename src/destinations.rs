@@ -0,0 +1,121 @@
+use std::collections::{HashMap, HashSet};
+
+use lopdf::{Dictionary, Document, Object, StringFormat};
+
+use crate::{attachments, input::InputSpec, messages::tr, text};
+
+/// Named destinations (the catalog's `Dests` and the `Dests` name tree, PDF 32000-1, 12.3.2.3)
+/// of the inputs, gathered into one name tree for the merged document, whose catalog comes from
+/// a single input.
+#[derive(Debug, Clone, Default)]
+pub struct Destinations {
+    names: Vec<(Vec<u8>, Object)>,
+    used: HashSet<Vec<u8>>,
+}
+
+impl Destinations {
+    /// Add the named destinations of `doc`, renumbered as it will be merged, taking them out of
+    /// its catalog. A name an earlier input has is changed to `name (2)` and so on, and so are
+    /// the links and actions of `doc` that go to it. Destinations of pages left out, which
+    /// `annotations::remap` took out, are dropped.
+    pub fn append(&mut self, spec: &InputSpec, doc: &mut Document) {
+        let mut found = Vec::new();
+        let Ok(catalog) = doc.catalog() else { return };
+        if let Ok(dests) = catalog.get_deref(b"Dests", doc).and_then(Object::as_dict) {
+            found.extend(dests.iter().map(|(name, destination)| (name.clone(), destination.clone())));
+        }
+        let names = catalog.get_deref(b"Names", doc).and_then(Object::as_dict).ok().cloned();
+        if let Some(tree) = names.as_ref().and_then(|names| names.get(b"Dests").ok()) {
+            attachments::leaves(doc, tree, 0, &mut found);
+        }
+        if let Ok(catalog) = doc.catalog_mut() {
+            catalog.remove(b"Dests");
+            if let Some(mut names) = names {
+                names.remove(b"Dests");
+                catalog.set("Names", names);
+            }
+        }
+
+        let mut renamed = HashMap::new();
+        let mut kept = HashSet::new();
+        for (name, destination) in found {
+            let target = match &destination {
+                Object::Reference(id) => doc.get_object(*id).unwrap_or(&Object::Null),
+                destination => destination,
+            };
+            // A destination can be a dictionary holding it
+            let gone = match target {
+                Object::Dictionary(dict) => !dict.has(b"D"),
+                target => matches!(target, Object::Null),
+            };
+            if gone || !kept.insert(name.clone()) {
+                continue;
+            }
+            let mut unique = name.clone();
+            let mut n = 1;
+            while self.used.contains(&unique) {
+                n += 1;
+                unique = text::encode(&format!("{} ({})", text::decode(&name), n));
+            }
+            if unique != name {
+                log::info!("{}", tr("{}: destination {} renamed to {}", &[&spec.path.display(), &text::decode(&name), &text::decode(&unique)]));
+            }
+            self.used.insert(unique.clone());
+            self.names.push((unique.clone(), destination));
+            renamed.insert(name, unique);
+        }
+        // Names in the Dests dictionary are names, those of the tree strings; only the tree is left
+        for object in doc.objects.values_mut() {
+            rename(object, &renamed);
+        }
+    }
+
+    /// Give `catalog` a `Dests` name tree of all the destinations, in place of its own.
+    pub fn apply(&self, catalog: &mut Dictionary) {
+        catalog.remove(b"Dests");
+        let mut dictionary = catalog.get(b"Names").and_then(Object::as_dict).cloned().unwrap_or_default();
+        dictionary.remove(b"Dests");
+        if !self.names.is_empty() {
+            // Names in a name tree are sorted by their bytes
+            let mut names = self.names.clone();
+            names.sort_by(|a, b| a.0.cmp(&b.0));
+            let names = names
+                .into_iter()
+                .flat_map(|(name, destination)| [Object::String(name, StringFormat::Literal), destination])
+                .collect::<Vec<_>>();
+            let mut tree = Dictionary::new();
+            tree.set("Names", names);
+            dictionary.set("Dests", tree);
+        }
+        if !dictionary.is_empty() {
+            catalog.set("Names", dictionary);
+        } else {
+            catalog.remove(b"Names");
+        }
+    }
+}
+
+/// Point the links (`Dest`) and go-to actions (`D`) in `object` that go to a destination by name
+/// at its name in the merged tree, removing those to names it does not have: they were dropped,
+/// or never there, and could otherwise go to the destination of another input.
+fn rename(object: &mut Object, renamed: &HashMap<Vec<u8>, Vec<u8>>) {
+    let dict = match object {
+        Object::Dictionary(dict) => dict,
+        Object::Array(items) => return items.iter_mut().for_each(|item| rename(item, renamed)),
+        _ => return,
+    };
+    let go_to = dict.get(b"S").and_then(Object::as_name).ok() == Some(b"GoTo".as_slice());
+    let mut removed = Vec::new();
+    for (key, value) in dict.iter_mut() {
+        match value {
+            Object::Name(name) | Object::String(name, _) if key == b"Dest" || (go_to && key == b"D") => match renamed.get(name.as_slice()) {
+                Some(unique) => *value = Object::String(unique.clone(), StringFormat::Literal),
+                None => removed.push(key.clone()),
+            },
+            value => rename(value, renamed),
+        }
+    }
+    for key in removed {
+        dict.remove(&key);
+    }
+}
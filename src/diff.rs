@@ -0,0 +1,80 @@
+use std::collections::BTreeMap;
+
+use lopdf::{Document, Object, ObjectId};
+
+use crate::{messages::tr, sha256, text};
+
+/// Document information entries compared. The dates are left out: they differ between any two
+/// runs of a merge.
+const METADATA_KEYS: [&str; 6] = ["Title", "Author", "Subject", "Keywords", "Creator", "Producer"];
+
+/// How `a` and `b` differ, a line each: their PDF version, page count, document information
+/// (dates aside), the content streams of every page both have, hashed, and how many objects of
+/// each type they hold. Nothing when they are equivalent, as the same merge run twice makes
+/// them, though objects may be numbered and written differently.
+pub fn diff(a: &Document, b: &Document) -> Vec<String> {
+    let mut differences = Vec::new();
+    if a.version != b.version {
+        differences.push(tr("version: {} and {}", &[&a.version, &b.version]));
+    }
+    let (pages_a, pages_b) = (a.get_pages(), b.get_pages());
+    if pages_a.len() != pages_b.len() {
+        differences.push(tr("pages: {} and {}", &[&pages_a.len(), &pages_b.len()]));
+    }
+    for key in METADATA_KEYS {
+        let (value_a, value_b) = (info(a, key), info(b, key));
+        if value_a != value_b {
+            let show = |value: Option<String>| value.map_or_else(|| tr("none", &[]), |value| format!("{:?}", value));
+            differences.push(tr("{}: {} and {}", &[&key, &show(value_a), &show(value_b)]));
+        }
+    }
+    for ((number, &page_a), &page_b) in pages_a.iter().zip(pages_b.values()) {
+        if content_hash(a, page_a) != content_hash(b, page_b) {
+            differences.push(tr("page {}: the content differs", &[number]));
+        }
+    }
+    if a.objects.len() != b.objects.len() {
+        differences.push(tr("objects: {} and {}", &[&a.objects.len(), &b.objects.len()]));
+    }
+    let (counts_a, counts_b) = (object_counts(a), object_counts(b));
+    let mut kinds = counts_a.keys().chain(counts_b.keys()).collect::<Vec<_>>();
+    kinds.sort();
+    kinds.dedup();
+    for kind in kinds {
+        let (count_a, count_b) = (counts_a.get(kind).copied().unwrap_or(0), counts_b.get(kind).copied().unwrap_or(0));
+        if count_a != count_b {
+            differences.push(tr("{} objects: {} and {}", &[kind, &count_a, &count_b]));
+        }
+    }
+    differences
+}
+
+fn info(doc: &Document, key: &str) -> Option<String> {
+    let info = doc.trailer.get_deref(b"Info", doc).and_then(Object::as_dict).ok()?;
+    info.get_deref(key.as_bytes(), doc).and_then(Object::as_str).ok().map(text::decode)
+}
+
+/// The SHA-256 of a page's content streams, decoded and put together, or `None` when they cannot
+/// be read.
+pub fn content_hash(doc: &Document, page_id: ObjectId) -> Option<[u8; 32]> {
+    doc.get_page_content(page_id).ok().map(|content| sha256::digest(&content))
+}
+
+/// How many objects of `doc` there are of each `Type`, XObjects by `Subtype`.
+fn object_counts(doc: &Document) -> BTreeMap<String, usize> {
+    let mut counts = BTreeMap::new();
+    for object in doc.objects.values() {
+        let dict = match object {
+            Object::Dictionary(dict) => dict,
+            Object::Stream(stream) => &stream.dict,
+            _ => continue,
+        };
+        let Ok(kind) = dict.get(b"Type").and_then(Object::as_name) else { continue };
+        let kind = match dict.get(b"Subtype").and_then(Object::as_name) {
+            Ok(subtype) if kind == b"XObject" => format!("{}/{}", String::from_utf8_lossy(kind), String::from_utf8_lossy(subtype)),
+            _ => String::from_utf8_lossy(kind).into_owned(),
+        };
+        *counts.entry(kind).or_insert(0) += 1;
+    }
+    counts
+}
@@ -0,0 +1,46 @@
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use crate::{input, sort::SortOrder};
+
+/// Limits for collecting the PDFs below a directory input.
+#[derive(Debug, Clone, Copy)]
+pub struct DirOptions {
+    /// How many directory levels to descend; 1 takes only the files directly inside.
+    pub max_depth: Option<usize>,
+    /// Whether files and directories whose names start with a dot are included.
+    pub hidden: bool,
+}
+
+/// Collect every `*.pdf` file below `dir`, ordered by `order` (descending if `reverse`).
+///
+/// Like glob matches, the results keep the form of `dir`: relative to `predir` unless absolute.
+pub fn collect(dir: &Path, predir: &Path, options: DirOptions, order: SortOrder, reverse: bool) -> io::Result<Vec<PathBuf>> {
+    let mut found = Vec::new();
+    walk(&input::resolve(predir, dir), dir, 1, options, &mut found)?;
+    // Directory listings come back in arbitrary order, start from a stable one
+    found.sort();
+    order.sort(&mut found, predir, reverse);
+    Ok(found)
+}
+
+fn walk(dir: &Path, shown: &Path, depth: usize, options: DirOptions, found: &mut Vec<PathBuf>) -> io::Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let name = entry.file_name();
+        if !options.hidden && name.to_string_lossy().starts_with('.') {
+            continue;
+        }
+        let path = entry.path();
+        if path.is_dir() {
+            if options.max_depth.is_none_or(|max| depth < max) {
+                walk(&path, &shown.join(&name), depth + 1, options, found)?;
+            }
+        } else if path.extension().is_some_and(|e| e.eq_ignore_ascii_case("pdf")) {
+            found.push(shown.join(&name));
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,270 @@
+use std::io;
+
+use lopdf::{Dictionary, Document, Object, StringFormat};
+
+use crate::{aes::Aes, sha256, sha512, version_number};
+
+/// What readers who open the output with the user password may do with it (PDF 32000-2,
+/// table 22); the owner password allows everything.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+pub enum Permission {
+    /// Print, at low resolution unless `print-high` is also given
+    Print,
+    /// Print at full resolution
+    PrintHigh,
+    /// Change the content
+    Modify,
+    /// Copy or extract text and images
+    Copy,
+    /// Add or change annotations and fill in forms
+    Annotate,
+    /// Fill in forms, even without `annotate`
+    FillForms,
+    /// Extract text and images for accessibility tools
+    Accessibility,
+    /// Insert, delete and rotate pages and change bookmarks
+    Assemble,
+}
+
+impl Permission {
+    /// The bit of `/P` that allows it, counted from 1 as the standard does.
+    fn bit(self) -> u32 {
+        match self {
+            Permission::Print => 3,
+            Permission::Modify => 4,
+            Permission::Copy => 5,
+            Permission::Annotate => 6,
+            Permission::FillForms => 9,
+            Permission::Accessibility => 10,
+            Permission::Assemble => 11,
+            Permission::PrintHigh => 12,
+        }
+    }
+}
+
+/// Passwords and permissions of encrypted output, see `MergeOptions::encryption`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Encryption {
+    /// Password that opens the output, with `permissions`; empty to open without one
+    pub user_password: String,
+    /// Password that opens the output with every permission
+    pub owner_password: String,
+    /// What the user password allows
+    pub permissions: Vec<Permission>,
+}
+
+/// Encrypts strings and streams with the AES-256 standard security handler (revision 6), for a
+/// key it makes up for each document.
+pub(crate) struct Encryptor {
+    aes: Aes,
+}
+
+impl Encryptor {
+    /// A new file key and the encryption dictionary that unlocks it with the passwords of
+    /// `encryption`.
+    pub(crate) fn new(encryption: &Encryption) -> io::Result<(Encryptor, Dictionary)> {
+        let key = random::<32>()?;
+        // Reserved bits are set, as are the high ones of the 32-bit two's complement value
+        let permissions = encryption.permissions.iter().fold(0xffff_f0c0u32, |p, permission| p | 1 << (permission.bit() - 1));
+
+        let user = password(&encryption.user_password);
+        let (user_validation, user_key) = (random::<8>()?, random::<8>()?);
+        let u = [&hash(user, &user_validation, &[])[..], &user_validation, &user_key].concat();
+        let ue = Aes::new(&hash(user, &user_key, &[])).encrypt_cbc([0; 16], &key);
+
+        let owner = password(&encryption.owner_password);
+        let (owner_validation, owner_key) = (random::<8>()?, random::<8>()?);
+        let o = [&hash(owner, &owner_validation, &u)[..], &owner_validation, &owner_key].concat();
+        let oe = Aes::new(&hash(owner, &owner_key, &u)).encrypt_cbc([0; 16], &key);
+
+        let mut perms = [0u8; 16];
+        perms[..4].copy_from_slice(&permissions.to_le_bytes());
+        perms[4..8].fill(0xff);
+        perms[8..12].copy_from_slice(b"Tadb");
+        perms[12..].copy_from_slice(&random::<4>()?);
+        let aes = Aes::new(&key);
+        aes.encrypt_block(&mut perms);
+
+        let hex = |bytes: &[u8]| Object::String(bytes.to_vec(), StringFormat::Hexadecimal);
+        let mut filter = Dictionary::new();
+        filter.set("AuthEvent", "DocOpen");
+        filter.set("CFM", "AESV3");
+        filter.set("Length", 32);
+        let mut dict = Dictionary::new();
+        dict.set("Filter", "Standard");
+        dict.set("V", 5);
+        dict.set("R", 6);
+        dict.set("Length", 256);
+        dict.set("CF", Dictionary::from_iter(vec![("StdCF", Object::Dictionary(filter))]));
+        dict.set("StmF", "StdCF");
+        dict.set("StrF", "StdCF");
+        dict.set("O", hex(&o));
+        dict.set("U", hex(&u));
+        dict.set("OE", hex(&oe));
+        dict.set("UE", hex(&ue));
+        dict.set("P", permissions as i32 as i64);
+        dict.set("Perms", hex(&perms));
+        Ok((Encryptor { aes }, dict))
+    }
+
+    /// Encrypt the strings in `object` and, if it is a stream, its content.
+    pub(crate) fn encrypt(&self, object: &mut Object) -> io::Result<()> {
+        match object {
+            Object::String(text, format) => {
+                *text = self.encrypt_bytes(text)?;
+                // Encrypted bytes are mostly unprintable
+                *format = StringFormat::Hexadecimal;
+            }
+            Object::Array(items) => items.iter_mut().try_for_each(|item| self.encrypt(item))?,
+            Object::Dictionary(dict) => dict.iter_mut().try_for_each(|(_, value)| self.encrypt(value))?,
+            Object::Stream(stream) => {
+                stream.dict.iter_mut().try_for_each(|(_, value)| self.encrypt(value))?;
+                let content = self.encrypt_bytes(&stream.content)?;
+                stream.set_content(content);
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    /// `data` padded to whole blocks, encrypted and behind the random initialization vector.
+    fn encrypt_bytes(&self, data: &[u8]) -> io::Result<Vec<u8>> {
+        let iv = random::<16>()?;
+        let padding = 16 - data.len() % 16;
+        let mut padded = data.to_vec();
+        padded.resize(data.len() + padding, padding as u8);
+        Ok([iv.to_vec(), self.aes.encrypt_cbc(iv, &padded)].concat())
+    }
+}
+
+/// Encrypt every string and stream of `doc` for `encryption` and add the encryption dictionary
+/// and, if missing, a file identifier to the trailer. Nothing may change `doc` after this.
+pub fn encrypt(doc: &mut Document, encryption: &Encryption) -> crate::Result<()> {
+    let (encryptor, dict) = Encryptor::new(encryption)?;
+    for object in doc.objects.values_mut() {
+        encryptor.encrypt(object)?;
+    }
+    let dict_id = doc.add_object(dict);
+    doc.trailer.set("Encrypt", dict_id);
+    if !doc.trailer.has(b"ID") {
+        doc.trailer.set("ID", file_id()?);
+    }
+    let version = doc.version.clone();
+    doc.version = declare_aes(doc.catalog_mut()?, &version);
+    Ok(())
+}
+
+/// A random file identifier, the same for both halves as the file was never changed.
+pub(crate) fn file_id() -> io::Result<Vec<Object>> {
+    let id = Object::String(random::<16>()?.to_vec(), StringFormat::Hexadecimal);
+    Ok(vec![id.clone(), id])
+}
+
+/// Declare in `catalog` that AES-256 is used and return the version the file must then have:
+/// PDF 2.0 has it, PDF 1.7 through Adobe's extension level 8.
+pub(crate) fn declare_aes(catalog: &mut Dictionary, version: &str) -> String {
+    if version_number(version) >= Some((2, 0)) {
+        return version.to_string();
+    }
+    let mut adbe = Dictionary::new();
+    adbe.set("BaseVersion", Object::Name(b"1.7".to_vec()));
+    adbe.set("ExtensionLevel", 8);
+    catalog.set("Extensions", Dictionary::from_iter(vec![("ADBE", Object::Dictionary(adbe))]));
+    "1.7".to_string()
+}
+
+/// A password as revision 6 takes it: UTF-8, at most 127 bytes.
+pub(crate) fn password(password: &str) -> &[u8] {
+    let mut end = password.len().min(127);
+    while !password.is_char_boundary(end) {
+        end -= 1;
+    }
+    &password.as_bytes()[..end]
+}
+
+/// The password hash of revision 6 (PDF 32000-2, algorithm 2.B), `udata` being the `/U` string
+/// when hashing the owner password.
+pub(crate) fn hash(password: &[u8], salt: &[u8], udata: &[u8]) -> [u8; 32] {
+    let mut k = sha256::digest(&[password, salt, udata].concat()).to_vec();
+    let mut round = 0;
+    loop {
+        let k1 = [password, &k, udata].concat().repeat(64);
+        let iv = <[u8; 16]>::try_from(&k[16..32]).unwrap();
+        let e = Aes::new(&k[..16]).encrypt_cbc(iv, &k1);
+        // The first 16 bytes as a big-endian number modulo 3, which is their sum modulo 3
+        k = match e[..16].iter().map(|&b| b as u32).sum::<u32>() % 3 {
+            0 => sha256::digest(&e).to_vec(),
+            1 => sha512::digest384(&e).to_vec(),
+            _ => sha512::digest(&e).to_vec(),
+        };
+        round += 1;
+        if round >= 64 && u32::from(*e.last().unwrap()) <= round - 32 {
+            break;
+        }
+    }
+    k[..32].try_into().unwrap()
+}
+
+/// `N` random bytes from the operating system, or an error where it has none to give: keys made
+/// up any other way could be guessed.
+#[cfg(not(all(target_arch = "wasm32", target_os = "unknown")))]
+fn random<const N: usize>() -> io::Result<[u8; N]> {
+    let mut bytes = [0u8; N];
+    getrandom::getrandom(&mut bytes)?;
+    Ok(bytes)
+}
+
+#[cfg(all(target_arch = "wasm32", target_os = "unknown"))]
+fn random<const N: usize>() -> io::Result<[u8; N]> {
+    Err(io::Error::new(io::ErrorKind::Unsupported, crate::messages::tr("there is no random number generator to make encryption keys with", &[])))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use lopdf::{dictionary, Stream};
+
+    use super::*;
+
+    /// Known answers of algorithm 2.B, worked out apart from this code with the SHA-2 and AES of
+    /// a standard library: an empty password, a user password and an owner password over `/U`.
+    #[test]
+    fn hash_matches_known_answers() {
+        assert_eq!(sha256::hex(&hash(b"", &[1, 2, 3, 4, 5, 6, 7, 8], &[])), "8d1efb4f1bdbb651341704c2139de4f6be05d6d4609af56916b21646ed74825c");
+        assert_eq!(sha256::hex(&hash(b"user", b"saltsalt", &[])), "1beccb72cb28d43491db899cba6e2be838828ec0e51e0ae3f912aff2d75ad148");
+        let udata = (0..48).collect::<Vec<u8>>();
+        assert_eq!(
+            sha256::hex(&hash(password("pässwort"), b"ownersal", &udata)),
+            "8c254ed0e1488320d67c00a11bfd0dab0858af32832a18b0eb1ebb609155010a"
+        );
+    }
+
+    #[test]
+    fn passwords_are_cut_at_127_bytes_between_characters() {
+        assert_eq!(password(&"a".repeat(200)).len(), 127);
+        // 63 two-byte characters fit behind the `a`, without it the 64th would be split
+        assert_eq!(password(&format!("a{}", "ä".repeat(64))).len(), 127);
+        assert_eq!(password(&"ä".repeat(64)).len(), 126);
+    }
+
+    #[test]
+    fn encrypted_documents_open_with_either_password() {
+        let encryption = Encryption { user_password: "user".to_string(), owner_password: "owner".to_string(), permissions: vec![Permission::Print] };
+        for password in ["user", "owner"] {
+            let mut doc = crate::tests::document(1);
+            let secret = doc.add_object(Stream::new(dictionary! {}, b"BT (secret) Tj ET".to_vec()));
+            encrypt(&mut doc, &encryption).unwrap();
+            let mut bytes = Vec::new();
+            doc.save_to(&mut bytes).unwrap();
+            assert!(!bytes.windows(6).any(|w| w == b"secret"));
+
+            let loaded = Document::load_mem(&bytes).unwrap();
+            assert!(crate::decrypt::decrypt(Path::new("a.pdf"), &bytes, loaded.clone(), &["wrong"]).is_err());
+            let decrypted = crate::decrypt::decrypt(Path::new("a.pdf"), &bytes, loaded, &[password]).unwrap();
+            let content = &decrypted.get_object(secret).unwrap().as_stream().unwrap().content;
+            assert_eq!(content, b"BT (secret) Tj ET", "{}", password);
+        }
+    }
+}
@@ -0,0 +1,131 @@
+use std::{
+    fmt, io,
+    path::{Path, PathBuf},
+};
+
+use crate::messages::tr;
+
+/// `Result` with the errors of a merge.
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// Why a merge, or another command on PDFs, failed. Every kind has an exit code of its own, see
+/// `exit_code`, for scripts to tell them apart.
+#[derive(Debug)]
+pub enum Error {
+    /// An input does not exist or cannot be read
+    MissingInput(PathBuf, io::Error),
+    /// An input is encrypted and none of the passwords tried, `tried` of them, opens it
+    EncryptedInput { path: PathBuf, tried: usize },
+    /// An input is not a PDF that can be read, or repaired when loaded leniently
+    BrokenInput(PathBuf, lopdf::Error),
+    /// No input has any (selected) pages
+    NoPages,
+    /// The input the merged document is built around has no Catalog or page tree root
+    NoPagesRoot,
+    /// Options that don't go together or point past the inputs
+    Usage(String),
+    /// The merge was cancelled when asked to confirm or arrange it
+    Cancelled,
+    /// Two documents compared are not equivalent, in `differences` ways
+    Different { differences: usize },
+    /// The merge was stopped, see `MergeOptions::cancel`
+    Interrupted,
+    /// `--skip-broken` left inputs out, after merging the others
+    SkippedInputs { skipped: usize, total: usize },
+    /// Pages differ in size, orientation or color from most of the merge, in `findings` ways,
+    /// see `preflight::geometry`
+    Mismatched { findings: usize },
+    /// Another process is writing the output, the one of `pid` if the lock tells
+    Locked { path: PathBuf, pid: Option<u32> },
+    /// The output, or another file the merge writes, cannot be written
+    SaveFailed(PathBuf, io::Error),
+    Io(io::Error),
+    /// Anything else wrong with a document
+    Pdf(lopdf::Error),
+    /// Anything else, described
+    Invalid(String),
+}
+
+impl Error {
+    /// `e`, which came of writing `path`, as `SaveFailed` when it is an I/O error.
+    pub fn saving(path: &Path, e: impl Into<Error>) -> Self {
+        match e.into() {
+            Error::Io(e) => Error::SaveFailed(path.to_path_buf(), e),
+            e => e,
+        }
+    }
+
+    /// The exit code of the merge_pdf binary when it fails with this error. Usage errors share
+    /// code 2 with those found when parsing the command line.
+    pub fn exit_code(&self) -> u8 {
+        match self {
+            Error::Invalid(_) | Error::Pdf(_) | Error::Io(_) => 1,
+            Error::Usage(_) => 2,
+            Error::MissingInput(..) => 3,
+            Error::EncryptedInput { .. } => 4,
+            Error::BrokenInput(..) => 5,
+            Error::NoPages => 6,
+            Error::NoPagesRoot => 7,
+            Error::SaveFailed(..) => 8,
+            Error::SkippedInputs { .. } => 9,
+            Error::Cancelled => 10,
+            Error::Different { .. } => 11,
+            Error::Locked { .. } => 12,
+            Error::Mismatched { .. } => 13,
+            // What shells give a process that Ctrl-C ended
+            Error::Interrupted => 130,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let message = match self {
+            Error::MissingInput(path, e) => tr("{} could not be loaded: {}", &[&path.display(), e]),
+            Error::EncryptedInput { path, tried: 0 } => tr("{} is encrypted and needs a password", &[&path.display()]),
+            Error::EncryptedInput { path, tried: 1 } => tr("{} could not be opened, the password is wrong", &[&path.display()]),
+            Error::EncryptedInput { path, tried } => tr("{} could not be opened with any of the {} passwords tried", &[&path.display(), tried]),
+            Error::BrokenInput(path, e) => tr("{} could not be loaded: {}", &[&path.display(), e]),
+            Error::NoPages => tr("no input has any pages", &[]),
+            Error::NoPagesRoot => tr("the root input has no Catalog or Pages root", &[]),
+            Error::Usage(message) | Error::Invalid(message) => return write!(f, "{}", message),
+            Error::Cancelled => tr("merge cancelled", &[]),
+            Error::Different { differences } => tr("the documents differ in {} way(s)", &[differences]),
+            Error::Interrupted => tr("interrupted", &[]),
+            Error::SkippedInputs { skipped, total } => tr("{} of {} inputs were skipped", &[skipped, total]),
+            Error::Mismatched { findings } => tr("pages differ from the rest of the merge in {} way(s)", &[findings]),
+            Error::Locked { path, pid: Some(pid) } => tr("{} is being written by another merge_pdf, process {}", &[&path.display(), pid]),
+            Error::Locked { path, pid: None } => tr("{} is being written by another merge_pdf", &[&path.display()]),
+            Error::SaveFailed(path, e) => tr("{} could not be written: {}", &[&path.display(), e]),
+            Error::Io(e) => return e.fmt(f),
+            Error::Pdf(lopdf::Error::Invalid(message)) => return write!(f, "{}", message),
+            Error::Pdf(e) => return e.fmt(f),
+        };
+        f.write_str(&message)
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::MissingInput(_, e) | Error::SaveFailed(_, e) | Error::Io(e) => Some(e),
+            Error::BrokenInput(_, e) | Error::Pdf(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<lopdf::Error> for Error {
+    fn from(e: lopdf::Error) -> Self {
+        match e {
+            lopdf::Error::IO(e) => Error::Io(e),
+            e => Error::Pdf(e),
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Self {
+        Error::Io(e)
+    }
+}
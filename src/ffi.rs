@@ -0,0 +1,131 @@
+use std::{
+    cell::RefCell,
+    ffi::{c_char, CString},
+    panic::{self, AssertUnwindSafe},
+    ptr, slice,
+};
+
+use crate::{merge_bytes, outline::BookmarkStyle, MergeOptions};
+
+/// Result of a `merge_pdf_*` call.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MergePdfStatus {
+    Ok = 0,
+    /// A required pointer was null, or an option was out of range.
+    InvalidArgument = 1,
+    /// An input could not be parsed or the merge failed, see `merge_pdf_last_error`.
+    MergeFailed = 2,
+    /// The merge engine panicked; no output was produced.
+    Panicked = 3,
+}
+
+/// Settings of a merge, the C counterpart of `MergeOptions`.
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct MergePdfOptions {
+    /// 0 plain, 1 italic, 2 bold, 3 bold italic.
+    pub bookmark_style: u32,
+    pub compress: bool,
+    pub pdfa: bool,
+    /// Input (0-based) whose Catalog and page tree root are kept, negative for the first.
+    pub root: i64,
+}
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+fn set_last_error(message: String) {
+    // Interior NULs would cut the message short on the C side anyway
+    let message = CString::new(message.replace('\0', " ")).unwrap_or_default();
+    LAST_ERROR.with(|last| *last.borrow_mut() = Some(message));
+}
+
+/// Merge `count` PDFs held in memory, in order, into a newly allocated buffer.
+///
+/// On `MERGE_PDF_STATUS_OK` the merged file is stored in `*out` and its length in `*out_len`;
+/// release it with `merge_pdf_free`. `options` may be null for the defaults.
+///
+/// # Safety
+///
+/// `inputs` and `lengths` must point to `count` elements each, every `inputs[i]` to
+/// `lengths[i]` readable bytes. `options` must be null or valid, `out` and `out_len` writable.
+#[no_mangle]
+pub unsafe extern "C" fn merge_pdf_merge(
+    inputs: *const *const u8,
+    lengths: *const usize,
+    count: usize,
+    options: *const MergePdfOptions,
+    out: *mut *mut u8,
+    out_len: *mut usize,
+) -> MergePdfStatus {
+    if inputs.is_null() || lengths.is_null() || out.is_null() || out_len.is_null() {
+        set_last_error("inputs, lengths, out and out_len must not be null".to_string());
+        return MergePdfStatus::InvalidArgument;
+    }
+    let options = match options.as_ref().map(merge_options).transpose() {
+        Ok(options) => options.unwrap_or_default(),
+        Err(message) => {
+            set_last_error(message);
+            return MergePdfStatus::InvalidArgument;
+        }
+    };
+    let pointers = slice::from_raw_parts(inputs, count);
+    let lengths = slice::from_raw_parts(lengths, count);
+    if pointers.iter().any(|p| p.is_null()) {
+        set_last_error("an input pointer is null".to_string());
+        return MergePdfStatus::InvalidArgument;
+    }
+    let buffers = pointers.iter().zip(lengths).map(|(&p, &len)| slice::from_raw_parts(p, len)).collect::<Vec<_>>();
+
+    match panic::catch_unwind(AssertUnwindSafe(|| merge_bytes(&buffers, &options))) {
+        Ok(Ok(merged)) => {
+            let merged = Box::into_raw(merged.into_boxed_slice());
+            *out_len = merged.len();
+            *out = merged as *mut u8;
+            MergePdfStatus::Ok
+        }
+        Ok(Err(e)) => {
+            set_last_error(e.to_string());
+            MergePdfStatus::MergeFailed
+        }
+        Err(_) => {
+            set_last_error("the merge panicked".to_string());
+            MergePdfStatus::Panicked
+        }
+    }
+}
+
+/// Release a buffer returned by `merge_pdf_merge`. Null is ignored.
+///
+/// # Safety
+///
+/// `buffer` and `len` must be exactly what `merge_pdf_merge` stored, and not be freed twice.
+#[no_mangle]
+pub unsafe extern "C" fn merge_pdf_free(buffer: *mut u8, len: usize) {
+    if !buffer.is_null() {
+        drop(Box::from_raw(ptr::slice_from_raw_parts_mut(buffer, len)));
+    }
+}
+
+/// The message of the last failed call on this thread, or null. Valid until the next call.
+#[no_mangle]
+pub extern "C" fn merge_pdf_last_error() -> *const c_char {
+    LAST_ERROR.with(|last| last.borrow().as_ref().map_or(ptr::null(), |message| message.as_ptr()))
+}
+
+fn merge_options(options: &MergePdfOptions) -> Result<MergeOptions, String> {
+    let style = match options.bookmark_style {
+        0 => BookmarkStyle::Plain,
+        1 => BookmarkStyle::Italic,
+        2 => BookmarkStyle::Bold,
+        3 => BookmarkStyle::BoldItalic,
+        n => return Err(format!("bookmark_style {} is not one of 0..=3", n)),
+    };
+    let mut merge_options = MergeOptions::new().bookmark_style(style).compress(options.compress).pdfa(options.pdfa);
+    if let Ok(root) = usize::try_from(options.root) {
+        merge_options = merge_options.root(root);
+    }
+    Ok(merge_options)
+}
@@ -0,0 +1,51 @@
+use std::{
+    path::Path,
+    process::Command,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use lopdf::Document;
+
+use crate::{messages::tr, tempdir::TempDir};
+
+/// Inputs filtered so far, each in a scratch directory of its own since inputs load in parallel.
+static FILTERED: AtomicUsize = AtomicUsize::new(0);
+
+/// Pass `doc`, loaded from `name`, through the external `command` and load what it made in its
+/// place, for transformations this tool does not do itself. `{in}` in the command is replaced by
+/// a PDF of the input, already decrypted, and `{out}` by the file to write the result to; both
+/// are also in `MERGE_PDF_IN` and `MERGE_PDF_OUT`. The command runs in `sh`, or `cmd` on Windows.
+pub fn run(doc: &mut Document, command: &str, name: &Path) -> crate::Result<Document> {
+    let scratch = TempDir::named(&format!("filter-{}", FILTERED.fetch_add(1, Ordering::Relaxed)))?;
+    let input = scratch.path().join("in.pdf");
+    let output = scratch.path().join("out.pdf");
+    doc.save(&input).map_err(|e| crate::Error::saving(&input, e))?;
+    let command_line = command.replace("{in}", &quote(&input)).replace("{out}", &quote(&output));
+    #[cfg(unix)]
+    let mut shell = Command::new("sh");
+    #[cfg(unix)]
+    shell.arg("-c").arg(&command_line);
+    #[cfg(not(unix))]
+    let mut shell = Command::new("cmd");
+    #[cfg(not(unix))]
+    shell.arg("/C").arg(&command_line);
+    let status = shell
+        .env("MERGE_PDF_IN", &input)
+        .env("MERGE_PDF_OUT", &output)
+        .status()
+        .map_err(|e| crate::Error::Invalid(tr("{} could not be run: {}", &[&command, &e])))?;
+    if !status.success() {
+        return Err(crate::Error::Invalid(tr("{} failed on {}: {}", &[&command, &name.display(), &status])));
+    }
+    Document::load(&output).map_err(|e| crate::Error::Invalid(tr("{} left no PDF for {}: {}", &[&command, &name.display(), &e])))
+}
+
+/// `path` as one word of the shell's command line.
+fn quote(path: &Path) -> String {
+    let path = path.display().to_string();
+    if cfg!(unix) {
+        format!("'{}'", path.replace('\'', r"'\''"))
+    } else {
+        format!("\"{}\"", path)
+    }
+}
@@ -0,0 +1,151 @@
+use lopdf::{Dictionary, Document, Object, ObjectId};
+
+use crate::{input::InputSpec, messages::tr, stamp};
+
+/// Annotation flags (PDF 32000-1, 12.5.3) that keep an annotation from being shown.
+const HIDDEN: i64 = 1 << 1;
+const NO_VIEW: i64 = 1 << 5;
+
+/// What happens to the annotations of the inputs' pages, form fields included.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+pub enum Annotations {
+    /// Carry them over as they are
+    #[default]
+    Keep,
+    /// Draw them into the page content as they look and remove them; links stay, there is
+    /// nothing of them to draw
+    Flatten,
+    /// Remove them
+    Strip,
+}
+
+/// Flatten or strip the annotations of `doc` as `mode` says, dropping the interactive form along
+/// with its fields. Annotations without an appearance to flatten are left out, with a warning.
+pub fn handle_annotations(spec: &InputSpec, doc: &mut Document, mode: Annotations) -> crate::Result<()> {
+    let lost = match mode {
+        Annotations::Keep => return Ok(()),
+        Annotations::Flatten => flatten_annotations(doc, |annotation| {
+            annotation.get(b"Subtype").and_then(Object::as_name).ok() != Some(b"Link".as_slice())
+        })?,
+        Annotations::Strip => {
+            for page_id in doc.get_pages().into_values() {
+                doc.get_dictionary_mut(page_id)?.remove(b"Annots");
+            }
+            0
+        }
+    };
+    if let Ok(catalog) = doc.catalog_mut() {
+        catalog.remove(b"AcroForm");
+    }
+    if lost > 0 {
+        log::warn!(path:% = spec.path.display(); "{}", tr("{} annotation(s) have no appearance and were left out", &[&lost]));
+    }
+    Ok(())
+}
+
+/// Bake the form fields of `doc` into its pages and drop its interactive form, so the fields
+/// print as they look and can no longer be filled in. Fields without an appearance to bake are
+/// left out, with a warning.
+pub fn flatten_forms(spec: &InputSpec, doc: &mut Document) -> crate::Result<()> {
+    let lost = flatten_annotations(doc, |annotation| {
+        annotation.get(b"Subtype").and_then(Object::as_name).ok() == Some(b"Widget".as_slice())
+    })?;
+    if let Ok(catalog) = doc.catalog_mut() {
+        catalog.remove(b"AcroForm");
+    }
+    if lost > 0 {
+        log::warn!(path:% = spec.path.display(); "{}", tr("{} form field(s) have no appearance and were left out", &[&lost]));
+    }
+    Ok(())
+}
+
+/// Draw the normal appearance of the annotations of every page of `doc` that `which` accepts
+/// into the page's content, over what is there, and remove them. Hidden annotations are just
+/// removed, and so are popups, which only ever show with their parent. Returns how many shown
+/// annotations had no appearance to draw and were removed anyway.
+pub fn flatten_annotations(doc: &mut Document, which: impl Fn(&Dictionary) -> bool) -> crate::Result<usize> {
+    let mut lost = 0;
+    let mut save_id = None;
+    for page_id in doc.get_pages().into_values() {
+        let page = doc.get_dictionary(page_id)?;
+        let Ok(annotations) = page.get_deref(b"Annots", doc).and_then(Object::as_array).cloned() else { continue };
+        let mut kept = Vec::new();
+        let mut content = String::new();
+        let mut forms = Vec::new();
+        for annotation in &annotations {
+            let dict = match annotation {
+                Object::Reference(id) => doc.get_dictionary(*id).ok(),
+                Object::Dictionary(dict) => Some(dict),
+                _ => None,
+            };
+            let Some(dict) = dict.filter(|dict| which(dict)) else {
+                kept.push(annotation.clone());
+                continue;
+            };
+            if dict.get(b"F").and_then(Object::as_i64).unwrap_or(0) & (HIDDEN | NO_VIEW) != 0
+                || dict.get(b"Subtype").and_then(Object::as_name).ok() == Some(b"Popup".as_slice())
+            {
+                continue;
+            }
+            match appearance(doc, dict) {
+                Some((form_id, [a, b, c, d, e, f])) => {
+                    let name = format!("MergePdfFlat{}", form_id.0);
+                    content.push_str(&format!("q {} {} {} {} {} {} cm /{} Do Q\n", a, b, c, d, e, f, name));
+                    forms.push((name, form_id));
+                }
+                None => lost += 1,
+            }
+        }
+        if kept.len() == annotations.len() {
+            continue;
+        }
+        let page = doc.get_dictionary_mut(page_id)?;
+        if kept.is_empty() {
+            page.remove(b"Annots");
+        } else {
+            page.set("Annots", kept);
+        }
+        if !content.is_empty() {
+            let save_id = *save_id.get_or_insert_with(|| stamp::save_state(doc));
+            let resources = forms.iter().map(|(name, id)| (b"XObject".as_slice(), name.as_bytes(), *id)).collect::<Vec<_>>();
+            stamp::add_layer(doc, page_id, content, &resources, Some(save_id), false)?;
+        }
+    }
+    Ok(lost)
+}
+
+/// The normal appearance stream of an annotation, in the state its `/AS` picks, and the matrix
+/// that fits it into the annotation's rectangle (PDF 32000-1, 12.5.5).
+fn appearance(doc: &Document, annotation: &Dictionary) -> Option<(ObjectId, [f32; 6])> {
+    let normal = annotation.get_deref(b"AP", doc).and_then(Object::as_dict).ok()?.get(b"N").ok()?;
+    let form_id = match normal {
+        Object::Reference(id) => match doc.get_object(*id).ok()? {
+            Object::Stream(_) => *id,
+            Object::Dictionary(states) => states.get(annotation.get(b"AS").ok()?.as_name().ok()?).ok()?.as_reference().ok()?,
+            _ => return None,
+        },
+        Object::Dictionary(states) => states.get(annotation.get(b"AS").ok()?.as_name().ok()?).ok()?.as_reference().ok()?,
+        _ => return None,
+    };
+    let form = doc.get_object(form_id).and_then(Object::as_stream).ok()?;
+    let numbers = |object: &Object| object.as_array().ok()?.iter().map(|v| v.as_float().ok()).collect::<Option<Vec<_>>>();
+    let [x0, y0, x1, y1] = <[f32; 4]>::try_from(numbers(form.dict.get(b"BBox").ok()?)?).ok()?;
+    let [a, b, c, d, e, f] = match form.dict.get(b"Matrix") {
+        Ok(matrix) => <[f32; 6]>::try_from(numbers(matrix)?).ok()?,
+        Err(_) => [1.0, 0.0, 0.0, 1.0, 0.0, 0.0],
+    };
+    let [r0, s0, r1, s1] = <[f32; 4]>::try_from(numbers(annotation.get_deref(b"Rect", doc).ok()?)?).ok()?;
+
+    // The box the form's matrix turns its bounding box into
+    let corners = [(x0, y0), (x1, y0), (x0, y1), (x1, y1)].map(|(x, y)| (a * x + c * y + e, b * x + d * y + f));
+    let (left, right) = corners.iter().fold((f32::MAX, f32::MIN), |(lo, hi), &(x, _)| (lo.min(x), hi.max(x)));
+    let (bottom, top) = corners.iter().fold((f32::MAX, f32::MIN), |(lo, hi), &(_, y)| (lo.min(y), hi.max(y)));
+    let (width, height) = (right - left, top - bottom);
+    if width <= 0.0 || height <= 0.0 {
+        return None;
+    }
+    let (rect_left, rect_bottom) = (r0.min(r1), s0.min(s1));
+    let (sx, sy) = ((r1 - r0).abs() / width, (s1 - s0).abs() / height);
+    Some((form_id, [sx, 0.0, 0.0, sy, rect_left - left * sx, rect_bottom - bottom * sy]))
+}
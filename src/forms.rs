@@ -0,0 +1,177 @@
+use std::collections::{BTreeSet, HashSet};
+
+use lopdf::{Dictionary, Document, Object, ObjectId};
+
+use crate::{input::InputSpec, messages::tr, text};
+
+/// How deep the field tree is followed.
+const MAX_DEPTH: usize = 32;
+
+/// Entries of the interactive form every field inherits unless it has its own (PDF 32000-1,
+/// 12.7.3.3): the default appearance and the quadding.
+const INHERITED: [&[u8]; 2] = [b"DA", b"Q"];
+
+/// The interactive forms (the catalog's `AcroForm`, PDF 32000-1, 12.7.2) of the inputs, gathered
+/// into one for the merged document, whose catalog comes from a single input.
+#[derive(Debug, Clone, Default)]
+pub struct Forms {
+    /// Whether any input has an interactive form
+    found: bool,
+    fields: Vec<Object>,
+    /// Names of the top-level fields, which the fully qualified names of all others start with
+    names: HashSet<String>,
+    resources: Dictionary,
+    inherited: Dictionary,
+    calculation_order: Vec<Object>,
+    need_appearances: bool,
+    signature_flags: i64,
+    /// The XFA form of the only input with a form, which no longer describes a merged one
+    xfa: Option<Object>,
+    inputs: usize,
+}
+
+impl Forms {
+    /// Add the interactive form of `doc`, renumbered as it will be merged, taking it out of its
+    /// catalog. Only the fields with a widget on one of `pages`, or none at all, are kept. A
+    /// top-level field whose name an earlier input has is renamed to `name (2)` and so on, so
+    /// the fields of different inputs do not share their values.
+    pub fn append(&mut self, spec: &InputSpec, doc: &mut Document, pages: &BTreeSet<ObjectId>) {
+        let Ok(catalog) = doc.catalog() else { return };
+        let Some(form) = catalog.get_deref(b"AcroForm", doc).and_then(Object::as_dict).ok().cloned() else { return };
+        if let Ok(catalog) = doc.catalog_mut() {
+            catalog.remove(b"AcroForm");
+        }
+        self.found = true;
+        self.inputs += 1;
+
+        let widgets = pages
+            .iter()
+            .filter_map(|id| doc.get_dictionary(*id).ok())
+            .filter_map(|page| page.get_deref(b"Annots", doc).and_then(Object::as_array).ok())
+            .flatten()
+            .filter_map(|annotation| annotation.as_reference().ok())
+            .collect::<HashSet<_>>();
+        let fields = form.get_deref(b"Fields", doc).and_then(Object::as_array).cloned().unwrap_or_default();
+        let fields = fields.into_iter().filter(|field| on_pages(doc, field, &widgets, 0).unwrap_or(true)).collect::<Vec<_>>();
+
+        // What the fields of this input inherit from its form, if the merged one differs
+        let mut inherited = Vec::new();
+        for key in INHERITED {
+            if let Ok(value) = form.get_deref(key, doc) {
+                match self.inherited.get(key) {
+                    Ok(first) if first == value => {}
+                    Ok(_) => inherited.push((key, value.clone())),
+                    Err(_) => self.inherited.set(key, value.clone()),
+                }
+            }
+        }
+
+        let mut renamed = Vec::new();
+        let mut names = HashSet::new();
+        for field in &fields {
+            let dict = match field {
+                Object::Reference(id) => doc.get_dictionary_mut(*id).ok(),
+                _ => None,
+            };
+            let Some(dict) = dict else { continue };
+            for (key, value) in &inherited {
+                if !dict.has(key) {
+                    dict.set(*key, value.clone());
+                }
+            }
+            let Ok(name) = dict.get(b"T").and_then(Object::as_str).map(text::decode) else { continue };
+            let mut unique = name.clone();
+            let mut n = 1;
+            while self.names.contains(&unique) {
+                n += 1;
+                unique = format!("{} ({})", name, n);
+            }
+            if unique != name {
+                dict.set("T", Object::String(text::encode(&unique), lopdf::StringFormat::Literal));
+                renamed.push((name, unique.clone()));
+            }
+            names.insert(unique);
+        }
+        for (name, unique) in renamed {
+            log::info!("{}", tr("{}: form field {} renamed to {}", &[&spec.path.display(), &name, &unique]));
+        }
+        // Fields of one input may share a name on purpose, to share a value
+        self.names.extend(names);
+        self.fields.extend(fields);
+
+        if let Ok(resources) = form.get_deref(b"DR", doc).and_then(Object::as_dict) {
+            for (category, entries) in resources.iter() {
+                let entries = match entries {
+                    Object::Reference(id) => doc.get_object(*id).unwrap_or(entries),
+                    entries => entries,
+                };
+                match (entries.as_dict(), self.resources.get_mut(category).and_then(Object::as_dict_mut)) {
+                    // The first input to name a resource keeps the name
+                    (Ok(entries), Ok(merged)) => {
+                        for (name, value) in entries.iter() {
+                            if !merged.has(name) {
+                                merged.set(name.clone(), value.clone());
+                            }
+                        }
+                    }
+                    (_, Err(_)) => self.resources.set(category.clone(), entries.clone()),
+                    _ => {}
+                }
+            }
+        }
+        if let Ok(order) = form.get_deref(b"CO", doc).and_then(Object::as_array) {
+            self.calculation_order.extend(order.iter().cloned());
+        }
+        self.need_appearances |= form.get(b"NeedAppearances").and_then(Object::as_bool).unwrap_or(false);
+        self.signature_flags |= form.get(b"SigFlags").and_then(Object::as_i64).unwrap_or(0);
+        let xfa = form.get(b"XFA").ok().cloned();
+        if self.inputs == 1 {
+            self.xfa = xfa;
+        } else if self.xfa.take().is_some() || xfa.is_some() {
+            log::warn!(path:% = spec.path.display(); "{}", tr("XFA forms cannot be merged, only their AcroForm fields are kept", &[]));
+        }
+    }
+
+    /// Give `catalog` an interactive form of all the fields, if any input has one.
+    pub fn apply(&self, catalog: &mut Dictionary) {
+        catalog.remove(b"AcroForm");
+        if !self.found {
+            return;
+        }
+        let mut form = self.inherited.clone();
+        form.set("Fields", self.fields.clone());
+        if !self.resources.is_empty() {
+            form.set("DR", self.resources.clone());
+        }
+        if !self.calculation_order.is_empty() {
+            form.set("CO", self.calculation_order.clone());
+        }
+        if self.need_appearances {
+            form.set("NeedAppearances", true);
+        }
+        if self.signature_flags != 0 {
+            form.set("SigFlags", self.signature_flags);
+        }
+        if let Some(xfa) = &self.xfa {
+            form.set("XFA", xfa.clone());
+        }
+        catalog.set("AcroForm", form);
+    }
+}
+
+/// Whether the field `field` has a widget among `widgets`, or `None` if it has no widgets at all.
+fn on_pages(doc: &Document, field: &Object, widgets: &HashSet<ObjectId>, depth: usize) -> Option<bool> {
+    let (id, dict) = match field {
+        Object::Reference(id) => (Some(*id), doc.get_dictionary(*id).ok()?),
+        field => (None, field.as_dict().ok()?),
+    };
+    // A field with a single widget can be one dictionary with it
+    if dict.get(b"Subtype").and_then(Object::as_name).ok() == Some(b"Widget".as_slice()) {
+        return Some(id.is_some_and(|id| widgets.contains(&id)));
+    }
+    if depth >= MAX_DEPTH {
+        return None;
+    }
+    let kids = dict.get_deref(b"Kids", doc).and_then(Object::as_array).ok()?;
+    kids.iter().filter_map(|kid| on_pages(doc, kid, widgets, depth + 1)).reduce(|a, b| a || b)
+}
@@ -0,0 +1,114 @@
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use crate::{input, sort::SortOrder};
+
+/// Whether `path` contains glob syntax (`*`, `?`, `[...]`) and needs expanding.
+pub fn is_pattern(path: &Path) -> bool {
+    path.to_string_lossy().contains(['*', '?', '['])
+}
+
+/// Expand a glob pattern into the files it matches, ordered by `order` (descending if `reverse`).
+///
+/// Relative patterns are matched below `predir` and yield paths relative to it, so the
+/// results resolve the same way as any other relative input. `**` matches any number of
+/// directories, and wildcards never match names starting with a dot unless the pattern does.
+pub fn expand(pattern: &Path, predir: &Path, order: SortOrder, reverse: bool) -> io::Result<Vec<PathBuf>> {
+    let mut literal = PathBuf::new();
+    let mut rest = Vec::new();
+    for component in pattern.components() {
+        let part = component.as_os_str().to_string_lossy().into_owned();
+        if rest.is_empty() && !is_pattern(Path::new(&part)) {
+            literal.push(component);
+        } else {
+            rest.push(part);
+        }
+    }
+
+    let mut matches = Vec::new();
+    walk(&input::resolve(predir, &literal), &literal, &rest, &mut matches)?;
+    // `**` can reach the same file along several routes
+    let mut seen = std::collections::HashSet::new();
+    matches.retain(|m| seen.insert(m.clone()));
+    order.sort(&mut matches, predir, reverse);
+    Ok(matches)
+}
+
+fn walk(dir: &Path, shown: &Path, rest: &[String], matches: &mut Vec<PathBuf>) -> io::Result<()> {
+    let Some((part, remaining)) = rest.split_first() else {
+        if dir.is_file() {
+            matches.push(shown.to_path_buf());
+        }
+        return Ok(());
+    };
+    if !dir.is_dir() {
+        return Ok(());
+    }
+    if part == "**" {
+        // Zero directories, then every subdirectory in turn
+        walk(dir, shown, remaining, matches)?;
+        for (name, path) in entries(dir)? {
+            if path.is_dir() && !name.starts_with('.') {
+                walk(&path, &shown.join(&name), rest, matches)?;
+            }
+        }
+        return Ok(());
+    }
+    if !is_pattern(Path::new(part)) {
+        return walk(&dir.join(part), &shown.join(part), remaining, matches);
+    }
+    for (name, path) in entries(dir)? {
+        if name.starts_with('.') && !part.starts_with('.') {
+            continue;
+        }
+        if matches_component(part.as_bytes(), name.as_bytes()) {
+            walk(&path, &shown.join(&name), remaining, matches)?;
+        }
+    }
+    Ok(())
+}
+
+fn entries(dir: &Path) -> io::Result<Vec<(String, PathBuf)>> {
+    fs::read_dir(dir)?
+        .map(|entry| entry.map(|e| (e.file_name().to_string_lossy().into_owned(), e.path())))
+        .collect()
+}
+
+/// Whether the file or directory `name` matches `pattern`, with the wildcards of one component.
+pub fn matches_name(pattern: &str, name: &str) -> bool {
+    matches_component(pattern.as_bytes(), name.as_bytes())
+}
+
+/// Match one path component against a pattern made of literals, `*`, `?` and `[...]` classes.
+fn matches_component(pattern: &[u8], name: &[u8]) -> bool {
+    match pattern.first() {
+        None => name.is_empty(),
+        Some(b'*') => (0..=name.len()).any(|skip| matches_component(&pattern[1..], &name[skip..])),
+        Some(b'?') => !name.is_empty() && matches_component(&pattern[1..], &name[1..]),
+        Some(b'[') => {
+            let Some(end) = pattern.iter().skip(2).position(|&c| c == b']').map(|p| p + 2) else {
+                return name.first() == Some(&b'[') && matches_component(&pattern[1..], &name[1..]);
+            };
+            let Some(&c) = name.first() else { return false };
+            let (negated, class) = match pattern[1] {
+                b'!' | b'^' => (true, &pattern[2..end]),
+                _ => (false, &pattern[1..end]),
+            };
+            let mut found = false;
+            let mut i = 0;
+            while i < class.len() {
+                if i + 2 < class.len() && class[i + 1] == b'-' {
+                    found |= class[i] <= c && c <= class[i + 2];
+                    i += 3;
+                } else {
+                    found |= class[i] == c;
+                    i += 1;
+                }
+            }
+            found != negated && matches_component(&pattern[end + 1..], &name[1..])
+        }
+        Some(&c) => name.first() == Some(&c) && matches_component(&pattern[1..], &name[1..]),
+    }
+}
@@ -0,0 +1,291 @@
+use std::collections::{HashMap, HashSet};
+
+use lopdf::{content::Content, Dictionary, Document, Object, ObjectId, Stream};
+
+use crate::{image, input::InputSpec, jpeg, messages::tr, pages};
+
+/// Quality of JPEG images re-encoded in gray.
+const JPEG_QUALITY: u8 = 90;
+
+/// The kinds of color space that matter for turning colors gray.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub(crate) enum Space {
+    Gray,
+    Rgb,
+    Cmyk,
+    /// Indexed, spot, Lab, patterns and anything else left as it is
+    Other,
+}
+
+/// Turn the colors of `doc` into shades of gray: RGB and CMYK colors set by the content of its
+/// pages, forms and tiling patterns, RGB and CMYK images (8-bit, stored raw, with Flate or as
+/// baseline or progressive JPEG) and the palettes of indexed color spaces. Shadings and spot
+/// colors are left as they are; images and content that cannot be read are too, with a warning.
+pub fn grayscale(spec: &InputSpec, doc: &mut Document) -> crate::Result<()> {
+    let known = known_spaces(doc);
+    let mut left = 0;
+
+    // Indexed color spaces keep their indices and get a gray palette
+    let mut lookups = Vec::new();
+    for object in doc.objects.values_mut() {
+        gray_indexed(object, &known, &mut lookups);
+    }
+    let mut done = HashSet::new();
+    for (id, base) in lookups {
+        if !done.insert(id) {
+            continue;
+        }
+        if let Ok(Object::Stream(lookup)) = doc.get_object_mut(id) {
+            match lookup.get_plain_content() {
+                Ok(palette) => lookup.set_plain_content(gray_palette(&palette, base)),
+                Err(_) => left += 1,
+            }
+        }
+    }
+
+    let mut replaced = false;
+    for page_id in doc.get_pages().into_values() {
+        let resources = match pages::with_inherited_attributes(doc, page_id)? {
+            Object::Dictionary(page) => page.get_deref(b"Resources", doc).and_then(Object::as_dict).cloned().ok(),
+            _ => None,
+        };
+        let content = doc.get_page_content(page_id).and_then(|content| Content::decode(&content));
+        match content.map(|content| gray_content(doc, content, resources.as_ref(), &known)) {
+            Ok(Some(content)) => {
+                let content_id = doc.add_object(Stream::new(Dictionary::new(), content?));
+                doc.get_dictionary_mut(page_id)?.set("Contents", content_id);
+                replaced = true;
+            }
+            Ok(None) => {}
+            Err(_) => left += 1,
+        }
+    }
+
+    let streams = doc.objects.iter().filter(|(_, object)| matches!(object, Object::Stream(_))).map(|(id, _)| *id).collect::<Vec<_>>();
+    for id in streams {
+        let Ok(Object::Stream(stream)) = doc.get_object(id) else { continue };
+        let is = |key: &[u8], value: &[u8]| stream.dict.get(key).and_then(Object::as_name).ok() == Some(value);
+        let is_content = is(b"Subtype", b"Form") || stream.dict.get(b"PatternType").and_then(Object::as_i64).ok() == Some(1);
+        let replacement = if is(b"Subtype", b"Image") {
+            match gray_image(doc, stream, &known) {
+                Ok(Some(image)) => image,
+                Ok(None) => continue,
+                Err(_) => {
+                    left += 1;
+                    continue;
+                }
+            }
+        } else if is_content {
+            let resources = stream.dict.get_deref(b"Resources", doc).and_then(Object::as_dict).ok();
+            let content = stream.get_plain_content().and_then(|content| Content::decode(&content));
+            match content.map(|content| gray_content(doc, content, resources, &known)) {
+                Ok(Some(content)) => {
+                    let mut form = stream.clone();
+                    form.set_plain_content(content?);
+                    form
+                }
+                Ok(None) => continue,
+                Err(_) => {
+                    left += 1;
+                    continue;
+                }
+            }
+        } else {
+            continue;
+        };
+        doc.objects.insert(id, Object::Stream(replacement));
+    }
+    if replaced {
+        // The streams the pages' content was in before
+        doc.prune_objects();
+    }
+    if left > 0 {
+        log::warn!(path:% = spec.path.display(); "{}", tr("{} image(s) or content stream(s) could not be read and were left in color", &[&left]));
+    }
+    Ok(())
+}
+
+/// The color spaces of the objects of `doc` that could be one, for looking up references.
+pub(crate) fn known_spaces(doc: &Document) -> HashMap<ObjectId, Space> {
+    // ICC profiles first, then the spaces built on them
+    let profiles = doc.objects.iter().map(|(id, object)| (*id, space_of(object, &HashMap::new()))).collect::<HashMap<_, _>>();
+    doc.objects.iter().map(|(id, object)| (*id, space_of(object, &profiles))).filter(|(_, space)| *space != Space::Other).collect()
+}
+
+/// The kind of color space `object` is, with `known` giving that of referenced objects.
+pub(crate) fn space_of(object: &Object, known: &HashMap<ObjectId, Space>) -> Space {
+    match object {
+        Object::Reference(id) => known.get(id).copied().unwrap_or(Space::Other),
+        Object::Name(name) => named_space(name),
+        Object::Array(items) => match items.first().and_then(|first| first.as_name().ok()) {
+            Some(b"ICCBased") => items.get(1).map_or(Space::Other, |profile| space_of(profile, known)),
+            Some(b"CalRGB") => Space::Rgb,
+            Some(b"CalGray") => Space::Gray,
+            _ => Space::Other,
+        },
+        // An ICC profile, by its number of components
+        Object::Stream(stream) => match stream.dict.get(b"N").and_then(Object::as_i64) {
+            Ok(1) => Space::Gray,
+            Ok(3) => Space::Rgb,
+            Ok(4) => Space::Cmyk,
+            _ => Space::Other,
+        },
+        _ => Space::Other,
+    }
+}
+
+fn named_space(name: &[u8]) -> Space {
+    match name {
+        b"DeviceGray" | b"G" | b"CalGray" => Space::Gray,
+        b"DeviceRGB" | b"RGB" => Space::Rgb,
+        b"DeviceCMYK" | b"CMYK" => Space::Cmyk,
+        _ => Space::Other,
+    }
+}
+
+/// The gray, 0 black to 1 white, of a color with `components` from 0 to 1.
+fn gray(components: &[f32], space: Space) -> f32 {
+    match (space, components) {
+        (Space::Rgb, [r, g, b]) => 0.299 * r + 0.587 * g + 0.114 * b,
+        (Space::Cmyk, [c, m, y, k]) => 1.0 - (0.299 * c + 0.587 * m + 0.114 * y + k).min(1.0),
+        (_, [gray, ..]) => *gray,
+        _ => 0.0,
+    }
+}
+
+fn gray_palette(palette: &[u8], base: Space) -> Vec<u8> {
+    let components = if base == Space::Cmyk { 4 } else { 3 };
+    palette
+        .chunks_exact(components)
+        .map(|color| (gray(&color.iter().map(|&c| c as f32 / 255.0).collect::<Vec<_>>(), base) * 255.0).round() as u8)
+        .collect()
+}
+
+/// Give the indexed color spaces in `object` with an RGB or CMYK base a gray one, converting
+/// their palettes right away when inline and saving the streams holding them into `lookups`.
+fn gray_indexed(object: &mut Object, known: &HashMap<ObjectId, Space>, lookups: &mut Vec<(ObjectId, Space)>) {
+    match object {
+        Object::Array(items) if items.len() == 4 && items[0].as_name().ok() == Some(b"Indexed".as_slice()) => {
+            let base = space_of(&items[1], known);
+            if !matches!(base, Space::Rgb | Space::Cmyk) {
+                return;
+            }
+            match &mut items[3] {
+                Object::String(palette, _) => *palette = gray_palette(palette, base),
+                Object::Reference(id) => lookups.push((*id, base)),
+                _ => return,
+            }
+            items[1] = Object::Name(b"DeviceGray".to_vec());
+        }
+        Object::Array(items) => items.iter_mut().for_each(|item| gray_indexed(item, known, lookups)),
+        Object::Dictionary(dict) => dict.iter_mut().for_each(|(_, value)| gray_indexed(value, known, lookups)),
+        Object::Stream(stream) => stream.dict.iter_mut().for_each(|(_, value)| gray_indexed(value, known, lookups)),
+        _ => {}
+    }
+}
+
+/// The content with its RGB and CMYK color operators turned gray, if it has any.
+fn gray_content(
+    doc: &Document,
+    mut content: Content,
+    resources: Option<&Dictionary>,
+    known: &HashMap<ObjectId, Space>,
+) -> Option<crate::Result<Vec<u8>>> {
+    let named = |name: &[u8]| match named_space(name) {
+        Space::Other => resources
+            .and_then(|resources| resources.get_deref(b"ColorSpace", doc).and_then(Object::as_dict).ok())
+            .and_then(|spaces| spaces.get(name).ok())
+            .map_or(Space::Other, |space| space_of(space, known)),
+        space => space,
+    };
+    // The spaces colors are set in for filling and stroking, saved with the graphics state
+    let (mut fill, mut stroke) = (Space::Gray, Space::Gray);
+    let mut saved = Vec::new();
+    let mut changed = false;
+    for operation in &mut content.operations {
+        let numbers = operation.operands.iter().map(|operand| operand.as_float().ok()).collect::<Option<Vec<_>>>();
+        let stroking = operation.operator.chars().all(|c| c.is_ascii_uppercase());
+        let current = if stroking { &mut stroke } else { &mut fill };
+        match operation.operator.as_str() {
+            "q" => saved.push((fill, stroke)),
+            "Q" => (fill, stroke) = saved.pop().unwrap_or((fill, stroke)),
+            "g" | "G" => *current = Space::Gray,
+            "rg" | "RG" | "k" | "K" => {
+                let space = if operation.operator.eq_ignore_ascii_case("rg") { Space::Rgb } else { Space::Cmyk };
+                *current = Space::Gray;
+                let Some(numbers) = numbers else { continue };
+                operation.operator = if stroking { "G" } else { "g" }.to_string();
+                operation.operands = vec![Object::Real(gray(&numbers, space))];
+                changed = true;
+            }
+            "cs" | "CS" => {
+                *current = operation.operands.first().and_then(|name| name.as_name().ok()).map_or(Space::Other, named);
+                if matches!(*current, Space::Rgb | Space::Cmyk) {
+                    operation.operands = vec![Object::Name(b"DeviceGray".to_vec())];
+                    changed = true;
+                }
+            }
+            "sc" | "scn" | "SC" | "SCN" => {
+                let Some(numbers) = numbers else { continue };
+                if matches!((*current, numbers.len()), (Space::Rgb, 3) | (Space::Cmyk, 4)) {
+                    operation.operands = vec![Object::Real(gray(&numbers, *current))];
+                    changed = true;
+                }
+            }
+            _ => {}
+        }
+    }
+    changed.then(|| Ok(content.encode()?))
+}
+
+/// The image in gray, if it is an RGB or CMYK image; an error if it is one that cannot be read.
+fn gray_image(doc: &Document, image: &Stream, known: &HashMap<ObjectId, Space>) -> Result<Option<Stream>, String> {
+    let dict = &image.dict;
+    if dict.get(b"ImageMask").and_then(Object::as_bool).unwrap_or(false) {
+        return Ok(None);
+    }
+    let space = dict.get(b"ColorSpace").map_or(Space::Other, |space| space_of(space, known));
+    let components = match space {
+        Space::Rgb => 3,
+        Space::Cmyk => 4,
+        _ => return Ok(None),
+    };
+    let number = |key: &[u8]| dict.get_deref(key, doc).and_then(Object::as_i64).ok().and_then(|n| usize::try_from(n).ok());
+    let (Some(width), Some(height)) = (number(b"Width"), number(b"Height")) else { return Err("image without a size".to_string()) };
+    let (samples, jpeg) = image::decode_samples(image, width, height, components)?;
+
+    // Decode arrays map samples onto other ranges, like Adobe's inverted CMYK
+    let ranges = dict
+        .get(b"Decode")
+        .and_then(Object::as_array)
+        .ok()
+        .and_then(|decode| decode.iter().map(|v| v.as_float().ok()).collect::<Option<Vec<_>>>())
+        .filter(|decode| decode.len() == 2 * components)
+        .unwrap_or_else(|| [0.0, 1.0].repeat(components));
+    let mut color = vec![0.0; components];
+    let gray = samples
+        .chunks_exact(components)
+        .take(width * height)
+        .map(|pixel| {
+            for (i, &sample) in pixel.iter().enumerate() {
+                color[i] = ranges[2 * i] + sample as f32 / 255.0 * (ranges[2 * i + 1] - ranges[2 * i]);
+            }
+            (gray(&color, space) * 255.0).round().clamp(0.0, 255.0) as u8
+        })
+        .collect::<Vec<_>>();
+
+    let mut dict = dict.clone();
+    for key in [b"Decode".as_slice(), b"DecodeParms", b"Filter"] {
+        dict.remove(key);
+    }
+    dict.set("ColorSpace", Object::Name(b"DeviceGray".to_vec()));
+    dict.set("BitsPerComponent", 8);
+    let content = if jpeg {
+        dict.set("Filter", Object::Name(b"DCTDecode".to_vec()));
+        jpeg::encode(width, height, 1, &gray, JPEG_QUALITY)
+    } else {
+        dict.set("Filter", Object::Name(b"FlateDecode".to_vec()));
+        image::deflate(&gray)
+    };
+    Ok(Some(Stream::new(dict, content)))
+}
@@ -0,0 +1,495 @@
+use std::{fs, io::Read, path::Path};
+
+use flate2::{read::ZlibDecoder, write::ZlibEncoder, Compression};
+use lopdf::{dictionary, Dictionary, Document, Object, Stream};
+
+use crate::{blank, jpeg, messages::tr};
+
+/// Resolution assumed when an image does not say how large it is meant to be printed.
+const DEFAULT_DPI: f32 = 72.0;
+
+/// Whether `path` looks like an image we can wrap into a PDF page.
+pub fn is_image(path: &Path) -> bool {
+    path.extension()
+        .map(|e| e.to_ascii_lowercase())
+        .is_some_and(|e| ["jpg", "jpeg", "png", "tif", "tiff"].iter().any(|x| e == *x))
+}
+
+/// An image ready to become a PDF image XObject.
+struct Image {
+    width: u32,
+    height: u32,
+    dpi: (f32, f32),
+    stream: Stream,
+}
+
+/// Wrap an image file into a document with one page per image (TIFFs may hold several),
+/// each page sized to the image at its stored resolution.
+pub fn load(path: &Path) -> crate::Result<Document> {
+    let data = fs::read(path)?;
+    let invalid = |message: String| crate::Error::Invalid(tr("{}: {}", &[&path.display(), &message]));
+    let images = if data.starts_with(&[0xff, 0xd8]) {
+        vec![jpeg(data).map_err(invalid)?]
+    } else if data.starts_with(b"\x89PNG\r\n\x1a\n") {
+        vec![png(&data).map_err(invalid)?]
+    } else if data.starts_with(b"II*\0") || data.starts_with(b"MM\0*") {
+        tiff(&data).map_err(invalid)?
+    } else {
+        return Err(invalid(tr("unsupported image format", &[])));
+    };
+    Ok(image_document(images))
+}
+
+fn image_document(images: Vec<Image>) -> Document {
+    let mut doc = Document::with_version("1.5");
+    let pages_id = doc.new_object_id();
+    let mut kids = Vec::new();
+    for image in images {
+        let width = image.width as f32 * 72.0 / image.dpi.0;
+        let height = image.height as f32 * 72.0 / image.dpi.1;
+        let image_id = doc.add_object(image.stream);
+        let content = format!("q {} 0 0 {} 0 0 cm /Im0 Do Q", width, height);
+        let content_id = doc.add_object(Stream::new(Dictionary::new(), content.into_bytes()));
+        kids.push(Object::Reference(doc.add_object(dictionary! {
+            "Type" => "Page",
+            "Parent" => pages_id,
+            "MediaBox" => vec![0.into(), 0.into(), width.into(), height.into()],
+            "Contents" => content_id,
+            "Resources" => dictionary! { "XObject" => dictionary! { "Im0" => image_id } },
+        })));
+    }
+    doc.objects.insert(
+        pages_id,
+        Object::Dictionary(dictionary! { "Type" => "Pages", "Count" => kids.len() as i64, "Kids" => kids }),
+    );
+    let catalog_id = doc.add_object(dictionary! { "Type" => "Catalog", "Pages" => pages_id });
+    doc.trailer.set("Root", catalog_id);
+    doc
+}
+
+fn image_dict(width: u32, height: u32, color_space: Object, bits: u8) -> Dictionary {
+    dictionary! {
+        "Type" => "XObject",
+        "Subtype" => "Image",
+        "Width" => width as i64,
+        "Height" => height as i64,
+        "ColorSpace" => color_space,
+        "BitsPerComponent" => bits as i64,
+    }
+}
+
+fn device_space(components: usize) -> Result<Object, String> {
+    match components {
+        1 => Ok(Object::Name(b"DeviceGray".to_vec())),
+        3 => Ok(Object::Name(b"DeviceRGB".to_vec())),
+        4 => Ok(Object::Name(b"DeviceCMYK".to_vec())),
+        n => Err(format!("{} color components are not supported", n)),
+    }
+}
+
+pub(crate) fn deflate(data: &[u8]) -> Vec<u8> {
+    let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+    std::io::Write::write_all(&mut encoder, data).expect("writing to a Vec cannot fail");
+    encoder.finish().expect("writing to a Vec cannot fail")
+}
+
+/// The 8-bit samples of an image XObject with `components` per pixel, stored raw, with Flate or
+/// as JPEG, and whether it was a JPEG.
+pub(crate) fn decode_samples(image: &Stream, width: usize, height: usize, components: usize) -> Result<(Vec<u8>, bool), String> {
+    let filter = match image.dict.get(b"Filter") {
+        Err(_) => None,
+        Ok(Object::Array(filters)) if filters.is_empty() => None,
+        Ok(Object::Array(filters)) if filters.len() == 1 => filters[0].as_name().ok(),
+        Ok(filter) => Some(filter.as_name().map_err(|e| e.to_string())?),
+    };
+    let (samples, is_jpeg) = match filter {
+        Some(b"DCTDecode") => {
+            let decoded = jpeg::decode(&image.content)?;
+            if decoded.components != components || (decoded.width, decoded.height) != (width, height) {
+                return Err("JPEG data does not match its image".to_string());
+            }
+            (decoded.samples, true)
+        }
+        None | Some(b"FlateDecode") if image.dict.get(b"BitsPerComponent").and_then(Object::as_i64).ok() == Some(8) => {
+            let samples = blank::image_samples(image, width * components, components, height).ok_or("unreadable image")?;
+            (samples, false)
+        }
+        _ => return Err("unsupported image".to_string()),
+    };
+    if samples.len() < width * height * components {
+        return Err("truncated image".to_string());
+    }
+    Ok((samples, is_jpeg))
+}
+
+/// JPEG data goes into the PDF untouched behind a DCTDecode filter.
+fn jpeg(data: Vec<u8>) -> Result<Image, String> {
+    let mut pos = 2;
+    let mut dpi = (DEFAULT_DPI, DEFAULT_DPI);
+    let mut adobe = false;
+    while pos + 4 <= data.len() {
+        if data[pos] != 0xff {
+            return Err("corrupt JPEG marker".to_string());
+        }
+        let marker = data[pos + 1];
+        let length = u16::from_be_bytes([data[pos + 2], data[pos + 3]]) as usize;
+        let segment = data.get(pos + 4..pos + 2 + length).ok_or("truncated JPEG")?;
+        match marker {
+            // JFIF density: units 1 = dots per inch, 2 = dots per cm
+            0xe0 if segment.starts_with(b"JFIF\0") && segment.len() >= 12 => {
+                let x = u16::from_be_bytes([segment[8], segment[9]]) as f32;
+                let y = u16::from_be_bytes([segment[10], segment[11]]) as f32;
+                match segment[7] {
+                    1 if x > 0.0 && y > 0.0 => dpi = (x, y),
+                    2 if x > 0.0 && y > 0.0 => dpi = (x * 2.54, y * 2.54),
+                    _ => {}
+                }
+            }
+            0xee if segment.starts_with(b"Adobe") => adobe = true,
+            0xc0..=0xcf if ![0xc4, 0xc8, 0xcc].contains(&marker) => {
+                let [bits, h0, h1, w0, w1, components, ..] = *segment else { return Err("truncated JPEG frame".to_string()) };
+                let (height, width) = (u16::from_be_bytes([h0, h1]) as u32, u16::from_be_bytes([w0, w1]) as u32);
+                let components = components as usize;
+                let mut dict = image_dict(width, height, device_space(components)?, bits);
+                dict.set("Filter", "DCTDecode");
+                // Adobe writes CMYK JPEGs inverted
+                if components == 4 && adobe {
+                    dict.set("Decode", [1, 0, 1, 0, 1, 0, 1, 0].iter().map(|&v| Object::Integer(v)).collect::<Vec<_>>());
+                }
+                let stream = Stream::new(dict, data).with_compression(false);
+                return Ok(Image { width, height, dpi, stream });
+            }
+            _ => {}
+        }
+        pos += 2 + length;
+    }
+    Err("no JPEG frame header found".to_string())
+}
+
+fn png(data: &[u8]) -> Result<Image, String> {
+    let mut pos = 8;
+    let (mut width, mut height, mut bits, mut color_type, mut interlace) = (0, 0, 8, 0, 0);
+    let mut palette = Vec::new();
+    let mut idat = Vec::new();
+    let mut dpi = (DEFAULT_DPI, DEFAULT_DPI);
+    while pos + 8 <= data.len() {
+        let length = u32::from_be_bytes([data[pos], data[pos + 1], data[pos + 2], data[pos + 3]]) as usize;
+        let kind = &data[pos + 4..pos + 8];
+        let chunk = data.get(pos + 8..pos + 8 + length).ok_or("truncated PNG")?;
+        match kind {
+            b"IHDR" if chunk.len() >= 13 => {
+                width = u32::from_be_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]);
+                height = u32::from_be_bytes([chunk[4], chunk[5], chunk[6], chunk[7]]);
+                bits = chunk[8];
+                color_type = chunk[9];
+                interlace = chunk[12];
+            }
+            b"PLTE" => palette = chunk.to_vec(),
+            b"IDAT" => idat.extend_from_slice(chunk),
+            // Pixels per metre when the unit byte is 1
+            b"pHYs" if chunk.len() >= 9 && chunk[8] == 1 => {
+                let x = u32::from_be_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]) as f32 * 0.0254;
+                let y = u32::from_be_bytes([chunk[4], chunk[5], chunk[6], chunk[7]]) as f32 * 0.0254;
+                if x > 0.0 && y > 0.0 {
+                    dpi = (x, y);
+                }
+            }
+            b"IEND" => break,
+            _ => {}
+        }
+        pos += 12 + length;
+    }
+    if interlace != 0 {
+        return Err("interlaced PNGs are not supported".to_string());
+    }
+    let (color_space, colors, alpha) = match color_type {
+        0 => (device_space(1)?, 1, false),
+        2 => (device_space(3)?, 3, false),
+        3 => {
+            let entries = palette.len() / 3;
+            let space = vec![
+                Object::Name(b"Indexed".to_vec()),
+                Object::Name(b"DeviceRGB".to_vec()),
+                Object::Integer(entries as i64 - 1),
+                Object::String(palette, lopdf::StringFormat::Hexadecimal),
+            ];
+            (Object::Array(space), 1, false)
+        }
+        4 => (device_space(1)?, 1, true),
+        6 => (device_space(3)?, 3, true),
+        other => return Err(format!("unknown PNG color type {}", other)),
+    };
+
+    if !alpha {
+        // PNG rows carry the same predictors PDF's FlateDecode understands, so IDAT is used as is
+        let mut dict = image_dict(width, height, color_space, bits);
+        dict.set("Filter", "FlateDecode");
+        dict.set(
+            "DecodeParms",
+            dictionary! { "Predictor" => 15, "Colors" => colors as i64, "BitsPerComponent" => bits as i64, "Columns" => width as i64 },
+        );
+        let stream = Stream::new(dict, idat).with_compression(false);
+        return Ok(Image { width, height, dpi, stream });
+    }
+
+    // Alpha has to be split off into a soft mask, which needs the raw pixels
+    let sample_bytes = bits as usize / 8;
+    let pixel_bytes = (colors + 1) * sample_bytes;
+    let mut raw = Vec::new();
+    ZlibDecoder::new(idat.as_slice()).read_to_end(&mut raw).map_err(|e| e.to_string())?;
+    let pixels = unfilter_png(&raw, width as usize * pixel_bytes, pixel_bytes, height as usize)?;
+    let mut color = Vec::with_capacity(pixels.len());
+    let mut mask = Vec::with_capacity(pixels.len() / (colors + 1));
+    for pixel in pixels.chunks_exact(pixel_bytes) {
+        color.extend_from_slice(&pixel[..colors * sample_bytes]);
+        mask.extend_from_slice(&pixel[colors * sample_bytes..]);
+    }
+    let mut mask_dict = image_dict(width, height, device_space(1)?, bits);
+    mask_dict.set("Filter", "FlateDecode");
+    let mut dict = image_dict(width, height, color_space, bits);
+    dict.set("Filter", "FlateDecode");
+    dict.set("SMask", Object::Stream(Stream::new(mask_dict, deflate(&mask))));
+    let stream = Stream::new(dict, deflate(&color)).with_compression(false);
+    Ok(Image { width, height, dpi, stream })
+}
+
+/// Undo the per-row PNG filters (None, Sub, Up, Average, Paeth).
+pub(crate) fn unfilter_png(data: &[u8], row_bytes: usize, pixel_bytes: usize, rows: usize) -> Result<Vec<u8>, String> {
+    let mut out = vec![0u8; row_bytes * rows];
+    for row in 0..rows {
+        let line = data.get(row * (row_bytes + 1)..(row + 1) * (row_bytes + 1)).ok_or("truncated PNG data")?;
+        let (filter, line) = (line[0], &line[1..]);
+        let (done, current) = out.split_at_mut(row * row_bytes);
+        let previous = if row > 0 { &done[(row - 1) * row_bytes..] } else { &[][..] };
+        let current = &mut current[..row_bytes];
+        for i in 0..row_bytes {
+            let left = if i >= pixel_bytes { current[i - pixel_bytes] } else { 0 };
+            let up = previous.get(i).copied().unwrap_or(0);
+            let up_left = if i >= pixel_bytes { previous.get(i - pixel_bytes).copied().unwrap_or(0) } else { 0 };
+            let predicted = match filter {
+                0 => 0,
+                1 => left,
+                2 => up,
+                3 => ((left as u16 + up as u16) / 2) as u8,
+                4 => {
+                    let p = left as i16 + up as i16 - up_left as i16;
+                    let (pa, pb, pc) = ((p - left as i16).abs(), (p - up as i16).abs(), (p - up_left as i16).abs());
+                    if pa <= pb && pa <= pc {
+                        left
+                    } else if pb <= pc {
+                        up
+                    } else {
+                        up_left
+                    }
+                }
+                other => return Err(format!("unknown PNG filter {}", other)),
+            };
+            current[i] = line[i].wrapping_add(predicted);
+        }
+    }
+    Ok(out)
+}
+
+/// Every image (IFD) of a TIFF file becomes a page. Strips are decoded and recompressed,
+/// except single-strip CCITT fax data which PDF can take over directly.
+fn tiff(data: &[u8]) -> Result<Vec<Image>, String> {
+    let big_endian = data.starts_with(b"MM");
+    let u16_at = |at: usize| -> Result<u16, String> {
+        let b = data.get(at..at + 2).ok_or("truncated TIFF")?;
+        Ok(if big_endian { u16::from_be_bytes([b[0], b[1]]) } else { u16::from_le_bytes([b[0], b[1]]) })
+    };
+    let u32_at = |at: usize| -> Result<u32, String> {
+        let b = data.get(at..at + 4).ok_or("truncated TIFF")?;
+        Ok(if big_endian { u32::from_be_bytes([b[0], b[1], b[2], b[3]]) } else { u32::from_le_bytes([b[0], b[1], b[2], b[3]]) })
+    };
+    // Values of a tag: inline when they fit into four bytes, behind an offset otherwise
+    let values = |entry: usize| -> Result<Vec<u32>, String> {
+        let (kind, count) = (u16_at(entry + 2)?, u32_at(entry + 4)? as usize);
+        let size = match kind {
+            3 => 2,
+            4 => 4,
+            5 => 8,
+            _ => 1,
+        };
+        let base = if size * count <= 4 { entry + 8 } else { u32_at(entry + 8)? as usize };
+        (0..count.min(1 << 20))
+            .map(|i| match kind {
+                3 => u16_at(base + i * 2).map(u32::from),
+                4 => u32_at(base + i * 4),
+                // RATIONAL: keep only the integer part, enough for resolutions
+                5 => Ok(u32_at(base + i * 8)? / u32_at(base + i * 8 + 4)?.max(1)),
+                _ => data.get(base + i).map(|&b| b as u32).ok_or_else(|| "truncated TIFF".to_string()),
+            })
+            .collect()
+    };
+
+    let mut images = Vec::new();
+    let mut ifd = u32_at(4)? as usize;
+    while ifd != 0 && images.len() < 10_000 {
+        let entries = u16_at(ifd)? as usize;
+        let mut tags = std::collections::HashMap::new();
+        for i in 0..entries {
+            let entry = ifd + 2 + i * 12;
+            tags.insert(u16_at(entry)?, values(entry)?);
+        }
+        let tag = |id: u16, default: u32| tags.get(&id).and_then(|v| v.first().copied()).unwrap_or(default);
+        let (width, height) = (tag(256, 0), tag(257, 0));
+        let bits = tag(258, 1) as u8;
+        let compression = tag(259, 1);
+        let photometric = tag(262, 1);
+        let samples = tag(277, 1) as usize;
+        let predictor = tag(317, 1);
+        let unit = tag(296, 2);
+        let resolution = |id: u16| match (tag(id, 0) as f32, unit) {
+            (r, 2) if r > 0.0 => r,
+            (r, 3) if r > 0.0 => r * 2.54,
+            _ => DEFAULT_DPI,
+        };
+        let dpi = (resolution(282), resolution(283));
+        let offsets = tags.get(&273).cloned().unwrap_or_default();
+        let counts = tags.get(&279).cloned().unwrap_or_default();
+        let strips = offsets
+            .iter()
+            .zip(&counts)
+            .map(|(&o, &c)| data.get(o as usize..o as usize + c as usize).ok_or("TIFF strip out of bounds"))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let color_space = match (photometric, samples) {
+            (0 | 1, 1) => device_space(1)?,
+            (2, 3) => device_space(3)?,
+            (5, 4) => device_space(4)?,
+            _ => return Err(format!("photometric interpretation {} with {} samples is not supported", photometric, samples)),
+        };
+        let stream = match compression {
+            3 | 4 if strips.len() == 1 => {
+                // Group 3 is 2D when T4Options says so, Group 4 always is
+                let k = if compression == 4 { -1 } else if tag(292, 0) & 1 != 0 { 1 } else { 0 };
+                let mut dict = image_dict(width, height, color_space, 1);
+                dict.set("Filter", "CCITTFaxDecode");
+                dict.set(
+                    "DecodeParms",
+                    dictionary! { "K" => k, "Columns" => width as i64, "Rows" => height as i64, "BlackIs1" => photometric == 1 },
+                );
+                Stream::new(dict, strips[0].to_vec()).with_compression(false)
+            }
+            1 | 5 | 8 | 32946 => {
+                let mut raw = Vec::new();
+                for strip in strips {
+                    match compression {
+                        1 => raw.extend_from_slice(strip),
+                        5 => raw.extend(
+                            weezl::decode::Decoder::with_tiff_size_switch(weezl::BitOrder::Msb, 8)
+                                .decode(strip)
+                                .map_err(|e| e.to_string())?,
+                        ),
+                        _ => {
+                            ZlibDecoder::new(strip).read_to_end(&mut raw).map_err(|e| e.to_string())?;
+                        }
+                    }
+                }
+                if predictor == 2 && bits == 8 {
+                    let row = width as usize * samples;
+                    for line in raw.chunks_mut(row) {
+                        for i in samples..line.len() {
+                            line[i] = line[i].wrapping_add(line[i - samples]);
+                        }
+                    }
+                }
+                let mut dict = image_dict(width, height, color_space, bits);
+                // WhiteIsZero is inverted against PDF's DeviceGray
+                if photometric == 0 {
+                    dict.set("Decode", vec![Object::Integer(1), Object::Integer(0)]);
+                }
+                dict.set("Filter", "FlateDecode");
+                Stream::new(dict, deflate(&raw)).with_compression(false)
+            }
+            other => return Err(format!("TIFF compression {} is not supported", other)),
+        };
+        images.push(Image { width, height, dpi, stream });
+        ifd = u32_at(ifd + 2 + entries * 12)? as usize;
+    }
+    if images.is_empty() {
+        return Err("TIFF contains no images".to_string());
+    }
+    Ok(images)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The markers of a JPEG up to its frame header `sof`, with a JFIF segment of `dpi` and an
+    /// Adobe segment if asked for; nothing after the frame header is read.
+    fn header(sof: u8, width: u16, height: u16, components: u8, dpi: Option<u16>, adobe: bool) -> Vec<u8> {
+        let mut data = vec![0xff, 0xd8];
+        let mut segment = |marker: u8, body: &[u8]| {
+            data.extend_from_slice(&[0xff, marker]);
+            data.extend_from_slice(&(body.len() as u16 + 2).to_be_bytes());
+            data.extend_from_slice(body);
+        };
+        if let Some(dpi) = dpi {
+            segment(0xe0, &[b"JFIF\0".as_slice(), &[1, 2, 1], &dpi.to_be_bytes(), &dpi.to_be_bytes(), &[0, 0]].concat());
+        }
+        if adobe {
+            segment(0xee, &[b"Adobe".as_slice(), &[0, 100, 0, 0, 0, 0, 0]].concat());
+        }
+        let mut frame = [&[8], &height.to_be_bytes()[..], &width.to_be_bytes(), &[components]].concat();
+        for id in 1..=components {
+            frame.extend_from_slice(&[id, 0x11, 0]);
+        }
+        segment(sof, &frame);
+        data.extend_from_slice(&[0xff, 0xd9]);
+        data
+    }
+
+    fn name(dict: &Dictionary, key: &[u8]) -> String {
+        String::from_utf8_lossy(dict.get(key).unwrap().as_name().unwrap()).into_owned()
+    }
+
+    #[test]
+    fn baseline_jpegs_go_in_untouched() {
+        let data = jpeg::encode(40, 30, 3, &vec![200; 40 * 30 * 3], 80);
+        let image = jpeg(data.clone()).unwrap();
+        assert_eq!((image.width, image.height, image.dpi), (40, 30, (DEFAULT_DPI, DEFAULT_DPI)));
+        assert_eq!(name(&image.stream.dict, b"ColorSpace"), "DeviceRGB");
+        assert_eq!(name(&image.stream.dict, b"Filter"), "DCTDecode");
+        assert_eq!(image.stream.content, data);
+    }
+
+    #[test]
+    fn progressive_frames_give_their_size() {
+        let image = jpeg(header(0xc2, 640, 480, 1, Some(300), false)).unwrap();
+        assert_eq!((image.width, image.height, image.dpi), (640, 480, (300.0, 300.0)));
+        assert_eq!(name(&image.stream.dict, b"ColorSpace"), "DeviceGray");
+        // 640 pixels at 300 dpi are 153.6 points wide
+        let doc = image_document(vec![image]);
+        let page = doc.get_dictionary(doc.get_pages()[&1]).unwrap();
+        let media_box = page.get(b"MediaBox").unwrap().as_array().unwrap();
+        assert!((media_box[2].as_float().unwrap() - 153.6).abs() < 0.01);
+    }
+
+    #[test]
+    fn adobe_cmyk_is_inverted_by_its_decode_array() {
+        let image = jpeg(header(0xc0, 8, 8, 4, None, true)).unwrap();
+        assert_eq!(name(&image.stream.dict, b"ColorSpace"), "DeviceCMYK");
+        let decode = image.stream.dict.get(b"Decode").unwrap().as_array().unwrap();
+        assert_eq!(decode.iter().map(|v| v.as_i64().unwrap()).collect::<Vec<_>>(), [1, 0, 1, 0, 1, 0, 1, 0]);
+        let image = jpeg(header(0xc0, 8, 8, 4, None, false)).unwrap();
+        assert!(!image.stream.dict.has(b"Decode"));
+    }
+
+    #[test]
+    fn malformed_headers_are_errors_not_panics() {
+        assert!(jpeg(header(0xc0, 8, 8, 2, None, false)).is_err());
+        let data = header(0xc2, 8, 8, 3, Some(96), true);
+        for end in 0..data.len() {
+            let _ = jpeg(data[..end].to_vec());
+        }
+        // A frame header too short to hold the size
+        let mut short = vec![0xff, 0xd8, 0xff, 0xc0, 0, 5, 8, 0, 8];
+        assert!(jpeg(short.clone()).is_err());
+        short[5] = 0;
+        assert!(jpeg(short).is_err());
+        assert!(jpeg(vec![0xff, 0xd8, 0x00, 0xc0, 0, 0]).is_err());
+    }
+}
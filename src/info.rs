@@ -0,0 +1,357 @@
+use std::{
+    collections::HashSet,
+    path::{Path, PathBuf},
+};
+
+use lopdf::{Dictionary, Document, Object, ObjectId};
+
+use crate::{
+    json::{self, Value},
+    messages::tr,
+    outline::{self, OutlineEntry},
+    pages, text,
+};
+
+/// Document information entries `Summary` shows, in this order, with their labels in `print`.
+const METADATA_KEYS: [(&str, &str); 8] = [
+    ("Title", "title"),
+    ("Author", "author"),
+    ("Subject", "subject"),
+    ("Keywords", "keywords"),
+    ("Creator", "creator"),
+    ("Producer", "producer"),
+    ("CreationDate", "created"),
+    ("ModDate", "modified"),
+];
+
+/// How deep the field tree of a form is followed.
+const MAX_FIELD_DEPTH: usize = 32;
+
+/// How deep Form XObjects are looked into for the fonts and images of a page.
+const MAX_FORM_DEPTH: usize = 8;
+
+/// What `merge_pdf info` tells about a PDF before it is merged.
+#[derive(Debug, Default)]
+pub struct Summary {
+    pub path: PathBuf,
+    pub version: String,
+    pub pages: usize,
+    /// Every page size in points, with how many pages have it, in the order they come up
+    pub page_sizes: Vec<((f32, f32), usize)>,
+    pub encrypted: bool,
+    /// Bookmarks at every level
+    pub bookmarks: usize,
+    /// Levels of the outline, 0 without one
+    pub outline_depth: usize,
+    /// Fields of the interactive form
+    pub form_fields: usize,
+    /// Annotations of all pages, links and form widgets included
+    pub annotations: usize,
+    /// Document information entries that are set, dates as written
+    pub metadata: Vec<(&'static str, String)>,
+}
+
+impl Summary {
+    /// Look `doc`, loaded from `path`, over.
+    pub fn new(path: &Path, doc: &Document) -> Self {
+        let pages = doc.get_pages();
+        let mut page_sizes: Vec<((f32, f32), usize)> = Vec::new();
+        for &id in pages.values() {
+            let Some(size) = page_size(doc, id) else { continue };
+            match page_sizes.iter_mut().find(|(known, _)| *known == size) {
+                Some((_, count)) => *count += 1,
+                None => page_sizes.push((size, 1)),
+            }
+        }
+        let annotations = pages
+            .values()
+            .filter_map(|&id| pages::with_inherited_attributes(doc, id).ok())
+            .filter_map(|page| Some(page.as_dict().ok()?.get_deref(b"Annots", doc).ok()?.as_array().ok()?.len()))
+            .sum();
+        let outline = outline::read(doc);
+        let info = doc.trailer.get_deref(b"Info", doc).and_then(Object::as_dict).ok();
+        let metadata = METADATA_KEYS
+            .into_iter()
+            .filter_map(|(key, _)| Some((key, text::decode(info?.get_deref(key.as_bytes(), doc).and_then(Object::as_str).ok()?))))
+            .collect();
+        Summary {
+            path: path.to_path_buf(),
+            version: doc.version.clone(),
+            pages: pages.len(),
+            page_sizes,
+            encrypted: is_encrypted(path),
+            bookmarks: count_entries(&outline),
+            outline_depth: depth(&outline),
+            form_fields: form_fields(doc),
+            annotations,
+            metadata,
+        }
+    }
+
+    /// Print the summary for people to read.
+    pub fn print(&self) {
+        let yes_no = |yes: bool| if yes { tr("yes", &[]) } else { tr("no", &[]) };
+        let label = |label: &str| tr(label, &[]);
+        let metadata = self
+            .metadata
+            .iter()
+            .map(|(key, value)| {
+                let label = METADATA_KEYS.iter().find(|(known, _)| known == key).map_or("", |(_, label)| label);
+                (format!("{}:", tr(label, &[])), value)
+            })
+            .collect::<Vec<_>>();
+        // The values line up after the longest label, however long it is in the language used
+        let labels = ["version:", "pages:", "page size:", "encrypted:", "bookmarks:", "form fields:", "annotations:"].map(label);
+        let width = labels.iter().chain(metadata.iter().map(|(label, _)| label)).map(|label| label.chars().count()).max().unwrap_or(0).max(12);
+        let [version, pages, page_size, encrypted, bookmarks, form_fields, annotations] = labels;
+        println!("{}", self.path.display());
+        println!("  {:<width$} {}", version, self.version);
+        println!("  {:<width$} {}", pages, self.pages);
+        for (n, ((width_pt, height_pt), count)) in self.page_sizes.iter().enumerate() {
+            let label = if n == 0 { page_size.as_str() } else { "" };
+            if self.page_sizes.len() == 1 {
+                println!("  {:<width$} {} x {} pt", label, width_pt, height_pt);
+            } else {
+                println!("  {:<width$} {}", label, tr("{} x {} pt ({} page(s))", &[width_pt, height_pt, count]));
+            }
+        }
+        println!("  {:<width$} {}", encrypted, yes_no(self.encrypted));
+        println!("  {:<width$} {}", bookmarks, tr("{} ({} level(s))", &[&self.bookmarks, &self.outline_depth]));
+        println!("  {:<width$} {}", form_fields, self.form_fields);
+        println!("  {:<width$} {}", annotations, self.annotations);
+        for (label, value) in &metadata {
+            println!("  {:<width$} {}", label, value);
+        }
+    }
+
+    /// The summary as a JSON object like
+    ///
+    /// ```json
+    /// {
+    ///   "path": "a.pdf",
+    ///   "version": "1.7",
+    ///   "pages": 3,
+    ///   "page_sizes": [{ "width": 612, "height": 792, "pages": 3 }],
+    ///   "encrypted": false,
+    ///   "bookmarks": 4,
+    ///   "outline_depth": 2,
+    ///   "form_fields": 0,
+    ///   "annotations": 1,
+    ///   "metadata": { "Title": "Report", "Producer": "merge_pdf" }
+    /// }
+    /// ```
+    pub fn to_json(&self) -> Value {
+        let number = |n: usize| Value::Number(n as f64);
+        let page_sizes = self
+            .page_sizes
+            .iter()
+            .map(|&((width, height), count)| {
+                Value::Object(vec![
+                    ("width".to_string(), Value::Number(width.into())),
+                    ("height".to_string(), Value::Number(height.into())),
+                    ("pages".to_string(), number(count)),
+                ])
+            })
+            .collect();
+        Value::Object(vec![
+            ("path".to_string(), Value::String(self.path.display().to_string())),
+            ("version".to_string(), Value::String(self.version.clone())),
+            ("pages".to_string(), number(self.pages)),
+            ("page_sizes".to_string(), Value::Array(page_sizes)),
+            ("encrypted".to_string(), Value::Bool(self.encrypted)),
+            ("bookmarks".to_string(), number(self.bookmarks)),
+            ("outline_depth".to_string(), number(self.outline_depth)),
+            ("form_fields".to_string(), number(self.form_fields)),
+            ("annotations".to_string(), number(self.annotations)),
+            ("metadata".to_string(), Value::Object(self.metadata.iter().map(|(key, value)| (key.to_string(), Value::String(value.clone()))).collect())),
+        ])
+    }
+}
+
+/// What `merge_pdf pages` tells about a page: its boxes, rotation and what it draws with.
+#[derive(Debug, Default)]
+pub struct PageSummary {
+    /// Counted from 1
+    pub number: u32,
+    /// Width and height in points
+    pub media_box: Option<(f32, f32)>,
+    /// Width and height in points, when the page has a CropBox of its own or inherited
+    pub crop_box: Option<(f32, f32)>,
+    /// Degrees clockwise
+    pub rotation: i64,
+    /// Names of the fonts of the page and its forms, subset prefixes and all
+    pub fonts: Vec<String>,
+    /// Image XObjects of the page and its forms
+    pub images: usize,
+}
+
+impl PageSummary {
+    /// Look page `number` of `doc`, the object `page_id`, over.
+    pub fn new(doc: &Document, number: u32, page_id: ObjectId) -> Self {
+        let mut summary = PageSummary { number, ..PageSummary::default() };
+        let Ok(Object::Dictionary(page)) = pages::with_inherited_attributes(doc, page_id) else { return summary };
+        let size = |key: &[u8]| {
+            let n = page.get_deref(key, doc).and_then(Object::as_array).ok()?.iter().map(|v| v.as_float().ok()).collect::<Option<Vec<_>>>()?;
+            (n.len() == 4).then(|| ((n[2] - n[0]).abs(), (n[3] - n[1]).abs()))
+        };
+        summary.media_box = size(b"MediaBox");
+        summary.crop_box = size(b"CropBox");
+        summary.rotation = page.get(b"Rotate").and_then(Object::as_i64).unwrap_or(0).rem_euclid(360);
+        let mut seen = HashSet::new();
+        if let Ok(resources) = page.get_deref(b"Resources", doc).and_then(Object::as_dict) {
+            summary.add_resources(doc, resources, &mut seen, 0);
+        }
+        summary
+    }
+
+    fn add_resources(&mut self, doc: &Document, resources: &Dictionary, seen: &mut HashSet<ObjectId>, depth: usize) {
+        if let Ok(fonts) = resources.get_deref(b"Font", doc).and_then(Object::as_dict) {
+            for (_, font) in fonts.iter() {
+                let name = doc.dereference(font).and_then(|(_, font)| font.as_dict()).and_then(|font| font.get_deref(b"BaseFont", doc)).and_then(Object::as_name);
+                if let Ok(name) = name {
+                    let name = String::from_utf8_lossy(name).into_owned();
+                    if !self.fonts.contains(&name) {
+                        self.fonts.push(name);
+                    }
+                }
+            }
+        }
+        let Ok(xobjects) = resources.get_deref(b"XObject", doc).and_then(Object::as_dict) else { return };
+        for (_, xobject) in xobjects.iter() {
+            // A form or image used under several names, or by several forms, counts once
+            if let Object::Reference(id) = xobject {
+                if !seen.insert(*id) {
+                    continue;
+                }
+            }
+            let Ok(stream) = doc.dereference(xobject).and_then(|(_, xobject)| xobject.as_stream()) else { continue };
+            match stream.dict.get(b"Subtype").and_then(Object::as_name) {
+                Ok(b"Image") => self.images += 1,
+                Ok(b"Form") if depth < MAX_FORM_DEPTH => {
+                    if let Ok(own) = stream.dict.get_deref(b"Resources", doc).and_then(Object::as_dict) {
+                        self.add_resources(doc, own, seen, depth + 1);
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    /// Print the page on one line for people to read.
+    pub fn print(&self) {
+        let mut line = format!("  {:>4}  ", self.number);
+        match self.media_box {
+            Some((width, height)) => line.push_str(&format!("{} x {} pt", width, height)),
+            None => line.push_str(&tr("no MediaBox", &[])),
+        }
+        if let Some((width, height)) = self.crop_box.filter(|&crop| Some(crop) != self.media_box) {
+            line.push_str(&tr(", cropped to {} x {} pt", &[&width, &height]));
+        }
+        if self.rotation != 0 {
+            line.push_str(&tr(", rotated {}°", &[&self.rotation]));
+        }
+        line.push_str(&tr(", {} font(s)", &[&self.fonts.len()]));
+        if !self.fonts.is_empty() {
+            line.push_str(&format!(" ({})", self.fonts.join(", ")));
+        }
+        line.push_str(&tr(", {} image(s)", &[&self.images]));
+        println!("{}", line);
+    }
+
+    /// The page as a JSON object like
+    ///
+    /// ```json
+    /// {
+    ///   "page": 1,
+    ///   "media_box": { "width": 612, "height": 792 },
+    ///   "crop_box": null,
+    ///   "rotation": 0,
+    ///   "fonts": ["Helvetica"],
+    ///   "images": 2
+    /// }
+    /// ```
+    pub fn to_json(&self) -> Value {
+        let size = |size: Option<(f32, f32)>| match size {
+            Some((width, height)) => Value::Object(vec![("width".to_string(), Value::Number(width.into())), ("height".to_string(), Value::Number(height.into()))]),
+            None => Value::Null,
+        };
+        Value::Object(vec![
+            ("page".to_string(), Value::Number(self.number.into())),
+            ("media_box".to_string(), size(self.media_box)),
+            ("crop_box".to_string(), size(self.crop_box)),
+            ("rotation".to_string(), Value::Number(self.rotation as f64)),
+            ("fonts".to_string(), Value::Array(self.fonts.iter().cloned().map(Value::String).collect())),
+            ("images".to_string(), Value::Number(self.images as f64)),
+        ])
+    }
+}
+
+/// Every page of `doc`, in order.
+pub fn page_summaries(doc: &Document) -> Vec<PageSummary> {
+    doc.get_pages().into_iter().map(|(number, id)| PageSummary::new(doc, number, id)).collect()
+}
+
+/// `summaries` as a JSON array, indented by two spaces per level.
+pub fn to_json(summaries: &[Summary]) -> String {
+    json::to_string_pretty(&Value::Array(summaries.iter().map(Summary::to_json).collect()))
+}
+
+/// `pages` as a JSON array, like `to_json`.
+pub fn pages_to_json(pages: &[PageSummary]) -> String {
+    json::to_string_pretty(&Value::Array(pages.iter().map(PageSummary::to_json).collect()))
+}
+
+/// Whether the file at `path` is encrypted; documents come decrypted out of `input::load`.
+pub fn is_encrypted(path: &Path) -> bool {
+    std::fs::read(path).is_ok_and(|bytes| Document::load_mem(&bytes).is_ok_and(|doc| doc.is_encrypted()))
+}
+
+/// Width and height of a page, from its own or an inherited MediaBox.
+fn page_size(doc: &Document, page_id: ObjectId) -> Option<(f32, f32)> {
+    let mut node = doc.get_dictionary(page_id).ok()?;
+    loop {
+        if let Ok(media_box) = node.get_deref(b"MediaBox", doc).and_then(Object::as_array) {
+            let n = media_box.iter().map(|v| v.as_float().ok()).collect::<Option<Vec<_>>>()?;
+            return (n.len() == 4).then(|| ((n[2] - n[0]).abs(), (n[3] - n[1]).abs()));
+        }
+        node = node.get_deref(b"Parent", doc).and_then(Object::as_dict).ok()?;
+    }
+}
+
+fn count_entries(entries: &[OutlineEntry]) -> usize {
+    entries.iter().map(|entry| 1 + count_entries(&entry.children)).sum()
+}
+
+fn depth(entries: &[OutlineEntry]) -> usize {
+    entries.iter().map(|entry| 1 + depth(&entry.children)).max().unwrap_or(0)
+}
+
+/// The terminal fields of the catalog's `AcroForm`, those without field kids.
+fn form_fields(doc: &Document) -> usize {
+    let fields = doc
+        .catalog()
+        .and_then(|catalog| catalog.get_deref(b"AcroForm", doc))
+        .and_then(Object::as_dict)
+        .and_then(|form| form.get_deref(b"Fields", doc))
+        .and_then(Object::as_array);
+    let Ok(fields) = fields else { return 0 };
+    let mut seen = HashSet::new();
+    fields.iter().map(|field| terminal_fields(doc, field, &mut seen, 0)).sum()
+}
+
+fn terminal_fields(doc: &Document, field: &Object, seen: &mut HashSet<ObjectId>, depth: usize) -> usize {
+    if let Object::Reference(id) = field {
+        if !seen.insert(*id) {
+            return 0;
+        }
+    }
+    let Ok(dict) = doc.dereference(field).and_then(|(_, object)| object.as_dict()) else { return 0 };
+    // Kids without a name of their own are its widgets, not fields
+    let kids = dict.get_deref(b"Kids", doc).and_then(Object::as_array).map(|kids| {
+        kids.iter().filter(|kid| doc.dereference(kid).and_then(|(_, kid)| kid.as_dict()).is_ok_and(|kid| kid.has(b"T"))).collect::<Vec<_>>()
+    });
+    match kids {
+        Ok(kids) if !kids.is_empty() && depth < MAX_FIELD_DEPTH => kids.into_iter().map(|kid| terminal_fields(doc, kid, seen, depth + 1)).sum(),
+        _ => 1,
+    }
+}
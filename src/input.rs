@@ -0,0 +1,424 @@
+use std::{
+    borrow::Cow,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+#[cfg(feature = "cli")]
+use std::{collections::HashMap, fs};
+
+#[cfg(feature = "cli")]
+use crate::sha256;
+use crate::{
+    messages::tr,
+    outline::{BookmarkColor, BookmarkStyle, PageBookmark},
+    pages::Margins,
+};
+
+/// A `--files` entry: a path optionally followed by `:option` markers, e.g. `scans.pdf:nocompress`
+/// or `invoice.pdf:1-3,7` to take only some pages, `:odd` or `:even` to take every other page, and
+/// `@rot=N` to rotate them, e.g. `scan.pdf@rot=180`, `@crop=L,B,R,T` to trim their margins, and
+/// `@pass=secret` to open it if it is encrypted, which goes last: the rest is the password.
+#[derive(Debug, Clone)]
+pub struct InputSpec {
+    pub path: PathBuf,
+    /// Whether objects coming from this document take part in the `--compress` pass.
+    pub compress: bool,
+    /// Bookmark title instead of the generated one.
+    pub title: Option<String>,
+    /// Outline layer instead of the input's position.
+    pub layer: Option<u32>,
+    /// Pages to take from the document, all of them if unset.
+    pub pages: Option<PageRanges>,
+    /// Take only the odd or the even pages, on top of `pages`.
+    pub parity: Option<Parity>,
+    /// Clockwise rotation in degrees added to every page, a multiple of 90.
+    pub rotate: i64,
+    /// Margins to trim off every page instead of `MergeOptions::crop`.
+    pub crop: Option<Margins>,
+    /// Take the pages last to first.
+    pub reverse: bool,
+    /// Titles of the outline sections the input sits in, outermost first; see `BookmarkMode::Sections`.
+    pub sections: Vec<String>,
+    /// Bookmarks below the input's own, in place of the document's outline.
+    pub bookmarks: Vec<PageBookmark>,
+    /// The manifest group, and so the output file, the input belongs to; see `manifest::Manifest::groups`.
+    pub group: Option<usize>,
+    /// Color of the input's bookmark instead of `MergeOptions::bookmark_color`.
+    pub bookmark_color: Option<BookmarkColor>,
+    /// Style of the input's bookmark instead of `MergeOptions::bookmark_style`.
+    pub bookmark_style: Option<BookmarkStyle>,
+    /// User or owner password that opens the document if it is encrypted.
+    pub password: Option<String>,
+}
+
+impl InputSpec {
+    pub fn new(path: PathBuf) -> Self {
+        InputSpec {
+            path,
+            compress: true,
+            title: None,
+            layer: None,
+            pages: None,
+            parity: None,
+            rotate: 0,
+            crop: None,
+            reverse: false,
+            sections: Vec::new(),
+            bookmarks: Vec::new(),
+            group: None,
+            bookmark_color: None,
+            bookmark_style: None,
+            password: None,
+        }
+    }
+
+    /// Whether page `number` (1-based) of the document is taken into the merge.
+    pub fn selects(&self, number: u32) -> bool {
+        self.pages.as_ref().is_none_or(|pages| pages.contains(number)) && self.parity.is_none_or(|parity| parity.contains(number))
+    }
+}
+
+impl FromStr for InputSpec {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut spec = InputSpec::new(PathBuf::new());
+        let mut rest = s;
+        // The password comes last and is taken as it is, ':' and '@' included
+        if let Some(at) = s.find("@pass=") {
+            spec.password = Some(s[at + "@pass=".len()..].to_string());
+            rest = &s[..at];
+        }
+        // Options are peeled off from the right so paths containing ':' or '@' still work
+        while let Some(at) = rest.rfind([':', '@']) {
+            let (head, option) = (&rest[..at], &rest[at + 1..]);
+            match (&rest[at..=at], option) {
+                (":", "nocompress") => spec.compress = false,
+                (":", "odd") => spec.parity = Some(Parity::Odd),
+                (":", "even") => spec.parity = Some(Parity::Even),
+                (":", _) => match option.parse::<PageRanges>() {
+                    Ok(pages) => spec.pages = Some(pages),
+                    Err(_) => break,
+                },
+                (_, _) => {
+                    if let Some(degrees) = option.strip_prefix("rot=") {
+                        spec.rotate = parse_rotation(degrees)?;
+                    } else if let Some(margins) = option.strip_prefix("crop=") {
+                        spec.crop = Some(margins.parse()?);
+                    } else {
+                        break;
+                    }
+                }
+            }
+            rest = head;
+        }
+        if rest.is_empty() {
+            return Err(format!("{:?} has no file path", s));
+        }
+        spec.path = PathBuf::from(rest);
+        Ok(spec)
+    }
+}
+
+/// Parse a rotation in degrees, which must be a multiple of 90 (negative turns counter-clockwise).
+pub fn parse_rotation(s: &str) -> Result<i64, String> {
+    s.trim()
+        .parse::<i64>()
+        .ok()
+        .filter(|degrees| degrees % 90 == 0)
+        .ok_or_else(|| format!("{:?} is not a rotation, it must be a multiple of 90", s))
+}
+
+/// Every other page of a document, counted by its page numbers in the document.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Parity {
+    Odd,
+    Even,
+}
+
+impl Parity {
+    pub fn contains(self, page: u32) -> bool {
+        (page % 2 == 1) == (self == Parity::Odd)
+    }
+}
+
+impl FromStr for Parity {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "odd" => Ok(Parity::Odd),
+            "even" => Ok(Parity::Even),
+            _ => Err(format!("{:?} is neither \"odd\" nor \"even\"", s)),
+        }
+    }
+}
+
+/// A set of 1-based page numbers written like `1-3,7,10-` (open ends run to the first/last page).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PageRanges(Vec<(u32, Option<u32>)>);
+
+impl PageRanges {
+    pub fn contains(&self, page: u32) -> bool {
+        self.0.iter().any(|&(first, last)| first <= page && last.is_none_or(|last| page <= last))
+    }
+}
+
+/// Exactly the given pages.
+impl FromIterator<u32> for PageRanges {
+    fn from_iter<I: IntoIterator<Item = u32>>(pages: I) -> Self {
+        PageRanges(pages.into_iter().map(|page| (page, Some(page))).collect())
+    }
+}
+
+impl FromStr for PageRanges {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let number = |n: &str| n.trim().parse::<u32>().ok().filter(|&n| n > 0);
+        s.split(',')
+            .map(|part| {
+                let range = match part.split_once('-') {
+                    Some((first, "")) => number(first).map(|first| (first, None)),
+                    Some(("", last)) => number(last).map(|last| (1, Some(last))),
+                    Some((first, last)) => number(first).zip(number(last)).map(|(first, last)| (first, Some(last))),
+                    None => number(part).map(|page| (page, Some(page))),
+                };
+                range.filter(|(first, last)| last.is_none_or(|last| *first <= last))
+                    .ok_or_else(|| format!("{:?} is not a valid page range", part))
+            })
+            .collect::<Result<Vec<_>, _>>()
+            .map(PageRanges)
+    }
+}
+
+/// Read a list of input paths, one per line or NUL-separated (as `find -print0` writes them),
+/// from `source`: a file, or stdin when it is `-`. Entries are taken as plain paths, without options.
+#[cfg(feature = "cli")]
+pub fn read_list(source: &Path) -> std::io::Result<Vec<InputSpec>> {
+    let mut text = Vec::new();
+    if source == Path::new("-") {
+        std::io::Read::read_to_end(&mut std::io::stdin().lock(), &mut text)?;
+    } else {
+        text = std::fs::read(source)?;
+    }
+    let separator = if text.contains(&0) { b'\0' } else { b'\n' };
+    Ok(text
+        .split(|&b| b == separator)
+        .map(|entry| String::from_utf8_lossy(entry).trim_end_matches('\r').to_string())
+        .filter(|entry| !entry.is_empty())
+        .map(|entry| InputSpec::new(entry.into()))
+        .collect())
+}
+
+/// What to do with an input whose bytes are identical to an earlier input at another path.
+#[cfg(feature = "cli")]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum Duplicates {
+    /// Merge it anyway without checking (no hashing)
+    #[default]
+    Keep,
+    /// Merge it but print a warning
+    Warn,
+    /// Leave it out of the merge
+    Skip,
+}
+
+/// Find inputs with the same SHA-256 as an earlier input at a different path and warn about or
+/// drop them. Listing the very same path twice is deliberate repetition and never a duplicate.
+#[cfg(feature = "cli")]
+pub fn handle_duplicates(files: Vec<InputSpec>, predir: &Path, mode: Duplicates) -> std::io::Result<Vec<InputSpec>> {
+    if mode == Duplicates::Keep {
+        return Ok(files);
+    }
+    let mut first_with_hash: HashMap<[u8; 32], PathBuf> = HashMap::new();
+    let mut hashes: HashMap<PathBuf, [u8; 32]> = HashMap::new();
+    let mut kept = Vec::with_capacity(files.len());
+    let mut skipped = 0;
+    for spec in files {
+        let path = resolve(predir, &spec.path);
+        let hash = match hashes.get(&path) {
+            Some(hash) => *hash,
+            None => {
+                let hash = sha256::digest(&fs::read(&path)?);
+                hashes.insert(path.clone(), hash);
+                hash
+            }
+        };
+        let original = first_with_hash.entry(hash).or_insert_with(|| path.clone());
+        if *original == path {
+            kept.push(spec);
+            continue;
+        }
+        match mode {
+            Duplicates::Skip => {
+                log::info!("{}", tr("Skipping {}: same content as {}", &[&path.display(), &original.display()]));
+                skipped += 1;
+            }
+            _ => {
+                log::warn!("{}", tr("{} has the same content as {}", &[&path.display(), &original.display()]));
+                kept.push(spec);
+            }
+        }
+    }
+    if skipped > 0 {
+        log::info!("{}", tr("Skipped {} duplicate input(s)", &[&skipped]));
+    }
+    Ok(kept)
+}
+
+/// Resolve an input path against `--predir`: absolute paths are used as given, relative ones
+/// are taken relative to `predir`. Messages about an input should always show this result.
+pub fn resolve(predir: &Path, path: &Path) -> PathBuf {
+    if path.is_absolute() {
+        path.to_path_buf()
+    } else {
+        predir.join(path)
+    }
+}
+
+/// Load every one of `inputs` with `load`, as many at a time as there are cores, and return the
+/// results in the order of `inputs`. `loaded` is called with the index and result of every input
+/// in that order too, as soon as it and all before it are loaded.
+pub fn load_all<I: Sync>(
+    inputs: &[I],
+    load: impl Fn(&I) -> crate::Result<lopdf::Document> + Sync,
+    mut loaded: impl FnMut(usize, &crate::Result<lopdf::Document>),
+) -> Vec<crate::Result<lopdf::Document>> {
+    let threads = std::thread::available_parallelism().map_or(1, usize::from).min(inputs.len());
+    if threads <= 1 {
+        return inputs
+            .iter()
+            .enumerate()
+            .map(|(index, input)| {
+                let result = load(input);
+                loaded(index, &result);
+                result
+            })
+            .collect();
+    }
+    let mut results = inputs.iter().map(|_| None).collect::<Vec<_>>();
+    // The next input for a thread to take
+    let next = std::sync::atomic::AtomicUsize::new(0);
+    let (sender, receiver) = std::sync::mpsc::channel();
+    std::thread::scope(|scope| {
+        for _ in 0..threads {
+            let (next, load, sender) = (&next, &load, sender.clone());
+            scope.spawn(move || loop {
+                let index = next.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+                let Some(input) = inputs.get(index) else { break };
+                if sender.send((index, load(input))).is_err() {
+                    break;
+                }
+            });
+        }
+        drop(sender);
+        let mut done = 0;
+        for (index, result) in receiver {
+            results[index] = Some(result);
+            while let Some(Some(result)) = results.get(done) {
+                loaded(done, result);
+                done += 1;
+            }
+        }
+    });
+    results.into_iter().map(|result| result.expect("every input is loaded by one of the threads")).collect()
+}
+
+/// Load the document at an already resolved path, naming that path in the error.
+pub fn load(path: &Path) -> crate::Result<lopdf::Document> {
+    load_with_passwords(path, &[])
+}
+
+/// `load`, decrypting the document with the first of `passwords` that opens it if it is
+/// encrypted, see `decrypt::decrypt`.
+pub fn load_with_passwords(path: &Path, passwords: &[&str]) -> crate::Result<lopdf::Document> {
+    load_with(path, passwords, false)
+}
+
+/// `load_with_passwords`, and when `lenient`, repairing a document that is damaged or does not
+/// follow the standard, see `repair::load`.
+pub fn load_with(path: &Path, passwords: &[&str], lenient: bool) -> crate::Result<lopdf::Document> {
+    log::debug!("Loading {}", path.display());
+    if crate::image::is_image(path) {
+        return crate::image::load(path);
+    }
+    let invalid = |e: lopdf::Error| crate::Error::BrokenInput(path.to_path_buf(), e);
+    let bytes = crate::mmap::read(path).map_err(|e| crate::Error::MissingInput(path.to_path_buf(), e))?;
+    let (doc, bytes) = if lenient {
+        crate::repair::load(&bytes).map_err(invalid)?
+    } else {
+        (lopdf::Document::load_mem(&bytes).map_err(invalid)?, Cow::Borrowed(&bytes[..]))
+    };
+    if let Cow::Owned(_) = bytes {
+        log::warn!(path:% = path.display(); "{}", tr("damaged, its objects were found by scanning the file", &[]));
+    }
+    if !doc.is_encrypted() {
+        return Ok(doc);
+    }
+    crate::decrypt::decrypt(path, &bytes, doc, passwords)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// An absolute path, whatever platform the tests run on.
+    fn absolute(name: &str) -> PathBuf {
+        std::env::temp_dir().join("merge_pdf-resolve").join(name)
+    }
+
+    #[test]
+    fn passwords_are_taken_verbatim() {
+        let spec = "a.pdf@pass=p@ss:1".parse::<InputSpec>().unwrap();
+        assert_eq!(spec.path, Path::new("a.pdf"));
+        assert_eq!(spec.password.as_deref(), Some("p@ss:1"));
+        assert_eq!(spec.pages, None);
+        // Other options go before it, and paths may still have ':' and '@'
+        let spec = "C:/scans/me@home.pdf:2-3@rot=90@pass=@rot=180:odd".parse::<InputSpec>().unwrap();
+        assert_eq!(spec.path, Path::new("C:/scans/me@home.pdf"));
+        assert_eq!(spec.pages, Some("2-3".parse().unwrap()));
+        assert_eq!(spec.rotate, 90);
+        assert_eq!(spec.parity, None);
+        assert_eq!(spec.password.as_deref(), Some("@rot=180:odd"));
+        assert_eq!("a.pdf@pass=".parse::<InputSpec>().unwrap().password.as_deref(), Some(""));
+        assert!("@pass=secret".parse::<InputSpec>().is_err());
+    }
+
+    #[test]
+    fn absolute_inputs_ignore_the_predir() {
+        let input = absolute("a.pdf");
+        assert_eq!(resolve(Path::new("scans"), &input), input);
+        assert_eq!(resolve(Path::new("."), &input), input);
+    }
+
+    #[test]
+    fn relative_inputs_are_joined_to_the_predir() {
+        assert_eq!(resolve(Path::new("scans"), Path::new("a.pdf")), Path::new("scans/a.pdf"));
+        assert_eq!(resolve(Path::new("scans"), Path::new("sub/a.pdf")), Path::new("scans/sub/a.pdf"));
+    }
+
+    #[test]
+    fn relative_inputs_without_a_predir_stay_in_the_working_directory() {
+        // `.` is the predir when none is given
+        assert_eq!(resolve(Path::new("."), Path::new("a.pdf")), Path::new("./a.pdf"));
+    }
+
+    #[test]
+    fn mixed_lists_resolve_each_input_on_its_own() {
+        let predir = absolute("predir");
+        let inputs = [absolute("a.pdf"), PathBuf::from("b.pdf"), absolute("c.pdf"), PathBuf::from("sub/d.pdf")];
+        let resolved = inputs.iter().map(|input| resolve(&predir, input)).collect::<Vec<_>>();
+        assert_eq!(resolved, [absolute("a.pdf"), predir.join("b.pdf"), absolute("c.pdf"), predir.join("sub/d.pdf")]);
+    }
+
+    #[test]
+    fn errors_show_the_resolved_path() {
+        let predir = absolute("missing");
+        for input in [PathBuf::from("gone.pdf"), absolute("gone.pdf")] {
+            let tried = resolve(&predir, &input);
+            let message = load(&tried).unwrap_err().to_string();
+            assert!(message.starts_with(&tried.display().to_string()), "{}", message);
+        }
+    }
+}
@@ -0,0 +1,82 @@
+use std::{
+    io::{self, BufRead, IsTerminal, Write},
+    path::PathBuf,
+};
+
+use merge_pdf::messages::tr;
+
+/// One input as shown in the interactive list.
+#[derive(Debug, Clone)]
+pub struct Entry {
+    /// Position of the input in the resolved input list.
+    pub source: usize,
+    pub path: PathBuf,
+    pub pages: usize,
+    /// Bookmark title, the generated one when unset.
+    pub title: Option<String>,
+}
+
+const HELP: &str = "\
+  m FROM TO     move entry FROM to position TO
+  d N           drop entry N
+  r N TITLE     rename the bookmark of entry N (no TITLE: back to the generated one)
+  done          merge in the order shown
+  q             cancel";
+
+/// Let the user reorder, drop and rename the inputs on the terminal before merging.
+///
+/// Returns the entries as arranged, or `None` when the user cancels.
+pub fn arrange(mut entries: Vec<Entry>) -> io::Result<Option<Vec<Entry>>> {
+    if !io::stdin().is_terminal() {
+        log::error!("{}", tr("--interactive needs a terminal", &[]));
+        return Ok(None);
+    }
+    let stdin = io::stdin();
+    let mut lines = stdin.lock().lines();
+    println!("{}", tr(HELP, &[]));
+    loop {
+        println!();
+        for (i, entry) in entries.iter().enumerate() {
+            let title = entry.title.as_ref().map(|t| format!(" \"{}\"", t)).unwrap_or_default();
+            println!("{:>3}. {}{}", i + 1, tr("{} ({} pages)", &[&entry.path.display(), &entry.pages]), title);
+        }
+        print!("> ");
+        io::stdout().flush()?;
+        let Some(line) = lines.next().transpose()? else {
+            return Ok(None);
+        };
+        let line = line.trim();
+        let (command, rest) = line.split_once(' ').map_or((line, ""), |(c, r)| (c, r.trim()));
+        // Positions are 1-based as listed
+        let position = |n: &str| n.parse::<usize>().ok().filter(|n| (1..=entries.len()).contains(n)).map(|n| n - 1);
+        match command {
+            "m" => {
+                let mut numbers = rest.split_whitespace().map(position);
+                match (numbers.next().flatten(), numbers.next().flatten()) {
+                    (Some(from), Some(to)) => {
+                        let entry = entries.remove(from);
+                        entries.insert(to, entry);
+                    }
+                    _ => println!("{}", tr("usage: m FROM TO, both between 1 and {}", &[&entries.len()])),
+                }
+            }
+            "d" => match position(rest) {
+                Some(n) if entries.len() > 1 => {
+                    entries.remove(n);
+                }
+                Some(_) => println!("{}", tr("cannot drop the last entry, use q to cancel", &[])),
+                None => println!("{}", tr("usage: d N, between 1 and {}", &[&entries.len()])),
+            },
+            "r" => {
+                let (n, title) = rest.split_once(' ').map_or((rest, ""), |(n, t)| (n, t.trim()));
+                match position(n) {
+                    Some(n) => entries[n].title = (!title.is_empty()).then(|| title.to_string()),
+                    None => println!("{}", tr("usage: r N TITLE, N between 1 and {}", &[&entries.len()])),
+                }
+            }
+            "done" => return Ok(Some(entries)),
+            "q" => return Ok(None),
+            _ => println!("{}", tr(HELP, &[])),
+        }
+    }
+}
@@ -0,0 +1,44 @@
+use lopdf::Object;
+
+use crate::{merge_inputs, messages::tr, outline::BookmarkMode, Input, MergeOptions};
+
+/// Combine the pages of `front` and `back` into one input, alternating: the first front page,
+/// the first back page, the second front page and so on. This puts a double-sided document
+/// back together from two single-sided scans, the fronts and then the backs.
+///
+/// With `reverse_back` the back pages are taken last to first, as a stack turned over for the
+/// second pass comes out. Leftover pages of the longer side go at the end. The result keeps
+/// the spec of `front`, minus what was already applied to its pages, and has no outline.
+pub fn interleave(front: Input, back: Input, reverse_back: bool) -> crate::Result<Input> {
+    let (front_count, back_count) = (front.selected_pages(), back.selected_pages());
+    if front_count != back_count {
+        log::warn!(
+            "{}",
+            tr("{} has {} pages but {} has {}, the rest go at the end", &[&front.spec.path.display(), &front_count, &back.spec.path.display(), &back_count])
+        );
+    }
+    let mut spec = front.spec.clone();
+    let mut back = back;
+    back.spec.reverse ^= reverse_back;
+    let options = MergeOptions::new().bookmarks(BookmarkMode::None);
+    let mut doc = merge_inputs(vec![front, back], &options)?.document;
+
+    let pages = doc.get_pages().into_values().collect::<Vec<_>>();
+    let (fronts, backs) = pages.split_at(front_count.min(pages.len()));
+    let mut kids = Vec::with_capacity(pages.len());
+    for n in 0..fronts.len().max(backs.len()) {
+        kids.extend(fronts.get(n).copied().map(Object::Reference));
+        kids.extend(backs.get(n).copied().map(Object::Reference));
+    }
+    let pages_id = doc.catalog()?.get(b"Pages").and_then(Object::as_reference)?;
+    doc.get_dictionary_mut(pages_id)?.set("Kids", kids);
+
+    // Page selection, rotation, cropping and bookmarks refer to the pages of `front` alone
+    spec.pages = None;
+    spec.parity = None;
+    spec.rotate = 0;
+    spec.crop = None;
+    spec.reverse = false;
+    spec.bookmarks.clear();
+    Ok(Input { spec, doc })
+}
@@ -0,0 +1,39 @@
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc, OnceLock,
+};
+
+/// Set by the first Ctrl-C (or SIGTERM); the merge stops at its next step, see
+/// `MergeOptions::cancel`, and what it wrote so far is removed as it unwinds.
+static INTERRUPTED: OnceLock<Arc<AtomicBool>> = OnceLock::new();
+
+/// Catch Ctrl-C and SIGTERM from now on, and return the flag they set. A second one ends the
+/// process at once, for a step that takes too long to wait for.
+pub fn install() -> Arc<AtomicBool> {
+    let flag = INTERRUPTED.get_or_init(|| Arc::new(AtomicBool::new(false))).clone();
+    #[cfg(unix)]
+    // SAFETY: the handler only touches an atomic and calls _exit, both async-signal-safe
+    unsafe {
+        libc::signal(libc::SIGINT, on_signal as *const () as libc::sighandler_t);
+        libc::signal(libc::SIGTERM, on_signal as *const () as libc::sighandler_t);
+    }
+    flag
+}
+
+/// `merge_pdf::Error::Interrupted` once Ctrl-C was pressed.
+pub fn check() -> merge_pdf::Result<()> {
+    match INTERRUPTED.get() {
+        Some(flag) if flag.load(Ordering::Relaxed) => Err(merge_pdf::Error::Interrupted),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(unix)]
+extern "C" fn on_signal(_: libc::c_int) {
+    if let Some(flag) = INTERRUPTED.get() {
+        if flag.swap(true, Ordering::Relaxed) {
+            // SAFETY: _exit ends the process without running anything else
+            unsafe { libc::_exit(130) }
+        }
+    }
+}
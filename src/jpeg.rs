@@ -0,0 +1,971 @@
+//! Baseline JPEG (ITU T.81) decoding and encoding, enough to rework the images inside PDFs.
+//!
+//! Decoding handles Huffman-coded 8-bit images, sequential or progressive, with any sampling and
+//! restart intervals, which covers what scanners, cameras and PDF producers write; lossless and
+//! arithmetic-coded images are refused. Encoding writes baseline images with the standard
+//! tables, 4:2:0 subsampled when in color.
+
+use std::{f32::consts::PI, sync::OnceLock};
+
+/// Natural (row by row) index of the `k`th coefficient in zigzag order.
+const ZIGZAG: [usize; 64] = [
+    0, 1, 8, 16, 9, 2, 3, 10, 17, 24, 32, 25, 18, 11, 4, 5, 12, 19, 26, 33, 40, 48, 41, 34, 27, 20, 13, 6, 7, 14, 21, 28, 35, 42,
+    49, 56, 57, 50, 43, 36, 29, 22, 15, 23, 30, 37, 44, 51, 58, 59, 52, 45, 38, 31, 39, 46, 53, 60, 61, 54, 47, 55, 62, 63,
+];
+
+/// The quantization tables suggested by T.81 Annex K, for quality 50, in natural order.
+const LUMINANCE_QUANTIZATION: [u16; 64] = [
+    16, 11, 10, 16, 24, 40, 51, 61, 12, 12, 14, 19, 26, 58, 60, 55, 14, 13, 16, 24, 40, 57, 69, 56, 14, 17, 22, 29, 51, 87, 80, 62,
+    18, 22, 37, 56, 68, 109, 103, 77, 24, 35, 55, 64, 81, 104, 113, 92, 49, 64, 78, 87, 103, 121, 120, 101, 72, 92, 95, 98, 112,
+    100, 103, 99,
+];
+const CHROMINANCE_QUANTIZATION: [u16; 64] = [
+    17, 18, 24, 47, 99, 99, 99, 99, 18, 21, 26, 66, 99, 99, 99, 99, 24, 26, 56, 99, 99, 99, 99, 99, 47, 66, 99, 99, 99, 99, 99, 99,
+    99, 99, 99, 99, 99, 99, 99, 99, 99, 99, 99, 99, 99, 99, 99, 99, 99, 99, 99, 99, 99, 99, 99, 99, 99, 99, 99, 99, 99, 99, 99, 99,
+];
+
+/// The Huffman tables of T.81 Annex K.3: how many codes there are of each length, then the values.
+const LUMINANCE_DC: ([u8; 16], &[u8]) = ([0, 1, 5, 1, 1, 1, 1, 1, 1, 0, 0, 0, 0, 0, 0, 0], &[0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11]);
+const CHROMINANCE_DC: ([u8; 16], &[u8]) = ([0, 3, 1, 1, 1, 1, 1, 1, 1, 1, 1, 0, 0, 0, 0, 0], &[0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11]);
+const LUMINANCE_AC: ([u8; 16], &[u8]) = (
+    [0, 2, 1, 3, 3, 2, 4, 3, 5, 5, 4, 4, 0, 0, 1, 0x7d],
+    &[
+        0x01, 0x02, 0x03, 0x00, 0x04, 0x11, 0x05, 0x12, 0x21, 0x31, 0x41, 0x06, 0x13, 0x51, 0x61, 0x07, 0x22, 0x71, 0x14, 0x32,
+        0x81, 0x91, 0xa1, 0x08, 0x23, 0x42, 0xb1, 0xc1, 0x15, 0x52, 0xd1, 0xf0, 0x24, 0x33, 0x62, 0x72, 0x82, 0x09, 0x0a, 0x16,
+        0x17, 0x18, 0x19, 0x1a, 0x25, 0x26, 0x27, 0x28, 0x29, 0x2a, 0x34, 0x35, 0x36, 0x37, 0x38, 0x39, 0x3a, 0x43, 0x44, 0x45,
+        0x46, 0x47, 0x48, 0x49, 0x4a, 0x53, 0x54, 0x55, 0x56, 0x57, 0x58, 0x59, 0x5a, 0x63, 0x64, 0x65, 0x66, 0x67, 0x68, 0x69,
+        0x6a, 0x73, 0x74, 0x75, 0x76, 0x77, 0x78, 0x79, 0x7a, 0x83, 0x84, 0x85, 0x86, 0x87, 0x88, 0x89, 0x8a, 0x92, 0x93, 0x94,
+        0x95, 0x96, 0x97, 0x98, 0x99, 0x9a, 0xa2, 0xa3, 0xa4, 0xa5, 0xa6, 0xa7, 0xa8, 0xa9, 0xaa, 0xb2, 0xb3, 0xb4, 0xb5, 0xb6,
+        0xb7, 0xb8, 0xb9, 0xba, 0xc2, 0xc3, 0xc4, 0xc5, 0xc6, 0xc7, 0xc8, 0xc9, 0xca, 0xd2, 0xd3, 0xd4, 0xd5, 0xd6, 0xd7, 0xd8,
+        0xd9, 0xda, 0xe1, 0xe2, 0xe3, 0xe4, 0xe5, 0xe6, 0xe7, 0xe8, 0xe9, 0xea, 0xf1, 0xf2, 0xf3, 0xf4, 0xf5, 0xf6, 0xf7, 0xf8,
+        0xf9, 0xfa,
+    ],
+);
+const CHROMINANCE_AC: ([u8; 16], &[u8]) = (
+    [0, 2, 1, 2, 4, 4, 3, 4, 7, 5, 4, 4, 0, 1, 2, 0x77],
+    &[
+        0x00, 0x01, 0x02, 0x03, 0x11, 0x04, 0x05, 0x21, 0x31, 0x06, 0x12, 0x41, 0x51, 0x07, 0x61, 0x71, 0x13, 0x22, 0x32, 0x81,
+        0x08, 0x14, 0x42, 0x91, 0xa1, 0xb1, 0xc1, 0x09, 0x23, 0x33, 0x52, 0xf0, 0x15, 0x62, 0x72, 0xd1, 0x0a, 0x16, 0x24, 0x34,
+        0xe1, 0x25, 0xf1, 0x17, 0x18, 0x19, 0x1a, 0x26, 0x27, 0x28, 0x29, 0x2a, 0x35, 0x36, 0x37, 0x38, 0x39, 0x3a, 0x43, 0x44,
+        0x45, 0x46, 0x47, 0x48, 0x49, 0x4a, 0x53, 0x54, 0x55, 0x56, 0x57, 0x58, 0x59, 0x5a, 0x63, 0x64, 0x65, 0x66, 0x67, 0x68,
+        0x69, 0x6a, 0x73, 0x74, 0x75, 0x76, 0x77, 0x78, 0x79, 0x7a, 0x82, 0x83, 0x84, 0x85, 0x86, 0x87, 0x88, 0x89, 0x8a, 0x92,
+        0x93, 0x94, 0x95, 0x96, 0x97, 0x98, 0x99, 0x9a, 0xa2, 0xa3, 0xa4, 0xa5, 0xa6, 0xa7, 0xa8, 0xa9, 0xaa, 0xb2, 0xb3, 0xb4,
+        0xb5, 0xb6, 0xb7, 0xb8, 0xb9, 0xba, 0xc2, 0xc3, 0xc4, 0xc5, 0xc6, 0xc7, 0xc8, 0xc9, 0xca, 0xd2, 0xd3, 0xd4, 0xd5, 0xd6,
+        0xd7, 0xd8, 0xd9, 0xda, 0xe2, 0xe3, 0xe4, 0xe5, 0xe6, 0xe7, 0xe8, 0xe9, 0xea, 0xf2, 0xf3, 0xf4, 0xf5, 0xf6, 0xf7, 0xf8,
+        0xf9, 0xfa,
+    ],
+);
+
+/// A decoded image: `components` samples per pixel (gray, RGB or CMYK), row by row.
+#[derive(Debug, Clone)]
+pub struct Image {
+    pub width: usize,
+    pub height: usize,
+    pub components: usize,
+    pub samples: Vec<u8>,
+}
+
+/// Decode a baseline or progressive JPEG. Three components come out as RGB, four as CMYK, as
+/// stored; Adobe's inverted CMYK is left to the `/Decode` array of the image that holds it.
+pub fn decode(data: &[u8]) -> Result<Image, String> {
+    if !data.starts_with(&[0xff, 0xd8]) {
+        return Err("not a JPEG image".to_string());
+    }
+    let mut decoder = Decoder::default();
+    let mut pos = 2;
+    loop {
+        // Markers may be padded with any number of 0xFF bytes
+        while data.get(pos).is_some_and(|&b| b != 0xff) {
+            pos += 1;
+        }
+        while data.get(pos) == Some(&0xff) {
+            pos += 1;
+        }
+        let Some(&marker) = data.get(pos) else { break };
+        pos += 1;
+        if marker == 0xd9 {
+            break;
+        }
+        if marker == 0xd8 || (0xd0..=0xd7).contains(&marker) || marker == 0x01 {
+            continue;
+        }
+        let length = data.get(pos..pos + 2).map(|b| u16::from_be_bytes([b[0], b[1]]) as usize).ok_or("truncated JPEG")?;
+        let segment = data.get(pos + 2..pos + length).ok_or("truncated JPEG")?;
+        pos += length;
+        match marker {
+            0xc0 | 0xc1 => decoder.frame(segment, false)?,
+            0xc2 => decoder.frame(segment, true)?,
+            0xc3 | 0xc5..=0xc7 | 0xc9..=0xcb | 0xcd..=0xcf => return Err("lossless and arithmetic-coded JPEGs are not supported".to_string()),
+            0xc4 => decoder.huffman_tables(segment)?,
+            0xdb => decoder.quantization_tables(segment)?,
+            0xdd => decoder.restart_interval = segment.get(..2).map_or(0, |b| u16::from_be_bytes([b[0], b[1]]) as usize),
+            0xee if segment.starts_with(b"Adobe") => decoder.adobe_transform = segment.get(11).copied(),
+            0xda => pos = decoder.scan(segment, data, pos)?,
+            _ => {}
+        }
+    }
+    decoder.image()
+}
+
+struct Decoder {
+    width: usize,
+    height: usize,
+    progressive: bool,
+    components: Vec<Component>,
+    quantization: [[u16; 64]; 4],
+    /// DC tables, then AC tables
+    huffman: [[Option<Huffman>; 4]; 2],
+    restart_interval: usize,
+    adobe_transform: Option<u8>,
+    max_h: usize,
+    max_v: usize,
+    /// Blocks of a progressive scan left with nothing more to decode
+    end_of_bands: u32,
+}
+
+struct Component {
+    id: u8,
+    h: usize,
+    v: usize,
+    table: usize,
+    /// Width of the component in blocks, whole MCUs of them
+    blocks_wide: usize,
+    /// The quantized coefficients of every block, in zigzag order
+    blocks: Vec<[i16; 64]>,
+    prediction: i32,
+}
+
+impl Default for Decoder {
+    fn default() -> Self {
+        Decoder {
+            width: 0,
+            height: 0,
+            progressive: false,
+            components: Vec::new(),
+            quantization: [[1; 64]; 4],
+            huffman: Default::default(),
+            restart_interval: 0,
+            adobe_transform: None,
+            max_h: 1,
+            max_v: 1,
+            end_of_bands: 0,
+        }
+    }
+}
+
+/// How a scan codes its blocks (T.81 G.1.2).
+#[derive(Clone, Copy)]
+enum Coding {
+    Sequential,
+    FirstDc { low: u8 },
+    RefineDc { low: u8 },
+    FirstAc { start: usize, end: usize, low: u8 },
+    RefineAc { start: usize, end: usize, low: u8 },
+}
+
+impl Decoder {
+    fn frame(&mut self, segment: &[u8], progressive: bool) -> Result<(), String> {
+        let [precision, h0, h1, w0, w1, count, ..] = *segment else { return Err("truncated JPEG frame header".to_string()) };
+        if precision != 8 {
+            return Err(format!("{}-bit JPEGs are not supported", precision));
+        }
+        self.progressive = progressive;
+        self.height = u16::from_be_bytes([h0, h1]) as usize;
+        self.width = u16::from_be_bytes([w0, w1]) as usize;
+        if self.width == 0 || self.height == 0 {
+            return Err("JPEG without a size".to_string());
+        }
+        let specs = segment.get(6..6 + 3 * count as usize).ok_or("truncated JPEG frame header")?;
+        for spec in specs.chunks(3) {
+            let (h, v) = ((spec[1] >> 4) as usize, (spec[1] & 15) as usize);
+            if !(1..=4).contains(&h) || !(1..=4).contains(&v) || spec[2] > 3 {
+                return Err("invalid JPEG component".to_string());
+            }
+            self.components.push(Component { id: spec[0], h, v, table: spec[2] as usize, blocks_wide: 0, blocks: Vec::new(), prediction: 0 });
+        }
+        if !matches!(self.components.len(), 1 | 3 | 4) {
+            return Err(format!("JPEGs with {} components are not supported", self.components.len()));
+        }
+        self.max_h = self.components.iter().map(|c| c.h).max().unwrap_or(1);
+        self.max_v = self.components.iter().map(|c| c.v).max().unwrap_or(1);
+        let (mcus_wide, mcus_high) = self.mcus();
+        for component in &mut self.components {
+            component.blocks_wide = mcus_wide * component.h;
+            component.blocks = vec![[0; 64]; component.blocks_wide * mcus_high * component.v];
+        }
+        Ok(())
+    }
+
+    fn mcus(&self) -> (usize, usize) {
+        (self.width.div_ceil(8 * self.max_h), self.height.div_ceil(8 * self.max_v))
+    }
+
+    fn huffman_tables(&mut self, mut segment: &[u8]) -> Result<(), String> {
+        while let Some((&class_id, rest)) = segment.split_first() {
+            let counts: [u8; 16] = rest.get(..16).ok_or("truncated JPEG Huffman table")?.try_into().unwrap_or_default();
+            let total = counts.iter().map(|&n| n as usize).sum::<usize>();
+            let values = rest.get(16..16 + total).ok_or("truncated JPEG Huffman table")?;
+            let (class, id) = ((class_id >> 4) as usize, (class_id & 15) as usize);
+            if class > 1 || id > 3 {
+                return Err("invalid JPEG Huffman table".to_string());
+            }
+            self.huffman[class][id] = Some(Huffman::new(&counts, values));
+            segment = &rest[16 + total..];
+        }
+        Ok(())
+    }
+
+    fn quantization_tables(&mut self, mut segment: &[u8]) -> Result<(), String> {
+        while let Some((&precision_id, rest)) = segment.split_first() {
+            let (wide, id) = (precision_id >> 4 != 0, (precision_id & 15) as usize);
+            let size = if wide { 128 } else { 64 };
+            let values = rest.get(..size).ok_or("truncated JPEG quantization table")?;
+            let table = self.quantization.get_mut(id).ok_or("invalid JPEG quantization table")?;
+            for (k, value) in table.iter_mut().enumerate() {
+                *value = if wide { u16::from_be_bytes([values[2 * k], values[2 * k + 1]]) } else { values[k] as u16 };
+            }
+            segment = &rest[size..];
+        }
+        Ok(())
+    }
+
+    /// Decode the entropy-coded data of the scan whose header is `segment`, which starts at `pos`
+    /// in `data`; returns where it ends.
+    fn scan(&mut self, segment: &[u8], data: &[u8], pos: usize) -> Result<usize, String> {
+        if self.components.is_empty() {
+            return Err("JPEG scan before its frame header".to_string());
+        }
+        let count = *segment.first().ok_or("truncated JPEG scan header")? as usize;
+        let header = segment.get(1..4 + 2 * count).ok_or("truncated JPEG scan header")?;
+        let (start, end, approximation) = (header[2 * count] as usize, header[2 * count + 1] as usize, header[2 * count + 2]);
+        let (high, low) = (approximation >> 4, approximation & 15);
+        let coding = match (self.progressive, start, high) {
+            (false, _, _) => Coding::Sequential,
+            (true, 0, 0) => Coding::FirstDc { low },
+            (true, 0, _) => Coding::RefineDc { low },
+            (true, _, 0) => Coding::FirstAc { start, end: end.min(63), low },
+            (true, _, _) => Coding::RefineAc { start, end: end.min(63), low },
+        };
+        let (needs_dc, needs_ac) = match coding {
+            Coding::Sequential => (true, true),
+            Coding::FirstDc { .. } => (true, false),
+            Coding::RefineDc { .. } => (false, false),
+            Coding::FirstAc { .. } | Coding::RefineAc { .. } => (false, true),
+        };
+        let mut scan = Vec::new();
+        for spec in header[..2 * count].chunks(2) {
+            let index = self.components.iter().position(|c| c.id == spec[0]).ok_or("JPEG scan of an unknown component")?;
+            let (dc, ac) = ((spec[1] >> 4) as usize, (spec[1] & 15) as usize);
+            if (needs_dc && self.huffman[0].get(dc).is_none_or(Option::is_none))
+                || (needs_ac && self.huffman[1].get(ac).is_none_or(Option::is_none))
+            {
+                return Err("JPEG scan without its Huffman tables".to_string());
+            }
+            scan.push((index, dc.min(3), ac.min(3)));
+        }
+        let mut reader = BitReader { data, pos, bits: 0, count: 0, marker: false };
+        for component in &mut self.components {
+            component.prediction = 0;
+        }
+        self.end_of_bands = 0;
+
+        // A scan of one component goes block by block over just the blocks showing the image,
+        // one of several goes MCU by MCU
+        let units = if let [(index, _, _)] = scan[..] {
+            let component = &self.components[index];
+            let wide = (self.width * component.h).div_ceil(self.max_h).div_ceil(8);
+            let high = (self.height * component.v).div_ceil(self.max_v).div_ceil(8);
+            (wide, high)
+        } else {
+            self.mcus()
+        };
+        for unit in 0..units.0 * units.1 {
+            if self.restart_interval > 0 && unit > 0 && unit % self.restart_interval == 0 {
+                reader.restart();
+                for component in &mut self.components {
+                    component.prediction = 0;
+                }
+                self.end_of_bands = 0;
+            }
+            let (x, y) = (unit % units.0, unit / units.0);
+            for &(index, dc, ac) in &scan {
+                let (h, v) = if scan.len() == 1 { (1, 1) } else { (self.components[index].h, self.components[index].v) };
+                for block in 0..h * v {
+                    let (bx, by) = (x * h + block % h, y * v + block / h);
+                    let (dc, ac) = (self.huffman[0][dc].as_ref(), self.huffman[1][ac].as_ref());
+                    let component = &mut self.components[index];
+                    let Some(coefficients) = component.blocks.get_mut(by * component.blocks_wide + bx) else { continue };
+                    let prediction = &mut component.prediction;
+                    let end_of_bands = &mut self.end_of_bands;
+                    decode_block(&mut reader, coding, dc, ac, coefficients, prediction, end_of_bands)?;
+                }
+            }
+        }
+        Ok(reader.pos)
+    }
+
+    fn image(self) -> Result<Image, String> {
+        if self.components.is_empty() {
+            return Err("JPEG without a frame header".to_string());
+        }
+        let planes = self
+            .components
+            .iter()
+            .map(|component| {
+                let quantization = &self.quantization[component.table];
+                let stride = component.blocks_wide * 8;
+                let mut plane = vec![0; component.blocks.len() * 64];
+                for (i, block) in component.blocks.iter().enumerate() {
+                    let mut coefficients = [0.0; 64];
+                    for k in 0..64 {
+                        coefficients[ZIGZAG[k]] = block[k] as f32 * quantization[k] as f32;
+                    }
+                    let samples = inverse_dct(&coefficients);
+                    let (bx, by) = (i % component.blocks_wide, i / component.blocks_wide);
+                    for row in 0..8 {
+                        let start = (by * 8 + row) * stride + bx * 8;
+                        plane[start..start + 8].copy_from_slice(&samples[row * 8..row * 8 + 8]);
+                    }
+                }
+                plane
+            })
+            .collect::<Vec<_>>();
+
+        let count = self.components.len();
+        let ids = self.components.iter().map(|c| c.id).collect::<Vec<_>>();
+        let transform = match (count, self.adobe_transform) {
+            (3, Some(transform)) => transform != 0,
+            (3, None) => ids != b"RGB",
+            (4, transform) => transform == Some(2),
+            _ => false,
+        };
+        let mut samples = Vec::with_capacity(self.width * self.height * count);
+        let mut pixel = [0u8; 4];
+        for y in 0..self.height {
+            for x in 0..self.width {
+                for ((component, plane), sample) in self.components.iter().zip(&planes).zip(&mut pixel) {
+                    let (cx, cy) = (x * component.h / self.max_h, y * component.v / self.max_v);
+                    *sample = plane[cy * component.blocks_wide * 8 + cx];
+                }
+                if transform {
+                    let [r, g, b] = rgb(pixel[0], pixel[1], pixel[2]);
+                    // YCCK is YCbCr of the inverted CMY
+                    pixel[..3].copy_from_slice(&if count == 4 { [255 - r, 255 - g, 255 - b] } else { [r, g, b] });
+                }
+                samples.extend_from_slice(&pixel[..count]);
+            }
+        }
+        Ok(Image { width: self.width, height: self.height, components: count, samples })
+    }
+}
+
+fn rgb(y: u8, cb: u8, cr: u8) -> [u8; 3] {
+    let (y, cb, cr) = (y as f32, cb as f32 - 128.0, cr as f32 - 128.0);
+    [y + 1.402 * cr, y - 0.344136 * cb - 0.714136 * cr, y + 1.772 * cb].map(|v| v.round().clamp(0.0, 255.0) as u8)
+}
+
+/// Decode what a scan coded `coding` holds of one block into its `coefficients`.
+fn decode_block(
+    reader: &mut BitReader,
+    coding: Coding,
+    dc: Option<&Huffman>,
+    ac: Option<&Huffman>,
+    coefficients: &mut [i16; 64],
+    prediction: &mut i32,
+    end_of_bands: &mut u32,
+) -> Result<(), String> {
+    fn table(table: Option<&Huffman>) -> Result<&Huffman, String> {
+        table.ok_or_else(|| "JPEG scan without its Huffman tables".to_string())
+    }
+    match coding {
+        Coding::Sequential | Coding::FirstDc { .. } => {
+            let size = table(dc)?.decode(reader)?;
+            // DC differences are at most 11 bits, 15 for 12-bit images
+            if size > 15 {
+                return Err("corrupt JPEG data".to_string());
+            }
+            *prediction = prediction.wrapping_add(reader.signed(size));
+            let low = if let Coding::FirstDc { low } = coding { low } else { 0 };
+            coefficients[0] = (*prediction << low) as i16;
+            if let Coding::Sequential = coding {
+                decode_ac(reader, table(ac)?, coefficients, 1, 63, 0, end_of_bands)?;
+            }
+        }
+        Coding::RefineDc { low } => {
+            if reader.bit() == 1 {
+                coefficients[0] |= 1 << low;
+            }
+        }
+        Coding::FirstAc { start, end, low } => {
+            if *end_of_bands > 0 {
+                *end_of_bands -= 1;
+                return Ok(());
+            }
+            decode_ac(reader, table(ac)?, coefficients, start, end, low, end_of_bands)?;
+        }
+        Coding::RefineAc { start, end, low } => refine_ac(reader, table(ac)?, coefficients, start, end, low, end_of_bands)?,
+    }
+    Ok(())
+}
+
+/// Decode the AC coefficients `start..=end` of a block, scaled up by `low` bits; a progressive
+/// end of band run goes into `end_of_bands`.
+fn decode_ac(
+    reader: &mut BitReader,
+    ac: &Huffman,
+    coefficients: &mut [i16; 64],
+    start: usize,
+    end: usize,
+    low: u8,
+    end_of_bands: &mut u32,
+) -> Result<(), String> {
+    let mut k = start;
+    while k <= end {
+        let run_size = ac.decode(reader)?;
+        let (run, size) = ((run_size >> 4) as usize, run_size & 15);
+        if size == 0 {
+            if run != 15 {
+                // This block and `end_of_bands` more end here
+                *end_of_bands = (1 << run) - 1 + reader.unsigned(run as u8);
+                break;
+            }
+            k += 16;
+            continue;
+        }
+        k += run;
+        if k > end {
+            return Err("corrupt JPEG data".to_string());
+        }
+        coefficients[k] = (reader.signed(size) << low) as i16;
+        k += 1;
+    }
+    Ok(())
+}
+
+/// Refine the AC coefficients `start..=end` of a block by bit `low` (T.81 G.1.2.3).
+fn refine_ac(
+    reader: &mut BitReader,
+    ac: &Huffman,
+    coefficients: &mut [i16; 64],
+    start: usize,
+    end: usize,
+    low: u8,
+    end_of_bands: &mut u32,
+) -> Result<(), String> {
+    let (plus, minus) = (1i16 << low, -1i16 << low);
+    let refine = |reader: &mut BitReader, coefficient: &mut i16| {
+        if reader.bit() == 1 && *coefficient & plus == 0 {
+            *coefficient += if *coefficient >= 0 { plus } else { minus };
+        }
+    };
+    let mut k = start;
+    if *end_of_bands == 0 {
+        while k <= end {
+            let run_size = ac.decode(reader)?;
+            let (mut run, size) = ((run_size >> 4) as i32, run_size & 15);
+            let mut value = 0;
+            if size != 0 {
+                value = if reader.bit() == 1 { plus } else { minus };
+            } else if run != 15 {
+                *end_of_bands = (1 << run) + reader.unsigned(run as u8);
+                break;
+            }
+            // Skip `run` coefficients that are still zero, refining the others passed
+            while k <= end {
+                if coefficients[k] != 0 {
+                    refine(reader, &mut coefficients[k]);
+                } else {
+                    if run == 0 {
+                        break;
+                    }
+                    run -= 1;
+                }
+                k += 1;
+            }
+            if value != 0 && k <= end {
+                coefficients[k] = value;
+            }
+            k += 1;
+        }
+    }
+    if *end_of_bands > 0 {
+        while k <= end {
+            if coefficients[k] != 0 {
+                refine(reader, &mut coefficients[k]);
+            }
+            k += 1;
+        }
+        *end_of_bands -= 1;
+    }
+    Ok(())
+}
+
+/// `COSINES[x][u]` is C(u) cos((2x + 1) u pi / 16) of the 8x8 DCT.
+fn cosines() -> &'static [[f32; 8]; 8] {
+    static COSINES: OnceLock<[[f32; 8]; 8]> = OnceLock::new();
+    COSINES.get_or_init(|| {
+        let mut cosines = [[0.0; 8]; 8];
+        for (x, row) in cosines.iter_mut().enumerate() {
+            for (u, value) in row.iter_mut().enumerate() {
+                let scale = if u == 0 { std::f32::consts::FRAC_1_SQRT_2 } else { 1.0 };
+                *value = scale * ((2 * x + 1) as f32 * u as f32 * PI / 16.0).cos();
+            }
+        }
+        cosines
+    })
+}
+
+fn inverse_dct(coefficients: &[f32; 64]) -> [u8; 64] {
+    let cosines = cosines();
+    let mut rows = [0.0f32; 64];
+    for v in 0..8 {
+        if coefficients[v * 8..v * 8 + 8].iter().all(|&c| c == 0.0) {
+            continue;
+        }
+        for x in 0..8 {
+            rows[v * 8 + x] = (0..8).map(|u| cosines[x][u] * coefficients[v * 8 + u]).sum();
+        }
+    }
+    let mut samples = [0; 64];
+    for y in 0..8 {
+        for x in 0..8 {
+            let value = (0..8).map(|v| cosines[y][v] * rows[v * 8 + x]).sum::<f32>() / 4.0 + 128.0;
+            samples[y * 8 + x] = value.round().clamp(0.0, 255.0) as u8;
+        }
+    }
+    samples
+}
+
+fn forward_dct(samples: &[f32; 64]) -> [f32; 64] {
+    let cosines = cosines();
+    let mut rows = [0.0f32; 64];
+    for y in 0..8 {
+        for u in 0..8 {
+            rows[y * 8 + u] = (0..8).map(|x| cosines[x][u] * samples[y * 8 + x]).sum();
+        }
+    }
+    let mut coefficients = [0.0; 64];
+    for v in 0..8 {
+        for u in 0..8 {
+            coefficients[v * 8 + u] = (0..8).map(|y| cosines[y][v] * rows[y * 8 + u]).sum::<f32>() / 4.0;
+        }
+    }
+    coefficients
+}
+
+/// A Huffman table as T.81 F.2.2.3 decodes with it.
+struct Huffman {
+    max_code: [i32; 18],
+    min_code: [i32; 17],
+    first_value: [usize; 17],
+    values: Vec<u8>,
+}
+
+impl Huffman {
+    fn new(counts: &[u8; 16], values: &[u8]) -> Self {
+        let mut table = Huffman { max_code: [-1; 18], min_code: [0; 17], first_value: [0; 17], values: values.to_vec() };
+        let (mut code, mut k) = (0, 0);
+        for length in 1..=16 {
+            let count = counts[length - 1] as i32;
+            if count > 0 {
+                table.first_value[length] = k;
+                table.min_code[length] = code;
+                code += count;
+                k += count as usize;
+                table.max_code[length] = code - 1;
+            }
+            code <<= 1;
+        }
+        table.max_code[17] = i32::MAX;
+        table
+    }
+
+    fn decode(&self, reader: &mut BitReader) -> Result<u8, String> {
+        let mut code = reader.bit() as i32;
+        let mut length = 1;
+        while code > self.max_code[length] {
+            code = code << 1 | reader.bit() as i32;
+            length += 1;
+            if length > 16 {
+                return Err("corrupt JPEG data".to_string());
+            }
+        }
+        let index = self.first_value[length] + (code - self.min_code[length]) as usize;
+        self.values.get(index).copied().ok_or_else(|| "corrupt JPEG data".to_string())
+    }
+}
+
+/// Reads entropy-coded data, undoing byte stuffing and reading zeros once a marker is reached.
+struct BitReader<'a> {
+    data: &'a [u8],
+    pos: usize,
+    bits: u32,
+    count: u32,
+    marker: bool,
+}
+
+impl BitReader<'_> {
+    fn bit(&mut self) -> u32 {
+        if self.count == 0 {
+            self.bits = self.byte() as u32;
+            self.count = 8;
+        }
+        self.count -= 1;
+        (self.bits >> self.count) & 1
+    }
+
+    fn byte(&mut self) -> u8 {
+        if self.marker {
+            return 0;
+        }
+        match self.data.get(self.pos..self.pos + 2) {
+            Some([0xff, 0]) => {
+                self.pos += 2;
+                0xff
+            }
+            Some([0xff, _]) => {
+                self.marker = true;
+                0
+            }
+            _ => match self.data.get(self.pos) {
+                Some(&byte) => {
+                    self.pos += 1;
+                    byte
+                }
+                None => 0,
+            },
+        }
+    }
+
+    fn unsigned(&mut self, size: u8) -> u32 {
+        (0..size).fold(0, |value, _| value << 1 | self.bit())
+    }
+
+    /// `size` bits as the signed value T.81 F.2.2.1 codes them as.
+    fn signed(&mut self, size: u8) -> i32 {
+        if size == 0 {
+            return 0;
+        }
+        let value = (0..size).fold(0, |value, _| value << 1 | self.bit() as i32);
+        if value < 1 << (size - 1) {
+            value - (1 << size) + 1
+        } else {
+            value
+        }
+    }
+
+    /// Skip to after the next restart marker.
+    fn restart(&mut self) {
+        self.count = 0;
+        self.marker = false;
+        while let Some(pair) = self.data.get(self.pos..self.pos + 2) {
+            self.pos += 1;
+            if pair[0] == 0xff && (0xd0..=0xd7).contains(&pair[1]) {
+                self.pos += 1;
+                return;
+            }
+        }
+    }
+}
+
+/// Encode `samples`, with `components` of 1 (gray) or 3 (RGB) per pixel, as a baseline JPEG of
+/// `quality` 1 to 100.
+pub fn encode(width: usize, height: usize, components: usize, samples: &[u8], quality: u8) -> Vec<u8> {
+    let scale = |table: &[u16; 64]| {
+        let quality = quality.clamp(1, 100) as u32;
+        let factor = if quality < 50 { 5000 / quality } else { 200 - 2 * quality };
+        table.map(|q| ((q as u32 * factor + 50) / 100).clamp(1, 255) as u16)
+    };
+    let tables = [scale(&LUMINANCE_QUANTIZATION), scale(&CHROMINANCE_QUANTIZATION)];
+    let color = components == 3;
+
+    let mut out = vec![0xff, 0xd8];
+    let mut segment = |marker: u8, body: Vec<u8>| {
+        out.extend_from_slice(&[0xff, marker]);
+        out.extend_from_slice(&((body.len() + 2) as u16).to_be_bytes());
+        out.extend(body);
+    };
+    let mut quantization = Vec::new();
+    for (id, table) in tables.iter().enumerate().take(if color { 2 } else { 1 }) {
+        quantization.push(id as u8);
+        quantization.extend(ZIGZAG.iter().map(|&i| table[i] as u8));
+    }
+    segment(0xdb, quantization);
+    let mut frame = vec![8];
+    frame.extend_from_slice(&(height as u16).to_be_bytes());
+    frame.extend_from_slice(&(width as u16).to_be_bytes());
+    if color {
+        frame.extend_from_slice(&[3, 1, 0x22, 0, 2, 0x11, 1, 3, 0x11, 1]);
+    } else {
+        frame.extend_from_slice(&[1, 1, 0x11, 0]);
+    }
+    segment(0xc0, frame);
+    let mut huffman = Vec::new();
+    let specs = [(0x00, LUMINANCE_DC), (0x10, LUMINANCE_AC), (0x01, CHROMINANCE_DC), (0x11, CHROMINANCE_AC)];
+    for (class_id, (counts, values)) in specs.iter().take(if color { 4 } else { 2 }) {
+        huffman.push(*class_id);
+        huffman.extend_from_slice(counts);
+        huffman.extend_from_slice(values);
+    }
+    segment(0xc4, huffman);
+    segment(0xda, if color { vec![3, 1, 0x00, 2, 0x11, 3, 0x11, 0, 63, 0] } else { vec![1, 1, 0x00, 0, 63, 0] });
+
+    // Full-size luminance, chrominance averaged over 2x2 pixels
+    let pixel = |x: usize, y: usize| {
+        let i = (y.min(height - 1) * width + x.min(width - 1)) * components;
+        &samples[i..i + components]
+    };
+    let luminance = |x: usize, y: usize| match pixel(x, y) {
+        &[r, g, b] => 0.299 * r as f32 + 0.587 * g as f32 + 0.114 * b as f32,
+        gray => gray[0] as f32,
+    };
+    let chrominance = |x: usize, y: usize| {
+        let (mut cb, mut cr) = (0.0, 0.0);
+        for (dx, dy) in [(0, 0), (1, 0), (0, 1), (1, 1)] {
+            if let &[r, g, b] = pixel(x + dx, y + dy) {
+                let (r, g, b) = (r as f32, g as f32, b as f32);
+                cb += -0.168736 * r - 0.331264 * g + 0.5 * b;
+                cr += 0.5 * r - 0.418688 * g - 0.081312 * b;
+            }
+        }
+        (cb / 4.0 + 128.0, cr / 4.0 + 128.0)
+    };
+
+    let luminance_codes = [Codes::new(&LUMINANCE_DC), Codes::new(&LUMINANCE_AC)];
+    let chrominance_codes = [Codes::new(&CHROMINANCE_DC), Codes::new(&CHROMINANCE_AC)];
+    let mut writer = BitWriter::default();
+    let mut predictions = [0; 3];
+    let mcu = if color { 16 } else { 8 };
+    for mcu_y in (0..height).step_by(mcu) {
+        for mcu_x in (0..width).step_by(mcu) {
+            for by in (0..mcu).step_by(8) {
+                for bx in (0..mcu).step_by(8) {
+                    let block = std::array::from_fn(|i| luminance(mcu_x + bx + i % 8, mcu_y + by + i / 8) - 128.0);
+                    encode_block(&mut writer, &block, &tables[0], &luminance_codes, &mut predictions[0]);
+                }
+            }
+            if color {
+                let block: [(f32, f32); 64] = std::array::from_fn(|i| chrominance(mcu_x + 2 * (i % 8), mcu_y + 2 * (i / 8)));
+                let cb = block.map(|(cb, _)| cb - 128.0);
+                let cr = block.map(|(_, cr)| cr - 128.0);
+                encode_block(&mut writer, &cb, &tables[1], &chrominance_codes, &mut predictions[1]);
+                encode_block(&mut writer, &cr, &tables[1], &chrominance_codes, &mut predictions[2]);
+            }
+        }
+    }
+    writer.flush();
+    out.extend(writer.bytes);
+    out.extend_from_slice(&[0xff, 0xd9]);
+    out
+}
+
+fn encode_block(writer: &mut BitWriter, block: &[f32; 64], quantization: &[u16; 64], codes: &[Codes; 2], prediction: &mut i32) {
+    let coefficients = forward_dct(block);
+    let quantized = ZIGZAG.map(|i| (coefficients[i] / quantization[i] as f32).round() as i32);
+    let difference = quantized[0] - *prediction;
+    *prediction = quantized[0];
+    let size = bit_size(difference);
+    codes[0].write(writer, size);
+    writer.put(magnitude_bits(difference, size), size);
+    let mut run = 0;
+    for &coefficient in &quantized[1..] {
+        if coefficient == 0 {
+            run += 1;
+            continue;
+        }
+        while run > 15 {
+            codes[1].write(writer, 0xf0);
+            run -= 16;
+        }
+        let size = bit_size(coefficient);
+        codes[1].write(writer, (run << 4) as u8 | size);
+        writer.put(magnitude_bits(coefficient, size), size);
+        run = 0;
+    }
+    if run > 0 {
+        // End of block
+        codes[1].write(writer, 0x00);
+    }
+}
+
+fn bit_size(value: i32) -> u8 {
+    (32 - value.unsigned_abs().leading_zeros()) as u8
+}
+
+/// The bits T.81 F.1.2.1 codes `value` of `size` bits with: negative values are one less.
+fn magnitude_bits(value: i32, size: u8) -> u32 {
+    if value < 0 {
+        (value - 1) as u32 & ((1 << size) - 1)
+    } else {
+        value as u32
+    }
+}
+
+/// The code and its length for each value of a Huffman table.
+struct Codes([(u16, u8); 256]);
+
+impl Codes {
+    fn new((counts, values): &([u8; 16], &[u8])) -> Self {
+        let mut codes = [(0, 0); 256];
+        let (mut code, mut k) = (0u16, 0);
+        for (length, &count) in counts.iter().enumerate() {
+            for _ in 0..count {
+                codes[values[k] as usize] = (code, length as u8 + 1);
+                code += 1;
+                k += 1;
+            }
+            code <<= 1;
+        }
+        Codes(codes)
+    }
+
+    fn write(&self, writer: &mut BitWriter, value: u8) {
+        let (code, length) = self.0[value as usize];
+        writer.put(code as u32, length);
+    }
+}
+
+/// Writes entropy-coded data, stuffing a zero after every 0xFF byte.
+#[derive(Default)]
+struct BitWriter {
+    bytes: Vec<u8>,
+    bits: u32,
+    count: u8,
+}
+
+impl BitWriter {
+    fn put(&mut self, value: u32, size: u8) {
+        for i in (0..size).rev() {
+            self.bits = self.bits << 1 | (value >> i) & 1;
+            self.count += 1;
+            if self.count == 8 {
+                self.push(self.bits as u8);
+            }
+        }
+    }
+
+    fn push(&mut self, byte: u8) {
+        self.bytes.push(byte);
+        if byte == 0xff {
+            self.bytes.push(0);
+        }
+        self.bits = 0;
+        self.count = 0;
+    }
+
+    /// Pad the last byte with ones.
+    fn flush(&mut self) {
+        if self.count > 0 {
+            let byte = (self.bits << (8 - self.count)) as u8 | (0xff >> self.count);
+            self.push(byte);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A progressive JPEG of `components` whose only scan sends every DC coefficient as zero,
+    /// so every sample decodes to 128; with `adobe`, an Adobe segment of that transform first.
+    fn progressive(width: u16, height: u16, components: u8, adobe: Option<u8>) -> Vec<u8> {
+        let mut data = vec![0xff, 0xd8];
+        let mut segment = |marker: u8, body: &[u8]| {
+            data.extend_from_slice(&[0xff, marker]);
+            data.extend_from_slice(&(body.len() as u16 + 2).to_be_bytes());
+            data.extend_from_slice(body);
+        };
+        if let Some(transform) = adobe {
+            segment(0xee, &[b"Adobe".as_slice(), &[0, 100, 0, 0, 0, 0, transform]].concat());
+        }
+        let mut frame = [&[8], &height.to_be_bytes()[..], &width.to_be_bytes(), &[components]].concat();
+        let mut scan = vec![components];
+        for id in 1..=components {
+            frame.extend_from_slice(&[id, 0x11, 0]);
+            scan.extend_from_slice(&[id, 0x00]);
+        }
+        scan.extend_from_slice(&[0, 0, 0]);
+        segment(0xc2, &frame);
+        // One DC code, a single 0 bit, for a difference of zero
+        segment(0xc4, &[&[0x00, 1], &[0; 15][..], &[0]].concat());
+        segment(0xda, &scan);
+        let blocks = width.div_ceil(8) as usize * height.div_ceil(8) as usize * components as usize;
+        data.extend(std::iter::repeat_n(0, blocks / 8));
+        if !blocks.is_multiple_of(8) {
+            data.push(0xff >> (blocks % 8));
+        }
+        data.extend_from_slice(&[0xff, 0xd9]);
+        data
+    }
+
+    fn gradient(width: usize, height: usize, components: usize) -> Vec<u8> {
+        (0..width * height * components).map(|i| ((i / components % width) * 255 / width) as u8).collect()
+    }
+
+    #[test]
+    fn baseline_images_round_trip() {
+        for components in [1, 3] {
+            let (width, height) = (37, 21);
+            let samples = gradient(width, height, components);
+            let image = decode(&encode(width, height, components, &samples, 90)).unwrap();
+            assert_eq!((image.width, image.height, image.components), (width, height, components));
+            let error = image.samples.iter().zip(&samples).map(|(&a, &b)| (a as i32 - b as i32).abs()).max().unwrap();
+            assert!(error < 24, "{} components off by {}", components, error);
+        }
+    }
+
+    #[test]
+    fn progressive_frames_are_decoded() {
+        let image = decode(&progressive(20, 9, 1, None)).unwrap();
+        assert_eq!((image.width, image.height, image.components), (20, 9, 1));
+        assert_eq!(image.samples, vec![128; 20 * 9]);
+        let image = decode(&progressive(16, 16, 3, None)).unwrap();
+        assert_eq!((image.width, image.height, image.components), (16, 16, 3));
+    }
+
+    #[test]
+    fn adobe_cmyk_is_left_as_stored() {
+        let image = decode(&progressive(8, 8, 4, Some(0))).unwrap();
+        assert_eq!(image.components, 4);
+        assert_eq!(image.samples, vec![128; 8 * 8 * 4]);
+        // YCCK is turned into CMYK
+        let image = decode(&progressive(8, 8, 4, Some(2))).unwrap();
+        assert_eq!(image.samples.len(), 8 * 8 * 4);
+        assert_eq!(image.samples[3], 128);
+    }
+
+    #[test]
+    fn unsupported_frames_are_refused() {
+        let mut twelve_bit = progressive(8, 8, 1, None);
+        let frame = twelve_bit.windows(2).position(|w| w == [0xff, 0xc2]).unwrap();
+        twelve_bit[frame + 4] = 12;
+        assert!(decode(&twelve_bit).is_err());
+        let mut lossless = progressive(8, 8, 1, None);
+        lossless[frame + 1] = 0xc3;
+        assert!(decode(&lossless).is_err());
+        assert!(decode(&progressive(0, 8, 1, None)).is_err());
+        assert!(decode(&progressive(8, 8, 2, None)).is_err());
+        assert!(decode(b"\x89PNG").is_err());
+    }
+
+    #[test]
+    fn truncated_or_corrupt_images_are_errors_not_panics() {
+        let samples = gradient(16, 16, 3);
+        for data in [encode(16, 16, 3, &samples, 75), progressive(16, 16, 4, Some(2))] {
+            // Cut before the frame header ends there is no image, after it missing data decodes as zeros
+            let frame = data.windows(2).position(|w| w == [0xff, 0xc0] || w == [0xff, 0xc2]).unwrap();
+            let frame_end = frame + 2 + u16::from_be_bytes([data[frame + 2], data[frame + 3]]) as usize;
+            for end in 0..data.len() {
+                let decoded = decode(&data[..end]);
+                assert!(end >= frame_end || decoded.is_err(), "cut at {}", end);
+            }
+            for at in 2..data.len() {
+                for flip in [0x01, 0xff] {
+                    let mut corrupt = data.clone();
+                    corrupt[at] ^= flip;
+                    let _ = decode(&corrupt);
+                }
+            }
+        }
+    }
+}
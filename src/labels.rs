@@ -0,0 +1,150 @@
+use lopdf::{Dictionary, Document, Object};
+
+/// How deep the nodes of a number tree are followed.
+const MAX_DEPTH: usize = 32;
+
+/// The label a page shows in place of its number (PDF 32000-1, 12.4.2): a prefix and a number
+/// in some style, either of which can be missing.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Label {
+    style: Option<Vec<u8>>,
+    prefix: Option<Vec<u8>>,
+    number: i64,
+}
+
+impl Label {
+    fn numbered(style: &str, number: i64) -> Self {
+        Label { style: Some(style.as_bytes().to_vec()), prefix: None, number }
+    }
+
+    /// The label of the page after this one, in the same range.
+    fn next(&self) -> Self {
+        Label { number: self.number + 1, ..self.clone() }
+    }
+
+    fn range(&self) -> Dictionary {
+        let mut dict = Dictionary::new();
+        if let Some(style) = &self.style {
+            dict.set("S", Object::Name(style.clone()));
+        }
+        if let Some(prefix) = &self.prefix {
+            dict.set("P", Object::String(prefix.clone(), lopdf::StringFormat::Literal));
+        }
+        if self.number != 1 {
+            dict.set("St", self.number);
+        }
+        dict
+    }
+}
+
+/// A page of the merged document, as its label is concerned.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Page {
+    /// A cover or table of contents page, before every input
+    Front,
+    /// A page of the input at this index, with its label there, if it has labels
+    Input(usize, Option<Label>),
+    /// A page put between the inputs, a separator or padding
+    Inserted,
+}
+
+/// The page ranges of the `/PageLabels` number tree of `doc`, sorted by their first page
+/// (0-based).
+pub fn ranges(doc: &Document) -> Vec<(i64, Dictionary)> {
+    let mut found = Vec::new();
+    if let Ok(tree) = doc.catalog().and_then(|catalog| catalog.get(b"PageLabels")) {
+        leaves(doc, tree, 0, &mut found);
+    }
+    let mut ranges = found
+        .into_iter()
+        .filter_map(|(first, range)| match range {
+            Object::Reference(id) => doc.get_dictionary(id).ok().map(|range| (first, range.clone())),
+            Object::Dictionary(range) => Some((first, range)),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    ranges.sort_by_key(|(first, _)| *first);
+    ranges
+}
+
+/// The label of the page at `index` (0-based) of a document with these `ranges`, if it has any.
+pub fn label(doc: &Document, ranges: &[(i64, Dictionary)], index: usize) -> Option<Label> {
+    let index = index as i64;
+    let (first, range) = ranges.iter().rev().find(|(first, _)| *first <= index)?;
+    Some(Label {
+        style: range.get(b"S").and_then(Object::as_name).ok().map(<[u8]>::to_vec),
+        prefix: range.get_deref(b"P", doc).and_then(Object::as_str).ok().map(<[u8]>::to_vec),
+        number: range.get(b"St").and_then(Object::as_i64).unwrap_or(1) + index - first,
+    })
+}
+
+/// A `/PageLabels` number tree for `pages`, in their order in the merged document, or `None`
+/// when they are just numbered from 1.
+///
+/// Pages keep the labels their input gave them; those of inputs without labels are numbered
+/// from 1 after the cover and table of contents, which are numbered i, ii, ... With `relabel`,
+/// the labels of the inputs are left out and the pages of the first `relabel` inputs are
+/// numbered on from the front pages in roman numerals, those after them from 1.
+pub fn number_tree(pages: &[Page], relabel: Option<usize>) -> Option<Dictionary> {
+    let labeled = pages.iter().any(|page| matches!(page, Page::Front | Page::Input(_, Some(_))));
+    if !labeled && relabel.is_none() {
+        return None;
+    }
+    let mut nums = Vec::new();
+    let mut previous: Option<Label> = None;
+    // Pages numbered in roman numerals, and in arabic ones, so far
+    let (mut roman, mut arabic) = (0, 0);
+    let mut in_front = false;
+    for (index, page) in pages.iter().enumerate() {
+        let label = match (page, relabel) {
+            (Page::Front, _) => None,
+            (Page::Inserted, _) if in_front => None,
+            (Page::Input(source, _), Some(front)) if *source < front => None,
+            (Page::Input(_, _), Some(_)) => Some(Label::numbered("D", arabic + 1)),
+            (Page::Input(_, label), None) => Some(label.clone().unwrap_or_else(|| Label::numbered("D", arabic + 1))),
+            (Page::Inserted, _) => Some(previous.as_ref().map_or_else(|| Label::numbered("D", arabic + 1), Label::next)),
+        };
+        in_front = label.is_none();
+        let label = match label {
+            Some(label) => {
+                arabic += 1;
+                label
+            }
+            None => {
+                roman += 1;
+                Label::numbered("r", roman)
+            }
+        };
+        if previous.as_ref().map(Label::next).as_ref() != Some(&label) {
+            nums.push(Object::Integer(index as i64));
+            nums.push(Object::Dictionary(label.range()));
+        }
+        previous = Some(label);
+    }
+    let mut tree = Dictionary::new();
+    tree.set("Nums", nums);
+    Some(tree)
+}
+
+/// Collect the key and value of every leaf below the number tree node `node`.
+pub(crate) fn leaves(doc: &Document, node: &Object, depth: usize, found: &mut Vec<(i64, Object)>) {
+    let node = match node {
+        Object::Reference(id) => doc.get_dictionary(*id).ok(),
+        node => node.as_dict().ok(),
+    };
+    let Some(node) = node else { return };
+    if let Ok(nums) = node.get_deref(b"Nums", doc).and_then(Object::as_array) {
+        for pair in nums.chunks_exact(2) {
+            if let Ok(key) = pair[0].as_i64() {
+                found.push((key, pair[1].clone()));
+            }
+        }
+    }
+    if depth < MAX_DEPTH {
+        if let Ok(kids) = node.get_deref(b"Kids", doc).and_then(Object::as_array) {
+            for kid in kids {
+                leaves(doc, kid, depth + 1, found);
+            }
+        }
+    }
+}
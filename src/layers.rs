@@ -0,0 +1,116 @@
+use std::collections::{BTreeMap, HashMap};
+
+use lopdf::{Dictionary, Document, Object, ObjectId};
+
+use crate::{input::InputSpec, messages::tr, text};
+
+/// Entries of the default configuration whose arrays the inputs add to.
+const LISTS: [&[u8]; 5] = [b"ON", b"OFF", b"Order", b"RBGroups", b"Locked"];
+
+/// The optional content (the catalog's `OCProperties`, PDF 32000-1, 8.11.4) of the inputs, their
+/// layers, gathered into one for the merged document, whose catalog comes from a single input.
+#[derive(Debug, Clone, Default)]
+pub struct Layers {
+    groups: Vec<Object>,
+    /// The group kept for every name
+    names: HashMap<String, ObjectId>,
+    /// The default configuration, its lists those of every input
+    default: Dictionary,
+    configs: Vec<Object>,
+}
+
+impl Layers {
+    /// Add the layers of `doc`, renumbered as it will be merged, taking them out of its catalog.
+    /// A layer named like one of an earlier input is merged into it: what `doc` shows in it, it
+    /// shows in the earlier one, so both are turned on and off together.
+    pub fn append(&mut self, spec: &InputSpec, doc: &mut Document) {
+        let Ok(catalog) = doc.catalog() else { return };
+        let Some(properties) = catalog.get_deref(b"OCProperties", doc).and_then(Object::as_dict).ok().cloned() else { return };
+        if let Ok(catalog) = doc.catalog_mut() {
+            catalog.remove(b"OCProperties");
+        }
+
+        let groups = properties.get_deref(b"OCGs", doc).and_then(Object::as_array).cloned().unwrap_or_default();
+        let mut merged = BTreeMap::new();
+        for group in groups {
+            let Ok(id) = group.as_reference() else { continue };
+            let name = doc.get_dictionary(id).and_then(|group| group.get(b"Name")).and_then(Object::as_str).map(text::decode).unwrap_or_default();
+            match self.names.get(&name) {
+                Some(&kept) => {
+                    merged.insert(id, kept);
+                }
+                None => {
+                    self.names.insert(name, id);
+                    self.groups.push(group);
+                }
+            }
+        }
+        if !merged.is_empty() {
+            for id in merged.keys() {
+                doc.objects.remove(id);
+            }
+            for object in doc.objects.values_mut() {
+                replace(object, &merged);
+            }
+            log::info!("{}", tr("{}: {} layer(s) merged with those of the same name in earlier inputs", &[&spec.path.display(), &merged.len()]));
+        }
+
+        let mut default = properties.get_deref(b"D", doc).and_then(Object::as_dict).cloned().unwrap_or_default();
+        let mut configs = properties.get_deref(b"Configs", doc).and_then(Object::as_array).cloned().unwrap_or_default();
+        replace_in_dictionary(&mut default, &merged);
+        configs.iter_mut().for_each(|config| replace(config, &merged));
+        self.configs.extend(configs);
+        let first = self.default.is_empty();
+        if first {
+            self.default = default.clone();
+        }
+        for key in LISTS {
+            let Ok(items) = default.get_deref(key, doc).and_then(Object::as_array) else { continue };
+            let mut list = if first { Vec::new() } else { self.default.get(key).and_then(Object::as_array).cloned().unwrap_or_default() };
+            for item in items {
+                // A merged layer is listed once, where the earlier input has it
+                if !matches!(item, Object::Reference(_)) || !list.contains(item) {
+                    list.push(item.clone());
+                }
+            }
+            self.default.set(key, list);
+        }
+    }
+
+    /// Give `catalog` the optional content of all the inputs, if any has layers.
+    pub fn apply(&self, catalog: &mut Dictionary) {
+        catalog.remove(b"OCProperties");
+        if self.groups.is_empty() {
+            return;
+        }
+        let mut properties = Dictionary::new();
+        properties.set("OCGs", self.groups.clone());
+        properties.set("D", self.default.clone());
+        if !self.configs.is_empty() {
+            properties.set("Configs", self.configs.clone());
+        }
+        catalog.set("OCProperties", properties);
+    }
+}
+
+/// Replace the references in `object` to the objects in `merged`, layers here, by references to
+/// those they are merged into.
+pub(crate) fn replace(object: &mut Object, merged: &BTreeMap<ObjectId, ObjectId>) {
+    match object {
+        Object::Reference(id) => {
+            if let Some(&kept) = merged.get(id) {
+                *id = kept;
+            }
+        }
+        Object::Array(items) => items.iter_mut().for_each(|item| replace(item, merged)),
+        Object::Dictionary(dict) => replace_in_dictionary(dict, merged),
+        Object::Stream(stream) => replace_in_dictionary(&mut stream.dict, merged),
+        _ => {}
+    }
+}
+
+fn replace_in_dictionary(dict: &mut Dictionary, merged: &BTreeMap<ObjectId, ObjectId>) {
+    for (_, value) in dict.iter_mut() {
+        replace(value, merged);
+    }
+}
@@ -0,0 +1,1598 @@
+//! Merge PDF documents into one, with a bookmark per input.
+//!
+//! ```no_run
+//! use std::{fs::File, io::{BufWriter, Write}};
+//!
+//! use merge_pdf::{merge, outline::BookmarkStyle, write_to, MergeOptions};
+//!
+//! let options = MergeOptions::new().bookmark_style(BookmarkStyle::Bold).compress(true);
+//! let mut merged = merge(&["a.pdf".into(), "b.pdf".into()], &options)?;
+//! // As the command line writes it: header comment, object streams and linearization included
+//! write_to(&mut merged, BufWriter::new(File::create("merged.pdf")?), &options)?.flush()?;
+//! # Ok::<(), merge_pdf::Error>(())
+//! ```
+
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap, HashSet},
+    io::Write,
+    ops::Range,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+};
+
+use lopdf::{xref::XrefType, Bookmark, Dictionary, Document, Object, ObjectId};
+use md5::{Digest, Md5};
+
+mod aes;
+pub mod annotations;
+pub mod append;
+pub mod attachments;
+#[cfg(feature = "tokio")]
+pub mod asynchronous;
+#[cfg(feature = "cli")]
+pub mod burst;
+pub mod blank;
+#[cfg(feature = "cli")]
+pub mod cache;
+pub mod compress;
+pub mod cover;
+pub mod dedupe;
+mod decrypt;
+pub mod destinations;
+pub mod diff;
+#[cfg(feature = "cli")]
+pub mod dir;
+pub mod encrypt;
+mod error;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+#[cfg(feature = "cli")]
+pub mod filter;
+pub mod flatten;
+pub mod fonts;
+pub mod forms;
+#[cfg(feature = "cli")]
+pub mod glob;
+pub mod grayscale;
+pub mod image;
+#[cfg(feature = "cli")]
+pub mod info;
+pub mod input;
+pub mod labels;
+pub mod layers;
+pub mod linearize;
+pub mod interleave;
+pub mod jpeg;
+#[cfg(feature = "cli")]
+mod json;
+#[cfg(feature = "cli")]
+pub mod manifest;
+pub mod messages;
+pub mod metadata;
+mod mmap;
+pub mod nup;
+pub mod ocr;
+pub mod optimize;
+pub mod outline;
+#[cfg(feature = "cli")]
+pub mod outline_json;
+#[cfg(feature = "cli")]
+pub mod output;
+pub mod pages;
+#[cfg(feature = "cli")]
+pub mod passwords;
+pub mod pdfa;
+#[cfg(feature = "cli")]
+pub mod portfolio;
+pub mod preflight;
+pub mod redact;
+pub mod regex;
+pub mod renumber;
+#[cfg(feature = "python")]
+mod python;
+#[cfg(feature = "cli")]
+pub mod remote;
+pub mod repair;
+#[cfg(feature = "cli")]
+pub mod report;
+pub mod sanitize;
+#[cfg(feature = "cli")]
+pub mod serve;
+pub mod sha256;
+mod sha512;
+#[cfg(feature = "cli")]
+pub mod sign;
+pub mod signatures;
+#[cfg(feature = "cli")]
+pub mod sort;
+#[cfg(feature = "cli")]
+pub mod stats;
+pub mod stamp;
+pub mod structure;
+pub mod streaming;
+#[cfg(any(feature = "cli", feature = "ocr"))]
+pub mod tempdir;
+pub mod text;
+pub mod threads;
+pub mod toc;
+pub mod verify;
+pub mod view;
+mod writer;
+#[cfg(feature = "cli")]
+pub mod zip;
+
+use compress::CompressionStats;
+pub use error::{Error, Result};
+use input::InputSpec;
+use messages::tr;
+use outline::{BookmarkColor, BookmarkMode, BookmarkStyle, OutlineItem, TitleFields};
+
+/// Settings of a merge, built up from `MergeOptions::new()`.
+#[derive(Debug, Clone, Default)]
+pub struct MergeOptions {
+    pub bookmarks: BookmarkMode,
+    pub bookmark_color: BookmarkColor,
+    pub bookmark_style: BookmarkStyle,
+    pub bookmark_template: Option<String>,
+    pub outline: Option<Vec<OutlineItem>>,
+    pub max_outline_depth: Option<usize>,
+    pub flatten_outline: bool,
+    pub toc_page: bool,
+    pub orientation: Option<pages::Orientation>,
+    pub page_size: Option<pages::PageSize>,
+    pub crop: Option<pages::Margins>,
+    pub nup: Option<usize>,
+    pub booklet: bool,
+    pub duplex_pad: bool,
+    pub separator: Option<pages::Separator>,
+    pub cover: Option<cover::Cover>,
+    pub drop_blank_pages: bool,
+    pub signatures: signatures::Signatures,
+    pub sanitize: bool,
+    pub flatten_forms: bool,
+    pub annotations: flatten::Annotations,
+    pub redact: Vec<regex::Regex>,
+    /// Languages to recognize scanned pages in, see `ocr::ocr`
+    pub ocr: Option<String>,
+    pub grayscale: bool,
+    pub optimize_images: Option<optimize::ImageOptimization>,
+    pub strip_thumbnails: bool,
+    pub dedupe_pages: bool,
+    pub dedupe_resources: bool,
+    pub merge_font_subsets: bool,
+    pub page_numbers: Option<stamp::PageNumbers>,
+    pub bates: Option<stamp::Bates>,
+    pub watermark: Option<stamp::Watermark>,
+    pub overlay: Option<stamp::Overlay>,
+    pub background: Option<stamp::Overlay>,
+    pub foreground: Option<stamp::Overlay>,
+    pub header_footer: Option<stamp::HeaderFooter>,
+    pub compress: bool,
+    pub decompress: bool,
+    pub pdfa: Option<pdfa::Mode>,
+    pub metadata: metadata::Metadata,
+    pub metadata_from: Option<usize>,
+    pub view_from: Option<usize>,
+    pub page_mode: Option<view::PageMode>,
+    pub page_layout: Option<view::PageLayout>,
+    pub open_at: Option<view::OpenAt>,
+    pub strip_metadata: bool,
+    pub encryption: Option<encrypt::Encryption>,
+    pub pdf_version: Option<String>,
+    pub deterministic: bool,
+    pub lenient: bool,
+    pub no_verify: bool,
+    pub object_streams: bool,
+    pub linearize: bool,
+    pub relabel: Option<usize>,
+    pub root: Option<usize>,
+    pub cancel: Option<Arc<AtomicBool>>,
+}
+
+impl MergeOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Which bookmarks the inputs get.
+    pub fn bookmarks(mut self, mode: BookmarkMode) -> Self {
+        self.bookmarks = mode;
+        self
+    }
+
+    /// Color of every input's bookmark, unless its spec gives one.
+    pub fn bookmark_color(mut self, color: BookmarkColor) -> Self {
+        self.bookmark_color = color;
+        self
+    }
+
+    /// How the bookmark of every input is drawn, unless its spec says otherwise.
+    pub fn bookmark_style(mut self, style: BookmarkStyle) -> Self {
+        self.bookmark_style = style;
+        self
+    }
+
+    /// Title every input's bookmark by filling in this template, see `outline::expand_template`.
+    /// A title given in the input's spec still wins.
+    pub fn bookmark_template(mut self, template: impl Into<String>) -> Self {
+        self.bookmark_template = Some(template.into());
+        self
+    }
+
+    /// Use this outline instead of generating bookmarks for the inputs.
+    pub fn outline(mut self, items: Vec<OutlineItem>) -> Self {
+        self.outline = Some(items);
+        self
+    }
+
+    /// Leave out bookmarks deeper than `depth` levels, see `outline::limit_depth`.
+    pub fn max_outline_depth(mut self, depth: usize) -> Self {
+        self.max_outline_depth = Some(depth);
+        self
+    }
+
+    /// Put every bookmark on the top level, see `outline::flatten`.
+    pub fn flatten_outline(mut self, flatten: bool) -> Self {
+        self.flatten_outline = flatten;
+        self
+    }
+
+    /// Start the merged document with a visible table of contents, see `toc::build`.
+    pub fn toc_page(mut self, toc_page: bool) -> Self {
+        self.toc_page = toc_page;
+        self
+    }
+
+    /// Turn pages so that all of them read in `orientation`, see `pages::orient`.
+    pub fn auto_orient(mut self, orientation: pages::Orientation) -> Self {
+        self.orientation = Some(orientation);
+        self
+    }
+
+    /// Scale every page to `size`, see `pages::resize`.
+    pub fn page_size(mut self, size: pages::PageSize) -> Self {
+        self.page_size = Some(size);
+        self
+    }
+
+    /// Trim `margins` off every page of inputs that don't give their own, see `pages::crop`.
+    pub fn crop(mut self, margins: pages::Margins) -> Self {
+        self.crop = Some(margins);
+        self
+    }
+
+    /// Put this many pages (2 or 4) on every sheet of the merged document, see `nup::impose`.
+    pub fn nup(mut self, per_sheet: usize) -> Self {
+        self.nup = Some(per_sheet);
+        self
+    }
+
+    /// Lay the merged document out as a booklet, see `nup::booklet`.
+    pub fn booklet(mut self, booklet: bool) -> Self {
+        self.booklet = booklet;
+        self
+    }
+
+    /// Follow every input with an odd number of pages by a blank page, so each starts on a new
+    /// sheet when printed double-sided.
+    pub fn duplex_pad(mut self, duplex_pad: bool) -> Self {
+        self.duplex_pad = duplex_pad;
+        self
+    }
+
+    /// Put `separator` between every two inputs. Bookmarks never point at its pages.
+    pub fn separator(mut self, separator: pages::Separator) -> Self {
+        self.separator = Some(separator);
+        self
+    }
+
+    /// Start the merged document with `cover`, before the table of contents if there is one.
+    pub fn cover(mut self, cover: cover::Cover) -> Self {
+        self.cover = Some(cover);
+        self
+    }
+
+    /// Leave out pages that show nothing, see `blank::is_blank`.
+    pub fn drop_blank_pages(mut self, drop: bool) -> Self {
+        self.drop_blank_pages = drop;
+        self
+    }
+
+    /// Warn about, strip or refuse digitally signed inputs, see `signatures::handle_signatures`.
+    pub fn signatures(mut self, signatures: signatures::Signatures) -> Self {
+        self.signatures = signatures;
+        self
+    }
+
+    /// Take scripts, launch actions and embedded files out of the inputs, see `sanitize::sanitize`.
+    pub fn sanitize(mut self, sanitize: bool) -> Self {
+        self.sanitize = sanitize;
+        self
+    }
+
+    /// Bake form fields into the pages and drop the interactive forms, see `flatten::flatten_forms`.
+    pub fn flatten_forms(mut self, flatten: bool) -> Self {
+        self.flatten_forms = flatten;
+        self
+    }
+
+    /// Keep, flatten or strip the inputs' annotations, see `flatten::handle_annotations`.
+    pub fn annotations(mut self, annotations: flatten::Annotations) -> Self {
+        self.annotations = annotations;
+        self
+    }
+
+    /// Black out text matching `pattern`, on top of earlier patterns, see `redact::redact_pages`.
+    pub fn redact(mut self, pattern: regex::Regex) -> Self {
+        self.redact.push(pattern);
+        self
+    }
+
+    /// Give scanned pages an invisible text layer read in `languages`, like `eng+deu`, see
+    /// `ocr::ocr`.
+    pub fn ocr(mut self, languages: impl Into<String>) -> Self {
+        self.ocr = Some(languages.into());
+        self
+    }
+
+    /// Turn the inputs' colors and color images gray, see `grayscale::grayscale`.
+    pub fn grayscale(mut self, grayscale: bool) -> Self {
+        self.grayscale = grayscale;
+        self
+    }
+
+    /// Downsample and re-encode the inputs' images, see `optimize::optimize_images`.
+    pub fn optimize_images(mut self, optimization: optimize::ImageOptimization) -> Self {
+        self.optimize_images = Some(optimization);
+        self
+    }
+
+    /// Drop the page thumbnails of the inputs, see `pages::strip_thumbnails`.
+    pub fn strip_thumbnails(mut self, strip: bool) -> Self {
+        self.strip_thumbnails = strip;
+        self
+    }
+
+    /// Leave out pages that repeat a page of an earlier input, see `dedupe::drop_duplicate_pages`.
+    pub fn dedupe_pages(mut self, dedupe: bool) -> Self {
+        self.dedupe_pages = dedupe;
+        self
+    }
+
+    /// Keep one copy of the streams several inputs have alike, see `dedupe::share_streams`.
+    pub fn dedupe_resources(mut self, dedupe: bool) -> Self {
+        self.dedupe_resources = dedupe;
+        self
+    }
+
+    /// Merge the subsets of the same font the inputs embed into one, see `fonts::merge_subsets`.
+    pub fn merge_font_subsets(mut self, merge: bool) -> Self {
+        self.merge_font_subsets = merge;
+        self
+    }
+
+    /// Write page numbers on every page of the merged document, see `stamp::stamp_page_numbers`.
+    pub fn page_numbers(mut self, numbers: stamp::PageNumbers) -> Self {
+        self.page_numbers = Some(numbers);
+        self
+    }
+
+    /// Write a Bates identifier on every page of the merged document, see `stamp::stamp_bates`.
+    pub fn bates(mut self, bates: stamp::Bates) -> Self {
+        self.bates = Some(bates);
+        self
+    }
+
+    /// Draw a text watermark on every page of the merged document, see `stamp::watermark`.
+    pub fn watermark(mut self, watermark: stamp::Watermark) -> Self {
+        self.watermark = Some(watermark);
+        self
+    }
+
+    /// Put a PDF page or image on pages of the merged document, see `stamp::overlay`.
+    pub fn overlay(mut self, overlay: stamp::Overlay) -> Self {
+        self.overlay = Some(overlay);
+        self
+    }
+
+    /// Put the first page of `doc` beneath every page of the merged document, like letterhead.
+    pub fn background(mut self, doc: Document) -> Self {
+        self.background = Some(stamp::Overlay::page(doc, true));
+        self
+    }
+
+    /// Put the first page of `doc` over every page of the merged document.
+    pub fn foreground(mut self, doc: Document) -> Self {
+        self.foreground = Some(stamp::Overlay::page(doc, false));
+        self
+    }
+
+    /// Write a header and footer on every page of the merged document, see `stamp::header_footer`.
+    pub fn header_footer(mut self, header_footer: stamp::HeaderFooter) -> Self {
+        self.header_footer = Some(header_footer);
+        self
+    }
+
+    /// Compress the streams of the merged document, except those of inputs with `compress` unset.
+    pub fn compress(mut self, compress: bool) -> Self {
+        self.compress = compress;
+        self
+    }
+
+    /// Decode the streams of the merged document for reading it in a text editor, see
+    /// `compress::decompress_document`.
+    pub fn decompress(mut self, decompress: bool) -> Self {
+        self.decompress = decompress;
+        self
+    }
+
+    /// Make the merged document PDF/A-2b conformant.
+    pub fn pdfa(mut self, pdfa: bool) -> Self {
+        self.pdfa = pdfa.then_some(pdfa::Mode::Convert);
+        self
+    }
+
+    /// Make the merged document PDF/A, or check or keep the PDF/A conformance of the inputs,
+    /// see `pdfa::Mode`.
+    pub fn pdfa_mode(mut self, mode: pdfa::Mode) -> Self {
+        self.pdfa = Some(mode);
+        self
+    }
+
+    /// Set the title, author, subject and keywords of the merged document, see `metadata::apply`.
+    pub fn metadata(mut self, metadata: metadata::Metadata) -> Self {
+        self.metadata = metadata;
+        self
+    }
+
+    /// Take the document information and XMP metadata from the input at this index (0-based)
+    /// rather than leave them to chance; given metadata still goes on top.
+    pub fn metadata_from(mut self, index: usize) -> Self {
+        self.metadata_from = Some(index);
+        self
+    }
+
+    /// Take the viewer preferences, language and page layout from the input at this index
+    /// (0-based) rather than from the one whose Catalog is kept, see `view::settings`.
+    pub fn view_from(mut self, index: usize) -> Self {
+        self.view_from = Some(index);
+        self
+    }
+
+    /// Open the merged document with this panel showing, in place of the bookmarks whenever
+    /// there are some.
+    pub fn page_mode(mut self, mode: view::PageMode) -> Self {
+        self.page_mode = Some(mode);
+        self
+    }
+
+    /// Lay the pages out like this when the merged document is opened, whatever the inputs say.
+    pub fn page_layout(mut self, layout: view::PageLayout) -> Self {
+        self.page_layout = Some(layout);
+        self
+    }
+
+    /// Open the merged document at this page, counted in the output, see `view::OpenAt`.
+    pub fn open_at(mut self, open_at: view::OpenAt) -> Self {
+        self.open_at = Some(open_at);
+        self
+    }
+
+    /// Strip the inputs of their metadata, see `metadata::strip`, leaving the merged document
+    /// only the metadata given.
+    pub fn strip_metadata(mut self, strip: bool) -> Self {
+        self.strip_metadata = strip;
+        self
+    }
+
+    /// The lowest PDF version, like "1.4", the merged document declares; inputs of a later
+    /// version raise it to theirs.
+    pub fn pdf_version(mut self, version: impl Into<String>) -> Self {
+        self.pdf_version = Some(version.into());
+        self
+    }
+
+    /// Number the pages of the merged document afresh rather than keep the page labels of the
+    /// inputs: those of the first `front` inputs in roman numerals, the others from 1, see
+    /// `labels::number_tree`.
+    pub fn relabel(mut self, front: usize) -> Self {
+        self.relabel = Some(front);
+        self
+    }
+
+    /// Make merging the same inputs with the same options give the same bytes every time: no
+    /// creation and modification dates, and a trailer `ID` that is a hash of the content.
+    pub fn deterministic(mut self, deterministic: bool) -> Self {
+        self.deterministic = deterministic;
+        self
+    }
+
+    /// Repair the inputs `merge` and `merge_bytes` load if they are damaged, see `repair::load`.
+    pub fn lenient(mut self, lenient: bool) -> Self {
+        self.lenient = lenient;
+        self
+    }
+
+    /// Only warn about what `verify::check` finds wrong with the merged document, instead of
+    /// failing.
+    pub fn no_verify(mut self, no_verify: bool) -> Self {
+        self.no_verify = no_verify;
+        self
+    }
+
+    /// Pack the objects of the merged document into object streams when it is written, see
+    /// `write_to`.
+    pub fn object_streams(mut self, object_streams: bool) -> Self {
+        self.object_streams = object_streams;
+        self
+    }
+
+    /// Linearize the merged document when it is written, see `linearize::write`.
+    pub fn linearize(mut self, linearize: bool) -> Self {
+        self.linearize = linearize;
+        self
+    }
+
+    /// Encrypt the merged document with these passwords and permissions, see `encrypt::encrypt`.
+    pub fn encryption(mut self, encryption: encrypt::Encryption) -> Self {
+        self.encryption = Some(encryption);
+        self
+    }
+
+    /// Take the Catalog and page tree root from the input at this index (0-based) instead of the first.
+    pub fn root(mut self, index: usize) -> Self {
+        self.root = Some(index);
+        self
+    }
+
+    /// Stop the merge with `Error::Interrupted` at its next step once `flag` is set, from a
+    /// signal handler or another thread.
+    pub fn cancel(mut self, flag: Arc<AtomicBool>) -> Self {
+        self.cancel = Some(flag);
+        self
+    }
+
+    /// `Error::Interrupted` if the merge was asked to stop, see `cancel`.
+    fn check_cancelled(&self) -> Result<()> {
+        match &self.cancel {
+            Some(flag) if flag.load(Ordering::Relaxed) => Err(Error::Interrupted),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// A loaded input together with the options it was given.
+#[derive(Debug, Clone)]
+pub struct Input {
+    pub spec: InputSpec,
+    pub doc: Document,
+}
+
+impl Input {
+    /// Number of pages the spec takes from the document.
+    pub fn selected_pages(&self) -> usize {
+        self.doc.get_pages().into_keys().filter(|n| self.spec.selects(*n)).count()
+    }
+}
+
+/// A step of a merge, reported to the callback of the `*_with_progress` functions.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Progress {
+    /// Input `index` (0-based) of `total` was loaded from `path`.
+    Loaded { index: usize, total: usize, path: PathBuf },
+    /// The pages and objects of input `index` of `total` were copied into the merged document.
+    Copied { index: usize, total: usize },
+    /// All objects of the merged document are being renumbered.
+    Renumbering,
+    /// Fonts are being merged and pages imposed and stamped, as the options ask.
+    Transforming,
+    /// The outline of the merged document is being built.
+    Outline,
+    /// Metadata is being written and the document checked and encrypted, as the options ask.
+    Finishing,
+    /// The merged document is being written to `path`.
+    Saving { path: PathBuf },
+    /// The merged document was written, `bytes` long.
+    Saved { bytes: u64 },
+}
+
+/// The result of `merge_inputs`.
+#[derive(Debug)]
+pub struct Merged {
+    pub document: Document,
+    /// Per input, in input order; empty unless compression was asked for.
+    pub compression: Vec<CompressionStats>,
+    /// Every input that has pages in the document, in input order, with those pages counting
+    /// from 1 before `MergeOptions::nup` or `booklet` put several on a sheet.
+    pub pages: Vec<(PathBuf, Range<usize>)>,
+}
+
+/// Merge the PDFs at `inputs`, in order, taking all their pages.
+pub fn merge(inputs: &[PathBuf], options: &MergeOptions) -> crate::Result<Document> {
+    merge_with_progress(inputs, options, |_| {})
+}
+
+/// `merge`, calling `progress` as every input is loaded and copied and as every later step starts.
+pub fn merge_with_progress(
+    inputs: &[PathBuf],
+    options: &MergeOptions,
+    mut progress: impl FnMut(Progress),
+) -> crate::Result<Document> {
+    let total = inputs.len();
+    let loaded = input::load_all(
+        inputs,
+        |path| input::load_with(path, &[], options.lenient),
+        |index, result| {
+            if result.is_ok() {
+                progress(Progress::Loaded { index, total, path: inputs[index].clone() });
+            }
+        },
+    );
+    options.check_cancelled()?;
+    let inputs = inputs
+        .iter()
+        .zip(loaded)
+        .map(|(path, doc)| Ok(Input { spec: InputSpec::new(path.clone()), doc: doc? }))
+        .collect::<crate::Result<Vec<_>>>()?;
+    Ok(merge_inputs_with_progress(without_empty(inputs), options, progress)?.document)
+}
+
+/// Merge PDFs held in memory, in order, taking all their pages, and return the merged file.
+/// Nothing touches the filesystem, so this is the entry point on wasm32-unknown-unknown.
+pub fn merge_bytes(inputs: &[&[u8]], options: &MergeOptions) -> crate::Result<Vec<u8>> {
+    let inputs = inputs
+        .iter()
+        .enumerate()
+        .map(|(index, bytes)| {
+            // Messages name an input by its position, there is no path to show
+            let name = PathBuf::from(format!("input {}", index + 1));
+            let loaded = if options.lenient { repair::load(bytes).map(|(doc, _)| doc) } else { Document::load_mem(bytes) };
+            let doc = loaded.map_err(|e| crate::Error::BrokenInput(name.clone(), e))?;
+            Ok(Input { spec: InputSpec::new(name), doc })
+        })
+        .collect::<crate::Result<Vec<_>>>()?;
+    let mut document = merge_inputs(without_empty(inputs), options)?.document;
+    write_to(&mut document, Vec::new(), options)
+}
+
+/// Merge loaded inputs, in order, into as few files as possible that are each at most
+/// `max_bytes` long, and return those files. Parts only break between inputs: an input too large
+/// on its own gets a part to itself, with a warning.
+///
+/// Every part is merged like `merge_inputs` would, cover and table of contents included; finding
+/// where to break takes a trial merge per input.
+pub fn merge_in_parts(inputs: Vec<Input>, options: &MergeOptions, max_bytes: u64) -> crate::Result<Vec<Vec<u8>>> {
+    let serialize = |inputs: Vec<Input>| -> crate::Result<Vec<u8>> {
+        write_to(&mut merge_inputs(inputs, options)?.document, Vec::new(), options)
+    };
+    let mut parts = Vec::new();
+    let mut current: Vec<Input> = Vec::new();
+    let mut current_bytes = Vec::new();
+    for input in without_empty(inputs) {
+        let path = input.spec.path.clone();
+        let mut candidate = current.clone();
+        candidate.push(input.clone());
+        let bytes = serialize(candidate.clone())?;
+        if bytes.len() as u64 <= max_bytes || current.is_empty() {
+            (current, current_bytes) = (candidate, bytes);
+        } else {
+            parts.push(std::mem::take(&mut current_bytes));
+            current_bytes = serialize(vec![input.clone()])?;
+            current = vec![input];
+        }
+        if current.len() == 1 && current_bytes.len() as u64 > max_bytes {
+            log::warn!(path:% = path.display(); "{}", tr("takes {} bytes alone, over the limit of {}", &[&current_bytes.len(), &max_bytes]));
+        }
+    }
+    if current.is_empty() {
+        return Err(crate::Error::NoPages);
+    }
+    parts.push(current_bytes);
+    Ok(parts)
+}
+
+/// Write `document`, merged with `options`, to `out`: linearized if `MergeOptions::linearize`
+/// asks for it, its objects packed into compressed object streams behind a cross-reference
+/// stream if `MergeOptions::object_streams` does, else as lopdf writes it. Object streams need
+/// PDF 1.5, older output gets a cross-reference table; encrypted output is written as lopdf
+/// writes it, its strings are encrypted one by one.
+pub fn write_to<W: Write>(document: &mut Document, mut out: W, options: &MergeOptions) -> crate::Result<W> {
+    if options.linearize {
+        if document.is_encrypted() {
+            log::warn!("{}", tr("encrypted output is written without linearizing it", &[]));
+        } else {
+            return linearize::write(document, out);
+        }
+    }
+    if options.object_streams {
+        if version_number(&document.version) < Some((1, 5)) {
+            log::warn!("{}", tr("object streams need PDF 1.5, the output is PDF {} and is written without them", &[&document.version]));
+        } else if document.is_encrypted() {
+            log::warn!("{}", tr("encrypted output is written without object streams", &[]));
+        } else {
+            return Ok(writer::write_packed(document, out)?);
+        }
+    }
+    save_to(document, &mut out)?;
+    Ok(out)
+}
+
+/// Write `document` as lopdf does, but with the binary comment after the header that tells
+/// transfer programs the file is not text, as PDF/A requires and the other writers add.
+fn save_to<W: Write>(document: &mut Document, out: &mut W) -> std::io::Result<()> {
+    // lopdf writes the version as the rest of the header line, the comment goes in with it
+    let version = document.version.clone();
+    document.version = format!("{}\n%\u{e2}\u{e3}\u{cf}\u{d3}", version);
+    let saved = document.save_to(out);
+    document.version = version;
+    saved
+}
+
+/// Save `document` to `path`, reporting `Saving` before and `Saved` after writing.
+pub fn save(document: &mut Document, path: &Path, mut progress: impl FnMut(Progress)) -> crate::Result<()> {
+    progress(Progress::Saving { path: path.to_path_buf() });
+    let failed = |e| crate::Error::SaveFailed(path.to_path_buf(), e);
+    let mut out = std::io::BufWriter::new(std::fs::File::create(path).map_err(failed)?);
+    save_to(document, &mut out).map_err(failed)?;
+    out.into_inner().map_err(|e| failed(e.into_error()))?;
+    progress(Progress::Saved { bytes: std::fs::metadata(path)?.len() });
+    Ok(())
+}
+
+/// Drop the inputs that have no (selected) pages; they would only leave a dangling bookmark behind.
+fn without_empty(inputs: Vec<Input>) -> Vec<Input> {
+    inputs
+        .into_iter()
+        .filter(|input| {
+            let has_pages = input.selected_pages() > 0;
+            if !has_pages {
+                log::warn!(path:% = input.spec.path.display(); "{}", tr("no (selected) pages, skipping", &[]));
+            }
+            has_pages
+        })
+        .collect()
+}
+
+/// Merge loaded inputs, in order. Each input gets a bookmark, nested by `InputSpec::layer` or
+/// else by its position, and contributes the pages its spec selects. Inputs without (selected)
+/// pages are left out, with a warning.
+pub fn merge_inputs(inputs: Vec<Input>, options: &MergeOptions) -> crate::Result<Merged> {
+    merge_inputs_with_progress(inputs, options, |_| {})
+}
+
+/// `merge_inputs`, calling `progress` as every input is copied and as every later step starts.
+pub fn merge_inputs_with_progress(
+    inputs: Vec<Input>,
+    options: &MergeOptions,
+    mut progress: impl FnMut(Progress),
+) -> crate::Result<Merged> {
+    if options.encryption.is_some() && matches!(options.pdfa, Some(pdfa::Mode::Convert | pdfa::Mode::Preserve)) {
+        return Err(crate::Error::Usage(tr("PDF/A forbids encryption, PDF/A output cannot be encrypted", &[])));
+    }
+    let mut seen = HashSet::new();
+    let inputs = without_empty(inputs.into_iter().map(|mut input| {
+        if options.drop_blank_pages {
+            blank::drop_blank_pages(&mut input.spec, &input.doc);
+        }
+        if options.dedupe_pages {
+            dedupe::drop_duplicate_pages(&mut input.spec, &input.doc, &mut seen);
+        }
+        input
+    }).collect());
+    let total = inputs.len();
+    if inputs.is_empty() {
+        return Err(crate::Error::NoPages);
+    }
+    let root = options.root.unwrap_or(0);
+    if root >= inputs.len() {
+        return Err(crate::Error::Usage(tr("root input {} is out of range 0..{}", &[&root, &inputs.len()])));
+    }
+    if let Some(index) = options.metadata_from.filter(|&index| index >= inputs.len()) {
+        return Err(crate::Error::Usage(tr("metadata input {} is out of range 0..{}", &[&index, &inputs.len()])));
+    }
+    if let Some(index) = options.view_from.filter(|&index| index >= inputs.len()) {
+        return Err(crate::Error::Usage(tr("view input {} is out of range 0..{}", &[&index, &inputs.len()])));
+    }
+    let compress_enabled = inputs.iter().map(|i| i.spec.compress).collect::<Vec<_>>();
+    let file_names = inputs
+        .iter()
+        .map(|i| i.spec.path.file_name().map(|name| name.to_string_lossy().into_owned()).unwrap_or_default())
+        .collect::<Vec<_>>();
+
+    // Define a starting max_id (will be used as start index for object_ids)
+    let mut max_id = 1;
+    // Kept for the root of the merged structure tree, see `structure::Structure`
+    let structure_id = (max_id, 0);
+    max_id += 1;
+    let mut pagenum = 1;
+    // Catalog and root "Pages" ids of the document chosen by `MergeOptions::root`
+    let mut root_ids: Option<(ObjectId, ObjectId)> = None;
+    // Metadata of the input chosen by `MergeOptions::metadata_from`, else of the root one
+    let mut metadata_source = None;
+    // Viewer settings of the input chosen by `MergeOptions::view_from`
+    let mut view_settings = None;
+    // Collect all Documents Objects grouped by a map
+    let mut documents_pages = BTreeMap::new();
+    // Page ids in output order, which is not id order once an input is reversed
+    let mut page_order = Vec::new();
+    // The label every input page had in its input, see `labels::number_tree`
+    let mut page_labels = BTreeMap::new();
+    let mut documents_objects = BTreeMap::new();
+    // Which input every collected object came from, indexed like `inputs`
+    let mut provenance = BTreeMap::new();
+    let mut res = Document::new();
+    if let Some(version) = &options.pdf_version {
+        res.version = version.clone();
+    }
+
+    // Let's try to set these to be bigger to avoid multi allocations for faster handling of files.
+    // We are just saying each Document it about 1000 objects in size. can be adjusted for better speeds.
+    // This can only be used if you use nightly or the #![feature(extend_one)] is stabilized.
+    // documents_pages.extend_reserve(documents.len() * 1000);
+    // documents_objects.extend_reserve(documents.len() * 1000);
+
+    let mut bookmarks = InputBookmarks::new(&mut res, options.bookmarks)?;
+    let mut toc_entries = Vec::new();
+    // Where every input's pages go, before the cover and table of contents are counted in
+    let mut input_pages = Vec::with_capacity(total);
+    // Embedded files of every input, the kept Catalog would only have those of its own
+    let mut attachments = attachments::Attachments::default();
+    // Form fields and named destinations of every input, likewise
+    let mut forms = forms::Forms::default();
+    let mut destinations = destinations::Destinations::default();
+    let mut structure = structure::Structure::default();
+    let mut layers = layers::Layers::default();
+    let mut threads = threads::Threads::default();
+    // PDF/A identification of every input, for `MergeOptions::pdfa` to check or keep
+    let mut identifications = Vec::new();
+
+    // Bookmark formatting and color come from the input's spec or else the options.
+    // Formating is 1 for italic 2 for bold 3 for bold and italic
+    // Color is RGB 0.0..1.0
+    for (source, Input { spec, mut doc }) in inputs.into_iter().enumerate() {
+        let layer = spec.layer.unwrap_or(source as u32 + 1);
+        let color = spec.bookmark_color.unwrap_or(options.bookmark_color).0;
+        let format = spec.bookmark_style.unwrap_or(options.bookmark_style).format();
+
+        res.version = later_version(options, &spec, &res.version, &doc).to_string();
+        if matches!(options.pdfa, Some(pdfa::Mode::Check | pdfa::Mode::Preserve)) {
+            identifications.push(pdfa::check_input(&spec, &doc));
+        }
+        signatures::handle_signatures(&spec, &mut doc, options.signatures)?;
+        if options.sanitize {
+            sanitize::sanitize(&spec, &mut doc)?;
+        }
+        if options.flatten_forms {
+            flatten::flatten_forms(&spec, &mut doc)?;
+        }
+        flatten::handle_annotations(&spec, &mut doc, options.annotations)?;
+        // Before redaction, so recognized text is blacked out too
+        if let Some(languages) = &options.ocr {
+            ocr::ocr(&spec, &mut doc, languages, options.compress)?;
+        }
+        if !options.redact.is_empty() {
+            redact::redact_pages(&spec, &mut doc, &options.redact)?;
+        }
+        if options.grayscale {
+            grayscale::grayscale(&spec, &mut doc)?;
+        }
+        if let Some(optimization) = options.optimize_images {
+            optimize::optimize_images(&spec, &mut doc, optimization)?;
+        }
+        if options.strip_thumbnails {
+            pages::strip_thumbnails(&spec, &mut doc)?;
+        }
+        if options.strip_metadata {
+            metadata::strip(&mut doc);
+        }
+        if let Some(size) = options.page_size {
+            let selected = doc.get_pages().into_iter().filter(|(n, _)| spec.selects(*n)).map(|(_, id)| id).collect::<Vec<_>>();
+            pages::resize(&mut doc, &selected, size, spec.rotate)?;
+        }
+
+        renumber::renumber_objects_with(&mut doc, max_id);
+
+        max_id = doc.max_id + 1;
+        attachments.append(&spec, attachments::Attachments::take(&mut doc));
+
+        if source == root {
+            let catalog_id = doc.trailer.get(b"Root").and_then(Object::as_reference)?;
+            let pages_id = doc.catalog()?.get(b"Pages").and_then(Object::as_reference)?;
+            root_ids = Some((catalog_id, pages_id));
+        }
+
+        if let Some(separator) = &options.separator {
+            if let Some(previous) = page_order.last().and_then(|id| documents_pages.get(id)) {
+                let (separator_pages, objects) = separator.pages(previous, &mut max_id)?;
+                for (id, page) in separator_pages {
+                    documents_pages.insert(id, page);
+                    page_order.push(id);
+                }
+                documents_objects.extend(objects);
+            }
+        }
+
+        let mut first_object = None;
+        // The input's own outline goes below its bookmark, so read it before the objects move on
+        let source_outline = outline::entries_for(&spec, &doc);
+        let mut taken = BTreeSet::new();
+        let first_page = documents_pages.len() + 1;
+
+        let mut selected = doc.get_pages().into_iter().filter(|(number, _)| spec.selects(*number)).collect::<Vec<_>>();
+        if spec.reverse {
+            selected.reverse();
+        }
+        annotations::remap(&mut doc, &selected.iter().map(|(_, id)| *id).collect())?;
+        destinations.append(&spec, &mut doc);
+        structure.append(&spec, &mut doc, structure_id);
+        layers.append(&spec, &mut doc);
+        threads.append(&spec, &mut doc, &selected.iter().map(|(_, id)| *id).collect());
+        if options.view_from == Some(source) {
+            view_settings = Some(view::settings(&doc));
+        }
+        let ranges = labels::ranges(&doc);
+        for (number, id) in &selected {
+            page_labels.insert(*id, labels::Page::Input(source, labels::label(&doc, &ranges, *number as usize - 1)));
+        }
+
+        // Fields are found through the annotations of the pages, before those move out of `doc`
+        taken.extend(selected.iter().map(|(_, id)| *id));
+        forms.append(&spec, &mut doc, &taken);
+
+        // The pages are moved out of `doc` rather than cloned, the input is not needed afterwards
+        for (_, key) in selected {
+            // We use this as the return object for Bookmarking to determine what it points to.
+            // We only want to do this for the first page though.
+            if first_object.is_none() {
+                first_object = Some(key);
+            }
+            let mut page = pages::take_with_inherited_attributes(&mut doc, key)?;
+            if let Some(margins) = spec.crop.or(options.crop) {
+                pages::crop(&mut page, margins)?;
+            }
+            pages::rotate(&mut page, spec.rotate);
+            if let Some(orientation) = options.orientation {
+                pages::orient(&doc, &mut page, orientation);
+            }
+            provenance.insert(key, source);
+            documents_pages.insert(key, page);
+            page_order.push(key);
+        }
+        input_pages.push((spec.path.clone(), first_page..first_page + taken.len()));
+        if options.duplex_pad && taken.len() % 2 == 1 {
+            let last = page_order.last().and_then(|id| documents_pages.get(id)).map(pages::blank_like);
+            if let Some(blank) = last {
+                documents_pages.insert((max_id, 0), blank);
+                page_order.push((max_id, 0));
+                max_id += 1;
+            }
+        }
+
+        let fields = TitleFields {
+            path: &spec.path,
+            index: pagenum,
+            first: first_page,
+            last: first_page + taken.len() - 1,
+            title: outline::document_title(&doc),
+        };
+        let display = bookmark_title(options, &spec, &fields);
+        if first_object.is_some() {
+            pagenum += 1;
+        }
+
+        // A fresh Info dictionary and XMP metadata replace those of the inputs
+        let input_metadata = metadata::Source::take(&mut doc);
+        if options.metadata_from.map_or(source == root, |index| index == source) {
+            metadata_source = Some(input_metadata);
+        }
+        log::debug!(path:% = spec.path.display(); "{} page(s) and {} other object(s)", taken.len(), doc.objects.len());
+        provenance.extend(doc.objects.keys().map(|&id| (id, source)));
+        documents_objects.extend(doc.objects);
+        progress(Progress::Copied { index: source, total });
+        options.check_cancelled()?;
+
+        // Let's shadow our pointer back if nothing then set to (0,0) tto point to the next page
+        let object = first_object.unwrap_or((0, 0));
+        toc_entries.push(toc::TocEntry { title: display.clone(), page: object, number: first_page });
+
+        if let Some(bookmark) = bookmarks.add(&mut res, &spec, layer, Bookmark::new(display, color, format, object))? {
+            outline::attach(&mut res, &source_outline, bookmark, &|page| taken.contains(&page));
+        }
+    }
+
+    // The cover goes before everything, sized like the first page
+    let mut cover_pages = Vec::new();
+    if let Some(cover) = &options.cover {
+        let media_box = page_order.first().and_then(|id| documents_pages.get(id)).map(toc::media_box).unwrap_or_default();
+        let (pages, objects) = cover.pages(total, media_box, &mut max_id)?;
+        for (id, page) in pages {
+            documents_pages.insert(id, page);
+            cover_pages.push(id);
+        }
+        documents_objects.extend(objects);
+    }
+
+    // Catalog and Pages are mandatory
+    let mut catalog_object: Option<(ObjectId, Object)> = None;
+    let mut pages_object: Option<(ObjectId, Object)> = None;
+
+    // Process all objects except "Page" type
+    for (object_id, object) in documents_objects.into_iter() {
+        // We have to ignore "Page" (as are processed later), "Outlines" and "Outline" objects
+        // All other objects should be collected and inserted into the main Document
+        match object.type_name().unwrap_or("") {
+            "Catalog" => {
+                // Keep the "Catalog" of the chosen root document and use it for the future "Pages"
+                if Some(object_id) == root_ids.map(|(catalog_id, _)| catalog_id) {
+                    catalog_object = Some((object_id, object));
+                }
+            }
+            "Pages" => {
+                // Only the root "Pages" node of the chosen document survives, every other node is
+                // replaced by it. Attributes it could pass down were already copied into the pages
+                // of its own document, so they are dropped here instead of leaking into the others.
+                if Some(object_id) == root_ids.map(|(_, pages_id)| pages_id) {
+                    if let Object::Dictionary(mut dictionary) = object {
+                        for key in pages::INHERITABLE_ATTRIBUTES {
+                            dictionary.remove(key);
+                        }
+                        dictionary.remove(b"Parent");
+                        pages_object = Some((object_id, Object::Dictionary(dictionary)));
+                    }
+                }
+            }
+            "Page" => {}     // Ignored, processed later and separately
+            "Outlines" => {} // Ignored, not supported yet
+            "Outline" => {}  // Ignored, not supported yet
+            _ => {
+                res.objects.insert(object_id, object);
+            }
+        }
+    }
+
+    // If no "Pages" found abort
+    if pages_object.is_none() {
+        return Err(crate::Error::NoPagesRoot);
+    }
+
+    // Iter over all "Page" and collect with the parent "Pages" created before
+    let page_count = documents_pages.len();
+    for (object_id, object) in documents_pages {
+        if let Object::Dictionary(mut dictionary) = object {
+            dictionary.set("Parent", pages_object.as_ref().unwrap().0);
+
+            res
+                .objects
+                .insert(object_id, Object::Dictionary(dictionary));
+        }
+    }
+
+    // If no "Catalog" found abort
+    if catalog_object.is_none() {
+        return Err(crate::Error::NoPagesRoot);
+    }
+
+    let (catalog_id, catalog_object) = catalog_object.unwrap();
+    let (page_id, page_object) = pages_object.unwrap();
+
+    // The table of contents comes before every input, sized like the first page
+    let mut toc_pages = Vec::new();
+    if options.toc_page {
+        let media_box = page_order.first().and_then(|id| res.objects.get(id)).map(toc::media_box).unwrap_or_default();
+        let objects = toc::build(&toc_entries, media_box, page_id, max_id);
+        toc_pages = objects.iter().filter(|(_, o)| o.type_name().ok() == Some("Page")).map(|(id, _)| *id).collect();
+        res.objects.extend(objects);
+    }
+
+    let front = cover_pages.len() + toc_pages.len();
+    let pages = input_pages.into_iter().map(|(path, pages)| (path, pages.start + front..pages.end + front)).collect();
+
+    // The file every page came from, in output order; pages made here come from none
+    let page_sources = cover_pages
+        .iter()
+        .chain(&toc_pages)
+        .chain(&page_order)
+        .map(|id| provenance.get(id).map(|&source| file_names[source].clone()).unwrap_or_default())
+        .collect::<Vec<_>>();
+    let labels = cover_pages
+        .iter()
+        .chain(&toc_pages)
+        .map(|_| labels::Page::Front)
+        .chain(page_order.iter().map(|id| page_labels.remove(id).unwrap_or(labels::Page::Inserted)))
+        .collect::<Vec<_>>();
+
+    // Build a new "Pages" with updated fields
+    if let Object::Dictionary(mut dictionary) = page_object {
+        // Set new pages count
+        dictionary.set("Count", (toc_pages.len() + page_count) as u32);
+
+        // Set new "Kids" list (collected from documents pages) for "Pages"
+        dictionary.set(
+            "Kids",
+            cover_pages
+                .iter()
+                .chain(&toc_pages)
+                .copied()
+                .chain(page_order)
+                .map(Object::Reference)
+                .collect::<Vec<_>>(),
+        );
+
+        res
+            .objects
+            .insert(page_id, Object::Dictionary(dictionary));
+    }
+
+    // Build a new "Catalog" with updated fields
+    if let Object::Dictionary(mut dictionary) = catalog_object {
+        dictionary.set("Pages", page_id);
+        if let Some(mode) = options.page_mode {
+            dictionary.set("PageMode", mode.name());
+        } else if options.bookmarks != BookmarkMode::None {
+            dictionary.set("PageMode", "UseOutlines");
+        }
+        dictionary.remove(b"Outlines"); // Outlines not supported in merged PDFs
+        // The header says the version, see `later_version`
+        dictionary.remove(b"Version");
+        attachments.apply(&mut dictionary);
+        forms.apply(&mut dictionary);
+        destinations.apply(&mut dictionary);
+        layers.apply(&mut dictionary);
+        threads.apply(&mut dictionary);
+        if let Some(settings) = &view_settings {
+            view::apply(&mut dictionary, settings);
+        }
+        if let Some(layout) = options.page_layout {
+            dictionary.set("PageLayout", layout.name());
+        }
+        structure.apply(&mut dictionary, structure_id);
+        if let Some(root) = structure.root() {
+            res.objects.insert(structure_id, Object::Dictionary(root));
+        }
+        dictionary.remove(b"PageLabels");
+        if let Some(tree) = labels::number_tree(&labels, options.relabel) {
+            dictionary.set("PageLabels", tree);
+        }
+
+        res
+            .objects
+            .insert(catalog_id, Object::Dictionary(dictionary));
+    }
+
+    res.trailer.set("Root", catalog_id);
+
+    if options.dedupe_resources {
+        dedupe::share_streams(&mut res, &mut HashMap::new()).report();
+    }
+
+    // Compress before renumbering, while object ids still match their provenance
+    let compression = if options.compress {
+        compress::compress_by_source(&mut res, &provenance, &compress_enabled)
+    } else {
+        Vec::new()
+    };
+
+    // Update the max internal ID as wasn't updated before due to direct objects insertion
+    res.max_id = res.objects.len() as u32;
+
+    // Reorder all new Document objects
+    progress(Progress::Renumbering);
+    options.check_cancelled()?;
+    renumber::renumber_objects(&mut res);
+
+    progress(Progress::Transforming);
+    options.check_cancelled()?;
+    if options.merge_font_subsets {
+        fonts::merge_subsets(&mut res).report();
+    }
+
+    // A given outline replaces the generated bookmarks
+    if let Some(items) = &options.outline {
+        res.bookmarks.clear();
+        res.bookmark_table.clear();
+        let pages = res.get_pages().into_values().collect::<Vec<_>>();
+        outline::add_items(&mut res, items, &pages, None);
+    }
+
+    //Set any Bookmarks to the First child if they are not set to a page
+    res.adjust_zero_pages();
+    shape_outline(&mut res, options);
+    if let Some(per_sheet) = options.nup {
+        nup::impose(&mut res, per_sheet, options.compress)?;
+    }
+    if options.booklet {
+        nup::booklet(&mut res, options.compress)?;
+    }
+    // The page to open at is counted on the sheets
+    if let Some(open_at) = &options.open_at {
+        let pages = res.get_pages().into_values().collect::<Vec<_>>();
+        let destination = open_at.destination(&pages)?;
+        res.catalog_mut()?.set("OpenAction", destination);
+    }
+    // Stamps go on the sheets as printed, after any imposition, numbers over the watermark
+    if let Some(background) = &options.background {
+        stamp::overlay(&mut res, background, options.compress)?;
+    }
+    if let Some(watermark) = &options.watermark {
+        stamp::watermark(&mut res, watermark, options.compress)?;
+    }
+    for overlay in [&options.foreground, &options.overlay].into_iter().flatten() {
+        stamp::overlay(&mut res, overlay, options.compress)?;
+    }
+    if let Some(header_footer) = &options.header_footer {
+        // Sheets hold pages of several files
+        let sources = if options.nup.is_some() || options.booklet { Vec::new() } else { page_sources };
+        stamp::header_footer(&mut res, header_footer, &sources, options.compress)?;
+    }
+    if let Some(numbers) = &options.page_numbers {
+        stamp::stamp_page_numbers(&mut res, numbers, options.compress)?;
+    }
+    if let Some(bates) = &options.bates {
+        stamp::stamp_bates(&mut res, bates, options.compress)?;
+    }
+
+    //Set all bookmarks to the PDF Object tree then set the Outlines to the Bookmark content map.
+    // Renumbering moved the Catalog unless it came from the first input, so look it up again
+    progress(Progress::Outline);
+    options.check_cancelled()?;
+    let catalog_id = res.trailer.get(b"Root").and_then(Object::as_reference)?;
+    if let Some(n) = res.build_outline() {
+        if let Ok(Object::Dictionary(ref mut dict)) = res.get_object_mut(catalog_id) {
+            dict.set("Outlines", Object::Reference(n));
+        }
+    }
+
+    progress(Progress::Finishing);
+    options.check_cancelled()?;
+    metadata::apply(&mut res, output_info(options, metadata_source.unwrap_or_default()))?;
+    match options.pdfa {
+        Some(pdfa::Mode::Convert) => pdfa::apply(&mut res)?,
+        Some(mode) => {
+            if let Some(identification) = pdfa::report(mode, &identifications) {
+                pdfa::preserve(&mut res, identification)?;
+            }
+        }
+        None => {}
+    }
+    if options.decompress {
+        compress::decompress_document(&mut res);
+    }
+    // What the inputs brought and the merge no longer uses is not written
+    if verify::drop_unreachable(&mut res) > 0 {
+        renumber::renumber_objects(&mut res);
+    }
+    verify::verify(&res, options.no_verify)?;
+    if options.deterministic {
+        res.trailer.set("ID", content_id(&res));
+    }
+    if let Some(encryption) = &options.encryption {
+        encrypt::encrypt(&mut res, encryption)?;
+    }
+    // Cross-reference streams came with PDF 1.5
+    if version_number(&res.version) < Some((1, 5)) {
+        res.reference_table.cross_reference_type = XrefType::CrossReferenceTable;
+    }
+
+    Ok(Merged { document: res, compression, pages })
+}
+
+/// Apply the outline depth limit and flattening of `options` to the bookmarks of `res`. Zero
+/// pages must be adjusted before, bookmarks that lose their children still need their page.
+fn shape_outline(res: &mut Document, options: &MergeOptions) {
+    if let Some(depth) = options.max_outline_depth {
+        outline::limit_depth(res, depth);
+    }
+    if options.flatten_outline {
+        outline::flatten(res);
+    }
+}
+
+/// The later of `version`, the merged document's so far, and the version `doc`, the input
+/// `spec`, declares in its header or Catalog; a warning if that goes past the one asked for.
+fn later_version<'a>(options: &MergeOptions, spec: &InputSpec, version: &'a str, doc: &'a Document) -> &'a str {
+    let catalog = doc.catalog().and_then(|catalog| catalog.get(b"Version")).and_then(Object::as_name_str).ok();
+    let input = catalog.filter(|catalog| version_number(catalog) > version_number(&doc.version)).unwrap_or(&doc.version);
+    if let Some(asked) = options.pdf_version.as_deref().filter(|asked| version_number(input) > version_number(asked)) {
+        log::warn!("{}", tr("{} is PDF {}, the output cannot be PDF {}", &[&spec.path.display(), &input, &asked]));
+    }
+    if version_number(input) > version_number(version) {
+        input
+    } else {
+        version
+    }
+}
+
+/// A PDF version like "1.7" as `(major, minor)`, to compare numerically; `None`, lower than
+/// any version, if it is not one.
+pub(crate) fn version_number(version: &str) -> Option<(u32, u32)> {
+    let (major, minor) = version.trim().split_once('.')?;
+    Some((major.parse().ok()?, minor.parse().ok()?))
+}
+
+/// The Info dictionary of the merged document, from the metadata `source` (the input chosen by
+/// `MergeOptions::metadata_from`, else the root one) and the metadata given.
+pub(crate) fn output_info(options: &MergeOptions, source: metadata::Source) -> Dictionary {
+    if options.strip_metadata {
+        // Nothing but what is given
+        let mut info = Dictionary::new();
+        options.metadata.update_info(&mut info);
+        return info;
+    }
+    let title = source.title();
+    // Without an input to take it from, only the title of the root input's metadata is kept
+    let info = source.info.filter(|_| options.metadata_from.is_some());
+    let mut info = metadata::merged_info(info, title, &options.metadata);
+    if options.deterministic {
+        info.remove(b"CreationDate");
+        info.remove(b"ModDate");
+    }
+    info
+}
+
+/// A trailer `ID` for `doc` that depends on nothing but its objects: both halves are the MD5 of
+/// them as they are written.
+pub(crate) fn content_id(doc: &Document) -> Vec<Object> {
+    let mut hasher = Md5::new();
+    for ((id, generation), object) in &doc.objects {
+        let mut written = format!("{} {} obj\n", id, generation).into_bytes();
+        writer::write_object(&mut written, object);
+        hasher.update(&written);
+    }
+    let id = Object::String(hasher.finalize().to_vec(), lopdf::StringFormat::Hexadecimal);
+    vec![id.clone(), id]
+}
+
+/// The bookmark title of an input: the title its spec gives, else `MergeOptions::bookmark_template`
+/// filled in, else what `MergeOptions::bookmarks` calls it.
+fn bookmark_title(options: &MergeOptions, spec: &InputSpec, fields: &TitleFields) -> String {
+    if let Some(title) = &spec.title {
+        return title.clone();
+    }
+    if let Some(template) = &options.bookmark_template {
+        return outline::expand_template(template, fields);
+    }
+    match options.bookmarks {
+        BookmarkMode::Page | BookmarkMode::None => format!("Page {}", fields.index),
+        BookmarkMode::PerFile => outline::file_title(&spec.path),
+        BookmarkMode::Sections => outline::file_title(&spec.path),
+        BookmarkMode::Metadata => fields.title.clone().unwrap_or_else(|| outline::file_title(&spec.path)),
+    }
+}
+
+/// Where the bookmark of every input goes, by `BookmarkMode`.
+enum InputBookmarks {
+    Layers(BookmarkLayers),
+    /// The sections opened so far with their bookmark ids, outermost first.
+    Sections(Vec<(String, u32)>),
+    TopLevel,
+    Disabled,
+}
+
+impl InputBookmarks {
+    fn new(res: &mut Document, mode: BookmarkMode) -> crate::Result<Self> {
+        Ok(match mode {
+            BookmarkMode::Page => InputBookmarks::Layers(BookmarkLayers::new(res)?),
+            BookmarkMode::Sections => InputBookmarks::Sections(Vec::new()),
+            BookmarkMode::PerFile | BookmarkMode::Metadata => InputBookmarks::TopLevel,
+            BookmarkMode::None => InputBookmarks::Disabled,
+        })
+    }
+
+    /// Add the bookmark of the input `spec` describes, `layer` deep in `BookmarkMode::Page`.
+    /// Returns its id, or `None` when bookmarks are disabled.
+    fn add(&mut self, res: &mut Document, spec: &InputSpec, layer: u32, bookmark: Bookmark) -> crate::Result<Option<u32>> {
+        match self {
+            InputBookmarks::Layers(layers) => layers.add(res, layer, bookmark).map(Some),
+            InputBookmarks::TopLevel => Ok(Some(res.add_bookmark(bookmark, None))),
+            InputBookmarks::Disabled => Ok(None),
+            InputBookmarks::Sections(open) => {
+                // Sections the previous input sat in stay open as far as this one shares them
+                let shared = open.iter().zip(&spec.sections).take_while(|((open, _), title)| open == *title).count();
+                open.truncate(shared);
+                for title in &spec.sections[shared..] {
+                    // (0, 0) is pointed at the first page below the section by adjust_zero_pages
+                    let section = Bookmark::new(title.clone(), [0.0, 0.0, 0.0], 0, (0, 0));
+                    let id = res.add_bookmark(section, open.last().map(|(_, id)| *id));
+                    open.push((title.clone(), id));
+                }
+                Ok(Some(res.add_bookmark(bookmark, open.last().map(|(_, id)| *id))))
+            }
+        }
+    }
+}
+
+/// Nests the bookmark of every input below a "Table of Contents" entry, by layer.
+struct BookmarkLayers {
+    // We use this to keep track of the last Parent per layer depth.
+    layer_parent: Vec<Option<u32>>,
+    // This is the last layer ran.
+    last_layer: u32,
+}
+
+impl BookmarkLayers {
+    /// Add the "Table of Contents" entry to `res`.
+    fn new(res: &mut Document) -> crate::Result<Self> {
+        let mut layer_parent = vec![None; 2];
+        // Add a Table of Contents
+        // We set the object page to (0,0) which means it will point to the first object after it.
+        *layer_parent.get_mut(0).ok_or_else(|| crate::Error::Invalid(tr("layer_parent is empty", &[])))? = Some(res.add_bookmark(
+            Bookmark::new("Table of Contents".to_string(), [0.0, 0.0, 0.0], 0, (0, 0)),
+            None,
+        ));
+        Ok(BookmarkLayers { layer_parent, last_layer: 0 })
+    }
+
+    // This will use the layering to implement children under Parents in the bookmarks
+    // Example as we are generating it here.
+    // Table of Contents
+    // - Page 1
+    // -- Page 2
+    // -- Page 3
+    // --- Page 4
+    fn add(&mut self, res: &mut Document, layer: u32, bookmark: Bookmark) -> crate::Result<u32> {
+        let depth = layer.max(self.last_layer) as usize + 1;
+        if self.layer_parent.len() < depth {
+            self.layer_parent.resize(depth, None);
+        }
+        // Every arm stores the new bookmark in a slot of layer_parent and yields that slot
+        let slot = match layer {
+            0 => {
+                *self.layer_parent.get_mut(0).ok_or_else(|| crate::Error::Invalid(tr("layer_parent is empty", &[])))? =
+                    Some(res.add_bookmark(bookmark, None));
+                self.last_layer = 0;
+                0
+            },
+            1 => {
+                let parent = *self.layer_parent.first().ok_or_else(|| crate::Error::Invalid(tr("layer_parent is empty", &[])))?;
+                *self.layer_parent.get_mut(1).ok_or_else(|| crate::Error::Invalid(tr("layer_parent[{}] is out of index", &[&1])))? = Some(res.add_bookmark(
+                    bookmark,
+                    parent,
+                ));
+                self.last_layer = 1;
+                1
+            },
+            l if l <= self.last_layer || l - 1 == self.last_layer => {
+                let parent = *self.layer_parent.get(l as usize -1).ok_or_else(|| crate::Error::Invalid(tr("layer_parent is empty", &[])))?;
+                *self.layer_parent.get_mut(l as usize - 1).ok_or_else(|| crate::Error::Invalid(tr("layer_parent[{}] is out of index", &[&l])))? = Some(res.add_bookmark(
+                    bookmark,
+                    parent,
+                ));
+                self.last_layer = l;
+                l as usize - 1
+            },
+            _ if self.last_layer > 0 => {
+                let parent = *self.layer_parent.get(self.last_layer as usize -1).ok_or_else(|| crate::Error::Invalid(tr("layer_parent[{}] is out of index", &[&(self.last_layer-1)])))?;
+                *self.layer_parent.get_mut(self.last_layer as usize).ok_or_else(|| crate::Error::Invalid(tr("layer_parent[{}] is out of index", &[&self.last_layer])))? = Some(res.add_bookmark(
+                    bookmark,
+                    parent,
+                ));
+                self.last_layer as usize
+            },
+            _ => {
+                let parent = *self.layer_parent.first().ok_or_else(|| crate::Error::Invalid(tr("layer_parent[{}] is out of index", &[&0])))?;
+                *self.layer_parent.get_mut(1).ok_or_else(|| crate::Error::Invalid(tr("layer_parent[{}] is out of index", &[&1])))? = Some(res.add_bookmark(
+                    bookmark,
+                    parent,
+                ));
+                self.last_layer = 1;
+                1
+            },
+        };
+        self.layer_parent[slot].ok_or_else(|| crate::Error::Invalid(tr("layer_parent[{}] is empty", &[&slot])))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+
+    use lopdf::{dictionary, Stream};
+
+    use super::*;
+
+    thread_local! {
+        /// Warnings logged on this thread, with the input they name, as `path: message`.
+        static WARNINGS: RefCell<Vec<String>> = const { RefCell::new(Vec::new()) };
+    }
+
+    struct Capture;
+
+    impl log::Log for Capture {
+        fn enabled(&self, metadata: &log::Metadata) -> bool {
+            metadata.level() <= log::Level::Warn
+        }
+
+        fn log(&self, record: &log::Record) {
+            if self.enabled(record.metadata()) {
+                let path = record.key_values().get(log::kv::Key::from("path")).map(|path| path.to_string()).unwrap_or_default();
+                WARNINGS.with(|warnings| warnings.borrow_mut().push(format!("{}: {}", path, record.args())));
+            }
+        }
+
+        fn flush(&self) {}
+    }
+
+    fn capture_warnings() {
+        static CAPTURE: Capture = Capture;
+        if log::set_logger(&CAPTURE).is_ok() {
+            log::set_max_level(log::LevelFilter::Warn);
+        }
+        WARNINGS.with(|warnings| warnings.borrow_mut().clear());
+    }
+
+    /// A document of `pages` empty Letter pages.
+    pub(crate) fn document(pages: usize) -> Document {
+        let mut doc = Document::with_version("1.7");
+        let pages_id = doc.new_object_id();
+        let kids = (0..pages)
+            .map(|_| {
+                let content = doc.add_object(Stream::new(Dictionary::new(), Vec::new()));
+                let page = doc.add_object(dictionary! { "Type" => "Page", "Parent" => pages_id, "MediaBox" => vec![0.into(), 0.into(), 612.into(), 792.into()], "Contents" => content });
+                Object::Reference(page)
+            })
+            .collect::<Vec<_>>();
+        doc.objects.insert(pages_id, Object::Dictionary(dictionary! { "Type" => "Pages", "Count" => pages as i64, "Kids" => kids }));
+        let catalog = doc.add_object(dictionary! { "Type" => "Catalog", "Pages" => pages_id });
+        doc.trailer.set("Root", catalog);
+        doc
+    }
+
+    fn input(path: &str, doc: Document) -> Input {
+        Input { spec: InputSpec::new(PathBuf::from(path)), doc }
+    }
+
+    #[test]
+    fn inputs_without_pages_are_skipped_with_a_warning() {
+        capture_warnings();
+        // Only a form, its page tree empty
+        let mut form = document(0);
+        let field = form.add_object(dictionary! { "FT" => "Tx", "T" => Object::string_literal("name") });
+        let catalog = form.trailer.get(b"Root").and_then(Object::as_reference).unwrap();
+        form.get_dictionary_mut(catalog).unwrap().set("AcroForm", dictionary! { "Fields" => vec![field.into()] });
+
+        let inputs = vec![input("first.pdf", document(1)), input("form.pdf", form), input("last.pdf", document(2))];
+        let merged = merge_inputs(inputs, &MergeOptions::new().bookmarks(BookmarkMode::PerFile)).unwrap();
+
+        let warnings = WARNINGS.with(|warnings| warnings.borrow().clone());
+        assert!(warnings.iter().any(|warning| warning.starts_with("form.pdf: ")), "{:?}", warnings);
+        let pages = merged.document.get_pages();
+        assert_eq!(pages.len(), 3);
+        assert_eq!(merged.pages.iter().map(|(path, _)| path.to_str().unwrap()).collect::<Vec<_>>(), ["first.pdf", "last.pdf"]);
+
+        let entries = outline::read(&merged.document);
+        assert_eq!(entries.iter().map(|entry| entry.title.as_str()).collect::<Vec<_>>(), ["first", "last"]);
+        for entry in &entries {
+            let page = entry.page.expect("every bookmark points at a page");
+            assert_ne!(page, (0, 0));
+            assert!(pages.values().any(|&id| id == page));
+        }
+    }
+
+    #[test]
+    fn pdfa_output_is_never_encrypted() {
+        let encryption = encrypt::Encryption { user_password: String::new(), owner_password: "owner".to_string(), permissions: Vec::new() };
+        for mode in [pdfa::Mode::Convert, pdfa::Mode::Preserve] {
+            let options = MergeOptions::new().pdfa_mode(mode).encryption(encryption.clone());
+            let merged = merge_inputs(vec![input("a.pdf", document(1)), input("b.pdf", document(1))], &options);
+            assert!(matches!(merged, Err(Error::Usage(_))), "{:?}", mode);
+        }
+    }
+
+    #[test]
+    fn versions_compare_by_number() {
+        assert!(version_number("1.10") > version_number("1.9"));
+        assert!(version_number("2.0") > version_number("1.7"));
+        assert_eq!(version_number(" 1.4 "), Some((1, 4)));
+        assert_eq!(version_number("1"), None);
+        assert!(version_number("x.y") < Some((1, 5)));
+
+        let (mut later, mut earlier) = (document(1), document(1));
+        later.version = "1.10".to_string();
+        earlier.version = "1.9".to_string();
+        let merged = merge_inputs(vec![input("later.pdf", later), input("earlier.pdf", earlier)], &MergeOptions::new()).unwrap();
+        assert_eq!(merged.document.version, "1.10");
+    }
+}
@@ -0,0 +1,464 @@
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    io::Write,
+};
+
+use lopdf::{Dictionary, Document, Object, ObjectId, Stream};
+
+use crate::{layers, messages::tr, verify, writer};
+
+/// How many times the layout is redone for offsets that depend on each other to settle.
+const MAX_PASSES: usize = 16;
+
+/// Trailer entries of the document carried over to the first-page trailer.
+const TRAILER: [&[u8]; 3] = [b"Root", b"Info", b"ID"];
+
+/// Write `doc` linearized (PDF 32000-1, Annex F), so a viewer reading it over a slow connection
+/// can show the first page before the rest arrives: the first page and what it uses come right
+/// after the catalog, with a cross-reference table of their own, then every other page with the
+/// objects only it uses, then the objects several pages share, then the rest. A hint stream
+/// tells where each page starts and which shared objects it needs.
+///
+/// Nothing may be inherited from the page tree, as in every merged document, and `doc` must
+/// not be encrypted: its hint stream would have to be.
+pub fn write<W: Write>(doc: &Document, mut out: W) -> crate::Result<W> {
+    let plan = Plan::new(doc)?;
+    let mut layout = Layout::default();
+    for _ in 0..MAX_PASSES {
+        let next = plan.measure(&layout);
+        if next == layout {
+            plan.write(&layout, &mut out)?;
+            return Ok(out);
+        }
+        layout = next;
+    }
+    Err(crate::Error::Invalid(tr("the layout of the linearized file did not settle", &[])))
+}
+
+/// The objects of the linearized file, renumbered and written out, in the order they go.
+struct Plan {
+    header: Vec<u8>,
+    /// Number of the linearization dictionary, the first of the first-page section; those of the
+    /// rest of the file come before it
+    first_id: u32,
+    /// One more than the last object number
+    size: u32,
+    catalog: Vec<u8>,
+    hint_id: u32,
+    /// The first page and the objects it uses, the page first
+    first_page: Vec<(u32, Vec<u8>)>,
+    /// The other pages, each followed by the objects only it uses, then the shared objects and
+    /// everything else
+    rest: Vec<(u32, Vec<u8>)>,
+    pages: Vec<PageSection>,
+    /// The objects several pages use, those of the first page first, as shared object groups of
+    /// one object each
+    shared: Vec<u32>,
+    /// How many of `shared` are in the first-page section
+    first_shared: usize,
+    trailer: Dictionary,
+}
+
+/// What the page offset hint table says about a page.
+struct PageSection {
+    /// The page's object number
+    id: u32,
+    objects: usize,
+    length: u64,
+    /// Indices in `Plan::shared` of the shared objects it uses
+    shared: Vec<usize>,
+}
+
+/// Where everything ended up the last time the file was laid out.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+struct Layout {
+    offsets: BTreeMap<u32, u64>,
+    first_xref: u64,
+    hint: (u64, u64),
+    end_of_first_page: u64,
+    main_xref: u64,
+    length: u64,
+}
+
+impl Plan {
+    fn new(doc: &Document) -> crate::Result<Self> {
+        if doc.is_encrypted() {
+            return Err(crate::Error::Invalid(tr("an encrypted document cannot be linearized", &[])));
+        }
+        let catalog_id = doc.trailer.get(b"Root").and_then(Object::as_reference)?;
+        let page_ids = doc.get_pages().into_values().collect::<Vec<_>>();
+        if page_ids.is_empty() {
+            return Err(crate::Error::Invalid(tr("a document without pages cannot be linearized", &[])));
+        }
+
+        // The objects of every page; pages, the page tree and the catalog belong to no page
+        let stops = |id: &ObjectId| matches!(doc.get_object(*id).and_then(Object::type_name), Ok("Page" | "Pages" | "Catalog"));
+        let reachable = |page: ObjectId| -> Vec<ObjectId> {
+            let mut found = vec![page];
+            let mut seen = HashSet::from([page]);
+            let mut index = 0;
+            while index < found.len() {
+                let mut references = Vec::new();
+                if let Ok(object) = doc.get_object(found[index]) {
+                    verify::references(object, &mut references);
+                }
+                for id in references {
+                    if doc.objects.contains_key(&id) && !stops(&id) && seen.insert(id) {
+                        found.push(id);
+                    }
+                }
+                index += 1;
+            }
+            found
+        };
+        let first_page = reachable(page_ids[0]);
+        let in_first_page = first_page.iter().copied().collect::<HashSet<_>>();
+        let others = page_ids[1..].iter().map(|&page| reachable(page)).collect::<Vec<_>>();
+        let mut users = HashMap::<ObjectId, usize>::new();
+        for objects in &others {
+            for id in objects {
+                *users.entry(*id).or_default() += 1;
+            }
+        }
+        let mut shared = first_page[1..].iter().copied().filter(|id| users.contains_key(id)).collect::<Vec<_>>();
+        let first_shared = shared.len();
+        let mut seen_shared = shared.iter().copied().collect::<HashSet<_>>();
+        for objects in &others {
+            for &id in objects {
+                if !in_first_page.contains(&id) && users[&id] > 1 && seen_shared.insert(id) {
+                    shared.push(id);
+                }
+            }
+        }
+        let shared_index = shared.iter().enumerate().map(|(index, id)| (*id, index)).collect::<HashMap<_, _>>();
+
+        // The rest of the file is numbered from 1, the first-page section after it
+        let mut order = Vec::new();
+        for objects in &others {
+            order.extend(objects.iter().copied().filter(|id| users[id] == 1 && !in_first_page.contains(id)));
+        }
+        order.extend(shared[first_shared..].iter().copied());
+        let mut placed = order.iter().chain(&first_page).copied().collect::<HashSet<_>>();
+        placed.insert(catalog_id);
+        order.extend(
+            doc.objects
+                .iter()
+                .filter(|(id, object)| !placed.contains(id) && !matches!(object.type_name(), Ok("XRef" | "ObjStm")))
+                .map(|(id, _)| *id),
+        );
+        let mut numbers = BTreeMap::new();
+        for (index, id) in order.iter().enumerate() {
+            numbers.insert(*id, (index as u32 + 1, 0));
+        }
+        let first_id = order.len() as u32 + 1;
+        numbers.insert(catalog_id, (first_id + 1, 0));
+        let hint_id = first_id + 2;
+        for (index, id) in first_page.iter().enumerate() {
+            numbers.insert(*id, (hint_id + 1 + index as u32, 0));
+        }
+        let size = hint_id + 1 + first_page.len() as u32;
+
+        let serialize = |id: &ObjectId| -> (u32, Vec<u8>) {
+            let mut object = doc.objects[id].clone();
+            layers::replace(&mut object, &numbers);
+            let number = numbers[id];
+            (number.0, writer::indirect_object(number, &object))
+        };
+        let first_page_objects = first_page.iter().map(serialize).collect::<Vec<_>>();
+        let rest = order.iter().map(serialize).collect::<Vec<_>>();
+        let length = |objects: &[(u32, Vec<u8>)]| objects.iter().map(|(_, bytes)| bytes.len() as u64).sum::<u64>();
+
+        let mut pages = vec![PageSection { id: numbers[&page_ids[0]].0, objects: first_page.len(), length: length(&first_page_objects), shared: Vec::new() }];
+        let mut start = 0;
+        for (page, objects) in page_ids[1..].iter().zip(&others) {
+            let count = objects.iter().filter(|id| users[id] == 1 && !in_first_page.contains(id)).count();
+            pages.push(PageSection {
+                id: numbers[page].0,
+                objects: count,
+                length: length(&rest[start..start + count]),
+                shared: objects.iter().filter_map(|id| shared_index.get(id).copied()).collect(),
+            });
+            start += count;
+        }
+
+        let mut trailer = Dictionary::new();
+        for key in TRAILER {
+            if let Ok(value) = doc.trailer.get(key) {
+                let mut value = value.clone();
+                layers::replace(&mut value, &numbers);
+                trailer.set(key, value);
+            }
+        }
+        Ok(Plan {
+            // The binary comment tells transfer programs the file is not text
+            header: [format!("%PDF-{}\n", doc.version).as_bytes(), b"%\xe2\xe3\xcf\xd3\n"].concat(),
+            first_id,
+            size,
+            catalog: serialize(&catalog_id).1,
+            hint_id,
+            first_page: first_page_objects,
+            rest,
+            pages,
+            shared: shared.iter().map(|id| numbers[id].0).collect(),
+            first_shared,
+            trailer,
+        })
+    }
+
+    /// Where everything goes if the values that depend on the layout are those of `layout`.
+    fn measure(&self, layout: &Layout) -> Layout {
+        let mut next = Layout::default();
+        let mut position = self.header.len() as u64;
+        next.offsets.insert(self.first_id, position);
+        position += self.linearization(layout).len() as u64;
+        next.first_xref = position;
+        position += self.first_page_xref(layout).len() as u64;
+        next.offsets.insert(self.first_id + 1, position);
+        position += self.catalog.len() as u64;
+        let hint = self.hint_stream(layout);
+        next.offsets.insert(self.hint_id, position);
+        next.hint = (position, hint.len() as u64);
+        position += hint.len() as u64;
+        for (id, bytes) in &self.first_page {
+            next.offsets.insert(*id, position);
+            position += bytes.len() as u64;
+        }
+        next.end_of_first_page = position;
+        for (id, bytes) in &self.rest {
+            next.offsets.insert(*id, position);
+            position += bytes.len() as u64;
+        }
+        next.main_xref = position;
+        position += self.main_xref(layout).len() as u64;
+        next.length = position;
+        next
+    }
+
+    fn write(&self, layout: &Layout, out: &mut impl Write) -> std::io::Result<()> {
+        out.write_all(&self.header)?;
+        out.write_all(&self.linearization(layout))?;
+        out.write_all(&self.first_page_xref(layout))?;
+        out.write_all(&self.catalog)?;
+        out.write_all(&self.hint_stream(layout))?;
+        for (_, bytes) in self.first_page.iter().chain(&self.rest) {
+            out.write_all(bytes)?;
+        }
+        out.write_all(&self.main_xref(layout))?;
+        out.flush()
+    }
+
+    fn linearization(&self, layout: &Layout) -> Vec<u8> {
+        let mut dict = Dictionary::new();
+        dict.set("Linearized", 1);
+        dict.set("L", layout.length as i64);
+        dict.set("H", vec![(layout.hint.0 as i64).into(), (layout.hint.1 as i64).into()]);
+        dict.set("O", self.pages[0].id as i64);
+        dict.set("E", layout.end_of_first_page as i64);
+        dict.set("N", self.pages.len() as i64);
+        // The white-space before the entry of object 0 in the main table
+        dict.set("T", (layout.main_xref + format!("xref\n0 {}", self.first_id).len() as u64) as i64);
+        writer::indirect_object((self.first_id, 0), &Object::Dictionary(dict))
+    }
+
+    fn first_page_xref(&self, layout: &Layout) -> Vec<u8> {
+        let mut out = format!("xref\n{} {}\n", self.first_id, self.size - self.first_id).into_bytes();
+        for id in self.first_id..self.size {
+            out.extend_from_slice(format!("{:010} 00000 n \n", layout.offsets.get(&id).unwrap_or(&0)).as_bytes());
+        }
+        let mut trailer = self.trailer.clone();
+        trailer.set("Size", self.size as i64);
+        trailer.set("Prev", layout.main_xref as i64);
+        out.extend_from_slice(b"trailer\n");
+        writer::write_object(&mut out, &Object::Dictionary(trailer));
+        out.extend_from_slice(b"\nstartxref\n0\n%%EOF\n");
+        out
+    }
+
+    fn main_xref(&self, layout: &Layout) -> Vec<u8> {
+        let mut out = format!("xref\n0 {}\n0000000000 65535 f \n", self.first_id).into_bytes();
+        for id in 1..self.first_id {
+            out.extend_from_slice(format!("{:010} 00000 n \n", layout.offsets.get(&id).unwrap_or(&0)).as_bytes());
+        }
+        out.extend_from_slice(format!("trailer\n<</Size {}>>\nstartxref\n{}\n%%EOF\n", self.first_id, layout.first_xref).as_bytes());
+        out
+    }
+
+    /// The primary hint stream, with the page offset and shared object hint tables (F.4.1 and
+    /// F.4.2). Their offsets are those of a file without the hint stream, as Annex F has them.
+    fn hint_stream(&self, layout: &Layout) -> Vec<u8> {
+        let offset = |id: u32| {
+            let offset = layout.offsets.get(&id).copied().unwrap_or(0);
+            if offset > layout.hint.0 {
+                offset.saturating_sub(layout.hint.1)
+            } else {
+                offset
+            }
+        };
+        let mut table = Bits::default();
+
+        let objects = self.pages.iter().map(|page| page.objects as u64).collect::<Vec<_>>();
+        let lengths = self.pages.iter().map(|page| page.length).collect::<Vec<_>>();
+        let (least_objects, objects_bits) = range(&objects);
+        let (least_length, length_bits) = range(&lengths);
+        let most_shared = self.pages.iter().map(|page| page.shared.len() as u64).max().unwrap_or(0);
+        let shared_bits = bits(self.shared.len().saturating_sub(1) as u64);
+        table.number(least_objects, 32);
+        table.number(offset(self.pages[0].id), 32);
+        table.number(objects_bits as u64, 16);
+        table.number(least_length, 32);
+        table.number(length_bits as u64, 16);
+        // Content streams are taken as starting with their page and as long as it
+        table.number(0, 32);
+        table.number(0, 16);
+        table.number(least_length, 32);
+        table.number(length_bits as u64, 16);
+        table.number(bits(most_shared) as u64, 16);
+        table.number(shared_bits as u64, 16);
+        table.number(0, 16);
+        table.number(1, 16);
+        let column = |table: &mut Bits, values: &mut dyn Iterator<Item = u64>, width: u32| {
+            values.for_each(|value| table.number(value, width));
+            table.align();
+        };
+        column(&mut table, &mut objects.iter().map(|n| n - least_objects), objects_bits);
+        column(&mut table, &mut lengths.iter().map(|n| n - least_length), length_bits);
+        column(&mut table, &mut self.pages.iter().map(|page| page.shared.len() as u64), bits(most_shared));
+        column(&mut table, &mut self.pages.iter().flat_map(|page| page.shared.iter().map(|&index| index as u64)), shared_bits);
+        column(&mut table, &mut self.pages.iter().flat_map(|page| page.shared.iter().map(|_| 0)), 0);
+        column(&mut table, &mut self.pages.iter().map(|_| 0), 0);
+        column(&mut table, &mut lengths.iter().map(|n| n - least_length), length_bits);
+        let shared_table = table.bytes.len();
+
+        let sizes = self.first_page.iter().chain(&self.rest).map(|(id, bytes)| (*id, bytes.len() as u64)).collect::<HashMap<_, _>>();
+        let group_lengths = self.shared.iter().map(|id| sizes[id]).collect::<Vec<_>>();
+        let (least_group, group_bits) = range(&group_lengths);
+        let first_in_section = self.shared.get(self.first_shared).copied();
+        table.number(first_in_section.unwrap_or(0) as u64, 32);
+        table.number(first_in_section.map_or(0, offset), 32);
+        table.number(self.first_shared as u64, 32);
+        table.number(self.shared.len() as u64, 32);
+        table.number(0, 16);
+        table.number(least_group, 32);
+        table.number(group_bits as u64, 16);
+        column(&mut table, &mut group_lengths.iter().map(|n| n - least_group), group_bits);
+        column(&mut table, &mut self.shared.iter().map(|_| 0), 1);
+        column(&mut table, &mut self.shared.iter().map(|_| 0), 0);
+
+        let mut dict = Dictionary::new();
+        dict.set("S", shared_table as i64);
+        let mut stream = Stream::new(dict, table.bytes);
+        let _ = stream.compress();
+        writer::indirect_object((self.hint_id, 0), &Object::Stream(stream))
+    }
+}
+
+/// The least of `values` and the bits needed for how far the others are from it.
+fn range(values: &[u64]) -> (u64, u32) {
+    let least = values.iter().copied().min().unwrap_or(0);
+    let most = values.iter().copied().max().unwrap_or(0);
+    (least, bits(most - least))
+}
+
+/// The bits needed to write `value`.
+fn bits(value: u64) -> u32 {
+    u64::BITS - value.leading_zeros()
+}
+
+/// A hint table being written, a number of bits at a time, most significant first.
+#[derive(Default)]
+struct Bits {
+    bytes: Vec<u8>,
+    /// Bits used of the last byte, 0 when it is full
+    used: u32,
+}
+
+impl Bits {
+    fn number(&mut self, value: u64, width: u32) {
+        for bit in (0..width).rev() {
+            if self.used == 0 {
+                self.bytes.push(0);
+            }
+            if value >> bit & 1 == 1 {
+                *self.bytes.last_mut().expect("a byte was pushed") |= 0x80 >> self.used;
+            }
+            self.used = (self.used + 1) % 8;
+        }
+    }
+
+    /// Start the next number on a byte boundary.
+    fn align(&mut self) {
+        self.used = 0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use lopdf::dictionary;
+
+    use crate::{input::InputSpec, merge_inputs, Input, MergeOptions};
+
+    use super::*;
+
+    /// Inputs of 2 and 3 pages merged, every page using the same font.
+    fn merged() -> Document {
+        let inputs = [2, 3]
+            .into_iter()
+            .enumerate()
+            .map(|(i, pages)| Input { spec: InputSpec::new(PathBuf::from(format!("{}.pdf", i))), doc: crate::tests::document(pages) })
+            .collect();
+        let mut doc = merge_inputs(inputs, &MergeOptions::new()).unwrap().document;
+        let font = doc.add_object(dictionary! { "Type" => "Font", "Subtype" => "Type1", "BaseFont" => "Helvetica" });
+        for page in doc.get_pages().into_values() {
+            doc.get_dictionary_mut(page).unwrap().set("Resources", dictionary! { "Font" => dictionary! { "F1" => font } });
+        }
+        doc
+    }
+
+    #[test]
+    fn linearized_documents_load_again() {
+        let bytes = write(&merged(), Vec::new()).unwrap();
+        let loaded = Document::load_mem(&bytes).unwrap();
+        assert_eq!(loaded.get_pages().len(), 5);
+        let problems = verify::check(&loaded);
+        assert!(problems.is_empty(), "{:?}", problems);
+        // Every page keeps its font
+        for page in loaded.get_pages().into_values() {
+            let resources = loaded.get_dictionary(page).unwrap().get(b"Resources").and_then(Object::as_dict).unwrap();
+            let font = resources.get(b"Font").and_then(Object::as_dict).unwrap().get(b"F1").and_then(Object::as_reference).unwrap();
+            assert_eq!(loaded.get_dictionary(font).unwrap().get(b"BaseFont").unwrap(), &Object::Name(b"Helvetica".to_vec()));
+        }
+    }
+
+    #[test]
+    fn linearization_dictionary_matches_the_file() {
+        let bytes = write(&merged(), Vec::new()).unwrap();
+        let loaded = Document::load_mem(&bytes).unwrap();
+        let (&id, linearization) = loaded
+            .objects
+            .iter()
+            .find(|(_, object)| object.as_dict().is_ok_and(|dict| dict.has(b"Linearized")))
+            .unwrap();
+        let linearization = linearization.as_dict().unwrap();
+        let number = |key: &[u8]| linearization.get(key).and_then(Object::as_i64).unwrap();
+
+        // The first object in the file, right after the header
+        let text = String::from_utf8_lossy(&bytes);
+        let first = text.find(" 0 obj").unwrap();
+        assert!(text[..first].ends_with(&format!("\n{}", id.0)));
+        assert_eq!(number(b"L"), bytes.len() as i64);
+        assert_eq!(number(b"O"), loaded.get_pages()[&1].0 as i64);
+        assert_eq!(number(b"N"), 5);
+        let end_of_first_page = number(b"E") as usize;
+        assert!(end_of_first_page < bytes.len());
+        // The main cross-reference table, at the white-space before the entry of object 0
+        assert!(bytes[number(b"T") as usize..].starts_with(b"\n0000000000 65535 f"));
+        // The hint stream
+        let hint = linearization.get(b"H").and_then(Object::as_array).unwrap();
+        let (offset, length) = (hint[0].as_i64().unwrap() as usize, hint[1].as_i64().unwrap() as usize);
+        let hint_stream = String::from_utf8_lossy(&bytes[offset..offset + length]);
+        assert!(hint_stream.contains(" 0 obj") && hint_stream.ends_with("endstream\nendobj\n"), "{}", hint_stream);
+        assert!(offset + length <= end_of_first_page);
+    }
+}
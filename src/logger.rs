@@ -0,0 +1,156 @@
+use std::{
+    fmt::Write as _,
+    io::{self, IsTerminal, Write},
+    sync::{Mutex, OnceLock},
+};
+
+use clap::ValueEnum;
+use log::{
+    kv::{Key, Value, VisitSource},
+    Level, LevelFilter, Log, Metadata, Record,
+};
+use merge_pdf::messages::tr;
+
+static LOGGER: OnceLock<Logger> = OnceLock::new();
+
+/// Warnings logged so far, for `--report`, see `warnings`.
+static WARNINGS: Mutex<Vec<String>> = Mutex::new(Vec::new());
+
+/// Prints what the merge logs, errors and warnings marked as such, as one line per message (or
+/// more when the message has them) that a batch job can keep.
+struct Logger {
+    level: LevelFilter,
+    /// Print on stderr, stdout being taken by a report
+    stderr: bool,
+    color: bool,
+}
+
+/// When to color what is logged, see `--color`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, ValueEnum)]
+pub enum Color {
+    /// When printing on a terminal and `NO_COLOR` is not set
+    #[default]
+    Auto,
+    Always,
+    Never,
+}
+
+/// The input, page and object a message is about, as the merge logs them with it, like
+/// `log::warn!(path:% = path.display(), page = 3; "...")`.
+#[derive(Default)]
+struct Context {
+    path: Option<String>,
+    page: Option<String>,
+    pages: Option<String>,
+    object: Option<String>,
+}
+
+impl<'kvs> VisitSource<'kvs> for Context {
+    fn visit_pair(&mut self, key: Key<'kvs>, value: Value<'kvs>) -> Result<(), log::kv::Error> {
+        match key.as_str() {
+            "path" => self.path = Some(value.to_string()),
+            "page" => self.page = Some(value.to_string()),
+            "pages" => self.pages = Some(value.to_string()),
+            "object" => self.object = Some(value.to_string()),
+            _ => {}
+        }
+        Ok(())
+    }
+}
+
+impl Context {
+    /// `message` after what it is about, like `a.pdf, page 3: message`, the input in bold when
+    /// `color`.
+    fn describe(&self, message: &str, color: bool) -> String {
+        let mut line = String::new();
+        if let Some(path) = &self.path {
+            line += &paint(path, "1", color);
+        }
+        for (what, value) in [("page", &self.page), ("pages", &self.pages), ("object", &self.object)] {
+            if let Some(value) = value {
+                let _ = write!(line, "{}{} {}", if line.is_empty() { "" } else { ", " }, what, value);
+            }
+        }
+        if line.is_empty() {
+            return message.to_string();
+        }
+        format!("{}: {}", line, message)
+    }
+}
+
+/// `text` in the SGR `style`, e.g. 1 for bold, when `color`.
+fn paint(text: &str, style: &str, color: bool) -> String {
+    match color {
+        true => format!("\x1b[{}m{}\x1b[0m", style, text),
+        false => text.to_string(),
+    }
+}
+
+impl Logger {
+    fn prints(&self, metadata: &Metadata) -> bool {
+        // The PDF parser underneath logs every object it cannot read, too much but for -vv
+        metadata.level() <= self.level && (metadata.target().starts_with("merge_pdf") || self.level == LevelFilter::Trace)
+    }
+}
+
+impl Log for Logger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        self.prints(metadata) || (metadata.level() == Level::Warn && metadata.target().starts_with("merge_pdf"))
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+        let mut context = Context::default();
+        let _ = record.key_values().visit(&mut context);
+        let message = record.args().to_string();
+        if record.level() == Level::Warn {
+            WARNINGS.lock().unwrap_or_else(|e| e.into_inner()).push(context.describe(&message, false));
+        }
+        if !self.prints(record.metadata()) {
+            return;
+        }
+        crate::progress::clear_line();
+        let message = context.describe(&message, self.color);
+        let line = match record.level() {
+            Level::Error => format!("{} {}", paint(&tr("ERROR:", &[]), "1;31", self.color), message),
+            Level::Warn => format!("{} {}", paint(&tr("WARNING:", &[]), "1;33", self.color), message),
+            Level::Info => message,
+            // Where the message comes from helps the most when it is a detail of the merge
+            Level::Debug | Level::Trace => format!("{} {}", paint(&format!("[{}]", record.target()), "2", self.color), message),
+        };
+        let _ = if self.stderr { writeln!(io::stderr().lock(), "{}", line) } else { writeln!(io::stdout().lock(), "{}", line) };
+    }
+
+    fn flush(&self) {
+        let _ = io::stdout().flush();
+    }
+}
+
+/// Log through `Logger` from now on, on stderr when `stderr` else on stdout: only errors when
+/// `quiet`, else what the merge did, with every input loaded and what it contributed at `verbose`
+/// 1, and at 2 or more with what the libraries underneath log as well. Errors and warnings are
+/// colored, and the inputs they are about are in bold, as `color` says.
+pub fn init(verbose: u8, quiet: bool, stderr: bool, color: Color) {
+    let level = match (quiet, verbose) {
+        (true, _) => LevelFilter::Error,
+        (false, 0) => LevelFilter::Info,
+        (false, 1) => LevelFilter::Debug,
+        (false, _) => LevelFilter::Trace,
+    };
+    let color = match color {
+        Color::Auto => std::env::var_os("NO_COLOR").is_none_or(|value| value.is_empty()) && if stderr { io::stderr().is_terminal() } else { io::stdout().is_terminal() },
+        Color::Always => true,
+        Color::Never => false,
+    };
+    if log::set_logger(LOGGER.get_or_init(|| Logger { level, stderr, color })).is_ok() {
+        // Warnings are kept even when not printed
+        log::set_max_level(level.max(LevelFilter::Warn));
+    }
+}
+
+/// The warnings logged so far, printed or not.
+pub fn warnings() -> Vec<String> {
+    WARNINGS.lock().unwrap_or_else(|e| e.into_inner()).clone()
+}
@@ -1,281 +1,2132 @@
-use std::{collections::BTreeMap, path::PathBuf};
+use std::{
+    collections::HashMap,
+    io::Write,
+    path::{Path, PathBuf},
+    process::ExitCode,
+    time::Duration,
+};
 
-use clap::Parser;
-use lopdf::{Bookmark, Document, Object, ObjectId};
+use clap::{CommandFactory, Parser, Subcommand, ValueEnum};
+use lopdf::Document;
+use merge_pdf::{
+    append, burst, cache::Cache, cover, diff, dir, encrypt, flatten, glob, info, interleave,
+    input::{self, InputSpec, PageRanges},
+    manifest, messages::{self, tr}, metadata, optimize, outline, outline_json, output, pages, passwords::PasswordList, pdfa, preflight, regex, remote, repair, report, sha256, sign, signatures, sort, stamp, stats::Stats, tempdir, view, zip, Input, MergeOptions, Merged, Progress,
+};
+
+mod config;
+mod confirm;
+mod interactive;
+mod interrupt;
+mod logger;
+mod progress;
+mod toml;
+mod watch;
+
+/// Exit codes, see `merge_pdf::Error::exit_code`
+const EXIT_CODES: &str = "Exit codes:
+  0   success
+  1   any other error
+  2   wrong usage: options that don't go together or point past the inputs
+  3   an input does not exist or cannot be read
+  4   an input is encrypted and no password given opens it
+  5   an input is not a PDF that can be read
+  6   no input has any (selected) pages
+  7   the root input has no Catalog or Pages root
+  8   the output cannot be written
+  9   --skip-broken left inputs out
+  10  the merge was cancelled
+  11  diff found the documents differ
+  12  another merge_pdf is writing the output
+  13  --strict found pages that differ from the rest
+  130 the merge was interrupted, by Ctrl-C say";
 
 #[derive(Debug, Parser)]
-struct Args {
+#[command(after_help = EXIT_CODES)]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+
+    /// Also log every input as it is loaded and what it contributed; twice for everything
+    #[arg(short, long, global = true, action = clap::ArgAction::Count)]
+    verbose: u8,
+
+    /// Log errors only
+    #[arg(short, long, global = true, conflicts_with = "verbose")]
+    quiet: bool,
+
+    /// Color errors and warnings, and put the inputs they are about in bold
+    #[arg(long, global = true, value_enum, value_name = "WHEN", default_value_t)]
+    color: logger::Color,
+
+    /// Language of errors, warnings and summaries, by default that of the locale:
+    /// `MERGE_PDF_LANG`, else `LC_ALL`, `LC_MESSAGES` or `LANG`
+    #[arg(long, global = true, value_enum)]
+    lang: Option<messages::Lang>,
+}
+
+#[derive(Debug, Subcommand)]
+enum Command {
+    /// Merge PDFs into one, with a bookmark per input
+    Merge(Box<MergeArgs>),
+    /// Split one PDF into several, at its top-level bookmarks, at given pages or every N pages
+    Split(SplitArgs),
+    /// Copy some pages of a PDF into a new file
+    Extract(ExtractArgs),
+    /// Show version, page count, page sizes, encryption, outline, forms, annotations and document
+    /// info of PDFs
+    Info(InfoArgs),
+    /// List the pages of a PDF with their sizes, rotation, fonts and images
+    Pages(PagesArgs),
+    /// Compare two PDFs, like the outputs of two runs of a merge, and list how they differ
+    Diff(DiffArgs),
+    /// Load a damaged PDF leniently, rebuild its page tree, drop what nothing refers to and save it
+    Repair(RepairArgs),
+    /// Merge the PDFs a scanner drops into a directory whenever a batch of them is complete
+    Watch(WatchArgs),
+    /// Merge PDFs sent over HTTP: POST them to /merge and get the merged PDF back
+    Serve(ServeArgs),
+    /// Print a script completing the commands and flags of merge_pdf in a shell, e.g.
+    /// `merge_pdf completions bash > /etc/bash_completion.d/merge_pdf`
+    Completions(CompletionsArgs),
+}
+
+#[derive(Debug, clap::Args)]
+struct MergeArgs {
+    /// Read defaults from this file instead of `~/.config/merge_pdf/config.toml`
+    #[arg(long, value_name = "FILE")]
+    config: Option<PathBuf>,
+
     #[arg(short, long)]
     predir: Option<PathBuf>,
 
+    /// Input PDFs, JPEG/PNG/TIFF images (one page per image), http(s) URLs (with the `net` feature), s3:// URLs (with the `s3` feature), directories (searched recursively for PDFs)
+    /// or glob patterns (`*`, `?`, `[...]`, `**`);
+    /// append `:1-3,7` to take only those pages, `:odd` or `:even` to take every other page, `:nocompress` to keep a file's objects out of the `--compress` pass,
+    /// `@rot=90` to rotate its pages clockwise by a multiple of 90 degrees, `@crop=L,B,R,T` to trim its margins like `--crop`,
+    /// `@pass=secret` to open it if it is encrypted, last, as everything after it is the password
+    #[arg(short, long, num_args = 1..)]
+    files: Vec<InputSpec>,
+
+    /// User or owner password of encrypted inputs that have no `@pass=` of their own; encrypted
+    /// inputs that open without a password need none
+    #[arg(long, value_name = "PASSWORD")]
+    password: Option<String>,
+
+    /// File of `PATTERN PASSWORD` lines, e.g. `statement-*.pdf s3cret`, whose passwords are
+    /// tried in order on the encrypted inputs whose file name matches, after their own
+    #[arg(long, value_name = "FILE")]
+    password_file: Option<PathBuf>,
+
+    /// Rotate every page clockwise by this multiple of 90 degrees, on top of any per-input rotation
+    #[arg(long, value_name = "DEGREES", value_parser = input::parse_rotation, allow_negative_numbers = true)]
+    rotate: Option<i64>,
+
+    /// Turn pages a quarter where needed so that every page reads in this orientation, applied
+    /// after `--rotate` and `@rot=N`
+    #[arg(long, value_enum, value_name = "ORIENTATION")]
+    auto_orient: Option<pages::Orientation>,
+
+    /// Take the pages of every input last to first, e.g. for scans fed face down; a manifest
+    /// entry can ask for this with `"reverse": true`
+    #[arg(long)]
+    reverse_pages: bool,
+
+    /// Scale and center every page to this size: `A4`, `Letter`, `A3`, `A5`, `Legal` or `WxH` in
+    /// points (`595x842`), millimetres (`210x297mm`) or inches (`8.5x11in`)
+    #[arg(long, value_name = "SIZE")]
+    page_size: Option<pages::PageSize>,
+
+    /// Trim these margins, in points from the left, bottom, right and top of every page's MediaBox,
+    /// off the visible page, e.g. `36,36,36,36`; an input's `@crop=` takes precedence
+    #[arg(long, value_name = "L,B,R,T")]
+    crop: Option<pages::Margins>,
+
+    /// Put 2 or 4 pages on every sheet of the output, sized like the first page, for handouts;
+    /// links inside the pages are lost
+    #[arg(long, value_name = "N", value_parser = parse_nup)]
+    nup: Option<usize>,
+
+    /// Lay the output out as a booklet: pages reordered in pairs on 2-up sheets that fold in
+    /// the middle when printed double-sided (flip on the short edge), padded with blank pages
+    /// to a multiple of four
+    #[arg(long, conflicts_with = "nup")]
+    booklet: bool,
+
+    /// Add a blank page after every input with an odd number of pages, so each input starts on
+    /// a new sheet when printed double-sided
+    #[arg(long)]
+    duplex_pad: bool,
+
+    /// Start the output with all pages of this PDF
+    #[arg(long, value_name = "FILE")]
+    cover: Option<PathBuf>,
+
+    /// Start the output with a generated cover showing this title, today's date and the number
+    /// of merged files; `{date}` and `{count}` in the title are filled in too
+    #[arg(long, value_name = "TITLE", conflicts_with = "cover")]
+    cover_title: Option<String>,
+
+    /// Leave out pages that show nothing (no visible text or drawing, only white images), as
+    /// scanners produce them; how many is reported per input
+    #[arg(long)]
+    drop_blank_pages: bool,
+
+    /// What to do with digitally signed inputs, whose signatures cannot survive the merge: merge
+    /// them with a warning, remove their signatures, or stop with an error
+    #[arg(long, value_enum, default_value_t)]
+    signatures: signatures::Signatures,
+
+    /// Take JavaScript, launch actions and embedded files out of the inputs, so untrusted inputs
+    /// bring nothing into the output that could run
+    #[arg(long)]
+    sanitize: bool,
+
+    /// Bake form fields into the pages as they look and drop the interactive forms, so the output
+    /// prints the same everywhere and can no longer be filled in
+    #[arg(long)]
+    flatten_forms: bool,
+
+    /// What to do with the inputs' annotations (comments, highlights, links, form fields):
+    /// carry them over, draw them into the pages as they look, or remove them
+    #[arg(long, value_enum, default_value_t)]
+    annotations: flatten::Annotations,
+
+    /// Black out text matching this regular expression, like `\d{3}-\d{2}-\d{4}`, wherever the
+    /// pages show it, taking the text itself out as well; may be given more than once. Text in
+    /// images, scans included, is not found
+    #[arg(long, value_name = "REGEX")]
+    redact_pattern: Vec<regex::Regex>,
+
+    /// Turn colors and color images gray, for print-ready monochrome bundles that are usually
+    /// smaller too
+    #[arg(long)]
+    grayscale: bool,
+
+    /// Give scanned pages, those showing images and no text, an invisible text layer recognized
+    /// by tesseract in these languages (`eng` by default, `eng+deu` for several), so the output
+    /// can be searched; needs the `ocr` feature
+    #[arg(long, value_name = "LANGUAGES", num_args = 0..=1, default_missing_value = "eng")]
+    ocr: Option<String>,
+
+    /// Downsample images shown at more than --max-dpi and write them again as JPEG or with Flate,
+    /// whichever is smaller, to shrink bundles of scans; images in formats it cannot read (1-bit
+    /// scans among them) are left as they are
+    #[arg(long)]
+    optimize_images: bool,
+
+    /// Resolution, in pixels per inch as placed on the page, above which images are downsampled
+    #[arg(long, value_name = "DPI", default_value_t = 150.0, requires = "optimize_images")]
+    max_dpi: f32,
+
+    /// Quality, from 1 to 100, of the images --optimize-images writes as JPEG
+    #[arg(long, value_name = "1-100", default_value_t = 80, value_parser = clap::value_parser!(u8).range(1..=100), requires = "optimize_images")]
+    jpeg_quality: u8,
+
+    /// Drop the page thumbnails the inputs carry, often large ones in scanned PDFs; viewers draw
+    /// their own from the pages
+    #[arg(long)]
+    strip_thumbnails: bool,
+
+    /// Leave out pages that are exact copies (same content, resources and size) of a page of an
+    /// earlier input, like a cover or legal page every fragment repeats; how many is reported per input
+    #[arg(long)]
+    dedupe_pages: bool,
+
+    /// Keep one copy of the fonts, color profiles, images and other streams that several inputs
+    /// embed alike, such as reports from the same generator, pointing every page at it
+    #[arg(long)]
+    dedupe_resources: bool,
+
+    /// Merge the subsets of the same TrueType font that several inputs embed, each with only the
+    /// glyphs it uses, into one font with the glyphs of all
+    #[arg(long)]
+    merge_font_subsets: bool,
+
+    /// Put a blank page (`blank`) or all pages of this PDF between every two inputs; bookmarks
+    /// still point at the inputs
+    #[arg(long, value_name = "blank|FILE")]
+    separator: Option<PathBuf>,
+
+    /// Put a double-sided document together from two single-sided scans: the pages of FRONT and
+    /// BACK alternate, as one input merged after all the others
+    #[arg(long, num_args = 2, value_names = ["FRONT", "BACK"])]
+    interleave: Vec<InputSpec>,
+
+    /// Take the pages of the `--interleave` BACK file last to first, as a turned over stack
+    /// scans them
+    #[arg(long, requires = "interleave")]
+    reverse_back: bool,
+
+    /// Read more input paths from this file (`-` for stdin), one per line or NUL-separated,
+    /// merged after `--files`
+    #[arg(long, value_name = "FILE")]
+    files_from: Option<PathBuf>,
+
+    /// Merge all PDFs inside these ZIP archives, after `--files` and `--files-from`
+    #[arg(long, value_name = "ZIP")]
+    from_zip: Vec<PathBuf>,
+
+    /// JSON manifest of inputs with per-file title, layer, pages, rotate, reverse, compress and bookmarks
+    /// settings, or an `outline` tree of sections holding them, merged after `--files`,
+    /// `--files-from` and `--from-zip`; or `groups` of them, each merged into its own `output`
+    #[arg(long, value_name = "FILE")]
+    manifest: Option<PathBuf>,
+
+    /// How many levels below a directory input to search, 1 for only its direct children
+    #[arg(long, value_name = "N")]
+    max_depth: Option<usize>,
+
+    /// Include hidden files and directories when searching a directory input
+    #[arg(long)]
+    hidden: bool,
+
+    /// Order of the files a glob pattern or directory expands to (`name` by default), and of the
+    /// PDFs in a `--from-zip` archive (archive order by default)
+    #[arg(long, value_enum)]
+    sort: Option<sort::SortOrder>,
+
+    /// Reverse the `--sort` order
+    #[arg(long)]
+    reverse: bool,
+
+    /// Check inputs for identical content (SHA-256) at different paths and warn or skip them
+    #[arg(long, value_enum, default_value_t)]
+    duplicates: input::Duplicates,
+
+    /// Where to save the merged PDF, `merged.pdf` in the predir by default. The config file's
+    /// `output` pattern may use `{first}`, `{count}` and `{date}` and is relative to the predir.
+    /// `-` writes it to stdout, and every message to stderr
     #[arg(short, long)]
+    output: Option<PathBuf>,
+
+    /// Pass every input through this command before merging, e.g. `qpdf --rotate=+90 {in} {out}`:
+    /// `{in}` is the input as a PDF and `{out}` where the command writes what takes its place
+    #[arg(long, value_name = "COMMAND")]
+    page_filter: Option<String>,
+
+    /// Embed the inputs byte for byte in a PDF portfolio with a cover sheet (--cover or a
+    /// generated one), in place of merging their pages; page options do not apply
+    #[arg(long, conflicts_with_all = ["append", "stream", "low_memory", "max_size", "nup", "booklet", "interactive", "confirm", "report", "dump_outline"])]
+    portfolio: bool,
+
+    /// Append the merged inputs to this PDF, a merged output from before, in place of writing a
+    /// new file: their pages and bookmarks go after its own in an incremental update, which
+    /// leaves the file as it was up to the end
+    #[arg(long, value_name = "FILE", conflicts_with_all = ["output", "stream", "low_memory", "max_size", "encrypt", "linearize", "object_streams", "dump_outline"])]
+    append: Option<PathBuf>,
+
+    /// What to do when the output file already exists (`fail` by default)
+    #[arg(long, value_enum)]
+    on_existing: Option<output::OnExisting>,
+
+    /// Make the output PDF/A-2b conformant (sRGB output intent, XMP metadata, document ID), the
+    /// default; or `check` which inputs are PDF/A and warn that the output will not be; or
+    /// `preserve` the PDF/A conformance of inputs that all have it
+    #[arg(long, value_enum, value_name = "MODE", num_args = 0..=1, default_missing_value = "convert")]
+    pdfa: Option<pdfa::Mode>,
+
+    /// Lowest PDF version the output declares, for systems that reject later ones; an input of
+    /// a later version still raises it to its own, with a warning
+    #[arg(long, value_name = "VERSION", value_parser = ["1.4", "1.7", "2.0"])]
+    pdf_version: Option<String>,
+
+    /// Number the pages of the output afresh instead of keeping the page labels of the inputs:
+    /// the cover, table of contents and first FRONT inputs i, ii, ..., the rest from 1
+    #[arg(long, value_name = "FRONT", num_args = 0..=1, default_missing_value = "0")]
+    relabel: Option<usize>,
+
+    /// Give the same output, byte for byte, every time the same inputs are merged with the same
+    /// options: no creation or modification dates, and a file ID hashed from the content
+    #[arg(long, conflicts_with_all = ["encrypt", "sign"])]
+    deterministic: bool,
+
+    /// Encrypt the output with AES-256, so it opens only with --user-pass, with --permissions, or
+    /// with --owner-pass, with every permission. Neither the outline nor a report, which holds the
+    /// bookmarks, can be written in the clear alongside
+    #[arg(long, requires = "owner_pass", conflicts_with_all = ["pdfa", "dump_outline", "report"])]
+    encrypt: bool,
+
+    /// Password that opens the encrypted output; without it, it opens without one but keeps to
+    /// --permissions
+    #[arg(long, value_name = "PASSWORD", requires = "encrypt")]
+    user_pass: Option<String>,
+
+    /// Password that opens the encrypted output with every permission
+    #[arg(long, value_name = "PASSWORD", requires = "encrypt")]
+    owner_pass: Option<String>,
+
+    /// What the user password allows, comma-separated; everything by default
+    #[arg(long, value_enum, value_delimiter = ',', value_name = "PERMISSIONS", requires = "encrypt")]
+    permissions: Option<Vec<encrypt::Permission>>,
+
+    /// Sign the output with the certificate and private key in this PKCS#12 file, a PAdES
+    /// signature over everything merged; needs the `crypto` feature and openssl
+    #[arg(long, value_name = "P12", conflicts_with = "encrypt")]
+    sign: Option<PathBuf>,
+
+    /// Password of the --sign file
+    #[arg(long, value_name = "PASSWORD", requires = "sign")]
+    sign_pass: Option<String>,
+
+    /// Title of the output, written to its document information (and XMP metadata, if any) in
+    /// place of whatever an input left there
+    #[arg(long, value_name = "TEXT")]
+    title: Option<String>,
+
+    /// Author of the output, see --title
+    #[arg(long, value_name = "TEXT")]
+    author: Option<String>,
+
+    /// Subject of the output, see --title
+    #[arg(long, value_name = "TEXT")]
+    subject: Option<String>,
+
+    /// Keywords of the output, see --title
+    #[arg(long, value_name = "TEXT")]
+    keywords: Option<String>,
+
+    /// Input whose document information and XMP metadata the output takes, as `first`, `last`, a
+    /// 1-based number in `--files` order or its path; --title and the like still go on top
+    #[arg(long, value_name = "N|first|last|PATH")]
+    metadata_from: Option<String>,
+
+    /// Remove what identifies the inputs' authors and history: their document information, XMP
+    /// metadata, private application data (PieceInfo) and the author and dates of annotations;
+    /// the output's metadata is only what --title and the like give
+    #[arg(long, conflicts_with = "metadata_from")]
+    strip_metadata: bool,
+
+    /// Input (1-based, in `--files` order) whose Catalog and page tree root become the output's.
+    /// Page attributes inherited from any input's page tree (Resources, MediaBox, CropBox, Rotate)
+    /// are copied into each page first, so the chosen root never changes how another input's pages look
+    #[arg(long, value_name = "N")]
+    root_from: Option<usize>,
+
+    /// Input whose viewer preferences, language and page layout the output takes, as `first`,
+    /// `last`, a 1-based number in `--files` order or its path; by default those of --root-from
+    #[arg(long, value_name = "N|first|last|PATH")]
+    view_from: Option<String>,
+
+    /// Panel the output opens with; by default the bookmarks, unless there are none
+    #[arg(long, value_enum)]
+    page_mode: Option<view::PageMode>,
+
+    /// How the output lays out its pages when opened, in place of what --view-from gives
+    #[arg(long, value_enum)]
+    page_layout: Option<view::PageLayout>,
+
+    /// Page of the output to open at, counted from 1 after any cover and table of contents, with
+    /// an optional zoom of `fit`, `width` or a percentage, e.g. `3,150`
+    #[arg(long, value_name = "PAGE[,ZOOM]")]
+    open_at: Option<view::OpenAt>,
+
+    /// Reorder, drop or rename the inputs on the terminal before merging
+    #[arg(long)]
+    interactive: bool,
+
+    /// List the inputs with their page counts and ask before merging
+    #[arg(long)]
+    confirm: bool,
+
+    /// Ask for confirmation whenever more than this many files are merged
+    #[arg(long, value_name = "N")]
+    confirm_threshold: Option<usize>,
+
+    /// Answer yes to the confirmation, required when not running on a terminal
+    #[arg(short, long)]
+    yes: bool,
+
+    /// Load the inputs and list what would be merged, in order, with their pages, which are
+    /// encrypted or cannot be loaded and about how large the output would be, then stop without
+    /// writing anything
+    #[arg(long, conflicts_with_all = ["interactive", "confirm", "stats", "report", "dump_outline"])]
+    dry_run: bool,
+
+    /// Which bookmarks the inputs get: `page` for "Page N" entries below a table of contents
+    /// (the default), `per-file` for one top-level bookmark per input named after the file,
+    /// `metadata` for the same named after the PDF's Info or XMP title, falling back to the file,
+    /// `sections` for per-file bookmarks nested in the manifest's outline sections (the default
+    /// when the manifest has an outline)
+    #[arg(long, value_enum)]
+    bookmarks: Option<outline::BookmarkMode>,
+
+    /// Don't generate any outline and leave the PageMode alone, same as `--bookmarks none`
+    #[arg(long, conflicts_with = "bookmarks")]
+    no_bookmarks: bool,
+
+    /// Leave out bookmarks more than N levels deep, their ancestors stand in for them
+    #[arg(long, value_name = "N", value_parser = clap::value_parser!(u32).range(1..))]
+    max_outline_depth: Option<u32>,
+
+    /// Put every bookmark on the top level, in the order they appear
+    #[arg(long)]
+    flatten_outline: bool,
+
+    /// Start the merged PDF with a table of contents page listing every input's title and first
+    /// page, each line a link; the pages after it are numbered from 1
+    #[arg(long)]
+    toc_page: bool,
+
+    /// Split the output into `merged-001.pdf`, `merged-002.pdf`, ... of at most this size, like
+    /// `25MB` or `10MiB`, breaking only between inputs; an input larger than that gets a file to itself
+    #[arg(long, value_name = "SIZE", value_parser = output::parse_size, conflicts_with_all = ["stream", "root_from", "dump_outline"])]
+    max_size: Option<u64>,
+
+    /// Write page numbers on every page of the output, `{page}` and `{total}` filled in, e.g.
+    /// `"{page} / {total}"`
+    #[arg(long, value_name = "TEMPLATE")]
+    stamp_pages: Option<String>,
+
+    /// Where the page numbers go
+    #[arg(long, value_enum, default_value_t, requires = "stamp_pages")]
+    stamp_position: stamp::Position,
+
+    /// Write a Bates identifier on every page of the output, numbered up printf-style, e.g.
+    /// `ACME-%06d` for ACME-001000, ACME-001001, ...
+    #[arg(long, value_name = "FORMAT")]
+    bates: Option<stamp::BatesFormat>,
+
+    /// Bates number of the first page
+    #[arg(long, value_name = "N", default_value_t = 1, requires = "bates")]
+    bates_start: u64,
+
+    /// Where the Bates identifiers go
+    #[arg(long, value_enum, default_value_t = stamp::Position::BottomRight, requires = "bates")]
+    bates_position: stamp::Position,
+
+    /// Draw this text across the middle of every page of the output, e.g. `CONFIDENTIAL`
+    #[arg(long, value_name = "TEXT")]
+    watermark: Option<String>,
+
+    /// Opacity of the watermark, from 0 (invisible) to 1 (opaque)
+    #[arg(long, value_name = "0-1", default_value_t = 0.3, requires = "watermark")]
+    watermark_opacity: f32,
+
+    /// Counter-clockwise angle of the watermark in degrees, 45 for diagonal
+    #[arg(long, value_name = "DEGREES", default_value_t = 45.0, allow_negative_numbers = true, requires = "watermark")]
+    watermark_rotation: f32,
+
+    /// Color of the watermark as R,G,B from 0 to 255
+    #[arg(long, value_name = "R,G,B", default_value = "128,128,128", requires = "watermark")]
+    watermark_color: outline::BookmarkColor,
+
+    /// Size of the watermark text in points
+    #[arg(long, value_name = "POINTS", default_value_t = 60.0, requires = "watermark")]
+    watermark_font_size: f32,
+
+    /// Draw the watermark beneath the page content instead of over it
+    #[arg(long, requires = "watermark")]
+    watermark_under: bool,
+
+    /// Put the first page of this PDF, or this image, on every page of the output, e.g. an
+    /// "APPROVED" stamp; links on it are lost
+    #[arg(long, value_name = "FILE")]
+    stamp: Option<PathBuf>,
+
+    /// Pages of the output that get the stamp, like `1-3,7,10-`; all of them by default
+    #[arg(long, value_name = "PAGES", requires = "stamp")]
+    stamp_on: Option<PageRanges>,
+
+    /// Where the stamp goes
+    #[arg(long, value_enum, default_value_t = stamp::Position::Center, requires = "stamp")]
+    stamp_at: stamp::Position,
+
+    /// Factor on the stamp's own size
+    #[arg(long, value_name = "FACTOR", default_value_t = 1.0, requires = "stamp")]
+    stamp_scale: f32,
+
+    /// Put the first page of this PDF beneath every page of the output, scaled to fit it, like letterhead
+    #[arg(long, value_name = "FILE")]
+    background: Option<PathBuf>,
+
+    /// Put the first page of this PDF over every page of the output, scaled to fit it
+    #[arg(long, value_name = "FILE")]
+    foreground: Option<PathBuf>,
+
+    /// Write this text centered at the top of every page of the output; `{page}`, `{total}`,
+    /// `{date}` and `{file}`, the input the page came from, are filled in
+    #[arg(long, value_name = "TEMPLATE")]
+    header: Option<String>,
+
+    /// Write this text centered at the bottom of every page of the output, filled in like `--header`
+    #[arg(long, value_name = "TEMPLATE")]
+    footer: Option<String>,
+
+    /// Size of the page numbers, Bates identifiers, header and footer in points
+    #[arg(long, value_name = "POINTS", default_value_t = 10.0)]
+    stamp_font_size: f32,
+
+    /// Distance of the page numbers, Bates identifiers, header, footer and stamp from the edges of the page in points
+    #[arg(long, value_name = "POINTS", default_value_t = 24.0)]
+    stamp_margin: f32,
+
+    /// Save the outline of the merged PDF to this JSON file, to edit it for `--outline-from`
+    #[arg(long, value_name = "FILE", conflicts_with = "stream")]
+    dump_outline: Option<PathBuf>,
+
+    /// Use the outline in this JSON file (as `--dump-outline` writes it) instead of generating one
+    #[arg(long, value_name = "FILE", conflicts_with = "no_bookmarks")]
+    outline_from: Option<PathBuf>,
+
+    /// Title every input's bookmark from this template instead, e.g. `"{stem} (pp. {first}-{last})"`;
+    /// also `{file}`, `{index}` and `{title}` (the PDF's own title). Manifest titles still win
+    #[arg(long, value_name = "TEMPLATE")]
+    bookmark_template: Option<String>,
+
+    /// Color of each input's bookmark as `R,G,B` from 0 to 255 (black by default)
+    #[arg(long, value_name = "R,G,B")]
+    bookmark_color: Option<outline::BookmarkColor>,
+
+    /// How the bookmark of each input is drawn (`plain` by default)
+    #[arg(long, value_enum)]
+    bookmark_style: Option<outline::BookmarkStyle>,
+
+    /// Leave out inputs that cannot be loaded instead of aborting; the merge still exits with an
+    /// error when anything was left out
+    #[arg(long)]
+    skip_broken: bool,
+
+    /// Load inputs that are damaged or not quite standard, such as scanner and printer output
+    /// with a broken cross-reference table or bytes before the header, by finding their objects
+    /// anew; such inputs are named in a warning
+    #[arg(long)]
+    lenient: bool,
+
+    /// Only warn, instead of failing, when the merged document refers to objects it does not have
+    /// or its page tree is inconsistent
+    #[arg(long)]
+    no_verify: bool,
+
+    /// Pack the objects of the output into compressed object streams behind a cross-reference
+    /// stream, which makes documents of many small objects much smaller; needs PDF 1.5 output
+    #[arg(long)]
+    object_streams: bool,
+
+    /// Linearize the output ("fast web view"), so a viewer fetching it over HTTP shows the first
+    /// page before the rest has arrived; not with encryption or object streams
+    #[arg(long, conflicts_with = "object_streams")]
+    linearize: bool,
+
+    /// Write every input to the output as soon as it is loaded instead of holding all of them in
+    /// memory; inputs are not asked about, so the config file's confirmation threshold is not applied
+    #[arg(long, conflicts_with_all = ["interactive", "confirm", "pdfa", "nup", "booklet", "interleave", "stamp_pages", "bates", "watermark", "stamp", "header", "footer", "background", "foreground", "merge_font_subsets", "linearize"])]
+    stream: bool,
+
+    /// Merge in as little memory as possible: like --stream one input at a time, spilling the
+    /// objects to a scratch file in the temporary directory that becomes the output once its page
+    /// tree and cross-reference table are written, so a job that dies leaves no partial output
+    #[arg(long, conflicts_with_all = ["interactive", "confirm", "pdfa", "nup", "booklet", "interleave", "stamp_pages", "bates", "watermark", "stamp", "header", "footer", "background", "foreground", "merge_font_subsets", "linearize", "max_size", "dump_outline"])]
+    low_memory: bool,
+
+    /// Print how long every phase of the merge took (loading, collecting, renumbering, building
+    /// the outline, saving, ...) and how many pages and objects the output has, and its size
+    #[arg(long, conflicts_with_all = ["stream", "low_memory", "max_size"])]
+    stats: bool,
+
+    /// Write what the merge made for scripts to read: the output, its size and pages, every input
+    /// with the pages it became, the inputs skipped, the bookmarks and the warnings
+    #[arg(long, value_enum, value_name = "FORMAT", conflicts_with_all = ["stream", "low_memory", "max_size"])]
+    report: Option<report::Format>,
+
+    /// Write the --report to this file instead of stdout; the messages of the merge go to stdout
+    /// then, else to stderr
+    #[arg(long, value_name = "FILE", requires = "report")]
+    report_file: Option<PathBuf>,
+
+    /// Add the SHA-256 of every page's content to the --report, next to that of the output
+    #[arg(long, requires = "report", conflicts_with = "append")]
+    report_page_hashes: bool,
+
+    /// Write the SHA-256 of the output next to it, to OUTPUT.sha256, for `sha256sum -c` to check
+    #[arg(long, conflicts_with = "max_size")]
+    checksum: bool,
+
+    /// Write the files --max-size or the groups of a manifest make to this ZIP archive instead,
+    /// each under its own name
+    #[arg(long, value_name = "FILE", conflicts_with_all = ["stream", "low_memory", "append", "portfolio"])]
+    zip_output: Option<PathBuf>,
+
+    /// Wait this many seconds for another merge_pdf writing the same output, watch mode's say, to
+    /// finish, instead of failing at once
+    #[arg(long, value_name = "SECONDS", default_value_t = 0)]
+    lock_wait: u64,
+
+    /// Fail, instead of only warning, when pages differ in size, orientation or color model from
+    /// most pages of the merge, like one A3 landscape page among Letter ones
+    #[arg(long, conflicts_with_all = ["stream", "low_memory", "portfolio"])]
+    strict: bool,
+
+    /// Keep the merge within this much memory, like `2G` or `512MiB`: when the inputs look too
+    /// large for it, merge like --low-memory and leave out --dedupe-resources and --dedupe-pages
+    #[arg(long, value_name = "SIZE", value_parser = output::parse_size)]
+    max_memory: Option<u64>,
+
+    /// Keep the inputs parsed in this directory, keyed by their contents, so merging them again
+    /// only parses those that changed; encrypted inputs are not kept
+    #[arg(long, value_name = "DIR")]
+    cache: Option<PathBuf>,
+
+    /// Don't draw a progress bar on stderr while loading, merging and saving; it is only drawn
+    /// when stderr is a terminal
+    #[arg(long)]
+    no_progress: bool,
+
+    /// Compress the streams of the merged output
+    #[arg(long, overrides_with = "no_compress")]
+    compress: bool,
+
+    /// Don't compress, even when the config file asks for it
+    #[arg(long, overrides_with = "compress")]
+    no_compress: bool,
+
+    /// Decode the streams of the merged output, images aside, to read it in a text editor
+    #[arg(long, conflicts_with = "compress")]
+    decompress: bool,
+}
+
+#[derive(Debug, clap::Args)]
+struct SplitArgs {
+    file: PathBuf,
+
+    /// One file per top-level bookmark, named after it
+    #[arg(long, conflicts_with_all = ["every", "at"])]
+    by_bookmark: bool,
+
+    /// Pages per file
+    #[arg(long, value_name = "N", default_value_t = 1, value_parser = clap::value_parser!(u32).range(1..))]
+    every: u32,
+
+    /// Start a new file at each of these pages, e.g. `5,12,40` writes pages 1-4, 5-11, 12-39 and 40 to the end
+    #[arg(long, value_name = "PAGES", value_delimiter = ',', conflicts_with = "every", value_parser = clap::value_parser!(u32).range(1..))]
+    at: Vec<u32>,
+
+    /// Directory to write the parts into
+    #[arg(short, long, value_name = "DIR", default_value = ".")]
+    out_dir: PathBuf,
+
+    /// What to do when a part's file already exists
+    #[arg(long, value_enum, default_value_t)]
+    on_existing: output::OnExisting,
+
+    /// Wait this many seconds for another merge_pdf writing the same part, watch mode's say, to
+    /// finish, instead of failing at once
+    #[arg(long, value_name = "SECONDS", default_value_t = 0)]
+    lock_wait: u64,
+}
+
+#[derive(Debug, clap::Args)]
+struct ExtractArgs {
+    file: PathBuf,
+
+    /// Pages to copy, like `1-3,7,10-`
+    #[arg(long)]
+    pages: PageRanges,
+
+    /// Where to save the extracted pages
+    #[arg(short, long, default_value = "extracted.pdf")]
+    output: PathBuf,
+
+    /// What to do when the output file already exists
+    #[arg(long, value_enum, default_value_t)]
+    on_existing: output::OnExisting,
+}
+
+#[derive(Debug, clap::Args)]
+struct InfoArgs {
+    #[arg(required = true)]
     files: Vec<PathBuf>,
 
+    /// Print a JSON array with an object per file in place of the summary for people
+    #[arg(long)]
+    json: bool,
+}
+
+#[derive(Debug, clap::Args)]
+struct PagesArgs {
+    file: PathBuf,
+
+    /// Password of the file if it is encrypted
+    #[arg(long, value_name = "PASSWORD")]
+    password: Option<String>,
+
+    /// Print a JSON array with an object per page in place of the listing for people
+    #[arg(long)]
+    json: bool,
+}
+
+#[derive(Debug, clap::Args)]
+struct DiffArgs {
+    first: PathBuf,
+    second: PathBuf,
+
+    /// Password of the files if they are encrypted
+    #[arg(long, value_name = "PASSWORD")]
+    password: Option<String>,
+}
+
+#[derive(Debug, clap::Args)]
+struct RepairArgs {
+    file: PathBuf,
+
+    /// Password of the file if it is encrypted; the repaired copy is saved decrypted
+    #[arg(long, value_name = "PASSWORD")]
+    password: Option<String>,
+
+    /// Where to save the repaired file
+    #[arg(short, long, default_value = "repaired.pdf")]
+    output: PathBuf,
+
+    /// What to do when the output file already exists
+    #[arg(long, value_enum, default_value_t)]
+    on_existing: output::OnExisting,
+}
+
+#[derive(Debug, clap::Args)]
+struct WatchArgs {
+    /// Directory to watch; only the PDFs directly inside it are merged
+    dir: PathBuf,
+
+    /// Where to save every merge, outside the watched directory itself
     #[arg(short, long)]
-    output: Option<PathBuf>,
+    output: PathBuf,
+
+    /// What to do when the output file already exists, e.g. from the batch before
+    #[arg(long, value_enum, default_value = "increment")]
+    on_existing: output::OnExisting,
+
+    /// Seconds the PDFs have to stay the same, none added, removed or growing, before they are merged
+    #[arg(long, value_name = "SECONDS", default_value = "5", value_parser = watch::parse_seconds)]
+    settle: Duration,
+
+    /// Seconds between two looks at the directory
+    #[arg(long, value_name = "SECONDS", default_value = "1", value_parser = watch::parse_seconds)]
+    interval: Duration,
+
+    /// Order of the inputs
+    #[arg(long, value_enum, default_value = "natural")]
+    sort: sort::SortOrder,
+
+    /// Move the inputs into this directory once merged, so the next batch starts empty
+    #[arg(long, value_name = "DIR")]
+    move_to: Option<PathBuf>,
+
+    /// Run this shell command after every merge, with the output as `$1` (and in `MERGE_PDF_OUTPUT`)
+    #[arg(long, value_name = "COMMAND")]
+    exec: Option<String>,
+
+    /// Compress the content streams of the output
+    #[arg(long)]
+    compress: bool,
+
+    /// Wait this many seconds for another merge_pdf writing the same output, a split's say, to
+    /// finish, instead of failing the batch at once
+    #[arg(long, value_name = "SECONDS", default_value_t = 10)]
+    lock_wait: u64,
 }
 
-fn main() -> lopdf::Result<()> {
-    let args = Args::parse();
-    if args.files.len() < 2 {
-        println!("ERROR: files must be more than 1");
-        return Err(lopdf::Error::Invalid("not enough files".to_string()));
+#[derive(Debug, clap::Args)]
+struct ServeArgs {
+    #[arg(long, default_value_t = 8080)]
+    port: u16,
+
+    /// Address to listen on; 0.0.0.0 for every network the machine is on
+    #[arg(long, default_value = "127.0.0.1")]
+    bind: String,
+
+    /// Also merge files on the server, named by their paths below this directory
+    #[arg(long, value_name = "DIR")]
+    root: Option<PathBuf>,
+
+    /// Largest request accepted, like 100MB
+    #[arg(long, value_name = "SIZE", default_value = "100MB", value_parser = output::parse_size)]
+    max_upload: u64,
+
+    /// Most connections answered at once, each holding up to --max-upload in memory; the
+    /// others wait their turn
+    #[arg(long, value_name = "N", default_value_t = 8, value_parser = clap::value_parser!(u64).range(1..))]
+    max_connections: u64,
+
+    /// Compress the content streams of the merged PDFs
+    #[arg(long)]
+    compress: bool,
+}
+
+#[derive(Debug, clap::Args)]
+struct CompletionsArgs {
+    #[arg(value_enum)]
+    shell: clap_complete::Shell,
+}
+
+fn main() -> ExitCode {
+    let cli = Cli::parse();
+    // A report or the merged PDF on stdout takes it for itself
+    let stdout_taken = matches!(&cli.command, Command::Merge(args) if (args.report.is_some() && args.report_file.is_none()) || args.output.as_deref().is_some_and(output::is_stdout));
+    messages::set_lang(cli.lang.unwrap_or_else(messages::Lang::from_env));
+    logger::init(cli.verbose, cli.quiet, stdout_taken, cli.color);
+    let result = match cli.command {
+        Command::Merge(args) => merge(*args),
+        Command::Split(args) => split(args),
+        Command::Extract(args) => extract(args),
+        Command::Info(args) => info(args),
+        Command::Pages(args) => pages(args),
+        Command::Diff(args) => diff(args),
+        Command::Repair(args) => repair(args),
+        Command::Watch(args) => watch(args),
+        Command::Serve(args) => serve(args),
+        Command::Completions(args) => completions(args),
+    };
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            log::error!("{}", e);
+            ExitCode::from(e.exit_code())
+        }
+    }
+}
+
+fn split(args: SplitArgs) -> merge_pdf::Result<()> {
+    let doc = input::load(&args.file)?;
+    let interrupted = interrupt::install();
+    let to = burst::PartOutput {
+        source: &args.file,
+        out_dir: &args.out_dir,
+        on_existing: args.on_existing,
+        lock_wait: Duration::from_secs(args.lock_wait),
+        cancel: Some(&interrupted),
+    };
+    let written = if args.by_bookmark {
+        burst::burst_by_bookmark(&doc, &to)?
+    } else {
+        let stem = args.file.file_stem().map(|s| s.to_string_lossy().into_owned()).unwrap_or_default();
+        if args.at.is_empty() {
+            burst::burst_every(&doc, args.every, &stem, &to)?
+        } else {
+            burst::burst_at(&doc, &args.at, &stem, &to)?
+        }
+    };
+    for path in written {
+        println!("{}", path.display());
+    }
+    Ok(())
+}
+
+fn info(args: InfoArgs) -> merge_pdf::Result<()> {
+    let mut summaries = Vec::new();
+    for path in &args.files {
+        let summary = info::Summary::new(path, &input::load(path)?);
+        if args.json {
+            summaries.push(summary);
+        } else {
+            summary.print();
+        }
     }
-    let predir = args.predir.unwrap_or(PathBuf::from("."));
-    let docs = args
-        .files
+    if args.json {
+        println!("{}", info::to_json(&summaries));
+    }
+    Ok(())
+}
+
+fn pages(args: PagesArgs) -> merge_pdf::Result<()> {
+    let doc = input::load_with_passwords(&args.file, &args.password.as_deref().into_iter().collect::<Vec<_>>())?;
+    let pages = info::page_summaries(&doc);
+    if args.json {
+        println!("{}", info::pages_to_json(&pages));
+        return Ok(());
+    }
+    println!("{}", args.file.display());
+    for page in &pages {
+        page.print();
+    }
+    Ok(())
+}
+
+fn diff(args: DiffArgs) -> merge_pdf::Result<()> {
+    let passwords = args.password.as_deref().into_iter().collect::<Vec<_>>();
+    let (first, second) = (input::load_with_passwords(&args.first, &passwords)?, input::load_with_passwords(&args.second, &passwords)?);
+    let differences = merge_pdf::diff::diff(&first, &second);
+    if differences.is_empty() {
+        println!("{}", tr("{} and {} are equivalent", &[&args.first.display(), &args.second.display()]));
+        return Ok(());
+    }
+    for difference in &differences {
+        println!("{}", difference);
+    }
+    Err(merge_pdf::Error::Different { differences: differences.len() })
+}
+
+fn extract(args: ExtractArgs) -> merge_pdf::Result<()> {
+    let doc = input::load(&args.file)?;
+    if !doc.get_pages().into_keys().any(|n| args.pages.contains(n)) {
+        return Err(merge_pdf::Error::Invalid(tr("{} has none of the requested pages", &[&args.file.display()])));
+    }
+    let mut part = burst::subset(&doc, |n| args.pages.contains(n));
+    let output = output::target_path(&args.output, args.on_existing)?;
+    let partial = output::Partial::new(&output);
+    part.save(partial.path()).map_err(|e| merge_pdf::Error::saving(&output, e))?;
+    partial.persist()?;
+    log::info!("{}", tr("Saved {}", &[&output.display()]));
+    Ok(())
+}
+
+fn watch(args: WatchArgs) -> merge_pdf::Result<()> {
+    let inside = |path: &Path| path.parent().map(|parent| if parent.as_os_str().is_empty() { Path::new(".") } else { parent }).and_then(|parent| parent.canonicalize().ok());
+    if inside(&args.output).is_some_and(|parent| args.dir.canonicalize().is_ok_and(|dir| dir == parent)) {
+        return Err(merge_pdf::Error::Usage(tr("the output cannot be in the watched directory, it would be merged with the next batch", &[])));
+    }
+    if let Some(move_to) = &args.move_to {
+        std::fs::create_dir_all(move_to).map_err(|e| merge_pdf::Error::SaveFailed(move_to.clone(), e))?;
+    }
+    let interrupted = interrupt::install();
+    let options = MergeOptions::new().compress(args.compress).cancel(interrupted.clone());
+    watch::run(&args.dir, args.sort, args.settle, args.interval, &interrupted, |inputs| {
+        log::info!("{}", tr("Merging {} input(s) from {}", &[&inputs.len(), &args.dir.display()]));
+        let mut document = merge_pdf::merge(inputs, &options)?;
+        let target = output::target_path(&args.output, args.on_existing)?;
+        let _lock = output::Lock::acquire(&target, Duration::from_secs(args.lock_wait), interrupt::check)?;
+        let partial = output::Partial::new(&target);
+        save_merged(&mut document, &partial, &options)?;
+        interrupt::check()?;
+        partial.persist()?;
+        log::info!("{}", tr("Saved {}", &[&target.display()]));
+        if let Some(move_to) = &args.move_to {
+            for input in inputs {
+                let moved = move_to.join(input.file_name().unwrap_or_default());
+                if let Err(e) = std::fs::rename(input, &moved) {
+                    log::warn!("{}", tr("{} could not be moved to {}: {}", &[&input.display(), &move_to.display(), &e]));
+                }
+            }
+        }
+        if let Some(command) = &args.exec {
+            run_hook(command, &target);
+        }
+        Ok(())
+    })
+}
+
+/// Run `command` with the shell, `output` given as its first argument and in `MERGE_PDF_OUTPUT`,
+/// and warn when it fails.
+fn run_hook(command: &str, output: &Path) {
+    #[cfg(unix)]
+    let mut shell = std::process::Command::new("sh");
+    #[cfg(unix)]
+    shell.arg("-c").arg(command).arg("merge_pdf").arg(output);
+    #[cfg(not(unix))]
+    let mut shell = std::process::Command::new("cmd");
+    #[cfg(not(unix))]
+    shell.arg("/C").arg(command);
+    match shell.env("MERGE_PDF_OUTPUT", output).status() {
+        Ok(status) if status.success() => {}
+        Ok(status) => log::warn!("{}", tr("{} failed: {}", &[&command, &status])),
+        Err(e) => log::warn!("{}", tr("{} could not be run: {}", &[&command, &e])),
+    }
+}
+
+fn serve(args: ServeArgs) -> merge_pdf::Result<()> {
+    let listener = std::net::TcpListener::bind((args.bind.as_str(), args.port))?;
+    let server = merge_pdf::serve::Server { root: args.root, max_upload: args.max_upload, max_connections: args.max_connections as usize, options: MergeOptions::new().compress(args.compress) };
+    server.run(listener)
+}
+
+fn completions(args: CompletionsArgs) -> merge_pdf::Result<()> {
+    // Written at once, a closed pipe is an error rather than a panic of the generator
+    let mut script = Vec::new();
+    clap_complete::generate(args.shell, &mut Cli::command(), "merge_pdf", &mut script);
+    std::io::stdout().write_all(&script)?;
+    Ok(())
+}
+
+fn repair(args: RepairArgs) -> merge_pdf::Result<()> {
+    let mut doc = input::load_with(&args.file, &args.password.as_deref().into_iter().collect::<Vec<_>>(), true)?;
+    let repaired = repair::repair(&mut doc)?;
+    if repaired.recovered > 0 {
+        log::info!("{}", tr("{}: {} page(s) missing from the page tree recovered", &[&args.file.display(), &repaired.recovered]));
+    }
+    if repaired.dropped > 0 {
+        log::info!("{}", tr("{}: {} object(s) nothing refers to dropped", &[&args.file.display(), &repaired.dropped]));
+    }
+    let output = output::target_path(&args.output, args.on_existing)?;
+    let partial = output::Partial::new(&output);
+    doc.save(partial.path()).map_err(|e| merge_pdf::Error::saving(&output, e))?;
+    partial.persist()?;
+    log::info!("{}", tr("Saved {} ({} pages)", &[&output.display(), &repaired.pages]));
+    Ok(())
+}
+
+fn merge(args: MergeArgs) -> merge_pdf::Result<()> {
+    let interrupted = interrupt::install();
+    // Command line flags win over the config file, which wins over the built-in defaults
+    let config = config::load(args.config.as_deref())?;
+    let predir = args.predir.or(config.predir).unwrap_or(PathBuf::from("."));
+    let sort_order = args.sort.or(config.sort);
+    let compress = args.compress || (!args.no_compress && !args.decompress && config.compress == Some(true));
+    let bookmarks = if args.no_bookmarks {
+        outline::BookmarkMode::None
+    } else {
+        args.bookmarks.or(config.bookmarks).unwrap_or_default()
+    };
+    let bookmark_color = args.bookmark_color.or(config.bookmark_color).unwrap_or_default();
+    let bookmark_style = args.bookmark_style.or(config.bookmark_style).unwrap_or_default();
+    let bookmark_template = args.bookmark_template.or(config.bookmark_template);
+
+    let manifest = match &args.manifest {
+        Some(path) => Some(manifest::read(path)?),
+        None => None,
+    };
+    // A manifest outline decides the bookmark tree unless --bookmarks asks for another one
+    let bookmarks = match (args.bookmarks, &manifest) {
+        (None, Some(manifest)) if manifest.outline && !args.no_bookmarks => outline::BookmarkMode::Sections,
+        _ => bookmarks,
+    };
+    // Outputs of the manifest's groups; every group is merged on its own, in place of `--output`
+    let groups = manifest.as_ref().map(|m| m.groups.clone()).unwrap_or_default();
+    if !groups.is_empty() {
+        let conflicting = [
+            (args.output.is_some(), "--output"),
+            (args.stream, "--stream"),
+            (args.low_memory, "--low-memory"),
+            (args.stats, "--stats"),
+            (args.report.is_some(), "--report"),
+            (args.append.is_some(), "--append"),
+            (args.root_from.is_some(), "--root-from"),
+            (args.metadata_from.is_some(), "--metadata-from"),
+            (args.view_from.is_some(), "--view-from"),
+            (args.dump_outline.is_some(), "--dump-outline"),
+            (args.max_size.is_some(), "--max-size"),
+            (args.portfolio, "--portfolio"),
+            (args.checksum, "--checksum"),
+        ];
+        if let Some((_, flag)) = conflicting.iter().find(|(given, _)| *given) {
+            return Err(merge_pdf::Error::Usage(tr("{} cannot be used with a manifest that has groups", &[&flag])));
+        }
+    }
+    if args.output.as_deref().is_some_and(output::is_stdout) {
+        let conflicting = [
+            (args.report.is_some() && args.report_file.is_none(), "--report without --report-file"),
+            (args.stats, "--stats"),
+            (args.interactive, "--interactive"),
+            (args.confirm, "--confirm"),
+            (args.dry_run, "--dry-run"),
+            (args.max_size.is_some(), "--max-size"),
+            (args.checksum, "--checksum"),
+            (args.zip_output.is_some(), "--zip-output"),
+        ];
+        if let Some((_, flag)) = conflicting.iter().find(|(given, _)| *given) {
+            return Err(merge_pdf::Error::Usage(tr("{} cannot be used with --output -, stdout takes the PDF", &[&flag])));
+        }
+    }
+    if args.zip_output.is_some() && args.max_size.is_none() && groups.is_empty() {
+        return Err(merge_pdf::Error::Usage(tr("--zip-output needs --max-size or a manifest that has groups", &[])));
+    }
+    let manifest_files = manifest.map(|m| m.files).unwrap_or_default();
+    let password_list = match &args.password_file {
+        Some(path) => PasswordList::read(path)?,
+        None => PasswordList::default(),
+    };
+    let listed_files = match &args.files_from {
+        Some(path) => input::read_list(path)?,
+        None => Vec::new(),
+    };
+    // Expand directories and glob patterns in place, every match inherits the options of its entry
+    let dir_options = dir::DirOptions { max_depth: args.max_depth, hidden: args.hidden };
+    let glob_order = sort_order.unwrap_or_default();
+    // Downloaded and extracted inputs live here until the merge is done
+    let mut scratch: Option<tempdir::TempDir> = None;
+    let mut zip_files = Vec::new();
+    for (n, archive) in args.from_zip.iter().enumerate() {
+        let dir = match &scratch {
+            Some(dir) => dir,
+            None => scratch.insert(tempdir::TempDir::new()?),
+        };
+        let mut extracted = zip::extract_pdfs(archive, &dir.path().join(format!("zip-{}", n)))?;
+        if extracted.is_empty() {
+            log::warn!("{}", tr("{} contains no PDFs", &[&archive.display()]));
+        }
+        sort_order.unwrap_or(sort::SortOrder::None).sort(&mut extracted, &predir, args.reverse);
+        zip_files.extend(extracted.into_iter().map(InputSpec::new));
+    }
+    let mut files = Vec::new();
+    let specs = args.files.into_iter().chain(listed_files).chain(zip_files).chain(manifest_files);
+    for mut spec in specs {
+        if spec.password.is_none() {
+            spec.password = args.password.clone();
+        }
+        if remote::is_url(&spec.path) {
+            let dir = match &scratch {
+                Some(dir) => dir,
+                None => scratch.insert(tempdir::TempDir::new()?),
+            };
+            spec.path = remote::download(&spec.path.to_string_lossy(), dir.path(), files.len())?;
+            files.push(spec);
+            continue;
+        }
+        let matches = if input::resolve(&predir, &spec.path).is_dir() {
+            dir::collect(&spec.path, &predir, dir_options, glob_order, args.reverse)?
+        } else if glob::is_pattern(&spec.path) {
+            glob::expand(&spec.path, &predir, glob_order, args.reverse)?
+        } else {
+            files.push(spec);
+            continue;
+        };
+        if matches.is_empty() {
+            let nothing = std::io::Error::new(std::io::ErrorKind::NotFound, "no files match it");
+            return Err(merge_pdf::Error::MissingInput(input::resolve(&predir, &spec.path), nothing));
+        }
+        files.extend(matches.into_iter().map(|path| InputSpec { path, ..spec.clone() }));
+    }
+
+    let mut files = input::handle_duplicates(files, &predir, args.duplicates)?;
+    // Loaded like any other input, then combined into one in place of the front
+    let interleave_at = (!args.interleave.is_empty()).then_some(files.len());
+    files.extend(args.interleave);
+    if !groups.is_empty() && files.iter().any(|f| f.group.is_none()) {
+        return Err(merge_pdf::Error::Usage(tr("with manifest groups, every input must be listed in a group", &[])));
+    }
+    if let Some(degrees) = args.rotate {
+        for spec in &mut files {
+            spec.rotate += degrees;
+        }
+    }
+    if args.reverse_pages {
+        for spec in &mut files {
+            spec.reverse = true;
+        }
+    }
+
+    // A single input is fine when it gets rewritten on the way (pages picked, rotated, made PDF/A
+    // or compressed), merging it with nothing would just copy it
+    let transforms = args.pdfa == Some(pdfa::Mode::Convert)
+        || args.portfolio
+        || compress
+        || args.auto_orient.is_some()
+        || args.page_size.is_some()
+        || args.crop.is_some()
+        || args.nup.is_some()
+        || args.booklet
+        || args.duplex_pad
+        || args.toc_page
+        || args.cover.is_some()
+        || args.cover_title.is_some()
+        || args.drop_blank_pages
+        || args.dedupe_pages
+        || args.dedupe_resources
+        || args.merge_font_subsets
+        || args.signatures == signatures::Signatures::Strip
+        || args.sanitize
+        || args.flatten_forms
+        || args.annotations != flatten::Annotations::Keep
+        || !args.redact_pattern.is_empty()
+        || args.ocr.is_some()
+        || args.grayscale
+        || args.optimize_images
+        || args.strip_thumbnails
+        || args.strip_metadata
+        || args.encrypt
+        || args.sign.is_some()
+        || args.title.is_some()
+        || args.author.is_some()
+        || args.subject.is_some()
+        || args.keywords.is_some()
+        || args.stamp_pages.is_some()
+        || args.bates.is_some()
+        || args.watermark.is_some()
+        || args.stamp.is_some()
+        || args.header.is_some()
+        || args.background.is_some()
+        || args.foreground.is_some()
+        || args.footer.is_some()
+        || files.iter().any(|f| f.pages.is_some() || f.parity.is_some() || f.crop.is_some() || f.rotate != 0 || f.reverse);
+    if files.is_empty() || (files.len() < 2 && !transforms) {
+        return Err(merge_pdf::Error::Usage(tr("files must be more than 1, unless pages are selected, rotated, compressed or made PDF/A", &[])));
+    }
+    // Fully resolved input paths, used for loading and in every message about an input
+    let file_names = files.iter().map(|f| input::resolve(&predir, &f.path)).collect::<Vec<_>>();
+
+    // Store file in the predir unless told otherwise.
+    let requested = match (args.output, config.output) {
+        _ if args.append.is_some() => args.append.clone().unwrap_or_default(),
+        (Some(path), _) => path,
+        (None, Some(pattern)) => predir.join(output::expand_pattern(&pattern, &file_names[0], file_names.len())),
+        (None, None) => predir.join("merged.pdf"),
+    };
+    let on_existing = args.on_existing.or(config.on_existing).unwrap_or_default();
+    // Groups and parts check their own outputs when they are saved
+    let output = if groups.is_empty() && args.max_size.is_none() && args.append.is_none() {
+        output::target_path(&requested, on_existing)?
+    } else {
+        requested
+    };
+
+    if args.dry_run {
+        let target = if groups.is_empty() { output.display().to_string() } else { format!("the outputs of {} group(s)", groups.len()) };
+        return dry_run(&files, &file_names, &target, &password_list, args.lenient, args.skip_broken);
+    }
+
+    // Held until the merge is done, so two runs never write the same file at once
+    let locked = match &args.zip_output {
+        _ if output::is_stdout(&output) => Vec::new(),
+        Some(archive) => vec![archive.clone()],
+        None if groups.is_empty() => vec![output.clone()],
+        None => groups.iter().map(|group| input::resolve(&predir, group)).collect(),
+    };
+    let _locks = locked
+        .iter()
+        .map(|path| output::Lock::acquire(path, Duration::from_secs(args.lock_wait), interrupt::check))
+        .collect::<merge_pdf::Result<Vec<_>>>()?;
+
+    let cache = args.cache.clone().map(Cache::new);
+    let separator = match &args.separator {
+        Some(path) if path.as_os_str() == "blank" => Some(pages::Separator::Blank),
+        Some(path) => Some(pages::Separator::Document(Box::new(load_input(&input::resolve(&predir, path), args.password.as_deref(), &password_list, args.lenient)?))),
+        None => None,
+    };
+    let cover = match (&args.cover, args.cover_title) {
+        (Some(path), _) => Some(cover::Cover::Document(Box::new(load_input(&input::resolve(&predir, path), args.password.as_deref(), &password_list, args.lenient)?))),
+        (None, Some(title)) => Some(cover::Cover::Generated { title, date: output::today() }),
+        (None, None) => None,
+    };
+
+    let metadata = metadata::Metadata {
+        title: args.title.clone(),
+        author: args.author.clone(),
+        subject: args.subject.clone(),
+        keywords: args.keywords.clone(),
+    };
+
+    let encryption = args.encrypt.then(|| encrypt::Encryption {
+        user_password: args.user_pass.clone().unwrap_or_default(),
+        owner_password: args.owner_pass.clone().unwrap_or_default(),
+        permissions: args.permissions.clone().unwrap_or_else(|| encrypt::Permission::value_variants().to_vec()),
+    });
+    let signer = args.sign.clone().map(|certificate| sign::Signer { certificate, password: args.sign_pass.clone().unwrap_or_default() });
+
+    // Past --max-memory the merge goes one input at a time if it can, without the dedupe hashes
+    let (mut low_memory, mut dedupe_pages, mut dedupe_resources) = (args.low_memory, args.dedupe_pages, args.dedupe_resources);
+    if let Some(budget) = args.max_memory {
+        let needed = estimated_working_set(&file_names);
+        if needed > budget {
+            log::warn!("{}", tr("the merge is estimated to need {} bytes, more than the {} --max-memory allows", &[&needed, &budget]));
+            let whole_document = [
+                (!groups.is_empty(), "a manifest with groups"),
+                (args.interactive, "--interactive"),
+                (args.confirm, "--confirm"),
+                (args.pdfa.is_some(), "--pdfa"),
+                (args.nup.is_some(), "--nup"),
+                (args.booklet, "--booklet"),
+                (interleave_at.is_some(), "--interleave"),
+                (args.stamp_pages.is_some(), "--stamp-pages"),
+                (args.bates.is_some(), "--bates"),
+                (args.watermark.is_some(), "--watermark"),
+                (args.stamp.is_some(), "--stamp"),
+                (args.header.is_some() || args.footer.is_some(), "--header and --footer"),
+                (args.background.is_some(), "--background"),
+                (args.foreground.is_some(), "--foreground"),
+                (args.merge_font_subsets, "--merge-font-subsets"),
+                (args.linearize, "--linearize"),
+                (args.max_size.is_some(), "--max-size"),
+                (args.dump_outline.is_some(), "--dump-outline"),
+                (args.stats, "--stats"),
+                (args.report.is_some(), "--report"),
+                (args.append.is_some(), "--append"),
+                (args.strict, "--strict"),
+            ];
+            match whole_document.iter().find(|(given, _)| *given) {
+                Some((_, feature)) => log::warn!("{}", tr("{} needs the whole document in memory, the merge cannot go one input at a time", &[&feature])),
+                None if !args.stream && !low_memory => {
+                    log::warn!("{}", tr("merging one input at a time, as --low-memory does", &[]));
+                    low_memory = true;
+                }
+                None => {}
+            }
+            for (given, flag) in [(&mut dedupe_pages, "--dedupe-pages"), (&mut dedupe_resources, "--dedupe-resources")] {
+                if std::mem::take(given) {
+                    log::warn!("{}", tr("{} is left out, its hashes would not fit", &[&flag]));
+                }
+            }
+        }
+    }
+
+    if args.portfolio {
+        let mut options = MergeOptions::new().compress(compress).metadata(metadata.clone()).deterministic(args.deterministic);
+        options = options.object_streams(args.object_streams).linearize(args.linearize).strip_metadata(args.strip_metadata);
+        if let Some(size) = args.page_size {
+            options = options.page_size(size);
+        }
+        if let Some(cover) = &cover {
+            options = options.cover(cover.clone());
+        }
+        if let Some(encryption) = &encryption {
+            options = options.encryption(encryption.clone());
+        }
+        let mut document = merge_pdf::portfolio::portfolio(&file_names, &options)?;
+        let partial = output::Partial::new(&output);
+        save_merged(&mut document, &partial, &options)?;
+        sign_output(&partial, signer.as_ref())?;
+        interrupt::check()?;
+        let (_, digest) = saved_file(&partial, args.checksum)?;
+        partial.persist()?;
+        if !output::is_stdout(&output) {
+            log::info!("{}", tr("Saved a portfolio of {} file(s) to {}", &[&file_names.len(), &output.display()]));
+        }
+        if let Some(digest) = digest {
+            write_checksum(&output, &digest)?;
+        }
+        return Ok(());
+    }
+
+    if args.stream || low_memory {
+        let mut options = MergeOptions::new().bookmarks(bookmarks).bookmark_color(bookmark_color).bookmark_style(bookmark_style).compress(compress).metadata(metadata.clone());
+        options = options.cancel(interrupted.clone());
+        if let Some(template) = &bookmark_template {
+            options = options.bookmark_template(template);
+        }
+        if let Some(version) = &args.pdf_version {
+            options = options.pdf_version(version);
+        }
+        options = options.deterministic(args.deterministic);
+        options = options.no_verify(args.no_verify);
+        options = options.object_streams(args.object_streams);
+        options = options.linearize(args.linearize);
+        options = options.decompress(args.decompress);
+        if let Some(front) = args.relabel {
+            options = options.relabel(front);
+        }
+        if let Some(path) = &args.outline_from {
+            options = options.outline(outline_json::read(path)?);
+        }
+        if let Some(depth) = args.max_outline_depth {
+            options = options.max_outline_depth(depth as usize);
+        }
+        options = options
+            .flatten_outline(args.flatten_outline)
+            .toc_page(args.toc_page)
+            .duplex_pad(args.duplex_pad)
+            .drop_blank_pages(args.drop_blank_pages)
+            .dedupe_pages(dedupe_pages)
+            .dedupe_resources(dedupe_resources)
+            .merge_font_subsets(args.merge_font_subsets)
+            .signatures(args.signatures)
+            .sanitize(args.sanitize)
+            .flatten_forms(args.flatten_forms)
+            .annotations(args.annotations)
+            .grayscale(args.grayscale)
+            .strip_thumbnails(args.strip_thumbnails)
+            .strip_metadata(args.strip_metadata);
+        if let Some(encryption) = &encryption {
+            options = options.encryption(encryption.clone());
+        }
+        if args.optimize_images {
+            options = options.optimize_images(optimize::ImageOptimization { max_dpi: args.max_dpi, jpeg_quality: args.jpeg_quality });
+        }
+        for pattern in &args.redact_pattern {
+            options = options.redact(pattern.clone());
+        }
+        if let Some(languages) = &args.ocr {
+            options = options.ocr(languages.clone());
+        }
+        if let Some(separator) = &separator {
+            options = options.separator(separator.clone());
+        }
+        if let Some(cover) = &cover {
+            options = options.cover(cover.clone());
+        }
+        if let Some(orientation) = args.auto_orient {
+            options = options.auto_orient(orientation);
+        }
+        if let Some(size) = args.page_size {
+            options = options.page_size(size);
+        }
+        if let Some(margins) = args.crop {
+            options = options.crop(margins);
+        }
+        match args.root_from {
+            Some(n) if n == 0 || n > file_names.len() => {
+                return Err(merge_pdf::Error::Usage(tr("--root-from {} is out of range 1..={}", &[&n, &file_names.len()])))
+            }
+            Some(n) => options = options.root(n - 1),
+            None => {}
+        }
+        if let Some(value) = &args.metadata_from {
+            options = options.metadata_from(chosen_input("--metadata-from", value, &file_names, &predir)?);
+        }
+        if let Some(value) = &args.view_from {
+            options = options.view_from(chosen_input("--view-from", value, &file_names, &predir)?);
+        }
+        if let Some(mode) = args.page_mode {
+            options = options.page_mode(mode);
+        }
+        if let Some(layout) = args.page_layout {
+            options = options.page_layout(layout);
+        }
+        if let Some(open_at) = args.open_at {
+            options = options.open_at(open_at);
+        }
+        let specs = files
+            .iter()
+            .zip(&file_names)
+            .map(|(spec, path)| InputSpec { path: path.clone(), ..spec.clone() })
+            .collect::<Vec<_>>();
+        let mut broken: Vec<PathBuf> = Vec::new();
+        let load = |spec: &InputSpec| match load_input_cached(cache.as_ref(), &spec.path, spec.password.as_deref(), &password_list, args.lenient)
+            .and_then(|doc| filter_input(doc, &spec.path, args.page_filter.as_deref()))
+        {
+            Ok(doc) => Ok(Some(doc)),
+            Err(e) if args.skip_broken => {
+                log::error!("{}", tr("{}, skipping", &[&e]));
+                broken.push(spec.path.clone());
+                Ok(None)
+            }
+            Err(e) => Err(e),
+        };
+        let partial = output::Partial::new(&output);
+        let spill = if low_memory { Some(tempdir::TempDir::named("spill")?) } else { None };
+        let written = spill.as_ref().map_or_else(|| partial.path().to_path_buf(), |dir| dir.path().join("merged.pdf"));
+        let out = std::io::BufWriter::new(std::fs::File::create(&written).map_err(|e| merge_pdf::Error::SaveFailed(output.clone(), e))?);
+        let mut bar = progress::Bar::new(!args.no_progress);
+        let merged = merge_pdf::streaming::merge_streaming(&specs, &options, out, load, |progress| bar.progress(&progress));
+        bar.clear();
+        let compression = merged?;
+        if spill.is_some() {
+            move_file(&written, partial.path()).map_err(|e| merge_pdf::Error::SaveFailed(output.clone(), e))?;
+        }
+        sign_output(&partial, signer.as_ref())?;
+        interrupt::check()?;
+        let (_, digest) = saved_file(&partial, args.checksum)?;
+        partial.persist()?;
+        for (name, s) in file_names.iter().zip(compression) {
+            if !broken.contains(name) {
+                log::info!("{}", tr("{}: {} streams compressed, {} skipped", &[&name.display(), &s.compressed, &s.skipped]));
+            }
+        }
+        if !output::is_stdout(&output) {
+            log::info!("{}", tr("Saved {}", &[&output.display()]));
+        }
+        if let Some(digest) = digest {
+            write_checksum(&output, &digest)?;
+        }
+        return report_broken(&broken.iter().collect::<Vec<_>>(), file_names.len());
+    }
+
+    let mut stats = args.stats.then(Stats::new);
+    if let Some(stats) = &mut stats {
+        stats.start("load");
+    }
+    let mut bar = progress::Bar::new(!args.no_progress);
+    // An input listed several times (a cover sheet before every section, say) is parsed once and
+    // cloned; every copy is renumbered on its own in the merge, so their ids never collide.
+    let unique = file_names.iter().enumerate().filter(|&(source, path)| !file_names[..source].contains(path)).collect::<Vec<_>>();
+    let parsed = input::load_all(
+        &unique,
+        |&(source, path)| {
+            load_input_cached(cache.as_ref(), path, files[source].password.as_deref(), &password_list, args.lenient)
+                .and_then(|doc| filter_input(doc, path, args.page_filter.as_deref()))
+        },
+        |index, _| bar.progress(&Progress::Loaded { index, total: unique.len(), path: unique[index].1.clone() }),
+    );
+    bar.clear();
+    interrupt::check()?;
+    let mut loaded: HashMap<&PathBuf, merge_pdf::Result<Document>> = unique.iter().map(|&(_, path)| path).zip(parsed).collect();
+    // Loaded inputs with their position in the input list
+    let mut inputs = Vec::with_capacity(file_names.len());
+    // Inputs left out by --skip-broken
+    let mut broken: Vec<&PathBuf> = Vec::new();
+    for (source, path) in file_names.iter().enumerate() {
+        if broken.contains(&path) {
+            continue;
+        }
+        let doc = match loaded.remove(path).expect("every input is loaded") {
+            Ok(doc) => doc,
+            Err(e) if args.skip_broken => {
+                log::error!("{}", tr("{}, skipping", &[&e]));
+                broken.push(path);
+                continue;
+            }
+            Err(e) => return Err(e),
+        };
+        if file_names[source + 1..].contains(path) {
+            loaded.insert(path, Ok(doc.clone()));
+        }
+        let spec = InputSpec { path: path.clone(), ..files[source].clone() };
+        inputs.push((source, Input { spec, doc }));
+    }
+    if let Some(front) = interleave_at {
+        // Both sides come last, unless --skip-broken left one out
+        let back = inputs.pop();
+        match (inputs.pop(), back) {
+            (Some((source, front_input)), Some((_, back))) if source == front => {
+                inputs.push((front, interleave::interleave(front_input, back, args.reverse_back)?));
+            }
+            _ => return Err(merge_pdf::Error::Usage(tr("--interleave needs both of its files", &[]))),
+        }
+    }
+
+    let mut inputs = inputs
         .into_iter()
-        .map(|f| {
-            let path = predir.join(f);
-            Document::load(path.clone()).map_err(|e| lopdf::Error::Invalid(format!("{:?} is not found", path)))
+        .filter(|(_, input)| {
+            let has_pages = input.selected_pages() > 0;
+            if !has_pages {
+                log::warn!(path:% = input.spec.path.display(); "{}", tr("no (selected) pages, skipping", &[]));
+            }
+            has_pages
         })
-        .zip(1u32..)
-        .map(|(dr, i)| dr.map(|d| (i, d)))
-        .collect::<lopdf::Result<Vec<_>>>()?;
-
-    // We use this to keep track of the last Parent per layer depth.
-    let mut layer_parent: Vec<Option<u32>> = vec![None; docs.len()];
-
-    // This is the last layer ran.
-    let mut last_layer = 0;
-
-    // Define a starting max_id (will be used as start index for object_ids)
-    let mut max_id = 1;
-    let mut pagenum = 1;
-    // Collect all Documents Objects grouped by a map
-    let mut documents_pages = BTreeMap::new();
-    let mut documents_objects = BTreeMap::new();
-    let mut res = Document::new();
-
-    // Let's try to set these to be bigger to avoid multi allocations for faster handling of files.
-    // We are just saying each Document it about 1000 objects in size. can be adjusted for better speeds.
-    // This can only be used if you use nightly or the #![feature(extend_one)] is stabilized.
-    // documents_pages.extend_reserve(documents.len() * 1000);
-    // documents_objects.extend_reserve(documents.len() * 1000);
-
-    // Add a Table of Contents
-    // We set the object page to (0,0) which means it will point to the first object after it.
-    *layer_parent.get_mut(0).ok_or(lopdf::Error::Invalid("layer_parent is empty".to_string()))? = Some(res.add_bookmark(
-        Bookmark::new("Table of Contents".to_string(), [0.0, 0.0, 0.0], 0, (0, 0)),
-        None,
-    ));
-
-    // Can set bookmark formatting and color per report bookmark added.
-    // Formating is 1 for italic 2 for bold 3 for bold and italic
-    // Color is RGB 0.0..255.0
-    for (layer, mut doc) in docs {
-        let color = [0.0, 0.0, 0.0];
-        let format = 0;
-        let mut display = String::new();
-
-        doc.renumber_objects_with(max_id);
-
-        max_id = doc.max_id + 1;
-
-        let mut first_object = None;
-
-        // This is actually better than extend as we use fewer allocations and cloning then.
-        for (key, value) in doc.get_pages()
+        .collect::<Vec<_>>();
+    if inputs.is_empty() {
+        return Err(merge_pdf::Error::NoPages);
+    }
+    // Options that even out a way pages differ leave it unchecked; groups are checked apart
+    let checks = preflight::Checks { size: args.page_size.is_none(), orientation: args.auto_orient.is_none(), color: !args.grayscale };
+    let mut findings = Vec::new();
+    for group in 0..groups.len().max(1) {
+        let members = inputs.iter().map(|(_, input)| input).filter(|input| groups.is_empty() || input.spec.group == Some(group)).collect::<Vec<_>>();
+        findings.extend(preflight::geometry(&members, checks));
+    }
+    for finding in &findings {
+        match finding.pages.as_slice() {
+            [page] => log::warn!(path:% = finding.path.display(), page = page, check = finding.check; "{}", finding.message),
+            pages => log::warn!(path:% = finding.path.display(), pages:% = preflight::page_list(pages), check = finding.check; "{}", finding.message),
+        }
+    }
+    if args.strict && !findings.is_empty() {
+        return Err(merge_pdf::Error::Mismatched { findings: findings.len() });
+    }
+    if args.interactive {
+        let entries = inputs
+            .iter()
+            .enumerate()
+            .map(|(position, (_, input))| interactive::Entry {
+                source: position,
+                path: input.spec.path.clone(),
+                pages: input.selected_pages(),
+                title: input.spec.title.clone(),
+            })
+            .collect::<Vec<_>>();
+        let Some(arranged) = interactive::arrange(entries)? else {
+            return Err(merge_pdf::Error::Cancelled);
+        };
+        // Bookmark layers follow the new order, just like they follow the input order otherwise
+        let mut listed = inputs.into_iter().map(Some).collect::<Vec<_>>();
+        inputs = arranged
             .into_iter()
-            .map(|(_, object_id)| {
-                // We use this as the return object for Bookmarking to determine what it points to.
-                // We only want to do this for the first page though.
-                if first_object.is_none() {
-                    first_object = Some(object_id);
-                    display = format!("Page {}", pagenum);
-                    pagenum += 1;
-                }
+            .filter_map(|entry| {
+                let (source, mut input) = listed[entry.source].take()?;
+                input.spec.title = entry.title;
+                Some((source, input))
+            })
+            .collect();
+    }
+    if args.confirm || args.confirm_threshold.or(config.confirm_threshold).is_some_and(|n| inputs.len() > n) {
+        let listing = inputs.iter().map(|(_, input)| (input.spec.path.clone(), input.selected_pages())).collect::<Vec<_>>();
+        if !confirm::confirm(&listing, args.yes)? {
+            return Err(merge_pdf::Error::Cancelled);
+        }
+    }
 
-                (object_id, doc.get_object(object_id).map(|obj| obj.to_owned()))
-            }) {
-            documents_pages.insert(key, value?);
-        }
-
-        documents_objects.extend(doc.objects);
-
-        // Let's shadow our pointer back if nothing then set to (0,0) tto point to the next page
-        let object = first_object.unwrap_or((0, 0));
-
-        // This will use the layering to implement children under Parents in the bookmarks
-        // Example as we are generating it here.
-        // Table of Contents
-        // - Page 1
-        // -- Page 2
-        // -- Page 3
-        // --- Page 4
-
-        match layer {
-            0 => {
-                *layer_parent.get_mut(0).ok_or(lopdf::Error::Invalid("layer_parent is empty".to_string()))? =
-                    Some(res.add_bookmark(Bookmark::new(display, color, format, object), None));
-                last_layer = 0;
-            },
-            1 => {
-                let parent = *layer_parent.get(0).ok_or(lopdf::Error::Invalid("layer_parent is empty".to_string()))?;
-                *layer_parent.get_mut(1).ok_or(lopdf::Error::Invalid("layer_parent[1] is out of index".to_string()))? = Some(res.add_bookmark(
-                    Bookmark::new(display, color, format, object),
-                    parent,
-                ));
-                last_layer = 1;
-            },
-            l if l <= last_layer || l - 1 == last_layer => {
-                let parent = *layer_parent.get(l as usize -1).ok_or(lopdf::Error::Invalid("layer_parent is empty".to_string()))?;
-                *layer_parent.get_mut(l as usize - 1).ok_or(lopdf::Error::Invalid(format!("layer_parent[{}] is out of index", l)))? = Some(res.add_bookmark(
-                    Bookmark::new(display, color, format, object),
-                    parent,
-                ));
-                last_layer = l;
-            },
-            _ if last_layer > 0 => {
-                let parent = *layer_parent.get(last_layer as usize -1).ok_or(lopdf::Error::Invalid(format!("layer_parent[{}] is out of index", last_layer-1)))?;
-                *layer_parent.get_mut(last_layer as usize).ok_or(lopdf::Error::Invalid(format!("layer_parent[{}] is out of index", last_layer)))? = Some(res.add_bookmark(
-                    Bookmark::new(display, color, format, object),
-                    parent,
-                ));
-            },
-            _ => {
-                let parent = *layer_parent.get(0).ok_or(lopdf::Error::Invalid(format!("layer_parent[{}] is out of index", 0)))?;
-                *layer_parent.get_mut(1).ok_or(lopdf::Error::Invalid(format!("layer_parent[{}] is out of index", 1)))? = Some(res.add_bookmark(
-                    Bookmark::new(display, color, format, object),
-                    parent,
-                ));
-                last_layer = 1;
-            },
-        }
-    }
-
-    // Catalog and Pages are mandatory
-    let mut catalog_object: Option<(ObjectId, Object)> = None;
-    let mut pages_object: Option<(ObjectId, Object)> = None;
-
-    // Process all objects except "Page" type
-    for (object_id, object) in documents_objects.into_iter() {
-        // We have to ignore "Page" (as are processed later), "Outlines" and "Outline" objects
-        // All other objects should be collected and inserted into the main Document
-        match object.type_name().unwrap_or("") {
-            "Catalog" => {
-                // Collect a first "Catalog" object and use it for the future "Pages"
-                catalog_object = Some((
-                    if let Some((id, _)) = catalog_object {
-                        id
-                    } else {
-                        object_id
-                    },
-                    object,
-                ));
+    let mut options = MergeOptions::new().bookmarks(bookmarks).bookmark_color(bookmark_color).bookmark_style(bookmark_style).compress(compress).metadata(metadata);
+    options = options.cancel(interrupted);
+    if let Some(mode) = args.pdfa {
+        options = options.pdfa_mode(mode);
+    }
+    if let Some(version) = args.pdf_version {
+        options = options.pdf_version(version);
+    }
+    options = options.deterministic(args.deterministic);
+    options = options.no_verify(args.no_verify);
+    options = options.object_streams(args.object_streams);
+    options = options.linearize(args.linearize);
+    options = options.decompress(args.decompress);
+    if let Some(front) = args.relabel {
+        options = options.relabel(front);
+    }
+    if let Some(template) = bookmark_template {
+        options = options.bookmark_template(template);
+    }
+    if let Some(path) = &args.outline_from {
+        options = options.outline(outline_json::read(path)?);
+    }
+    if let Some(depth) = args.max_outline_depth {
+        options = options.max_outline_depth(depth as usize);
+    }
+    options = options.flatten_outline(args.flatten_outline).toc_page(args.toc_page);
+    if let Some(orientation) = args.auto_orient {
+        options = options.auto_orient(orientation);
+    }
+    if let Some(size) = args.page_size {
+        options = options.page_size(size);
+    }
+    if let Some(margins) = args.crop {
+        options = options.crop(margins);
+    }
+    if let Some(per_sheet) = args.nup {
+        options = options.nup(per_sheet);
+    }
+    options = options
+        .booklet(args.booklet)
+        .duplex_pad(args.duplex_pad)
+        .drop_blank_pages(args.drop_blank_pages)
+        .dedupe_pages(dedupe_pages)
+        .dedupe_resources(dedupe_resources)
+        .merge_font_subsets(args.merge_font_subsets)
+        .signatures(args.signatures)
+        .sanitize(args.sanitize)
+        .flatten_forms(args.flatten_forms)
+        .annotations(args.annotations)
+        .grayscale(args.grayscale)
+        .strip_thumbnails(args.strip_thumbnails)
+        .strip_metadata(args.strip_metadata);
+    if let Some(encryption) = encryption {
+        options = options.encryption(encryption);
+    }
+    if args.optimize_images {
+        options = options.optimize_images(optimize::ImageOptimization { max_dpi: args.max_dpi, jpeg_quality: args.jpeg_quality });
+    }
+    for pattern in &args.redact_pattern {
+        options = options.redact(pattern.clone());
+    }
+    if let Some(languages) = &args.ocr {
+        options = options.ocr(languages.clone());
+    }
+    if let Some(separator) = separator {
+        options = options.separator(separator);
+    }
+    if let Some(cover) = cover {
+        options = options.cover(cover);
+    }
+    if let Some(text) = args.watermark {
+        options = options.watermark(stamp::Watermark {
+            text,
+            opacity: args.watermark_opacity,
+            rotation: args.watermark_rotation,
+            color: args.watermark_color,
+            font_size: args.watermark_font_size,
+            under: args.watermark_under,
+        });
+    }
+    if let Some(path) = &args.stamp {
+        options = options.overlay(stamp::Overlay {
+            pages: args.stamp_on,
+            position: args.stamp_at,
+            scale: args.stamp_scale,
+            margin: args.stamp_margin,
+            ..stamp::Overlay::new(load_input(&input::resolve(&predir, path), args.password.as_deref(), &password_list, args.lenient)?)
+        });
+    }
+    if let Some(path) = &args.background {
+        options = options.background(load_input(&input::resolve(&predir, path), args.password.as_deref(), &password_list, args.lenient)?);
+    }
+    if let Some(path) = &args.foreground {
+        options = options.foreground(load_input(&input::resolve(&predir, path), args.password.as_deref(), &password_list, args.lenient)?);
+    }
+    if args.header.is_some() || args.footer.is_some() {
+        options = options.header_footer(stamp::HeaderFooter {
+            header: args.header,
+            footer: args.footer,
+            date: output::today(),
+            font_size: args.stamp_font_size,
+            margin: args.stamp_margin,
+        });
+    }
+    if let Some(template) = args.stamp_pages {
+        options = options.page_numbers(stamp::PageNumbers {
+            template,
+            position: args.stamp_position,
+            font_size: args.stamp_font_size,
+            margin: args.stamp_margin,
+        });
+    }
+    if let Some(format) = args.bates {
+        options = options.bates(stamp::Bates {
+            format,
+            start: args.bates_start,
+            position: args.bates_position,
+            font_size: args.stamp_font_size,
+            margin: args.stamp_margin,
+        });
+    }
+    match args.root_from {
+        Some(n) if n == 0 || n > file_names.len() => {
+            return Err(merge_pdf::Error::Usage(tr("--root-from {} is out of range 1..={}", &[&n, &file_names.len()])))
+        }
+        Some(n) => match inputs.iter().position(|(source, _)| *source == n - 1) {
+            Some(position) => options = options.root(position),
+            None => {
+                return Err(merge_pdf::Error::Usage(tr("--root-from {}: {} was skipped", &[&n, &file_names[n - 1].display()])))
             }
-            "Pages" => {
-                // Collect and update a first "Pages" object and use it for the future "Catalog"
-                // We have also to merge all dictionaries of the old and the new "Pages" object
-                if let Ok(dictionary) = object.as_dict() {
-                    let mut dictionary = dictionary.clone();
-                    if let Some((_, ref object)) = pages_object {
-                        if let Ok(old_dictionary) = object.as_dict() {
-                            dictionary.extend(old_dictionary);
-                        }
-                    }
-
-                    pages_object = Some((
-                        if let Some((id, _)) = pages_object {
-                            id
-                        } else {
-                            object_id
-                        },
-                        Object::Dictionary(dictionary),
-                    ));
-                }
+        },
+        None => {}
+    }
+    if let Some(value) = &args.metadata_from {
+        let n = chosen_input("--metadata-from", value, &file_names, &predir)?;
+        match inputs.iter().position(|(source, _)| *source == n) {
+            Some(position) => options = options.metadata_from(position),
+            None => {
+                return Err(merge_pdf::Error::Usage(tr("--metadata-from {}: {} was skipped", &[&value, &file_names[n].display()])))
+            }
+        }
+    }
+    if let Some(value) = &args.view_from {
+        let n = chosen_input("--view-from", value, &file_names, &predir)?;
+        match inputs.iter().position(|(source, _)| *source == n) {
+            Some(position) => options = options.view_from(position),
+            None => {
+                return Err(merge_pdf::Error::Usage(tr("--view-from {}: {} was skipped", &[&value, &file_names[n].display()])))
             }
-            "Page" => {}     // Ignored, processed later and separately
-            "Outlines" => {} // Ignored, not supported yet
-            "Outline" => {}  // Ignored, not supported yet
-            _ => {
-                res.objects.insert(object_id, object);
+        }
+    }
+    if let Some(mode) = args.page_mode {
+        options = options.page_mode(mode);
+    }
+    if let Some(layout) = args.page_layout {
+        options = options.page_layout(layout);
+    }
+    if let Some(open_at) = args.open_at {
+        options = options.open_at(open_at);
+    }
+
+    if !groups.is_empty() {
+        let inputs = inputs.into_iter().map(|(_, input)| input).collect();
+        let scratch = args.zip_output.as_ref().map(|_| tempdir::TempDir::named("zip-output")).transpose()?;
+        let saved = merge_groups(inputs, &groups, &predir, on_existing, &options, signer.as_ref(), scratch.as_ref().map(tempdir::TempDir::path))?;
+        if let Some(archive) = &args.zip_output {
+            zip_outputs(archive, &saved, on_existing)?;
+        }
+        return report_broken(&broken, file_names.len());
+    }
+
+    if let Some(max_bytes) = args.max_size {
+        let inputs = inputs.into_iter().map(|(_, input)| input).collect();
+        let parts = merge_pdf::merge_in_parts(inputs, &options, max_bytes)?;
+        // Parts bound for the archive wait in a scratch directory, it is never read as a whole
+        let scratch = args.zip_output.as_ref().map(|_| tempdir::TempDir::named("zip-output")).transpose()?;
+        let mut saved = Vec::new();
+        for (n, bytes) in parts.iter().enumerate() {
+            let part = output::part_path(&output, n + 1);
+            let name = part.file_name().map(|name| name.to_string_lossy().into_owned()).unwrap_or_default();
+            let path = match &scratch {
+                Some(dir) => dir.path().join(&name),
+                None => output::target_path(&part, on_existing)?,
+            };
+            let partial = output::Partial::new(&path);
+            std::fs::write(partial.path(), bytes).map_err(|e| merge_pdf::Error::SaveFailed(path.clone(), e))?;
+            sign_output(&partial, signer.as_ref())?;
+            interrupt::check()?;
+            partial.persist()?;
+            if scratch.is_none() {
+                log::info!("{}", tr("Saved {} ({} bytes)", &[&path.display(), &bytes.len()]));
             }
+            saved.push((name, path));
+        }
+        if let Some(archive) = &args.zip_output {
+            zip_outputs(archive, &saved, on_existing)?;
         }
+        return report_broken(&broken, file_names.len());
     }
 
-    // If no "Pages" found abort
-    if pages_object.is_none() {
-        return Err(lopdf::Error::Invalid("Pages root not found.".to_string()));
+    let names = inputs.iter().map(|(_, input)| input.spec.path.clone()).collect::<Vec<_>>();
+    if let Some(stats) = &mut stats {
+        stats.start("collect");
+    }
+    let inputs = inputs.into_iter().map(|(_, input)| input).collect::<Vec<_>>();
+    bar.pages(inputs.iter().map(Input::selected_pages).collect());
+    let merged = merge_pdf::merge_inputs_with_progress(inputs, &options, |progress| {
+        if let Some(stats) = &mut stats {
+            stats.progress(&progress);
+        }
+        bar.progress(&progress);
+    });
+    bar.clear();
+    let Merged { document: mut res, compression, pages: input_pages } = merged?;
+    for (name, s) in names.iter().zip(compression) {
+        log::info!("{}", tr("{}: {} streams compressed, {} skipped", &[&name.display(), &s.compressed, &s.skipped]));
     }
 
-    // Iter over all "Page" and collect with the parent "Pages" created before
-    for (object_id, object) in documents_pages.iter() {
-        if let Ok(dictionary) = object.as_dict() {
-            let mut dictionary = dictionary.clone();
-            dictionary.set("Parent", pages_object.as_ref().unwrap().0);
+    // Counted before --append takes the document
+    let reported = args.report.map(|_| {
+        let hashes = args.report_page_hashes.then(|| {
+            res.get_pages().into_values().map(|id| diff::content_hash(&res, id).map(|hash| sha256::hex(&hash)).unwrap_or_default()).collect()
+        });
+        (res.get_pages().len(), outline::items(&res), hashes)
+    });
+    let hashed = args.checksum || reported.is_some();
 
-            res
-                .objects
-                .insert(*object_id, Object::Dictionary(dictionary));
+    // Save the merged PDF
+    if let Some(stats) = &mut stats {
+        stats.progress(&Progress::Saving { path: output.clone() });
+        stats.count(&res);
+    }
+    bar.progress(&Progress::Saving { path: output.clone() });
+    let partial = output::Partial::new(&output);
+    // Sized before it moves in place, stdout has no size to look up afterwards
+    let (saved, digest) = if args.append.is_some() {
+        let mut bytes = std::fs::read(&output).map_err(|e| merge_pdf::Error::MissingInput(output.clone(), e))?;
+        let pages = res.get_pages().len();
+        // --dump-outline cannot be given with --append, nothing needs the document after this
+        let update = append::update(&bytes, std::mem::take(&mut res))?;
+        // The file with its update replaces it as a whole, like any output
+        bytes.extend_from_slice(&update);
+        std::fs::write(partial.path(), bytes).map_err(|e| merge_pdf::Error::SaveFailed(output.clone(), e))?;
+        sign_output(&partial, signer.as_ref())?;
+        interrupt::check()?;
+        let saved = saved_file(&partial, hashed)?;
+        partial.persist()?;
+        bar.clear();
+        log::info!("{}", tr("Appended {} page(s) to {}", &[&pages, &output.display()]));
+        saved
+    } else {
+        save_merged(&mut res, &partial, &options)?;
+        sign_output(&partial, signer.as_ref())?;
+        interrupt::check()?;
+        let saved = saved_file(&partial, hashed)?;
+        partial.persist()?;
+        bar.clear();
+        if !output::is_stdout(&output) {
+            log::info!("{}", tr("Saved {}", &[&output.display()]));
         }
+        saved
+    };
+    if args.checksum {
+        write_checksum(&output, digest.as_deref().unwrap_or_default())?;
+    }
+    if let Some(stats) = &mut stats {
+        stats.progress(&Progress::Saved { bytes: saved });
+        stats.report();
     }
+    if let Some(path) = &args.dump_outline {
+        outline_json::write(path, &outline::items(&res))?;
+        log::info!("{}", tr("Saved the outline to {}", &[&path.display()]));
+    }
+    if let Some((pages, bookmarks, page_hashes)) = reported {
+        let report = report::Report {
+            bytes: saved,
+            sha256: digest.unwrap_or_default(),
+            pages,
+            page_hashes,
+            inputs: input_pages,
+            imposed: options.nup.is_some() || options.booklet,
+            skipped: broken.iter().map(|path| path.to_path_buf()).collect(),
+            bookmarks,
+            warnings: logger::warnings(),
+            output: output.clone(),
+        };
+        match &args.report_file {
+            Some(path) => std::fs::write(path, report.to_json())?,
+            None => print!("{}", report.to_json()),
+        }
+    }
+    report_broken(&broken, file_names.len())
+}
 
-    // If no "Catalog" found abort
-    if catalog_object.is_none() {
-        return Err(lopdf::Error::Invalid("Catalog root not found.".to_string()));
+/// Merge the inputs of every manifest group on its own and save it to the group's output,
+/// resolved against `predir`, or below `scratch` for --zip-output. Groups left without inputs
+/// are skipped with a warning. Returns the name of every file saved, as given, and its path.
+fn merge_groups(
+    inputs: Vec<Input>,
+    groups: &[PathBuf],
+    predir: &Path,
+    on_existing: output::OnExisting,
+    options: &MergeOptions,
+    signer: Option<&sign::Signer>,
+    scratch: Option<&Path>,
+) -> merge_pdf::Result<Vec<(String, PathBuf)>> {
+    let mut saved = Vec::new();
+    let mut grouped = groups.iter().map(|_| Vec::new()).collect::<Vec<_>>();
+    for input in inputs {
+        if let Some(group) = input.spec.group.and_then(|g| grouped.get_mut(g)) {
+            group.push(input);
+        }
+    }
+    for (group, output) in grouped.into_iter().zip(groups) {
+        if group.is_empty() {
+            log::warn!("{}", tr("no input of {} has any pages, skipping", &[&output.display()]));
+            continue;
+        }
+        let names = group.iter().map(|input| input.spec.path.clone()).collect::<Vec<_>>();
+        let Merged { document: mut res, compression, .. } = merge_pdf::merge_inputs(group, options)?;
+        for (name, s) in names.iter().zip(compression) {
+            log::info!("{}", tr("{}: {} streams compressed, {} skipped", &[&name.display(), &s.compressed, &s.skipped]));
+        }
+        // Entries of the archive keep the relative path the manifest gives
+        let name = output
+            .components()
+            .filter_map(|c| match c {
+                std::path::Component::Normal(part) => Some(part.to_string_lossy()),
+                _ => None,
+            })
+            .collect::<Vec<_>>()
+            .join("/");
+        let output = match scratch {
+            Some(dir) => {
+                let path = dir.join(&name);
+                if let Some(parent) = path.parent() {
+                    std::fs::create_dir_all(parent)?;
+                }
+                path
+            }
+            None => output::target_path(&input::resolve(predir, output), on_existing)?,
+        };
+        let partial = output::Partial::new(&output);
+        save_merged(&mut res, &partial, options)?;
+        sign_output(&partial, signer)?;
+        interrupt::check()?;
+        partial.persist()?;
+        if scratch.is_none() {
+            log::info!("{}", tr("Saved {}", &[&output.display()]));
+        }
+        saved.push((name, output));
     }
+    Ok(saved)
+}
 
-    let (catalog_id, catalog_object) = catalog_object.unwrap();
-    let (page_id, page_object) = pages_object.unwrap();
+/// Write the `files` saved for --zip-output, each under its name, to the archive at `archive`.
+fn zip_outputs(archive: &Path, files: &[(String, PathBuf)], on_existing: output::OnExisting) -> merge_pdf::Result<()> {
+    let archive = output::target_path(archive, on_existing)?;
+    let partial = output::Partial::new(&archive);
+    zip::write_archive(partial.path(), files).map_err(|e| merge_pdf::Error::saving(&archive, e))?;
+    interrupt::check()?;
+    partial.persist_unchecked()?;
+    log::info!("{}", tr("Saved {} files to {}", &[&files.len(), &archive.display()]));
+    Ok(())
+}
 
-    // Build a new "Pages" with updated fields
-    if let Ok(dictionary) = page_object.as_dict() {
-        let mut dictionary = dictionary.clone();
+/// Save the merged document to `partial`, see `merge_pdf::write_to`.
+fn save_merged(document: &mut Document, partial: &output::Partial, options: &MergeOptions) -> merge_pdf::Result<()> {
+    let failed = |e| merge_pdf::Error::SaveFailed(partial.target().to_path_buf(), e);
+    let out = std::io::BufWriter::new(std::fs::File::create(partial.path()).map_err(failed)?);
+    let out = merge_pdf::write_to(document, out, options).map_err(|e| merge_pdf::Error::saving(partial.target(), e))?;
+    out.into_inner().map_err(|e| failed(e.into_error()))?;
+    Ok(())
+}
 
-        // Set new pages count
-        dictionary.set("Count", documents_pages.len() as u32);
+/// The size of the output saved to `partial` and, if `hash`, its SHA-256 in hexadecimal, looked
+/// up before it moves in place.
+fn saved_file(partial: &output::Partial, hash: bool) -> merge_pdf::Result<(u64, Option<String>)> {
+    let bytes = std::fs::metadata(partial.path())?.len();
+    let digest = if hash { Some(sha256::hex(&sha256::file(partial.path())?)) } else { None };
+    Ok((bytes, digest))
+}
 
-        // Set new "Kids" list (collected from documents pages) for "Pages"
-        dictionary.set(
-            "Kids",
-            documents_pages
-                .into_iter()
-                .map(|(object_id, _)| Object::Reference(object_id))
-                .collect::<Vec<_>>(),
-        );
+/// Write `digest`, the SHA-256 of `output`, to `output` with `.sha256` added, the way
+/// `sha256sum` does, naming the output without its directory so it checks from there.
+fn write_checksum(output: &Path, digest: &str) -> merge_pdf::Result<()> {
+    let mut sidecar = output.as_os_str().to_owned();
+    sidecar.push(".sha256");
+    let sidecar = PathBuf::from(sidecar);
+    let name = output.file_name().map_or_else(|| output.display().to_string(), |name| name.to_string_lossy().into_owned());
+    std::fs::write(&sidecar, format!("{}  {}\n", digest, name)).map_err(|e| merge_pdf::Error::SaveFailed(sidecar.clone(), e))?;
+    log::info!("{}", tr("Saved the checksum to {}", &[&sidecar.display()]));
+    Ok(())
+}
 
-        res
-            .objects
-            .insert(page_id, Object::Dictionary(dictionary));
+/// Sign the output saved to `partial` if `--sign` was given; a failure leaves no output behind,
+/// unsigned where a signed one was asked for.
+fn sign_output(partial: &output::Partial, signer: Option<&sign::Signer>) -> merge_pdf::Result<()> {
+    if let Some(signer) = signer {
+        sign::sign(partial.path(), signer)?;
+        log::info!("{}", tr("Signed {}", &[&partial.target().display()]));
     }
+    Ok(())
+}
 
-    // Build a new "Catalog" with updated fields
-    if let Ok(dictionary) = catalog_object.as_dict() {
-        let mut dictionary = dictionary.clone();
-        dictionary.set("Pages", page_id);
-        dictionary.set("PageMode", "UseOutlines");
-        dictionary.remove(b"Outlines"); // Outlines not supported in merged PDFs
+/// List what merging `files`, resolved to `file_names`, into `target` would do, without writing
+/// anything, and fail when an input cannot be loaded unless `skip_broken`.
+fn dry_run(files: &[InputSpec], file_names: &[PathBuf], target: &str, password_list: &PasswordList, lenient: bool, skip_broken: bool) -> merge_pdf::Result<()> {
+    println!("{}", tr("Would merge {} input(s) into {}:", &[&files.len(), &target]));
+    let (mut pages, mut bytes, mut broken) = (0, 0, 0);
+    for (n, (spec, path)) in files.iter().zip(file_names).enumerate() {
+        let size = std::fs::metadata(path).map_or(0, |metadata| metadata.len());
+        let encrypted = if info::is_encrypted(path) { tr(", encrypted", &[]) } else { String::new() };
+        match load_input(path, spec.password.as_deref(), password_list, lenient) {
+            Ok(doc) => {
+                let input = Input { spec: InputSpec { path: path.clone(), ..spec.clone() }, doc };
+                let (selected, total) = (input.selected_pages(), input.doc.get_pages().len());
+                println!("{:>4}. {}", n + 1, tr("{} ({} of {} pages, {} bytes{})", &[&path.display(), &selected, &total, &size, &encrypted]));
+                pages += selected;
+                // Pages left out take their share of the file with them
+                bytes += size * selected as u64 / total.max(1) as u64;
+            }
+            Err(e) => {
+                println!("{:>4}. {}", n + 1, e);
+                broken += 1;
+            }
+        }
+    }
+    println!("{}", tr("{} page(s), about {} bytes; nothing was written", &[&pages, &bytes]));
+    if broken > 0 && !skip_broken {
+        return Err(merge_pdf::Error::Invalid(tr("{} of {} inputs cannot be loaded", &[&broken, &files.len()])));
+    }
+    Ok(())
+}
 
-        res
-            .objects
-            .insert(catalog_id, Object::Dictionary(dictionary));
+/// Fail the merge, after listing them, when `--skip-broken` left any inputs out.
+fn report_broken(broken: &[&PathBuf], total: usize) -> merge_pdf::Result<()> {
+    if !broken.is_empty() {
+        let paths = broken.iter().map(|path| format!("\n  {}", path.display())).collect::<String>();
+        log::warn!("{}", tr("{} input(s) could not be loaded and were left out:{}", &[&broken.len(), &paths]));
+        return Err(merge_pdf::Error::SkippedInputs { skipped: broken.len(), total });
     }
+    Ok(())
+}
 
-    res.trailer.set("Root", catalog_id);
+/// The input (0-based, in `--files` order) `--metadata-from` names: `first`, `last`, a 1-based
+/// number or one of the inputs' paths.
+fn chosen_input(flag: &str, value: &str, file_names: &[PathBuf], predir: &Path) -> merge_pdf::Result<usize> {
+    let found = match value {
+        "first" => Some(0),
+        "last" => file_names.len().checked_sub(1),
+        _ => match value.parse::<usize>() {
+            Ok(n) => (1..=file_names.len()).contains(&n).then(|| n - 1),
+            Err(_) => {
+                let path = input::resolve(predir, Path::new(value));
+                file_names.iter().position(|name| *name == path)
+            }
+        },
+    };
+    found.ok_or_else(|| merge_pdf::Error::Usage(tr("{} {}: not one of the {} inputs", &[&flag, &value, &file_names.len()])))
+}
 
-    // Update the max internal ID as wasn't updated before due to direct objects insertion
-    res.max_id = res.objects.len() as u32;
+/// How much memory merging `paths` as a whole is taken to need: parsed, an input takes a few times
+/// the room of its file, and the merged document holds its objects once more.
+fn estimated_working_set(paths: &[PathBuf]) -> u64 {
+    let bytes = paths.iter().filter_map(|path| std::fs::metadata(path).ok()).map(|metadata| metadata.len()).sum::<u64>();
+    bytes.saturating_mul(4)
+}
 
-    // Reorder all new Document objects
-    res.renumber_objects();
+/// Move `from` to `to`, copying it when they are on different filesystems.
+fn move_file(from: &Path, to: &Path) -> std::io::Result<()> {
+    if std::fs::rename(from, to).is_err() {
+        std::fs::copy(from, to)?;
+        std::fs::remove_file(from)?;
+    }
+    Ok(())
+}
 
-    //Set any Bookmarks to the First child if they are not set to a page
-    res.adjust_zero_pages();
+/// Load `path` trying its own password first, then those of `--password-file` entries that
+/// match it, repairing it if damaged with `--lenient`.
+fn load_input(path: &Path, own: Option<&str>, password_list: &PasswordList, lenient: bool) -> merge_pdf::Result<Document> {
+    let passwords = own.into_iter().chain(password_list.matching(path)).collect::<Vec<_>>();
+    input::load_with(path, &passwords, lenient)
+}
 
-    //Set all bookmarks to the PDF Object tree then set the Outlines to the Bookmark content map.
-    if let Some(n) = res.build_outline() {
-        if let Ok(Object::Dictionary(ref mut dict)) = res.get_object_mut(catalog_id) {
-            dict.set("Outlines", Object::Reference(n));
-        }
+/// `load_input`, through `cache` if `--cache` was given.
+fn load_input_cached(cache: Option<&Cache>, path: &Path, own: Option<&str>, password_list: &PasswordList, lenient: bool) -> merge_pdf::Result<Document> {
+    let load = || load_input(path, own, password_list, lenient);
+    match cache {
+        Some(cache) => cache.load(path, lenient, load),
+        None => load(),
     }
+}
 
-    // Most of the time this does nothing unless there are a lot of streams
-    // Can be disabled to speed up the process.
-    // document.compress();
+/// `doc`, loaded from `path`, through `--page-filter` if it was given.
+fn filter_input(mut doc: Document, path: &Path, command: Option<&str>) -> merge_pdf::Result<Document> {
+    match command {
+        Some(command) => merge_pdf::filter::run(&mut doc, command, path),
+        None => Ok(doc),
+    }
+}
 
-    // Save the merged PDF
-    // Store file in current working directory.
-    res.save(predir.join("merged.pdf"))?;
-    Ok(())
+fn parse_nup(s: &str) -> Result<usize, String> {
+    match s {
+        "2" => Ok(2),
+        "4" => Ok(4),
+        _ => Err(format!("{:?} pages per sheet is not supported, use 2 or 4", s)),
+    }
 }
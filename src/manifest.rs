@@ -0,0 +1,195 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use clap::ValueEnum;
+
+use crate::{
+    input::{InputSpec, PageRanges, Parity},
+    json::{self, Value},
+    messages::tr,
+    outline::{BookmarkStyle, PageBookmark},
+};
+
+/// The inputs of a manifest.
+#[derive(Debug, Clone)]
+pub struct Manifest {
+    pub files: Vec<InputSpec>,
+    /// Whether the files came from an `outline` tree, whose sections are in `InputSpec::sections`.
+    pub outline: bool,
+    /// Outputs of the manifest's `groups`, indexed by `InputSpec::group`; empty without groups.
+    pub groups: Vec<PathBuf>,
+}
+
+/// Read the inputs listed in a JSON manifest.
+///
+/// The manifest is either an array of entries, an object with a `files` array or an object with
+/// an `outline` array. An entry is a path string or an object:
+///
+/// ```json
+/// { "path": "a.pdf", "title": "Intro", "layer": 1, "pages": "1-3,7", "parity": "odd", "rotate": 90,
+///   "crop": "36,36,36,36", "reverse": true, "compress": false, "bookmark_color": "255,0,0", "bookmark_style": "bold",
+///   "password": "secret", "bookmarks": [{ "title": "Summary", "page": 2, "children": [] }] }
+/// ```
+///
+/// `bookmarks` go below the file's own bookmark instead of the document's outline; `page` counts
+/// from 1 within the file. An `outline` holds entries and sections, which nest further:
+///
+/// ```json
+/// { "outline": [{ "section": "Part I", "children": ["a.pdf", { "path": "b.pdf" }] }, "c.pdf"] }
+/// ```
+///
+/// Several merged files can come out of one manifest: `groups` gives each its `output` and
+/// `files`, and inputs listed in more than one group are still loaded once.
+///
+/// ```json
+/// { "groups": [{ "output": "part1.pdf", "files": ["ch1.pdf", "ch2.pdf"] }, { "output": "part2.pdf", "files": ["ch3.pdf"] }] }
+/// ```
+///
+/// Paths, outputs included, resolve against `--predir` like `--files` entries do.
+pub fn read(path: &Path) -> crate::Result<Manifest> {
+    let invalid = |message: String| crate::Error::Invalid(tr("{}: {}", &[&path.display(), &message]));
+    let text = fs::read_to_string(path)?;
+    let manifest = json::parse(&text).map_err(invalid)?;
+    if let Some(groups) = manifest.get("groups") {
+        if manifest.get("files").is_some() || manifest.get("outline").is_some() {
+            return Err(invalid(tr("\"groups\" cannot be given with \"files\" or \"outline\", each group lists its files", &[])));
+        }
+        return group_specs(groups).map_err(invalid);
+    }
+    if let Some(outline) = manifest.get("outline") {
+        if manifest.get("files").is_some() {
+            return Err(invalid(tr("\"files\" and \"outline\" cannot both be given, the outline lists the files", &[])));
+        }
+        let nodes = outline.as_array().ok_or_else(|| invalid(tr("\"outline\" must be an array", &[])))?;
+        let mut files = Vec::new();
+        outline_specs(nodes, &mut Vec::new(), &mut files).map_err(invalid)?;
+        return Ok(Manifest { files, outline: true, groups: Vec::new() });
+    }
+    let entries = manifest
+        .as_array()
+        .or_else(|| manifest.get("files").and_then(Value::as_array))
+        .ok_or_else(|| invalid(tr("expected an array of files or an object with a \"files\" or \"outline\" array", &[])))?;
+    let files = entries
+        .iter()
+        .enumerate()
+        .map(|(i, entry)| entry_spec(entry).map_err(|e| invalid(tr("entry {}: {}", &[&(i + 1), &e]))))
+        .collect::<crate::Result<_>>()?;
+    Ok(Manifest { files, outline: false, groups: Vec::new() })
+}
+
+/// Collect the files of all `groups` in order, each knowing its group, and the outputs.
+fn group_specs(groups: &Value) -> Result<Manifest, String> {
+    let groups = groups.as_array().ok_or_else(|| tr("\"groups\" must be an array", &[]))?;
+    let mut files = Vec::new();
+    let mut outputs = Vec::new();
+    for (index, group) in groups.iter().enumerate() {
+        let invalid = |message: String| tr("group {}: {}", &[&(index + 1), &message]);
+        let output = group.get("output").and_then(Value::as_str).ok_or_else(|| invalid(tr("missing \"output\"", &[])))?;
+        let entries = group
+            .get("files")
+            .and_then(Value::as_array)
+            .ok_or_else(|| invalid(tr("\"files\" must be an array", &[])))?;
+        for (i, entry) in entries.iter().enumerate() {
+            let mut spec = entry_spec(entry).map_err(|e| invalid(tr("entry {}: {}", &[&(i + 1), &e])))?;
+            spec.group = Some(index);
+            files.push(spec);
+        }
+        outputs.push(PathBuf::from(output));
+    }
+    if outputs.is_empty() {
+        return Err(tr("\"groups\" is empty", &[]));
+    }
+    Ok(Manifest { files, outline: false, groups: outputs })
+}
+
+/// Collect the files of outline `nodes` in order, each knowing the `sections` it sits in.
+fn outline_specs(nodes: &[Value], sections: &mut Vec<String>, files: &mut Vec<InputSpec>) -> Result<(), String> {
+    for node in nodes {
+        let Some(section) = node.get("section") else {
+            let mut spec = entry_spec(node).map_err(|e| tr("outline entry {}: {}", &[&(files.len() + 1), &e]))?;
+            spec.sections = sections.clone();
+            files.push(spec);
+            continue;
+        };
+        let title = section.as_str().ok_or_else(|| tr("\"section\" must be a string", &[]))?;
+        let children = match node.get("children") {
+            Some(children) => children.as_array().ok_or_else(|| tr("section \"{}\": \"children\" must be an array", &[&title]))?,
+            None => &[],
+        };
+        sections.push(title.to_string());
+        outline_specs(children, sections, files)?;
+        sections.pop();
+    }
+    Ok(())
+}
+
+fn entry_spec(entry: &Value) -> Result<InputSpec, String> {
+    if let Some(path) = entry.as_str() {
+        return path.parse();
+    }
+    let field = |key: &str| entry.get(key).filter(|v| **v != Value::Null);
+    let path = field("path").and_then(Value::as_str).ok_or_else(|| tr("missing \"path\"", &[]))?;
+    let mut spec = InputSpec::new(path.into());
+    if let Some(title) = field("title") {
+        spec.title = Some(title.as_str().ok_or_else(|| tr("\"title\" must be a string", &[]))?.to_string());
+    }
+    if let Some(layer) = field("layer") {
+        let layer = layer.as_i64().and_then(|l| u32::try_from(l).ok()).ok_or_else(|| tr("\"layer\" must be a non-negative integer", &[]))?;
+        spec.layer = Some(layer);
+    }
+    if let Some(pages) = field("pages") {
+        spec.pages = Some(pages.as_str().ok_or_else(|| tr("\"pages\" must be a string like \"1-3,7\"", &[]))?.parse::<PageRanges>()?);
+    }
+    if let Some(parity) = field("parity") {
+        spec.parity = Some(parity.as_str().ok_or_else(|| tr("\"parity\" must be \"odd\" or \"even\"", &[]))?.parse::<Parity>()?);
+    }
+    if let Some(rotate) = field("rotate") {
+        spec.rotate = rotate.as_i64().filter(|r| r % 90 == 0).ok_or_else(|| tr("\"rotate\" must be a multiple of 90", &[]))?;
+    }
+    if let Some(crop) = field("crop") {
+        spec.crop = Some(crop.as_str().ok_or_else(|| tr("\"crop\" must be a string like \"36,36,36,36\"", &[]))?.parse()?);
+    }
+    if let Some(reverse) = field("reverse") {
+        spec.reverse = reverse.as_bool().ok_or_else(|| tr("\"reverse\" must be true or false", &[]))?;
+    }
+    if let Some(compress) = field("compress") {
+        spec.compress = compress.as_bool().ok_or_else(|| tr("\"compress\" must be true or false", &[]))?;
+    }
+    if let Some(color) = field("bookmark_color") {
+        spec.bookmark_color = Some(color.as_str().ok_or_else(|| tr("\"bookmark_color\" must be a string like \"255,0,0\"", &[]))?.parse()?);
+    }
+    if let Some(style) = field("bookmark_style") {
+        let style = style.as_str().ok_or_else(|| tr("\"bookmark_style\" must be a string", &[]))?;
+        spec.bookmark_style = Some(BookmarkStyle::from_str(&style.replace('_', "-"), true)?);
+    }
+    if let Some(password) = field("password") {
+        spec.password = Some(password.as_str().ok_or_else(|| tr("\"password\" must be a string", &[]))?.to_string());
+    }
+    if let Some(bookmarks) = field("bookmarks") {
+        spec.bookmarks = page_bookmarks(bookmarks)?;
+    }
+    Ok(spec)
+}
+
+fn page_bookmarks(bookmarks: &Value) -> Result<Vec<PageBookmark>, String> {
+    let bookmarks = bookmarks.as_array().ok_or_else(|| tr("\"bookmarks\" must be an array", &[]))?;
+    bookmarks
+        .iter()
+        .map(|bookmark| {
+            let title = bookmark.get("title").and_then(Value::as_str).ok_or_else(|| tr("a bookmark is missing its \"title\"", &[]))?;
+            let page = bookmark
+                .get("page")
+                .and_then(Value::as_i64)
+                .and_then(|p| u32::try_from(p).ok())
+                .filter(|p| *p > 0)
+                .ok_or_else(|| tr("bookmark \"{}\": \"page\" must be a page number from 1", &[&title]))?;
+            let children = match bookmark.get("children") {
+                Some(children) => page_bookmarks(children)?,
+                None => Vec::new(),
+            };
+            Ok(PageBookmark { title: title.to_string(), page, children })
+        })
+        .collect()
+}
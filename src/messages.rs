@@ -0,0 +1,385 @@
+use std::{
+    fmt::Display,
+    sync::atomic::{AtomicU8, Ordering},
+};
+
+/// Languages the errors, warnings and summaries of a merge can be in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+#[repr(u8)]
+pub enum Lang {
+    #[default]
+    En,
+    De,
+}
+
+static LANG: AtomicU8 = AtomicU8::new(Lang::En as u8);
+
+impl Lang {
+    /// The language of a locale like `de_DE.UTF-8`, `None` if it has none of its own here.
+    pub fn from_locale(locale: &str) -> Option<Lang> {
+        match locale.split(['_', '-', '.', '@']).next()?.to_ascii_lowercase().as_str() {
+            "en" | "c" | "posix" => Some(Lang::En),
+            "de" => Some(Lang::De),
+            _ => None,
+        }
+    }
+
+    /// The language `MERGE_PDF_LANG` names, else the first of `LC_ALL`, `LC_MESSAGES` and
+    /// `LANG` set, English if none or another one.
+    pub fn from_env() -> Lang {
+        ["MERGE_PDF_LANG", "LC_ALL", "LC_MESSAGES", "LANG"]
+            .iter()
+            .filter_map(|name| std::env::var(name).ok().filter(|value| !value.is_empty()))
+            .next()
+            .and_then(|locale| Lang::from_locale(&locale))
+            .unwrap_or_default()
+    }
+
+    fn catalog(self) -> &'static [(&'static str, &'static str)] {
+        match self {
+            Lang::En => &[],
+            Lang::De => DE,
+        }
+    }
+}
+
+/// Tell users everything in `lang` from now on.
+pub fn set_lang(lang: Lang) {
+    LANG.store(lang as u8, Ordering::Relaxed);
+}
+
+pub fn lang() -> Lang {
+    match LANG.load(Ordering::Relaxed) {
+        1 => Lang::De,
+        _ => Lang::En,
+    }
+}
+
+/// `template`, an English message, in the language set with `set_lang`, or as it is when the
+/// catalog has no translation, with `args` in place of its `{}`, in order. Translations put
+/// `{0}`, `{1}`... for arguments in another order; `{{` and `}}` are braces.
+pub fn tr(template: &str, args: &[&dyn Display]) -> String {
+    let template = lang().catalog().iter().find(|(english, _)| *english == template).map_or(template, |(_, translated)| translated);
+    let mut out = String::with_capacity(template.len());
+    let mut next = 0;
+    let mut rest = template;
+    while let Some(at) = rest.find(['{', '}']) {
+        out.push_str(&rest[..at]);
+        rest = &rest[at..];
+        if let Some(after) = rest.strip_prefix("{{").or_else(|| rest.strip_prefix("}}")) {
+            out.push_str(&rest[..1]);
+            rest = after;
+            continue;
+        }
+        let Some(end) = rest.find('}').filter(|_| rest.starts_with('{')) else {
+            out.push_str(&rest[..1]);
+            rest = &rest[1..];
+            continue;
+        };
+        let index = match &rest[1..end] {
+            "" => {
+                next += 1;
+                Some(next - 1)
+            }
+            position => position.parse::<usize>().ok(),
+        };
+        match index.and_then(|index| args.get(index)) {
+            Some(arg) => out.push_str(&arg.to_string()),
+            None => out.push_str(&rest[..=end]),
+        }
+        rest = &rest[end + 1..];
+    }
+    out.push_str(rest);
+    out
+}
+
+/// German, `(English, German)`.
+const DE: &[(&str, &str)] = &[
+    ("ERROR:", "FEHLER:"),
+    ("WARNING:", "WARNUNG:"),
+    ("{} could not be loaded: {}", "{} konnte nicht geladen werden: {}"),
+    ("{} is encrypted and needs a password", "{} ist verschlüsselt und braucht ein Passwort"),
+    ("{} could not be opened, the password is wrong", "{} konnte nicht geöffnet werden, das Passwort ist falsch"),
+    ("{} could not be opened with any of the {} passwords tried", "{} konnte mit keinem der {} versuchten Passwörter geöffnet werden"),
+    ("no input has any pages", "keine Eingabe hat Seiten"),
+    ("the root input has no Catalog or Pages root", "die Stammeingabe hat keinen Catalog oder keine Seitenbaumwurzel"),
+    ("merge cancelled", "Zusammenführen abgebrochen"),
+    ("interrupted", "unterbrochen"),
+    ("{} of {} inputs were skipped", "{} von {} Eingaben wurden übersprungen"),
+    ("{} could not be written: {}", "{} konnte nicht geschrieben werden: {}"),
+    ("{}, skipping", "{}, wird übersprungen"),
+    ("{}: line {}: missing the password after \"{}\"", "{}: Zeile {}: das Passwort nach \"{}\" fehlt"),
+    ("no page objects were found", "es wurden keine Seitenobjekte gefunden"),
+    ("cannot open at page {}, the merged document has {} pages", "kann nicht auf Seite {} öffnen, das zusammengeführte Dokument hat {} Seiten"),
+    ("the overlay has no pages", "die Überlagerung hat keine Seiten"),
+    ("is encrypted for the {} security handler, only passwords are supported", "ist für den Sicherheitshandler {} verschlüsselt, nur Passwörter werden unterstützt"),
+    ("is encrypted in a way that is not supported: {}", "ist auf eine nicht unterstützte Weise verschlüsselt: {}"),
+    ("encrypted files cannot be appended to", "an verschlüsselte Dateien kann nicht angehängt werden"),
+    ("{} could not be downloaded, is curl installed? {}", "{} konnte nicht heruntergeladen werden, ist curl installiert? {}"),
+    ("{} could not be downloaded ({})", "{} konnte nicht heruntergeladen werden ({})"),
+    ("{}: URL inputs need merge_pdf built with the `net` feature", "{}: URL-Eingaben brauchen merge_pdf mit dem Feature `net` gebaut"),
+    ("{} could not be downloaded, is the AWS CLI installed? {}", "{} konnte nicht heruntergeladen werden, ist die AWS CLI installiert? {}"),
+    ("{}: s3:// inputs need merge_pdf built with the `s3` feature", "{}: s3://-Eingaben brauchen merge_pdf mit dem Feature `s3` gebaut"),
+    ("unsupported image format", "nicht unterstütztes Bildformat"),
+    ("the layout of the linearized file did not settle", "das Layout der linearisierten Datei kam nicht zur Ruhe"),
+    ("an encrypted document cannot be linearized", "ein verschlüsseltes Dokument kann nicht linearisiert werden"),
+    ("a document without pages cannot be linearized", "ein Dokument ohne Seiten kann nicht linearisiert werden"),
+    ("PDF/A output needs the whole document, it cannot be streamed", "PDF/A-Ausgabe braucht das ganze Dokument, sie kann nicht gestreamt werden"),
+    ("N-up and booklet output need the whole document, they cannot be streamed", "N-up- und Broschürenausgabe brauchen das ganze Dokument, sie können nicht gestreamt werden"),
+    ("linearized output needs the whole document, it cannot be streamed", "linearisierte Ausgabe braucht das ganze Dokument, sie kann nicht gestreamt werden"),
+    ("merging font subsets needs the whole document, it cannot be streamed", "das Zusammenführen von Schrift-Teilmengen braucht das ganze Dokument, es kann nicht gestreamt werden"),
+    ("page stamps, watermarks and overlays are put on the whole document, they cannot be streamed", "Seitenstempel, Wasserzeichen und Überlagerungen kommen auf das ganze Dokument, sie können nicht gestreamt werden"),
+    ("the input chosen as root was left out", "die als Stamm gewählte Eingabe wurde weggelassen"),
+    ("root input {} is out of range 0..{}", "die Stammeingabe {} liegt außerhalb von 0..{}"),
+    ("metadata input {} is out of range 0..{}", "die Metadaten-Eingabe {} liegt außerhalb von 0..{}"),
+    ("view input {} is out of range 0..{}", "die Ansichts-Eingabe {} liegt außerhalb von 0..{}"),
+    ("{} could not be signed: {}", "{} konnte nicht signiert werden: {}"),
+    ("{}: signing needs merge_pdf built with the `crypto` feature", "{}: Signieren braucht merge_pdf mit dem Feature `crypto` gebaut"),
+    ("encrypted files cannot be signed", "verschlüsselte Dateien können nicht signiert werden"),
+    ("there is no random number generator to make encryption keys with", "es gibt keinen Zufallszahlengenerator, um Verschlüsselungsschlüssel zu erzeugen"),
+    ("no pages", "keine Seiten"),
+    ("{}: OCR needs merge_pdf built with the `ocr` feature", "{}: OCR braucht merge_pdf mit dem Feature `ocr` gebaut"),
+    ("no startxref", "kein startxref"),
+    ("loading task failed: {}", "Ladeaufgabe fehlgeschlagen: {}"),
+    ("document has no top-level bookmarks to split at", "das Dokument hat keine Lesezeichen der obersten Ebene, an denen geteilt werden kann"),
+    ("{} is the file being split, a part cannot replace it", "{} ist die Datei, die aufgeteilt wird, ein Teil kann sie nicht ersetzen"),
+    ("cannot put {} pages on a sheet, only 2 or 4", "kann nicht {} Seiten auf ein Blatt setzen, nur 2 oder 4"),
+    ("cropping {},{},{},{} leaves nothing of a {} by {} page", "Zuschneiden um {},{},{},{} lässt nichts von einer {} mal {} großen Seite übrig"),
+    ("not a ZIP archive", "kein ZIP-Archiv"),
+    ("ZIP64 archives are not supported", "ZIP64-Archive werden nicht unterstützt"),
+    ("corrupt central directory", "beschädigtes zentrales Verzeichnis"),
+    ("truncated central directory", "abgeschnittenes zentrales Verzeichnis"),
+    ("{} is encrypted", "{} ist verschlüsselt"),
+    ("{} has a corrupt local header", "{} hat einen beschädigten lokalen Header"),
+    ("{} is truncated", "{} ist abgeschnitten"),
+    ("{} uses unsupported compression method {}", "{} verwendet die nicht unterstützte Kompressionsmethode {}"),
+    ("{} fails its CRC check", "{} besteht die CRC-Prüfung nicht"),
+    ("{}: too large for a ZIP archive without ZIP64", "{}: zu groß für ein ZIP-Archiv ohne ZIP64"),
+    ("{}: name too long for a ZIP archive", "{}: Name zu lang für ein ZIP-Archiv"),
+    ("layer_parent is empty", "layer_parent ist leer"),
+    ("layer_parent[{}] is out of index", "layer_parent[{}] liegt außerhalb des Index"),
+    ("layer_parent[{}] is empty", "layer_parent[{}] ist leer"),
+    ("expected an array of bookmarks", "erwartet wurde ein Array von Lesezeichen"),
+    ("a bookmark is missing its \"title\"", "einem Lesezeichen fehlt sein \"title\""),
+    ("bookmark \"{}\": {}", "Lesezeichen \"{}\": {}"),
+    ("\"page\" must be a page number from 1", "\"page\" muss eine Seitenzahl ab 1 sein"),
+    ("\"color\" must be [R, G, B] from 0 to 255", "\"color\" muss [R, G, B] von 0 bis 255 sein"),
+    ("\"style\" must be a string", "\"style\" muss eine Zeichenkette sein"),
+    ("\"groups\" cannot be given with \"files\" or \"outline\", each group lists its files", "\"groups\" kann nicht mit \"files\" oder \"outline\" angegeben werden, jede Gruppe listet ihre Dateien"),
+    ("\"files\" and \"outline\" cannot both be given, the outline lists the files", "\"files\" und \"outline\" können nicht beide angegeben werden, die Gliederung listet die Dateien"),
+    ("\"outline\" must be an array", "\"outline\" muss ein Array sein"),
+    ("expected an array of files or an object with a \"files\" or \"outline\" array", "erwartet wurde ein Array von Dateien oder ein Objekt mit einem Array \"files\" oder \"outline\""),
+    ("entry {}: {}", "Eintrag {}: {}"),
+    ("\"groups\" must be an array", "\"groups\" muss ein Array sein"),
+    ("group {}: {}", "Gruppe {}: {}"),
+    ("missing \"output\"", "\"output\" fehlt"),
+    ("\"files\" must be an array", "\"files\" muss ein Array sein"),
+    ("\"groups\" is empty", "\"groups\" ist leer"),
+    ("outline entry {}: {}", "Gliederungseintrag {}: {}"),
+    ("\"section\" must be a string", "\"section\" muss eine Zeichenkette sein"),
+    ("section \"{}\": \"children\" must be an array", "Abschnitt \"{}\": \"children\" muss ein Array sein"),
+    ("missing \"path\"", "\"path\" fehlt"),
+    ("\"title\" must be a string", "\"title\" muss eine Zeichenkette sein"),
+    ("\"layer\" must be a non-negative integer", "\"layer\" muss eine nicht negative ganze Zahl sein"),
+    ("\"pages\" must be a string like \"1-3,7\"", "\"pages\" muss eine Zeichenkette wie \"1-3,7\" sein"),
+    ("\"parity\" must be \"odd\" or \"even\"", "\"parity\" muss \"odd\" oder \"even\" sein"),
+    ("\"rotate\" must be a multiple of 90", "\"rotate\" muss ein Vielfaches von 90 sein"),
+    ("\"crop\" must be a string like \"36,36,36,36\"", "\"crop\" muss eine Zeichenkette wie \"36,36,36,36\" sein"),
+    ("\"reverse\" must be true or false", "\"reverse\" muss true oder false sein"),
+    ("\"compress\" must be true or false", "\"compress\" muss true oder false sein"),
+    ("\"bookmark_color\" must be a string like \"255,0,0\"", "\"bookmark_color\" muss eine Zeichenkette wie \"255,0,0\" sein"),
+    ("\"bookmark_style\" must be a string", "\"bookmark_style\" muss eine Zeichenkette sein"),
+    ("\"password\" must be a string", "\"password\" muss eine Zeichenkette sein"),
+    ("\"bookmarks\" must be an array", "\"bookmarks\" muss ein Array sein"),
+    ("bookmark \"{}\": \"page\" must be a page number from 1", "Lesezeichen \"{}\": \"page\" muss eine Seitenzahl ab 1 sein"),
+    ("PDF/A forbids encryption, PDF/A output cannot be encrypted", "PDF/A verbietet Verschlüsselung, PDF/A-Ausgabe kann nicht verschlüsselt werden"),
+    ("{}x{} pt where most pages are {}x{} pt, --page-size evens them out", "{}x{} pt, wo die meisten Seiten {}x{} pt sind, --page-size gleicht das aus"),
+    ("{} where most pages are {}, --auto-orient evens them out", "{}, wo die meisten Seiten {} sind, --auto-orient gleicht das aus"),
+    ("{} where most pages are {}, --grayscale evens them out", "{}, wo die meisten Seiten {} sind, --grayscale gleicht das aus"),
+    ("landscape", "Querformat"),
+    ("portrait", "Hochformat"),
+    ("pages differ from the rest of the merge in {} way(s)", "Seiten weichen in {} Punkt(en) vom Rest ab"),
+    ("{} was left by process {}, which is gone, taking it over", "{} stammt von Prozess {}, der nicht mehr läuft, wird übernommen"),
+    ("{} is being written by another merge_pdf, process {}", "{} wird von einem anderen merge_pdf geschrieben, Prozess {}"),
+    ("{} is being written by another merge_pdf", "{} wird von einem anderen merge_pdf geschrieben"),
+    ("--zip-output needs --max-size or a manifest that has groups", "--zip-output braucht --max-size oder ein Manifest mit Gruppen"),
+    ("Saved {} files to {}", "{} Dateien in {} gespeichert"),
+    ("Saved the checksum to {}", "Prüfsumme in {} gespeichert"),
+    ("version: {} and {}", "Version: {} und {}"),
+    ("pages: {} and {}", "Seiten: {} und {}"),
+    ("none", "keine"),
+    ("{}: {} and {}", "{}: {} und {}"),
+    ("page {}: the content differs", "Seite {}: der Inhalt unterscheidet sich"),
+    ("objects: {} and {}", "Objekte: {} und {}"),
+    ("{} objects: {} and {}", "{}-Objekte: {} und {}"),
+    ("{} and {} are equivalent", "{} und {} sind gleichwertig"),
+    ("the documents differ in {} way(s)", "die Dokumente unterscheiden sich in {} Punkt(en)"),
+    ("Saved a portfolio of {} file(s) to {}", "Portfolio mit {} Datei(en) in {} gespeichert"),
+    ("{} scanned page(s) have images OCR cannot read and were left as they are", "{} gescannte Seite(n) haben Bilder, die die Texterkennung nicht lesen kann, und bleiben wie sie sind"),
+    ("{}: recognized the text of {} scanned page(s)", "{}: Text von {} gescannten Seite(n) erkannt"),
+    ("tesseract could not be run, is it installed? {}", "tesseract konnte nicht ausgeführt werden, ist es installiert? {}"),
+    ("{}: tesseract failed on page {}: {}", "{}: tesseract ist bei Seite {} fehlgeschlagen: {}"),
+    ("{} failed on {}: {}", "{} ist bei {} fehlgeschlagen: {}"),
+    ("{} left no PDF for {}: {}", "{} hat für {} kein PDF hinterlassen: {}"),
+    ("{} cannot be used with --output -, stdout takes the PDF", "{} geht nicht mit --output -, die Standardausgabe nimmt das PDF auf"),
+    ("Listening on http://{}", "Erreichbar unter http://{}"),
+    ("the request could not be read", "die Anfrage konnte nicht gelesen werden"),
+    ("send the body with a Content-Length", "den Inhalt bitte mit einer Content-Length senden"),
+    ("the Content-Length is not a number", "die Content-Length ist keine Zahl"),
+    ("the request is over the limit of {} bytes", "die Anfrage ist größer als die Grenze von {} Bytes"),
+    ("{} is not allowed here", "{} ist hier nicht erlaubt"),
+    ("{} is not here, try POST /merge", "{} gibt es nicht, versuche POST /merge"),
+    ("the multipart body has no boundary", "der Multipart-Inhalt hat keine Boundary"),
+    ("send the files as multipart/form-data, or their paths as application/json", "die Dateien als multipart/form-data senden oder ihre Pfade als application/json"),
+    ("no files to merge", "keine Dateien zum Zusammenführen"),
+    ("merging files on the server needs --root", "das Zusammenführen von Dateien auf dem Server braucht --root"),
+    ("expected a list of paths", "eine Liste von Pfaden erwartet"),
+    ("{} is not below the root", "{} liegt nicht unterhalb der Wurzel"),
+    ("the multipart body could not be read", "der Multipart-Inhalt konnte nicht gelesen werden"),
+    ("Watching {}", "{} wird beobachtet"),
+    ("Merging {} input(s) from {}", "{} Eingabe(n) aus {} werden zusammengeführt"),
+    ("the output cannot be in the watched directory, it would be merged with the next batch", "die Ausgabe kann nicht im beobachteten Verzeichnis liegen, sie würde mit dem nächsten Stapel zusammengeführt"),
+    ("{} could not be moved to {}: {}", "{} konnte nicht nach {} verschoben werden: {}"),
+    ("{} failed: {}", "{} ist fehlgeschlagen: {}"),
+    ("{} could not be run: {}", "{} konnte nicht ausgeführt werden: {}"),
+    ("{} already exists, pass --on-existing increment or overwrite", "{} existiert schon, mit --on-existing increment oder overwrite wird trotzdem geschrieben"),
+    ("{} is digitally signed, merging it would invalidate {} signature(s)", "{} ist digital signiert, das Zusammenführen würde {} Signatur(en) ungültig machen"),
+    ("{} has none of the requested pages", "{} hat keine der verlangten Seiten"),
+    ("{} cannot be used with a manifest that has groups", "{} geht nicht mit einem Manifest mit Gruppen"),
+    ("with manifest groups, every input must be listed in a group", "mit Manifest-Gruppen muss jede Eingabe in einer Gruppe stehen"),
+    ("files must be more than 1, unless pages are selected, rotated, compressed or made PDF/A", "es braucht mehr als eine Datei, außer Seiten werden ausgewählt, gedreht, komprimiert oder zu PDF/A gemacht"),
+    ("--interleave needs both of its files", "--interleave braucht beide Dateien"),
+    ("--root-from {} is out of range 1..={}", "--root-from {} liegt außerhalb von 1..={}"),
+    ("--root-from {}: {} was skipped", "--root-from {}: {} wurde übersprungen"),
+    ("--metadata-from {}: {} was skipped", "--metadata-from {}: {} wurde übersprungen"),
+    ("--view-from {}: {} was skipped", "--view-from {}: {} wurde übersprungen"),
+    ("{} {}: not one of the {} inputs", "{} {}: keine der {} Eingaben"),
+    ("{} input(s) could not be loaded and were left out:{}", "{} Eingabe(n) konnten nicht geladen werden und wurden weggelassen:{}"),
+    ("{} contains no PDFs", "{} enthält keine PDFs"),
+    ("no input of {} has any pages, skipping", "keine Eingabe von {} hat Seiten, wird übersprungen"),
+    ("no (selected) pages, skipping", "keine (ausgewählten) Seiten, wird übersprungen"),
+    ("damaged, its objects were found by scanning the file", "beschädigt, die Objekte wurden durch Durchsuchen der Datei gefunden"),
+    ("{} has the same content as {}", "{} hat denselben Inhalt wie {}"),
+    ("Skipping {}: same content as {}", "{} wird übersprungen: derselbe Inhalt wie {}"),
+    ("Skipped {} duplicate input(s)", "{} doppelte Eingabe(n) übersprungen"),
+    ("takes {} bytes alone, over the limit of {}", "belegt allein {} Bytes, mehr als die Grenze von {}"),
+    ("{} is PDF {}, the output cannot be PDF {}", "{} ist PDF {}, die Ausgabe kann nicht PDF {} sein"),
+    ("object streams need PDF 1.5, the output is PDF {} and is written without them", "Objektströme brauchen PDF 1.5, die Ausgabe ist PDF {} und wird ohne sie geschrieben"),
+    ("encrypted output is written without linearizing it", "verschlüsselte Ausgabe wird nicht linearisiert"),
+    ("encrypted output is written without object streams", "verschlüsselte Ausgabe wird ohne Objektströme geschrieben"),
+    ("the merge is estimated to need {} bytes, more than the {} --max-memory allows", "das Zusammenführen braucht voraussichtlich {} Bytes, mehr als die {}, die --max-memory erlaubt"),
+    ("{} needs the whole document in memory, the merge cannot go one input at a time", "{} braucht das ganze Dokument im Speicher, es kann nicht eine Eingabe nach der anderen zusammengeführt werden"),
+    ("merging one input at a time, as --low-memory does", "es wird eine Eingabe nach der anderen zusammengeführt, wie mit --low-memory"),
+    ("{} is left out, its hashes would not fit", "{} wird weggelassen, die Hashes würden nicht passen"),
+    ("the merged document is broken: {}", "das zusammengeführte Dokument ist fehlerhaft: {}"),
+    ("{} of {} input(s) are PDF/A, the output will not be{}", "{} von {} Eingabe(n) sind PDF/A, die Ausgabe wird es nicht sein{}"),
+    (", as not every input is", ", da nicht jede Eingabe es ist"),
+    ("the output will not be {}: {}", "die Ausgabe wird nicht {} sein: {}"),
+    ("{}: not PDF/A", "{}: kein PDF/A"),
+    ("the {} of the appended inputs are left out", "{} der angehängten Eingaben werden weggelassen"),
+    ("named destinations and attachments", "Benannte Ziele und Anhänge"),
+    ("named destinations", "Benannte Ziele"),
+    ("layers", "Ebenen"),
+    ("the structure tree", "Der Strukturbaum"),
+    ("article threads", "Artikelverläufe"),
+    ("bookmark {} does not point at a page, skipping", "Lesezeichen {} verweist auf keine Seite, wird übersprungen"),
+    ("pages 1-{} come before the first bookmark and are not written", "die Seiten 1-{} stehen vor dem ersten Lesezeichen und werden nicht geschrieben"),
+    ("{} has {} pages, not splitting at page {}", "{} hat {} Seiten, bei Seite {} wird nicht geteilt"),
+    ("{} could not be cached in {}: {}", "{} konnte nicht in {} zwischengespeichert werden: {}"),
+    ("unknown config key `{}`", "unbekannter Konfigurationsschlüssel `{}`"),
+    ("XFA forms cannot be merged, only their AcroForm fields are kept", "XFA-Formulare können nicht zusammengeführt werden, nur ihre AcroForm-Felder bleiben erhalten"),
+    ("{} digital signature(s) will not be valid in the output", "{} digitale Signatur(en) werden in der Ausgabe nicht gültig sein"),
+    ("{} image(s) or content stream(s) could not be read and were left in color", "{} Bild(er) oder Inhaltsströme konnten nicht gelesen werden und bleiben farbig"),
+    ("{} annotation(s) have no appearance and were left out", "{} Anmerkung(en) haben kein Erscheinungsbild und wurden weggelassen"),
+    ("{} form field(s) have no appearance and were left out", "{} Formularfeld(er) haben kein Erscheinungsbild und wurden weggelassen"),
+    ("could not read its content, it was not redacted", "der Inhalt konnte nicht gelesen werden, er wurde nicht geschwärzt"),
+    ("could not read the content of the form, it was not redacted", "der Inhalt des Formulars konnte nicht gelesen werden, er wurde nicht geschwärzt"),
+    ("{} image(s) over {} dpi are stored in a way that cannot be read and were left as they are", "{} Bild(er) über {} dpi sind in einer Weise gespeichert, die nicht gelesen werden kann, und bleiben unverändert"),
+    ("{} has {} pages but {} has {}, the rest go at the end", "{} hat {} Seiten, aber {} hat {}, der Rest kommt ans Ende"),
+    ("--interactive needs a terminal", "--interactive braucht ein Terminal"),
+    ("not running on a terminal, pass --yes to merge without confirmation", "kein Terminal, mit --yes wird ohne Bestätigung zusammengeführt"),
+    ("About to merge {} files ({} pages):", "{} Dateien ({} Seiten) werden zusammengeführt:"),
+    ("{} ({} pages)", "{} ({} Seiten)"),
+    ("Proceed? [y/N]", "Fortfahren? [j/N]"),
+    ("Would merge {} input(s) into {}:", "{} Eingabe(n) würden zu {} zusammengeführt:"),
+    (", encrypted", ", verschlüsselt"),
+    ("{} ({} of {} pages, {} bytes{})", "{} ({} von {} Seiten, {} Bytes{})"),
+    ("{} page(s), about {} bytes; nothing was written", "{} Seite(n), etwa {} Bytes; nichts wurde geschrieben"),
+    ("{} of {} inputs cannot be loaded", "{} von {} Eingaben können nicht geladen werden"),
+    ("Saved {}", "{} gespeichert"),
+    ("Saved {} ({} pages)", "{} gespeichert ({} Seiten)"),
+    ("Saved {} ({} bytes)", "{} gespeichert ({} Bytes)"),
+    ("Saved the outline to {}", "Gliederung in {} gespeichert"),
+    ("Signed {}", "{} signiert"),
+    ("Appended {} page(s) to {}", "{} Seite(n) an {} angehängt"),
+    ("Downloaded {} to {}", "{} nach {} heruntergeladen"),
+    ("{}: {} page(s) missing from the page tree recovered", "{}: {} im Seitenbaum fehlende Seite(n) wiederhergestellt"),
+    ("{}: {} object(s) nothing refers to dropped", "{}: {} Objekt(e), auf die nichts verweist, entfernt"),
+    ("{}: {} streams compressed, {} skipped", "{}: {} Ströme komprimiert, {} übersprungen"),
+    ("{}: attachment {} renamed to {}", "{}: Anhang {} in {} umbenannt"),
+    ("{}: destination {} renamed to {}", "{}: Ziel {} in {} umbenannt"),
+    ("{}: form field {} renamed to {}", "{}: Formularfeld {} in {} umbenannt"),
+    ("{}: dropped {} blank page(s)", "{}: {} leere Seite(n) entfernt"),
+    ("{}: dropped {} duplicate page(s)", "{}: {} doppelte Seite(n) entfernt"),
+    ("{}: dropped {} page thumbnail(s)", "{}: {} Seitenvorschaubild(er) entfernt"),
+    ("{} duplicate stream(s) replaced by a single copy, {} bytes saved", "{} doppelte Ströme durch eine einzige Kopie ersetzt, {} Bytes gespart"),
+    ("{} font subset(s) merged into {} font(s)", "{} Schriftuntergruppe(n) zu {} Schrift(en) zusammengeführt"),
+    ("{}: downsampled {} image(s), {} KB smaller", "{}: {} Bild(er) herunterskaliert, {} KB kleiner"),
+    ("{}: redacted {} match(es)", "{}: {} Treffer geschwärzt"),
+    ("{}: removed {} digital signature(s)", "{}: {} digitale Signatur(en) entfernt"),
+    ("{}: removed {} script or launch action(s) and {} embedded file(s)", "{}: {} Skript- oder Startaktion(en) und {} eingebettete Datei(en) entfernt"),
+    ("{}: role {} maps to another type than in an earlier input, which is kept", "{}: Rolle {} bildet auf einen anderen Typ ab als in einer früheren Eingabe, der beibehalten wird"),
+    ("{}: structure element ID {} is used by an earlier input", "{}: die Strukturelement-ID {} wird von einer früheren Eingabe verwendet"),
+    ("{}: {} article thread(s) left out with their pages", "{}: {} Artikelverläufe mit ihren Seiten weggelassen"),
+    ("{}: {} layer(s) merged with those of the same name in earlier inputs", "{}: {} Ebene(n) mit gleichnamigen Ebenen früherer Eingaben zusammengeführt"),
+    ("  m FROM TO     move entry FROM to position TO\n  d N           drop entry N\n  r N TITLE     rename the bookmark of entry N (no TITLE: back to the generated one)\n  done          merge in the order shown\n  q             cancel", "  m VON NACH    Eintrag VON an Position NACH verschieben\n  d N           Eintrag N weglassen\n  r N TITEL     das Lesezeichen von Eintrag N umbenennen (ohne TITEL: wieder das erzeugte)\n  done          in der gezeigten Reihenfolge zusammenführen\n  q             abbrechen"),
+    ("{} ({} pages)", "{} ({} Seiten)"),
+    ("usage: m FROM TO, both between 1 and {}", "Verwendung: m VON NACH, beide zwischen 1 und {}"),
+    ("cannot drop the last entry, use q to cancel", "der letzte Eintrag kann nicht weggelassen werden, q bricht ab"),
+    ("usage: d N, between 1 and {}", "Verwendung: d N, zwischen 1 und {}"),
+    ("usage: r N TITLE, N between 1 and {}", "Verwendung: r N TITEL, N zwischen 1 und {}"),
+    ("yes", "ja"),
+    ("no", "nein"),
+    ("version:", "Version:"),
+    ("pages:", "Seiten:"),
+    ("page size:", "Seitengröße:"),
+    ("encrypted:", "verschlüsselt:"),
+    ("bookmarks:", "Lesezeichen:"),
+    ("form fields:", "Formularfelder:"),
+    ("annotations:", "Anmerkungen:"),
+    ("title", "Titel"),
+    ("author", "Autor"),
+    ("subject", "Thema"),
+    ("keywords", "Stichwörter"),
+    ("creator", "Ersteller"),
+    ("producer", "Erzeuger"),
+    ("created", "erstellt"),
+    ("modified", "geändert"),
+    ("{} x {} pt ({} page(s))", "{} x {} pt ({} Seite(n))"),
+    ("{} ({} level(s))", "{} ({} Ebene(n))"),
+    ("no MediaBox", "keine MediaBox"),
+    (", cropped to {} x {} pt", ", beschnitten auf {} x {} pt"),
+    (", rotated {}°", ", um {}° gedreht"),
+    (", {} font(s)", ", {} Schrift(en)"),
+    (", {} image(s)", ", {} Bild(er)"),
+    ("Stats:", "Statistik:"),
+    ("load", "Laden"),
+    ("collect", "Sammeln"),
+    ("renumber", "Nummerieren"),
+    ("transform", "Umformen"),
+    ("outline", "Gliederung"),
+    ("finish", "Abschluss"),
+    ("save", "Speichern"),
+    ("total", "gesamt"),
+    ("{} input(s), {} page(s), {} object(s), {} bytes written", "{} Eingabe(n), {} Seite(n), {} Objekt(e), {} Bytes geschrieben"),
+    ("Loading", "Laden"),
+    ("Copying pages", "Seiten kopieren"),
+    ("Copying", "Kopieren"),
+    ("Renumbering", "Nummerieren"),
+    ("Transforming pages", "Seiten umformen"),
+    ("Building the outline", "Gliederung aufbauen"),
+    ("Finishing", "Abschließen"),
+    ("Saving", "Speichern"),
+    ("{} could not be read, trying again: {}", "{} konnte nicht gelesen werden, neuer Versuch: {}"),
+];
@@ -0,0 +1,253 @@
+use std::time::SystemTime;
+
+use lopdf::{dictionary, Dictionary, Document, Object, ObjectId, Stream, StringFormat};
+
+use crate::{outline, pdfa::Identification, text};
+
+/// What the merged document names as its producer.
+const PRODUCER: &str = concat!("merge_pdf ", env!("CARGO_PKG_VERSION"));
+
+/// Document information for the merged document, see `apply`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Metadata {
+    pub title: Option<String>,
+    pub author: Option<String>,
+    pub subject: Option<String>,
+    pub keywords: Option<String>,
+}
+
+impl Metadata {
+    pub fn is_empty(&self) -> bool {
+        self.fields().all(|(_, value)| value.is_none())
+    }
+
+    /// The Info dictionary key of each field, with its value.
+    fn fields(&self) -> impl Iterator<Item = (&'static str, Option<&str>)> {
+        [("Title", &self.title), ("Author", &self.author), ("Subject", &self.subject), ("Keywords", &self.keywords)]
+            .into_iter()
+            .map(|(key, value)| (key, value.as_deref()))
+    }
+
+    /// Set the fields given on an Info dictionary, leaving the others as they are.
+    pub fn update_info(&self, info: &mut Dictionary) {
+        for (key, value) in self.fields() {
+            if let Some(value) = value {
+                info.set(key, Object::String(text::encode(value), StringFormat::Literal));
+            }
+        }
+    }
+}
+
+/// The document information and XMP metadata of one input, for the merged document to take.
+#[derive(Debug, Clone, Default)]
+pub struct Source {
+    pub info: Option<Dictionary>,
+    pub xmp: Option<Stream>,
+}
+
+impl Source {
+    /// The metadata of `doc`, taken out so it is not copied along with the rest.
+    pub fn take(doc: &mut Document) -> Self {
+        let info = match doc.trailer.remove(b"Info") {
+            Some(Object::Reference(id)) => doc.objects.remove(&id).and_then(|info| info.as_dict().ok().cloned()),
+            Some(Object::Dictionary(info)) => Some(info),
+            _ => None,
+        };
+        let xmp_id = doc.catalog().and_then(|catalog| catalog.get(b"Metadata")).and_then(Object::as_reference);
+        let xmp = match xmp_id.map(|id| doc.objects.remove(&id)) {
+            Ok(Some(Object::Stream(stream))) => Some(stream),
+            _ => None,
+        };
+        // Renumbering would leave the reference to the stream taken pointing at another object
+        if let Ok(catalog) = doc.catalog_mut() {
+            catalog.remove(b"Metadata");
+        }
+        Source { info, xmp }
+    }
+
+    /// The title the metadata gives, from the Info dictionary or else the XMP metadata.
+    pub fn title(&self) -> Option<String> {
+        let info_title = self.info.as_ref().and_then(|info| info.get(b"Title").and_then(Object::as_str).ok()).map(text::decode);
+        info_title
+            .filter(|title| !title.trim().is_empty())
+            .or_else(|| self.xmp.as_ref().and_then(outline::xmp_stream_title))
+            .map(|title| title.trim().to_string())
+            .filter(|title| !title.is_empty())
+    }
+}
+
+/// Give `doc`, a merged document, `info` as its document information and a fresh XMP metadata
+/// stream saying the same, in place of any it has; neither when `info` is empty.
+pub fn apply(doc: &mut Document, info: Dictionary) -> crate::Result<()> {
+    // Stripped of everything, see `strip`
+    if info.is_empty() {
+        return Ok(());
+    }
+    set_xmp(doc, xmp_stream(&info, None))?;
+    let info_id = doc.add_object(info);
+    doc.trailer.set("Info", info_id);
+    Ok(())
+}
+
+/// The Info dictionary of a merged document: `info` (an input's, if any) with `metadata` on top,
+/// `title` when it has none, and this program as its producer, created and modified just now.
+pub fn merged_info(info: Option<Dictionary>, title: Option<String>, metadata: &Metadata) -> Dictionary {
+    let mut info = info.unwrap_or_default();
+    if let Some(title) = title.filter(|_| !info.has(b"Title")) {
+        info.set("Title", Object::String(text::encode(&title), StringFormat::Literal));
+    }
+    metadata.update_info(&mut info);
+    // D:YYYYMMDDHHmmSSZ from YYYY-MM-DDTHH:mm:SSZ
+    let date = format!("D:{}", now().replace(['-', ':', 'T'], ""));
+    info.set("Producer", Object::string_literal(PRODUCER));
+    info.set("CreationDate", Object::string_literal(date.clone()));
+    info.set("ModDate", Object::string_literal(date));
+    info
+}
+
+/// Strip `doc` of what could tell who made it and with what: its Info dictionary, XMP metadata
+/// wherever it is, private application data (`PieceInfo`), modification dates and the authors and
+/// dates of comments.
+pub fn strip(doc: &mut Document) {
+    doc.trailer.remove(b"Info");
+    for object in doc.objects.values_mut() {
+        let dict = match object {
+            Object::Dictionary(dict) => dict,
+            Object::Stream(stream) => &mut stream.dict,
+            _ => continue,
+        };
+        for key in [b"Metadata".as_slice(), b"PieceInfo", b"LastModified"] {
+            dict.remove(key);
+        }
+        // Form fields use T for their names
+        let subtype = dict.get(b"Subtype").and_then(Object::as_name).ok();
+        if dict.has(b"Rect") && !matches!(subtype, Some(b"Widget") | Some(b"Link") | None) {
+            for key in [b"T".as_slice(), b"M", b"CreationDate"] {
+                dict.remove(key);
+            }
+        }
+    }
+    // The streams and dictionaries nothing points to any more
+    doc.prune_objects();
+}
+
+/// An XMP metadata stream saying what `info` does, with the PDF/A identification `pdfa`, if any.
+/// It stays uncompressed so tools can read it without a PDF parser.
+pub fn xmp_stream(info: &Dictionary, pdfa: Option<Identification>) -> Stream {
+    Stream::new(dictionary! { "Type" => "Metadata", "Subtype" => "XML" }, xmp_packet(info, pdfa).into_bytes()).with_compression(false)
+}
+
+/// Make `xmp` the metadata stream of the catalog of `doc`, in place of the one it has.
+pub(crate) fn set_xmp(doc: &mut Document, xmp: Stream) -> crate::Result<()> {
+    let xmp_id = doc.catalog()?.get(b"Metadata").and_then(Object::as_reference).ok();
+    let xmp_id = put(doc, xmp_id, Object::Stream(xmp));
+    doc.catalog_mut()?.set("Metadata", xmp_id);
+    Ok(())
+}
+
+/// Store `object` as `id` when that is an object of `doc`, as a new object otherwise.
+fn put(doc: &mut Document, id: Option<ObjectId>, object: Object) -> ObjectId {
+    match id.filter(|id| doc.objects.contains_key(id)) {
+        Some(id) => {
+            doc.objects.insert(id, object);
+            id
+        }
+        None => doc.add_object(object),
+    }
+}
+
+fn xmp_packet(info: &Dictionary, pdfa: Option<Identification>) -> String {
+    let text = |key: &[u8]| info.get(key).and_then(Object::as_str).ok().map(|s| xml_escape(&text::decode(s)));
+    let mut properties = String::new();
+    if let Some(Identification { part, conformance }) = pdfa {
+        properties.push_str(&format!("   <pdfaid:part>{}</pdfaid:part>\n   <pdfaid:conformance>{}</pdfaid:conformance>\n", part, conformance));
+    }
+    properties.push_str("   <dc:format>application/pdf</dc:format>\n");
+    if let Some(title) = text(b"Title") {
+        properties.push_str(&format!("   <dc:title><rdf:Alt><rdf:li xml:lang=\"x-default\">{}</rdf:li></rdf:Alt></dc:title>\n", title));
+    }
+    if let Some(author) = text(b"Author") {
+        properties.push_str(&format!("   <dc:creator><rdf:Seq><rdf:li>{}</rdf:li></rdf:Seq></dc:creator>\n", author));
+    }
+    if let Some(subject) = text(b"Subject") {
+        properties.push_str(&format!(
+            "   <dc:description><rdf:Alt><rdf:li xml:lang=\"x-default\">{}</rdf:li></rdf:Alt></dc:description>\n",
+            subject
+        ));
+    }
+    for (key, property) in [(b"Keywords".as_slice(), "pdf:Keywords"), (b"Producer", "pdf:Producer"), (b"Creator", "xmp:CreatorTool")] {
+        if let Some(value) = text(key) {
+            properties.push_str(&format!("   <{0}>{1}</{0}>\n", property, value));
+        }
+    }
+    for (key, property) in [(b"CreationDate".as_slice(), "xmp:CreateDate"), (b"ModDate", "xmp:ModifyDate"), (b"ModDate", "xmp:MetadataDate")] {
+        if let Some(date) = info.get(key).and_then(Object::as_str).ok().and_then(xmp_date) {
+            properties.push_str(&format!("   <{0}>{1}</{0}>\n", property, date));
+        }
+    }
+
+    format!(
+        "<?xpacket begin=\"\u{feff}\" id=\"W5M0MpCehiHzreSzNTczkc9d\"?>\n\
+         <x:xmpmeta xmlns:x=\"adobe:ns:meta/\">\n\
+         <rdf:RDF xmlns:rdf=\"http://www.w3.org/1999/02/22-rdf-syntax-ns#\">\n\
+         <rdf:Description rdf:about=\"\"\n\
+         \x20   xmlns:pdfaid=\"http://www.aiim.org/pdfa/ns/id/\"\n\
+         \x20   xmlns:dc=\"http://purl.org/dc/elements/1.1/\"\n\
+         \x20   xmlns:pdf=\"http://ns.adobe.com/pdf/1.3/\"\n\
+         \x20   xmlns:xmp=\"http://ns.adobe.com/xap/1.0/\">\n\
+         {}\
+         </rdf:Description>\n\
+         </rdf:RDF>\n\
+         </x:xmpmeta>\n\
+         <?xpacket end=\"w\"?>",
+        properties
+    )
+}
+
+fn xml_escape(s: &str) -> String {
+    s.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;")
+}
+
+/// A PDF date (`D:YYYYMMDDHHmmSSOHH'mm`, everything after the year optional) as XMP writes
+/// dates, `YYYY-MM-DDTHH:mm:SS` and the offset.
+fn xmp_date(date: &[u8]) -> Option<String> {
+    let date = std::str::from_utf8(date).ok()?;
+    let date = date.strip_prefix("D:").unwrap_or(date);
+    let digits = date.bytes().take_while(u8::is_ascii_digit).count();
+    if digits < 4 {
+        return None;
+    }
+    let part = |from: usize, default: &'static str| date.get(from..from + 2).filter(|_| digits >= from + 2).unwrap_or(default);
+    let offset = match &date[digits.min(14)..] {
+        "" | "Z" => "Z".to_string(),
+        rest if rest.starts_with(['+', '-']) => {
+            let numbers = rest[1..].replace('\'', "");
+            format!("{}{}:{}", &rest[..1], numbers.get(..2)?, numbers.get(2..4).unwrap_or("00"))
+        }
+        rest if rest.starts_with("Z") => "Z".to_string(),
+        _ => return None,
+    };
+    Some(format!("{}-{}-{}T{}:{}:{}{}", &date[..4], part(4, "01"), part(6, "01"), part(8, "00"), part(10, "00"), part(12, "00"), offset))
+}
+
+/// The current time (UTC) as YYYY-MM-DDThh:mm:ssZ.
+pub fn now() -> String {
+    timestamp(SystemTime::now())
+}
+
+/// `time` (UTC) as YYYY-MM-DDThh:mm:ssZ.
+pub fn timestamp(time: SystemTime) -> String {
+    let seconds = time.duration_since(SystemTime::UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0);
+    // Civil date from days since the epoch (Howard Hinnant's algorithm)
+    let z = (seconds / 86_400) as i64 + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z - era * 146_097;
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    let time = seconds % 86_400;
+    format!("{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z", year, month, day, time / 3600, time / 60 % 60, time % 60)
+}
@@ -0,0 +1,58 @@
+use std::{fs::File, io, ops::Deref, path::Path};
+
+/// The contents of a file, mapped into memory where the platform allows it and read otherwise.
+///
+/// A mapping is read in by the kernel as pages are touched and can be dropped again under memory
+/// pressure, so a large scan never has to sit in the heap as a whole. The file must not be
+/// truncated while it is mapped; inputs are only ever read, the output is never one of them.
+pub enum Contents {
+    #[cfg(unix)]
+    Mapped { ptr: *mut libc::c_void, len: usize },
+    Read(Vec<u8>),
+}
+
+// The mapping is private and read-only, nothing writes through the pointer
+unsafe impl Send for Contents {}
+unsafe impl Sync for Contents {}
+
+/// The contents of the file at `path`, see `Contents`.
+pub fn read(path: &Path) -> io::Result<Contents> {
+    let file = File::open(path)?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::io::AsRawFd;
+
+        let len = usize::try_from(file.metadata()?.len()).map_err(|_| io::Error::new(io::ErrorKind::OutOfMemory, "file too large to map"))?;
+        // Nothing to map, and mmap refuses an empty mapping
+        if len > 0 {
+            let ptr = unsafe { libc::mmap(std::ptr::null_mut(), len, libc::PROT_READ, libc::MAP_PRIVATE, file.as_raw_fd(), 0) };
+            if ptr != libc::MAP_FAILED {
+                return Ok(Contents::Mapped { ptr, len });
+            }
+        }
+    }
+    let mut bytes = Vec::new();
+    io::Read::read_to_end(&mut &file, &mut bytes)?;
+    Ok(Contents::Read(bytes))
+}
+
+impl Deref for Contents {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        match self {
+            #[cfg(unix)]
+            Contents::Mapped { ptr, len } => unsafe { std::slice::from_raw_parts(*ptr as *const u8, *len) },
+            Contents::Read(bytes) => bytes,
+        }
+    }
+}
+
+impl Drop for Contents {
+    fn drop(&mut self) {
+        #[cfg(unix)]
+        if let Contents::Mapped { ptr, len } = *self {
+            unsafe { libc::munmap(ptr, len) };
+        }
+    }
+}
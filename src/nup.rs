@@ -0,0 +1,172 @@
+use std::collections::HashMap;
+
+use lopdf::{dictionary, Dictionary, Document, Object, ObjectId, Stream};
+
+use crate::messages::tr;
+
+/// Put every `per_sheet` (2 or 4) consecutive pages of `doc` onto one sheet, each page turned
+/// into a Form XObject, scaled to its cell and centered in it.
+///
+/// Sheets are as large as the first page: 2-up sheets are turned to landscape and hold two
+/// pages side by side, 4-up sheets keep its orientation and hold a 2 by 2 grid. Bookmarks move
+/// to the sheet holding their page; annotations, links included, are left behind with the pages.
+pub fn impose(doc: &mut Document, per_sheet: usize, compress: bool) -> crate::Result<()> {
+    let columns = match per_sheet {
+        2 | 4 => 2,
+        n => return Err(crate::Error::Invalid(tr("cannot put {} pages on a sheet, only 2 or 4", &[&n]))),
+    };
+    let page_ids = doc.get_pages().into_values().collect::<Vec<_>>();
+    let sheets = page_ids.chunks(per_sheet).map(|group| group.iter().copied().map(Some).collect()).collect();
+    compose(doc, sheets, columns, per_sheet / columns, compress)
+}
+
+/// Reorder the pages of `doc` into 2-up sheets that, printed double-sided and folded in the
+/// middle, make a saddle-stitched booklet: the last page beside the first, then the second
+/// beside the second to last, and so on. Blank cells pad the pages to a multiple of four.
+///
+/// Sheets are laid out like `impose` with 2 pages per sheet.
+pub fn booklet(doc: &mut Document, compress: bool) -> crate::Result<()> {
+    let mut pages = doc.get_pages().into_values().map(Some).collect::<Vec<_>>();
+    pages.resize(pages.len().next_multiple_of(4), None);
+    let last = pages.len() - 1;
+    // The outer page of every side is on the left of the front and the right of the back
+    let sheets = (0..pages.len() / 2)
+        .map(|i| if i % 2 == 0 { vec![pages[last - i], pages[i]] } else { vec![pages[i], pages[last - i]] })
+        .collect();
+    compose(doc, sheets, 2, 1, compress)
+}
+
+/// Replace the pages of `doc` by `sheets` of `columns` by `rows` cells, every cell holding the
+/// page with that id or nothing.
+fn compose(doc: &mut Document, sheets: Vec<Vec<Option<ObjectId>>>, columns: usize, rows: usize, compress: bool) -> crate::Result<()> {
+    let pages_id = doc.catalog()?.get(b"Pages").and_then(Object::as_reference)?;
+    let page_ids = doc.get_pages().into_values().collect::<Vec<_>>();
+    let Some(&first) = page_ids.first() else { return Ok(()) };
+    let (width, height) = displayed_size(doc, first)?;
+    let (sheet_width, sheet_height) = if rows == 1 {
+        (width.max(height), width.min(height))
+    } else {
+        (width, height)
+    };
+    let (cell_width, cell_height) = (sheet_width / columns as f32, sheet_height / rows as f32);
+
+    let mut sheet_ids = Vec::new();
+    let mut sheet_of = HashMap::new();
+    for cells in sheets {
+        let sheet_id = doc.new_object_id();
+        let mut content = String::new();
+        let mut xobjects = Dictionary::new();
+        for (n, page_id) in cells.into_iter().enumerate() {
+            let Some(page_id) = page_id else { continue };
+            let (form, [left, bottom, right, top], rotation) = form_xobject(doc, page_id, compress)?;
+            let form_id = doc.add_object(form);
+            let name = format!("P{}", n);
+            xobjects.set(name.as_bytes(), form_id);
+            sheet_of.insert(page_id, sheet_id);
+
+            let (w, h) = (right - left, top - bottom);
+            let (shown_w, shown_h) = if rotation % 180 == 90 { (h, w) } else { (w, h) };
+            let scale = (cell_width / shown_w).min(cell_height / shown_h);
+            // Cells fill left to right, then top to bottom
+            let (column, row) = (n % columns, n / columns);
+            let x = column as f32 * cell_width + (cell_width - shown_w * scale) / 2.0;
+            let y = sheet_height - (row + 1) as f32 * cell_height + (cell_height - shown_h * scale) / 2.0;
+            // The page's own /Rotate, clockwise, as a matrix from its box moved to the origin
+            let turn = match rotation {
+                90 => format!(" 0 -1 1 0 0 {} cm", w),
+                180 => format!(" -1 0 0 -1 {} {} cm", w, h),
+                270 => format!(" 0 1 -1 0 {} 0 cm", h),
+                _ => String::new(),
+            };
+            let shift = if left != 0.0 || bottom != 0.0 {
+                format!(" 1 0 0 1 {} {} cm", 0.0 - left, 0.0 - bottom)
+            } else {
+                String::new()
+            };
+            content.push_str(&format!("q {} 0 0 {} {} {} cm{}{} /{} Do Q\n", scale, scale, x, y, turn, shift, name));
+        }
+        let mut stream = Stream::new(Dictionary::new(), content.into_bytes());
+        if compress {
+            let _ = stream.compress();
+        }
+        let contents_id = doc.add_object(stream);
+        doc.objects.insert(
+            sheet_id,
+            Object::Dictionary(dictionary! {
+                "Type" => "Page",
+                "Parent" => pages_id,
+                "MediaBox" => vec![0.into(), 0.into(), sheet_width.into(), sheet_height.into()],
+                "Contents" => contents_id,
+                "Resources" => dictionary! { "XObject" => xobjects },
+            }),
+        );
+        sheet_ids.push(sheet_id);
+    }
+
+    for page_id in &page_ids {
+        doc.objects.remove(page_id);
+    }
+    let pages = doc.get_dictionary_mut(pages_id)?;
+    pages.set("Count", sheet_ids.len() as i64);
+    pages.set("Kids", sheet_ids.into_iter().map(Object::Reference).collect::<Vec<_>>());
+    for bookmark in doc.bookmark_table.values_mut() {
+        if let Some(sheet) = sheet_of.get(&bookmark.page) {
+            bookmark.page = *sheet;
+        }
+    }
+    // Labels counted the pages, which are gone
+    doc.catalog_mut()?.remove(b"PageLabels");
+    // The forms hold copies of the page contents, the originals are unreachable now
+    doc.prune_objects();
+    Ok(())
+}
+
+/// Width and height of a page as displayed, after its `/Rotate`.
+fn displayed_size(doc: &Document, page_id: ObjectId) -> crate::Result<(f32, f32)> {
+    let [left, bottom, right, top] = page_box(doc, doc.get_dictionary(page_id)?);
+    let (w, h) = (right - left, top - bottom);
+    Ok(if rotation(doc.get_dictionary(page_id)?) % 180 == 90 { (h, w) } else { (w, h) })
+}
+
+/// A Form XObject drawing the page, together with its box and rotation.
+pub(crate) fn form_xobject(doc: &Document, page_id: ObjectId, compress: bool) -> crate::Result<(Stream, [f32; 4], i64)> {
+    let page = doc.get_dictionary(page_id)?;
+    let page_box = page_box(doc, page);
+    // Separate content streams of a page only need to join at token boundaries
+    let mut content = Vec::new();
+    for id in doc.get_page_contents(page_id) {
+        if let Ok(stream) = doc.get_object(id).and_then(Object::as_stream) {
+            content.extend(stream.decompressed_content().unwrap_or_else(|_| stream.content.clone()));
+            content.push(b'\n');
+        }
+    }
+    let mut form = Stream::new(
+        dictionary! {
+            "Type" => "XObject",
+            "Subtype" => "Form",
+            "BBox" => page_box.iter().map(|&v| v.into()).collect::<Vec<Object>>(),
+            "Resources" => page.get(b"Resources").cloned().unwrap_or_else(|_| Dictionary::new().into()),
+        },
+        content,
+    );
+    if compress {
+        let _ = form.compress();
+    }
+    Ok((form, page_box, rotation(page)))
+}
+
+/// The visible box of a page: its CropBox, else its MediaBox, else US Letter.
+fn page_box(doc: &Document, page: &Dictionary) -> [f32; 4] {
+    [b"CropBox".as_slice(), b"MediaBox"]
+        .into_iter()
+        .filter_map(|key| page.get_deref(key, doc).and_then(Object::as_array).ok())
+        .find_map(|values| values.iter().map(|v| v.as_float().ok()).collect::<Option<Vec<_>>>())
+        .and_then(|values| <[f32; 4]>::try_from(values).ok())
+        .map(|[x0, y0, x1, y1]| [x0.min(x1), y0.min(y1), x0.max(x1), y0.max(y1)])
+        .filter(|[x0, y0, x1, y1]| x1 > x0 && y1 > y0)
+        .unwrap_or([0.0, 0.0, 612.0, 792.0])
+}
+
+fn rotation(page: &Dictionary) -> i64 {
+    page.get(b"Rotate").and_then(Object::as_i64).unwrap_or(0).rem_euclid(360)
+}
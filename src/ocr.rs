@@ -0,0 +1,169 @@
+#[cfg(feature = "ocr")]
+use std::{
+    fs,
+    path::Path,
+    process::{Command, Stdio},
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use lopdf::Document;
+#[cfg(feature = "ocr")]
+use lopdf::{Object, ObjectId, Stream};
+
+use crate::{input::InputSpec, messages::tr};
+#[cfg(feature = "ocr")]
+use crate::{blank, input, pages, stamp, tempdir::TempDir};
+
+/// Inputs recognized so far, each in a scratch directory of its own.
+#[cfg(feature = "ocr")]
+static RECOGNIZED: AtomicUsize = AtomicUsize::new(0);
+
+/// The image of a scanned page as a file tesseract reads: its extension and bytes.
+#[cfg(feature = "ocr")]
+type ScanFile = (&'static str, Vec<u8>);
+
+/// Give the scanned pages of `doc` that `spec` selects an invisible text layer, so the merged
+/// document can be searched and copied from. A page counts as scanned when its content shows
+/// images and no text; its largest image is read by the system tesseract in `languages` (like
+/// `eng+deu`) and the text it finds laid over the page, fitted to it. Pages run through
+/// tesseract side by side, as many at a time as there are cores.
+///
+/// Images stored in formats other than JPEG, or 1 and 8 bit gray and RGB samples, are left as
+/// they are, with a warning.
+#[cfg(feature = "ocr")]
+pub fn ocr(spec: &InputSpec, doc: &mut Document, languages: &str, compress: bool) -> crate::Result<()> {
+    let mut scans = Vec::new();
+    let mut unreadable = 0;
+    for (number, page_id) in doc.get_pages() {
+        if !spec.selects(number) {
+            continue;
+        }
+        match scanned_image(doc, page_id) {
+            Some(Some(file)) => scans.push((number, page_id, file)),
+            Some(None) => unreadable += 1,
+            None => {}
+        }
+    }
+    if unreadable > 0 {
+        log::warn!(path:% = spec.path.display(); "{}", tr("{} scanned page(s) have images OCR cannot read and were left as they are", &[&unreadable]));
+    }
+    if scans.is_empty() {
+        return Ok(());
+    }
+    let scratch = TempDir::named(&format!("ocr-{}", RECOGNIZED.fetch_add(1, Ordering::Relaxed)))?;
+    let layers = input::load_all(&scans, |(number, _, file)| recognize(scratch.path(), &spec.path, *number, file, languages), |_, _| {});
+    for ((number, page_id, _), layer) in scans.iter().zip(layers) {
+        let mut layer = layer?;
+        // Drawn in the page's own coordinates, the text turns with the page as its image does
+        let rotation = doc.get_dictionary(*page_id)?.get(b"Rotate").and_then(Object::as_i64).unwrap_or(0);
+        if let Some(&layer_page) = layer.get_pages().values().next() {
+            layer.get_dictionary_mut(layer_page)?.set("Rotate", rotation);
+        }
+        let overlay = stamp::Overlay { pages: Some(std::iter::once(*number).collect()), ..stamp::Overlay::page(layer, false) };
+        stamp::overlay(doc, &overlay, compress)?;
+    }
+    log::info!("{}", tr("{}: recognized the text of {} scanned page(s)", &[&spec.path.display(), &scans.len()]));
+    Ok(())
+}
+
+#[cfg(not(feature = "ocr"))]
+pub fn ocr(spec: &InputSpec, _doc: &mut Document, _languages: &str, _compress: bool) -> crate::Result<()> {
+    Err(crate::Error::Invalid(tr("{}: OCR needs merge_pdf built with the `ocr` feature", &[&spec.path.display()])))
+}
+
+/// The largest image of a page that shows images and no text, `None` for any other page and
+/// `Some(None)` when the image cannot be written for tesseract. Pages drawing forms or inline
+/// images are not looked into.
+#[cfg(feature = "ocr")]
+fn scanned_image(doc: &Document, page_id: ObjectId) -> Option<Option<ScanFile>> {
+    let Ok(Object::Dictionary(page)) = pages::with_inherited_attributes(doc, page_id) else { return None };
+    let content = doc.get_and_decode_page_content(page_id).ok()?;
+    let xobjects = page.get_deref(b"Resources", doc).and_then(Object::as_dict).ok()?.get_deref(b"XObject", doc).and_then(Object::as_dict).ok()?;
+    let mut images = Vec::new();
+    for operation in &content.operations {
+        match operation.operator.as_str() {
+            "Tj" | "TJ" | "'" | "\"" | "BI" => return None,
+            "Do" => {
+                let name = operation.operands.first()?.as_name().ok()?;
+                let xobject = xobjects.get_deref(name, doc).and_then(Object::as_stream).ok()?;
+                if xobject.dict.get(b"Subtype").and_then(Object::as_name).ok()? != b"Image" {
+                    return None;
+                }
+                images.push(xobject);
+            }
+            _ => {}
+        }
+    }
+    let size = |image: &&Stream| ["Width", "Height"].iter().map(|key| image.dict.get(key.as_bytes()).and_then(Object::as_i64).unwrap_or(0)).product::<i64>();
+    let image = images.into_iter().max_by_key(size)?;
+    Some(scan_file(doc, image))
+}
+
+/// `image` as a JPEG file when it is stored as one, or as a PBM, PGM or PPM file of its samples.
+#[cfg(feature = "ocr")]
+fn scan_file(doc: &Document, image: &Stream) -> Option<ScanFile> {
+    let dict = &image.dict;
+    let filter = match dict.get(b"Filter") {
+        Ok(Object::Array(filters)) if filters.len() == 1 => filters[0].as_name().ok(),
+        Ok(filter) => filter.as_name().ok(),
+        Err(_) => None,
+    };
+    if filter == Some(b"DCTDecode") {
+        return Some(("jpg", image.content.clone()));
+    }
+    if dict.get(b"ImageMask").and_then(Object::as_bool).unwrap_or(false) {
+        return None;
+    }
+    let components = match dict.get_deref(b"ColorSpace", doc).and_then(Object::as_name) {
+        Ok(b"DeviceGray") => 1,
+        Ok(b"DeviceRGB") => 3,
+        _ => return None,
+    };
+    let number = |key: &[u8]| dict.get(key).and_then(Object::as_i64).ok().and_then(|n| usize::try_from(n).ok());
+    let (width, height, bits) = (number(b"Width")?, number(b"Height")?, number(b"BitsPerComponent")?);
+    let row_bytes = (width * components * bits).div_ceil(8);
+    let mut samples = blank::image_samples(image, row_bytes, (components * bits).div_ceil(8), height)?;
+    samples.truncate(row_bytes * height);
+    if width == 0 || samples.len() < row_bytes * height {
+        return None;
+    }
+    let inverted = dict.get(b"Decode").and_then(Object::as_array).ok().and_then(|decode| decode.first()?.as_float().ok()) == Some(1.0);
+    let (magic, maxval) = match (components, bits) {
+        // PBM has 1 for black where PDF has 0
+        (1, 1) => {
+            if !inverted {
+                samples.iter_mut().for_each(|byte| *byte = !*byte);
+            }
+            ("P4", "")
+        }
+        (1, 8) => ("P5", " 255"),
+        (3, 8) => ("P6", " 255"),
+        _ => return None,
+    };
+    if inverted && bits == 8 {
+        samples.iter_mut().for_each(|byte| *byte = !*byte);
+    }
+    let mut file = format!("{}\n{} {}{}\n", magic, width, height, maxval).into_bytes();
+    file.extend_from_slice(&samples);
+    Some(("pnm", file))
+}
+
+/// The text layer tesseract makes of page `number` of `name` from `file`, as a one-page PDF.
+#[cfg(feature = "ocr")]
+fn recognize(dir: &Path, name: &Path, number: u32, (extension, data): &ScanFile, languages: &str) -> crate::Result<Document> {
+    let image = dir.join(format!("{}.{}", number, extension));
+    fs::write(&image, data)?;
+    let base = dir.join(number.to_string());
+    let output = Command::new("tesseract")
+        .arg(&image)
+        .arg(&base)
+        .args(["-l", languages, "-c", "textonly_pdf=1", "pdf"])
+        .stdin(Stdio::null())
+        .output()
+        .map_err(|e| crate::Error::Invalid(tr("tesseract could not be run, is it installed? {}", &[&e])))?;
+    if !output.status.success() {
+        let message = String::from_utf8_lossy(&output.stderr);
+        return Err(crate::Error::Invalid(tr("{}: tesseract failed on page {}: {}", &[&name.display(), &number, &message.trim()])));
+    }
+    Ok(Document::load(base.with_extension("pdf"))?)
+}
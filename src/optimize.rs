@@ -0,0 +1,214 @@
+use std::collections::HashMap;
+
+use lopdf::{content::Content, Dictionary, Document, Object, ObjectId, Stream};
+
+use crate::{
+    image,
+    input::InputSpec,
+    jpeg,
+    messages::tr,
+    pages,
+    redact::{self, Matrix, IDENTITY},
+};
+
+/// How deep forms inside forms are followed looking for the images they show.
+const MAX_DEPTH: usize = 16;
+
+/// How images are shrunk, see `optimize_images`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ImageOptimization {
+    /// Resolution, in pixels per inch as placed on the page, above which images are downsampled
+    pub max_dpi: f32,
+    /// Quality, from 1 to 100, of the images written as JPEG
+    pub jpeg_quality: u8,
+}
+
+impl Default for ImageOptimization {
+    fn default() -> Self {
+        ImageOptimization { max_dpi: 150.0, jpeg_quality: 80 }
+    }
+}
+
+/// Downsample the images of `doc` shown at more than `max_dpi`, at the largest size its pages
+/// show them, and write them again as JPEG or with Flate, whichever is smaller. Only 8-bit images
+/// stored raw, with Flate or as JPEG can be read; others are left as they are, with a warning,
+/// and so are images that no page content places (in patterns or annotations).
+pub fn optimize_images(spec: &InputSpec, doc: &mut Document, optimization: ImageOptimization) -> crate::Result<()> {
+    let (mut downsampled, mut saved, mut left) = (0, 0, 0);
+    for (id, (placed_width, placed_height)) in placements(doc)? {
+        let Ok(Object::Stream(image)) = doc.get_object(id) else { continue };
+        let number = |key: &[u8]| image.dict.get_deref(key, doc).and_then(Object::as_i64).ok().and_then(|n| usize::try_from(n).ok());
+        let (Some(width), Some(height)) = (number(b"Width"), number(b"Height")) else { continue };
+        // Pixels enough for the resolution asked for, in points at 72 per inch
+        let needed = |points: f32, pixels: usize| ((points / 72.0 * optimization.max_dpi).ceil() as usize).clamp(1, pixels);
+        let size = (needed(placed_width, width), needed(placed_height, height));
+        if size == (width, height) {
+            continue;
+        }
+        match downsample(doc, image, (width, height), size, optimization.jpeg_quality) {
+            Ok(Some(smaller)) => {
+                downsampled += 1;
+                saved += image.content.len() - smaller.content.len();
+                doc.objects.insert(id, Object::Stream(smaller));
+            }
+            Ok(None) => {}
+            Err(_) => left += 1,
+        }
+    }
+    if downsampled > 0 {
+        log::info!("{}", tr("{}: downsampled {} image(s), {} KB smaller", &[&spec.path.display(), &downsampled, &(saved / 1024)]));
+    }
+    if left > 0 {
+        log::warn!(
+            path:% = spec.path.display();
+            "{}",
+            tr("{} image(s) over {} dpi are stored in a way that cannot be read and were left as they are", &[&left, &optimization.max_dpi])
+        );
+    }
+    Ok(())
+}
+
+/// The largest width and height, in points, at which the content of the pages of `doc` shows
+/// each image, soft masks going with their images.
+fn placements(doc: &Document) -> crate::Result<HashMap<ObjectId, (f32, f32)>> {
+    let mut placed = HashMap::new();
+    for page_id in doc.get_pages().into_values() {
+        let resources = match pages::with_inherited_attributes(doc, page_id)? {
+            Object::Dictionary(page) => page.get_deref(b"Resources", doc).and_then(Object::as_dict).cloned().ok(),
+            _ => None,
+        };
+        let Ok(content) = doc.get_page_content(page_id).and_then(|content| Content::decode(&content)) else { continue };
+        place(doc, &content, resources.as_ref(), IDENTITY, 0, &mut placed);
+    }
+    Ok(placed)
+}
+
+fn place(
+    doc: &Document,
+    content: &Content,
+    resources: Option<&Dictionary>,
+    mut ctm: Matrix,
+    depth: usize,
+    placed: &mut HashMap<ObjectId, (f32, f32)>,
+) {
+    let mut saved = Vec::new();
+    for operation in &content.operations {
+        match operation.operator.as_str() {
+            "q" => saved.push(ctm),
+            "Q" => ctm = saved.pop().unwrap_or(ctm),
+            "cm" => {
+                if let Some(matrix) = redact::matrix(&operation.operands) {
+                    ctm = redact::multiply(matrix, ctm);
+                }
+            }
+            "Do" => {
+                let Some(Ok(name)) = operation.operands.first().map(Object::as_name) else { continue };
+                let xobjects = resources.and_then(|resources| resources.get_deref(b"XObject", doc).and_then(Object::as_dict).ok());
+                let Some(Ok(id)) = xobjects.and_then(|xobjects| xobjects.get(name).ok()).map(Object::as_reference) else { continue };
+                let Ok(xobject) = doc.get_object(id).and_then(Object::as_stream) else { continue };
+                match xobject.dict.get(b"Subtype").and_then(Object::as_name) {
+                    Ok(b"Image") => {
+                        // Images fill the unit square of the current matrix
+                        let size = (ctm[0].hypot(ctm[1]), ctm[2].hypot(ctm[3]));
+                        let mask = xobject.dict.get(b"SMask").and_then(Object::as_reference).ok();
+                        for id in std::iter::once(id).chain(mask) {
+                            let largest = placed.entry(id).or_insert((0.0, 0.0));
+                            *largest = (largest.0.max(size.0), largest.1.max(size.1));
+                        }
+                    }
+                    Ok(b"Form") if depth < MAX_DEPTH => {
+                        let matrix = xobject.dict.get(b"Matrix").and_then(Object::as_array).ok().and_then(|matrix| redact::matrix(matrix));
+                        let form_resources = xobject.dict.get_deref(b"Resources", doc).and_then(Object::as_dict).ok().or(resources);
+                        let Ok(form) = xobject.get_plain_content().and_then(|content| Content::decode(&content)) else { continue };
+                        let form_ctm = matrix.map_or(ctm, |matrix| redact::multiply(matrix, ctm));
+                        place(doc, &form, form_resources, form_ctm, depth + 1, placed);
+                    }
+                    _ => {}
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// The image scaled down to `size`, if that takes less room; an error if it cannot be read.
+fn downsample(doc: &Document, image: &Stream, (width, height): (usize, usize), size: (usize, usize), quality: u8) -> Result<Option<Stream>, String> {
+    let dict = &image.dict;
+    // Averaged samples would no longer match the colors a color key mask hides
+    if dict.get(b"ImageMask").and_then(Object::as_bool).unwrap_or(false) || matches!(dict.get(b"Mask"), Ok(Object::Array(_))) {
+        return Err("mask".to_string());
+    }
+    let components = match dict.get(b"ColorSpace") {
+        // Soft masks have no color space of their own
+        Err(_) => 1,
+        Ok(space) => components(doc, space).ok_or("unsupported color space")?,
+    };
+    let (samples, _) = image::decode_samples(image, width, height, components)?;
+    let small = shrink(&samples, (width, height), components, size);
+
+    let flate = image::deflate(&small);
+    let jpeg = (matches!(components, 1 | 3) && dict.has(b"ColorSpace")).then(|| jpeg::encode(size.0, size.1, components, &small, quality));
+    let (filter, content) = match jpeg {
+        Some(jpeg) if jpeg.len() < flate.len() => ("DCTDecode", jpeg),
+        _ => ("FlateDecode", flate),
+    };
+    if content.len() >= image.content.len() {
+        return Ok(None);
+    }
+    let mut dict = dict.clone();
+    dict.remove(b"DecodeParms");
+    dict.set("Width", size.0 as i64);
+    dict.set("Height", size.1 as i64);
+    dict.set("BitsPerComponent", 8);
+    dict.set("Filter", Object::Name(filter.as_bytes().to_vec()));
+    Ok(Some(Stream::new(dict, content)))
+}
+
+/// How many components a pixel has in `space`, for the spaces where averaging pixels makes sense.
+fn components(doc: &Document, space: &Object) -> Option<usize> {
+    let space = match space {
+        Object::Reference(id) => doc.get_object(*id).ok()?,
+        space => space,
+    };
+    match space {
+        Object::Name(name) => match name.as_slice() {
+            b"DeviceGray" | b"G" => Some(1),
+            b"DeviceRGB" | b"RGB" => Some(3),
+            b"DeviceCMYK" | b"CMYK" => Some(4),
+            _ => None,
+        },
+        Object::Array(items) => match items.first()?.as_name().ok()? {
+            b"CalGray" | b"Separation" => Some(1),
+            b"CalRGB" | b"Lab" => Some(3),
+            b"ICCBased" => {
+                let profile = doc.get_object(items.get(1)?.as_reference().ok()?).and_then(Object::as_stream).ok()?;
+                usize::try_from(profile.dict.get(b"N").and_then(Object::as_i64).ok()?).ok()
+            }
+            b"DeviceN" => Some(items.get(1)?.as_array().ok()?.len()),
+            // Averaged palette indices would pick unrelated colors
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// Scale `samples` down to `size` pixels, each the average of the pixels it covers.
+fn shrink(samples: &[u8], (width, height): (usize, usize), components: usize, (new_width, new_height): (usize, usize)) -> Vec<u8> {
+    let mut shrunk = Vec::with_capacity(new_width * new_height * components);
+    let mut sums = vec![0u32; components];
+    for y in 0..new_height {
+        let rows = y * height / new_height..(y + 1) * height / new_height;
+        for x in 0..new_width {
+            let columns = x * width / new_width..(x + 1) * width / new_width;
+            sums.fill(0);
+            for row in rows.clone() {
+                for pixel in samples[(row * width + columns.start) * components..(row * width + columns.end) * components].chunks_exact(components) {
+                    sums.iter_mut().zip(pixel).for_each(|(sum, &sample)| *sum += sample as u32);
+                }
+            }
+            let count = (rows.len() * columns.len()) as u32;
+            shrunk.extend(sums.iter().map(|sum| ((sum + count / 2) / count) as u8));
+        }
+    }
+    shrunk
+}
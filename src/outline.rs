@@ -0,0 +1,409 @@
+use std::{
+    collections::{BTreeMap, HashSet},
+    path::Path,
+    str::FromStr,
+};
+
+use lopdf::{Bookmark, Dictionary, Document, Object, ObjectId, Stream};
+
+use crate::{input::InputSpec, text};
+
+/// How the bookmark of every input is drawn in the merged outline.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+pub enum BookmarkStyle {
+    #[default]
+    Plain,
+    Italic,
+    Bold,
+    BoldItalic,
+}
+
+impl BookmarkStyle {
+    /// The outline item flags (`/F`): 1 for italic, 2 for bold.
+    pub fn format(self) -> u32 {
+        match self {
+            BookmarkStyle::Plain => 0,
+            BookmarkStyle::Italic => 1,
+            BookmarkStyle::Bold => 2,
+            BookmarkStyle::BoldItalic => 3,
+        }
+    }
+
+    /// The style outline item flags stand for; flags other than italic and bold are ignored.
+    pub fn from_format(flags: i64) -> Self {
+        match flags & 3 {
+            1 => BookmarkStyle::Italic,
+            2 => BookmarkStyle::Bold,
+            3 => BookmarkStyle::BoldItalic,
+            _ => BookmarkStyle::Plain,
+        }
+    }
+}
+
+/// The color of a bookmark, written `R,G,B` with components from 0 to 255. Black by default.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct BookmarkColor(pub [f32; 3]);
+
+impl FromStr for BookmarkColor {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("{:?} is not a color like 255,0,0", s);
+        let components = s
+            .split(',')
+            .map(|c| c.trim().parse::<u8>().map(|c| c as f32 / 255.0))
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|_| invalid())?;
+        // The outline item /C array takes components from 0.0 to 1.0
+        Ok(BookmarkColor(components.try_into().map_err(|_| invalid())?))
+    }
+}
+
+/// Which bookmarks the merged outline gets for the inputs.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+pub enum BookmarkMode {
+    /// "Page N" per input, nested below a "Table of Contents" entry by layer
+    #[default]
+    Page,
+    /// One top-level bookmark per input, named after its file stem
+    PerFile,
+    /// Like `per-file`, but named after the document's own title when it has one
+    Metadata,
+    /// Like `per-file`, nested below the sections of a manifest's `outline`
+    Sections,
+    /// No outline at all, and the root input's PageMode is left alone
+    None,
+}
+
+/// The bookmark title of an input in `BookmarkMode::PerFile`.
+pub fn file_title(path: &Path) -> String {
+    path.file_stem().unwrap_or(path.as_os_str()).to_string_lossy().into_owned()
+}
+
+/// A bookmark at a page of one input, e.g. from a manifest.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PageBookmark {
+    pub title: String,
+    /// 1-based page number in the input document.
+    pub page: u32,
+    pub children: Vec<PageBookmark>,
+}
+
+/// `bookmarks` as outline entries of the document whose pages are `pages` (by number, as
+/// `Document::get_pages` returns them). Pages the document doesn't have resolve to nothing.
+pub fn page_entries(bookmarks: &[PageBookmark], pages: &BTreeMap<u32, ObjectId>) -> Vec<OutlineEntry> {
+    bookmarks
+        .iter()
+        .map(|bookmark| OutlineEntry {
+            title: bookmark.title.clone(),
+            page: pages.get(&bookmark.page).copied(),
+            color: BookmarkColor::default(),
+            style: BookmarkStyle::default(),
+            children: page_entries(&bookmark.children, pages),
+        })
+        .collect()
+}
+
+/// The entries that go below an input's bookmark: the bookmarks its spec gives, else the
+/// document's own outline.
+pub fn entries_for(spec: &InputSpec, doc: &Document) -> Vec<OutlineEntry> {
+    if spec.bookmarks.is_empty() {
+        read(doc)
+    } else {
+        page_entries(&spec.bookmarks, &doc.get_pages())
+    }
+}
+
+/// What a bookmark title template can refer to, for one input.
+#[derive(Debug, Clone)]
+pub struct TitleFields<'a> {
+    pub path: &'a Path,
+    /// 1-based position among the merged inputs.
+    pub index: usize,
+    /// First and last page the input fills in the merged document, 1-based.
+    pub first: usize,
+    pub last: usize,
+    /// The document's own title, see `document_title`.
+    pub title: Option<String>,
+}
+
+/// Fill in a bookmark title template: `{file}` is the file name, `{stem}` the file stem, `{index}`
+/// the input's position, `{first}` and `{last}` its page numbers in the merged document, and
+/// `{title}` the document's own title (the file stem when it has none).
+pub fn expand_template(template: &str, fields: &TitleFields) -> String {
+    let stem = file_title(fields.path);
+    let file = fields.path.file_name().map(|f| f.to_string_lossy().into_owned()).unwrap_or_default();
+    template
+        .replace("{file}", &file)
+        .replace("{stem}", &stem)
+        .replace("{index}", &fields.index.to_string())
+        .replace("{first}", &fields.first.to_string())
+        .replace("{last}", &fields.last.to_string())
+        .replace("{title}", fields.title.as_deref().unwrap_or(&stem))
+}
+
+/// The document's own title: the Title of its Info dictionary, else the `dc:title` of its XMP
+/// metadata. Blank titles count as none.
+pub fn document_title(doc: &Document) -> Option<String> {
+    let info_title = doc
+        .trailer
+        .get_deref(b"Info", doc)
+        .and_then(Object::as_dict)
+        .and_then(|info| info.get_deref(b"Title", doc))
+        .and_then(Object::as_str)
+        .map(text::decode)
+        .ok();
+    info_title
+        .filter(|t| !t.trim().is_empty())
+        .or_else(|| xmp_title(doc))
+        .map(|t| t.trim().to_string())
+        .filter(|t| !t.is_empty())
+}
+
+/// The first `rdf:li` of the `dc:title` in the catalog's XMP metadata stream.
+fn xmp_title(doc: &Document) -> Option<String> {
+    xmp_stream_title(doc.catalog().ok()?.get_deref(b"Metadata", doc).and_then(Object::as_stream).ok()?)
+}
+
+/// The first `rdf:li` of the `dc:title` in an XMP metadata stream.
+pub(crate) fn xmp_stream_title(stream: &Stream) -> Option<String> {
+    let content = stream.decompressed_content().unwrap_or_else(|_| stream.content.clone());
+    let xmp = String::from_utf8_lossy(&content);
+    let title = &xmp[xmp.find("<dc:title")?..];
+    let title = &title[..title.find("</dc:title>")?];
+    let item = &title[title.find("<rdf:li")?..];
+    let item = &item[item.find('>')? + 1..];
+    let item = &item[..item.find("</rdf:li>")?];
+    Some(
+        item.replace("&lt;", "<")
+            .replace("&gt;", ">")
+            .replace("&quot;", "\"")
+            .replace("&apos;", "'")
+            .replace("&amp;", "&"),
+    )
+}
+
+/// One entry of a document's outline, with its destination resolved to a page object.
+#[derive(Debug, Clone)]
+pub struct OutlineEntry {
+    pub title: String,
+    pub page: Option<ObjectId>,
+    pub color: BookmarkColor,
+    pub style: BookmarkStyle,
+    pub children: Vec<OutlineEntry>,
+}
+
+/// Read the outline tree of `doc`, top-level entries first. Documents without outlines yield an empty list.
+pub fn read(doc: &Document) -> Vec<OutlineEntry> {
+    let Ok(outlines) = doc.catalog().and_then(|c| c.get_deref(b"Outlines", doc)).and_then(Object::as_dict) else {
+        return Vec::new();
+    };
+    let mut seen = HashSet::new();
+    read_siblings(doc, outlines, &mut seen)
+}
+
+fn read_siblings(doc: &Document, parent: &Dictionary, seen: &mut HashSet<ObjectId>) -> Vec<OutlineEntry> {
+    let mut entries = Vec::new();
+    let mut next = parent.get(b"First").and_then(Object::as_reference).ok();
+    // Outline items are linked lists, a cycle would otherwise never end
+    while let Some(id) = next.filter(|id| seen.insert(*id)) {
+        let Ok(item) = doc.get_dictionary(id) else { break };
+        let title = item
+            .get_deref(b"Title", doc)
+            .and_then(Object::as_str)
+            .map(text::decode)
+            .unwrap_or_default();
+        let destination = item.get_deref(b"Dest", doc).ok().or_else(|| {
+            let action = item.get_deref(b"A", doc).and_then(Object::as_dict).ok()?;
+            (action.get(b"S").and_then(Object::as_name).ok() == Some(b"GoTo".as_slice()))
+                .then(|| action.get_deref(b"D", doc).ok())
+                .flatten()
+        });
+        let color = item
+            .get(b"C")
+            .and_then(Object::as_array)
+            .ok()
+            .and_then(|c| c.iter().map(|v| v.as_float().ok()).collect::<Option<Vec<_>>>())
+            .and_then(|c| <[f32; 3]>::try_from(c).ok())
+            .map(BookmarkColor)
+            .unwrap_or_default();
+        entries.push(OutlineEntry {
+            title,
+            page: destination.and_then(|d| resolve_destination(doc, d)),
+            color,
+            style: item.get(b"F").and_then(Object::as_i64).map(BookmarkStyle::from_format).unwrap_or_default(),
+            children: read_siblings(doc, item, seen),
+        });
+        next = item.get(b"Next").and_then(Object::as_reference).ok();
+    }
+    entries
+}
+
+/// Ids of the outline root of `doc` and of every item below it. Items usually have no `/Type`,
+/// so they can only be told apart from other objects by walking the tree.
+pub fn tree_ids(doc: &Document) -> HashSet<ObjectId> {
+    let mut ids = HashSet::new();
+    let Ok(root) = doc.catalog().and_then(|c| c.get(b"Outlines")).and_then(Object::as_reference) else {
+        return ids;
+    };
+    let mut pending = vec![root];
+    while let Some(id) = pending.pop() {
+        // A cycle would otherwise never end
+        if !ids.insert(id) {
+            continue;
+        }
+        let Ok(item) = doc.get_dictionary(id) else { continue };
+        for key in [b"First".as_slice(), b"Next"] {
+            if let Ok(next) = item.get(key).and_then(Object::as_reference) {
+                pending.push(next);
+            }
+        }
+    }
+    ids
+}
+
+/// Add `entries` and their children to the bookmarks of `res` below `parent`. Entries pointing
+/// at a page `keep` rejects (or nowhere) are left out, their children move up a level instead.
+pub fn attach(res: &mut Document, entries: &[OutlineEntry], parent: u32, keep: &impl Fn(ObjectId) -> bool) {
+    for entry in entries {
+        match entry.page.filter(|page| keep(*page)) {
+            Some(page) => {
+                let bookmark = Bookmark::new(entry.title.clone(), entry.color.0, entry.style.format(), page);
+                let id = res.add_bookmark(bookmark, Some(parent));
+                attach(res, &entry.children, id, keep);
+            }
+            None => attach(res, &entry.children, parent, keep),
+        }
+    }
+}
+
+/// One bookmark of a merged outline, pointing at a page by number, so it can be saved, edited by
+/// hand and applied to another merge (see `MergeOptions::outline`).
+#[derive(Debug, Clone, PartialEq)]
+pub struct OutlineItem {
+    pub title: String,
+    /// 1-based page of the merged document; `None` points at the first page of the children.
+    pub page: Option<u32>,
+    pub color: BookmarkColor,
+    pub style: BookmarkStyle,
+    pub children: Vec<OutlineItem>,
+}
+
+/// The outline of `doc` with destinations as page numbers. Entries pointing at no page of the
+/// document keep no page.
+pub fn items(doc: &Document) -> Vec<OutlineItem> {
+    let numbers = doc.get_pages().into_iter().map(|(number, id)| (id, number)).collect::<BTreeMap<_, _>>();
+    fn convert(entries: Vec<OutlineEntry>, numbers: &BTreeMap<ObjectId, u32>) -> Vec<OutlineItem> {
+        entries
+            .into_iter()
+            .map(|entry| OutlineItem {
+                title: entry.title,
+                page: entry.page.and_then(|id| numbers.get(&id).copied()),
+                color: entry.color,
+                style: entry.style,
+                children: convert(entry.children, numbers),
+            })
+            .collect()
+    }
+    convert(read(doc), &numbers)
+}
+
+/// Add `items` to the bookmarks of `res` below `parent`, looking their pages up in `pages` (the
+/// page objects in page order). Items without a page, or past the last one, get (0, 0), which
+/// `Document::adjust_zero_pages` points at their first child.
+pub fn add_items(res: &mut Document, items: &[OutlineItem], pages: &[ObjectId], parent: Option<u32>) {
+    for item in items {
+        let page = item.page.and_then(|n| pages.get(n.checked_sub(1)? as usize)).copied().unwrap_or((0, 0));
+        let id = res.add_bookmark(Bookmark::new(item.title.clone(), item.color.0, item.style.format(), page), parent);
+        add_items(res, &item.children, pages, Some(id));
+    }
+}
+
+/// Drop the bookmarks of `doc` below level `max_depth` (1 keeps only the top level); their
+/// ancestors at the deepest kept level stand in for them.
+pub fn limit_depth(doc: &mut Document, max_depth: usize) {
+    fn prune(doc: &mut Document, ids: &[u32], depth: usize, max_depth: usize) {
+        for id in ids {
+            let Some(bookmark) = doc.bookmark_table.get_mut(id) else { continue };
+            if depth >= max_depth {
+                let removed = std::mem::take(&mut bookmark.children);
+                remove(doc, &removed);
+            } else {
+                let children = bookmark.children.clone();
+                prune(doc, &children, depth + 1, max_depth);
+            }
+        }
+    }
+    fn remove(doc: &mut Document, ids: &[u32]) {
+        for id in ids {
+            if let Some(bookmark) = doc.bookmark_table.remove(id) {
+                remove(doc, &bookmark.children);
+            }
+        }
+    }
+    let top = doc.bookmarks.clone();
+    prune(doc, &top, 1, max_depth.max(1));
+}
+
+/// Make every bookmark of `doc` top-level, keeping their order as read from top to bottom.
+pub fn flatten(doc: &mut Document) {
+    fn collect(doc: &mut Document, ids: &[u32], order: &mut Vec<u32>) {
+        for id in ids {
+            let Some(bookmark) = doc.bookmark_table.get_mut(id) else { continue };
+            let children = std::mem::take(&mut bookmark.children);
+            order.push(*id);
+            collect(doc, &children, order);
+        }
+    }
+    let top = std::mem::take(&mut doc.bookmarks);
+    let mut order = Vec::new();
+    collect(doc, &top, &mut order);
+    doc.bookmarks = order;
+}
+
+/// Resolve an explicit (`[page /Fit ...]`) or named destination to its page object.
+pub fn resolve_destination(doc: &Document, destination: &Object) -> Option<ObjectId> {
+    match destination {
+        Object::Array(array) => array.first()?.as_reference().ok(),
+        Object::Dictionary(dict) => resolve_destination(doc, dict.get_deref(b"D", doc).ok()?),
+        Object::Reference(id) => resolve_destination(doc, doc.get_object(*id).ok()?),
+        Object::Name(name) | Object::String(name, _) => resolve_destination(doc, find_named_destination(doc, name)?),
+        _ => None,
+    }
+}
+
+fn find_named_destination<'a>(doc: &'a Document, name: &[u8]) -> Option<&'a Object> {
+    let catalog = doc.catalog().ok()?;
+    // PDF 1.1 style /Dests dictionary keyed by name
+    if let Ok(dests) = catalog.get_deref(b"Dests", doc).and_then(Object::as_dict) {
+        if let Ok(found) = dests.get_deref(name, doc) {
+            return Some(found);
+        }
+    }
+    let tree = catalog
+        .get_deref(b"Names", doc)
+        .and_then(Object::as_dict)
+        .and_then(|names| names.get_deref(b"Dests", doc))
+        .and_then(Object::as_dict)
+        .ok()?;
+    find_in_name_tree(doc, tree, name, 0)
+}
+
+fn find_in_name_tree<'a>(doc: &'a Document, node: &'a Dictionary, name: &[u8], depth: usize) -> Option<&'a Object> {
+    if depth > 32 {
+        return None;
+    }
+    if let Ok(names) = node.get(b"Names").and_then(Object::as_array) {
+        for pair in names.chunks_exact(2) {
+            if pair[0].as_str().ok() == Some(name) {
+                return doc.dereference(&pair[1]).ok().map(|(_, o)| o);
+            }
+        }
+    }
+    let kids = node.get(b"Kids").and_then(Object::as_array).ok()?;
+    kids.iter()
+        .filter_map(|kid| doc.dereference(kid).ok().and_then(|(_, o)| o.as_dict().ok()))
+        .find_map(|kid| find_in_name_tree(doc, kid, name, depth + 1))
+}
@@ -0,0 +1,100 @@
+use std::{fs, path::Path};
+
+use clap::ValueEnum;
+
+use crate::{
+    json::{self, Value},
+    messages::tr,
+    outline::{BookmarkColor, BookmarkStyle, OutlineItem},
+};
+
+/// Save an outline as JSON, an array of bookmarks like
+///
+/// ```json
+/// { "title": "Intro", "page": 1, "color": [255, 0, 0], "style": "bold", "children": [] }
+/// ```
+///
+/// where `page` counts from 1 in the merged document. Black, plain and childless bookmarks leave
+/// out `color`, `style` and `children`; bookmarks without a page leave out `page`.
+pub fn write(path: &Path, items: &[OutlineItem]) -> crate::Result<()> {
+    fs::write(path, json::to_string_pretty(&to_value(items)))?;
+    Ok(())
+}
+
+/// Read an outline saved by `write`, possibly edited since.
+pub fn read(path: &Path) -> crate::Result<Vec<OutlineItem>> {
+    let text = fs::read_to_string(path)?;
+    let value = json::parse(&text).map_err(|e| crate::Error::Invalid(tr("{}: {}", &[&path.display(), &e])))?;
+    from_value(&value).map_err(|e| crate::Error::Invalid(tr("{}: {}", &[&path.display(), &e])))
+}
+
+pub(crate) fn to_value(items: &[OutlineItem]) -> Value {
+    Value::Array(
+        items
+            .iter()
+            .map(|item| {
+                let mut members = vec![("title".to_string(), Value::String(item.title.clone()))];
+                if let Some(page) = item.page {
+                    members.push(("page".to_string(), Value::Number(page.into())));
+                }
+                if item.color != BookmarkColor::default() {
+                    let components = item.color.0.iter().map(|c| Value::Number((c * 255.0).round().into())).collect();
+                    members.push(("color".to_string(), Value::Array(components)));
+                }
+                if item.style != BookmarkStyle::default() {
+                    if let Some(name) = item.style.to_possible_value() {
+                        members.push(("style".to_string(), Value::String(name.get_name().to_string())));
+                    }
+                }
+                if !item.children.is_empty() {
+                    members.push(("children".to_string(), to_value(&item.children)));
+                }
+                Value::Object(members)
+            })
+            .collect(),
+    )
+}
+
+fn from_value(value: &Value) -> Result<Vec<OutlineItem>, String> {
+    let items = value.as_array().ok_or_else(|| tr("expected an array of bookmarks", &[]))?;
+    items
+        .iter()
+        .map(|item| {
+            let field = |key: &str| item.get(key).filter(|v| **v != Value::Null);
+            let title = field("title").and_then(Value::as_str).ok_or_else(|| tr("a bookmark is missing its \"title\"", &[]))?;
+            let invalid = |message: &str| tr("bookmark \"{}\": {}", &[&title, &message]);
+            let page = match field("page") {
+                Some(page) => Some(
+                    page.as_i64()
+                        .and_then(|p| u32::try_from(p).ok())
+                        .filter(|p| *p > 0)
+                        .ok_or_else(|| invalid(&tr("\"page\" must be a page number from 1", &[])))?,
+                ),
+                None => None,
+            };
+            let color = match field("color") {
+                Some(color) => {
+                    let components = color
+                        .as_array()
+                        .and_then(|c| c.iter().map(|v| v.as_i64().and_then(|v| u8::try_from(v).ok())).collect::<Option<Vec<_>>>())
+                        .and_then(|c| <[u8; 3]>::try_from(c).ok())
+                        .ok_or_else(|| invalid(&tr("\"color\" must be [R, G, B] from 0 to 255", &[])))?;
+                    BookmarkColor(components.map(|c| c as f32 / 255.0))
+                }
+                None => BookmarkColor::default(),
+            };
+            let style = match field("style") {
+                Some(style) => {
+                    let style = style.as_str().ok_or_else(|| invalid(&tr("\"style\" must be a string", &[])))?;
+                    BookmarkStyle::from_str(style, true).map_err(|e| invalid(&e))?
+                }
+                None => BookmarkStyle::default(),
+            };
+            let children = match field("children") {
+                Some(children) => from_value(children)?,
+                None => Vec::new(),
+            };
+            Ok(OutlineItem { title: title.to_string(), page, color, style, children })
+        })
+        .collect()
+}
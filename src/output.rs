@@ -0,0 +1,229 @@
+use std::{
+    fs,
+    io::{self, Write},
+    path::{Path, PathBuf},
+    thread,
+    time::{Duration, Instant},
+};
+
+use clap::ValueEnum;
+
+use crate::messages::tr;
+
+/// What to do when the output file already exists.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum OnExisting {
+    /// Refuse to write and fail
+    #[default]
+    Fail,
+    /// Pick the next free name: merged-1.pdf, merged-2.pdf, ...
+    Increment,
+    /// Replace the existing file
+    Overwrite,
+}
+
+/// Whether an output of `-` asks for the PDF on stdout.
+pub fn is_stdout(path: &Path) -> bool {
+    path == Path::new("-")
+}
+
+/// Decide the path to save to, given the requested one and the collision policy.
+pub fn target_path(requested: &Path, on_existing: OnExisting) -> crate::Result<PathBuf> {
+    if is_stdout(requested) || !requested.exists() || on_existing == OnExisting::Overwrite {
+        return Ok(requested.to_path_buf());
+    }
+    match on_existing {
+        OnExisting::Fail => Err(crate::Error::Invalid(tr("{} already exists, pass --on-existing increment or overwrite", &[&requested.display()]))),
+        _ => {
+            let stem = requested.file_stem().map(|s| s.to_string_lossy().into_owned()).unwrap_or_default();
+            let extension = requested.extension().map(|e| format!(".{}", e.to_string_lossy())).unwrap_or_default();
+            let candidate = (1u32..)
+                .map(|n| requested.with_file_name(format!("{}-{}{}", stem, n, extension)))
+                .find(|p| !p.exists())
+                .expect("ran out of file names");
+            Ok(candidate)
+        }
+    }
+}
+
+/// A file written next to the output and moved in place by `persist` once it is a whole PDF, so
+/// a merge that fails or is interrupted never leaves a truncated output behind. Dropped before
+/// then, the file goes away. For stdout, see `is_stdout`, it is written in the temporary
+/// directory and copied out as a whole.
+#[derive(Debug)]
+pub struct Partial {
+    path: PathBuf,
+    target: PathBuf,
+}
+
+impl Partial {
+    /// A file for `target`, in its directory so moving it there is a rename.
+    pub fn new(target: &Path) -> Self {
+        if is_stdout(target) {
+            let path = std::env::temp_dir().join(format!("merge_pdf-{}-stdout.partial", std::process::id()));
+            return Partial { path, target: target.to_path_buf() };
+        }
+        let name = target.file_name().map(|name| name.to_string_lossy().into_owned()).unwrap_or_default();
+        let path = target.with_file_name(format!(".{}.{}.partial", name, std::process::id()));
+        Partial { path, target: target.to_path_buf() }
+    }
+
+    /// Where to write the output meanwhile.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// The output.
+    pub fn target(&self) -> &Path {
+        &self.target
+    }
+
+    /// Move the file written in place of the output, if it looks like a whole PDF.
+    pub fn persist(self) -> crate::Result<()> {
+        let failed = |e: io::Error| crate::Error::SaveFailed(self.target.clone(), e);
+        if !is_whole_pdf(&self.path).map_err(failed)? {
+            return Err(failed(io::Error::other("what was written is not a whole PDF")));
+        }
+        self.persist_unchecked()
+    }
+
+    /// Move the file written in place of the output whatever it holds, for outputs that are not PDFs.
+    pub fn persist_unchecked(self) -> crate::Result<()> {
+        let failed = |e: io::Error| crate::Error::SaveFailed(self.target.clone(), e);
+        if is_stdout(&self.target) {
+            let mut stdout = io::stdout().lock();
+            return fs::File::open(&self.path).and_then(|mut file| io::copy(&mut file, &mut stdout)).and_then(|_| io::Write::flush(&mut stdout)).map_err(failed);
+        }
+        fs::rename(&self.path, &self.target).map_err(failed)
+    }
+}
+
+impl Drop for Partial {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+/// An advisory lock on an output, `OUTPUT.lock` next to it holding the id of the process that
+/// took it, so two merges writing the same file do not race. Removed again when dropped.
+pub struct Lock(PathBuf);
+
+impl Lock {
+    /// Lock `output`, waiting up to `wait` for the process holding it to let go; `interrupted`
+    /// is asked between tries. A lock left by a process that is gone is taken over.
+    pub fn acquire(output: &Path, wait: Duration, interrupted: impl Fn() -> crate::Result<()>) -> crate::Result<Lock> {
+        let mut path = output.as_os_str().to_owned();
+        path.push(".lock");
+        let path = PathBuf::from(path);
+        let started = Instant::now();
+        loop {
+            match fs::File::options().write(true).create_new(true).open(&path) {
+                Ok(mut file) => {
+                    let lock = Lock(path);
+                    write!(file, "{}", std::process::id()).map_err(|e| crate::Error::SaveFailed(lock.0.clone(), e))?;
+                    return Ok(lock);
+                }
+                Err(e) if e.kind() == io::ErrorKind::AlreadyExists => {}
+                Err(e) => return Err(crate::Error::SaveFailed(path, e)),
+            }
+            // A lock just made may not have its id written yet, it counts as held
+            let holder = fs::read_to_string(&path).ok().and_then(|id| id.trim().parse::<u32>().ok());
+            if holder.is_some_and(|pid| !is_running(pid)) {
+                log::warn!("{}", tr("{} was left by process {}, which is gone, taking it over", &[&path.display(), &holder.unwrap_or_default()]));
+                let _ = fs::remove_file(&path);
+                continue;
+            }
+            if started.elapsed() >= wait {
+                return Err(crate::Error::Locked { path: output.to_path_buf(), pid: holder });
+            }
+            interrupted()?;
+            thread::sleep(Duration::from_millis(200));
+        }
+    }
+}
+
+impl Drop for Lock {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.0);
+    }
+}
+
+/// Whether the process `pid` is still running; taken to be where that cannot be told.
+fn is_running(pid: u32) -> bool {
+    #[cfg(unix)]
+    {
+        let Ok(pid) = libc::pid_t::try_from(pid) else { return true };
+        // SAFETY: signal 0 only checks that the process exists and may be signalled
+        if unsafe { libc::kill(pid, 0) } == 0 {
+            return true;
+        }
+        io::Error::last_os_error().raw_os_error() != Some(libc::ESRCH)
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = pid;
+        true
+    }
+}
+
+/// Whether the file at `path` has the header of a PDF and a trailer at its end.
+fn is_whole_pdf(path: &Path) -> io::Result<bool> {
+    use io::{Read, Seek, SeekFrom};
+    let mut file = fs::File::open(path)?;
+    let mut header = [0; 5];
+    if file.read_exact(&mut header).is_err() || &header != b"%PDF-" {
+        return Ok(false);
+    }
+    let len = file.metadata()?.len();
+    file.seek(SeekFrom::Start(len.saturating_sub(1024)))?;
+    let mut tail = Vec::new();
+    file.read_to_end(&mut tail)?;
+    let has = |needle: &[u8]| tail.windows(needle.len()).any(|window| window == needle);
+    Ok(has(b"startxref") && has(b"%%EOF"))
+}
+
+/// The path of part `number` (1-based) of a split output: `merged.pdf` becomes `merged-001.pdf`.
+pub fn part_path(output: &Path, number: usize) -> PathBuf {
+    let stem = output.file_stem().map(|s| s.to_string_lossy().into_owned()).unwrap_or_default();
+    let extension = output.extension().map(|e| format!(".{}", e.to_string_lossy())).unwrap_or_default();
+    output.with_file_name(format!("{}-{:03}{}", stem, number, extension))
+}
+
+/// Parse a file size like `25MB`, `500kB`, `1.5GiB` or a plain number of bytes. `k`, `M` and `G`
+/// count in thousands, `Ki`, `Mi` and `Gi` in 1024s.
+pub fn parse_size(s: &str) -> Result<u64, String> {
+    let invalid = || format!("{:?} is not a size, use bytes or a number with KB, MB or GB like 25MB", s);
+    let upper = s.trim().to_ascii_uppercase();
+    let number = upper.trim_end_matches(|c: char| c.is_ascii_alphabetic());
+    let unit = match upper[number.len()..].trim_end_matches('B') {
+        "" => 1.0,
+        "K" => 1e3,
+        "M" => 1e6,
+        "G" => 1e9,
+        "KI" => 1024.0,
+        "MI" => 1024.0 * 1024.0,
+        "GI" => 1024.0 * 1024.0 * 1024.0,
+        _ => return Err(invalid()),
+    };
+    match number.trim().parse::<f64>() {
+        Ok(n) if n.is_finite() && n > 0.0 => Ok((n * unit) as u64),
+        _ => Err(invalid()),
+    }
+}
+
+/// Fill in an output naming pattern: `{first}` is the file stem of the first input, `{count}` the
+/// number of inputs and `{date}` today's date (UTC) as YYYY-MM-DD.
+pub fn expand_pattern(pattern: &str, first: &Path, count: usize) -> PathBuf {
+    let first = first.file_stem().map(|s| s.to_string_lossy().into_owned()).unwrap_or_default();
+    PathBuf::from(
+        pattern
+            .replace("{first}", &first)
+            .replace("{count}", &count.to_string())
+            .replace("{date}", &today()),
+    )
+}
+
+/// Today's date (UTC) as YYYY-MM-DD.
+pub fn today() -> String {
+    crate::metadata::now()[..10].to_string()
+}
@@ -0,0 +1,323 @@
+use std::{collections::BTreeMap, str::FromStr};
+
+use lopdf::{dictionary, Dictionary, Document, Object, ObjectId, Stream};
+
+use crate::{input::InputSpec, messages::tr};
+
+/// Page attributes a `Pages` node passes down to the pages beneath it (PDF 32000-1, 7.7.3.4).
+pub const INHERITABLE_ATTRIBUTES: [&[u8]; 4] = [b"Resources", b"MediaBox", b"CropBox", b"Rotate"];
+
+/// Clone a page dictionary with every inherited attribute written into the page itself.
+///
+/// Once the pages of several documents hang under a single `Pages` node, nothing may be
+/// inherited from that node any more: its values belong to one document only and would
+/// silently apply to the pages of all the others.
+pub fn with_inherited_attributes(doc: &Document, page_id: ObjectId) -> crate::Result<Object> {
+    let mut page = doc.get_dictionary(page_id)?.clone();
+    inherit(doc, &mut page);
+    Ok(Object::Dictionary(page))
+}
+
+/// `with_inherited_attributes`, moving the page out of `doc` instead of cloning it.
+pub fn take_with_inherited_attributes(doc: &mut Document, page_id: ObjectId) -> crate::Result<Object> {
+    let Some(Object::Dictionary(mut page)) = doc.objects.remove(&page_id) else {
+        return Err(lopdf::Error::ObjectNotFound.into());
+    };
+    inherit(doc, &mut page);
+    Ok(Object::Dictionary(page))
+}
+
+/// Write into `page` the attributes it inherits from its ancestors in `doc`.
+fn inherit(doc: &Document, page: &mut Dictionary) {
+    let mut parent = page.get(b"Parent").and_then(Object::as_reference).ok();
+    // Guard against Parent cycles in broken page trees
+    let mut depth = 0;
+    while let Some(parent_id) = parent.filter(|_| depth < 64) {
+        let Ok(node) = doc.get_dictionary(parent_id) else { break };
+        for key in INHERITABLE_ATTRIBUTES {
+            if !page.has(key) {
+                if let Ok(value) = node.get(key) {
+                    page.set(key, value.clone());
+                }
+            }
+        }
+        parent = node.get(b"Parent").and_then(Object::as_reference).ok();
+        depth += 1;
+    }
+}
+
+/// Add `degrees` (a multiple of 90) to a page's clockwise `/Rotate`, keeping it within 0..360.
+pub fn rotate(page: &mut Object, degrees: i64) {
+    if degrees % 360 == 0 {
+        return;
+    }
+    if let Ok(dict) = page.as_dict_mut() {
+        let current = dict.get(b"Rotate").and_then(Object::as_i64).unwrap_or(0);
+        dict.set("Rotate", (current + degrees).rem_euclid(360));
+    }
+}
+
+/// Drop the thumbnail images (`/Thumb`) of the pages of `doc`, which viewers can draw from the
+/// pages themselves; scanners often embed large ones.
+pub fn strip_thumbnails(spec: &InputSpec, doc: &mut Document) -> crate::Result<()> {
+    let mut thumbnails = Vec::new();
+    for page_id in doc.get_pages().into_values() {
+        if let Some(thumbnail) = doc.get_dictionary_mut(page_id)?.remove(b"Thumb") {
+            thumbnails.push(thumbnail);
+        }
+    }
+    for thumbnail in &thumbnails {
+        if let Object::Reference(id) = thumbnail {
+            doc.objects.remove(id);
+        }
+    }
+    if !thumbnails.is_empty() {
+        log::info!("{}", tr("{}: dropped {} page thumbnail(s)", &[&spec.path.display(), &thumbnails.len()]));
+    }
+    Ok(())
+}
+
+/// An empty page as large as `like`, turned the same way and below the same parent.
+pub fn blank_like(like: &Object) -> Object {
+    let mut page = dictionary! { "Type" => "Page", "Resources" => Dictionary::new() };
+    if let Ok(like) = like.as_dict() {
+        for key in [b"Parent".as_slice(), b"MediaBox", b"CropBox", b"Rotate"] {
+            if let Ok(value) = like.get(key) {
+                page.set(key, value.clone());
+            }
+        }
+    }
+    if !page.has(b"MediaBox") {
+        page.set("MediaBox", vec![0.into(), 0.into(), 612.into(), 792.into()]);
+    }
+    Object::Dictionary(page)
+}
+
+/// Which way every page of the merged document should read, see `orient`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+pub enum Orientation {
+    /// Taller than wide
+    Portrait,
+    /// Wider than tall
+    Landscape,
+}
+
+/// Turn a page of `doc` a quarter clockwise when, as displayed (its CropBox or MediaBox after
+/// `/Rotate`), it doesn't read in `orientation`. Square pages and pages without a box are left alone.
+pub fn orient(doc: &Document, page: &mut Object, orientation: Orientation) {
+    let Ok(dict) = page.as_dict() else { return };
+    let page_box = [b"CropBox".as_slice(), b"MediaBox"]
+        .into_iter()
+        .filter_map(|key| dict.get_deref(key, doc).and_then(Object::as_array).ok())
+        .find_map(|values| values.iter().map(|v| v.as_float().ok()).collect::<Option<Vec<_>>>())
+        .filter(|values| values.len() == 4);
+    let Some(page_box) = page_box else { return };
+    let (mut width, mut height) = ((page_box[2] - page_box[0]).abs(), (page_box[3] - page_box[1]).abs());
+    if dict.get(b"Rotate").and_then(Object::as_i64).unwrap_or(0).rem_euclid(180) == 90 {
+        (width, height) = (height, width);
+    }
+    let wrong = match orientation {
+        Orientation::Portrait => width > height,
+        Orientation::Landscape => height > width,
+    };
+    if wrong {
+        rotate(page, 90);
+    }
+}
+
+/// A page size in points, written `A4`, `Letter` or `WxH` (points, or with an `mm` or `in` suffix,
+/// e.g. `210x297mm`).
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PageSize {
+    pub width: f32,
+    pub height: f32,
+}
+
+impl FromStr for PageSize {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let named = match s.to_ascii_lowercase().as_str() {
+            "a3" => Some((842.0, 1191.0)),
+            "a4" => Some((595.0, 842.0)),
+            "a5" => Some((420.0, 595.0)),
+            "letter" => Some((612.0, 792.0)),
+            "legal" => Some((612.0, 1008.0)),
+            _ => None,
+        };
+        if let Some((width, height)) = named {
+            return Ok(PageSize { width, height });
+        }
+        let invalid = || format!("{:?} is not a page size, use A4, Letter or WxH like 595x842 or 210x297mm", s);
+        let (dimensions, unit) = match s.len().checked_sub(2).map(|at| s.split_at(at)) {
+            Some((dimensions, "mm")) => (dimensions, 72.0 / 25.4),
+            Some((dimensions, "in")) => (dimensions, 72.0),
+            _ => (s.strip_suffix("pt").unwrap_or(s), 1.0),
+        };
+        let (width, height) = dimensions.split_once(['x', 'X']).ok_or_else(invalid)?;
+        let length = |n: &str| n.trim().parse::<f32>().ok().filter(|n| n.is_finite() && *n > 0.0).map(|n| n * unit);
+        match (length(width), length(height)) {
+            (Some(width), Some(height)) => Ok(PageSize { width, height }),
+            _ => Err(invalid()),
+        }
+    }
+}
+
+/// Margins in points to trim off a page, written `L,B,R,T` like `36,36,36,36`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Margins {
+    pub left: f32,
+    pub bottom: f32,
+    pub right: f32,
+    pub top: f32,
+}
+
+impl FromStr for Margins {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let values = s
+            .split(',')
+            .map(|n| n.trim().parse::<f32>().ok().filter(|n| n.is_finite() && *n >= 0.0))
+            .collect::<Option<Vec<_>>>();
+        match values.as_deref() {
+            Some(&[left, bottom, right, top]) => Ok(Margins { left, bottom, right, top }),
+            _ => Err(format!("{:?} is not a crop, use four non-negative numbers of points like 36,36,36,36", s)),
+        }
+    }
+}
+
+/// Show only the part of a page inside `margins`, measured in from its MediaBox (before
+/// `/Rotate`), by setting its CropBox. Any CropBox the page had is replaced; pages without a
+/// MediaBox are taken as US Letter.
+pub fn crop(page: &mut Object, margins: Margins) -> crate::Result<()> {
+    let Ok(dict) = page.as_dict_mut() else { return Ok(()) };
+    let [x0, y0, x1, y1] = dict
+        .get(b"MediaBox")
+        .and_then(Object::as_array)
+        .ok()
+        .and_then(|values| values.iter().map(|v| v.as_float().ok()).collect::<Option<Vec<_>>>())
+        .and_then(|values| <[f32; 4]>::try_from(values).ok())
+        .unwrap_or([0.0, 0.0, 612.0, 792.0]);
+    let (left, bottom) = (x0.min(x1) + margins.left, y0.min(y1) + margins.bottom);
+    let (right, top) = (x0.max(x1) - margins.right, y0.max(y1) - margins.top);
+    if right <= left || top <= bottom {
+        return Err(crate::Error::Invalid(tr(
+            "cropping {},{},{},{} leaves nothing of a {} by {} page",
+            &[&margins.left, &margins.bottom, &margins.right, &margins.top, &(x1 - x0).abs(), &(y1 - y0).abs()],
+        )));
+    }
+    dict.set("CropBox", vec![left.into(), bottom.into(), right.into(), top.into()]);
+    Ok(())
+}
+
+/// Scale and center the content of the pages `page_ids` of `doc` to fill `size` as they will be
+/// displayed, that is after their own `/Rotate` plus `extra_rotation`, and make `size` their
+/// MediaBox and CropBox. The content is wrapped in a transformation and link rectangles are moved
+/// along; the other page boxes are dropped, as they no longer match.
+pub fn resize(doc: &mut Document, page_ids: &[ObjectId], size: PageSize, extra_rotation: i64) -> crate::Result<()> {
+    for &page_id in page_ids {
+        let Object::Dictionary(page) = with_inherited_attributes(doc, page_id)? else { continue };
+        let page_box = [b"CropBox".as_slice(), b"MediaBox"]
+            .into_iter()
+            .filter_map(|key| page.get_deref(key, doc).and_then(Object::as_array).ok())
+            .find_map(|values| values.iter().map(|v| v.as_float().ok()).collect::<Option<Vec<_>>>())
+            .filter(|values| values.len() == 4 && values[2] != values[0] && values[3] != values[1]);
+        let Some(page_box) = page_box else { continue };
+        let (left, bottom) = (page_box[0].min(page_box[2]), page_box[1].min(page_box[3]));
+        let (width, height) = ((page_box[2] - page_box[0]).abs(), (page_box[3] - page_box[1]).abs());
+        // Content is laid out before rotation, so a quarter turn swaps the target's sides
+        let rotation = page.get(b"Rotate").and_then(Object::as_i64).unwrap_or(0) + extra_rotation;
+        let (target_width, target_height) = if rotation.rem_euclid(180) == 90 {
+            (size.height, size.width)
+        } else {
+            (size.width, size.height)
+        };
+        let scale = (target_width / width).min(target_height / height);
+        let dx = (target_width - width * scale) / 2.0 - left * scale;
+        let dy = (target_height - height * scale) / 2.0 - bottom * scale;
+
+        let mut contents = match page.get(b"Contents") {
+            Ok(Object::Array(contents)) => contents.clone(),
+            Ok(contents) => vec![contents.clone()],
+            Err(_) => Vec::new(),
+        };
+        let before = doc.add_object(Stream::new(Dictionary::new(), format!("q {} 0 0 {} {} {} cm\n", scale, scale, dx, dy).into_bytes()));
+        let after = doc.add_object(Stream::new(Dictionary::new(), b"\nQ".to_vec()));
+        contents.insert(0, before.into());
+        contents.push(after.into());
+
+        let annotations = match page.get_deref(b"Annots", doc).and_then(Object::as_array) {
+            Ok(annotations) => annotations.iter().filter_map(|a| a.as_reference().ok()).collect::<Vec<_>>(),
+            Err(_) => Vec::new(),
+        };
+        for id in annotations {
+            if let Ok(annotation) = doc.get_dictionary_mut(id) {
+                if let Ok(rect) = annotation.get(b"Rect").and_then(Object::as_array) {
+                    if let Some(rect) = rect.iter().map(|v| v.as_float().ok()).collect::<Option<Vec<_>>>().filter(|r| r.len() == 4) {
+                        let moved = vec![
+                            (rect[0] * scale + dx).into(),
+                            (rect[1] * scale + dy).into(),
+                            (rect[2] * scale + dx).into(),
+                            (rect[3] * scale + dy).into(),
+                        ];
+                        annotation.set("Rect", Object::Array(moved));
+                    }
+                }
+            }
+        }
+
+        let page = doc.get_dictionary_mut(page_id)?;
+        page.set("Contents", contents);
+        let media_box = vec![0.into(), 0.into(), target_width.into(), target_height.into()];
+        // Set rather than removed, or a CropBox could still be inherited from the page tree
+        page.set("CropBox", media_box.clone());
+        page.set("MediaBox", media_box);
+        for key in [b"BleedBox".as_slice(), b"TrimBox", b"ArtBox"] {
+            page.remove(key);
+        }
+    }
+    Ok(())
+}
+
+/// Objects by id, as `Document::objects` holds them.
+pub type Objects = BTreeMap<ObjectId, Object>;
+
+/// What goes between every two inputs of a merge.
+#[derive(Debug, Clone)]
+pub enum Separator {
+    /// A blank page as large as the last page before it.
+    Blank,
+    /// All pages of a divider document.
+    Document(Box<Document>),
+}
+
+impl Separator {
+    /// The separator's pages and objects like `all_pages` gives them; `previous` is the page the
+    /// separator follows.
+    pub fn pages(&self, previous: &Object, max_id: &mut u32) -> crate::Result<(Vec<(ObjectId, Object)>, Objects)> {
+        match self {
+            Separator::Blank => {
+                let id = (*max_id, 0);
+                *max_id += 1;
+                Ok((vec![(id, blank_like(previous))], BTreeMap::new()))
+            }
+            Separator::Document(doc) => all_pages(doc, max_id),
+        }
+    }
+}
+
+/// All pages of a copy of `doc` in order, with inherited attributes written into them, and all
+/// its objects (pages included), numbered from `*max_id` on; `*max_id` moves past them.
+pub fn all_pages(doc: &Document, max_id: &mut u32) -> crate::Result<(Vec<(ObjectId, Object)>, Objects)> {
+    let mut doc = doc.clone();
+    doc.renumber_objects_with(*max_id);
+    *max_id = doc.max_id + 1;
+    let pages = doc
+        .get_pages()
+        .into_values()
+        .map(|id| Ok((id, with_inherited_attributes(&doc, id)?)))
+        .collect::<crate::Result<Vec<_>>>()?;
+    Ok((pages, doc.objects))
+}
@@ -0,0 +1,45 @@
+use std::path::Path;
+
+use crate::{glob, messages::tr};
+
+/// Passwords for encrypted inputs, read from a `--password-file`: one `PATTERN PASSWORD` entry
+/// per line, the pattern a file name or glob like `statement-*.pdf`, the password everything
+/// after the blanks that follow it. Blank lines and lines starting with `#` are skipped.
+///
+/// A pattern with `/` in it matches as many of the last components of the path, e.g.
+/// `bank/*.pdf`, any other just the file name.
+#[derive(Debug, Clone, Default)]
+pub struct PasswordList {
+    entries: Vec<(String, String)>,
+}
+
+impl PasswordList {
+    pub fn read(path: &Path) -> crate::Result<Self> {
+        let text = std::fs::read_to_string(path)?;
+        let mut entries = Vec::new();
+        for (n, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let Some((pattern, password)) = line.split_once(char::is_whitespace) else {
+                return Err(crate::Error::Invalid(tr("{}: line {}: missing the password after \"{}\"", &[&path.display(), &(n + 1), &line])));
+            };
+            entries.push((pattern.to_string(), password.trim_start().to_string()));
+        }
+        Ok(PasswordList { entries })
+    }
+
+    /// The passwords of the entries whose pattern matches `path`, in file order.
+    pub fn matching<'a>(&'a self, path: &Path) -> impl Iterator<Item = &'a str> + 'a {
+        let components = path.components().map(|c| c.as_os_str().to_string_lossy().into_owned()).collect::<Vec<_>>();
+        self.entries
+            .iter()
+            .filter(move |(pattern, _)| {
+                let parts = pattern.split('/').collect::<Vec<_>>();
+                parts.len() <= components.len()
+                    && parts.iter().zip(&components[components.len() - parts.len()..]).all(|(part, name)| glob::matches_name(part, name))
+            })
+            .map(|(_, password)| password.as_str())
+    }
+}
@@ -0,0 +1,320 @@
+use lopdf::{dictionary, Dictionary, Document, Object, ObjectId, Stream, StringFormat};
+use md5::{Digest, Md5};
+
+use crate::{input::InputSpec, messages::tr, metadata};
+
+// PDF/A-2 is based on PDF 1.7. The binary comment the standard requires right after the
+// header is written by `write_to` and `save`.
+const PDFA_VERSION: &str = "1.7";
+
+/// What the merge does about PDF/A, see `MergeOptions::pdfa_mode`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+pub enum Mode {
+    /// Make the output PDF/A-2b, see `apply`
+    Convert,
+    /// Report which inputs are PDF/A and warn that the output will not be
+    Check,
+    /// Keep the output PDF/A when every input is, see `preserve`; warn like `check` otherwise
+    Preserve,
+}
+
+/// The part of the PDF/A standard a document claims to conform to, and the level.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Identification {
+    /// 1, 2 or 3
+    pub part: u8,
+    /// `A`, `U` or `B`
+    pub conformance: char,
+}
+
+impl Identification {
+    pub const PDFA_2B: Identification = Identification { part: 2, conformance: 'B' };
+
+    /// The identification the XMP metadata of `doc`'s catalog gives, if any.
+    pub fn of(doc: &Document) -> Option<Identification> {
+        let xmp = doc.catalog().ok()?.get_deref(b"Metadata", doc).and_then(Object::as_stream).ok()?;
+        let content = xmp.decompressed_content().unwrap_or_else(|_| xmp.content.clone());
+        let xmp = String::from_utf8_lossy(&content);
+        let part = xmp_property(&xmp, "pdfaid:part")?.parse().ok()?;
+        let conformance = xmp_property(&xmp, "pdfaid:conformance").and_then(|level| level.chars().next()).unwrap_or('B');
+        Some(Identification { part, conformance: conformance.to_ascii_uppercase() })
+    }
+
+    /// The identification a merge of documents claiming `identifications` can keep: the latest
+    /// part, which allows what the earlier ones do, and their level if they share it, else B,
+    /// which every level meets.
+    pub fn common(identifications: &[Identification]) -> Option<Identification> {
+        let part = identifications.iter().map(|id| id.part).max()?;
+        let conformance = identifications[0].conformance;
+        let conformance = if identifications.iter().all(|id| id.conformance == conformance) { conformance } else { 'B' };
+        Some(Identification { part, conformance })
+    }
+}
+
+impl std::fmt::Display for Identification {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "PDF/A-{}{}", self.part, self.conformance.to_ascii_lowercase())
+    }
+}
+
+/// The PDF/A identification of the input `spec`, reported when there is one.
+pub fn check_input(spec: &InputSpec, doc: &Document) -> Option<Identification> {
+    let identification = Identification::of(doc);
+    match identification {
+        Some(identification) => log::info!("{}: {}", spec.path.display(), identification),
+        None => log::info!("{}", tr("{}: not PDF/A", &[&spec.path.display()])),
+    }
+    identification
+}
+
+/// Warn, for `mode` `Check` or `Preserve`, when some of the inputs, whose identifications are
+/// `identifications`, are PDF/A and the merged document will not be. Returns what to `preserve`
+/// the merged document as when it can stay PDF/A.
+pub fn report(mode: Mode, identifications: &[Option<Identification>]) -> Option<Identification> {
+    let claimed = identifications.iter().flatten().copied().collect::<Vec<_>>();
+    if claimed.is_empty() {
+        return None;
+    }
+    if mode == Mode::Preserve && claimed.len() == identifications.len() {
+        return Identification::common(&claimed);
+    }
+    let reason = if mode == Mode::Preserve { tr(", as not every input is", &[]) } else { String::new() };
+    log::warn!("{}", tr("{} of {} input(s) are PDF/A, the output will not be{}", &[&claimed.len(), &identifications.len(), &reason]));
+    None
+}
+
+/// Turn an already merged document into a PDF/A-2b candidate.
+///
+/// Adds an sRGB output intent, an XMP metadata stream referenced from the catalog and a
+/// trailer `/ID`. Fails if the document uses features PDF/A forbids (encryption, JavaScript)
+/// or if any font is not embedded, since there is no way to fix that after the fact.
+pub fn apply(doc: &mut Document) -> crate::Result<()> {
+    if let Some(problem) = problems(doc).into_iter().next() {
+        return Err(crate::Error::Invalid(problem));
+    }
+
+    let catalog_id = doc.trailer.get(b"Root").and_then(Object::as_reference)?;
+
+    let profile_id = doc.add_object(Stream::new(dictionary! { "N" => 3 }, srgb_icc_profile()));
+    let intent_id = doc.add_object(dictionary! {
+        "Type" => "OutputIntent",
+        "S" => "GTS_PDFA1",
+        "OutputConditionIdentifier" => Object::string_literal("sRGB IEC61966-2.1"),
+        "Info" => Object::string_literal("sRGB IEC61966-2.1"),
+        "DestOutputProfile" => profile_id,
+    });
+
+    let info = info_dictionary(doc);
+    metadata::set_xmp(doc, metadata::xmp_stream(&info, Some(Identification::PDFA_2B)))?;
+
+    let catalog = doc.get_dictionary_mut(catalog_id)?;
+    catalog.set("OutputIntents", vec![Object::Reference(intent_id)]);
+
+    set_id(doc);
+    doc.version = PDFA_VERSION.to_string();
+    Ok(())
+}
+
+/// Keep a document merged from PDF/A inputs PDF/A: identify it as `identification` in fresh
+/// XMP metadata and give it a trailer `/ID`. Its output intent comes
+/// with the Catalog of the input it was taken from. What the merge added that PDF/A forbids, such
+/// as the fonts of stamps, which are never embedded, is only warned about.
+pub fn preserve(doc: &mut Document, identification: Identification) -> crate::Result<()> {
+    let mut problems = problems(doc);
+    if !doc.catalog()?.has(b"OutputIntents") {
+        problems.push("PDF/A requires an output intent, the kept Catalog has none".to_string());
+    }
+    for problem in problems {
+        log::warn!("{}", tr("the output will not be {}: {}", &[&identification, &problem]));
+    }
+    let info = info_dictionary(doc);
+    metadata::set_xmp(doc, metadata::xmp_stream(&info, Some(identification)))?;
+    set_id(doc);
+    Ok(())
+}
+
+/// What in `doc` PDF/A forbids: encryption, JavaScript and fonts that are not embedded.
+fn problems(doc: &Document) -> Vec<String> {
+    let mut problems = Vec::new();
+    if doc.trailer.has(b"Encrypt") {
+        problems.push("PDF/A forbids encryption".to_string());
+    }
+    if let Some(id) = find_javascript(doc) {
+        problems.push(format!("PDF/A forbids JavaScript (found in object {} {})", id.0, id.1));
+    }
+    let missing = non_embedded_fonts(doc);
+    if !missing.is_empty() {
+        problems.push(format!("PDF/A requires embedded fonts, not embedded: {}", missing.join(", ")));
+    }
+    problems
+}
+
+fn set_id(doc: &mut Document) {
+    let id = document_id(doc);
+    doc.trailer.set(
+        "ID",
+        vec![
+            Object::String(id.clone(), StringFormat::Hexadecimal),
+            Object::String(id, StringFormat::Hexadecimal),
+        ],
+    );
+}
+
+/// The value of an XMP property, written as an element or as an attribute.
+fn xmp_property<'a>(xmp: &'a str, name: &str) -> Option<&'a str> {
+    if let Some(start) = xmp.find(&format!("<{}>", name)) {
+        let value = &xmp[start + name.len() + 2..];
+        return Some(value[..value.find('<')?].trim());
+    }
+    let start = xmp.find(&format!("{}=", name))? + name.len() + 1;
+    let quote = xmp[start..].chars().next()?;
+    let value = &xmp[start + 1..];
+    Some(&value[..value.find(quote)?])
+}
+
+fn find_javascript(doc: &Document) -> Option<ObjectId> {
+    if let Ok(names) = doc.catalog().and_then(|c| c.get_deref(b"Names", doc)).and_then(Object::as_dict) {
+        if names.has(b"JavaScript") {
+            return doc.trailer.get(b"Root").and_then(Object::as_reference).ok();
+        }
+    }
+    doc.objects.iter().find_map(|(id, object)| {
+        let dict = match object {
+            Object::Dictionary(dict) => dict,
+            Object::Stream(stream) => &stream.dict,
+            _ => return None,
+        };
+        let is_js = dict.has(b"JS") || dict.get(b"S").and_then(Object::as_name).ok() == Some(b"JavaScript".as_slice());
+        is_js.then_some(*id)
+    })
+}
+
+fn non_embedded_fonts(doc: &Document) -> Vec<String> {
+    let mut missing = Vec::new();
+    for object in doc.objects.values() {
+        let Ok(font) = object.as_dict() else { continue };
+        if !font.type_is(b"Font") {
+            continue;
+        }
+        let subtype = font.get(b"Subtype").and_then(Object::as_name).unwrap_or_default();
+        // Type0 fonts carry their descriptor on the descendant, Type3 glyphs are content streams
+        if subtype == b"Type0" || subtype == b"Type3" {
+            continue;
+        }
+        let embedded = font
+            .get_deref(b"FontDescriptor", doc)
+            .and_then(Object::as_dict)
+            .map(|d| d.has(b"FontFile") || d.has(b"FontFile2") || d.has(b"FontFile3"))
+            .unwrap_or(false);
+        if !embedded {
+            let name = font.get(b"BaseFont").and_then(Object::as_name_str).unwrap_or("<unnamed>");
+            if !missing.iter().any(|m| m == name) {
+                missing.push(name.to_string());
+            }
+        }
+    }
+    missing
+}
+
+fn info_dictionary(doc: &Document) -> Dictionary {
+    doc.trailer
+        .get_deref(b"Info", doc)
+        .and_then(Object::as_dict)
+        .cloned()
+        .unwrap_or_default()
+}
+
+fn document_id(doc: &Document) -> Vec<u8> {
+    let mut hasher = Md5::new();
+    for ((id, generation), object) in &doc.objects {
+        hasher.update(id.to_be_bytes());
+        hasher.update(generation.to_be_bytes());
+        if let Object::Stream(stream) = object {
+            hasher.update(&stream.content);
+        }
+    }
+    hasher.finalize().to_vec()
+}
+
+/// Build a minimal ICC v2 display profile for sRGB (D50 adapted primaries, gamma 2.2).
+fn srgb_icc_profile() -> Vec<u8> {
+    fn s15f16(v: f64) -> [u8; 4] {
+        ((v * 65536.0).round() as i32).to_be_bytes()
+    }
+    fn xyz(x: f64, y: f64, z: f64) -> Vec<u8> {
+        let mut tag = b"XYZ \0\0\0\0".to_vec();
+        for v in [x, y, z] {
+            tag.extend(s15f16(v));
+        }
+        tag
+    }
+    fn curve(gamma: f64) -> Vec<u8> {
+        let mut tag = b"curv\0\0\0\0".to_vec();
+        tag.extend(1u32.to_be_bytes());
+        tag.extend(((gamma * 256.0).round() as u16).to_be_bytes());
+        tag
+    }
+    fn text(s: &str) -> Vec<u8> {
+        let mut tag = b"text\0\0\0\0".to_vec();
+        tag.extend(s.as_bytes());
+        tag.push(0);
+        tag
+    }
+    fn desc(s: &str) -> Vec<u8> {
+        let mut tag = b"desc\0\0\0\0".to_vec();
+        tag.extend((s.len() as u32 + 1).to_be_bytes());
+        tag.extend(s.as_bytes());
+        tag.push(0);
+        // empty Unicode and ScriptCode descriptions
+        tag.extend([0u8; 8]);
+        tag.extend([0u8; 3]);
+        tag.extend([0u8; 67]);
+        tag
+    }
+
+    let tags: Vec<(&[u8; 4], Vec<u8>)> = vec![
+        (b"desc", desc("sRGB IEC61966-2.1")),
+        (b"cprt", text("No copyright, use freely")),
+        (b"wtpt", xyz(0.9642, 1.0, 0.8249)),
+        (b"rXYZ", xyz(0.4361, 0.2225, 0.0139)),
+        (b"gXYZ", xyz(0.3851, 0.7169, 0.0971)),
+        (b"bXYZ", xyz(0.1431, 0.0606, 0.7141)),
+        (b"rTRC", curve(2.2)),
+        (b"gTRC", curve(2.2)),
+        (b"bTRC", curve(2.2)),
+    ];
+
+    let mut table = Vec::new();
+    let mut data = Vec::new();
+    let data_start = 128 + 4 + 12 * tags.len();
+    for (signature, tag) in &tags {
+        table.extend(*signature);
+        table.extend(((data_start + data.len()) as u32).to_be_bytes());
+        table.extend((tag.len() as u32).to_be_bytes());
+        data.extend(tag);
+        while data.len() % 4 != 0 {
+            data.push(0);
+        }
+    }
+
+    let mut header = Vec::with_capacity(128);
+    header.extend(((data_start + data.len()) as u32).to_be_bytes());
+    header.extend([0u8; 4]); // preferred CMM
+    header.extend([2, 0x10, 0, 0]); // version 2.1
+    header.extend(b"mntrRGB XYZ ");
+    header.extend([0u8; 12]); // creation date
+    header.extend(b"acsp");
+    header.extend([0u8; 24]); // platform, flags, manufacturer, model, attributes
+    header.extend([0u8; 4]); // perceptual intent
+    header.extend(s15f16(0.9642));
+    header.extend(s15f16(1.0));
+    header.extend(s15f16(0.8249));
+    header.resize(128, 0);
+
+    let mut profile = header;
+    profile.extend((tags.len() as u32).to_be_bytes());
+    profile.extend(table);
+    profile.extend(data);
+    profile
+}
@@ -0,0 +1,107 @@
+use std::{collections::HashSet, fs, path::PathBuf};
+
+use lopdf::{dictionary, Document, Object, Stream, StringFormat};
+use md5::{Digest, Md5};
+
+use crate::{attachments, content_id, cover::Cover, encrypt, messages::tr, metadata, output, output_info, text, MergeOptions};
+
+/// Make a PDF portfolio (a collection, PDF 32000-1, 12.3.5) of the files at `paths`, in place of
+/// merging their pages: every file is embedded as it is, byte for byte, and listed with its
+/// name, size and modification date. The document's own pages are the cover of `options`, or a
+/// generated one counting the files, sized by its page size or else Letter; readers that know
+/// no portfolios show the cover and the files as attachments.
+///
+/// Of the rest of `options`, the metadata, compression, encryption and `deterministic` apply.
+pub fn portfolio(paths: &[PathBuf], options: &MergeOptions) -> crate::Result<Document> {
+    let mut doc = Document::with_version("1.7");
+    let (width, height) = options.page_size.map_or((612.0, 792.0), |size| (size.width, size.height));
+    let generated = Cover::Generated { title: "Portfolio".to_string(), date: output::today() };
+    let cover = options.cover.as_ref().unwrap_or(&generated);
+    let mut max_id = 1;
+    let (pages, objects) = cover.pages(paths.len(), [0.0, 0.0, width, height], &mut max_id)?;
+    for (id, object) in objects {
+        if !matches!(object.type_name().unwrap_or(""), "Catalog" | "Pages" | "Page" | "Outlines" | "Outline") {
+            doc.objects.insert(id, object);
+        }
+    }
+    doc.max_id = max_id - 1;
+    let pages_id = doc.new_object_id();
+    let kids = pages.iter().map(|&(id, _)| Object::Reference(id)).collect::<Vec<_>>();
+    for (id, mut page) in pages {
+        if let Object::Dictionary(page) = &mut page {
+            page.set("Parent", pages_id);
+        }
+        doc.objects.insert(id, page);
+    }
+    doc.objects.insert(pages_id, Object::Dictionary(dictionary! { "Type" => "Pages", "Count" => kids.len() as i64, "Kids" => kids }));
+
+    let mut used = HashSet::new();
+    let mut files = Vec::new();
+    for path in paths {
+        let data = fs::read(path).map_err(|e| crate::Error::MissingInput(path.clone(), e))?;
+        let mut params = dictionary! {
+            "Size" => data.len() as i64,
+            "CheckSum" => Object::String(Md5::digest(&data).to_vec(), StringFormat::Hexadecimal),
+        };
+        // The same files give the same portfolio, wherever they were copied
+        let modified = fs::metadata(path).and_then(|metadata| metadata.modified()).ok().filter(|_| !options.deterministic);
+        if let Some(modified) = modified {
+            let date = format!("D:{}", metadata::timestamp(modified).replace(['-', ':', 'T'], ""));
+            params.set("ModDate", Object::string_literal(date));
+        }
+        let mut stream = Stream::new(dictionary! { "Type" => "EmbeddedFile", "Params" => params }, data);
+        if path.extension().is_some_and(|extension| extension.eq_ignore_ascii_case("pdf")) {
+            stream.dict.set("Subtype", Object::Name(b"application/pdf".to_vec()));
+        }
+        if options.compress {
+            let _ = stream.compress();
+        }
+        let file_name = path.file_name().map_or_else(|| path.display().to_string(), |name| name.to_string_lossy().into_owned());
+        let name = attachments::unique_name(&text::encode(&file_name), &mut used);
+        if text::decode(&name) != file_name {
+            log::info!("{}", tr("{}: attachment {} renamed to {}", &[&path.display(), &file_name, &text::decode(&name)]));
+        }
+        let stream_id = doc.add_object(stream);
+        let filespec = doc.add_object(dictionary! {
+            "Type" => "Filespec",
+            "F" => Object::String(name.clone(), StringFormat::Literal),
+            "UF" => Object::String(name.clone(), StringFormat::Literal),
+            "EF" => dictionary! { "F" => stream_id },
+        });
+        files.push((name, filespec));
+    }
+    // Names in a name tree are sorted by their bytes
+    files.sort();
+    let names = files.into_iter().flat_map(|(name, filespec)| [Object::String(name, StringFormat::Literal), Object::Reference(filespec)]).collect::<Vec<_>>();
+
+    let field = |subtype: &str, name: &str, order: i64| {
+        dictionary! { "Type" => "CollectionField", "Subtype" => subtype, "N" => Object::string_literal(name), "O" => order }
+    };
+    let catalog_id = doc.add_object(dictionary! {
+        "Type" => "Catalog",
+        "Pages" => pages_id,
+        "Names" => dictionary! { "EmbeddedFiles" => dictionary! { "Names" => names } },
+        "PageMode" => "UseAttachments",
+        "Collection" => dictionary! {
+            "Type" => "Collection",
+            "View" => "D",
+            "Schema" => dictionary! {
+                "Type" => "CollectionSchema",
+                "Name" => field("F", "Name", 1),
+                "Size" => field("Size", "Size", 2),
+                "Modified" => field("ModDate", "Modified", 3),
+            },
+            "Sort" => dictionary! { "S" => "Name" },
+        },
+    });
+    doc.trailer.set("Root", catalog_id);
+
+    metadata::apply(&mut doc, output_info(options, metadata::Source::default()))?;
+    if options.deterministic {
+        doc.trailer.set("ID", content_id(&doc));
+    }
+    if let Some(encryption) = &options.encryption {
+        encrypt::encrypt(&mut doc, encryption)?;
+    }
+    Ok(doc)
+}
@@ -0,0 +1,168 @@
+use std::{collections::HashMap, path::PathBuf};
+
+use lopdf::{Document, Object, ObjectId};
+
+use crate::{
+    grayscale::{self, Space},
+    messages::tr,
+    pages, Input,
+};
+
+/// How far, as a share of the usual one, a page's width or height may be off before it counts as
+/// another size; scans and exports round paper sizes differently.
+const SIZE_TOLERANCE: f32 = 0.03;
+
+/// Which of the checks of `geometry` to make; those an option already evens out are left out.
+#[derive(Debug, Clone, Copy)]
+pub struct Checks {
+    pub size: bool,
+    pub orientation: bool,
+    pub color: bool,
+}
+
+/// Pages of an input that stand out from most pages of the merge, in one way.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Finding {
+    pub path: PathBuf,
+    /// Page numbers in the input
+    pub pages: Vec<u32>,
+    /// `size`, `orientation` or `color`
+    pub check: &'static str,
+    /// How the pages differ and the option that evens it out
+    pub message: String,
+}
+
+/// What `geometry` looks at of a page.
+struct Page {
+    /// Width and height as displayed, after `/Rotate`, the input's rotation and crop
+    size: Option<(f32, f32)>,
+    color: Option<Space>,
+}
+
+/// The selected pages of `inputs` whose size, orientation or color model differs from that of
+/// most of their pages, counted by page, as a merge that ships in one piece should not have: an
+/// A3 page in a Letter bundle, a landscape page among portrait ones, CMYK among RGB. Sizes are
+/// compared whichever way the pages turn, with `SIZE_TOLERANCE`; the color model of a page is
+/// told by the color spaces its resources and images use, and only CMYK and RGB are told apart.
+pub fn geometry(inputs: &[&Input], checks: Checks) -> Vec<Finding> {
+    let pages = inputs
+        .iter()
+        .map(|input| {
+            let known = grayscale::known_spaces(&input.doc);
+            let pages = input.doc.get_pages().into_iter().filter(|(number, _)| input.spec.selects(*number));
+            pages.map(|(number, page_id)| (number, page(input, page_id, &known))).collect::<Vec<_>>()
+        })
+        .collect::<Vec<_>>();
+    let all = || pages.iter().flatten().map(|(_, page)| page);
+
+    // The size, orientation and color model most pages have
+    let usual_size = most(all().filter_map(|page| page.size).map(|(width, height)| (width.min(height).round() as i64, width.max(height).round() as i64)));
+    let usual_landscape = most(all().filter_map(|page| landscape(page.size?)));
+    let usual_color = most(all().filter_map(|page| page.color));
+
+    let mut findings = Vec::<Finding>::new();
+    for (input, pages) in inputs.iter().zip(&pages) {
+        // An input listed twice has findings of its own each time
+        let mut own = Vec::<Finding>::new();
+        let mut add = |number: u32, check: &'static str, message: String| match own.iter_mut().find(|f| f.check == check && f.message == message) {
+            Some(finding) => finding.pages.push(number),
+            None => own.push(Finding { path: input.spec.path.clone(), pages: vec![number], check, message }),
+        };
+        for (number, page) in pages {
+            if let (true, Some((width, height)), Some((short, long))) = (checks.size, page.size, usual_size) {
+                let off = |length: f32, usual: i64| (length - usual as f32).abs() > usual as f32 * SIZE_TOLERANCE;
+                if off(width.min(height), short) || off(width.max(height), long) {
+                    // Square pages are told the usual size the way most pages turn
+                    let turned = landscape((width, height)).or(usual_landscape).unwrap_or(false);
+                    let (usual_width, usual_height) = if turned { (long, short) } else { (short, long) };
+                    let message = tr("{}x{} pt where most pages are {}x{} pt, --page-size evens them out", &[&width.round(), &height.round(), &usual_width, &usual_height]);
+                    add(*number, "size", message);
+                }
+            }
+            if let (true, Some(is_landscape), Some(usual)) = (checks.orientation, page.size.and_then(landscape), usual_landscape) {
+                if is_landscape != usual {
+                    let name = |landscape: bool| if landscape { tr("landscape", &[]) } else { tr("portrait", &[]) };
+                    add(*number, "orientation", tr("{} where most pages are {}, --auto-orient evens them out", &[&name(is_landscape), &name(usual)]));
+                }
+            }
+            if let (true, Some(color), Some(usual)) = (checks.color, page.color, usual_color) {
+                if color != usual {
+                    let name = |space: Space| if space == Space::Cmyk { "CMYK" } else { "RGB" };
+                    add(*number, "color", tr("{} where most pages are {}, --grayscale evens them out", &[&name(color), &name(usual)]));
+                }
+            }
+        }
+        findings.append(&mut own);
+    }
+    findings
+}
+
+/// `pages` as short as they can be written, like `1-3, 7`.
+pub fn page_list(pages: &[u32]) -> String {
+    let mut ranges: Vec<(u32, u32)> = Vec::new();
+    for &number in pages {
+        match ranges.last_mut() {
+            Some((_, last)) if *last + 1 == number => *last = number,
+            _ => ranges.push((number, number)),
+        }
+    }
+    let range = |&(first, last): &(u32, u32)| if first == last { first.to_string() } else { format!("{}-{}", first, last) };
+    ranges.iter().map(range).collect::<Vec<_>>().join(", ")
+}
+
+fn page(input: &Input, page_id: ObjectId, known: &HashMap<ObjectId, Space>) -> Page {
+    let doc = &input.doc;
+    let Ok(Object::Dictionary(dict)) = pages::with_inherited_attributes(doc, page_id) else { return Page { size: None, color: None } };
+    let page_box = |key: &[u8]| {
+        let values = dict.get_deref(key, doc).and_then(Object::as_array).ok()?.iter().map(|v| v.as_float().ok()).collect::<Option<Vec<_>>>()?;
+        (values.len() == 4).then(|| ((values[2] - values[0]).abs(), (values[3] - values[1]).abs()))
+    };
+    // A crop of the input replaces the page's CropBox, measured in from its MediaBox
+    let size = match input.spec.crop {
+        Some(margins) => page_box(b"MediaBox").map(|(width, height)| (width - margins.left - margins.right, height - margins.bottom - margins.top)),
+        None => page_box(b"CropBox").or_else(|| page_box(b"MediaBox")),
+    };
+    let rotation = dict.get(b"Rotate").and_then(Object::as_i64).unwrap_or(0) + input.spec.rotate;
+    let size = size.filter(|(width, height)| *width > 0.0 && *height > 0.0).map(|(width, height)| if rotation.rem_euclid(180) == 90 { (height, width) } else { (width, height) });
+    let color = dict.get_deref(b"Resources", doc).and_then(Object::as_dict).ok().and_then(|resources| color(doc, resources, known));
+    Page { size, color }
+}
+
+/// CMYK when the color spaces and images of `resources` use it, else RGB when they use that.
+fn color(doc: &Document, resources: &lopdf::Dictionary, known: &HashMap<ObjectId, Space>) -> Option<Space> {
+    let mut spaces = Vec::new();
+    if let Ok(color_spaces) = resources.get_deref(b"ColorSpace", doc).and_then(Object::as_dict) {
+        spaces.extend(color_spaces.iter().map(|(_, space)| grayscale::space_of(space, known)));
+    }
+    if let Ok(xobjects) = resources.get_deref(b"XObject", doc).and_then(Object::as_dict) {
+        for (_, xobject) in xobjects {
+            let Ok(image) = doc.dereference(xobject).and_then(|(_, object)| object.as_stream()) else { continue };
+            if image.dict.get(b"Subtype").and_then(Object::as_name).ok() == Some(b"Image") {
+                spaces.extend(image.dict.get(b"ColorSpace").ok().map(|space| grayscale::space_of(space, known)));
+            }
+        }
+    }
+    [Space::Cmyk, Space::Rgb].into_iter().find(|wanted| spaces.contains(wanted))
+}
+
+/// Whether a page of `size` is wider than tall, `None` when it is square.
+fn landscape((width, height): (f32, f32)) -> Option<bool> {
+    ((width - height).abs() >= 1.0).then_some(width > height)
+}
+
+/// The value `values` have most often, the first of those when some tie.
+fn most<T: Copy + Eq + std::hash::Hash>(values: impl Iterator<Item = T>) -> Option<T> {
+    let mut counts = HashMap::new();
+    let mut order = Vec::new();
+    for value in values {
+        let count = counts.entry(value).or_insert(0);
+        if *count == 0 {
+            order.push(value);
+        }
+        *count += 1;
+    }
+    order.into_iter().fold(None, |best, value| match best {
+        Some(best) if counts[&best] >= counts[&value] => Some(best),
+        _ => Some(value),
+    })
+}
@@ -0,0 +1,99 @@
+use std::{
+    io::{self, IsTerminal, Write},
+    sync::atomic::{AtomicBool, Ordering},
+    time::{Duration, Instant},
+};
+
+use merge_pdf::{messages::tr, Progress};
+
+/// Width of the bar itself, in characters.
+const WIDTH: usize = 30;
+
+/// How often the bar is drawn at most; every step of a merge of thousands of small files would
+/// spend more time on the terminal than on the files.
+const INTERVAL: Duration = Duration::from_millis(100);
+
+/// Whether a bar is on the terminal now, for messages to take it off first.
+static ON_SCREEN: AtomicBool = AtomicBool::new(false);
+
+/// A progress bar on stderr for the steps of a merge, drawn only when stderr is a terminal so
+/// nothing of it ends up in a pipe or a log file.
+pub struct Bar {
+    enabled: bool,
+    drawn: Option<Instant>,
+    /// Pages each input takes, for the pages done as inputs are copied
+    pages: Vec<usize>,
+}
+
+impl Bar {
+    pub fn new(enabled: bool) -> Self {
+        Bar { enabled: enabled && io::stderr().is_terminal(), drawn: None, pages: Vec::new() }
+    }
+
+    /// Count the pages of the inputs as they are copied, `pages` of every input in order.
+    pub fn pages(&mut self, pages: Vec<usize>) {
+        self.pages = pages;
+    }
+
+    /// Show `progress`, a step of a merge.
+    pub fn progress(&mut self, progress: &Progress) {
+        match progress {
+            Progress::Loaded { index, total, .. } => self.show("Loading", Some((index + 1, *total))),
+            Progress::Copied { index, total } if self.pages.len() == *total => {
+                let done = self.pages[..=*index].iter().sum();
+                self.show("Copying pages", Some((done, self.pages.iter().sum())));
+            }
+            Progress::Copied { index, total } => self.show("Copying", Some((index + 1, *total))),
+            Progress::Renumbering => self.show("Renumbering", None),
+            Progress::Transforming => self.show("Transforming pages", None),
+            Progress::Outline => self.show("Building the outline", None),
+            Progress::Finishing => self.show("Finishing", None),
+            Progress::Saving { .. } => self.show("Saving", None),
+            Progress::Saved { .. } => self.clear(),
+        }
+    }
+
+    /// Draw `label` with `(done, total)` as a bar, unless the last drawing was just now. Steps
+    /// without a count, and the last of those with one, are always drawn.
+    fn show(&mut self, label: &str, count: Option<(usize, usize)>) {
+        let last = count.is_none_or(|(done, total)| done >= total);
+        if !self.enabled || (!last && self.drawn.is_some_and(|drawn| drawn.elapsed() < INTERVAL)) {
+            return;
+        }
+        let label = tr(label, &[]);
+        let line = match count {
+            Some((done, total)) => {
+                let filled = (done * WIDTH).checked_div(total).unwrap_or(WIDTH).min(WIDTH);
+                format!("{:<22} [{}{}] {}/{}", label, "#".repeat(filled), "-".repeat(WIDTH - filled), done, total)
+            }
+            None => format!("{}...", label),
+        };
+        let mut stderr = io::stderr().lock();
+        let _ = write!(stderr, "\r{}\x1b[K", line);
+        let _ = stderr.flush();
+        ON_SCREEN.store(true, Ordering::Relaxed);
+        self.drawn = Some(Instant::now());
+    }
+
+    /// Take the bar off the terminal, for what comes after to start on a clean line.
+    pub fn clear(&mut self) {
+        if self.enabled && self.drawn.take().is_some() {
+            clear_line();
+        }
+    }
+}
+
+/// Take whatever bar is drawn off the terminal; the next step draws it again below.
+pub fn clear_line() {
+    if ON_SCREEN.swap(false, Ordering::Relaxed) {
+        let mut stderr = io::stderr().lock();
+        let _ = write!(stderr, "\r\x1b[K");
+        let _ = stderr.flush();
+    }
+}
+
+impl Drop for Bar {
+    fn drop(&mut self) {
+        self.clear();
+    }
+}
@@ -0,0 +1,80 @@
+use std::path::PathBuf;
+
+use pyo3::{
+    exceptions::{PyRuntimeError, PyValueError},
+    prelude::*,
+    types::{PyBytes, PyDict},
+};
+
+use crate::{outline::BookmarkStyle, MergeOptions};
+
+/// merge(paths, options=None) -> bytes
+///
+/// Merge the PDFs at `paths`, in order, and return the merged file. `options` is a dict with any
+/// of `compress` and `pdfa` (bool), `bookmark_style` ("plain", "italic", "bold" or "bold-italic")
+/// and `root` (0-based index of the input whose Catalog is kept).
+#[pyfunction]
+#[pyo3(name = "merge", signature = (paths, options = None))]
+fn merge_paths<'py>(
+    py: Python<'py>,
+    paths: Vec<PathBuf>,
+    options: Option<&Bound<'py, PyDict>>,
+) -> PyResult<Bound<'py, PyBytes>> {
+    let options = match options {
+        Some(options) => merge_options(options)?,
+        None => MergeOptions::new(),
+    };
+    // The merge never touches Python objects, so other threads may run meanwhile
+    let merged = py.allow_threads(|| merge_to_bytes(&paths, &options));
+    merged.map(|out| PyBytes::new(py, &out)).map_err(|e| PyRuntimeError::new_err(e.to_string()))
+}
+
+/// The merged file as the command line writes it, see `crate::write_to`.
+fn merge_to_bytes(paths: &[PathBuf], options: &MergeOptions) -> crate::Result<Vec<u8>> {
+    let mut document = crate::merge(paths, options)?;
+    crate::write_to(&mut document, Vec::new(), options)
+}
+
+fn merge_options(dict: &Bound<'_, PyDict>) -> PyResult<MergeOptions> {
+    let mut options = MergeOptions::new();
+    for (key, value) in dict.iter() {
+        let key = key.extract::<String>()?;
+        options = match key.as_str() {
+            "compress" => options.compress(value.extract()?),
+            "pdfa" => options.pdfa(value.extract()?),
+            "root" => options.root(value.extract()?),
+            "bookmark_style" => options.bookmark_style(match value.extract::<String>()?.as_str() {
+                "plain" => BookmarkStyle::Plain,
+                "italic" => BookmarkStyle::Italic,
+                "bold" => BookmarkStyle::Bold,
+                "bold-italic" => BookmarkStyle::BoldItalic,
+                other => return Err(PyValueError::new_err(format!("unknown bookmark_style {:?}", other))),
+            }),
+            _ => return Err(PyValueError::new_err(format!("unknown option {:?}", key))),
+        };
+    }
+    Ok(options)
+}
+
+#[pymodule]
+fn merge_pdf(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add_function(wrap_pyfunction!(merge_paths, m)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::tempdir::TempDir;
+
+    use super::*;
+
+    #[test]
+    fn pdfa_output_has_the_binary_header_comment() {
+        let scratch = TempDir::named("python-pdfa").unwrap();
+        let paths = ["a.pdf", "b.pdf"].map(|name| scratch.path().join(name));
+        for path in &paths {
+            crate::tests::document(1).save(path).unwrap();
+        }
+        let out = merge_to_bytes(&paths, &MergeOptions::new().pdfa(true)).unwrap();
+        assert!(out.starts_with("%PDF-1.7\n%âãÏÓ\n".as_bytes()), "{:?}", String::from_utf8_lossy(&out[..20]));
+    }
+}
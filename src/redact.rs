@@ -0,0 +1,545 @@
+use std::collections::{HashMap, HashSet};
+
+use lopdf::{
+    content::{Content, Operation},
+    Dictionary, Document, Encoding, Object, ObjectId, Stream,
+};
+
+use crate::{input::InputSpec, messages::tr, pages, regex::Regex, stamp};
+
+/// Glyph ascent and descent in thousandths of the font size, for fonts that do not give theirs.
+const ASCENT: f32 = 800.0;
+const DESCENT: f32 = -200.0;
+
+pub(crate) type Matrix = [f32; 6];
+
+pub(crate) const IDENTITY: Matrix = [1.0, 0.0, 0.0, 1.0, 0.0, 0.0];
+
+/// Black out the text matching any of `patterns` on the pages `spec` selects from `doc`, saying
+/// how many matches there were when there are any; returns how many.
+///
+/// The text is read from the page content and the Form XObjects it draws, in the order it is
+/// drawn, with spaces and line breaks put in where the glyphs leave gaps or start a new line.
+/// Matched glyphs are taken out of the text operators, with the space they took kept so the
+/// rest of the line does not move, and a black box is drawn where they were. Images and
+/// annotations are left as they are, so text in scans is not found.
+pub fn redact_pages(spec: &InputSpec, doc: &mut Document, patterns: &[Regex]) -> crate::Result<usize> {
+    let mut matches = 0;
+    let mut forms = Vec::new();
+    for (number, page_id) in doc.get_pages() {
+        if !spec.selects(number) {
+            continue;
+        }
+        let resources = match pages::with_inherited_attributes(doc, page_id)? {
+            Object::Dictionary(page) => page.get_deref(b"Resources", doc).and_then(Object::as_dict).cloned().unwrap_or_default(),
+            _ => Dictionary::new(),
+        };
+        let Ok(content) = doc.get_page_content(page_id).and_then(|content| Content::decode(&content)) else {
+            log::warn!(path:% = spec.path.display(), page = number; "{}", tr("could not read its content, it was not redacted", &[]));
+            continue;
+        };
+        if let Some((content, found)) = redact_content(doc, content, &resources, patterns, &mut forms)? {
+            let stream_id = doc.add_object(Stream::new(Dictionary::new(), content));
+            doc.get_dictionary_mut(page_id)?.set("Contents", stream_id);
+            matches += found;
+        }
+    }
+
+    // The forms the pages draw and those they draw in turn, each once however often it is drawn
+    let mut done = HashSet::new();
+    while let Some((form_id, resources)) = forms.pop() {
+        if !done.insert(form_id) {
+            continue;
+        }
+        let Ok(form) = doc.get_object(form_id).and_then(Object::as_stream) else { continue };
+        let resources = form.dict.get_deref(b"Resources", doc).and_then(Object::as_dict).cloned().unwrap_or(resources);
+        let content = match form.dict.has(b"Filter") {
+            true => form.decompressed_content(),
+            false => Ok(form.content.clone()),
+        };
+        let Ok(content) = content.and_then(|content| Content::decode(&content)) else {
+            log::warn!(path:% = spec.path.display(), object:% = format!("{} {}", form_id.0, form_id.1); "{}", tr("could not read the content of the form, it was not redacted", &[]));
+            continue;
+        };
+        if let Some((content, found)) = redact_content(doc, content, &resources, patterns, &mut forms)? {
+            let form = doc.get_object_mut(form_id).and_then(Object::as_stream_mut)?;
+            form.dict.remove(b"Filter");
+            form.dict.remove(b"DecodeParms");
+            form.set_content(content);
+            matches += found;
+        }
+    }
+    if matches > 0 {
+        // The replaced content streams still hold the text
+        doc.prune_objects();
+        log::info!("{}", tr("{}: redacted {} match(es)", &[&spec.path.display(), &matches]));
+    }
+    Ok(matches)
+}
+
+/// Redact one content stream drawn with `resources`, giving the new content and how many matches
+/// it had when there were any. The Form XObjects it draws go into `forms`.
+fn redact_content(
+    doc: &Document,
+    content: Content,
+    resources: &Dictionary,
+    patterns: &[Regex],
+    forms: &mut Vec<(ObjectId, Dictionary)>,
+) -> crate::Result<Option<(Vec<u8>, usize)>> {
+    let mut reader = TextReader::new(doc, resources);
+    for (index, operation) in content.operations.iter().enumerate() {
+        reader.read(index, operation, forms);
+    }
+    let found = patterns.iter().flat_map(|pattern| pattern.find_all(&reader.text)).collect::<Vec<_>>();
+    if found.is_empty() {
+        return Ok(None);
+    }
+    let glyphs = reader.glyphs;
+    let mut redacted = vec![false; glyphs.len()];
+    for &(start, end) in &found {
+        for glyph in reader.owners[start..end].iter().flatten() {
+            redacted[*glyph] = true;
+        }
+    }
+
+    let mut operations = vec![Operation::new("q", vec![])];
+    let mut next = 0;
+    for (index, operation) in content.operations.into_iter().enumerate() {
+        let first = next;
+        while next < glyphs.len() && glyphs[next].operation == index {
+            next += 1;
+        }
+        let shown = first..next;
+        if !redacted[shown.clone()].contains(&true) {
+            operations.push(operation);
+            continue;
+        }
+        let glyphs = glyphs[shown.clone()].iter().zip(&redacted[shown]).collect::<Vec<_>>();
+        let mut operands = operation.operands;
+        let shown = match operation.operator.as_str() {
+            "TJ" => operands.pop().and_then(|array| array.as_array().ok().cloned()).unwrap_or_default(),
+            _ => operands.pop().into_iter().collect(),
+        };
+        let shown = without_glyphs(shown, &glyphs);
+        match operation.operator.as_str() {
+            "'" => operations.push(Operation::new("T*", vec![])),
+            "\"" => {
+                let mut spacing = operands.into_iter();
+                operations.push(Operation::new("Tw", spacing.next().into_iter().collect()));
+                operations.push(Operation::new("Tc", spacing.next().into_iter().collect()));
+                operations.push(Operation::new("T*", vec![]));
+            }
+            _ => {}
+        }
+        operations.push(Operation::new("TJ", vec![Object::Array(shown)]));
+    }
+    operations.push(Operation::new("Q", vec![]));
+
+    // One box per run of blacked out glyphs shown by the same string
+    let mut boxes = String::new();
+    let mut glyph = 0;
+    while glyph < glyphs.len() {
+        if !redacted[glyph] {
+            glyph += 1;
+            continue;
+        }
+        let first = &glyphs[glyph];
+        while glyph + 1 < glyphs.len()
+            && redacted[glyph + 1]
+            && (glyphs[glyph + 1].operation, glyphs[glyph + 1].string) == (first.operation, first.string)
+        {
+            glyph += 1;
+        }
+        let last = &glyphs[glyph];
+        let corners = [first.corners[0], last.corners[1], last.corners[2], first.corners[3]];
+        for (i, (x, y)) in corners.iter().enumerate() {
+            boxes.push_str(&format!("{} {} {}\n", x, y, if i == 0 { "m" } else { "l" }));
+        }
+        boxes.push_str("h\n");
+        glyph += 1;
+    }
+    let mut content = Content { operations }.encode()?;
+    content.extend(format!("\nq 0 g\n{}f\nQ\n", boxes).into_bytes());
+    Ok(Some((content, found.len())))
+}
+
+/// The strings and spacings of a `TJ` array showing `glyphs`, with the redacted ones replaced by
+/// spacing as wide as they were.
+fn without_glyphs(shown: Vec<Object>, glyphs: &[(&Glyph, &bool)]) -> Vec<Object> {
+    let mut kept = Vec::new();
+    for (element, object) in shown.into_iter().enumerate() {
+        let Object::String(bytes, format) = object else {
+            kept.push(object);
+            continue;
+        };
+        let mut run = Vec::new();
+        for (glyph, redacted) in glyphs.iter().filter(|(glyph, _)| glyph.string == element) {
+            if !**redacted {
+                run.extend_from_slice(&bytes[glyph.bytes.0..glyph.bytes.1]);
+                continue;
+            }
+            if !run.is_empty() {
+                kept.push(Object::String(std::mem::take(&mut run), format));
+            }
+            let space = if glyph.size == 0.0 { 0.0 } else { -glyph.advance * 1000.0 / glyph.size };
+            match kept.last_mut() {
+                Some(Object::Real(previous)) => *previous += space,
+                _ => kept.push(Object::Real(space)),
+            }
+        }
+        if !run.is_empty() {
+            kept.push(Object::String(run, format));
+        }
+    }
+    kept
+}
+
+/// A glyph shown by the content: the operation and string (0, or the element of a `TJ` array)
+/// showing it, its bytes there, how far it moves the text along and the corners of its box.
+struct Glyph {
+    operation: usize,
+    string: usize,
+    bytes: (usize, usize),
+    advance: f32,
+    size: f32,
+    corners: [(f32, f32); 4],
+}
+
+#[derive(Clone)]
+struct TextState {
+    ctm: Matrix,
+    char_spacing: f32,
+    word_spacing: f32,
+    scale: f32,
+    leading: f32,
+    font: Vec<u8>,
+    size: f32,
+    rise: f32,
+}
+
+/// Follows the text operators of a content stream (PDF 32000-1, 9.4), collecting the glyphs
+/// they show and the text they spell.
+struct TextReader<'a> {
+    doc: &'a Document,
+    resources: &'a Dictionary,
+    fonts: HashMap<Vec<u8>, Font<'a>>,
+    state: TextState,
+    saved: Vec<TextState>,
+    matrix: Matrix,
+    line: Matrix,
+    glyphs: Vec<Glyph>,
+    text: Vec<char>,
+    /// The glyph each character of `text` comes from, none for the spaces and breaks put in
+    owners: Vec<Option<usize>>,
+    /// Where the last glyph ended, on its baseline
+    end: Option<(f32, f32)>,
+}
+
+impl<'a> TextReader<'a> {
+    fn new(doc: &'a Document, resources: &'a Dictionary) -> Self {
+        let state = TextState {
+            ctm: IDENTITY,
+            char_spacing: 0.0,
+            word_spacing: 0.0,
+            scale: 1.0,
+            leading: 0.0,
+            font: Vec::new(),
+            size: 0.0,
+            rise: 0.0,
+        };
+        TextReader {
+            doc,
+            resources,
+            fonts: HashMap::new(),
+            state,
+            saved: Vec::new(),
+            matrix: IDENTITY,
+            line: IDENTITY,
+            glyphs: Vec::new(),
+            text: Vec::new(),
+            owners: Vec::new(),
+            end: None,
+        }
+    }
+
+    fn read(&mut self, index: usize, operation: &Operation, forms: &mut Vec<(ObjectId, Dictionary)>) {
+        let operands = &operation.operands;
+        let number = |i: usize| operands.get(i).and_then(|operand| operand.as_float().ok()).unwrap_or(0.0);
+        match operation.operator.as_str() {
+            "q" => self.saved.push(self.state.clone()),
+            "Q" => self.state = self.saved.pop().unwrap_or_else(|| self.state.clone()),
+            "cm" => {
+                if let Some(matrix) = matrix(operands) {
+                    self.state.ctm = multiply(matrix, self.state.ctm);
+                }
+            }
+            "BT" => (self.matrix, self.line) = (IDENTITY, IDENTITY),
+            "Tf" => {
+                self.state.font = operands.first().and_then(|name| name.as_name().ok()).unwrap_or_default().to_vec();
+                self.state.size = number(1);
+            }
+            "Tc" => self.state.char_spacing = number(0),
+            "Tw" => self.state.word_spacing = number(0),
+            "Tz" => self.state.scale = number(0) / 100.0,
+            "TL" => self.state.leading = number(0),
+            "Ts" => self.state.rise = number(0),
+            "Td" => self.next_line(number(0), number(1)),
+            "TD" => {
+                self.state.leading = -number(1);
+                self.next_line(number(0), number(1));
+            }
+            "Tm" => {
+                if let Some(matrix) = matrix(operands) {
+                    (self.matrix, self.line) = (matrix, matrix);
+                }
+            }
+            "T*" => self.next_line(0.0, -self.state.leading),
+            "Tj" => self.show(index, 0, operands.first()),
+            "'" => {
+                self.next_line(0.0, -self.state.leading);
+                self.show(index, 0, operands.first());
+            }
+            "\"" => {
+                (self.state.word_spacing, self.state.char_spacing) = (number(0), number(1));
+                self.next_line(0.0, -self.state.leading);
+                self.show(index, 0, operands.get(2));
+            }
+            "TJ" => {
+                let Some(Ok(elements)) = operands.first().map(Object::as_array) else { return };
+                for (element, object) in elements.iter().enumerate() {
+                    match object.as_float() {
+                        Ok(space) => self.advance(-space / 1000.0 * self.state.size),
+                        Err(_) => self.show(index, element, Some(object)),
+                    }
+                }
+            }
+            "Do" => {
+                let Some(Ok(name)) = operands.first().map(Object::as_name) else { return };
+                let xobjects = self.resources.get_deref(b"XObject", self.doc).and_then(Object::as_dict);
+                let Ok(Object::Reference(id)) = xobjects.and_then(|xobjects| xobjects.get(name)) else { return };
+                let is_form = self.doc.get_object(*id).and_then(Object::as_stream).is_ok_and(|xobject| {
+                    xobject.dict.get(b"Subtype").and_then(Object::as_name).ok() == Some(b"Form".as_slice())
+                });
+                if is_form {
+                    forms.push((*id, self.resources.clone()));
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn next_line(&mut self, x: f32, y: f32) {
+        self.line = multiply([1.0, 0.0, 0.0, 1.0, x, y], self.line);
+        self.matrix = self.line;
+    }
+
+    /// Move the text along by `distance`, in unscaled text space units.
+    fn advance(&mut self, distance: f32) {
+        self.matrix = multiply([1.0, 0.0, 0.0, 1.0, distance * self.state.scale, 0.0], self.matrix);
+    }
+
+    fn show(&mut self, operation: usize, string: usize, shown: Option<&Object>) {
+        let Some(Object::String(bytes, _)) = shown else { return };
+        let doc = self.doc;
+        let resources = self.resources;
+        let font = self.fonts.entry(self.state.font.clone()).or_insert_with(|| {
+            let dict = resources
+                .get_deref(b"Font", doc)
+                .and_then(Object::as_dict)
+                .and_then(|fonts| fonts.get_deref(&self.state.font, doc))
+                .and_then(Object::as_dict);
+            Font::load(doc, dict.ok())
+        });
+        let code_length = if font.two_byte { 2 } else { 1 };
+        let state = &self.state;
+        let mut glyphs = Vec::new();
+        for (i, code) in bytes.chunks(code_length).enumerate() {
+            let text = font.decode(code);
+            let width = font.width(code, &text) / 1000.0;
+            let word_spacing = if code == [b' '] { state.word_spacing } else { 0.0 };
+            let advance = width * state.size + state.char_spacing + word_spacing;
+            let rendering = multiply(
+                [state.size * state.scale, 0.0, 0.0, state.size, 0.0, state.rise],
+                multiply(self.matrix, state.ctm),
+            );
+            let (ascent, descent) = (font.ascent / 1000.0, font.descent / 1000.0);
+            let corners = [(0.0, descent), (width, descent), (width, ascent), (0.0, ascent)].map(|point| apply(rendering, point));
+            let start = i * code_length;
+            glyphs.push((text, rendering, width, Glyph {
+                operation,
+                string,
+                bytes: (start, start + code.len()),
+                advance,
+                size: state.size,
+                corners,
+            }));
+            self.matrix = multiply([1.0, 0.0, 0.0, 1.0, advance * state.scale, 0.0], self.matrix);
+        }
+        for (text, rendering, width, glyph) in glyphs {
+            self.separate(rendering);
+            self.end = Some(apply(rendering, (width, 0.0)));
+            for c in text.chars() {
+                self.text.push(c);
+                self.owners.push(Some(self.glyphs.len()));
+            }
+            self.glyphs.push(glyph);
+        }
+    }
+
+    /// Put a line break or space into the text when a glyph drawn with `rendering` starts on
+    /// another line or well after where the last one ended.
+    fn separate(&mut self, rendering: Matrix) {
+        let Some((x, y)) = self.end else { return };
+        if self.text.last().is_some_and(|c| c.is_whitespace()) {
+            return;
+        }
+        let origin = apply(rendering, (0.0, 0.0));
+        let along = apply(rendering, (1.0, 0.0));
+        let (dx, dy) = (along.0 - origin.0, along.1 - origin.1);
+        let size = dx.hypot(dy);
+        if size == 0.0 {
+            return;
+        }
+        let (gap_x, gap_y) = (origin.0 - x, origin.1 - y);
+        let ahead = (gap_x * dx + gap_y * dy) / size;
+        let aside = (dx * gap_y - dy * gap_x) / size;
+        let separator = if aside.abs() > size / 2.0 || ahead < -size {
+            '\n'
+        } else if ahead > size * 0.15 {
+            ' '
+        } else {
+            return;
+        };
+        self.text.push(separator);
+        self.owners.push(None);
+    }
+}
+
+/// What is needed of a font to read and measure its glyphs.
+struct Font<'a> {
+    encoding: Option<Encoding<'a>>,
+    two_byte: bool,
+    widths: HashMap<u32, f32>,
+    default_width: Option<f32>,
+    monospace: bool,
+    ascent: f32,
+    descent: f32,
+}
+
+impl<'a> Font<'a> {
+    fn load(doc: &'a Document, dict: Option<&'a Dictionary>) -> Self {
+        let mut font = Font {
+            encoding: None,
+            two_byte: false,
+            widths: HashMap::new(),
+            default_width: None,
+            monospace: false,
+            ascent: ASCENT,
+            descent: DESCENT,
+        };
+        let Some(dict) = dict else { return font };
+        let number = |object: &Object| object.as_float().ok();
+        // lopdf asserts it is given a font dictionary
+        if dict.type_is(b"Font") {
+            font.encoding = dict.get_font_encoding(doc).ok();
+        }
+        font.two_byte = dict.get(b"Subtype").and_then(Object::as_name).ok() == Some(b"Type0".as_slice());
+        font.monospace = dict.get(b"BaseFont").and_then(Object::as_name).is_ok_and(|name| name.starts_with(b"Courier"));
+        let descendant = dict
+            .get_deref(b"DescendantFonts", doc)
+            .and_then(Object::as_array)
+            .ok()
+            .and_then(|fonts| fonts.first())
+            .and_then(|first| doc.dereference(first).ok())
+            .and_then(|(_, first)| first.as_dict().ok());
+        let metrics = if font.two_byte { descendant.unwrap_or(dict) } else { dict };
+        if let Ok(descriptor) = metrics.get_deref(b"FontDescriptor", doc).and_then(Object::as_dict) {
+            if let Some(ascent) = descriptor.get(b"Ascent").ok().and_then(number).filter(|a| *a > 0.0) {
+                font.ascent = ascent;
+            }
+            if let Some(descent) = descriptor.get(b"Descent").ok().and_then(number).filter(|d| *d < 0.0) {
+                font.descent = descent;
+            }
+            font.default_width = descriptor.get(b"MissingWidth").ok().and_then(number);
+        }
+
+        if font.two_byte {
+            font.default_width = Some(metrics.get(b"DW").ok().and_then(number).unwrap_or(1000.0));
+            // Runs like `c [w1 w2 ...]` and ranges like `first last w`
+            let items = metrics.get_deref(b"W", doc).and_then(Object::as_array).cloned().unwrap_or_default();
+            let mut items = items.iter().map(|item| doc.dereference(item).map(|(_, item)| item.clone()).unwrap_or(Object::Null));
+            while let Some(first) = items.next().and_then(|first| first.as_i64().ok()) {
+                match items.next() {
+                    Some(Object::Array(widths)) => {
+                        for (i, width) in widths.iter().enumerate() {
+                            font.widths.insert((first + i as i64) as u32, number(width).unwrap_or(0.0));
+                        }
+                    }
+                    Some(last) => {
+                        let (Ok(last), Some(width)) = (last.as_i64(), items.next().as_ref().and_then(number)) else { break };
+                        for code in first..=last.min(first + 0xffff) {
+                            font.widths.insert(code as u32, width);
+                        }
+                    }
+                    None => break,
+                }
+            }
+        } else if let Ok(widths) = dict.get_deref(b"Widths", doc).and_then(Object::as_array) {
+            let first = dict.get(b"FirstChar").and_then(Object::as_i64).unwrap_or(0);
+            for (i, width) in widths.iter().enumerate() {
+                let width = doc.dereference(width).ok().and_then(|(_, width)| number(width)).unwrap_or(0.0);
+                font.widths.insert((first + i as i64) as u32, width);
+            }
+            font.default_width = Some(font.default_width.unwrap_or(0.0));
+        }
+        font
+    }
+
+    /// The text a character code stands for, never empty so that every glyph can be matched.
+    fn decode(&self, code: &[u8]) -> String {
+        let text = match (&self.encoding, code) {
+            // lopdf reads ToUnicode codes as two bytes, simple fonts have one
+            (Some(encoding @ Encoding::UnicodeMapEncoding(_)), [byte]) => encoding.bytes_to_string(&[0, *byte]).ok(),
+            (Some(encoding), _) => encoding.bytes_to_string(code).ok(),
+            (None, _) => None,
+        };
+        match (text, code) {
+            (Some(text), _) if !text.is_empty() => text,
+            (_, [byte]) => char::from(*byte).to_string(),
+            _ => char::REPLACEMENT_CHARACTER.to_string(),
+        }
+    }
+
+    /// How wide the glyph for `code` is, in thousandths of the font size. Fonts without widths,
+    /// like the standard 14 often are, get a guess.
+    fn width(&self, code: &[u8], text: &str) -> f32 {
+        let code = code.iter().fold(0, |code, byte| code << 8 | u32::from(*byte));
+        match (self.widths.get(&code), self.default_width) {
+            (Some(width), _) => *width,
+            (None, Some(width)) => width,
+            _ if self.monospace => 600.0,
+            _ => text.chars().next().map_or(0.5, stamp::helvetica_em) * 1000.0,
+        }
+    }
+}
+
+pub(crate) fn matrix(operands: &[Object]) -> Option<Matrix> {
+    let values = operands.iter().map(|operand| operand.as_float().ok()).collect::<Option<Vec<_>>>()?;
+    values.try_into().ok()
+}
+
+/// `m` then `n`, as PDF concatenates matrices.
+pub(crate) fn multiply(m: Matrix, n: Matrix) -> Matrix {
+    [
+        m[0] * n[0] + m[1] * n[2],
+        m[0] * n[1] + m[1] * n[3],
+        m[2] * n[0] + m[3] * n[2],
+        m[2] * n[1] + m[3] * n[3],
+        m[4] * n[0] + m[5] * n[2] + n[4],
+        m[4] * n[1] + m[5] * n[3] + n[5],
+    ]
+}
+
+fn apply(m: Matrix, (x, y): (f32, f32)) -> (f32, f32) {
+    (m[0] * x + m[2] * y + m[4], m[1] * x + m[3] * y + m[5])
+}
@@ -0,0 +1,421 @@
+use std::str::FromStr;
+
+/// A regular expression, in the common Perl-like syntax: literals, `.`, classes like `[a-z]` and
+/// `[^0-9]`, `\d` `\w` `\s` and their negations, `\b`, `^` and `$`, groups with `|`, and the
+/// `*` `+` `?` `{n}` `{n,}` `{n,m}` repetitions, lazy with a trailing `?`. A leading `(?i)` makes
+/// it ignore case.
+///
+/// Matching backtracks, which is plenty for the short patterns it is used with (text to redact)
+/// but can take long on pathological ones like `(a*)*b`.
+#[derive(Debug, Clone)]
+pub struct Regex {
+    alternatives: Vec<Vec<Node>>,
+    ignore_case: bool,
+}
+
+#[derive(Debug, Clone)]
+enum Node {
+    Char(char),
+    Any,
+    Class(Class),
+    Start,
+    End,
+    WordBoundary,
+    Group(Vec<Vec<Node>>),
+    Repeat { node: Box<Node>, min: usize, max: Option<usize>, lazy: bool },
+}
+
+#[derive(Debug, Clone)]
+struct Class {
+    items: Vec<ClassItem>,
+    negated: bool,
+}
+
+#[derive(Debug, Clone)]
+enum ClassItem {
+    Range(char, char),
+    Digit(bool),
+    Word(bool),
+    Space(bool),
+}
+
+impl FromStr for Regex {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (ignore_case, pattern) = match s.strip_prefix("(?i)") {
+            Some(rest) => (true, rest),
+            None => (false, s),
+        };
+        let mut parser = Parser { chars: pattern.chars().collect(), pos: 0 };
+        let alternatives = parser.alternatives()?;
+        if parser.pos < parser.chars.len() {
+            return Err(format!("invalid pattern {:?}: unmatched ')'", s));
+        }
+        Ok(Regex { alternatives, ignore_case })
+    }
+}
+
+impl Regex {
+    /// The start and end (exclusive) of the leftmost match in `text` at or after `from`.
+    pub fn find_at(&self, text: &[char], from: usize) -> Option<(usize, usize)> {
+        (from..=text.len()).find_map(|start| {
+            let mut end = None;
+            let matched = self.alternatives.iter().any(|sequence| {
+                self.matches(sequence, text, start, &mut |e| {
+                    end = Some(e);
+                    true
+                })
+            });
+            matched.then(|| (start, end.unwrap_or(start)))
+        })
+    }
+
+    /// Every non-empty match in `text`, left to right and not overlapping.
+    pub fn find_all(&self, text: &[char]) -> Vec<(usize, usize)> {
+        let mut found = Vec::new();
+        let mut from = 0;
+        while let Some((start, end)) = self.find_at(text, from) {
+            if end > start {
+                found.push((start, end));
+            }
+            from = end.max(start + 1);
+        }
+        found
+    }
+
+    /// Whether `sequence` matches at `pos` with `then` accepting where it ends.
+    fn matches(&self, sequence: &[Node], text: &[char], pos: usize, then: &mut dyn FnMut(usize) -> bool) -> bool {
+        let Some((node, rest)) = sequence.split_first() else { return then(pos) };
+        match node {
+            Node::Group(alternatives) => alternatives
+                .iter()
+                .any(|alternative| self.matches(alternative, text, pos, &mut |end| self.matches(rest, text, end, then))),
+            Node::Repeat { node, min, max, lazy } => self.repeat(node, (*min, *max, *lazy), 0, rest, text, pos, then),
+            Node::Start => pos == 0 && self.matches(rest, text, pos, then),
+            Node::End => pos == text.len() && self.matches(rest, text, pos, then),
+            Node::WordBoundary => {
+                let before = pos > 0 && is_word(text[pos - 1]);
+                let after = pos < text.len() && is_word(text[pos]);
+                before != after && self.matches(rest, text, pos, then)
+            }
+            _ => pos < text.len() && self.matches_char(node, text[pos]) && self.matches(rest, text, pos + 1, then),
+        }
+    }
+
+    /// Match `node` `count` times so far and then `rest`, trying more repetitions first unless lazy.
+    #[allow(clippy::too_many_arguments)]
+    fn repeat(
+        &self,
+        node: &Node,
+        (min, max, lazy): (usize, Option<usize>, bool),
+        count: usize,
+        rest: &[Node],
+        text: &[char],
+        pos: usize,
+        then: &mut dyn FnMut(usize) -> bool,
+    ) -> bool {
+        let again = |then: &mut dyn FnMut(usize) -> bool| {
+            max.is_none_or(|max| count < max)
+                && self.matches(std::slice::from_ref(node), text, pos, &mut |end| {
+                    // A repetition that matched nothing would only match nothing again
+                    end > pos && self.repeat(node, (min, max, lazy), count + 1, rest, text, end, then)
+                })
+        };
+        let done = count >= min;
+        if lazy && done && self.matches(rest, text, pos, then) {
+            return true;
+        }
+        again(then) || (!lazy && done && self.matches(rest, text, pos, then))
+    }
+
+    fn matches_char(&self, node: &Node, c: char) -> bool {
+        match node {
+            Node::Char(expected) => *expected == c || (self.ignore_case && same_letter(*expected, c)),
+            Node::Any => c != '\n',
+            Node::Class(class) => {
+                let contains = |c: char| class.items.iter().any(|item| item.contains(c));
+                let found = contains(c) || (self.ignore_case && (c.to_lowercase().any(contains) || c.to_uppercase().any(contains)));
+                found != class.negated
+            }
+            _ => false,
+        }
+    }
+}
+
+impl ClassItem {
+    fn contains(&self, c: char) -> bool {
+        match *self {
+            ClassItem::Range(low, high) => (low..=high).contains(&c),
+            ClassItem::Digit(negated) => c.is_ascii_digit() != negated,
+            ClassItem::Word(negated) => is_word(c) != negated,
+            ClassItem::Space(negated) => c.is_whitespace() != negated,
+        }
+    }
+}
+
+fn is_word(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+fn same_letter(a: char, b: char) -> bool {
+    a.to_lowercase().eq(b.to_lowercase())
+}
+
+struct Parser {
+    chars: Vec<char>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    fn next(&mut self) -> Option<char> {
+        let c = self.peek();
+        self.pos += 1;
+        c
+    }
+
+    fn alternatives(&mut self) -> Result<Vec<Vec<Node>>, String> {
+        let mut alternatives = vec![self.sequence()?];
+        while self.peek() == Some('|') {
+            self.pos += 1;
+            alternatives.push(self.sequence()?);
+        }
+        Ok(alternatives)
+    }
+
+    fn sequence(&mut self) -> Result<Vec<Node>, String> {
+        let mut sequence = Vec::new();
+        while let Some(c) = self.peek() {
+            if c == '|' || c == ')' {
+                break;
+            }
+            let atom = self.atom()?;
+            sequence.push(self.repetition(atom)?);
+        }
+        Ok(sequence)
+    }
+
+    fn atom(&mut self) -> Result<Node, String> {
+        let at = self.pos;
+        Ok(match self.next().unwrap_or_default() {
+            '(' => {
+                if self.chars[self.pos..].starts_with(&['?', ':']) {
+                    self.pos += 2;
+                }
+                let alternatives = self.alternatives()?;
+                if self.next() != Some(')') {
+                    return Err(format!("unclosed '(' at {}", at + 1));
+                }
+                Node::Group(alternatives)
+            }
+            '[' => Node::Class(self.class(at)?),
+            '.' => Node::Any,
+            '^' => Node::Start,
+            '$' => Node::End,
+            '\\' => match self.escape()? {
+                Escaped::Char(c) => Node::Char(c),
+                Escaped::Item(item) => Node::Class(Class { items: vec![item], negated: false }),
+                Escaped::WordBoundary => Node::WordBoundary,
+            },
+            '*' | '+' | '?' | '{' => return Err(format!("nothing to repeat at {}", at + 1)),
+            c => Node::Char(c),
+        })
+    }
+
+    fn repetition(&mut self, node: Node) -> Result<Node, String> {
+        let at = self.pos;
+        let (min, max) = match self.peek() {
+            Some('*') => (0, None),
+            Some('+') => (1, None),
+            Some('?') => (0, Some(1)),
+            Some('{') => {
+                let close = self.chars[at..].iter().position(|&c| c == '}').ok_or_else(|| format!("unclosed '{{' at {}", at + 1))?;
+                let bounds = self.chars[at + 1..at + close].iter().collect::<String>();
+                let invalid = || format!("invalid repetition {{{}}} at {}", bounds, at + 1);
+                let number = |s: &str| s.trim().parse::<usize>().map_err(|_| invalid());
+                let (min, max) = match bounds.split_once(',') {
+                    Some((min, "")) => (number(min)?, None),
+                    Some((min, max)) => (number(min)?, Some(number(max)?)),
+                    None => (number(&bounds)?, Some(number(&bounds)?)),
+                };
+                if max.is_some_and(|max| max < min) {
+                    return Err(invalid());
+                }
+                self.pos += close;
+                (min, max)
+            }
+            _ => return Ok(node),
+        };
+        self.pos += 1;
+        if matches!(node, Node::Start | Node::End | Node::WordBoundary) {
+            return Err(format!("nothing to repeat at {}", at + 1));
+        }
+        let lazy = self.peek() == Some('?');
+        if lazy {
+            self.pos += 1;
+        }
+        Ok(Node::Repeat { node: Box::new(node), min, max, lazy })
+    }
+
+    fn class(&mut self, at: usize) -> Result<Class, String> {
+        let negated = self.peek() == Some('^');
+        if negated {
+            self.pos += 1;
+        }
+        let mut items = Vec::new();
+        let mut first = true;
+        loop {
+            let low = match self.next() {
+                None => return Err(format!("unclosed '[' at {}", at + 1)),
+                // A ']' right at the start is taken literally
+                Some(']') if !first => return Ok(Class { items, negated }),
+                Some('\\') => match self.escape()? {
+                    Escaped::Char(c) => c,
+                    Escaped::Item(item) => {
+                        items.push(item);
+                        first = false;
+                        continue;
+                    }
+                    Escaped::WordBoundary => '\u{8}',
+                },
+                Some(c) => c,
+            };
+            first = false;
+            let high = if self.peek() == Some('-') && self.chars.get(self.pos + 1).is_some_and(|&c| c != ']') {
+                self.pos += 1;
+                match self.next() {
+                    Some('\\') => match self.escape()? {
+                        Escaped::Char(c) => c,
+                        _ => return Err(format!("invalid range in '[' at {}", at + 1)),
+                    },
+                    Some(c) => c,
+                    None => return Err(format!("unclosed '[' at {}", at + 1)),
+                }
+            } else {
+                low
+            };
+            if high < low {
+                return Err(format!("invalid range {}-{} in '[' at {}", low, high, at + 1));
+            }
+            items.push(ClassItem::Range(low, high));
+        }
+    }
+
+    fn escape(&mut self) -> Result<Escaped, String> {
+        let at = self.pos;
+        Ok(match self.next() {
+            None => return Err("trailing '\\'".to_string()),
+            Some('d') => Escaped::Item(ClassItem::Digit(false)),
+            Some('D') => Escaped::Item(ClassItem::Digit(true)),
+            Some('w') => Escaped::Item(ClassItem::Word(false)),
+            Some('W') => Escaped::Item(ClassItem::Word(true)),
+            Some('s') => Escaped::Item(ClassItem::Space(false)),
+            Some('S') => Escaped::Item(ClassItem::Space(true)),
+            Some('b') => Escaped::WordBoundary,
+            Some('n') => Escaped::Char('\n'),
+            Some('r') => Escaped::Char('\r'),
+            Some('t') => Escaped::Char('\t'),
+            Some(c) if c.is_ascii_alphanumeric() => return Err(format!("unknown escape \\{} at {}", c, at)),
+            Some(c) => Escaped::Char(c),
+        })
+    }
+}
+
+enum Escaped {
+    Char(char),
+    Item(ClassItem),
+    WordBoundary,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Every match of `pattern` in `text`.
+    fn find(pattern: &str, text: &str) -> Vec<String> {
+        let regex = pattern.parse::<Regex>().unwrap();
+        let text = text.chars().collect::<Vec<_>>();
+        regex.find_all(&text).into_iter().map(|(start, end)| text[start..end].iter().collect()).collect()
+    }
+
+    #[test]
+    fn literals_and_any() {
+        assert_eq!(find("cat", "concatenate cat"), ["cat", "cat"]);
+        assert_eq!(find("c.t", "cat cut c\nt"), ["cat", "cut"]);
+        assert_eq!(find(r"1\.5", "1.5 125"), ["1.5"]);
+        assert!(find("dog", "cat").is_empty());
+    }
+
+    #[test]
+    fn classes() {
+        assert_eq!(find("[a-c]+", "abcdcba"), ["abc", "cba"]);
+        assert_eq!(find("[^0-9 ]+", "ab12 cd"), ["ab", "cd"]);
+        assert_eq!(find(r"\d+", "tel 030-1234"), ["030", "1234"]);
+        assert_eq!(find(r"\D+", "a1b"), ["a", "b"]);
+        assert_eq!(find(r"\w+", "foo_bar, baz!"), ["foo_bar", "baz"]);
+        assert_eq!(find(r"\s+", "a \t b"), [" \t "]);
+        assert_eq!(find(r"[\d-]+", "ID 12-34"), ["12-34"]);
+        // A leading ']' and a trailing '-' are literal
+        assert_eq!(find("[]a-]+", "x]-a]y"), ["]-a]"]);
+    }
+
+    #[test]
+    fn anchors_and_word_boundaries() {
+        assert_eq!(find("^ab", "abab"), ["ab"]);
+        assert_eq!(find("ab$", "abab"), ["ab"]);
+        assert_eq!(find("^abab$", "abab"), ["abab"]);
+        assert!(find("^b", "ab").is_empty());
+        assert_eq!(find(r"\bcat\b", "cat concat cat_ cat."), ["cat", "cat"]);
+    }
+
+    #[test]
+    fn alternation_and_groups() {
+        assert_eq!(find("cat|dog", "hotdog catalog"), ["dog", "cat"]);
+        assert_eq!(find("gr(a|e)y", "gray grey groy"), ["gray", "grey"]);
+        assert_eq!(find("(?:ab)+", "ababa"), ["abab"]);
+        assert_eq!(find("a(b|)c", "ac abc"), ["ac", "abc"]);
+    }
+
+    #[test]
+    fn repetitions() {
+        assert_eq!(find("ab*", "a ab abbb"), ["a", "ab", "abbb"]);
+        assert_eq!(find("ab+", "a ab abbb"), ["ab", "abbb"]);
+        assert_eq!(find("colou?r", "color colour colouur"), ["color", "colour"]);
+        assert_eq!(find(r"\d{3}", "12 1234"), ["123"]);
+        assert_eq!(find(r"\d{2,}", "1 12 123"), ["12", "123"]);
+        assert_eq!(find(r"\d{1,2}", "12345"), ["12", "34", "5"]);
+        // Greedy takes as much as it can, lazy as little
+        assert_eq!(find("<.*>", "<a><b>"), ["<a><b>"]);
+        assert_eq!(find("<.*?>", "<a><b>"), ["<a>", "<b>"]);
+        // Backtracking gives back what the rest needs
+        assert_eq!(find("a+ab", "aaab"), ["aaab"]);
+    }
+
+    #[test]
+    fn non_ascii_text() {
+        assert_eq!(find("straße", "Die Straße, die straße"), ["straße"]);
+        assert_eq!(find(r"\w+", "naïve café 東京"), ["naïve", "café", "東京"]);
+        assert_eq!(find("[à-ÿ]+", "voilà où"), ["à", "ù"]);
+        assert_eq!(find("é.", "éa é😀"), ["éa", "é😀"]);
+        assert_eq!(find(r"\bÜber\b", "Über Überall"), ["Über"]);
+    }
+
+    #[test]
+    fn ignoring_case() {
+        assert_eq!(find("(?i)secret", "SECRET Secret secret"), ["SECRET", "Secret", "secret"]);
+        assert_eq!(find("(?i)[a-z]+", "ABC"), ["ABC"]);
+        assert_eq!(find("(?i)ÄRGER", "ärger"), ["ärger"]);
+        assert!(find("secret", "SECRET").is_empty());
+    }
+
+    #[test]
+    fn invalid_patterns_are_refused() {
+        for pattern in ["(a", "a)", "[a", "*a", "a{2", "a{3,1}", "a{x}", r"\q", "\\", "^*", "[z-a]"] {
+            assert!(pattern.parse::<Regex>().is_err(), "{}", pattern);
+        }
+    }
+}
@@ -0,0 +1,69 @@
+use std::path::{Path, PathBuf};
+
+use crate::messages::tr;
+
+/// Whether an input names a remote document rather than a local path.
+pub fn is_url(path: &Path) -> bool {
+    let path = path.to_string_lossy();
+    path.starts_with("http://") || path.starts_with("https://") || path.starts_with("s3://")
+}
+
+/// Download `url` into `dir` and return the local file. `index` keeps equally named files apart.
+pub fn download(url: &str, dir: &Path, index: usize) -> crate::Result<PathBuf> {
+    let name = url
+        .split(['?', '#'])
+        .next()
+        .and_then(|u| u.rsplit('/').next())
+        .filter(|n| !n.is_empty())
+        .unwrap_or("download.pdf")
+        .replace(|c: char| !(c.is_ascii_alphanumeric() || "._-".contains(c)), "_");
+    let target = dir.join(format!("{:03}-{}", index, name));
+    if url.starts_with("s3://") {
+        fetch_s3(url, &target)?;
+    } else {
+        fetch_http(url, &target)?;
+    }
+    log::info!("{}", tr("Downloaded {} to {}", &[&url, &target.display()]));
+    Ok(target)
+}
+
+#[cfg(feature = "net")]
+fn fetch_http(url: &str, target: &Path) -> crate::Result<()> {
+    // curl handles TLS, redirects and proxies the same way the rest of a CI pipeline does
+    let status = std::process::Command::new("curl")
+        .args(["--fail", "--silent", "--show-error", "--location", "--output"])
+        .arg(target)
+        .arg(url)
+        .status()
+        .map_err(|e| crate::Error::Invalid(tr("{} could not be downloaded, is curl installed? {}", &[&url, &e])))?;
+    if !status.success() {
+        return Err(crate::Error::Invalid(tr("{} could not be downloaded ({})", &[&url, &status])));
+    }
+    Ok(())
+}
+
+#[cfg(not(feature = "net"))]
+fn fetch_http(url: &str, _target: &Path) -> crate::Result<()> {
+    Err(crate::Error::Invalid(tr("{}: URL inputs need merge_pdf built with the `net` feature", &[&url])))
+}
+
+/// Fetch `s3://bucket/key` with the AWS CLI, which finds the credentials the way every other
+/// tool of the job does (environment, profile, instance or task role). Other S3 compatible
+/// stores are reached by pointing `AWS_ENDPOINT_URL` at them.
+#[cfg(feature = "s3")]
+fn fetch_s3(url: &str, target: &Path) -> crate::Result<()> {
+    let status = std::process::Command::new("aws")
+        .args(["s3", "cp", "--only-show-errors", "--no-progress", url])
+        .arg(target)
+        .status()
+        .map_err(|e| crate::Error::Invalid(tr("{} could not be downloaded, is the AWS CLI installed? {}", &[&url, &e])))?;
+    if !status.success() {
+        return Err(crate::Error::Invalid(tr("{} could not be downloaded ({})", &[&url, &status])));
+    }
+    Ok(())
+}
+
+#[cfg(not(feature = "s3"))]
+fn fetch_s3(url: &str, _target: &Path) -> crate::Result<()> {
+    Err(crate::Error::Invalid(tr("{}: s3:// inputs need merge_pdf built with the `s3` feature", &[&url])))
+}
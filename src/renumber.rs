@@ -0,0 +1,78 @@
+use std::collections::{BTreeMap, HashMap};
+
+use lopdf::{Dictionary, Document, Object, ObjectId};
+
+/// Objects a thread rewrites at least, fewer are not worth starting a thread for.
+const CHUNK: usize = 4096;
+
+/// `Document::renumber_objects`, see `renumber_objects_with`.
+pub fn renumber_objects(doc: &mut Document) {
+    renumber_objects_with(doc, 1);
+}
+
+/// `Document::renumber_objects_with`: number the objects of `doc` consecutively from `starting_id`,
+/// the pages first in page order, and point every reference and bookmark at the new ids.
+///
+/// The references are rewritten on as many threads as there are cores, every one taking its share
+/// of the objects with the same map of ids. Unlike lopdf, objects not reachable from the trailer
+/// are rewritten too, so none is left pointing at an id another object now has.
+pub fn renumber_objects_with(doc: &mut Document, starting_id: u32) {
+    let ids = doc.objects.keys().copied().collect::<Vec<_>>();
+    // Pages take the lowest of the page ids in page order, whatever ids they had
+    let pages = doc.page_iter().filter(|id| doc.objects.contains_key(id)).collect::<Vec<_>>();
+    let mut page_ids = pages.clone();
+    page_ids.sort_unstable();
+    page_ids.dedup();
+    let reordered = if page_ids.len() == pages.len() { page_ids.into_iter().zip(pages).collect() } else { HashMap::new() };
+    let replace = ids
+        .iter()
+        .enumerate()
+        .map(|(n, id)| {
+            let old = reordered.get(id).copied().unwrap_or(*id);
+            (old, (starting_id + n as u32, old.1))
+        })
+        .filter(|(old, new)| old != new)
+        .collect::<HashMap<_, _>>();
+
+    let mut objects = std::mem::take(&mut doc.objects).into_iter().collect::<Vec<_>>();
+    let rewrite_part = |part: &mut [(ObjectId, Object)]| {
+        for (id, object) in part {
+            *id = replace.get(id).copied().unwrap_or(*id);
+            rewrite(object, &replace);
+        }
+    };
+    let threads = std::thread::available_parallelism().map_or(1, usize::from).min(objects.len() / CHUNK);
+    if threads <= 1 {
+        rewrite_part(&mut objects);
+    } else {
+        let chunk = objects.len().div_ceil(threads);
+        let rewrite_part = &rewrite_part;
+        std::thread::scope(|scope| {
+            for part in objects.chunks_mut(chunk) {
+                scope.spawn(move || rewrite_part(part));
+            }
+        });
+    }
+    doc.objects = objects.into_iter().collect::<BTreeMap<_, _>>();
+    rewrite_dictionary(&mut doc.trailer, &replace);
+    for bookmark in doc.bookmark_table.values_mut() {
+        bookmark.page = replace.get(&bookmark.page).copied().unwrap_or(bookmark.page);
+    }
+    doc.max_id = starting_id + ids.len() as u32 - 1;
+}
+
+fn rewrite(object: &mut Object, replace: &HashMap<ObjectId, ObjectId>) {
+    match object {
+        Object::Reference(id) => *id = replace.get(id).copied().unwrap_or(*id),
+        Object::Array(array) => array.iter_mut().for_each(|item| rewrite(item, replace)),
+        Object::Dictionary(dict) => rewrite_dictionary(dict, replace),
+        Object::Stream(stream) => rewrite_dictionary(&mut stream.dict, replace),
+        _ => {}
+    }
+}
+
+fn rewrite_dictionary(dict: &mut Dictionary, replace: &HashMap<ObjectId, ObjectId>) {
+    for (_, value) in dict.iter_mut() {
+        rewrite(value, replace);
+    }
+}
@@ -0,0 +1,210 @@
+use std::{
+    borrow::Cow,
+    collections::{BTreeMap, BTreeSet},
+    io::Write,
+};
+
+use lopdf::{Dictionary, Document, Object};
+
+use crate::{messages::tr, pages};
+
+/// Trailer entries carried over from the damaged file, whichever of its trailers or cross-reference
+/// streams names them last.
+const TRAILER: [&[u8]; 4] = [b"Root", b"Info", b"Encrypt", b"ID"];
+
+/// Load a document from `bytes`, and if that fails or leaves it without a catalog or pages, load
+/// it again from `rebuild`'s repair of them, with its page tree rebuilt by `repair` if it has
+/// lost it. The bytes it was loaded from come along, borrowed
+/// unless they were repaired; when repairing does not help, it is loaded as it is, if it can be.
+pub fn load(bytes: &[u8]) -> lopdf::Result<(Document, Cow<'_, [u8]>)> {
+    let loaded = Document::load_mem(bytes);
+    if !loaded.as_ref().is_ok_and(is_whole) {
+        if let Some((doc, rebuilt)) = rebuild(bytes).and_then(|rebuilt| Some((load_rebuilt(&rebuilt)?, rebuilt))) {
+            return Ok((doc, Cow::Owned(rebuilt)));
+        }
+    }
+    loaded.map(|doc| (doc, Cow::Borrowed(bytes)))
+}
+
+fn load_rebuilt(bytes: &[u8]) -> Option<Document> {
+    let mut doc = Document::load_mem(bytes).ok()?;
+    // The cross-reference streams of the damaged file describe it no longer
+    doc.objects.retain(|_, object| object.type_name().ok() != Some("XRef"));
+    if doc.catalog().is_err() {
+        let catalog = doc.objects.iter().find(|(_, object)| object.type_name().ok() == Some("Catalog")).map(|(id, _)| *id)?;
+        doc.trailer.set("Root", catalog);
+    }
+    // Renumbering would change the keys of encrypted strings and streams
+    if doc.get_pages().is_empty() && !doc.is_encrypted() {
+        repair(&mut doc).ok()?;
+    }
+    is_whole(&doc).then_some(doc)
+}
+
+fn is_whole(doc: &Document) -> bool {
+    doc.catalog().is_ok() && !doc.get_pages().is_empty()
+}
+
+/// What `repair` did to a document.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Repaired {
+    /// Pages in the rebuilt page tree
+    pub pages: usize,
+    /// Pages that were not in the page tree, put after those that were
+    pub recovered: usize,
+    /// Objects nothing refers to any more, dropped
+    pub dropped: usize,
+}
+
+/// Give `doc`, loaded with `load`, a page tree built afresh: a single `Pages` node with the pages
+/// of its old tree, in order, then the other pages something in the document still refers to,
+/// each with what it inherited from the old tree and a MediaBox. A document whose page tree
+/// yields no page at all gets every page object found in it. Objects left unreachable are
+/// dropped and the rest renumbered.
+pub fn repair(doc: &mut Document) -> crate::Result<Repaired> {
+    let is_page = |object: &Object| object.type_name().ok() == Some("Page");
+    let mut pages = doc.get_pages().into_values().collect::<Vec<_>>();
+    let mut dropped = 0;
+    if !pages.is_empty() {
+        // Only the pages still reachable count; earlier versions of the file may have left others
+        dropped += doc.prune_objects().len();
+    }
+    let in_tree = pages.iter().copied().collect::<BTreeSet<_>>();
+    let recovered = doc.objects.iter().filter(|(id, object)| !in_tree.contains(id) && is_page(object)).map(|(id, _)| *id).collect::<Vec<_>>();
+    pages.extend(&recovered);
+    if pages.is_empty() {
+        return Err(crate::Error::Invalid(tr("no page objects were found", &[])));
+    }
+
+    let pages_id = doc.new_object_id();
+    for &page_id in &pages {
+        let mut page = pages::with_inherited_attributes(doc, page_id)?;
+        if let Ok(dict) = page.as_dict_mut() {
+            dict.set("Parent", pages_id);
+            if !dict.has(b"MediaBox") {
+                dict.set("MediaBox", vec![0.into(), 0.into(), 612.into(), 792.into()]);
+            }
+        }
+        doc.objects.insert(page_id, page);
+    }
+    let mut tree = Dictionary::new();
+    tree.set("Type", "Pages");
+    tree.set("Count", pages.len() as i64);
+    tree.set("Kids", pages.iter().map(|&id| Object::Reference(id)).collect::<Vec<_>>());
+    doc.objects.insert(pages_id, Object::Dictionary(tree));
+    doc.catalog_mut()?.set("Pages", pages_id);
+
+    dropped += doc.prune_objects().len();
+    doc.renumber_objects();
+    Ok(Repaired { pages: pages.len(), recovered: recovered.len(), dropped })
+}
+
+/// A copy of the PDF file in `bytes` with a cross-reference table made by scanning it for
+/// objects, for files whose own is broken, points at the wrong offsets, or is missing. Whatever
+/// comes before `%PDF-` is left out, and an object defined more than once is taken as last
+/// defined, like an incremental update would. `None` if there is no header or no object.
+pub fn rebuild(bytes: &[u8]) -> Option<Vec<u8>> {
+    let start = find(bytes, b"%PDF-", 0)?;
+    let bytes = &bytes[start..];
+
+    let mut offsets = BTreeMap::new();
+    let mut pos = 0;
+    while let Some(found) = find(bytes, b"obj", pos) {
+        pos = found + 3;
+        if bytes.get(pos).is_some_and(|&b| !is_delimiter(b)) {
+            continue;
+        }
+        let Some((offset, id, generation)) = object_start(bytes, found) else { continue };
+        offsets.insert(id, (generation, offset));
+        // Stream data could look like an object header
+        if let Some(end) = find(bytes, b"endobj", pos) {
+            pos = end + 6;
+        }
+    }
+    let size = offsets.keys().next_back()? + 1;
+
+    let mut out = bytes.to_vec();
+    if !out.ends_with(b"\n") {
+        out.push(b'\n');
+    }
+    let xref_start = out.len();
+    write!(out, "xref\n0 {}\n0000000000 65535 f \n", size).ok()?;
+    for id in 1..size {
+        match offsets.get(&id) {
+            Some((generation, offset)) => writeln!(out, "{:010} {:05} n ", offset, generation).ok()?,
+            None => out.extend_from_slice(b"0000000000 65535 f \n"),
+        }
+    }
+    write!(out, "trailer\n<< /Size {}", size).ok()?;
+    for key in TRAILER {
+        if let Some(value) = last_value(bytes, key) {
+            out.extend_from_slice(b" /");
+            out.extend_from_slice(key);
+            out.push(b' ');
+            out.extend_from_slice(value);
+        }
+    }
+    write!(out, " >>\nstartxref\n{}\n%%EOF\n", xref_start).ok()?;
+    Some(out)
+}
+
+/// The offset, number and generation of the object whose `obj` keyword is at `at`, if it
+/// follows a number and a generation.
+fn object_start(bytes: &[u8], at: usize) -> Option<(usize, u32, u16)> {
+    let generation_end = skip_back(bytes, at, |b| b.is_ascii_whitespace());
+    let generation_start = skip_back(bytes, generation_end, |b| b.is_ascii_digit());
+    let id_end = skip_back(bytes, generation_start, |b| b.is_ascii_whitespace());
+    let id_start = skip_back(bytes, id_end, |b| b.is_ascii_digit());
+    if generation_end == at || generation_start == generation_end || id_end == generation_start || id_start == id_end {
+        return None;
+    }
+    if id_start > 0 && !is_delimiter(bytes[id_start - 1]) {
+        return None;
+    }
+    let id = std::str::from_utf8(&bytes[id_start..id_end]).ok()?.parse().ok()?;
+    let generation = std::str::from_utf8(&bytes[generation_start..generation_end]).ok()?.parse().ok()?;
+    Some((id_start, id, generation))
+}
+
+/// The value of the last `/key` entry in `bytes` that is an indirect reference or an array.
+fn last_value<'a>(bytes: &'a [u8], key: &[u8]) -> Option<&'a [u8]> {
+    let name = [b"/".as_slice(), key].concat();
+    let mut found = None;
+    let mut pos = 0;
+    while let Some(at) = find(bytes, &name, pos) {
+        pos = at + name.len();
+        if bytes.get(pos).is_some_and(|&b| is_delimiter(b)) {
+            found = value(&bytes[pos..]).or(found);
+        }
+    }
+    found
+}
+
+/// The reference (`12 0 R`) or array at the start of `bytes`, after any whitespace.
+fn value(bytes: &[u8]) -> Option<&[u8]> {
+    let start = bytes.iter().position(|b| !b.is_ascii_whitespace())?;
+    let bytes = &bytes[start..];
+    if bytes[0] == b'[' {
+        let end = bytes.iter().position(|&b| b == b']')?;
+        return Some(&bytes[..=end]);
+    }
+    let end = bytes.iter().position(|&b| b == b'R')?;
+    let reference = &bytes[..=end];
+    let tokens = reference[..end].split(u8::is_ascii_whitespace).filter(|token| !token.is_empty()).collect::<Vec<_>>();
+    (tokens.len() == 2 && tokens.iter().all(|token| token.iter().all(u8::is_ascii_digit))).then_some(reference)
+}
+
+fn skip_back(bytes: &[u8], mut pos: usize, skip: impl Fn(u8) -> bool) -> usize {
+    while pos > 0 && skip(bytes[pos - 1]) {
+        pos -= 1;
+    }
+    pos
+}
+
+fn is_delimiter(b: u8) -> bool {
+    b.is_ascii_whitespace() || b"()<>[]{}/%".contains(&b)
+}
+
+fn find(bytes: &[u8], pattern: &[u8], from: usize) -> Option<usize> {
+    bytes.get(from..)?.windows(pattern.len()).position(|window| window == pattern).map(|at| from + at)
+}
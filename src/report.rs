@@ -0,0 +1,92 @@
+use std::{
+    ops::Range,
+    path::{Path, PathBuf},
+};
+
+use clap::ValueEnum;
+
+use crate::{
+    json::{self, Value},
+    outline::OutlineItem,
+    outline_json,
+};
+
+/// Formats of `--report`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Format {
+    Json,
+}
+
+/// What a merge made, for scripts that run it, as JSON like
+///
+/// ```json
+/// {
+///   "output": "merged.pdf",
+///   "bytes": 52311,
+///   "sha256": "9f86d081884c7d659a2feaa0c55ad015a3bf4f1b2b0b822cd15d6c15b0f00a08",
+///   "pages": 5,
+///   "page_sha256": ["2c26b46b68ffc68ff99b453c1d30413413422d706483bfa0f98a5e886266e7ae"],
+///   "inputs": [{ "path": "a.pdf", "pages": 2, "first_page": 1, "last_page": 2 }],
+///   "skipped": ["broken.pdf"],
+///   "bookmarks": [{ "title": "a", "page": 1 }],
+///   "warnings": ["broken.pdf could not be loaded: Invalid file header"]
+/// }
+/// ```
+///
+/// where pages count from 1 in the output and `page_sha256`, there when asked for, holds the
+/// hash of every page's content streams, decoded. Inputs leave out `first_page` and `last_page`
+/// when their pages were put several on a sheet; bookmarks are written like `outline_json::write`
+/// does.
+#[derive(Debug, Default)]
+pub struct Report {
+    pub output: PathBuf,
+    pub bytes: u64,
+    /// SHA-256 of the output file, in hexadecimal
+    pub sha256: String,
+    pub pages: usize,
+    /// SHA-256 of every page's content, in hexadecimal, if asked for
+    pub page_hashes: Option<Vec<String>>,
+    /// Every merged input with its pages in the output, see `Merged::pages`
+    pub inputs: Vec<(PathBuf, Range<usize>)>,
+    /// Whether the pages were put several on a sheet, so inputs have no pages of their own
+    pub imposed: bool,
+    /// Inputs left out by `--skip-broken`
+    pub skipped: Vec<PathBuf>,
+    pub bookmarks: Vec<OutlineItem>,
+    pub warnings: Vec<String>,
+}
+
+impl Report {
+    pub fn to_json(&self) -> String {
+        let path = |path: &Path| Value::String(path.display().to_string());
+        let number = |n: usize| Value::Number(n as f64);
+        let inputs = self
+            .inputs
+            .iter()
+            .map(|(input, pages)| {
+                let mut members = vec![("path".to_string(), path(input)), ("pages".to_string(), number(pages.len()))];
+                if !self.imposed {
+                    members.push(("first_page".to_string(), number(pages.start)));
+                    members.push(("last_page".to_string(), number(pages.end - 1)));
+                }
+                Value::Object(members)
+            })
+            .collect();
+        let mut members = vec![
+            ("output".to_string(), path(&self.output)),
+            ("bytes".to_string(), Value::Number(self.bytes as f64)),
+            ("sha256".to_string(), Value::String(self.sha256.clone())),
+            ("pages".to_string(), number(self.pages)),
+        ];
+        if let Some(hashes) = &self.page_hashes {
+            members.push(("page_sha256".to_string(), Value::Array(hashes.iter().cloned().map(Value::String).collect())));
+        }
+        members.extend([
+            ("inputs".to_string(), Value::Array(inputs)),
+            ("skipped".to_string(), Value::Array(self.skipped.iter().map(|skipped| path(skipped)).collect())),
+            ("bookmarks".to_string(), outline_json::to_value(&self.bookmarks)),
+            ("warnings".to_string(), Value::Array(self.warnings.iter().cloned().map(Value::String).collect())),
+        ]);
+        json::to_string_pretty(&Value::Object(members))
+    }
+}
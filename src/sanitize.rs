@@ -0,0 +1,99 @@
+use std::collections::HashSet;
+
+use lopdf::{Dictionary, Document, Object, ObjectId};
+
+use crate::{input::InputSpec, messages::tr};
+
+/// Take the active content out of `doc`: JavaScript actions (and any other action carrying
+/// `/JS`), launch actions, the document-level scripts, embedded files and the file attachment
+/// annotations that hold them. Actions are removed wherever they are triggered from, and
+/// dropped from the chains of actions that follow others.
+pub fn sanitize(spec: &InputSpec, doc: &mut Document) -> crate::Result<()> {
+    let active = doc
+        .objects
+        .iter()
+        .filter(|(_, object)| object.as_dict().is_ok_and(is_active))
+        .map(|(id, _)| *id)
+        .collect::<HashSet<_>>();
+    let mut actions = active.len();
+    let mut files = 0;
+
+    if let Ok(names) = doc.catalog().and_then(|catalog| catalog.get(b"Names")).cloned() {
+        let names = match names {
+            Object::Reference(id) => doc.get_dictionary_mut(id).ok(),
+            _ => doc.catalog_mut()?.get_mut(b"Names").and_then(Object::as_dict_mut).ok(),
+        };
+        if let Some(names) = names {
+            names.remove(b"JavaScript");
+            names.remove(b"EmbeddedFiles");
+        }
+    }
+    for page_id in doc.get_pages().into_values() {
+        let Ok(annotations) = doc.get_dictionary(page_id)?.get_deref(b"Annots", doc).and_then(Object::as_array) else { continue };
+        let kept = annotations
+            .iter()
+            .filter(|annotation| {
+                let dict = match annotation {
+                    Object::Reference(id) => doc.get_dictionary(*id).ok(),
+                    annotation => annotation.as_dict().ok(),
+                };
+                dict.and_then(|dict| dict.get(b"Subtype").and_then(Object::as_name).ok()) != Some(b"FileAttachment".as_slice())
+            })
+            .cloned()
+            .collect::<Vec<_>>();
+        if kept.len() < annotations.len() {
+            doc.get_dictionary_mut(page_id)?.set("Annots", kept);
+        }
+    }
+    for object in doc.objects.values_mut() {
+        let dict = match object {
+            Object::Dictionary(dict) => dict,
+            Object::Stream(stream) => &mut stream.dict,
+            _ => continue,
+        };
+        strip(dict, &active, &mut actions, &mut files);
+    }
+    // The scripts and files nothing points to any more
+    doc.prune_objects();
+
+    if actions > 0 || files > 0 {
+        log::info!("{}", tr("{}: removed {} script or launch action(s) and {} embedded file(s)", &[&spec.path.display(), &actions, &files]));
+    }
+    Ok(())
+}
+
+/// Whether `dict` is an action that runs something: a script or another program.
+fn is_active(dict: &Dictionary) -> bool {
+    matches!(dict.get(b"S").and_then(Object::as_name), Ok(b"JavaScript" | b"Launch")) || dict.has(b"JS")
+}
+
+/// Remove from `dict`, and the dictionaries in it, the entries that trigger actions that are
+/// `active` or inline active ones, and the embedded files of file specifications.
+fn strip(dict: &mut Dictionary, active: &HashSet<ObjectId>, actions: &mut usize, files: &mut usize) {
+    if dict.remove(b"EF").is_some() {
+        *files += 1;
+    }
+    let mut removed = Vec::new();
+    for (key, value) in dict.iter_mut() {
+        match value {
+            Object::Reference(id) if active.contains(id) => removed.push(key.clone()),
+            Object::Dictionary(inner) if is_active(inner) => {
+                *actions += 1;
+                removed.push(key.clone());
+            }
+            Object::Dictionary(inner) => strip(inner, active, actions, files),
+            Object::Array(items) if key == b"Next" => items.retain(|item| match item {
+                Object::Reference(id) => !active.contains(id),
+                Object::Dictionary(inner) if is_active(inner) => {
+                    *actions += 1;
+                    false
+                }
+                _ => true,
+            }),
+            _ => {}
+        }
+    }
+    for key in removed {
+        dict.remove(&key);
+    }
+}
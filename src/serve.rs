@@ -0,0 +1,330 @@
+use std::{
+    fs,
+    io::{BufRead, BufReader, Read, Write},
+    net::{TcpListener, TcpStream},
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Condvar, Mutex,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use crate::{json, messages::tr, tempdir::TempDir, MergeOptions};
+
+/// Longest request line and headers read, together.
+const MAX_HEAD: usize = 64 * 1024;
+
+/// How long a client may leave a request half sent, or an answer unread.
+const TIMEOUT: Duration = Duration::from_secs(30);
+
+/// How long sending a whole request may take, however steadily it trickles in.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(600);
+
+/// Requests served so far, for every upload to get a scratch directory of its own.
+static REQUESTS: AtomicUsize = AtomicUsize::new(0);
+
+/// Merging over HTTP, for `merge_pdf serve`:
+///
+/// - `POST /merge` with `multipart/form-data` merges the uploaded files, in the order sent;
+/// - `POST /merge` with `application/json`, `{"paths": ["a.pdf", "b.pdf"]}` or the list alone,
+///   merges files on the server, found below `root` and only there;
+/// - `GET /health` answers `ok`.
+///
+/// A merge answers with the merged PDF, a failure with its message as text and a status that
+/// tells what failed: 400 for the request, 404 for a missing input, 413 for an upload over
+/// `max_upload` bytes, 422 for inputs that cannot be merged.
+///
+/// At most `max_connections` connections are answered at once, each holding up to `max_upload`
+/// bytes; further clients wait in the listener's backlog until one is done.
+#[derive(Debug, Clone)]
+pub struct Server {
+    /// Directory the paths of JSON requests are in; without it, only uploads are merged
+    pub root: Option<PathBuf>,
+    pub max_upload: u64,
+    pub max_connections: usize,
+    pub options: MergeOptions,
+}
+
+/// The connections being answered, for `Server::run` to wait for one to end at the limit.
+struct Slots {
+    taken: Mutex<usize>,
+    freed: Condvar,
+}
+
+impl Slots {
+    /// Wait until fewer than `max` connections are answered, and count one more until the
+    /// `Slot` is dropped.
+    fn acquire(self: &Arc<Self>, max: usize) -> Slot {
+        let mut taken = self.taken.lock().unwrap_or_else(|e| e.into_inner());
+        while *taken >= max.max(1) {
+            taken = self.freed.wait(taken).unwrap_or_else(|e| e.into_inner());
+        }
+        *taken += 1;
+        Slot(self.clone())
+    }
+}
+
+/// A connection counted in `Slots`, also when answering it panics.
+struct Slot(Arc<Slots>);
+
+impl Drop for Slot {
+    fn drop(&mut self) {
+        *self.0.taken.lock().unwrap_or_else(|e| e.into_inner()) -= 1;
+        self.0.freed.notify_one();
+    }
+}
+
+/// A connection read with `TIMEOUT` between pieces and a deadline for all of them.
+struct Timed<'a> {
+    stream: &'a TcpStream,
+    deadline: Instant,
+}
+
+impl Read for Timed<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let left = self.deadline.saturating_duration_since(Instant::now());
+        if left.is_zero() {
+            return Err(std::io::ErrorKind::TimedOut.into());
+        }
+        self.stream.set_read_timeout(Some(left.min(TIMEOUT)))?;
+        self.stream.read(buf)
+    }
+}
+
+/// What to answer a request with.
+struct Response {
+    status: u16,
+    content_type: &'static str,
+    body: Vec<u8>,
+}
+
+impl Response {
+    fn text(status: u16, text: String) -> Self {
+        Response { status, content_type: "text/plain; charset=utf-8", body: format!("{}\n", text).into_bytes() }
+    }
+
+    fn error(e: &crate::Error) -> Self {
+        let status = match e {
+            crate::Error::Usage(_) => 400,
+            crate::Error::MissingInput(..) => 404,
+            crate::Error::EncryptedInput { .. } | crate::Error::BrokenInput(..) | crate::Error::NoPages | crate::Error::NoPagesRoot => 422,
+            _ => 500,
+        };
+        Response::text(status, e.to_string())
+    }
+}
+
+/// A request as read off the connection.
+struct Request {
+    method: String,
+    target: String,
+    /// Header names in lower case
+    headers: Vec<(String, String)>,
+    body: Vec<u8>,
+}
+
+impl Request {
+    fn header(&self, name: &str) -> Option<&str> {
+        self.headers.iter().find(|(key, _)| key == name).map(|(_, value)| value.as_str())
+    }
+}
+
+impl Server {
+    /// Answer requests on `listener`, each on a thread of its own, `max_connections` at a time,
+    /// until the process ends.
+    pub fn run(self, listener: TcpListener) -> crate::Result<()> {
+        log::info!("{}", tr("Listening on http://{}", &[&listener.local_addr()?]));
+        let server = Arc::new(self);
+        let slots = Arc::new(Slots { taken: Mutex::new(0), freed: Condvar::new() });
+        for stream in listener.incoming() {
+            let Ok(stream) = stream else { continue };
+            let slot = slots.acquire(server.max_connections);
+            let server = server.clone();
+            thread::spawn(move || {
+                server.handle(stream);
+                drop(slot);
+            });
+        }
+        Ok(())
+    }
+
+    fn handle(&self, stream: TcpStream) {
+        let peer = stream.peer_addr().map_or_else(|_| "?".to_string(), |peer| peer.to_string());
+        let _ = stream.set_write_timeout(Some(TIMEOUT));
+        let mut reader = BufReader::new(Timed { stream: &stream, deadline: Instant::now() + REQUEST_TIMEOUT });
+        let (line, response) = match self.read(&mut reader) {
+            Ok(request) => (format!("{} {}", request.method, request.target), self.respond(&request)),
+            Err(response) => ("-".to_string(), response),
+        };
+        log::info!("{} {} {} ({} bytes)", peer, line, response.status, response.body.len());
+        let head = format!(
+            "HTTP/1.1 {} {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+            response.status,
+            reason(response.status),
+            response.content_type,
+            response.body.len()
+        );
+        let mut stream = &stream;
+        let _ = stream.write_all(head.as_bytes()).and_then(|_| stream.write_all(&response.body)).and_then(|_| stream.flush());
+    }
+
+    /// The request on `reader`, or what to answer when it is not one this serves.
+    fn read(&self, reader: &mut BufReader<Timed>) -> Result<Request, Response> {
+        let bad = |text: &str| Response::text(400, tr(text, &[]));
+        let mut head = Vec::new();
+        while !head.ends_with(b"\r\n\r\n") && !head.ends_with(b"\n\n") {
+            let read = reader.by_ref().take((MAX_HEAD - head.len()) as u64).read_until(b'\n', &mut head).map_err(|_| bad("the request could not be read"))?;
+            if read == 0 || head.len() >= MAX_HEAD {
+                return Err(bad("the request could not be read"));
+            }
+        }
+        let head = String::from_utf8_lossy(&head);
+        let mut lines = head.lines();
+        let mut request_line = lines.next().unwrap_or_default().split_whitespace();
+        let (Some(method), Some(target)) = (request_line.next(), request_line.next()) else {
+            return Err(bad("the request could not be read"));
+        };
+        let headers = lines
+            .filter_map(|line| line.split_once(':'))
+            .map(|(name, value)| (name.trim().to_ascii_lowercase(), value.trim().to_string()))
+            .collect::<Vec<_>>();
+        let mut request = Request { method: method.to_string(), target: target.to_string(), headers, body: Vec::new() };
+        if request.header("transfer-encoding").is_some() {
+            return Err(Response::text(411, tr("send the body with a Content-Length", &[])));
+        }
+        let length = match request.header("content-length") {
+            Some(length) => length.parse::<u64>().map_err(|_| bad("the Content-Length is not a number"))?,
+            None => 0,
+        };
+        if length > self.max_upload {
+            return Err(Response::text(413, tr("the request is over the limit of {} bytes", &[&self.max_upload])));
+        }
+        if request.header("expect").is_some_and(|expect| expect.eq_ignore_ascii_case("100-continue")) {
+            let mut stream = reader.get_ref().stream;
+            let _ = stream.write_all(b"HTTP/1.1 100 Continue\r\n\r\n");
+        }
+        reader.take(length).read_to_end(&mut request.body).map_err(|_| bad("the request could not be read"))?;
+        if request.body.len() as u64 != length {
+            return Err(bad("the request could not be read"));
+        }
+        Ok(request)
+    }
+
+    fn respond(&self, request: &Request) -> Response {
+        let path = request.target.split('?').next().unwrap_or_default();
+        match (request.method.as_str(), path) {
+            ("GET", "/health") => Response::text(200, "ok".to_string()),
+            ("POST", "/merge") => match self.merge(request) {
+                Ok(pdf) => Response { status: 200, content_type: "application/pdf", body: pdf },
+                Err(e) => Response::error(&e),
+            },
+            (_, "/health" | "/merge") => Response::text(405, tr("{} is not allowed here", &[&request.method])),
+            _ => Response::text(404, tr("{} is not here, try POST /merge", &[&path])),
+        }
+    }
+
+    /// The merged PDF of the inputs `request` uploads or names.
+    fn merge(&self, request: &Request) -> crate::Result<Vec<u8>> {
+        let content_type = request.header("content-type").unwrap_or_default();
+        let media_type = content_type.split(';').next().unwrap_or_default().trim().to_ascii_lowercase();
+        // Kept until the merge is written, uploads are loaded from it
+        let scratch;
+        let inputs = match media_type.as_str() {
+            "multipart/form-data" => {
+                scratch = TempDir::named(&format!("serve-{}", REQUESTS.fetch_add(1, Ordering::Relaxed)))?;
+                let boundary = parameter(content_type, "boundary").ok_or_else(|| crate::Error::Usage(tr("the multipart body has no boundary", &[])))?;
+                save_uploads(&request.body, &boundary, scratch.path())?
+            }
+            "application/json" => self.paths(&request.body)?,
+            _ => return Err(crate::Error::Usage(tr("send the files as multipart/form-data, or their paths as application/json", &[]))),
+        };
+        if inputs.is_empty() {
+            return Err(crate::Error::Usage(tr("no files to merge", &[])));
+        }
+        let paths = inputs.iter().map(|(path, _)| path.clone()).collect::<Vec<_>>();
+        let mut document = crate::merge(&paths, &self.options).map_err(|e| as_sent(e, &inputs))?;
+        crate::write_to(&mut document, Vec::new(), &self.options)
+    }
+
+    /// The server-side inputs `body` lists, each below `root`, with the path it was listed as.
+    fn paths(&self, body: &[u8]) -> crate::Result<Vec<(PathBuf, String)>> {
+        let Some(root) = &self.root else {
+            return Err(crate::Error::Usage(tr("merging files on the server needs --root", &[])));
+        };
+        let value = json::parse(&String::from_utf8_lossy(body)).map_err(crate::Error::Usage)?;
+        let list = value.get("paths").unwrap_or(&value).as_array().ok_or_else(|| crate::Error::Usage(tr("expected a list of paths", &[])))?;
+        let root = root.canonicalize()?;
+        list.iter()
+            .map(|path| {
+                let path = path.as_str().ok_or_else(|| crate::Error::Usage(tr("expected a list of paths", &[])))?;
+                let resolved = root.join(path.trim_start_matches('/'));
+                let real = resolved.canonicalize().map_err(|e| crate::Error::MissingInput(PathBuf::from(path), e))?;
+                match real.starts_with(&root) {
+                    true => Ok((real, path.to_string())),
+                    false => Err(crate::Error::Usage(tr("{} is not below the root", &[&path]))),
+                }
+            })
+            .collect()
+    }
+}
+
+/// The value of `name=value` among the parameters of a header, unquoted.
+fn parameter(header: &str, name: &str) -> Option<String> {
+    header.split(';').skip(1).filter_map(|parameter| parameter.split_once('=')).find(|(key, _)| key.trim().eq_ignore_ascii_case(name)).map(|(_, value)| value.trim().trim_matches('"').to_string())
+}
+
+/// `e` naming the input it is about as the client sent it, not where it is on the server.
+fn as_sent(e: crate::Error, inputs: &[(PathBuf, String)]) -> crate::Error {
+    let sent = |path: PathBuf| inputs.iter().find(|(input, _)| *input == path).map_or(path, |(_, name)| PathBuf::from(name));
+    match e {
+        crate::Error::MissingInput(path, e) => crate::Error::MissingInput(sent(path), e),
+        crate::Error::EncryptedInput { path, tried } => crate::Error::EncryptedInput { path: sent(path), tried },
+        crate::Error::BrokenInput(path, e) => crate::Error::BrokenInput(sent(path), e),
+        e => e,
+    }
+}
+
+/// Write the files of a `multipart/form-data` `body` into `dir` and return them in the order
+/// they came in with their uploaded names, which they keep so images are told apart from PDFs.
+fn save_uploads(body: &[u8], boundary: &str, dir: &Path) -> crate::Result<Vec<(PathBuf, String)>> {
+    let delimiter = format!("--{}", boundary).into_bytes();
+    let broken = || crate::Error::Usage(tr("the multipart body could not be read", &[]));
+    let mut saved = Vec::new();
+    let mut rest = &body[find(body, &delimiter).ok_or_else(broken)? + delimiter.len()..];
+    // Every part ends where the next delimiter starts, the last with `--`
+    while !rest.starts_with(b"--") {
+        let part = rest.strip_prefix(b"\r\n").ok_or_else(broken)?;
+        let end = find(part, &[b"\r\n".as_slice(), &delimiter].concat()).ok_or_else(broken)?;
+        let (part, next) = (&part[..end], &part[end + 2 + delimiter.len()..]);
+        rest = next;
+        let head_end = find(part, b"\r\n\r\n").ok_or_else(broken)?;
+        let head = String::from_utf8_lossy(&part[..head_end]);
+        let disposition = head.lines().find(|line| line.to_ascii_lowercase().starts_with("content-disposition:")).unwrap_or_default();
+        // Form fields without a file are not inputs
+        let Some(sent) = parameter(disposition, "filename") else { continue };
+        let name = sent.rsplit(['/', '\\']).next().unwrap_or_default().replace(|c: char| !(c.is_ascii_alphanumeric() || "._-".contains(c)), "_");
+        let path = dir.join(format!("{:03}-{}", saved.len() + 1, name));
+        fs::write(&path, &part[head_end + 4..]).map_err(|e| crate::Error::saving(&path, e))?;
+        saved.push((path, sent));
+    }
+    Ok(saved)
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack.windows(needle.len()).position(|window| window == needle)
+}
+
+fn reason(status: u16) -> &'static str {
+    match status {
+        200 => "OK",
+        400 => "Bad Request",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        411 => "Length Required",
+        413 => "Content Too Large",
+        422 => "Unprocessable Content",
+        _ => "Internal Server Error",
+    }
+}
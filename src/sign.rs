@@ -0,0 +1,206 @@
+use std::path::{Path, PathBuf};
+
+use crate::messages::tr;
+
+/// A certificate and private key to sign the output with, see `sign`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Signer {
+    /// PKCS#12 file (`.p12` or `.pfx`) holding the certificate, its chain and the private key
+    pub certificate: PathBuf,
+    /// Password of the PKCS#12 file
+    pub password: String,
+}
+
+/// Bytes left for the signature at first; a certificate chain that needs more gets it on a
+/// second try.
+#[cfg(feature = "crypto")]
+const RESERVED: usize = 16 * 1024;
+
+/// Placeholder for the byte range, as long as the longest range the update can hold.
+#[cfg(feature = "crypto")]
+const BYTE_RANGE: &[u8] = b"[0 9999999999 9999999999 9999999999]";
+
+/// Sign the PDF at `path` with `signer`: an incremental update adds an invisible signature field
+/// to the first page holding a detached CAdES signature (`ETSI.CAdES.detached`, as PAdES asks)
+/// over the whole file but the signature itself. The signature is made by the system openssl,
+/// whose password prompts are kept away from the terminal.
+#[cfg(feature = "crypto")]
+pub fn sign(path: &Path, signer: &Signer) -> crate::Result<()> {
+    let invalid = |e: String| crate::Error::Invalid(tr("{} could not be signed: {}", &[&path.display(), &e]));
+    let original = std::fs::read(path)?;
+    let mut reserved = RESERVED;
+    loop {
+        let mut bytes = original.clone();
+        let (update, contents) = update(&original, reserved).map_err(|e| invalid(e.to_string()))?;
+        let (start, end) = (original.len() + contents.start, original.len() + contents.end);
+        bytes.extend_from_slice(&update);
+
+        let range = format!("[0 {} {} {}", start, end, bytes.len() - end).into_bytes();
+        let at = original.len() + find(&update, BYTE_RANGE).expect("the update has a byte range");
+        bytes[at..at + BYTE_RANGE.len()].fill(b' ');
+        bytes[at..at + range.len()].copy_from_slice(&range);
+        bytes[at + BYTE_RANGE.len() - 1] = b']';
+
+        let signature = cms(&[&bytes[..start], &bytes[end..]].concat(), signer).map_err(invalid)?;
+        // The hex string holds two digits a byte between its angle brackets
+        if signature.len() * 2 > end - start - 2 {
+            reserved = signature.len() + 1024;
+            continue;
+        }
+        let hex = signature.iter().map(|byte| format!("{:02X}", byte)).collect::<String>();
+        bytes[start + 1..start + 1 + hex.len()].copy_from_slice(hex.as_bytes());
+        std::fs::write(path, bytes)?;
+        return Ok(());
+    }
+}
+
+#[cfg(not(feature = "crypto"))]
+pub fn sign(path: &Path, _signer: &Signer) -> crate::Result<()> {
+    Err(crate::Error::Invalid(tr("{}: signing needs merge_pdf built with the `crypto` feature", &[&path.display()])))
+}
+
+/// The incremental update that signs the PDF `bytes`, with `reserved` bytes of room for the
+/// signature, and where in it the signature's hex string is. The update has the same kind of
+/// cross-reference section as the file.
+#[cfg(feature = "crypto")]
+fn update(bytes: &[u8], reserved: usize) -> crate::Result<(Vec<u8>, std::ops::Range<usize>)> {
+    use lopdf::{Dictionary, Document, Object, ObjectId, StringFormat};
+
+    use crate::{metadata, writer};
+
+    let doc = Document::load_mem(bytes)?;
+    if doc.is_encrypted() {
+        return Err(crate::Error::Invalid(tr("encrypted files cannot be signed", &[])));
+    }
+    let size = doc.trailer.get(b"Size").and_then(Object::as_i64).map_or(0, |size| size as u32).max(doc.max_id + 1);
+    let (field_id, signature_id, xref_id) = ((size, 0), (size + 1, 0), (size + 2, 0));
+
+    let root_id = doc.trailer.get(b"Root").and_then(Object::as_reference)?;
+    let page_id = *doc.get_pages().values().next().ok_or_else(|| crate::Error::Invalid(tr("no pages", &[])))?;
+    let deref_array = |dict: &Dictionary, key: &[u8]| dict.get_deref(key, &doc).and_then(Object::as_array).cloned().unwrap_or_default();
+
+    let mut page = doc.get_dictionary(page_id)?.clone();
+    let mut annotations = deref_array(&page, b"Annots");
+    annotations.push(Object::Reference(field_id));
+    page.set("Annots", annotations);
+
+    let mut catalog = doc.catalog()?.clone();
+    let mut form = catalog.get_deref(b"AcroForm", &doc).and_then(Object::as_dict).cloned().unwrap_or_default();
+    let mut fields = deref_array(&form, b"Fields");
+    fields.push(Object::Reference(field_id));
+    form.set("Fields", fields);
+    // Signatures exist, and the file may only be appended to
+    form.set("SigFlags", 3);
+    catalog.set("AcroForm", form);
+
+    let mut field = Dictionary::new();
+    field.set("Type", "Annot");
+    field.set("Subtype", "Widget");
+    field.set("FT", "Sig");
+    field.set("T", Object::string_literal("MergePdfSignature"));
+    field.set("Rect", vec![0.into(), 0.into(), 0.into(), 0.into()]);
+    // Print and Locked; without a size the widget is invisible
+    field.set("F", 132);
+    field.set("P", page_id);
+    field.set("V", signature_id);
+
+    // D:YYYYMMDDHHmmSSZ from YYYY-MM-DDTHH:mm:SSZ
+    let date = format!("D:{}", metadata::now().replace(['-', ':', 'T'], ""));
+    let mut signature = Dictionary::new();
+    signature.set("Type", "Sig");
+    signature.set("Filter", "Adobe.PPKLite");
+    signature.set("SubFilter", "ETSI.CAdES.detached");
+    signature.set("M", Object::string_literal(date));
+    signature.set("ByteRange", Object::Null);
+    signature.set("Contents", Object::String(vec![0; reserved], StringFormat::Hexadecimal));
+
+    let mut update = b"\n".to_vec();
+    let mut offsets: Vec<(ObjectId, usize)> = Vec::new();
+    let mut contents = 0..0;
+    for (id, object) in [(page_id, page), (root_id, catalog), (field_id, field), (signature_id, signature)] {
+        offsets.push((id, bytes.len() + update.len()));
+        update.extend_from_slice(format!("{} {} obj\n", id.0, id.1).as_bytes());
+        let mut written = Vec::new();
+        writer::write_object(&mut written, &Object::Dictionary(object));
+        if id == signature_id {
+            // Written in place of null, an array of the real numbers could come out shorter
+            let at = find(&written, b"null").expect("the signature has a null byte range");
+            written.splice(at..at + 4, BYTE_RANGE.iter().copied());
+            let start = find(&written, b"<000").expect("the signature has contents");
+            contents = update.len() + start..update.len() + start + reserved * 2 + 2;
+        }
+        update.extend_from_slice(&written);
+        update.extend_from_slice(b"\nendobj\n");
+    }
+
+    let mut trailer = Dictionary::new();
+    for key in [b"Root".as_slice(), b"Info", b"ID"] {
+        if let Ok(value) = doc.trailer.get(key) {
+            trailer.set(key, value.clone());
+        }
+    }
+    writer::finish_update(bytes, &mut update, offsets, trailer, xref_id.0)?;
+    Ok((update, contents))
+}
+
+/// A detached CMS signature of `data` in the CAdES form, made with `openssl cms`.
+#[cfg(feature = "crypto")]
+fn cms(data: &[u8], signer: &Signer) -> Result<Vec<u8>, String> {
+    let dir = crate::tempdir::TempDir::named("sign").map_err(|e| e.to_string())?;
+    // openssl reads the signer's certificate from a file, without asking the password for it
+    let certificate = dir.path().join("signer.pem");
+    let chain = dir.path().join("chain.pem");
+    let p12 = signer.certificate.as_os_str();
+    std::fs::write(&certificate, openssl(&["pkcs12".as_ref(), "-in".as_ref(), p12, "-nokeys".as_ref(), "-clcerts".as_ref()], signer, &[])?)
+        .map_err(|e| e.to_string())?;
+    let chain_pem = openssl(&["pkcs12".as_ref(), "-in".as_ref(), p12, "-nokeys".as_ref(), "-cacerts".as_ref()], signer, &[])?;
+
+    let mut args = vec!["cms", "-sign", "-binary", "-cades", "-nosmimecap", "-md", "sha256", "-outform", "DER"]
+        .into_iter()
+        .map(std::ffi::OsStr::new)
+        .collect::<Vec<_>>();
+    args.extend(["-signer".as_ref(), certificate.as_os_str(), "-inkey".as_ref(), p12]);
+    if !chain_pem.is_empty() {
+        std::fs::write(&chain, chain_pem).map_err(|e| e.to_string())?;
+        args.extend(["-certfile".as_ref(), chain.as_os_str()]);
+    }
+    openssl(&args, signer, data)
+}
+
+/// Run openssl with `args` and the password of `signer`, feeding it `input`, and return what it
+/// writes.
+#[cfg(feature = "crypto")]
+fn openssl(args: &[&std::ffi::OsStr], signer: &Signer, input: &[u8]) -> Result<Vec<u8>, String> {
+    use std::{
+        io::Write,
+        process::{Command, Stdio},
+    };
+
+    // The password goes through the environment, where other users cannot see it
+    let mut child = Command::new("openssl")
+        .args(args)
+        .args(["-passin", "env:MERGE_PDF_SIGN_PASSWORD"])
+        .env("MERGE_PDF_SIGN_PASSWORD", &signer.password)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| format!("openssl could not be run, is it installed? {}", e))?;
+    let mut stdin = child.stdin.take().expect("stdin is piped");
+    // Written on the side, openssl may start answering before it has read everything
+    let output = std::thread::scope(|scope| {
+        scope.spawn(move || stdin.write_all(input));
+        child.wait_with_output()
+    })
+    .map_err(|e| e.to_string())?;
+    if !output.status.success() {
+        let error = String::from_utf8_lossy(&output.stderr);
+        return Err(format!("openssl failed on {}: {}", signer.certificate.display(), error.lines().next().unwrap_or_default()));
+    }
+    Ok(output.stdout)
+}
+
+#[cfg(feature = "crypto")]
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack.windows(needle.len()).position(|window| window == needle)
+}
@@ -0,0 +1,124 @@
+use std::collections::HashSet;
+
+use lopdf::{Document, Object, ObjectId};
+
+use crate::{input::InputSpec, messages::tr};
+
+/// How deep fields inside fields are followed looking for signatures.
+const MAX_DEPTH: usize = 32;
+
+/// What happens to digitally signed inputs. Their signatures cover the bytes of the file as it
+/// was signed, so none of them holds in the output.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+pub enum Signatures {
+    /// Merge them, warning that their signatures will not be valid
+    #[default]
+    Warn,
+    /// Remove the signed signature fields, and the signatures with them
+    Strip,
+    /// Stop with an error
+    Fail,
+}
+
+/// Warn about, strip or refuse the signatures of `doc` as `mode` says: the signature fields
+/// with a value (PDF 32000-1, 12.7.4.5) and the usage rights signature. Fields waiting to be
+/// signed are kept.
+pub fn handle_signatures(spec: &InputSpec, doc: &mut Document, mode: Signatures) -> crate::Result<()> {
+    let signed = signed_fields(doc);
+    let usage_rights = doc.catalog().and_then(|catalog| catalog.get_deref(b"Perms", doc)).and_then(Object::as_dict).is_ok_and(|perms| perms.has(b"UR3"));
+    let count = signed.len() + usage_rights as usize;
+    if count == 0 {
+        return Ok(());
+    }
+    match mode {
+        Signatures::Warn => {
+            log::warn!(path:% = spec.path.display(); "{}", tr("{} digital signature(s) will not be valid in the output", &[&count]));
+        }
+        Signatures::Fail => {
+            return Err(crate::Error::Invalid(tr("{} is digitally signed, merging it would invalidate {} signature(s)", &[&spec.path.display(), &count])));
+        }
+        Signatures::Strip => {
+            strip(doc, &signed)?;
+            log::info!("{}", tr("{}: removed {} digital signature(s)", &[&spec.path.display(), &count]));
+        }
+    }
+    Ok(())
+}
+
+/// The signed signature fields of `doc`, each with the field it is a kid of, if any.
+fn signed_fields(doc: &Document) -> Vec<(ObjectId, Option<ObjectId>)> {
+    let mut signed = Vec::new();
+    let fields = doc
+        .catalog()
+        .and_then(|catalog| catalog.get_deref(b"AcroForm", doc))
+        .and_then(Object::as_dict)
+        .and_then(|form| form.get_deref(b"Fields", doc))
+        .and_then(Object::as_array);
+    if let Ok(fields) = fields {
+        collect(doc, fields, None, false, 0, &mut signed);
+    }
+    signed
+}
+
+fn collect(doc: &Document, fields: &[Object], parent: Option<ObjectId>, inherited_sig: bool, depth: usize, signed: &mut Vec<(ObjectId, Option<ObjectId>)>) {
+    for field in fields {
+        let Ok(id) = field.as_reference() else { continue };
+        let Ok(dict) = doc.get_dictionary(id) else { continue };
+        // The field type is inherited, widgets merged with their field have it too
+        let sig = match dict.get(b"FT").and_then(Object::as_name) {
+            Ok(kind) => kind == b"Sig",
+            Err(_) => inherited_sig,
+        };
+        if sig && dict.has(b"V") {
+            signed.push((id, parent));
+            continue;
+        }
+        if let Ok(kids) = dict.get_deref(b"Kids", doc).and_then(Object::as_array) {
+            if depth < MAX_DEPTH {
+                collect(doc, kids, Some(id), sig, depth + 1, signed);
+            }
+        }
+    }
+}
+
+/// Remove the `signed` fields, their widgets on the pages and the signature permissions of the
+/// catalog from `doc`.
+fn strip(doc: &mut Document, signed: &[(ObjectId, Option<ObjectId>)]) -> crate::Result<()> {
+    let mut removed = signed.iter().map(|(id, _)| *id).collect::<HashSet<_>>();
+    for (id, _) in signed {
+        if let Ok(kids) = doc.get_dictionary(*id).and_then(|field| field.get_deref(b"Kids", doc)).and_then(Object::as_array) {
+            removed.extend(kids.iter().filter_map(|kid| kid.as_reference().ok()));
+        }
+    }
+    let kept = |items: &Vec<Object>| items.iter().filter(|item| !item.as_reference().is_ok_and(|id| removed.contains(&id))).cloned().collect::<Vec<_>>();
+
+    for (_, parent) in signed {
+        let Some(parent) = parent else { continue };
+        let Ok(kids) = doc.get_dictionary(*parent).and_then(|field| field.get_deref(b"Kids", doc)).and_then(Object::as_array).map(kept) else { continue };
+        doc.get_dictionary_mut(*parent)?.set("Kids", kids);
+    }
+    for page_id in doc.get_pages().into_values() {
+        let Ok(annotations) = doc.get_dictionary(page_id)?.get_deref(b"Annots", doc).and_then(Object::as_array).map(kept) else { continue };
+        doc.get_dictionary_mut(page_id)?.set("Annots", annotations);
+    }
+
+    let catalog = doc.catalog()?;
+    let form_id = catalog.get(b"AcroForm").and_then(Object::as_reference).ok();
+    let fields = catalog.get_deref(b"AcroForm", doc).and_then(Object::as_dict).and_then(|form| form.get_deref(b"Fields", doc)).and_then(Object::as_array).map(kept);
+    let form = match form_id {
+        Some(id) => doc.get_dictionary_mut(id).ok(),
+        None => doc.catalog_mut()?.get_mut(b"AcroForm").and_then(Object::as_dict_mut).ok(),
+    };
+    if let Some(form) = form {
+        if let Ok(fields) = fields {
+            form.set("Fields", fields);
+        }
+        // Says the file has signatures and may only be appended to
+        form.remove(b"SigFlags");
+    }
+    doc.catalog_mut()?.remove(b"Perms");
+    // The signatures themselves, certificates included
+    doc.prune_objects();
+    Ok(())
+}
@@ -0,0 +1,141 @@
+use std::{
+    cmp::Ordering,
+    fs,
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+use clap::ValueEnum;
+
+use crate::input;
+
+/// How the files matched by a pattern or found in a directory are ordered.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum SortOrder {
+    /// Lexicographic by path
+    #[default]
+    Name,
+    /// By path, comparing runs of digits as numbers: page2.pdf before page10.pdf
+    Natural,
+    /// By modification time, oldest first
+    Mtime,
+    /// By file size, smallest first
+    Size,
+    /// Keep the order the filesystem lists them in
+    None,
+}
+
+impl SortOrder {
+    /// Sort `paths` (relative to `predir` unless absolute) in place, descending if `reverse`.
+    ///
+    /// Files whose metadata cannot be read sort first for `mtime` and `size`; ties keep name order.
+    pub fn sort(self, paths: &mut [PathBuf], predir: &Path, reverse: bool) {
+        let metadata = |path: &PathBuf| fs::metadata(input::resolve(predir, path)).ok();
+        match self {
+            SortOrder::Name => paths.sort(),
+            SortOrder::Natural => paths.sort_by(|a, b| natural_cmp(&a.to_string_lossy(), &b.to_string_lossy())),
+            SortOrder::Mtime => {
+                paths.sort();
+                paths.sort_by_cached_key(|p| metadata(p).and_then(|m| m.modified().ok()).unwrap_or(SystemTime::UNIX_EPOCH));
+            }
+            SortOrder::Size => {
+                paths.sort();
+                paths.sort_by_cached_key(|p| metadata(p).map(|m| m.len()).unwrap_or(0));
+            }
+            SortOrder::None => {}
+        }
+        if reverse {
+            paths.reverse();
+        }
+    }
+}
+
+/// Compare two strings treating each run of ASCII digits as one number.
+///
+/// Numbers with equal value but different zero padding (`01` and `1`) are ordered by their
+/// length so the comparison stays total, and everything else compares case-insensitively
+/// before falling back to a plain comparison.
+pub fn natural_cmp(a: &str, b: &str) -> Ordering {
+    let (mut a_rest, mut b_rest) = (a, b);
+    loop {
+        let (Some(ca), Some(cb)) = (a_rest.chars().next(), b_rest.chars().next()) else {
+            return a_rest.len().cmp(&b_rest.len()).then_with(|| a.cmp(b));
+        };
+        if ca.is_ascii_digit() && cb.is_ascii_digit() {
+            let a_len = a_rest.find(|c: char| !c.is_ascii_digit()).unwrap_or(a_rest.len());
+            let b_len = b_rest.find(|c: char| !c.is_ascii_digit()).unwrap_or(b_rest.len());
+            let (a_num, b_num) = (a_rest[..a_len].trim_start_matches('0'), b_rest[..b_len].trim_start_matches('0'));
+            let ordering = a_num
+                .len()
+                .cmp(&b_num.len())
+                .then_with(|| a_num.cmp(b_num))
+                .then_with(|| a_len.cmp(&b_len));
+            if ordering != Ordering::Equal {
+                return ordering;
+            }
+            a_rest = &a_rest[a_len..];
+            b_rest = &b_rest[b_len..];
+        } else {
+            let ordering = ca.to_lowercase().cmp(cb.to_lowercase());
+            if ordering != Ordering::Equal {
+                return ordering;
+            }
+            a_rest = &a_rest[ca.len_utf8()..];
+            b_rest = &b_rest[cb.len_utf8()..];
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// `names` sorted naturally.
+    fn natural(names: &[&str]) -> Vec<String> {
+        let mut paths = names.iter().map(PathBuf::from).collect::<Vec<_>>();
+        SortOrder::Natural.sort(&mut paths, Path::new("."), false);
+        paths.iter().map(|path| path.to_string_lossy().into_owned()).collect()
+    }
+
+    #[test]
+    fn digit_runs_compare_as_numbers() {
+        assert_eq!(natural(&["page10.pdf", "page2.pdf", "page1.pdf", "page100.pdf"]), ["page1.pdf", "page2.pdf", "page10.pdf", "page100.pdf"]);
+        // Longer than any integer type holds
+        assert_eq!(natural(&["n100000000000000000000001", "n99999999999999999999999"]), ["n99999999999999999999999", "n100000000000000000000001"]);
+        // Every run on its own; a digit against a letter compares as a character
+        assert_eq!(natural(&["v1.10.pdf", "v1.9.pdf", "v1.9.1.pdf", "v2.pdf"]), ["v1.9.1.pdf", "v1.9.pdf", "v1.10.pdf", "v2.pdf"]);
+    }
+
+    #[test]
+    fn leading_zeros_only_break_ties() {
+        assert_eq!(natural(&["page010.pdf", "page9.pdf", "page01.pdf", "page1.pdf", "page001.pdf"]), ["page1.pdf", "page01.pdf", "page001.pdf", "page9.pdf", "page010.pdf"]);
+        assert_eq!(natural_cmp("0", "00"), Ordering::Less);
+        assert_eq!(natural_cmp("a0b", "ab"), Ordering::Less);
+    }
+
+    #[test]
+    fn case_only_breaks_ties() {
+        assert_eq!(natural(&["b.pdf", "C.pdf", "a.pdf", "B.pdf"]), ["a.pdf", "B.pdf", "b.pdf", "C.pdf"]);
+        assert_eq!(natural_cmp("Scan2", "scan10"), Ordering::Less);
+        // Plain name order puts every capital first
+        let mut paths = vec![PathBuf::from("b.pdf"), PathBuf::from("C.pdf"), PathBuf::from("a.pdf")];
+        SortOrder::Name.sort(&mut paths, Path::new("."), false);
+        assert_eq!(paths, [PathBuf::from("C.pdf"), PathBuf::from("a.pdf"), PathBuf::from("b.pdf")]);
+    }
+
+    #[test]
+    fn non_ascii_names_compare_by_lowercase_character() {
+        assert_eq!(natural(&["Éclair.pdf", "zebra.pdf", "éclair.pdf", "Apfel.pdf"]), ["Apfel.pdf", "zebra.pdf", "Éclair.pdf", "éclair.pdf"]);
+        assert_eq!(natural(&["Übung10.pdf", "übung9.pdf"]), ["übung9.pdf", "Übung10.pdf"]);
+        // Only ASCII digits are numbers
+        assert_eq!(natural_cmp("٣", "1"), Ordering::Greater);
+        assert_eq!(natural(&["日本2.pdf", "日本10.pdf"]), ["日本2.pdf", "日本10.pdf"]);
+    }
+
+    #[test]
+    fn reverse_sorts_descending() {
+        let mut paths = vec![PathBuf::from("page2.pdf"), PathBuf::from("page10.pdf"), PathBuf::from("page1.pdf")];
+        SortOrder::Natural.sort(&mut paths, Path::new("."), true);
+        assert_eq!(paths, [PathBuf::from("page10.pdf"), PathBuf::from("page2.pdf"), PathBuf::from("page1.pdf")]);
+    }
+}
@@ -0,0 +1,498 @@
+use std::str::FromStr;
+
+use lopdf::{dictionary, Dictionary, Document, Object, ObjectId, Stream};
+
+use crate::{input::PageRanges, messages::tr, nup, outline::BookmarkColor, pages, toc::literal};
+
+/// Where on the page a stamp goes, as the page is displayed.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+pub enum Position {
+    TopLeft,
+    TopCenter,
+    TopRight,
+    Center,
+    BottomLeft,
+    #[default]
+    BottomCenter,
+    BottomRight,
+}
+
+impl Position {
+    /// Bottom left corner of an item `item_width` by `item_height` placed on a page `width` by
+    /// `height` at this position, `margin` in from the edges it is near.
+    fn place(self, margin: f32, (width, height): (f32, f32), (item_width, item_height): (f32, f32)) -> (f32, f32) {
+        let x = match self {
+            Position::TopLeft | Position::BottomLeft => margin,
+            Position::TopCenter | Position::Center | Position::BottomCenter => (width - item_width) / 2.0,
+            Position::TopRight | Position::BottomRight => width - margin - item_width,
+        };
+        let y = match self {
+            Position::TopLeft | Position::TopCenter | Position::TopRight => height - margin - item_height,
+            Position::Center => (height - item_height) / 2.0,
+            Position::BottomLeft | Position::BottomCenter | Position::BottomRight => margin,
+        };
+        (x, y)
+    }
+}
+
+/// Page numbers written on every page of the merged document, see `stamp_page_numbers`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PageNumbers {
+    /// The text, with `{page}` and `{total}` filled in, e.g. `"{page} / {total}"`.
+    pub template: String,
+    pub position: Position,
+    pub font_size: f32,
+    /// Distance in points from the edges of the page.
+    pub margin: f32,
+}
+
+impl PageNumbers {
+    /// `template` centered at the bottom, 10 points high and 24 points from the edge.
+    pub fn new(template: impl Into<String>) -> Self {
+        PageNumbers { template: template.into(), position: Position::BottomCenter, font_size: 10.0, margin: 24.0 }
+    }
+}
+
+/// Write `numbers.template` on every page of `doc`, `{page}` counting the pages from 1 in order.
+pub fn stamp_page_numbers(doc: &mut Document, numbers: &PageNumbers, compress: bool) -> crate::Result<()> {
+    let total = doc.get_pages().len().to_string();
+    let text = |index: usize| numbers.template.replace("{page}", &(index + 1).to_string()).replace("{total}", &total);
+    stamp(doc, numbers.position, numbers.font_size, numbers.margin, compress, text)
+}
+
+/// A header and a footer written on every page of the merged document, see `header_footer`.
+///
+/// The templates fill in `{page}` and `{total}` like `PageNumbers`, `{date}` and `{file}`, the
+/// name of the input the page came from.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct HeaderFooter {
+    /// Centered at the top.
+    pub header: Option<String>,
+    /// Centered at the bottom.
+    pub footer: Option<String>,
+    /// What `{date}` stands for.
+    pub date: String,
+    pub font_size: f32,
+    /// Distance in points from the edges of the page.
+    pub margin: f32,
+}
+
+/// Write the header and footer of `header_footer` on every page of `doc`, `{file}` filled in from
+/// `sources`, the input file names in page order; pages past its end get an empty one.
+pub fn header_footer(doc: &mut Document, header_footer: &HeaderFooter, sources: &[String], compress: bool) -> crate::Result<()> {
+    let total = doc.get_pages().len().to_string();
+    let templates = [(&header_footer.header, Position::TopCenter), (&header_footer.footer, Position::BottomCenter)];
+    for (template, position) in templates {
+        let Some(template) = template else { continue };
+        let text = |index: usize| {
+            template
+                .replace("{page}", &(index + 1).to_string())
+                .replace("{total}", &total)
+                .replace("{date}", &header_footer.date)
+                .replace("{file}", sources.get(index).map(String::as_str).unwrap_or(""))
+        };
+        stamp(doc, position, header_footer.font_size, header_footer.margin, compress, text)?;
+    }
+    Ok(())
+}
+
+/// Bates numbers written on every page of the merged document, see `stamp_bates`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Bates {
+    pub format: BatesFormat,
+    /// The number of the first page.
+    pub start: u64,
+    pub position: Position,
+    pub font_size: f32,
+    /// Distance in points from the edges of the page.
+    pub margin: f32,
+}
+
+impl Bates {
+    /// Numbers from `start` in the bottom right corner, 10 points high and 24 points from the edge.
+    pub fn new(format: BatesFormat, start: u64) -> Self {
+        Bates { format, start, position: Position::BottomRight, font_size: 10.0, margin: 24.0 }
+    }
+}
+
+/// A Bates identifier written printf-style with one number in it, like `ACME-%06d`: `%d`, `%Nd`
+/// padded with spaces or `%0Nd` padded with zeros to N digits; `%%` is a literal `%`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BatesFormat {
+    prefix: String,
+    width: usize,
+    zeros: bool,
+    suffix: String,
+}
+
+impl BatesFormat {
+    /// The identifier of page `number`.
+    pub fn format(&self, number: u64) -> String {
+        let number = if self.zeros {
+            format!("{:0width$}", number, width = self.width)
+        } else {
+            format!("{:width$}", number, width = self.width)
+        };
+        format!("{}{}{}", self.prefix, number, self.suffix)
+    }
+}
+
+impl FromStr for BatesFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("{:?} is not a Bates format, it needs exactly one number like PREFIX-%06d", s);
+        let mut parts = Vec::new();
+        let mut literal = String::new();
+        let mut chars = s.chars().peekable();
+        while let Some(c) = chars.next() {
+            if c != '%' {
+                literal.push(c);
+                continue;
+            }
+            if chars.peek() == Some(&'%') {
+                chars.next();
+                literal.push('%');
+                continue;
+            }
+            let mut digits = String::new();
+            while let Some(d) = chars.next_if(char::is_ascii_digit) {
+                digits.push(d);
+            }
+            if chars.next() != Some('d') {
+                return Err(invalid());
+            }
+            parts.push((std::mem::take(&mut literal), digits));
+        }
+        match parts.as_slice() {
+            [(prefix, digits)] => Ok(BatesFormat {
+                prefix: prefix.clone(),
+                width: if digits.is_empty() { 0 } else { digits.parse().map_err(|_| invalid())? },
+                zeros: digits.starts_with('0'),
+                suffix: literal,
+            }),
+            _ => Err(invalid()),
+        }
+    }
+}
+
+/// Write a Bates identifier on every page of `doc`, counting up from `bates.start` in page order.
+pub fn stamp_bates(doc: &mut Document, bates: &Bates, compress: bool) -> crate::Result<()> {
+    let text = |index: usize| bates.format.format(bates.start + index as u64);
+    stamp(doc, bates.position, bates.font_size, bates.margin, compress, text)
+}
+
+/// Write `text(index)` in Helvetica on every page of `doc`, `index` counting the pages from 0 in
+/// order, at `position` and `margin` points in from the edges of the visible box as displayed.
+/// The stamp goes on top of the page's content.
+pub fn stamp(
+    doc: &mut Document,
+    position: Position,
+    font_size: f32,
+    margin: f32,
+    compress: bool,
+    text: impl Fn(usize) -> String,
+) -> crate::Result<()> {
+    let font_id = helvetica(doc);
+    let save_id = save_state(doc);
+    for (index, page_id) in doc.get_pages().into_values().enumerate() {
+        let text = text(index);
+        let (matrix, width, height) = displayed_frame(doc, page_id)?;
+        let (x, y) = position.place(margin, (width, height), (text_width(&text, font_size), font_size));
+        let content = format!(
+            "q {} cm BT /MergePdfStamp {} Tf {} {} Td {} Tj ET Q\n",
+            matrix_operands(matrix),
+            font_size,
+            x,
+            y,
+            literal(&text)
+        );
+        add_layer(doc, page_id, content, &[(b"Font", b"MergePdfStamp", font_id)], Some(save_id), compress)?;
+    }
+    Ok(())
+}
+
+/// A text watermark across the middle of every page, see `watermark`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Watermark {
+    pub text: String,
+    /// From 0, invisible, to 1, opaque.
+    pub opacity: f32,
+    /// Counter-clockwise degrees, 45 to run diagonally up from the bottom left.
+    pub rotation: f32,
+    pub color: BookmarkColor,
+    pub font_size: f32,
+    /// Draw it beneath the page's content instead of over it.
+    pub under: bool,
+}
+
+impl Watermark {
+    /// `text` in 60 point gray at 30% opacity, diagonally over the page.
+    pub fn new(text: impl Into<String>) -> Self {
+        Watermark {
+            text: text.into(),
+            opacity: 0.3,
+            rotation: 45.0,
+            color: BookmarkColor([0.5, 0.5, 0.5]),
+            font_size: 60.0,
+            under: false,
+        }
+    }
+}
+
+/// Draw `watermark.text` centered on every page of `doc` as displayed, made see-through with an
+/// ExtGState.
+pub fn watermark(doc: &mut Document, watermark: &Watermark, compress: bool) -> crate::Result<()> {
+    let font_id = helvetica(doc);
+    let opacity = watermark.opacity.clamp(0.0, 1.0);
+    let state_id = doc.add_object(dictionary! { "Type" => "ExtGState", "ca" => opacity, "CA" => opacity });
+    let save_id = (!watermark.under).then(|| save_state(doc));
+    let (sin, cos) = watermark.rotation.to_radians().sin_cos();
+    let [r, g, b] = watermark.color.0;
+    let text_width = text_width(&watermark.text, watermark.font_size);
+    for page_id in doc.get_pages().into_values() {
+        let (matrix, width, height) = displayed_frame(doc, page_id)?;
+        // Turned about the center of the page, the text centered on it
+        let content = format!(
+            "q {} cm 1 0 0 1 {} {} cm {} {} {} {} 0 0 cm /MergePdfWatermark gs {} {} {} rg BT /MergePdfStamp {} Tf {} {} Td {} Tj ET Q\n",
+            matrix_operands(matrix),
+            width / 2.0,
+            height / 2.0,
+            cos,
+            sin,
+            -sin,
+            cos,
+            r,
+            g,
+            b,
+            watermark.font_size,
+            -text_width / 2.0,
+            -watermark.font_size * 0.35,
+            literal(&watermark.text)
+        );
+        let resources: [(&[u8], &[u8], ObjectId); 2] =
+            [(b"Font", b"MergePdfStamp", font_id), (b"ExtGState", b"MergePdfWatermark", state_id)];
+        add_layer(doc, page_id, content, &resources, save_id, compress)?;
+    }
+    Ok(())
+}
+
+/// A page of a PDF, or an image, put on pages of the merged document, see `overlay`.
+#[derive(Debug, Clone)]
+pub struct Overlay {
+    /// Its first page is what gets put on the pages.
+    pub doc: Box<Document>,
+    /// Pages of the merged document (1-based) that get it, all of them if unset.
+    pub pages: Option<PageRanges>,
+    pub position: Position,
+    /// Factor on the size of the overlay's page.
+    pub scale: f32,
+    /// Scale it to fill each page as far as it keeps its proportions, instead of by `scale`.
+    pub fit: bool,
+    /// Distance in points from the edges of the page.
+    pub margin: f32,
+    /// Draw it beneath the page's content instead of over it.
+    pub under: bool,
+}
+
+impl Overlay {
+    /// The first page of `doc` at its own size, centered over every page.
+    pub fn new(doc: Document) -> Self {
+        Overlay { doc: Box::new(doc), pages: None, position: Position::Center, scale: 1.0, fit: false, margin: 24.0, under: false }
+    }
+
+    /// The first page of `doc` fitted to every page, beneath its content (`under`) or over it,
+    /// like letterhead.
+    pub fn page(doc: Document, under: bool) -> Self {
+        Overlay { fit: true, margin: 0.0, under, ..Overlay::new(doc) }
+    }
+}
+
+/// Draw the first page of `overlay.doc` over or under the selected pages of `doc`, turned into a
+/// Form XObject once and placed on each page as displayed. Its annotations, links included, are
+/// left out.
+pub fn overlay(doc: &mut Document, overlay: &Overlay, compress: bool) -> crate::Result<()> {
+    let (form_id, [left, bottom, right, top], rotation) = import_page(doc, &overlay.doc, compress)?;
+    // Every overlay needs a name of its own, pages can get several
+    let name = format!("MergePdfOverlay{}", form_id.0);
+    let (w, h) = (right - left, top - bottom);
+    let (shown_w, shown_h) = if rotation % 180 == 90 { (h, w) } else { (w, h) };
+    // The overlay's own /Rotate, clockwise, as a matrix from its box moved to the origin
+    let turn = match rotation {
+        90 => format!(" 0 -1 1 0 0 {} cm", w),
+        180 => format!(" -1 0 0 -1 {} {} cm", w, h),
+        270 => format!(" 0 1 -1 0 {} 0 cm", h),
+        _ => String::new(),
+    };
+    let shift = if left != 0.0 || bottom != 0.0 {
+        format!(" 1 0 0 1 {} {} cm", 0.0 - left, 0.0 - bottom)
+    } else {
+        String::new()
+    };
+    let save_id = (!overlay.under).then(|| save_state(doc));
+    for (number, page_id) in doc.get_pages() {
+        if !overlay.pages.as_ref().is_none_or(|pages| pages.contains(number)) {
+            continue;
+        }
+        let (matrix, width, height) = displayed_frame(doc, page_id)?;
+        let scale = if overlay.fit {
+            ((width - 2.0 * overlay.margin) / shown_w).min((height - 2.0 * overlay.margin) / shown_h)
+        } else {
+            overlay.scale
+        };
+        let (x, y) = overlay.position.place(overlay.margin, (width, height), (shown_w * scale, shown_h * scale));
+        let content = format!(
+            "q {} cm {} 0 0 {} {} {} cm{}{} /{} Do Q\n",
+            matrix_operands(matrix),
+            scale,
+            scale,
+            x,
+            y,
+            turn,
+            shift,
+            name
+        );
+        add_layer(doc, page_id, content, &[(b"XObject", name.as_bytes(), form_id)], save_id, compress)?;
+    }
+    Ok(())
+}
+
+/// Copy the objects of `from` into `doc` and turn its first page into a Form XObject there;
+/// returns the form with the page's box and rotation.
+fn import_page(doc: &mut Document, from: &Document, compress: bool) -> crate::Result<(ObjectId, [f32; 4], i64)> {
+    let mut from = from.clone();
+    from.renumber_objects_with(doc.max_id + 1);
+    let Some(&page_id) = from.get_pages().values().next() else {
+        return Err(crate::Error::Invalid(tr("the overlay has no pages", &[])));
+    };
+    let page = pages::with_inherited_attributes(&from, page_id)?;
+    from.objects.insert(page_id, page);
+    let (form, page_box, rotation) = nup::form_xobject(&from, page_id, compress)?;
+    for (id, object) in from.objects {
+        match object.type_name().unwrap_or("") {
+            "Page" | "Pages" | "Catalog" | "Outlines" | "Outline" => {}
+            _ => {
+                doc.objects.insert(id, object);
+            }
+        }
+    }
+    doc.max_id = doc.max_id.max(from.max_id);
+    Ok((doc.add_object(form), page_box, rotation))
+}
+
+/// A Helvetica font object for stamps, named `MergePdfStamp` in the pages' resources.
+fn helvetica(doc: &mut Document) -> ObjectId {
+    doc.add_object(dictionary! {
+        "Type" => "Font",
+        "Subtype" => "Type1",
+        "BaseFont" => "Helvetica",
+        "Encoding" => "WinAnsiEncoding",
+    })
+}
+
+/// A content stream saving the graphics state, put before the page's content when a layer goes
+/// over it, so whatever state the content leaves behind cannot move the layer.
+pub(crate) fn save_state(doc: &mut Document) -> ObjectId {
+    doc.add_object(Stream::new(Dictionary::new(), b"q\n".to_vec()))
+}
+
+/// The matrix from the page as displayed, origin at the bottom left of its visible box after
+/// `/Rotate`, to the page's own coordinates, and the displayed width and height.
+fn displayed_frame(doc: &Document, page_id: ObjectId) -> crate::Result<([f32; 6], f32, f32)> {
+    let page = doc.get_dictionary(page_id)?;
+    let [x0, y0, x1, y1] = visible_box(doc, page);
+    let rotation = page.get(b"Rotate").and_then(Object::as_i64).unwrap_or(0).rem_euclid(360);
+    Ok(match rotation {
+        90 => ([0.0, 1.0, -1.0, 0.0, x1, y0], y1 - y0, x1 - x0),
+        180 => ([-1.0, 0.0, 0.0, -1.0, x1, y1], x1 - x0, y1 - y0),
+        270 => ([0.0, -1.0, 1.0, 0.0, x0, y1], y1 - y0, x1 - x0),
+        _ => ([1.0, 0.0, 0.0, 1.0, x0, y0], x1 - x0, y1 - y0),
+    })
+}
+
+fn matrix_operands(matrix: [f32; 6]) -> String {
+    matrix.iter().map(f32::to_string).collect::<Vec<_>>().join(" ")
+}
+
+/// Add `content` to a page, over its content when `save` (a stream from `save_state`) is given and
+/// beneath it otherwise, along with `resources` given as category, name and object.
+pub(crate) fn add_layer(
+    doc: &mut Document,
+    page_id: ObjectId,
+    content: String,
+    resources: &[(&[u8], &[u8], ObjectId)],
+    save: Option<ObjectId>,
+    compress: bool,
+) -> crate::Result<()> {
+    let content = match save {
+        Some(_) => format!("\nQ\n{}", content),
+        None => format!("{}\n", content),
+    };
+    let mut stream = Stream::new(Dictionary::new(), content.into_bytes());
+    if compress {
+        let _ = stream.compress();
+    }
+    let stream_id = doc.add_object(stream);
+    let resources = with_resources(doc, page_id, resources);
+    let page = doc.get_dictionary_mut(page_id)?;
+    let mut contents = match page.get(b"Contents") {
+        Ok(Object::Array(items)) => items.clone(),
+        Ok(contents) => vec![contents.clone()],
+        Err(_) => Vec::new(),
+    };
+    match save {
+        Some(save_id) => {
+            contents.insert(0, save_id.into());
+            contents.push(stream_id.into());
+        }
+        None => contents.insert(0, stream_id.into()),
+    }
+    page.set("Contents", contents);
+    page.set("Resources", resources);
+    Ok(())
+}
+
+/// The page's resources, resolved into a dictionary of its own, with `added` put in.
+/// Resources shared with other pages are copied rather than changed.
+fn with_resources(doc: &Document, page_id: ObjectId, added: &[(&[u8], &[u8], ObjectId)]) -> Dictionary {
+    let page = doc.get_dictionary(page_id).ok();
+    let mut resources = page
+        .and_then(|page| page.get_deref(b"Resources", doc).and_then(Object::as_dict).ok())
+        .cloned()
+        .unwrap_or_default();
+    for &(category, name, id) in added {
+        let mut entries = resources.get_deref(category, doc).and_then(Object::as_dict).cloned().unwrap_or_default();
+        entries.set(name, id);
+        resources.set(category, entries);
+    }
+    resources
+}
+
+/// The CropBox of a page, else its MediaBox, else US Letter.
+fn visible_box(doc: &Document, page: &Dictionary) -> [f32; 4] {
+    [b"CropBox".as_slice(), b"MediaBox"]
+        .into_iter()
+        .filter_map(|key| page.get_deref(key, doc).and_then(Object::as_array).ok())
+        .find_map(|values| values.iter().map(|v| v.as_float().ok()).collect::<Option<Vec<_>>>())
+        .and_then(|values| <[f32; 4]>::try_from(values).ok())
+        .map(|[x0, y0, x1, y1]| [x0.min(x1), y0.min(y1), x0.max(x1), y0.max(y1)])
+        .unwrap_or([0.0, 0.0, 612.0, 792.0])
+}
+
+/// Roughly how wide `text` is in Helvetica at `size`, close enough to center or right-align it.
+fn text_width(text: &str, size: f32) -> f32 {
+    text.chars().map(helvetica_em).sum::<f32>() * size
+}
+
+/// Roughly how wide `c` is in Helvetica, in ems.
+pub(crate) fn helvetica_em(c: char) -> f32 {
+    match c {
+        ' ' | '/' | '.' | ',' | ':' | ';' | 'i' | 'j' | 'l' | 't' | 'f' | 'I' | '!' => 0.278,
+        '-' | '(' | ')' | 'r' => 0.333,
+        'm' | 'M' | 'W' => 0.833,
+        'w' => 0.722,
+        c if c.is_ascii_uppercase() => 0.667,
+        _ => 0.556,
+    }
+}
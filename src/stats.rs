@@ -0,0 +1,89 @@
+use std::time::{Duration, Instant};
+
+use lopdf::Document;
+
+use crate::{messages::tr, Progress};
+
+/// Where the time of a merge went, phase by phase, and what it made, for `--stats`.
+#[derive(Debug)]
+pub struct Stats {
+    started: Instant,
+    /// The phase under way and when it started
+    current: Option<(&'static str, Instant)>,
+    /// Phases done, in order; a phase entered twice adds up
+    phases: Vec<(&'static str, Duration)>,
+    inputs: usize,
+    pages: usize,
+    objects: usize,
+    bytes: u64,
+}
+
+impl Stats {
+    pub fn new() -> Self {
+        Stats { started: Instant::now(), current: None, phases: Vec::new(), inputs: 0, pages: 0, objects: 0, bytes: 0 }
+    }
+
+    /// End the phase under way, if any, and start `phase`.
+    pub fn start(&mut self, phase: &'static str) {
+        self.end();
+        self.current = Some((phase, Instant::now()));
+    }
+
+    /// End the phase under way.
+    pub fn end(&mut self) {
+        if let Some((phase, since)) = self.current.take() {
+            match self.phases.iter_mut().find(|(name, _)| *name == phase) {
+                Some((_, time)) => *time += since.elapsed(),
+                None => self.phases.push((phase, since.elapsed())),
+            }
+        }
+    }
+
+    /// Move on to the phase a merge reports it is in, see `merge_inputs_with_progress`.
+    pub fn progress(&mut self, progress: &Progress) {
+        match progress {
+            Progress::Loaded { total, .. } => {
+                self.inputs = *total;
+                self.start("load");
+            }
+            Progress::Copied { total, .. } => {
+                self.inputs = *total;
+                self.start("collect");
+            }
+            Progress::Renumbering => self.start("renumber"),
+            Progress::Transforming => self.start("transform"),
+            Progress::Outline => self.start("outline"),
+            Progress::Finishing => self.start("finish"),
+            Progress::Saving { .. } => self.start("save"),
+            Progress::Saved { bytes } => {
+                self.end();
+                self.bytes = *bytes;
+            }
+        }
+    }
+
+    /// Count what `doc`, the merged document, holds.
+    pub fn count(&mut self, doc: &Document) {
+        self.pages = doc.get_pages().len();
+        self.objects = doc.objects.len();
+    }
+
+    /// Print the time of every phase and the counts.
+    pub fn report(&self) {
+        let phases = self.phases.iter().map(|(phase, time)| (tr(phase, &[]), *time)).collect::<Vec<_>>();
+        let total = tr("total", &[]);
+        let width = phases.iter().map(|(phase, _)| phase).chain([&total]).map(|name| name.chars().count()).max().unwrap_or(0).max(10);
+        println!("{}", tr("Stats:", &[]));
+        for (phase, time) in &phases {
+            println!("  {:<width$} {:>9.3} s", phase, time.as_secs_f64());
+        }
+        println!("  {:<width$} {:>9.3} s", total, self.started.elapsed().as_secs_f64());
+        println!("  {}", tr("{} input(s), {} page(s), {} object(s), {} bytes written", &[&self.inputs, &self.pages, &self.objects, &self.bytes]));
+    }
+}
+
+impl Default for Stats {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,483 @@
+use std::{
+    collections::{HashMap, HashSet},
+    io::Write,
+};
+
+use lopdf::{Bookmark, Dictionary, Document, Object, ObjectId};
+
+use crate::{
+    compress::{self, CompressionStats},
+    input::InputSpec,
+    messages::tr,
+    outline::{self, BookmarkMode, TitleFields},
+    pages, toc,
+    writer::PdfWriter,
+    annotations, attachments, blank, bookmark_title, dedupe, destinations, encrypt, flatten, forms, grayscale, labels, layers, later_version, metadata, ocr, optimize, output_info, pdfa, redact, renumber, sanitize, shape_outline, signatures, structure, threads, verify, version_number, view, InputBookmarks, MergeOptions, Progress,
+};
+
+/// The page tree root and Catalog of the output get fixed ids, inputs are numbered after them.
+const PAGES_ID: ObjectId = (1, 0);
+const CATALOG_ID: ObjectId = (2, 0);
+const STRUCTURE_ID: ObjectId = (3, 0);
+
+/// Merge `inputs` like `merge_inputs`, but load them one at a time and write every input's
+/// objects to `out` as soon as it is processed, so only one input is ever held in memory.
+///
+/// `load` is called for every input in order; returning `Ok(None)` leaves the input out. Only the
+/// page tree, Catalog and outline wait for the end. PDF/A, N-up and booklet output and page
+/// stamps are not available this way, as they need the whole document. Returns the compression
+/// statistics per input.
+pub fn merge_streaming(
+    inputs: &[InputSpec],
+    options: &MergeOptions,
+    out: impl Write,
+    mut load: impl FnMut(&InputSpec) -> crate::Result<Option<Document>>,
+    mut progress: impl FnMut(Progress),
+) -> crate::Result<Vec<CompressionStats>> {
+    if matches!(options.pdfa, Some(pdfa::Mode::Convert | pdfa::Mode::Preserve)) {
+        return Err(crate::Error::Usage(tr("PDF/A output needs the whole document, it cannot be streamed", &[])));
+    }
+    if options.nup.is_some() || options.booklet {
+        return Err(crate::Error::Usage(tr("N-up and booklet output need the whole document, they cannot be streamed", &[])));
+    }
+    if options.linearize {
+        return Err(crate::Error::Usage(tr("linearized output needs the whole document, it cannot be streamed", &[])));
+    }
+    if options.merge_font_subsets {
+        return Err(crate::Error::Usage(tr("merging font subsets needs the whole document, it cannot be streamed", &[])));
+    }
+    let stamped = options.page_numbers.is_some()
+        || options.bates.is_some()
+        || options.header_footer.is_some()
+        || options.watermark.is_some()
+        || options.overlay.is_some()
+        || options.background.is_some()
+        || options.foreground.is_some();
+    if stamped {
+        return Err(crate::Error::Usage(tr("page stamps, watermarks and overlays are put on the whole document, they cannot be streamed", &[])));
+    }
+    let total = inputs.len();
+    // Written first, inputs of a later version raise it in the Catalog
+    let header = options.pdf_version.as_deref().unwrap_or("1.7");
+    let mut version = header.to_string();
+    let mut writer = PdfWriter::new(out, header)?;
+    if let Some(encryption) = &options.encryption {
+        writer.encrypt(encryption)?;
+    }
+    if options.deterministic {
+        writer.hash_content();
+    }
+    if options.object_streams {
+        if version_number(header) < Some((1, 5)) {
+            log::warn!("{}", tr("object streams need PDF 1.5, the output is PDF {} and is written without them", &[&header]));
+        } else {
+            writer.pack_objects();
+        }
+    }
+    let mut max_id = STRUCTURE_ID.0 + 1;
+    let mut pagenum = 1;
+    let mut position = 0;
+    let mut kids = Vec::new();
+    // What every page in `kids` is labeled, see `labels::number_tree`
+    let mut page_labels = Vec::new();
+    // Catalog and page tree root of the input chosen by `MergeOptions::root`, or of the first one
+    let mut root: Option<(Dictionary, Dictionary)> = None;
+    // Metadata of the input chosen by `MergeOptions::metadata_from`, else of the root one
+    let mut metadata_source: Option<metadata::Source> = None;
+    // Viewer settings of the input chosen by `MergeOptions::view_from`
+    let mut view_settings = None;
+    // Per input, empty unless compression was asked for
+    let mut stats = if options.compress { vec![CompressionStats::default(); total] } else { Vec::new() };
+    // Holds nothing but the bookmarks until the outline is built at the end
+    let mut res = Document::new();
+    let mut bookmarks = InputBookmarks::new(&mut res, options.bookmarks)?;
+    let mut toc_entries = Vec::new();
+    // Size of the table of contents, that of the first page written
+    let mut media_box = None;
+    // The last page written, which separators and blank pages are sized like
+    let mut previous_page: Option<Object> = None;
+    // Fingerprints of the pages written, for `MergeOptions::dedupe_pages`
+    let mut seen = HashSet::new();
+    // Streams written, and those left out as copies of them, for `MergeOptions::dedupe_resources`
+    let mut streams = HashMap::new();
+    let mut shared = dedupe::Shared::default();
+    // Embedded files of every input, the kept Catalog would only have those of its own
+    let mut attachments = attachments::Attachments::default();
+    let mut forms = forms::Forms::default();
+    let mut destinations = destinations::Destinations::default();
+    let mut structure = structure::Structure::default();
+    let mut layers = layers::Layers::default();
+    let mut threads = threads::Threads::default();
+    // PDF/A identification of every input, for `MergeOptions::pdfa` to check
+    let mut identifications = Vec::new();
+
+    for (source, spec) in inputs.iter().enumerate() {
+        let Some(mut doc) = load(spec)? else { continue };
+        progress(Progress::Loaded { index: source, total, path: spec.path.clone() });
+        let mut spec = spec.clone();
+        if options.drop_blank_pages {
+            blank::drop_blank_pages(&mut spec, &doc);
+        }
+        if options.dedupe_pages {
+            dedupe::drop_duplicate_pages(&mut spec, &doc, &mut seen);
+        }
+        let spec = &spec;
+        version = later_version(options, spec, &version, &doc).to_string();
+        if options.pdfa == Some(pdfa::Mode::Check) {
+            identifications.push(pdfa::check_input(spec, &doc));
+        }
+        signatures::handle_signatures(spec, &mut doc, options.signatures)?;
+        if options.sanitize {
+            sanitize::sanitize(spec, &mut doc)?;
+        }
+        if options.flatten_forms {
+            flatten::flatten_forms(spec, &mut doc)?;
+        }
+        flatten::handle_annotations(spec, &mut doc, options.annotations)?;
+        // Before redaction, so recognized text is blacked out too
+        if let Some(languages) = &options.ocr {
+            ocr::ocr(spec, &mut doc, languages, options.compress)?;
+        }
+        if !options.redact.is_empty() {
+            redact::redact_pages(spec, &mut doc, &options.redact)?;
+        }
+        if options.grayscale {
+            grayscale::grayscale(spec, &mut doc)?;
+        }
+        if let Some(optimization) = options.optimize_images {
+            optimize::optimize_images(spec, &mut doc, optimization)?;
+        }
+        if options.strip_thumbnails {
+            pages::strip_thumbnails(spec, &mut doc)?;
+        }
+        if options.strip_metadata {
+            metadata::strip(&mut doc);
+        }
+        if let Some(size) = options.page_size {
+            let selected = doc.get_pages().into_iter().filter(|(n, _)| spec.selects(*n)).map(|(_, id)| id).collect::<Vec<_>>();
+            pages::resize(&mut doc, &selected, size, spec.rotate)?;
+        }
+        renumber::renumber_objects_with(&mut doc, max_id);
+        max_id = doc.max_id + 1;
+        let input_attachments = attachments::Attachments::take(&mut doc);
+        let mut selected = doc.get_pages().into_iter().filter(|(number, _)| spec.selects(*number)).collect::<Vec<_>>();
+        if spec.reverse {
+            selected.reverse();
+        }
+        // The input's own outline goes below its bookmark, so read it before its destinations move
+        let source_outline = outline::entries_for(spec, &doc);
+        annotations::remap(&mut doc, &selected.iter().map(|(_, id)| *id).collect())?;
+
+        let chosen = match options.root {
+            Some(index) => index == source,
+            None => root.is_none() && !selected.is_empty(),
+        };
+        if chosen {
+            let catalog = doc.catalog()?.clone();
+            let pages_root = catalog.get_deref(b"Pages", &doc).and_then(Object::as_dict)?.clone();
+            root = Some((catalog, pages_root));
+        }
+        if selected.is_empty() {
+            log::warn!(path:% = spec.path.display(); "{}", tr("no (selected) pages, skipping", &[]));
+            continue;
+        }
+        attachments.append(spec, input_attachments);
+        destinations.append(spec, &mut doc);
+        structure.append(spec, &mut doc, STRUCTURE_ID);
+        layers.append(spec, &mut doc);
+        threads.append(spec, &mut doc, &selected.iter().map(|(_, id)| *id).collect());
+        if options.view_from == Some(source) {
+            view_settings = Some(view::settings(&doc));
+        }
+        forms.append(spec, &mut doc, &selected.iter().map(|(_, id)| *id).collect());
+        if options.dedupe_resources {
+            let input_shared = dedupe::share_streams(&mut doc, &mut streams);
+            shared.streams += input_shared.streams;
+            shared.bytes += input_shared.bytes;
+        }
+        if options.decompress {
+            compress::decompress_document(&mut doc);
+        }
+        if let Some(s) = stats.get_mut(source) {
+            if spec.compress {
+                *s = compress::compress_document(&mut doc);
+            } else {
+                s.skipped = doc.objects.values().filter(|o| matches!(o, Object::Stream(_))).count();
+            }
+        }
+
+        if let (Some(separator), Some(previous)) = (&options.separator, &previous_page) {
+            let (separator_pages, objects) = separator.pages(previous, &mut max_id)?;
+            for (id, mut page) in separator_pages {
+                if let Object::Dictionary(ref mut dictionary) = page {
+                    dictionary.set("Parent", PAGES_ID);
+                }
+                writer.write_object(id, &page)?;
+                kids.push(Object::Reference(id));
+                page_labels.push(labels::Page::Inserted);
+            }
+            write_objects(&mut writer, &objects, &HashSet::new())?;
+        }
+
+        let first_page = kids.len() + 1;
+        let ranges = labels::ranges(&doc);
+        for (number, page_id) in &selected {
+            let mut page = pages::take_with_inherited_attributes(&mut doc, *page_id)?;
+            if let Some(margins) = spec.crop.or(options.crop) {
+                pages::crop(&mut page, margins)?;
+            }
+            pages::rotate(&mut page, spec.rotate);
+            if let Some(orientation) = options.orientation {
+                pages::orient(&doc, &mut page, orientation);
+            }
+            media_box.get_or_insert_with(|| toc::media_box(&page));
+            if let Object::Dictionary(ref mut dictionary) = page {
+                dictionary.set("Parent", PAGES_ID);
+            }
+            writer.write_object(*page_id, &page)?;
+            kids.push(Object::Reference(*page_id));
+            page_labels.push(labels::Page::Input(source, labels::label(&doc, &ranges, *number as usize - 1)));
+            previous_page = Some(page);
+        }
+        if let Some(last) = previous_page.as_mut().filter(|_| options.duplex_pad && selected.len() % 2 == 1) {
+            // Blank pages are numbered after the input's objects, like the next input will be
+            *last = pages::blank_like(last);
+            writer.write_object((max_id, 0), last)?;
+            kids.push(Object::Reference((max_id, 0)));
+            page_labels.push(labels::Page::Inserted);
+            max_id += 1;
+        }
+        // A fresh Info dictionary and XMP metadata replace those of the inputs
+        let title = outline::document_title(&doc);
+        let input_metadata = metadata::Source::take(&mut doc);
+        if options.metadata_from.map_or(chosen, |index| index == source) {
+            metadata_source = Some(input_metadata);
+        }
+        log::debug!(path:% = spec.path.display(); "{} page(s) and {} other object(s)", selected.len(), doc.objects.len());
+        write_objects(&mut writer, &doc.objects, &outline::tree_ids(&doc))?;
+
+        position += 1;
+        let layer = spec.layer.unwrap_or(position);
+        let fields = TitleFields {
+            path: &spec.path,
+            index: pagenum,
+            first: first_page,
+            last: first_page + selected.len() - 1,
+            title,
+        };
+        let display = bookmark_title(options, spec, &fields);
+        pagenum += 1;
+        let color = spec.bookmark_color.unwrap_or(options.bookmark_color).0;
+        let format = spec.bookmark_style.unwrap_or(options.bookmark_style).format();
+        toc_entries.push(toc::TocEntry { title: display.clone(), page: selected[0].1, number: fields.first });
+        let bookmark = Bookmark::new(display, color, format, selected[0].1);
+        if let Some(bookmark) = bookmarks.add(&mut res, spec, layer, bookmark)? {
+            let taken = selected.iter().map(|(_, id)| *id).collect::<HashSet<_>>();
+            outline::attach(&mut res, &source_outline, bookmark, &|page| taken.contains(&page));
+        }
+        progress(Progress::Copied { index: source, total });
+        options.check_cancelled()?;
+    }
+
+    if options.pdfa == Some(pdfa::Mode::Check) {
+        pdfa::report(pdfa::Mode::Check, &identifications);
+    }
+    let Some((mut catalog, mut pages_root)) = root else {
+        return Err(crate::Error::Usage(tr("the input chosen as root was left out", &[])));
+    };
+    if kids.is_empty() {
+        return Err(crate::Error::NoPages);
+    }
+
+    // The table of contents comes before every input, its objects after theirs
+    let mut front_pages = 0;
+    if options.toc_page {
+        let objects = toc::build(&toc_entries, media_box.unwrap_or_default(), PAGES_ID, max_id);
+        max_id += objects.len() as u32;
+        let ids = objects.iter().filter(|(_, o)| o.type_name().ok() == Some("Page")).map(|(id, _)| Object::Reference(*id)).collect::<Vec<_>>();
+        front_pages = ids.len();
+        kids.splice(0..0, ids);
+        for (id, object) in &objects {
+            writer.write_object(*id, object)?;
+        }
+    }
+    // The cover goes before the table of contents
+    if let Some(cover) = &options.cover {
+        let (cover_pages, objects) = cover.pages(toc_entries.len(), media_box.unwrap_or_default(), &mut max_id)?;
+        front_pages += cover_pages.len();
+        kids.splice(0..0, cover_pages.iter().map(|(id, _)| Object::Reference(*id)));
+        for (id, mut page) in cover_pages {
+            if let Object::Dictionary(ref mut dictionary) = page {
+                dictionary.set("Parent", PAGES_ID);
+            }
+            writer.write_object(id, &page)?;
+        }
+        write_objects(&mut writer, &objects, &HashSet::new())?;
+    }
+
+    // A given outline replaces the generated bookmarks
+    if let Some(items) = &options.outline {
+        res.bookmarks.clear();
+        res.bookmark_table.clear();
+        let pages = kids.iter().filter_map(|kid| kid.as_reference().ok()).collect::<Vec<_>>();
+        outline::add_items(&mut res, items, &pages, None);
+    }
+
+    for key in pages::INHERITABLE_ATTRIBUTES {
+        pages_root.remove(key);
+    }
+    pages_root.remove(b"Parent");
+    let open_action = match &options.open_at {
+        Some(open_at) => Some(open_at.destination(&kids.iter().filter_map(|kid| kid.as_reference().ok()).collect::<Vec<_>>())?),
+        None => None,
+    };
+    pages_root.set("Count", kids.len() as i64);
+    pages_root.set("Kids", kids);
+    writer.write_object(PAGES_ID, &Object::Dictionary(pages_root))?;
+
+    //Set any Bookmarks to the First child if they are not set to a page
+    res.max_id = max_id;
+    res.adjust_zero_pages();
+    shape_outline(&mut res, options);
+    catalog.set("Pages", PAGES_ID);
+    if let Some(mode) = options.page_mode {
+        catalog.set("PageMode", mode.name());
+    } else if options.bookmarks != BookmarkMode::None {
+        catalog.set("PageMode", "UseOutlines");
+    }
+    catalog.remove(b"Outlines");
+    if let Some(destination) = open_action {
+        catalog.set("OpenAction", destination);
+    }
+    attachments.apply(&mut catalog);
+    forms.apply(&mut catalog);
+    destinations.apply(&mut catalog);
+    layers.apply(&mut catalog);
+    threads.apply(&mut catalog);
+    if let Some(settings) = &view_settings {
+        view::apply(&mut catalog, settings);
+    }
+    if let Some(layout) = options.page_layout {
+        catalog.set("PageLayout", layout.name());
+    }
+    structure.apply(&mut catalog, STRUCTURE_ID);
+    if let Some(root) = structure.root() {
+        writer.write_object(STRUCTURE_ID, &Object::Dictionary(root))?;
+    }
+    let labels = std::iter::repeat_n(labels::Page::Front, front_pages).chain(page_labels).collect::<Vec<_>>();
+    catalog.remove(b"PageLabels");
+    if let Some(tree) = labels::number_tree(&labels, options.relabel) {
+        catalog.set("PageLabels", tree);
+    }
+    if let Some(outlines_id) = res.build_outline() {
+        catalog.set("Outlines", outlines_id);
+        for (id, object) in &res.objects {
+            writer.write_object(*id, object)?;
+        }
+    }
+    let mut trailer = Dictionary::new();
+    trailer.set("Root", CATALOG_ID);
+    // Numbered after everything else, the outline included
+    let info_id = (res.max_id.max(max_id) + 1, 0);
+    let xmp_id = (info_id.0 + 1, 0);
+    let info = output_info(options, metadata_source.unwrap_or_default());
+    if !info.is_empty() {
+        writer.write_object(xmp_id, &Object::Stream(metadata::xmp_stream(&info, None)))?;
+        writer.write_object(info_id, &Object::Dictionary(info))?;
+        trailer.set("Info", info_id);
+        catalog.set("Metadata", xmp_id);
+    }
+    if options.encryption.is_some() {
+        version = encrypt::declare_aes(&mut catalog, &version);
+    }
+    if version != header {
+        catalog.set("Version", Object::Name(version.into_bytes()));
+    } else {
+        catalog.remove(b"Version");
+    }
+    writer.write_object(CATALOG_ID, &Object::Dictionary(catalog))?;
+    shared.report();
+
+    // Our page tree is right by construction, what the inputs refer to may not be written
+    verify::report(verify::dangling(writer.unwritten().map(|(target, referrer)| (target, Some(referrer)))), options.no_verify)?;
+    writer.finish(trailer)?;
+    Ok(stats)
+}
+
+/// Write the objects of an input, except pages, which are written on their own, the page tree
+/// nodes, Catalog and outline, which are replaced by ours, and the cross-reference and object
+/// streams it was read from. Outline items have no `/Type` of their own, they are given as `outline`.
+fn write_objects<'a, W: Write>(
+    writer: &mut PdfWriter<W>,
+    objects: impl IntoIterator<Item = (&'a ObjectId, &'a Object)>,
+    outline: &HashSet<ObjectId>,
+) -> crate::Result<()> {
+    for (id, object) in objects {
+        if outline.contains(id) {
+            continue;
+        }
+        match object.type_name().unwrap_or("") {
+            "Page" | "Pages" | "Catalog" | "Outlines" | "Outline" | "XRef" | "ObjStm" => {}
+            _ => writer.write_object(*id, object)?,
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use lopdf::dictionary;
+
+    use super::*;
+
+    /// `doc` with one bookmark per page, its items without a `/Type` like most writers leave them.
+    fn bookmarked(mut doc: Document) -> Document {
+        let outlines = doc.new_object_id();
+        let pages = doc.get_pages().into_values().collect::<Vec<_>>();
+        let items = pages.iter().map(|_| doc.new_object_id()).collect::<Vec<_>>();
+        for (index, (&item, &page)) in items.iter().zip(&pages).enumerate() {
+            let mut dictionary = dictionary! {
+                "Title" => Object::string_literal(format!("page {}", index + 1)),
+                "Parent" => outlines,
+                "Dest" => vec![page.into(), "Fit".into()],
+            };
+            if let Some(&next) = items.get(index + 1) {
+                dictionary.set("Next", next);
+            }
+            if let Some(&prev) = index.checked_sub(1).and_then(|i| items.get(i)) {
+                dictionary.set("Prev", prev);
+            }
+            doc.objects.insert(item, Object::Dictionary(dictionary));
+        }
+        doc.objects.insert(
+            outlines,
+            Object::Dictionary(dictionary! {
+                "Type" => "Outlines",
+                "First" => items[0],
+                "Last" => items[items.len() - 1],
+                "Count" => items.len() as i64,
+            }),
+        );
+        doc.catalog_mut().unwrap().set("Outlines", outlines);
+        doc
+    }
+
+    #[test]
+    fn outlines_of_the_inputs_are_left_out() {
+        let inputs = [InputSpec::new(PathBuf::from("a.pdf")), InputSpec::new(PathBuf::from("b.pdf"))];
+        let mut out = Vec::new();
+        let options = MergeOptions::new().bookmarks(BookmarkMode::PerFile);
+        merge_streaming(&inputs, &options, &mut out, |_| Ok(Some(bookmarked(crate::tests::document(2)))), |_| {}).unwrap();
+
+        let merged = Document::load_mem(&out).unwrap();
+        assert_eq!(merged.get_pages().len(), 4);
+        // The inputs' outline nests below the bookmark of each input, no item is written twice
+        let entries = outline::read(&merged);
+        assert_eq!(entries.iter().map(|entry| entry.title.as_str()).collect::<Vec<_>>(), ["a", "b"]);
+        assert!(entries.iter().all(|entry| entry.children.len() == 2));
+        let items = merged.objects.values().filter_map(|object| object.as_dict().ok()).filter(|d| d.has(b"Title") && d.has(b"Parent")).count();
+        assert_eq!(items, 6);
+    }
+}
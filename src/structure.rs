@@ -0,0 +1,166 @@
+use std::collections::HashSet;
+
+use lopdf::{Dictionary, Document, Object, ObjectId, StringFormat};
+
+use crate::{attachments, input::InputSpec, labels, messages::tr, text};
+
+/// The logical structure (the catalog's `StructTreeRoot`, PDF 32000-1, 14.7) of the tagged
+/// inputs, gathered under one structure tree root for the merged document, so screen readers
+/// still find the headings and reading order of every input.
+#[derive(Debug, Clone, Default)]
+pub struct Structure {
+    /// The top-level structure elements of every input
+    kids: Vec<Object>,
+    parent_tree: Vec<(i64, Object)>,
+    next_key: i64,
+    role_map: Dictionary,
+    class_map: Dictionary,
+    ids: Vec<(Vec<u8>, Object)>,
+    suspects: bool,
+}
+
+impl Structure {
+    /// Add the structure tree of `doc`, renumbered as it will be merged, taking it out of its
+    /// catalog; its top-level elements get `root_id`, the merged root's id, as their parent. The
+    /// keys pages and annotations have in the parent tree (`StructParents`, `StructParent`) are
+    /// moved past those of earlier inputs. Role and class map entries an earlier input has are
+    /// kept as it has them.
+    pub fn append(&mut self, spec: &InputSpec, doc: &mut Document, root_id: ObjectId) {
+        let Ok(catalog) = doc.catalog() else { return };
+        let Some(root) = catalog.get_deref(b"StructTreeRoot", doc).and_then(Object::as_dict).ok().cloned() else { return };
+        let root_object = catalog.get(b"StructTreeRoot").and_then(Object::as_reference).ok();
+        let lang = catalog.get(b"Lang").ok().cloned();
+        self.suspects |= catalog
+            .get_deref(b"MarkInfo", doc)
+            .and_then(Object::as_dict)
+            .and_then(|mark_info| mark_info.get(b"Suspects"))
+            .and_then(Object::as_bool)
+            .unwrap_or(false);
+        if let Ok(catalog) = doc.catalog_mut() {
+            catalog.remove(b"StructTreeRoot");
+            catalog.remove(b"MarkInfo");
+        }
+        // Replaced by the merged root
+        if let Some(id) = root_object {
+            doc.objects.remove(&id);
+        }
+
+        let offset = self.next_key;
+        let mut next_key = root.get(b"ParentTreeNextKey").and_then(Object::as_i64).unwrap_or(0);
+        if offset > 0 {
+            for object in doc.objects.values_mut() {
+                let dict = match object {
+                    Object::Dictionary(dict) => dict,
+                    Object::Stream(stream) => &mut stream.dict,
+                    _ => continue,
+                };
+                for key in [b"StructParents".as_slice(), b"StructParent"] {
+                    if let Ok(number) = dict.get(key).and_then(Object::as_i64) {
+                        dict.set(key, number + offset);
+                    }
+                }
+            }
+        }
+        let mut parents = Vec::new();
+        if let Ok(tree) = root.get(b"ParentTree") {
+            labels::leaves(doc, tree, 0, &mut parents);
+        }
+        for (key, parent) in parents {
+            next_key = next_key.max(key + 1);
+            self.parent_tree.push((key + offset, parent));
+        }
+        self.next_key = offset + next_key;
+
+        let kids = match root.get(b"K") {
+            Ok(Object::Array(kids)) => kids.clone(),
+            Ok(kid) => vec![kid.clone()],
+            Err(_) => Vec::new(),
+        };
+        for kid in &kids {
+            if let Ok(element) = kid.as_reference().and_then(|id| doc.get_dictionary_mut(id)) {
+                element.set("P", root_id);
+                // The language of the input, which the merged catalog may not have
+                if let Some(lang) = lang.as_ref().filter(|_| !element.has(b"Lang")) {
+                    element.set("Lang", lang.clone());
+                }
+            }
+        }
+        self.kids.extend(kids);
+
+        for (key, map) in [(b"RoleMap".as_slice(), &mut self.role_map), (b"ClassMap", &mut self.class_map)] {
+            let Ok(entries) = root.get_deref(key, doc).and_then(Object::as_dict) else { continue };
+            for (name, value) in entries.iter() {
+                match map.get(name) {
+                    Ok(kept) if kept != value && key == b"RoleMap" => log::info!(
+                        "{}",
+                        tr("{}: role {} maps to another type than in an earlier input, which is kept", &[&spec.path.display(), &String::from_utf8_lossy(name)])
+                    ),
+                    Ok(_) => {}
+                    Err(_) => map.set(name.clone(), value.clone()),
+                }
+            }
+        }
+        if let Ok(tree) = root.get(b"IDTree") {
+            let mut ids = Vec::new();
+            attachments::leaves(doc, tree, 0, &mut ids);
+            let used = self.ids.iter().map(|(id, _)| id.clone()).collect::<HashSet<_>>();
+            for (id, element) in ids {
+                if used.contains(&id) {
+                    log::info!("{}", tr("{}: structure element ID {} is used by an earlier input", &[&spec.path.display(), &text::decode(&id)]));
+                } else {
+                    self.ids.push((id, element));
+                }
+            }
+        }
+    }
+
+    /// The merged structure tree root, if any input is tagged.
+    pub fn root(&self) -> Option<Dictionary> {
+        if self.kids.is_empty() {
+            return None;
+        }
+        let mut root = Dictionary::new();
+        root.set("Type", "StructTreeRoot");
+        root.set("K", self.kids.clone());
+        let mut parent_tree = self.parent_tree.clone();
+        // Keys in a number tree are sorted
+        parent_tree.sort_by_key(|(key, _)| *key);
+        let nums = parent_tree.into_iter().flat_map(|(key, parent)| [Object::Integer(key), parent]).collect::<Vec<_>>();
+        let mut tree = Dictionary::new();
+        tree.set("Nums", nums);
+        root.set("ParentTree", tree);
+        root.set("ParentTreeNextKey", self.next_key);
+        if !self.role_map.is_empty() {
+            root.set("RoleMap", self.role_map.clone());
+        }
+        if !self.class_map.is_empty() {
+            root.set("ClassMap", self.class_map.clone());
+        }
+        if !self.ids.is_empty() {
+            let mut ids = self.ids.clone();
+            ids.sort_by(|a, b| a.0.cmp(&b.0));
+            let names = ids.into_iter().flat_map(|(id, element)| [Object::String(id, StringFormat::Literal), element]).collect::<Vec<_>>();
+            let mut tree = Dictionary::new();
+            tree.set("Names", names);
+            root.set("IDTree", tree);
+        }
+        Some(root)
+    }
+
+    /// Point `catalog` at the merged structure tree root, written as `root_id`, and say the
+    /// document is tagged, or take both out if no input is.
+    pub fn apply(&self, catalog: &mut Dictionary, root_id: ObjectId) {
+        catalog.remove(b"StructTreeRoot");
+        catalog.remove(b"MarkInfo");
+        if self.kids.is_empty() {
+            return;
+        }
+        catalog.set("StructTreeRoot", root_id);
+        let mut mark_info = Dictionary::new();
+        mark_info.set("Marked", true);
+        if self.suspects {
+            mark_info.set("Suspects", true);
+        }
+        catalog.set("MarkInfo", mark_info);
+    }
+}
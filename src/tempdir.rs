@@ -0,0 +1,32 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+/// A scratch directory for downloaded or extracted inputs, removed again when dropped.
+pub struct TempDir(PathBuf);
+
+impl TempDir {
+    pub fn new() -> std::io::Result<Self> {
+        let dir = std::env::temp_dir().join(format!("merge_pdf-{}", std::process::id()));
+        fs::create_dir_all(&dir)?;
+        Ok(TempDir(dir))
+    }
+
+    /// A scratch directory of its own for `purpose`, apart from the one `new` makes.
+    pub fn named(purpose: &str) -> std::io::Result<Self> {
+        let dir = std::env::temp_dir().join(format!("merge_pdf-{}-{}", std::process::id(), purpose));
+        fs::create_dir_all(&dir)?;
+        Ok(TempDir(dir))
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
@@ -0,0 +1,20 @@
+/// Decode a PDF text string (UTF-16BE or UTF-8 with BOM, PDFDocEncoding otherwise).
+pub fn decode(bytes: &[u8]) -> String {
+    if let Some(utf16) = bytes.strip_prefix(&[0xfe, 0xff]) {
+        let units = utf16.chunks_exact(2).map(|c| u16::from_be_bytes([c[0], c[1]])).collect::<Vec<_>>();
+        return String::from_utf16_lossy(&units);
+    }
+    if let Some(utf8) = bytes.strip_prefix(&[0xef, 0xbb, 0xbf]) {
+        return String::from_utf8_lossy(utf8).into_owned();
+    }
+    // PDFDocEncoding matches Latin-1 for everything that matters in titles
+    bytes.iter().map(|&b| b as char).collect()
+}
+
+/// Encode a PDF text string: as is when ASCII, as UTF-16BE with a byte order mark otherwise.
+pub fn encode(text: &str) -> Vec<u8> {
+    if text.is_ascii() {
+        return text.as_bytes().to_vec();
+    }
+    [0xfe, 0xff].into_iter().chain(text.encode_utf16().flat_map(u16::to_be_bytes)).collect()
+}
@@ -0,0 +1,83 @@
+use std::collections::BTreeSet;
+
+use lopdf::{Dictionary, Document, Object, ObjectId};
+
+use crate::{input::InputSpec, messages::tr};
+
+/// How many beads of a thread are followed.
+const MAX_BEADS: usize = 100_000;
+
+/// The article threads (the catalog's `Threads`, PDF 32000-1, 12.4.3) of the inputs, gathered
+/// for the merged document, whose catalog comes from a single input.
+#[derive(Debug, Clone, Default)]
+pub struct Threads {
+    threads: Vec<Object>,
+}
+
+impl Threads {
+    /// Add the threads of `doc`, renumbered as it will be merged, taking them out of its catalog.
+    /// Beads on pages other than `pages` are unlinked from their thread, and a thread left
+    /// without beads is dropped.
+    pub fn append(&mut self, spec: &InputSpec, doc: &mut Document, pages: &BTreeSet<ObjectId>) {
+        let Ok(catalog) = doc.catalog() else { return };
+        let Some(threads) = catalog.get_deref(b"Threads", doc).and_then(Object::as_array).ok().cloned() else { return };
+        if let Ok(catalog) = doc.catalog_mut() {
+            catalog.remove(b"Threads");
+        }
+
+        let mut dropped = 0;
+        for thread in threads {
+            let Ok(thread_id) = thread.as_reference() else { continue };
+            let beads = beads(doc, thread_id);
+            let (kept, gone): (Vec<_>, Vec<_>) = beads.into_iter().partition(|&bead| {
+                doc.get_dictionary(bead).and_then(|bead| bead.get(b"P")).and_then(Object::as_reference).is_ok_and(|page| pages.contains(&page))
+            });
+            for bead in gone {
+                doc.objects.remove(&bead);
+            }
+            if kept.is_empty() {
+                doc.objects.remove(&thread_id);
+                dropped += 1;
+                continue;
+            }
+            // The beads left form a ring, each pointing back at the thread
+            for (index, &bead) in kept.iter().enumerate() {
+                if let Ok(dict) = doc.get_dictionary_mut(bead) {
+                    dict.set("T", thread_id);
+                    dict.set("N", kept[(index + 1) % kept.len()]);
+                    dict.set("V", kept[(index + kept.len() - 1) % kept.len()]);
+                }
+            }
+            if let Ok(dict) = doc.get_dictionary_mut(thread_id) {
+                dict.set("F", kept[0]);
+            }
+            self.threads.push(thread);
+        }
+        if dropped > 0 {
+            log::info!("{}", tr("{}: {} article thread(s) left out with their pages", &[&spec.path.display(), &dropped]));
+        }
+    }
+
+    /// Give `catalog` the threads of all the inputs, if any has some.
+    pub fn apply(&self, catalog: &mut Dictionary) {
+        catalog.remove(b"Threads");
+        if !self.threads.is_empty() {
+            catalog.set("Threads", self.threads.clone());
+        }
+    }
+}
+
+/// The beads of the thread `thread_id`, in order from its first.
+fn beads(doc: &Document, thread_id: ObjectId) -> Vec<ObjectId> {
+    let mut beads = Vec::new();
+    let first = doc.get_dictionary(thread_id).and_then(|thread| thread.get(b"F")).and_then(Object::as_reference);
+    let mut next = first.ok();
+    while let Some(bead) = next {
+        if beads.contains(&bead) || beads.len() >= MAX_BEADS {
+            break;
+        }
+        beads.push(bead);
+        next = doc.get_dictionary(bead).and_then(|bead| bead.get(b"N")).and_then(Object::as_reference).ok();
+    }
+    beads
+}
@@ -0,0 +1,127 @@
+use lopdf::{dictionary, Dictionary, Object, ObjectId, Stream};
+
+/// One inch, less on pages too small for that.
+const MARGIN: f32 = 72.0;
+const HEADING_SIZE: f32 = 18.0;
+const FONT_SIZE: f32 = 12.0;
+const LINE_HEIGHT: f32 = 18.0;
+
+/// One line of the table of contents.
+#[derive(Debug, Clone)]
+pub struct TocEntry {
+    pub title: String,
+    /// The page the line links to.
+    pub page: ObjectId,
+    /// The number printed for that page, as its page label reads.
+    pub number: usize,
+}
+
+/// The MediaBox of `page`, US Letter if it has none.
+pub fn media_box(page: &Object) -> [f32; 4] {
+    page.as_dict()
+        .and_then(|page| page.get(b"MediaBox"))
+        .and_then(Object::as_array)
+        .ok()
+        .and_then(|values| values.iter().map(|v| v.as_float().ok()).collect::<Option<Vec<_>>>())
+        .and_then(|values| <[f32; 4]>::try_from(values).ok())
+        .unwrap_or([0.0, 0.0, 612.0, 792.0])
+}
+
+fn margin(media_box: [f32; 4]) -> f32 {
+    MARGIN.min((media_box[2] - media_box[0]).min(media_box[3] - media_box[1]) / 8.5)
+}
+
+/// Lines that fit on one page of `media_box`, below the heading.
+pub fn lines_per_page(media_box: [f32; 4]) -> usize {
+    let height = media_box[3] - media_box[1] - 2.0 * margin(media_box);
+    (((height - 2.0 * LINE_HEIGHT) / LINE_HEIGHT).floor() as usize).max(1)
+}
+
+/// How many pages a table of contents of `entries` lines takes.
+pub fn page_count(entries: usize, media_box: [f32; 4]) -> usize {
+    entries.div_ceil(lines_per_page(media_box)).max(1)
+}
+
+/// Build the pages of a table of contents listing `entries`, every line a link to its page.
+///
+/// The pages hang below `parent` and have `media_box`; objects are numbered from `next_id`.
+/// Returns all new objects, pages first and in order.
+pub fn build(entries: &[TocEntry], media_box: [f32; 4], parent: ObjectId, next_id: u32) -> Vec<(ObjectId, Object)> {
+    let [left, _, right, top] = media_box;
+    let per_page = lines_per_page(media_box);
+    let margin = margin(media_box);
+    let pages = page_count(entries.len(), media_box);
+    let page_ids = (0..pages as u32).map(|i| (next_id + i, 0)).collect::<Vec<_>>();
+    let font_id = (next_id + pages as u32, 0);
+    let mut objects = Vec::new();
+    let mut streams = Vec::new();
+    let number_x = right - margin - 36.0;
+    // An empty table still gets its page, with just the heading
+    let chunks = if entries.is_empty() { vec![entries] } else { entries.chunks(per_page).collect() };
+
+    for (n, chunk) in chunks.into_iter().enumerate() {
+        let mut content = String::new();
+        let mut y = top - margin - HEADING_SIZE;
+        if n == 0 {
+            content.push_str(&format!("BT /F1 {} Tf {} {} Td {} Tj ET\n", HEADING_SIZE, left + margin, y, literal("Contents")));
+        }
+        y -= 2.0 * LINE_HEIGHT;
+        let mut annotations = Vec::new();
+        for entry in chunk {
+            content.push_str(&format!("BT /F1 {} Tf {} {} Td {} Tj ET\n", FONT_SIZE, left + margin, y, literal(&entry.title)));
+            content.push_str(&format!("BT /F1 {} Tf {} {} Td {} Tj ET\n", FONT_SIZE, number_x, y, literal(&entry.number.to_string())));
+            // The whole line is clickable
+            annotations.push(Object::Dictionary(dictionary! {
+                "Type" => "Annot",
+                "Subtype" => "Link",
+                "Rect" => vec![(left + margin).into(), (y - 4.0).into(), (right - margin).into(), (y + FONT_SIZE).into()],
+                "Border" => vec![0.into(), 0.into(), 0.into()],
+                "Dest" => vec![entry.page.into(), "Fit".into()],
+            }));
+            y -= LINE_HEIGHT;
+        }
+        let content_id = (next_id + pages as u32 + 1 + n as u32, 0);
+        streams.push((content_id, Object::Stream(Stream::new(Dictionary::new(), content.into_bytes()))));
+        objects.push((
+            page_ids[n],
+            Object::Dictionary(dictionary! {
+                "Type" => "Page",
+                "Parent" => parent,
+                "MediaBox" => media_box.iter().map(|&v| v.into()).collect::<Vec<Object>>(),
+                "Contents" => content_id,
+                "Resources" => dictionary! { "Font" => dictionary! { "F1" => font_id } },
+                "Annots" => annotations,
+            }),
+        ));
+    }
+    objects.push((
+        font_id,
+        Object::Dictionary(dictionary! {
+            "Type" => "Font",
+            "Subtype" => "Type1",
+            "BaseFont" => "Helvetica",
+            "Encoding" => "WinAnsiEncoding",
+        }),
+    ));
+    objects.extend(streams);
+    objects
+}
+
+/// A PDF literal string of `text` in WinAnsi encoding; characters outside Latin-1 become '?'.
+pub(crate) fn literal(text: &str) -> String {
+    let mut out = String::from("(");
+    for c in text.chars() {
+        match c {
+            '(' | ')' | '\\' => {
+                out.push('\\');
+                out.push(c);
+            }
+            c if (c as u32) < 0x20 => out.push(' '),
+            c if (c as u32) < 0x80 => out.push(c),
+            c if (c as u32) <= 0xff => out.push_str(&format!("\\{:03o}", c as u32)),
+            _ => out.push('?'),
+        }
+    }
+    out.push(')');
+    out
+}
@@ -0,0 +1,266 @@
+/// A TOML value. Only the scalar types a flat settings file needs, and arrays of them, are supported.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    String(String),
+    Integer(i64),
+    Bool(bool),
+    Array(Vec<Value>),
+}
+
+impl Value {
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Value::String(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    pub fn as_i64(&self) -> Option<i64> {
+        match self {
+            Value::Integer(n) => Some(*n),
+            _ => None,
+        }
+    }
+
+    pub fn as_bool(&self) -> Option<bool> {
+        match self {
+            Value::Bool(b) => Some(*b),
+            _ => None,
+        }
+    }
+}
+
+/// Parse a TOML document into its key/value pairs in document order. Keys inside a `[table]`
+/// are returned dotted, as `table.key`. Arrays may span lines.
+pub fn parse(text: &str) -> Result<Vec<(String, Value)>, String> {
+    let mut table = String::new();
+    let mut entries = Vec::new();
+    let mut lines = text.lines().enumerate();
+    while let Some((number, line)) = lines.next() {
+        let error = |message: &str| format!("{} at line {}", message, number + 1);
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        if let Some(header) = line.strip_prefix('[') {
+            let (name, rest) = header.split_once(']').ok_or_else(|| error("unterminated table header"))?;
+            if name.starts_with('[') || !is_comment(rest) {
+                return Err(error("arrays of tables are not supported"));
+            }
+            table = name.trim().to_string();
+            continue;
+        }
+        let (key, value) = line.split_once('=').ok_or_else(|| error("expected `key = value`"))?;
+        let key = key.trim().trim_matches('"');
+        if key.is_empty() {
+            return Err(error("empty key"));
+        }
+        let key = if table.is_empty() { key.to_string() } else { format!("{}.{}", table, key) };
+        // An array left open goes on over the next lines
+        let mut value = value.to_string();
+        let (parsed, rest) = loop {
+            match parse_value(&value) {
+                Err(Unterminated) => match lines.next() {
+                    Some((_, next)) => {
+                        value.push('\n');
+                        value.push_str(next);
+                    }
+                    None => return Err(error("unterminated array")),
+                },
+                Ok(Some((parsed, rest))) => break (parsed, rest.to_string()),
+                Ok(None) if value.trim_start().starts_with(['"', '\'']) => return Err(error("invalid string")),
+                Ok(None) => return Err(error("unsupported value")),
+            }
+        };
+        if !is_comment(&rest) {
+            return Err(error("trailing characters after value"));
+        }
+        entries.push((key, parsed));
+    }
+    Ok(entries)
+}
+
+/// An array that goes on past the text given.
+struct Unterminated;
+
+/// Read the value at the start of `text`, returning it and what follows; `None` if it is not one.
+fn parse_value(text: &str) -> Result<Option<(Value, &str)>, Unterminated> {
+    let text = text.trim_start();
+    if let Some(quoted) = text.strip_prefix('"') {
+        return Ok(basic_string(quoted).map(|(value, rest)| (Value::String(value), rest)));
+    }
+    if let Some(quoted) = text.strip_prefix('\'') {
+        return Ok(quoted.split_once('\'').map(|(literal, rest)| (Value::String(literal.to_string()), rest)));
+    }
+    if let Some(mut rest) = text.strip_prefix('[') {
+        let mut items = Vec::new();
+        loop {
+            rest = skip_blank(rest);
+            if rest.is_empty() {
+                return Err(Unterminated);
+            }
+            if let Some(after) = rest.strip_prefix(']') {
+                return Ok(Some((Value::Array(items), after)));
+            }
+            let Some((item, after)) = parse_value(rest)? else { return Ok(None) };
+            items.push(item);
+            rest = skip_blank(after);
+            if let Some(after) = rest.strip_prefix(',') {
+                rest = after;
+            } else if !rest.starts_with(']') {
+                return if rest.is_empty() { Err(Unterminated) } else { Ok(None) };
+            }
+        }
+    }
+    let end = text.find(|c: char| c.is_whitespace() || matches!(c, ',' | ']' | '#')).unwrap_or(text.len());
+    let (word, rest) = text.split_at(end);
+    let value = match word {
+        "true" => Value::Bool(true),
+        "false" => Value::Bool(false),
+        word => match word.replace('_', "").parse() {
+            Ok(n) => Value::Integer(n),
+            Err(_) => return Ok(None),
+        },
+    };
+    Ok(Some((value, rest)))
+}
+
+/// `text` past whitespace, line breaks and comments.
+fn skip_blank(mut text: &str) -> &str {
+    loop {
+        text = text.trim_start();
+        match text.strip_prefix('#') {
+            Some(comment) => text = comment.split_once('\n').map_or("", |(_, next)| next),
+            None => return text,
+        }
+    }
+}
+
+fn is_comment(rest: &str) -> bool {
+    let rest = rest.trim();
+    rest.is_empty() || rest.starts_with('#')
+}
+
+/// Read a `"..."` string whose opening quote is already consumed, returning it and what follows.
+fn basic_string(text: &str) -> Option<(String, &str)> {
+    let mut out = String::new();
+    let mut chars = text.char_indices();
+    while let Some((at, c)) = chars.next() {
+        match c {
+            '"' => return Some((out, &text[at + 1..])),
+            '\\' => match chars.next()?.1 {
+                'b' => out.push('\u{8}'),
+                't' => out.push('\t'),
+                'n' => out.push('\n'),
+                'f' => out.push('\u{c}'),
+                'r' => out.push('\r'),
+                '"' => out.push('"'),
+                '\\' => out.push('\\'),
+                escape @ ('u' | 'U') => {
+                    let len = if escape == 'u' { 4 } else { 8 };
+                    let digits = (0..len).map(|_| chars.next().map(|(_, d)| d)).collect::<Option<String>>()?;
+                    out.push(char::from_u32(u32::from_str_radix(&digits, 16).ok()?)?);
+                }
+                _ => return None,
+            },
+            c => out.push(c),
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn string(s: &str) -> Value {
+        Value::String(s.to_string())
+    }
+
+    #[test]
+    fn strings_unescape() {
+        let entries = parse(concat!(
+            "basic = \"tab\\there \\\"quoted\\\" back\\\\slash\"\n",
+            "unicode = \"\\u00e9t\\U0001F600\"\n",
+            "literal = 'C:\\scans\\no escapes'\n",
+            "\"quoted key\" = \"a # not a comment\"\n",
+        ))
+        .unwrap();
+        assert_eq!(
+            entries,
+            [
+                ("basic".to_string(), string("tab\there \"quoted\" back\\slash")),
+                ("unicode".to_string(), string("\u{e9}t\u{1F600}")),
+                ("literal".to_string(), string("C:\\scans\\no escapes")),
+                ("quoted key".to_string(), string("a # not a comment")),
+            ]
+        );
+    }
+
+    #[test]
+    fn scalars_and_comments_after_values() {
+        let entries = parse("# settings\ncompress = true # always\nthreshold = 1_000 # pages\nname = \"x\" # c\n\n").unwrap();
+        assert_eq!(
+            entries,
+            [
+                ("compress".to_string(), Value::Bool(true)),
+                ("threshold".to_string(), Value::Integer(1000)),
+                ("name".to_string(), string("x")),
+            ]
+        );
+    }
+
+    #[test]
+    fn arrays_may_span_lines_and_nest() {
+        let entries = parse(concat!(
+            "inline = [1, 2, 3]\n",
+            "empty = []\n",
+            "mixed = [\"a, b]\", 'c', true, [4, 5]] # nested\n",
+            "long = [\n",
+            "  \"first\", # the first\n",
+            "  \"second\",\n",
+            "]\n",
+            "after = 1\n",
+        ))
+        .unwrap();
+        assert_eq!(
+            entries,
+            [
+                ("inline".to_string(), Value::Array(vec![Value::Integer(1), Value::Integer(2), Value::Integer(3)])),
+                ("empty".to_string(), Value::Array(Vec::new())),
+                (
+                    "mixed".to_string(),
+                    Value::Array(vec![string("a, b]"), string("c"), Value::Bool(true), Value::Array(vec![Value::Integer(4), Value::Integer(5)])])
+                ),
+                ("long".to_string(), Value::Array(vec![string("first"), string("second")])),
+                ("after".to_string(), Value::Integer(1)),
+            ]
+        );
+    }
+
+    #[test]
+    fn keys_in_tables_are_dotted() {
+        let entries = parse("top = 1\n[output]\nname = \"merged\"\n[ bookmarks ] # spaced\nstyle = \"bold\"\n").unwrap();
+        let keys = entries.iter().map(|(key, _)| key.as_str()).collect::<Vec<_>>();
+        assert_eq!(keys, ["top", "output.name", "bookmarks.style"]);
+    }
+
+    #[test]
+    fn errors_give_the_line() {
+        for (text, error) in [
+            ("a = 1\nnot a pair\n", "expected `key = value` at line 2"),
+            ("a = 1\n\nb = \"open\n", "invalid string at line 3"),
+            ("a = \"bad \\q escape\"\n", "invalid string at line 1"),
+            ("# c\na = 1 2\n", "trailing characters after value at line 2"),
+            ("a = maybe\n", "unsupported value at line 1"),
+            ("[table\n", "unterminated table header at line 1"),
+            ("[[list]]\n", "arrays of tables are not supported at line 1"),
+            ("= 1\n", "empty key at line 1"),
+            ("a = [1, 2\nb = 3\n", "unsupported value at line 1"),
+            ("x = 0\na = [1,\n2,\n", "unterminated array at line 2"),
+            ("a = [1 2]\n", "unsupported value at line 1"),
+        ] {
+            assert_eq!(parse(text).unwrap_err(), error, "{:?}", text);
+        }
+    }
+}
@@ -0,0 +1,155 @@
+use std::collections::BTreeSet;
+
+use lopdf::{Document, Object, ObjectId};
+
+use crate::messages::tr;
+
+/// How many dangling references are listed before the rest are only counted.
+const MAX_LISTED: usize = 10;
+
+/// What is structurally wrong with `doc`: references to objects it does not have, and page tree
+/// nodes whose `Parent` is not the node listing them or whose `Count` is not the number of pages
+/// below them. Nothing is wrong if this is empty.
+pub fn check(doc: &Document) -> Vec<String> {
+    let mut missing = Vec::new();
+    let mut found = Vec::new();
+    for (&id, object) in &doc.objects {
+        found.clear();
+        references(object, &mut found);
+        missing.extend(found.iter().filter(|target| !doc.objects.contains_key(target)).map(|&target| (target, Some(id))));
+    }
+    for (_, value) in doc.trailer.iter() {
+        if let Some(target) = value.as_reference().ok().filter(|target| !doc.objects.contains_key(target)) {
+            missing.push((target, None));
+        }
+    }
+    let mut problems = dangling(missing);
+
+    let root = doc.catalog().ok().and_then(|catalog| catalog.get(b"Pages").and_then(Object::as_reference).ok());
+    match root {
+        Some(root) => {
+            page_count(doc, root, None, &mut BTreeSet::new(), &mut problems);
+        }
+        None => problems.push("the catalog has no page tree".to_string()),
+    }
+    problems
+}
+
+/// Fail with what `check` finds wrong with `doc`, or only warn about it with `warn_only`.
+pub fn verify(doc: &Document, warn_only: bool) -> crate::Result<()> {
+    report(check(doc), warn_only)
+}
+
+/// Fail with `problems`, if any, or only print them as warnings with `warn_only`.
+pub fn report(problems: Vec<String>, warn_only: bool) -> crate::Result<()> {
+    if problems.is_empty() {
+        return Ok(());
+    }
+    if warn_only {
+        for problem in &problems {
+            log::warn!("{}", tr("the merged document is broken: {}", &[&problem]));
+        }
+        return Ok(());
+    }
+    Err(crate::Error::Invalid(tr("the merged document is broken: {}", &[&problems.join("; ")])))
+}
+
+/// Describe references to objects that do not exist, given as the missing object and the one
+/// referring to it, `None` for the trailer.
+pub fn dangling(missing: impl IntoIterator<Item = (ObjectId, Option<ObjectId>)>) -> Vec<String> {
+    let mut problems = Vec::new();
+    let mut more = 0;
+    for (target, referrer) in missing {
+        if problems.len() == MAX_LISTED {
+            more += 1;
+            continue;
+        }
+        problems.push(match referrer {
+            Some(referrer) => format!("object {} {} refers to {} {}, which does not exist", referrer.0, referrer.1, target.0, target.1),
+            None => format!("the trailer refers to {} {}, which does not exist", target.0, target.1),
+        });
+    }
+    if more > 0 {
+        problems.push(format!("{} more references to objects that do not exist", more));
+    }
+    problems
+}
+
+/// Drop the objects of `doc` that cannot be reached from its trailer, such as the outlines,
+/// Info dictionaries and streams of the inputs that nothing refers to once they are merged, and
+/// return how many there were.
+pub fn drop_unreachable(doc: &mut Document) -> usize {
+    let mut reachable = BTreeSet::new();
+    let mut pending = Vec::new();
+    for (_, value) in doc.trailer.iter() {
+        references(value, &mut pending);
+    }
+    while let Some(id) = pending.pop() {
+        if reachable.insert(id) {
+            if let Some(object) = doc.objects.get(&id) {
+                references(object, &mut pending);
+            }
+        }
+    }
+    let before = doc.objects.len();
+    doc.objects.retain(|id, _| reachable.contains(id));
+    before - doc.objects.len()
+}
+
+/// Every indirect reference in `object`, streams' dictionaries included.
+pub(crate) fn references(object: &Object, found: &mut Vec<ObjectId>) {
+    match object {
+        Object::Reference(id) => found.push(*id),
+        Object::Array(items) => items.iter().for_each(|item| references(item, found)),
+        Object::Dictionary(dict) => dict.iter().for_each(|(_, value)| references(value, found)),
+        Object::Stream(stream) => stream.dict.iter().for_each(|(_, value)| references(value, found)),
+        _ => {}
+    }
+}
+
+/// The number of pages below the page tree node `id`, listed by `parent`, noting in `problems`
+/// where the tree is inconsistent.
+fn page_count(doc: &Document, id: ObjectId, parent: Option<ObjectId>, seen: &mut BTreeSet<ObjectId>, problems: &mut Vec<String>) -> i64 {
+    if !seen.insert(id) {
+        problems.push(format!("page tree node {} {} is listed more than once", id.0, id.1));
+        return 0;
+    }
+    // A missing node is a dangling reference, reported as such
+    let Ok(node) = doc.get_dictionary(id) else { return 0 };
+    let listed_by = node.get(b"Parent").and_then(Object::as_reference).ok();
+    match (parent, listed_by) {
+        (None, Some(_)) => problems.push(format!("the page tree root {} {} has a Parent", id.0, id.1)),
+        (Some(parent), listed_by) if listed_by != Some(parent) => {
+            problems.push(format!("page tree node {} {} does not have {} {}, which lists it, as its Parent", id.0, id.1, parent.0, parent.1))
+        }
+        _ => {}
+    }
+    match node.get(b"Type").and_then(Object::as_name).ok() {
+        Some(b"Page") if parent.is_some() => 1,
+        Some(b"Pages") => {
+            let kids = node.get(b"Kids").and_then(Object::as_array).map(Vec::as_slice).unwrap_or_default();
+            let mut pages = 0;
+            for kid in kids {
+                match kid.as_reference() {
+                    Ok(kid) => pages += page_count(doc, kid, Some(id), seen, problems),
+                    Err(_) => problems.push(format!("page tree node {} {} has a kid that is not a reference", id.0, id.1)),
+                }
+            }
+            let count = node.get(b"Count").and_then(Object::as_i64).ok();
+            if count != Some(pages) {
+                problems.push(format!(
+                    "page tree node {} {} has a Count of {}, but {} pages",
+                    id.0,
+                    id.1,
+                    count.map_or("nothing".to_string(), |count| count.to_string()),
+                    pages
+                ));
+            }
+            pages
+        }
+        _ => {
+            problems.push(format!("object {} {} in the page tree is not a Pages node or, below the root, a Page", id.0, id.1));
+            0
+        }
+    }
+}
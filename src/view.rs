@@ -0,0 +1,161 @@
+use std::str::FromStr;
+
+use lopdf::{Dictionary, Document, Object, ObjectId};
+
+use crate::messages::tr;
+
+/// Catalog entries that tell a viewer how to show the document: its preferences (toolbars,
+/// window, print dialog defaults), the natural language of its text and the page layout.
+const ENTRIES: [&[u8]; 3] = [b"ViewerPreferences", b"Lang", b"PageLayout"];
+
+/// The viewer preferences, language and page layout in the catalog of `doc`, those that are
+/// objects of their own copied in.
+pub fn settings(doc: &Document) -> Dictionary {
+    let mut settings = Dictionary::new();
+    let Ok(catalog) = doc.catalog() else { return settings };
+    for key in ENTRIES {
+        if let Ok(value) = catalog.get_deref(key, doc) {
+            settings.set(key, value.clone());
+        }
+    }
+    settings
+}
+
+/// Give `catalog` the viewer preferences, language and page layout in `settings` in place of
+/// its own, leaving out those `settings` does not have.
+pub fn apply(catalog: &mut Dictionary, settings: &Dictionary) {
+    for key in ENTRIES {
+        match settings.get(key) {
+            Ok(value) => catalog.set(key, value.clone()),
+            Err(_) => {
+                catalog.remove(key);
+            }
+        }
+    }
+}
+
+
+/// How the document is shown when opened (the catalog's `PageMode`): which panel is open beside
+/// the pages, or full screen.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+pub enum PageMode {
+    /// No panel open
+    UseNone,
+    /// The bookmarks panel open
+    UseOutlines,
+    /// The page thumbnails open
+    UseThumbs,
+    /// Full screen, with no menu bar or window controls
+    FullScreen,
+    /// The layers panel open
+    #[cfg_attr(feature = "cli", value(name = "use-oc"))]
+    UseOc,
+    /// The attachments panel open
+    UseAttachments,
+}
+
+impl PageMode {
+    pub fn name(self) -> &'static str {
+        match self {
+            PageMode::UseNone => "UseNone",
+            PageMode::UseOutlines => "UseOutlines",
+            PageMode::UseThumbs => "UseThumbs",
+            PageMode::FullScreen => "FullScreen",
+            PageMode::UseOc => "UseOC",
+            PageMode::UseAttachments => "UseAttachments",
+        }
+    }
+}
+
+/// How pages are laid out when the document is opened (the catalog's `PageLayout`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+pub enum PageLayout {
+    /// One page at a time
+    SinglePage,
+    /// The pages in one scrolling column
+    OneColumn,
+    /// The pages in two scrolling columns, odd pages on the left
+    TwoColumnLeft,
+    /// The pages in two scrolling columns, odd pages on the right
+    TwoColumnRight,
+    /// Two pages at a time, odd pages on the left
+    TwoPageLeft,
+    /// Two pages at a time, odd pages on the right
+    TwoPageRight,
+}
+
+impl PageLayout {
+    pub fn name(self) -> &'static str {
+        match self {
+            PageLayout::SinglePage => "SinglePage",
+            PageLayout::OneColumn => "OneColumn",
+            PageLayout::TwoColumnLeft => "TwoColumnLeft",
+            PageLayout::TwoColumnRight => "TwoColumnRight",
+            PageLayout::TwoPageLeft => "TwoPageLeft",
+            PageLayout::TwoPageRight => "TwoPageRight",
+        }
+    }
+}
+
+/// How far an opened page is zoomed.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Zoom {
+    /// The whole page in the window
+    Fit,
+    /// The width of the page in the window
+    FitWidth,
+    /// This percentage of the page's size
+    Percent(f32),
+}
+
+/// The page of the merged document to open at, written `PAGE[,ZOOM]` with the page counted
+/// from 1 and the zoom `fit` (the default), `width` or a percentage like `150`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct OpenAt {
+    pub page: usize,
+    pub zoom: Zoom,
+}
+
+impl FromStr for OpenAt {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("{:?} is not a page to open at, use a page number and an optional zoom like 3, 3,width or 3,150", s);
+        let (page, zoom) = match s.split_once(',') {
+            Some((page, zoom)) => (page, Some(zoom.trim())),
+            None => (s, None),
+        };
+        let page = page.trim().parse::<usize>().ok().filter(|&page| page > 0).ok_or_else(invalid)?;
+        let zoom = match zoom {
+            None | Some("fit") => Zoom::Fit,
+            Some("width") => Zoom::FitWidth,
+            Some(percent) => Zoom::Percent(
+                percent
+                    .trim_end_matches('%')
+                    .parse::<f32>()
+                    .ok()
+                    .filter(|n| n.is_finite() && *n > 0.0)
+                    .ok_or_else(invalid)?,
+            ),
+        };
+        Ok(OpenAt { page, zoom })
+    }
+}
+
+impl OpenAt {
+    /// The destination of the page to open at among `pages`, those of the merged document in
+    /// order.
+    pub fn destination(&self, pages: &[ObjectId]) -> crate::Result<Object> {
+        let Some(&page) = pages.get(self.page - 1) else {
+            return Err(crate::Error::Invalid(tr("cannot open at page {}, the merged document has {} pages", &[&self.page, &pages.len()])));
+        };
+        let destination = match self.zoom {
+            Zoom::Fit => vec![page.into(), "Fit".into()],
+            Zoom::FitWidth => vec![page.into(), "FitH".into(), Object::Null],
+            Zoom::Percent(percent) => vec![page.into(), "XYZ".into(), Object::Null, Object::Null, Object::Real(percent / 100.0)],
+        };
+        Ok(Object::Array(destination))
+    }
+}
@@ -0,0 +1,128 @@
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicBool, Ordering},
+    thread,
+    time::{Duration, Instant, SystemTime},
+};
+
+use merge_pdf::{
+    dir::{self, DirOptions},
+    messages::tr,
+    sort::SortOrder,
+};
+
+/// The PDFs directly inside a watched directory, each with its size and modification time, so a
+/// file still being written differs from one look to the next.
+type Snapshot = Vec<(PathBuf, u64, Option<SystemTime>)>;
+
+fn snapshot(dir: &Path, order: SortOrder) -> io::Result<Snapshot> {
+    let options = DirOptions { max_depth: Some(1), hidden: false };
+    let paths = dir::collect(dir, Path::new(""), options, order, false)?;
+    // A file gone since the listing is no longer part of the batch
+    Ok(paths
+        .into_iter()
+        .filter_map(|path| {
+            let metadata = fs::metadata(&path).ok()?;
+            Some((path, metadata.len(), metadata.modified().ok()))
+        })
+        .collect())
+}
+
+/// Look at the PDFs directly inside `dir` every `interval`, and once they have stayed the same
+/// for `settle` and are not those merged last, `merge` them in `order`, until `stop` is set. The
+/// directory is polled rather than subscribed to, which works the same on network shares that
+/// scanners often write to.
+///
+/// A failed merge is logged and not retried until the PDFs change; `merge` moving them away or
+/// leaving them is both fine. Only the first look at `dir` failing is an error: later ones, a
+/// file renamed mid-listing or a share gone for a moment, are logged and tried again.
+pub fn run(
+    dir: &Path,
+    order: SortOrder,
+    settle: Duration,
+    interval: Duration,
+    stop: &AtomicBool,
+    mut merge: impl FnMut(&[PathBuf]) -> merge_pdf::Result<()>,
+) -> merge_pdf::Result<()> {
+    let mut last = snapshot(dir, order)?;
+    let mut since = Instant::now();
+    let mut merged = None;
+    log::info!("{}", tr("Watching {}", &[&dir.display()]));
+    while !stop.load(Ordering::Relaxed) {
+        thread::sleep(interval);
+        let now = match snapshot(dir, order) {
+            Ok(now) => now,
+            Err(e) => {
+                log::warn!("{}", tr("{} could not be read, trying again: {}", &[&dir.display(), &e]));
+                continue;
+            }
+        };
+        if now != last {
+            last = now;
+            since = Instant::now();
+            continue;
+        }
+        if last.is_empty() || since.elapsed() < settle || merged.as_ref() == Some(&last) {
+            continue;
+        }
+        let paths = last.iter().map(|(path, ..)| path.clone()).collect::<Vec<_>>();
+        match merge(&paths) {
+            Err(merge_pdf::Error::Interrupted) => break,
+            Err(e) => log::error!("{}", e),
+            Ok(()) => {}
+        }
+        // Unless the directory cannot be read now, the PDFs merged are those left after the merge
+        match snapshot(dir, order) {
+            Ok(now) => last = now,
+            Err(e) => log::warn!("{}", tr("{} could not be read, trying again: {}", &[&dir.display(), &e])),
+        }
+        merged = Some(last.clone());
+    }
+    Ok(())
+}
+
+/// `s` seconds, like `2.5`, for the intervals of `merge_pdf watch`.
+pub fn parse_seconds(s: &str) -> Result<Duration, String> {
+    s.parse::<f64>().ok().and_then(|seconds| Duration::try_from_secs_f64(seconds).ok()).ok_or_else(|| format!("{} is not a number of seconds", s))
+}
+
+#[cfg(test)]
+mod tests {
+    use merge_pdf::tempdir::TempDir;
+
+    use super::*;
+
+    #[test]
+    fn watching_survives_the_directory_going_away() {
+        let scratch = TempDir::named("watch-gone").unwrap();
+        let dir = scratch.path().join("scans");
+        fs::create_dir(&dir).unwrap();
+        fs::write(dir.join("a.pdf"), "%PDF-1.7").unwrap();
+        let stop = AtomicBool::new(false);
+        let mut batches = Vec::new();
+        let ms = Duration::from_millis;
+        thread::scope(|scope| {
+            let watched = run(&dir, SortOrder::Name, Duration::ZERO, ms(10), &stop, |paths| {
+                batches.push(paths.iter().map(|path| path.file_name().unwrap().to_owned()).collect::<Vec<_>>());
+                if batches.len() == 1 {
+                    // Gone for a while, then back with another scan
+                    fs::remove_dir_all(&dir).unwrap();
+                    let dir = dir.clone();
+                    scope.spawn(move || {
+                        thread::sleep(ms(100));
+                        fs::create_dir(&dir).unwrap();
+                        fs::write(dir.join("b.pdf"), "%PDF-1.7").unwrap();
+                    });
+                } else {
+                    stop.store(true, Ordering::Relaxed);
+                }
+                Ok(())
+            });
+            assert!(watched.is_ok());
+        });
+        assert_eq!(batches, [vec!["a.pdf"], vec!["b.pdf"]]);
+        // Only the first look is fatal
+        assert!(run(&scratch.path().join("missing"), SortOrder::Name, Duration::ZERO, ms(10), &stop, |_| Ok(())).is_err());
+    }
+}
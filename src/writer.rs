@@ -0,0 +1,494 @@
+use std::{
+    collections::BTreeMap,
+    io::{self, Write},
+};
+
+use lopdf::{Dictionary, Document, Object, ObjectId, Stream, StringFormat};
+use md5::{Digest, Md5};
+
+use crate::{
+    encrypt::{self, Encryption, Encryptor},
+    messages::tr,
+    verify,
+};
+
+/// How many objects are packed into one object stream.
+const PACKED_PER_STREAM: usize = 100;
+
+/// Trailer entries that describe the file they were read from, never carried over.
+const OLD_TRAILER: [&[u8]; 9] = [b"Size", b"Prev", b"XRefStm", b"Type", b"W", b"Index", b"Filter", b"DecodeParms", b"Length"];
+
+/// Writes a PDF file one indirect object at a time, so a document never has to be held in
+/// memory as a whole. The cross-reference table is written by `finish`.
+pub struct PdfWriter<W: Write> {
+    out: W,
+    offset: u64,
+    /// Where every object written so far is.
+    xref: BTreeMap<u32, Entry>,
+    /// Objects to pack into object streams, when `pack_objects` was asked for.
+    packing: Option<Packing>,
+    /// What encrypts the objects, and the encryption dictionary `finish` writes.
+    encryption: Option<(Encryptor, Dictionary)>,
+    /// Hash of everything written so far, when the trailer `ID` is to be made from it.
+    hasher: Option<Md5>,
+    /// Objects referred to but not written so far, each with the first object referring to it.
+    unwritten: BTreeMap<ObjectId, ObjectId>,
+}
+
+impl<W: Write> PdfWriter<W> {
+    /// Start a file of the given PDF version, e.g. "1.7".
+    pub fn new(out: W, version: &str) -> io::Result<Self> {
+        let mut writer =
+            PdfWriter { out, offset: 0, xref: BTreeMap::new(), packing: None, encryption: None, hasher: None, unwritten: BTreeMap::new() };
+        // The binary comment tells transfer programs the file is not text
+        writer.write(format!("%PDF-{}\n", version).as_bytes())?;
+        writer.write(b"%\xe2\xe3\xcf\xd3\n")?;
+        Ok(writer)
+    }
+
+    /// Encrypt the objects written from now on, see `encrypt::encrypt`.
+    pub fn encrypt(&mut self, encryption: &Encryption) -> io::Result<()> {
+        self.encryption = Some(Encryptor::new(encryption)?);
+        Ok(())
+    }
+
+    /// Make the trailer `ID` a hash of the objects written from now on, for output that is the
+    /// same every time; encryption gives it a random one instead.
+    pub fn hash_content(&mut self) {
+        self.hasher = Some(Md5::new());
+    }
+
+    /// Pack the objects written from now on, but for streams, into compressed object streams,
+    /// and end the file with a cross-reference stream instead of a table: both need PDF 1.5. The
+    /// packed objects are held, compressed, until `finish` writes their object streams.
+    pub fn pack_objects(&mut self) {
+        self.packing = Some(Packing::default());
+    }
+
+    pub fn write_object(&mut self, id: ObjectId, object: &Object) -> io::Result<()> {
+        let mut references = Vec::new();
+        verify::references(object, &mut references);
+        for target in references {
+            if !self.xref.contains_key(&target.0) {
+                self.unwritten.entry(target).or_insert(id);
+            }
+        }
+        // Only objects of generation 0 can be packed; the object stream as a whole is encrypted
+        if let Some(packing) = self.packing.as_mut().filter(|_| id.1 == 0 && !matches!(object, Object::Stream(_))) {
+            self.xref.insert(id.0, packing.pack(id.0, object));
+            self.unwritten.remove(&id);
+            return Ok(());
+        }
+        if let Some((encryptor, _)) = &self.encryption {
+            let mut object = object.clone();
+            encryptor.encrypt(&mut object)?;
+            return self.write_plain_object(id, &object);
+        }
+        self.write_plain_object(id, object)
+    }
+
+    fn write_plain_object(&mut self, id: ObjectId, object: &Object) -> io::Result<()> {
+        self.xref.insert(id.0, Entry::Offset(self.offset));
+        self.unwritten.remove(&id);
+        let mut buffer = format!("{} {} obj\n", id.0, id.1).into_bytes();
+        if let Object::Stream(stream) = object {
+            // Stream contents are written straight through instead of being copied into the buffer.
+            // Length is set directly, an indirect Length object may not match the content anymore.
+            let mut dict = stream.dict.clone();
+            dict.set("Length", stream.content.len() as i64);
+            write_dictionary(&mut buffer, &dict);
+            buffer.extend_from_slice(b"stream\n");
+            self.write(&buffer)?;
+            self.write(&stream.content)?;
+            return self.write(b"\nendstream\nendobj\n");
+        }
+        write_object(&mut buffer, object);
+        buffer.extend_from_slice(b"\nendobj\n");
+        self.write(&buffer)
+    }
+
+    /// The objects referred to that have not been written, each with the first object referring
+    /// to it; once everything is written, references to objects that do not exist.
+    pub fn unwritten(&self) -> impl Iterator<Item = (ObjectId, ObjectId)> + '_ {
+        self.unwritten.iter().map(|(&target, &referrer)| (target, referrer))
+    }
+
+    /// Write the cross-reference table and the trailer, whose `Size` is filled in here, after the
+    /// encryption dictionary if encrypting; with `pack_objects`, the object streams and a
+    /// cross-reference stream instead.
+    pub fn finish(mut self, mut trailer: Dictionary) -> io::Result<W> {
+        // The encryptor stays for the object streams `finish_packed` writes
+        if let Some(dict) = self.encryption.as_mut().map(|(_, dict)| std::mem::take(dict)) {
+            let id = (self.xref.keys().next_back().map_or(1, |last| last + 1), 0);
+            self.write_plain_object(id, &Object::Dictionary(dict))?;
+            trailer.set("Encrypt", id);
+            trailer.set("ID", encrypt::file_id()?);
+        }
+        if let Some(hasher) = self.hasher.take().filter(|_| !trailer.has(b"ID")) {
+            let id = Object::String(hasher.finalize().to_vec(), StringFormat::Hexadecimal);
+            trailer.set("ID", vec![id.clone(), id]);
+        }
+        if let Some(packing) = self.packing.take() {
+            return self.finish_packed(packing, trailer);
+        }
+        let start = self.offset;
+        let size = self.xref.keys().next_back().map_or(1, |last| last + 1);
+        let mut table = String::from("xref\n0 1\n0000000000 65535 f \n");
+        // One subsection per run of consecutive object numbers
+        let ids = self.xref.keys().copied().collect::<Vec<_>>();
+        for run in ids.chunk_by(|a, b| b - a == 1) {
+            table.push_str(&format!("{} {}\n", run[0], run.len()));
+            for id in run {
+                if let Entry::Offset(offset) = self.xref[id] {
+                    table.push_str(&format!("{:010} 00000 n \n", offset));
+                }
+            }
+        }
+        trailer.set("Size", size as i64);
+        let mut buffer = table.into_bytes();
+        buffer.extend_from_slice(b"trailer\n");
+        write_dictionary(&mut buffer, &trailer);
+        buffer.extend_from_slice(format!("\nstartxref\n{}\n%%EOF\n", start).as_bytes());
+        self.write(&buffer)?;
+        self.out.flush()?;
+        Ok(self.out)
+    }
+
+    fn finish_packed(mut self, mut packing: Packing, mut trailer: Dictionary) -> io::Result<W> {
+        packing.close();
+        let first_stream = self.xref.keys().next_back().map_or(1, |last| last + 1);
+        for (index, stream) in packing.streams.into_iter().enumerate() {
+            let mut stream = Object::Stream(stream);
+            if let Some((encryptor, _)) = &self.encryption {
+                encryptor.encrypt(&mut stream)?;
+            }
+            self.write_plain_object((first_stream + index as u32, 0), &stream)?;
+        }
+
+        let start = self.offset;
+        let xref_id = self.xref.keys().next_back().map_or(1, |last| last + 1);
+        self.xref.insert(xref_id, Entry::Offset(start));
+        // Each entry is a type, 4 bytes of offset or object stream and 2 of generation or index
+        let mut entries = Vec::with_capacity((xref_id as usize + 1) * 7);
+        for id in 0..=xref_id {
+            let (kind, field, other) = match self.xref.get(&id) {
+                Some(Entry::Offset(offset)) => (1, *offset as u32, 0),
+                Some(Entry::Packed(stream, index)) => (2, first_stream + stream, *index),
+                None => (0, 0, if id == 0 { 65535 } else { 0 }),
+            };
+            entries.push(kind);
+            entries.extend_from_slice(&field.to_be_bytes());
+            entries.extend_from_slice(&other.to_be_bytes());
+        }
+        for key in OLD_TRAILER {
+            trailer.remove(key);
+        }
+        trailer.set("Type", "XRef");
+        trailer.set("Size", xref_id as i64 + 1);
+        trailer.set("W", vec![1.into(), 4.into(), 2.into()]);
+        let mut stream = Stream::new(trailer, entries);
+        let _ = stream.compress();
+        // The cross-reference stream is never encrypted
+        let mut buffer = indirect_object((xref_id, 0), &Object::Stream(stream));
+        buffer.extend_from_slice(format!("startxref\n{}\n%%EOF\n", start).as_bytes());
+        self.write(&buffer)?;
+        self.out.flush()?;
+        Ok(self.out)
+    }
+
+    fn write(&mut self, bytes: &[u8]) -> io::Result<()> {
+        self.out.write_all(bytes)?;
+        if let Some(hasher) = &mut self.hasher {
+            hasher.update(bytes);
+        }
+        self.offset += bytes.len() as u64;
+        Ok(())
+    }
+}
+
+/// Where an object written by `PdfWriter` is.
+#[derive(Debug, Clone, Copy)]
+enum Entry {
+    /// At a byte offset in the file
+    Offset(u64),
+    /// In one of the object streams, by its position among them, at an index
+    Packed(u32, u16),
+}
+
+/// Objects packed into object streams that are not written yet.
+#[derive(Default)]
+struct Packing {
+    /// The number and serialization of each object of the stream being filled
+    pending: Vec<(u32, Vec<u8>)>,
+    streams: Vec<Stream>,
+}
+
+impl Packing {
+    fn pack(&mut self, id: u32, object: &Object) -> Entry {
+        let mut bytes = Vec::new();
+        write_object(&mut bytes, object);
+        self.pending.push((id, bytes));
+        let entry = Entry::Packed(self.streams.len() as u32, self.pending.len() as u16 - 1);
+        if self.pending.len() == PACKED_PER_STREAM {
+            self.close();
+        }
+        entry
+    }
+
+    /// Make the objects pending into an object stream.
+    fn close(&mut self) {
+        if self.pending.is_empty() {
+            return;
+        }
+        let mut header = String::new();
+        let mut body = Vec::new();
+        for (id, bytes) in &self.pending {
+            header.push_str(&format!("{} {} ", id, body.len()));
+            body.extend_from_slice(bytes);
+            body.push(b'\n');
+        }
+        let mut dict = Dictionary::new();
+        dict.set("Type", "ObjStm");
+        dict.set("N", self.pending.len() as i64);
+        dict.set("First", header.len() as i64);
+        let mut stream = Stream::new(dict, [header.into_bytes(), body].concat());
+        let _ = stream.compress();
+        self.streams.push(stream);
+        self.pending.clear();
+    }
+}
+
+/// Write `doc` whole with `PdfWriter`, packing its objects into object streams; the objects of
+/// the file it was read from that held others or indexed them are left out. `doc` must not be
+/// encrypted: its strings would be encrypted twice.
+pub fn write_packed<W: Write>(doc: &Document, out: W) -> io::Result<W> {
+    let mut writer = PdfWriter::new(out, &doc.version)?;
+    writer.pack_objects();
+    for (&id, object) in &doc.objects {
+        if !matches!(object.type_name(), Ok("XRef" | "ObjStm")) {
+            writer.write_object(id, object)?;
+        }
+    }
+    writer.finish(doc.trailer.clone())
+}
+
+/// Finish `update`, an incremental update to the PDF `bytes` holding the objects at `offsets`
+/// (counted from the start of the file), with a cross-reference section of the kind the file's
+/// last one is and `trailer`. `next_id` is the first id neither the file nor the update uses.
+pub(crate) fn finish_update(bytes: &[u8], update: &mut Vec<u8>, mut offsets: Vec<(ObjectId, usize)>, mut trailer: Dictionary, next_id: u32) -> crate::Result<()> {
+    let previous = bytes
+        .windows(9)
+        .rposition(|window| window == b"startxref")
+        .and_then(|at| std::str::from_utf8(&bytes[at + 9..]).ok()?.split_whitespace().next()?.parse::<usize>().ok())
+        .ok_or_else(|| crate::Error::Invalid(tr("no startxref", &[])))?;
+    let xref_stream = !bytes.get(previous..).is_some_and(|rest| rest.starts_with(b"xref"));
+    trailer.set("Prev", previous as i64);
+    let start = bytes.len() + update.len();
+    if xref_stream {
+        let xref_id = (next_id, 0);
+        offsets.push((xref_id, start));
+        offsets.sort();
+        trailer.set("Type", "XRef");
+        trailer.set("Size", xref_id.0 as i64 + 1);
+        trailer.set("W", vec![1.into(), 4.into(), 2.into()]);
+        trailer.set("Index", offsets.iter().flat_map(|((id, _), _)| [Object::Integer(*id as i64), 1.into()]).collect::<Vec<_>>());
+        let mut rows = Vec::new();
+        for ((_, generation), offset) in &offsets {
+            rows.push(1);
+            rows.extend_from_slice(&(*offset as u32).to_be_bytes());
+            rows.extend_from_slice(&generation.to_be_bytes());
+        }
+        update.extend_from_slice(format!("{} 0 obj\n", xref_id.0).as_bytes());
+        write_object(update, &Object::Stream(Stream::new(trailer, rows)));
+        update.extend_from_slice(b"\nendobj\n");
+    } else {
+        offsets.sort();
+        update.extend_from_slice(b"xref\n");
+        for ((id, generation), offset) in &offsets {
+            update.extend_from_slice(format!("{} 1\n{:010} {:05} n \n", id, offset, generation).as_bytes());
+        }
+        trailer.set("Size", next_id as i64);
+        update.extend_from_slice(b"trailer\n");
+        write_object(update, &Object::Dictionary(trailer));
+        update.push(b'\n');
+    }
+    update.extend_from_slice(format!("startxref\n{}\n%%EOF\n", start).as_bytes());
+    Ok(())
+}
+
+/// `object` as the indirect object `id`, a stream with its `Length` set to that of its content.
+pub(crate) fn indirect_object(id: ObjectId, object: &Object) -> Vec<u8> {
+    let mut out = format!("{} {} obj\n", id.0, id.1).into_bytes();
+    match object {
+        Object::Stream(stream) => {
+            let mut stream = stream.clone();
+            stream.dict.set("Length", stream.content.len() as i64);
+            write_object(&mut out, &Object::Stream(stream));
+        }
+        object => write_object(&mut out, object),
+    }
+    out.extend_from_slice(b"\nendobj\n");
+    out
+}
+
+/// Whether a space is needed before `object` when it follows another token.
+fn needs_separator(object: &Object) -> bool {
+    matches!(object, Object::Null | Object::Boolean(_) | Object::Integer(_) | Object::Real(_) | Object::Reference(_))
+}
+
+pub(crate) fn write_object(out: &mut Vec<u8>, object: &Object) {
+    match object {
+        Object::Null => out.extend_from_slice(b"null"),
+        Object::Boolean(value) => out.extend_from_slice(if *value { b"true" } else { b"false" }),
+        Object::Integer(value) => out.extend_from_slice(value.to_string().as_bytes()),
+        Object::Real(value) => out.extend_from_slice(value.to_string().as_bytes()),
+        Object::Name(name) => write_name(out, name),
+        Object::String(text, format) => write_string(out, text, format),
+        Object::Array(array) => {
+            out.push(b'[');
+            for (i, item) in array.iter().enumerate() {
+                if i > 0 && needs_separator(item) {
+                    out.push(b' ');
+                }
+                write_object(out, item);
+            }
+            out.push(b']');
+        }
+        Object::Dictionary(dictionary) => write_dictionary(out, dictionary),
+        Object::Stream(Stream { dict, content, .. }) => {
+            write_dictionary(out, dict);
+            out.extend_from_slice(b"stream\n");
+            out.extend_from_slice(content);
+            out.extend_from_slice(b"\nendstream");
+        }
+        Object::Reference(id) => out.extend_from_slice(format!("{} {} R", id.0, id.1).as_bytes()),
+    }
+}
+
+fn write_dictionary(out: &mut Vec<u8>, dictionary: &Dictionary) {
+    out.extend_from_slice(b"<<");
+    for (key, value) in dictionary {
+        write_name(out, key);
+        if needs_separator(value) {
+            out.push(b' ');
+        }
+        write_object(out, value);
+    }
+    out.extend_from_slice(b">>");
+}
+
+fn write_name(out: &mut Vec<u8>, name: &[u8]) {
+    out.push(b'/');
+    for &byte in name {
+        // White-space, delimiters and anything outside of '!'..='~' is written as a #xx escape
+        if b" \t\n\r\x0C()<>[]{}/%#".contains(&byte) || !(33..=126).contains(&byte) {
+            out.extend_from_slice(format!("#{:02X}", byte).as_bytes());
+        } else {
+            out.push(byte);
+        }
+    }
+}
+
+fn write_string(out: &mut Vec<u8>, text: &[u8], format: &StringFormat) {
+    match format {
+        StringFormat::Literal => {
+            out.push(b'(');
+            for &byte in text {
+                match byte {
+                    // Escaping every parenthesis keeps them balanced without counting
+                    b'(' | b')' | b'\\' => out.extend_from_slice(&[b'\\', byte]),
+                    b'\r' => out.extend_from_slice(b"\\r"),
+                    _ => out.push(byte),
+                }
+            }
+            out.push(b')');
+        }
+        StringFormat::Hexadecimal => {
+            out.push(b'<');
+            for &byte in text {
+                out.extend_from_slice(format!("{:02X}", byte).as_bytes());
+            }
+            out.push(b'>');
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::{Path, PathBuf};
+
+    use crate::{input::InputSpec, merge_inputs, Input, MergeOptions};
+
+    use super::*;
+
+    /// Two inputs of `pages` and `pages + 1` pages merged.
+    fn merged(pages: usize) -> Document {
+        let inputs = [pages, pages + 1]
+            .into_iter()
+            .enumerate()
+            .map(|(i, pages)| Input { spec: InputSpec::new(PathBuf::from(format!("{}.pdf", i))), doc: crate::tests::document(pages) })
+            .collect();
+        merge_inputs(inputs, &MergeOptions::new()).unwrap().document
+    }
+
+    fn assert_whole(doc: &Document, pages: usize) {
+        assert_eq!(doc.get_pages().len(), pages);
+        let problems = verify::check(doc);
+        assert!(problems.is_empty(), "{:?}", problems);
+    }
+
+    #[test]
+    fn packed_documents_load_again() {
+        // More objects than fit into one object stream
+        let doc = merged(60);
+        let bytes = write_packed(&doc, Vec::new()).unwrap();
+        assert!(bytes.starts_with(b"%PDF-"));
+        let loaded = Document::load_mem(&bytes).unwrap();
+        assert_whole(&loaded, 121);
+        assert_eq!(loaded.trailer.get(b"Root").unwrap(), doc.trailer.get(b"Root").unwrap());
+        assert!(loaded.objects.len() >= doc.objects.len());
+        // Only streams are left outside of object streams
+        let text = String::from_utf8_lossy(&bytes);
+        assert!(text.matches("/ObjStm").count() >= 2);
+        assert!(!text.contains("\nxref\n"));
+    }
+
+    #[test]
+    fn plain_documents_load_again() {
+        let doc = merged(3);
+        let mut writer = PdfWriter::new(Vec::new(), "1.7").unwrap();
+        for (&id, object) in &doc.objects {
+            writer.write_object(id, object).unwrap();
+        }
+        assert_eq!(writer.unwritten().count(), 0);
+        let bytes = writer.finish(doc.trailer.clone()).unwrap();
+        assert_whole(&Document::load_mem(&bytes).unwrap(), 7);
+    }
+
+    #[test]
+    fn references_to_objects_not_written_are_reported() {
+        let mut writer = PdfWriter::new(Vec::new(), "1.7").unwrap();
+        writer.write_object((1, 0), &Object::Reference((2, 0))).unwrap();
+        assert_eq!(writer.unwritten().collect::<Vec<_>>(), [((2, 0), (1, 0))]);
+        writer.write_object((2, 0), &Object::Null).unwrap();
+        assert_eq!(writer.unwritten().count(), 0);
+    }
+
+    #[test]
+    fn encrypted_object_streams_decrypt_again() {
+        let doc = merged(3);
+        let encryption = Encryption { user_password: "user".to_string(), owner_password: "owner".to_string(), permissions: Vec::new() };
+        let mut writer = PdfWriter::new(Vec::new(), "1.7").unwrap();
+        writer.encrypt(&encryption).unwrap();
+        writer.pack_objects();
+        for (&id, object) in &doc.objects {
+            writer.write_object(id, object).unwrap();
+        }
+        let bytes = writer.finish(doc.trailer.clone()).unwrap();
+        let loaded = Document::load_mem(&bytes).unwrap();
+        let mut decrypted = crate::decrypt::decrypt(Path::new("a.pdf"), &bytes, loaded, &["user"]).unwrap();
+        // lopdf keeps the cross-reference stream, which refers to the encryption dictionary gone now
+        decrypted.objects.retain(|_, object| object.type_name().ok() != Some("XRef"));
+        assert_whole(&decrypted, 7);
+    }
+}
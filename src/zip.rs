@@ -0,0 +1,337 @@
+use std::{
+    fs::{self, File},
+    io::{self, BufWriter, Read, Seek, Write},
+    path::{Component, Path, PathBuf},
+    time::{Duration, SystemTime},
+};
+
+use flate2::{read::DeflateDecoder, write::DeflateEncoder, Compression};
+
+use crate::messages::tr;
+
+const END_OF_CENTRAL_DIRECTORY: u32 = 0x0605_4b50;
+const CENTRAL_DIRECTORY_HEADER: u32 = 0x0201_4b50;
+const LOCAL_FILE_HEADER: u32 = 0x0403_4b50;
+const DATA_DESCRIPTOR: u32 = 0x0807_4b50;
+/// Sizes and CRC follow the data (bit 3), names are UTF-8 (bit 11).
+const WRITE_FLAGS: u16 = 0x0808;
+
+/// Extract every `*.pdf` entry of a ZIP archive below `dir`.
+///
+/// Entries keep their relative path and modification time, so `--sort` behaves the same as on
+/// a directory. Returns the extracted files in the order the archive lists them.
+pub fn extract_pdfs(archive: &Path, dir: &Path) -> crate::Result<Vec<PathBuf>> {
+    let invalid = |message: &str| crate::Error::Invalid(tr("{}: {}", &[&archive.display(), &message]));
+    let data = fs::read(archive)?;
+
+    // The end of central directory record sits in the last 22 bytes plus an optional comment
+    let search_from = data.len().saturating_sub(22 + u16::MAX as usize);
+    let eocd = (search_from..data.len().saturating_sub(21))
+        .rev()
+        .find(|&i| u32_at(&data, i) == Some(END_OF_CENTRAL_DIRECTORY))
+        .ok_or_else(|| invalid(&tr("not a ZIP archive", &[])))?;
+    let entries = u16_at(&data, eocd + 10).unwrap_or(0) as usize;
+    let mut offset = u32_at(&data, eocd + 16).unwrap_or(0) as usize;
+    if offset == 0xffff_ffff {
+        return Err(invalid(&tr("ZIP64 archives are not supported", &[])));
+    }
+
+    let mut extracted = Vec::new();
+    for _ in 0..entries {
+        if u32_at(&data, offset) != Some(CENTRAL_DIRECTORY_HEADER) {
+            return Err(invalid(&tr("corrupt central directory", &[])));
+        }
+        let field16 = |at: usize| u16_at(&data, offset + at).unwrap_or(0);
+        let field32 = |at: usize| u32_at(&data, offset + at).unwrap_or(0);
+        let (flags, method, time, date) = (field16(8), field16(10), field16(12), field16(14));
+        let (crc, compressed_size, size) = (field32(16), field32(20) as usize, field32(24) as usize);
+        let (name_len, extra_len, comment_len) = (field16(28) as usize, field16(30) as usize, field16(32) as usize);
+        let local_offset = field32(42) as usize;
+        let name = data
+            .get(offset + 46..offset + 46 + name_len)
+            .map(|n| String::from_utf8_lossy(n).into_owned())
+            .ok_or_else(|| invalid(&tr("truncated central directory", &[])))?;
+        offset += 46 + name_len + extra_len + comment_len;
+
+        if name.ends_with('/') || !name.to_ascii_lowercase().ends_with(".pdf") {
+            continue;
+        }
+        if flags & 1 != 0 {
+            return Err(invalid(&tr("{} is encrypted", &[&name])));
+        }
+        // Never let an entry name escape the extraction directory
+        let relative = Path::new(&name)
+            .components()
+            .filter_map(|c| match c {
+                Component::Normal(part) => Some(part),
+                _ => None,
+            })
+            .collect::<PathBuf>();
+
+        if u32_at(&data, local_offset) != Some(LOCAL_FILE_HEADER) {
+            return Err(invalid(&tr("{} has a corrupt local header", &[&name])));
+        }
+        let start = local_offset
+            + 30
+            + u16_at(&data, local_offset + 26).unwrap_or(0) as usize
+            + u16_at(&data, local_offset + 28).unwrap_or(0) as usize;
+        let raw = data.get(start..start + compressed_size).ok_or_else(|| invalid(&tr("{} is truncated", &[&name])))?;
+        let content = match method {
+            0 => raw.to_vec(),
+            8 => {
+                let mut content = Vec::with_capacity(size);
+                DeflateDecoder::new(raw).read_to_end(&mut content)?;
+                content
+            }
+            other => return Err(invalid(&tr("{} uses unsupported compression method {}", &[&name, &other]))),
+        };
+        if crc32fast::hash(&content) != crc {
+            return Err(invalid(&tr("{} fails its CRC check", &[&name])));
+        }
+
+        let target = dir.join(&relative);
+        if let Some(parent) = target.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(&target, content)?;
+        File::options().write(true).open(&target)?.set_modified(dos_time(date, time))?;
+        extracted.push(target);
+    }
+    Ok(extracted)
+}
+
+/// Write a ZIP archive at `archive` of the files in `entries`, each deflated under its name.
+///
+/// Files are read and compressed a piece at a time, so no part of them is held in memory whole;
+/// their sizes and CRCs follow their data. Archives that would need ZIP64 are refused.
+pub fn write_archive(archive: &Path, entries: &[(String, PathBuf)]) -> crate::Result<()> {
+    let too_large = || crate::Error::Invalid(tr("{}: too large for a ZIP archive without ZIP64", &[&archive.display()]));
+    let narrow = |n: u64| u32::try_from(n).map_err(|_| too_large());
+    let mut out = BufWriter::new(File::create(archive)?);
+    let mut central = Vec::new();
+    for (name, path) in entries {
+        let offset = narrow(out.stream_position()?)?;
+        let modified = fs::metadata(path).and_then(|metadata| metadata.modified()).unwrap_or_else(|_| SystemTime::now());
+        let (date, time) = to_dos_time(modified);
+        let name_len = u16::try_from(name.len()).map_err(|_| crate::Error::Invalid(tr("{}: name too long for a ZIP archive", &[name])))?;
+        // Version needed, flags, method, time, date, then CRC and sizes left for the descriptor
+        let mut header = Vec::with_capacity(30 + name.len());
+        header.extend_from_slice(&LOCAL_FILE_HEADER.to_le_bytes());
+        for field in [20, WRITE_FLAGS, 8, time, date] {
+            header.extend_from_slice(&field.to_le_bytes());
+        }
+        header.extend_from_slice(&[0; 12]);
+        header.extend_from_slice(&name_len.to_le_bytes());
+        header.extend_from_slice(&0u16.to_le_bytes());
+        header.extend_from_slice(name.as_bytes());
+        out.write_all(&header)?;
+
+        let start = out.stream_position()?;
+        let mut file = File::open(path).map_err(|e| crate::Error::MissingInput(path.clone(), e))?;
+        let mut crc = crc32fast::Hasher::new();
+        let mut encoder = DeflateEncoder::new(&mut out, Compression::default());
+        let mut buffer = vec![0; 64 * 1024];
+        let mut size = 0u64;
+        loop {
+            let read = match file.read(&mut buffer) {
+                Ok(0) => break,
+                Ok(read) => read,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e.into()),
+            };
+            crc.update(&buffer[..read]);
+            encoder.write_all(&buffer[..read])?;
+            size += read as u64;
+        }
+        encoder.finish()?;
+        let (crc, compressed_size, size) = (crc.finalize(), narrow(out.stream_position()? - start)?, narrow(size)?);
+        for field in [DATA_DESCRIPTOR, crc, compressed_size, size] {
+            out.write_all(&field.to_le_bytes())?;
+        }
+
+        central.extend_from_slice(&CENTRAL_DIRECTORY_HEADER.to_le_bytes());
+        for field in [20, 20, WRITE_FLAGS, 8, time, date] {
+            central.extend_from_slice(&field.to_le_bytes());
+        }
+        for field in [crc, compressed_size, size] {
+            central.extend_from_slice(&field.to_le_bytes());
+        }
+        // Name length, then no extra field, comment, disk number or attributes
+        central.extend_from_slice(&name_len.to_le_bytes());
+        central.extend_from_slice(&[0; 12]);
+        central.extend_from_slice(&offset.to_le_bytes());
+        central.extend_from_slice(name.as_bytes());
+    }
+    let central_offset = narrow(out.stream_position()?)?;
+    let count = u16::try_from(entries.len()).map_err(|_| too_large())?;
+    out.write_all(&central)?;
+    out.write_all(&END_OF_CENTRAL_DIRECTORY.to_le_bytes())?;
+    for field in [0, 0, count, count] {
+        out.write_all(&field.to_le_bytes())?;
+    }
+    for field in [narrow(central.len() as u64)?, central_offset] {
+        out.write_all(&field.to_le_bytes())?;
+    }
+    out.write_all(&0u16.to_le_bytes())?;
+    out.into_inner().map_err(|e| e.into_error())?.sync_all()?;
+    Ok(())
+}
+
+fn u16_at(data: &[u8], at: usize) -> Option<u16> {
+    data.get(at..at + 2).map(|b| u16::from_le_bytes([b[0], b[1]]))
+}
+
+fn u32_at(data: &[u8], at: usize) -> Option<u32> {
+    data.get(at..at + 4).map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+}
+
+/// Convert an MS-DOS date and time (local time, taken as UTC) to a `SystemTime`.
+fn dos_time(date: u16, time: u16) -> SystemTime {
+    let year = 1980 + (date >> 9) as i64;
+    let month = ((date >> 5) & 0xf).clamp(1, 12) as i64;
+    let day = (date & 0x1f).max(1) as i64;
+    // Days since the epoch from a civil date (Howard Hinnant's algorithm)
+    let y = if month <= 2 { year - 1 } else { year };
+    let era = y.div_euclid(400);
+    let yoe = y - era * 400;
+    let doy = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    let days = era * 146_097 + doe - 719_468;
+    let seconds = days * 86_400 + (time >> 11) as i64 * 3600 + ((time >> 5) & 0x3f) as i64 * 60 + (time & 0x1f) as i64 * 2;
+    SystemTime::UNIX_EPOCH + Duration::from_secs(seconds.max(0) as u64)
+}
+
+/// Convert a `SystemTime` to an MS-DOS date and time (taken as UTC), no earlier than 1980.
+fn to_dos_time(time: SystemTime) -> (u16, u16) {
+    // YYYY-MM-DDThh:mm:ssZ
+    let stamp = crate::metadata::timestamp(time);
+    let field = |range: std::ops::Range<usize>| stamp.get(range).and_then(|n| n.parse::<u16>().ok()).unwrap_or(0);
+    if field(0..4) < 1980 {
+        return (0x21, 0);
+    }
+    let date = ((field(0..4) - 1980).min(127) << 9) | (field(5..7) << 5) | field(8..10);
+    (date, (field(11..13) << 11) | (field(14..16) << 5) | (field(17..19) / 2))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tempdir::TempDir;
+
+    /// The CRC-32 check value of the ZIP (and PNG, gzip) polynomial.
+    const CHECK: u32 = 0xcbf4_3926;
+
+    /// An archive of one entry stored without compression, its sizes and CRC in the headers.
+    fn stored(name: &str, content: &[u8]) -> Vec<u8> {
+        let crc = crc32fast::hash(content);
+        let size = content.len() as u32;
+        let mut data = LOCAL_FILE_HEADER.to_le_bytes().to_vec();
+        for field in [10, 0, 0, 0, 0x21] {
+            data.extend_from_slice(&u16::to_le_bytes(field));
+        }
+        for field in [crc, size, size] {
+            data.extend_from_slice(&field.to_le_bytes());
+        }
+        data.extend_from_slice(&(name.len() as u16).to_le_bytes());
+        data.extend_from_slice(&[0; 2]);
+        data.extend_from_slice(name.as_bytes());
+        data.extend_from_slice(content);
+
+        let central_offset = data.len() as u32;
+        data.extend_from_slice(&CENTRAL_DIRECTORY_HEADER.to_le_bytes());
+        for field in [10, 10, 0, 0, 0, 0x21] {
+            data.extend_from_slice(&u16::to_le_bytes(field));
+        }
+        for field in [crc, size, size] {
+            data.extend_from_slice(&field.to_le_bytes());
+        }
+        data.extend_from_slice(&(name.len() as u16).to_le_bytes());
+        data.extend_from_slice(&[0; 12]);
+        data.extend_from_slice(&0u32.to_le_bytes());
+        data.extend_from_slice(name.as_bytes());
+        let central_len = data.len() as u32 - central_offset;
+        data.extend_from_slice(&END_OF_CENTRAL_DIRECTORY.to_le_bytes());
+        for field in [0, 0, 1, 1] {
+            data.extend_from_slice(&u16::to_le_bytes(field));
+        }
+        data.extend_from_slice(&central_len.to_le_bytes());
+        data.extend_from_slice(&central_offset.to_le_bytes());
+        data.extend_from_slice(&[0; 2]);
+        data
+    }
+
+    #[test]
+    fn written_archives_extract_again() {
+        let scratch = TempDir::named("zip-round-trip").unwrap();
+        let (one, two) = (scratch.path().join("one.pdf"), scratch.path().join("two.txt"));
+        let content = b"%PDF-1.7\n".repeat(10_000);
+        fs::write(&one, &content).unwrap();
+        fs::write(&two, b"not a PDF").unwrap();
+        let modified = SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+        File::options().write(true).open(&one).unwrap().set_modified(modified).unwrap();
+        let archive = scratch.path().join("out.zip");
+        write_archive(&archive, &[("sub/one.pdf".to_string(), one), ("two.txt".to_string(), two)]).unwrap();
+        // Deflated, so much smaller than the repetitive content
+        assert!(fs::metadata(&archive).unwrap().len() < content.len() as u64 / 10);
+
+        let dir = scratch.path().join("extracted");
+        let extracted = extract_pdfs(&archive, &dir).unwrap();
+        assert_eq!(extracted, [dir.join("sub/one.pdf")]);
+        assert_eq!(fs::read(&extracted[0]).unwrap(), content);
+        assert_eq!(fs::metadata(&extracted[0]).unwrap().modified().unwrap(), modified);
+    }
+
+    #[test]
+    fn crcs_are_those_of_the_content() {
+        let scratch = TempDir::named("zip-crc").unwrap();
+        let file = scratch.path().join("check.pdf");
+        fs::write(&file, b"123456789").unwrap();
+        let archive = scratch.path().join("out.zip");
+        write_archive(&archive, &[("check.pdf".to_string(), file)]).unwrap();
+        let data = fs::read(&archive).unwrap();
+        let central = data.windows(4).position(|w| w == CENTRAL_DIRECTORY_HEADER.to_le_bytes()).unwrap();
+        assert_eq!(u32_at(&data, central + 16), Some(CHECK));
+        assert_eq!(u32_at(&data, central + 24), Some(9));
+
+        // A wrong CRC in the central directory is caught when extracting
+        let mut broken = stored("check.pdf", b"123456789");
+        let crc_at = broken.windows(4).rposition(|w| w == CHECK.to_le_bytes()).unwrap();
+        broken[crc_at] ^= 1;
+        fs::write(&archive, broken).unwrap();
+        assert!(extract_pdfs(&archive, &scratch.path().join("broken")).is_err());
+    }
+
+    #[test]
+    fn stored_entries_are_extracted_inside_the_directory() {
+        let scratch = TempDir::named("zip-stored").unwrap();
+        let archive = scratch.path().join("stored.zip");
+        fs::write(&archive, stored("../../escape.PDF", b"%PDF-1.4 stored")).unwrap();
+        let dir = scratch.path().join("extracted");
+        let extracted = extract_pdfs(&archive, &dir).unwrap();
+        assert_eq!(extracted, [dir.join("escape.PDF")]);
+        assert_eq!(fs::read(&extracted[0]).unwrap(), b"%PDF-1.4 stored");
+    }
+
+    #[test]
+    fn empty_archives_have_nothing_to_extract() {
+        let scratch = TempDir::named("zip-empty").unwrap();
+        let archive = scratch.path().join("empty.zip");
+        write_archive(&archive, &[]).unwrap();
+        let data = fs::read(&archive).unwrap();
+        assert_eq!(data.len(), 22);
+        assert_eq!(u32_at(&data, 0), Some(END_OF_CENTRAL_DIRECTORY));
+        assert_eq!(extract_pdfs(&archive, &scratch.path().join("extracted")).unwrap(), Vec::<PathBuf>::new());
+
+        fs::write(&archive, b"PK but nothing more").unwrap();
+        assert!(extract_pdfs(&archive, &scratch.path().join("extracted")).is_err());
+    }
+
+    #[test]
+    fn dos_times_round_trip_to_two_seconds() {
+        let time = SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_001);
+        let (date, clock) = to_dos_time(time);
+        assert_eq!(dos_time(date, clock), SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000));
+        // Before 1980 there is no DOS time, it is clamped to its first day
+        assert_eq!(to_dos_time(SystemTime::UNIX_EPOCH), (0x21, 0));
+    }
+}